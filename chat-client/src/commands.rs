@@ -1,26 +1,116 @@
 use anyhow::Result;
+use chat_common::archive::{self, DEFAULT_MAX_ARCHIVE_SIZE, EXTRACT_DIR};
 use chat_common::encryption::EncryptionService;
-use chat_common::file_ops;
-use chat_common::Message;
+use chat_common::file_ops::{self, ImageDownscale};
+use chat_common::{
+    Availability, Clock, Message, Priority, QuotedMessage, TimeStyle, MAX_EPHEMERAL_TTL_SECS,
+    MAX_POLL_OPTIONS,
+};
+use clap::ValueEnum;
+use std::path::Path;
 use std::sync::Arc;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 pub enum Command {
     Text(String),
+    Prioritized {
+        priority: Priority,
+        text: String,
+    },
+    Ephemeral {
+        ttl_secs: u64,
+        text: String,
+    },
+    Reply {
+        message_id: i32,
+        text: String,
+    },
+    Poll {
+        question: String,
+        options: Vec<String>,
+    },
+    Vote {
+        poll_id: i32,
+        option: u32,
+    },
+    Report {
+        message_id: i32,
+        reason: String,
+    },
+    Edit {
+        message_id: i32,
+        text: String,
+    },
+    Delete(i32),
+    Action(String),
+    /// Saves the text as the workspace's draft, or sends the draft if None
+    Draft(Option<String>),
+    /// Lists the unread messages per workspace
+    Unread,
+    /// Mutes a `@user` or pattern, or lists the mutes if None
+    Mute(Option<String>),
+    Unmute(String),
+    /// Highlights a keyword, or lists the highlights if None
+    Highlight(Option<String>),
+    Unhighlight(String),
+    /// Changes how timestamps are shown, or turns them off and on if None
+    Time(Option<TimeSetting>),
+    /// Fetches the attachment of a stored message, to `path` if given
+    Download {
+        message_id: i32,
+        path: Option<String>,
+    },
     File(String),
     Image(String),
-    Auth { username: String, password: String },
+    Dir(String),
+    Extract(String),
+    Auth {
+        username: String,
+        password: String,
+    },
+    Register {
+        username: String,
+        email: String,
+        password: String,
+        invite_code: Option<String>,
+    },
+    Workspace(String),
+    Status {
+        availability: Availability,
+        text: Option<String>,
+    },
+    Password {
+        current_password: String,
+        new_password: String,
+    },
     Quit,
     Invalid,
 }
 
+/// A timestamp setting changed with `.time`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSetting {
+    Style(TimeStyle),
+    Clock(Clock),
+}
+
 pub struct CommandProcessor {
     encryption: Arc<EncryptionService>,
+    image_downscale: Option<ImageDownscale>,
 }
 
 impl CommandProcessor {
     pub fn new(encryption: Arc<EncryptionService>) -> Self {
-        Self { encryption }
+        Self {
+            encryption,
+            image_downscale: None,
+        }
+    }
+
+    /// Downscales images sent with `.image` that exceed the given thresholds.
+    pub fn with_image_downscale(mut self, image_downscale: Option<ImageDownscale>) -> Self {
+        self.image_downscale = image_downscale;
+        self
     }
 
     /// Parses a command string into a Command enum.
@@ -28,8 +118,29 @@ impl CommandProcessor {
     /// The function supports the following commands:
     /// - `.quit` - Exits the chat
     /// - `.login <username> <password>` - Authenticates the user
+    /// - `.register <username> <email> <password> [invite code]` - Creates an account and logs in
     /// - `.file <path>` - Sends a file
     /// - `.image <path>` - Sends an image
+    /// - `.dir <path>` - Sends a directory as a tar archive
+    /// - `.extract <name>` - Extracts a received archive into the sandbox folder
+    /// - `.workspace <slug>` - Switches to another workspace
+    /// - `.password <current> <new>` - Changes the password
+    /// - `.status <available|away|busy> [text]` - Sets the status shown to others
+    /// - `.urgent <text>` / `.low <text>` - Sends a text message with the given priority
+    /// - `.ephemeral <seconds> <text>` - Sends a text message that is not stored and expires
+    /// - `.reply <message id> <text>` - Sends a text message quoting an earlier message
+    /// - `.poll <question> | <option> | <option>...` - Creates a poll
+    /// - `.vote <poll id> <option number>` - Votes in a poll, options are numbered from 1
+    /// - `.report <message id> <reason>` - Reports a message to the workspace's moderators
+    /// - `.edit <message id> <text>` - Replaces the text of one of your messages
+    /// - `.delete <message id>` - Deletes one of your messages
+    /// - `.me <action>` - Sends an action in the third person, shown as `* alice waves`
+    /// - `.draft [text]` - Saves the text as the workspace's draft, or sends the draft
+    /// - `.unread` - Lists the workspaces with unread messages
+    /// - `.mute [@user|pattern]` / `.unmute <@user|pattern>` - Hides a user's or matching messages
+    /// - `.highlight [keyword]` / `.unhighlight <keyword>` - Colorizes a keyword and notifies on it
+    /// - `.time [absolute|relative|off|12h|24h]` - Changes how timestamps are shown, or toggles them
+    /// - `.download <message id> [path]` - Saves a message's attachment, by default into `files/`
     /// - Any other text (without leading dot) is treated as a text message
     ///
     /// # Arguments
@@ -54,6 +165,19 @@ impl CommandProcessor {
             return Command::Invalid;
         }
 
+        if let Some(args) = input.strip_prefix(".register ") {
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            return match parts[..] {
+                [username, email, password] | [username, email, password, _] => Command::Register {
+                    username: username.to_string(),
+                    email: email.to_string(),
+                    password: password.to_string(),
+                    invite_code: parts.get(3).map(|code| code.to_string()),
+                },
+                _ => Command::Invalid,
+            };
+        }
+
         if input.starts_with(".file ") {
             let path = input.trim_start_matches(".file ").trim();
             if path.is_empty() {
@@ -70,6 +194,242 @@ impl CommandProcessor {
             return Command::Image(path.to_string());
         }
 
+        if input.starts_with(".dir ") {
+            let path = input.trim_start_matches(".dir ").trim();
+            if path.is_empty() {
+                return Command::Invalid;
+            }
+            return Command::Dir(path.to_string());
+        }
+
+        if input.starts_with(".extract ") {
+            let name = input.trim_start_matches(".extract ").trim();
+            if name.is_empty() {
+                return Command::Invalid;
+            }
+            return Command::Extract(name.to_string());
+        }
+
+        if input.starts_with(".workspace ") {
+            let slug = input.trim_start_matches(".workspace ").trim();
+            if slug.is_empty() || slug.contains(char::is_whitespace) {
+                return Command::Invalid;
+            }
+            return Command::Workspace(slug.to_string());
+        }
+
+        if let Some(args) = input.strip_prefix(".status ") {
+            let args = args.trim();
+            let (availability, text) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let Ok(availability) = availability.parse::<Availability>() else {
+                return Command::Invalid;
+            };
+            let text = text.trim();
+            return Command::Status {
+                availability,
+                text: (!text.is_empty()).then(|| text.to_string()),
+            };
+        }
+
+        if let Some(args) = input.strip_prefix(".password ") {
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            if let [current_password, new_password] = parts[..] {
+                return Command::Password {
+                    current_password: current_password.to_string(),
+                    new_password: new_password.to_string(),
+                };
+            }
+            return Command::Invalid;
+        }
+
+        for (prefix, priority) in [(".urgent ", Priority::Urgent), (".low ", Priority::Low)] {
+            if let Some(text) = input.strip_prefix(prefix) {
+                let text = text.trim();
+                if text.is_empty() {
+                    return Command::Invalid;
+                }
+                return Command::Prioritized {
+                    priority,
+                    text: text.to_string(),
+                };
+            }
+        }
+
+        if let Some(args) = input.strip_prefix(".ephemeral ") {
+            let Some((ttl, text)) = args.trim().split_once(char::is_whitespace) else {
+                return Command::Invalid;
+            };
+            return match ttl.parse::<u64>() {
+                Ok(ttl_secs @ 1..=MAX_EPHEMERAL_TTL_SECS) if !text.trim().is_empty() => {
+                    Command::Ephemeral {
+                        ttl_secs,
+                        text: text.trim().to_string(),
+                    }
+                }
+                _ => Command::Invalid,
+            };
+        }
+
+        if let Some(args) = input.strip_prefix(".reply ") {
+            let Some((id, text)) = args.trim().split_once(char::is_whitespace) else {
+                return Command::Invalid;
+            };
+            return match id.trim_start_matches('#').parse::<i32>() {
+                Ok(message_id) if !text.trim().is_empty() => Command::Reply {
+                    message_id,
+                    text: text.trim().to_string(),
+                },
+                _ => Command::Invalid,
+            };
+        }
+
+        if let Some(args) = input.strip_prefix(".poll ") {
+            let mut parts = args.split('|').map(|part| part.trim().to_string());
+            let question = parts.next().unwrap_or_default();
+            let options: Vec<String> = parts.collect();
+            if question.is_empty()
+                || !(2..=MAX_POLL_OPTIONS).contains(&options.len())
+                || options.iter().any(String::is_empty)
+            {
+                return Command::Invalid;
+            }
+            return Command::Poll { question, options };
+        }
+
+        if let Some(args) = input.strip_prefix(".vote ") {
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let [poll_id, option] = parts[..] else {
+                return Command::Invalid;
+            };
+            return match (
+                poll_id.trim_start_matches('#').parse::<i32>(),
+                option.parse::<u32>(),
+            ) {
+                (Ok(poll_id), Ok(option @ 1..)) => Command::Vote {
+                    poll_id,
+                    option: option - 1,
+                },
+                _ => Command::Invalid,
+            };
+        }
+
+        if let Some(args) = input.strip_prefix(".report ") {
+            let Some((id, reason)) = args.trim().split_once(char::is_whitespace) else {
+                return Command::Invalid;
+            };
+            return match id.trim_start_matches('#').parse::<i32>() {
+                Ok(message_id) if !reason.trim().is_empty() => Command::Report {
+                    message_id,
+                    reason: reason.trim().to_string(),
+                },
+                _ => Command::Invalid,
+            };
+        }
+
+        if let Some(args) = input.strip_prefix(".edit ") {
+            let Some((id, text)) = args.trim().split_once(char::is_whitespace) else {
+                return Command::Invalid;
+            };
+            return match id.trim_start_matches('#').parse::<i32>() {
+                Ok(message_id) if !text.trim().is_empty() => Command::Edit {
+                    message_id,
+                    text: text.trim().to_string(),
+                },
+                _ => Command::Invalid,
+            };
+        }
+
+        if let Some(id) = input.strip_prefix(".delete ") {
+            return match id.trim().trim_start_matches('#').parse::<i32>() {
+                Ok(message_id) => Command::Delete(message_id),
+                Err(_) => Command::Invalid,
+            };
+        }
+
+        if let Some(action) = input.strip_prefix(".me ") {
+            let action = action.trim();
+            if action.is_empty() {
+                return Command::Invalid;
+            }
+            return Command::Action(action.to_string());
+        }
+
+        if let Some(args) = input.strip_prefix(".download ") {
+            let args = args.trim();
+            let (id, path) = args
+                .split_once(char::is_whitespace)
+                .map_or((args, None), |(id, path)| (id, Some(path.trim())));
+            return match id.trim_start_matches('#').parse::<i32>() {
+                Ok(message_id) => Command::Download {
+                    message_id,
+                    path: path.map(str::to_string),
+                },
+                Err(_) => Command::Invalid,
+            };
+        }
+
+        if input == ".unread" {
+            return Command::Unread;
+        }
+
+        if input == ".mute" {
+            return Command::Mute(None);
+        }
+
+        if let Some(entry) = input.strip_prefix(".mute ") {
+            let entry = entry.trim();
+            return Command::Mute((!entry.is_empty()).then(|| entry.to_string()));
+        }
+
+        if let Some(entry) = input.strip_prefix(".unmute ") {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                return Command::Invalid;
+            }
+            return Command::Unmute(entry.to_string());
+        }
+
+        if input == ".highlight" {
+            return Command::Highlight(None);
+        }
+
+        if let Some(keyword) = input.strip_prefix(".highlight ") {
+            let keyword = keyword.trim();
+            return Command::Highlight((!keyword.is_empty()).then(|| keyword.to_string()));
+        }
+
+        if let Some(keyword) = input.strip_prefix(".unhighlight ") {
+            let keyword = keyword.trim();
+            if keyword.is_empty() {
+                return Command::Invalid;
+            }
+            return Command::Unhighlight(keyword.to_string());
+        }
+
+        if input == ".time" {
+            return Command::Time(None);
+        }
+
+        if let Some(setting) = input.strip_prefix(".time ") {
+            let setting = setting.trim();
+            if let Ok(style) = TimeStyle::from_str(setting, true) {
+                return Command::Time(Some(TimeSetting::Style(style)));
+            }
+            if let Ok(clock) = Clock::from_str(setting, true) {
+                return Command::Time(Some(TimeSetting::Clock(clock)));
+            }
+            return Command::Invalid;
+        }
+
+        if input == ".draft" {
+            return Command::Draft(None);
+        }
+
+        if let Some(text) = input.strip_prefix(".draft ") {
+            let text = text.trim();
+            return Command::Draft((!text.is_empty()).then(|| text.to_string()));
+        }
+
         if input.starts_with('.') {
             return Command::Invalid;
         }
@@ -84,10 +444,117 @@ impl CommandProcessor {
                 let encrypted = self.encryption.message().encrypt(&text)?;
                 Ok(Some(Message::Text(serde_json::to_string(&encrypted)?)))
             }
+            Command::Ephemeral { ttl_secs, text } => {
+                let encrypted = self.encryption.message().encrypt(&text)?;
+                Ok(Some(Message::Ephemeral {
+                    content: serde_json::to_string(&encrypted)?,
+                    ttl_secs,
+                    expires_at: None,
+                }))
+            }
+            Command::Reply { message_id, text } => {
+                let encrypted = self.encryption.message().encrypt(&text)?;
+                Ok(Some(Message::Reply {
+                    content: serde_json::to_string(&encrypted)?,
+                    quote: QuotedMessage::new(message_id),
+                }))
+            }
+            Command::Poll { question, options } => Ok(Some(Message::Poll { question, options })),
+            Command::Vote { poll_id, option } => Ok(Some(Message::Vote { poll_id, option })),
+            Command::Report { message_id, reason } => {
+                Ok(Some(Message::Report { message_id, reason }))
+            }
+            Command::Edit { message_id, text } => {
+                let encrypted = self.encryption.message().encrypt(&text)?;
+                Ok(Some(Message::Edit {
+                    message_id,
+                    new_content: serde_json::to_string(&encrypted)?,
+                }))
+            }
+            Command::Delete(message_id) => Ok(Some(Message::Delete { message_id })),
+            // The destination is remembered by the input loop
+            Command::Download { message_id, .. } => Ok(Some(Message::Download { message_id })),
+            Command::Action(action) => {
+                let encrypted = self.encryption.message().encrypt(&action)?;
+                Ok(Some(Message::Action {
+                    content: serde_json::to_string(&encrypted)?,
+                    username: None,
+                }))
+            }
+            Command::Prioritized { priority, text } => {
+                let encrypted = self.encryption.message().encrypt(&text)?;
+                Ok(Some(Message::PriorityText {
+                    priority,
+                    content: serde_json::to_string(&encrypted)?,
+                }))
+            }
             Command::File(path) => self.process_file_command(".file", &path).await,
-            Command::Image(path) => self.process_file_command(".image", &path).await,
+            Command::Image(path) => {
+                match file_ops::process_image_command(
+                    &path,
+                    self.image_downscale,
+                    Some(self.encryption.clone()),
+                )
+                .await
+                {
+                    Ok(msg) => Ok(Some(msg)),
+                    Err(e) => {
+                        error!("{}", e);
+                        Ok(Some(file_ops::create_error_message(&e)))
+                    }
+                }
+            }
+            Command::Dir(path) => {
+                match file_ops::process_dir_command(
+                    &path,
+                    DEFAULT_MAX_ARCHIVE_SIZE,
+                    Some(self.encryption.clone()),
+                )
+                .await
+                {
+                    Ok(msg) => Ok(Some(msg)),
+                    Err(e) => {
+                        error!("{}", e);
+                        Ok(Some(file_ops::create_error_message(&e)))
+                    }
+                }
+            }
+            Command::Extract(name) => {
+                self.extract_archive(&name).await;
+                Ok(None)
+            }
             Command::Auth { username, password } => Ok(Some(Message::Auth { username, password })),
-            Command::Quit => Ok(None),
+            Command::Register {
+                username,
+                email,
+                password,
+                invite_code,
+            } => Ok(Some(Message::Register {
+                username,
+                email,
+                password,
+                invite_code,
+            })),
+            Command::Workspace(slug) => Ok(Some(Message::SwitchWorkspace { slug })),
+            Command::Status { availability, text } => {
+                Ok(Some(Message::SetStatus { availability, text }))
+            }
+            Command::Password {
+                current_password,
+                new_password,
+            } => Ok(Some(Message::ChangePassword {
+                current_password,
+                new_password,
+            })),
+            // Drafts, unread counts, filters and timestamps are kept by the input loop
+            Command::Quit
+            | Command::Draft(_)
+            | Command::Unread
+            | Command::Mute(_)
+            | Command::Unmute(_)
+            | Command::Highlight(_)
+            | Command::Unhighlight(_)
+            | Command::Time(_) => Ok(None),
             Command::Invalid => {
                 warn!("Invalid command format");
                 Ok(None)
@@ -95,6 +562,25 @@ impl CommandProcessor {
         }
     }
 
+    /// Extracts an archive previously received into `files/` into the sandbox folder.
+    async fn extract_archive(&self, name: &str) {
+        // Only the file name is used so the command cannot reach outside `files/`
+        let Some(file_name) = Path::new(name).file_name() else {
+            warn!("Invalid archive name: {}", name);
+            return;
+        };
+        let archive_path = Path::new("files").join(file_name);
+
+        match archive::extract_archive(&archive_path, Path::new(EXTRACT_DIR)).await {
+            Ok((destination, entries)) => info!(
+                "Extracted {} entries into {}",
+                entries,
+                destination.display()
+            ),
+            Err(e) => error!("Failed to extract {}: {}", archive_path.display(), e),
+        }
+    }
+
     async fn process_file_command(&self, command: &str, path: &str) -> Result<Option<Message>> {
         match file_ops::process_file_command(command, path, Some(self.encryption.clone())).await {
             Ok(msg) => Ok(Some(msg)),
@@ -195,6 +681,353 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_parse_priority_commands() {
+        let processor = create_processor();
+        match processor.parse_command(".urgent server is down") {
+            Command::Prioritized { priority, text } => {
+                assert_eq!(priority, Priority::Urgent);
+                assert_eq!(text, "server is down");
+            }
+            _ => panic!("Expected Prioritized command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".low fyi"),
+            Command::Prioritized {
+                priority: Priority::Low,
+                ..
+            }
+        ));
+        assert!(matches!(
+            processor.parse_command(".urgent "),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_reply_command() {
+        let processor = create_processor();
+        match processor.parse_command(".reply #42 agreed, let's do it") {
+            Command::Reply { message_id, text } => {
+                assert_eq!(message_id, 42);
+                assert_eq!(text, "agreed, let's do it");
+            }
+            _ => panic!("Expected Reply command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".reply 42"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".reply latest hello"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_report_command() {
+        let processor = create_processor();
+        match processor.parse_command(".report #42 spam link") {
+            Command::Report { message_id, reason } => {
+                assert_eq!(message_id, 42);
+                assert_eq!(reason, "spam link");
+            }
+            _ => panic!("Expected Report command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".report 42"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".report 42   "),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_edit_and_delete_commands() {
+        let processor = create_processor();
+        match processor.parse_command(".edit #42  fixed the typo ") {
+            Command::Edit { message_id, text } => {
+                assert_eq!(message_id, 42);
+                assert_eq!(text, "fixed the typo");
+            }
+            _ => panic!("Expected Edit command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".edit 42"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".delete #42"),
+            Command::Delete(42)
+        ));
+        assert!(matches!(
+            processor.parse_command(".delete latest"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_download_command() {
+        let processor = create_processor();
+        match processor.parse_command(".download #42") {
+            Command::Download { message_id, path } => {
+                assert_eq!(message_id, 42);
+                assert_eq!(path, None);
+            }
+            _ => panic!("Expected Download command"),
+        }
+        match processor.parse_command(".download 42  my files/report.pdf ") {
+            Command::Download { message_id, path } => {
+                assert_eq!(message_id, 42);
+                assert_eq!(path.as_deref(), Some("my files/report.pdf"));
+            }
+            _ => panic!("Expected Download command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".download report.pdf"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_filter_commands() {
+        let processor = create_processor();
+        assert!(matches!(
+            processor.parse_command(".mute"),
+            Command::Mute(None)
+        ));
+        match processor.parse_command(".mute  @bob ") {
+            Command::Mute(Some(entry)) => assert_eq!(entry, "@bob"),
+            _ => panic!("Expected Mute command"),
+        }
+        match processor.parse_command(".unhighlight deploy") {
+            Command::Unhighlight(keyword) => assert_eq!(keyword, "deploy"),
+            _ => panic!("Expected Unhighlight command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".highlight "),
+            Command::Highlight(None)
+        ));
+        assert!(matches!(
+            processor.parse_command(".unmute "),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".muted"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_time_command() {
+        let processor = create_processor();
+        assert!(matches!(
+            processor.parse_command(".time"),
+            Command::Time(None)
+        ));
+        assert!(matches!(
+            processor.parse_command(".time Relative"),
+            Command::Time(Some(TimeSetting::Style(TimeStyle::Relative)))
+        ));
+        assert!(matches!(
+            processor.parse_command(".time 12h"),
+            Command::Time(Some(TimeSetting::Clock(Clock::H12)))
+        ));
+        assert!(matches!(
+            processor.parse_command(".time tomorrow"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_action_command() {
+        let processor = create_processor();
+        match processor.parse_command(".me  waves at everyone ") {
+            Command::Action(action) => assert_eq!(action, "waves at everyone"),
+            _ => panic!("Expected Action command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".me   "),
+            Command::Invalid
+        ));
+        assert!(matches!(processor.parse_command(".meow"), Command::Invalid));
+    }
+
+    #[test]
+    fn test_parse_draft_command() {
+        let processor = create_processor();
+        match processor.parse_command(".draft  see you at ") {
+            Command::Draft(Some(text)) => assert_eq!(text, "see you at"),
+            _ => panic!("Expected Draft command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".draft"),
+            Command::Draft(None)
+        ));
+    }
+
+    #[test]
+    fn test_parse_poll_commands() {
+        let processor = create_processor();
+        match processor.parse_command(".poll Lunch? | Pizza | Sushi ") {
+            Command::Poll { question, options } => {
+                assert_eq!(question, "Lunch?");
+                assert_eq!(options, vec!["Pizza", "Sushi"]);
+            }
+            _ => panic!("Expected Poll command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".poll Lunch? | Pizza"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".poll Lunch? | Pizza | "),
+            Command::Invalid
+        ));
+
+        // Options are numbered from 1 for people and from 0 on the wire
+        assert!(matches!(
+            processor.parse_command(".vote #4 2"),
+            Command::Vote {
+                poll_id: 4,
+                option: 1
+            }
+        ));
+        assert!(matches!(
+            processor.parse_command(".vote 4 0"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".vote 4"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_status_command() {
+        let processor = create_processor();
+        match processor.parse_command(".status away 🌴 on vacation") {
+            Command::Status { availability, text } => {
+                assert_eq!(availability, Availability::Away);
+                assert_eq!(text.as_deref(), Some("🌴 on vacation"));
+            }
+            _ => panic!("Expected Status command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".status busy"),
+            Command::Status {
+                availability: Availability::Busy,
+                text: None
+            }
+        ));
+        assert!(matches!(
+            processor.parse_command(".status offline"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_ephemeral_command() {
+        let processor = create_processor();
+        match processor.parse_command(".ephemeral 30 the door code is 1234") {
+            Command::Ephemeral { ttl_secs, text } => {
+                assert_eq!(ttl_secs, 30);
+                assert_eq!(text, "the door code is 1234");
+            }
+            _ => panic!("Expected Ephemeral command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".ephemeral 0 gone"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".ephemeral soon text"),
+            Command::Invalid
+        ));
+        assert!(matches!(
+            processor.parse_command(".ephemeral 30"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_dir_and_extract_commands() {
+        let processor = create_processor();
+        match processor.parse_command(".dir ./photos") {
+            Command::Dir(path) => assert_eq!(path, "./photos"),
+            _ => panic!("Expected Dir command"),
+        }
+        match processor.parse_command(".extract photos.tar") {
+            Command::Extract(name) => assert_eq!(name, "photos.tar"),
+            _ => panic!("Expected Extract command"),
+        }
+        assert!(matches!(processor.parse_command(".dir"), Command::Invalid));
+    }
+
+    #[test]
+    fn test_parse_workspace_command() {
+        let processor = create_processor();
+        match processor.parse_command(".workspace engineering") {
+            Command::Workspace(slug) => assert_eq!(slug, "engineering"),
+            _ => panic!("Expected Workspace command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".workspace a b"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_register_command() {
+        let processor = create_processor();
+        match processor.parse_command(".register dave dave@example.com secret welcome") {
+            Command::Register {
+                username,
+                email,
+                password,
+                invite_code,
+            } => {
+                assert_eq!(username, "dave");
+                assert_eq!(email, "dave@example.com");
+                assert_eq!(password, "secret");
+                assert_eq!(invite_code.as_deref(), Some("welcome"));
+            }
+            _ => panic!("Expected Register command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".register dave dave@example.com secret"),
+            Command::Register {
+                invite_code: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            processor.parse_command(".register dave secret"),
+            Command::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_parse_password_command() {
+        let processor = create_processor();
+        match processor.parse_command(".password old-secret new-secret") {
+            Command::Password {
+                current_password,
+                new_password,
+            } => {
+                assert_eq!(current_password, "old-secret");
+                assert_eq!(new_password, "new-secret");
+            }
+            _ => panic!("Expected Password command"),
+        }
+        assert!(matches!(
+            processor.parse_command(".password only-one"),
+            Command::Invalid
+        ));
+    }
+
     #[test]
     fn test_parse_text_command() {
         let processor = create_processor();
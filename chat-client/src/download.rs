@@ -0,0 +1,157 @@
+//! Saving of attachments fetched with `.download <message id> [path]`.
+//!
+//! The server answers a download with the attachment of the stored message.
+//! Without a path it is saved like any received file. Otherwise it goes where
+//! the command asked: into a directory under the name the sender gave it,
+//! sanitized and numbered if taken like every received file, or to an explicit
+//! file path, which is only replaced after the user confirmed it.
+
+use anyhow::{Context, Result};
+use chat_common::error::ChatError;
+use chat_common::file_ops::PartialFile;
+use std::path::{Path, PathBuf};
+
+/// Where a requested attachment is saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// Into the directory, under the attachment's own name or a numbered variant
+    Directory(PathBuf),
+    /// To the file, replacing it only if `overwrite` is set
+    File { path: PathBuf, overwrite: bool },
+}
+
+impl Destination {
+    /// Chooses the destination for the path given to `.download`.
+    ///
+    /// Existing directories and paths ending with a separator are directories,
+    /// anything else names the file to write.
+    pub fn parse(path: &str) -> Self {
+        if path.ends_with(['/', '\\']) || Path::new(path).is_dir() {
+            Destination::Directory(PathBuf::from(path))
+        } else {
+            Destination::File {
+                path: PathBuf::from(path),
+                overwrite: false,
+            }
+        }
+    }
+
+    /// Returns the file that would be replaced, if the destination names one.
+    pub fn existing_file(&self) -> Option<&Path> {
+        match self {
+            Destination::File {
+                path,
+                overwrite: false,
+            } if path.exists() => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Allows replacing the file the destination names.
+    pub fn overwriting(self) -> Self {
+        match self {
+            Destination::File { path, .. } => Destination::File {
+                path,
+                overwrite: true,
+            },
+            directory => directory,
+        }
+    }
+
+    /// Returns the directory the attachment is written in while it arrives.
+    pub fn dir(&self) -> &Path {
+        match self {
+            Destination::Directory(dir) => dir,
+            Destination::File { path, .. } => path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+        }
+    }
+
+    /// Moves a completely received attachment into place, never replacing a
+    /// file that was not confirmed.
+    ///
+    /// # Arguments
+    /// * `partial` - The attachment, written in [`Destination::dir`]
+    /// * `name` - The attachment's name as sent by the server
+    ///
+    /// # Returns
+    /// * `Result<PathBuf>` - Where the attachment was saved
+    pub async fn commit(&self, partial: PartialFile, name: &str) -> Result<PathBuf> {
+        match self {
+            Destination::Directory(_) => Ok(partial.commit(name).await?),
+            Destination::File { path, overwrite } => {
+                match partial.commit_to(path, *overwrite).await {
+                    Ok(path) => Ok(path),
+                    Err(ChatError::Conflict(_)) => Err(anyhow::anyhow!(
+                        "{} already exists, download to another path to keep both",
+                        path.display()
+                    )),
+                    Err(e) => Err(e).with_context(|| format!("Failed to save {}", path.display())),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    async fn save(destination: &Destination, name: &str, data: &[u8]) -> Result<PathBuf> {
+        let mut partial = PartialFile::create(destination.dir()).await?;
+        partial.file().write_all(data).await?;
+        destination.commit(partial, name).await
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+
+        assert_eq!(
+            Destination::parse(dir_path),
+            Destination::Directory(dir.path().to_path_buf())
+        );
+        assert_eq!(
+            Destination::parse("downloads/"),
+            Destination::Directory(PathBuf::from("downloads/"))
+        );
+        assert_eq!(
+            Destination::parse("downloads/report.pdf"),
+            Destination::File {
+                path: PathBuf::from("downloads/report.pdf"),
+                overwrite: false,
+            }
+        );
+        assert_eq!(Destination::parse("report.pdf").dir(), Path::new("."));
+    }
+
+    #[tokio::test]
+    async fn test_save_never_replaces_files_silently() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let into_dir = Destination::Directory(dir.path().join("files"));
+        let saved = save(&into_dir, "report.pdf", b"first").await.unwrap();
+        assert_eq!(saved, dir.path().join("files").join("report.pdf"));
+        let numbered = save(&into_dir, "report.pdf", b"second").await.unwrap();
+        assert_eq!(numbered, dir.path().join("files").join("report (1).pdf"));
+        assert!(save(&into_dir, "../report.pdf", b"third").await.is_err());
+        assert_eq!(std::fs::read(&saved).unwrap(), b"first");
+
+        let to_file = Destination::parse(saved.to_str().unwrap());
+        assert_eq!(to_file.existing_file(), Some(saved.as_path()));
+        assert!(save(&to_file, "other.pdf", b"second").await.is_err());
+
+        let to_file = to_file.overwriting();
+        assert_eq!(to_file.existing_file(), None);
+        save(&to_file, "other.pdf", b"second").await.unwrap();
+        assert_eq!(std::fs::read(&saved).unwrap(), b"second");
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("files")).unwrap().count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Mute and highlight filters, configured locally with `.mute` and `.highlight`.
+//!
+//! A mute starting with `@` hides the messages, actions and status changes of
+//! that user; any other mute hides the messages containing it. Highlights are
+//! keywords that are colorized in received messages and raise a desktop
+//! notification. Both match ignoring case and are kept in the state file.
+
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::output::Event;
+
+/// Style of highlighted keywords: bold yellow
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// The mutes and highlights in effect.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filters {
+    /// Lowercased `@user`s and patterns
+    muted: Vec<String>,
+    /// Lowercased keywords
+    highlights: Vec<String>,
+}
+
+impl Filters {
+    pub fn new<'a>(
+        muted: impl IntoIterator<Item = &'a String>,
+        highlights: impl IntoIterator<Item = &'a String>,
+    ) -> Self {
+        Self {
+            muted: muted.into_iter().map(|mute| mute.to_lowercase()).collect(),
+            highlights: highlights
+                .into_iter()
+                .map(|keyword| keyword.to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Filters a received event.
+    ///
+    /// # Returns
+    /// * `bool` - `false` if the event is muted and must not be shown;
+    ///   otherwise the keywords it contains are recorded in its `highlights`
+    pub fn apply(&self, event: &mut Event) -> bool {
+        if self.mutes(event) {
+            return false;
+        }
+        let found = match event.text() {
+            Some(text) => {
+                let text = text.to_lowercase();
+                self.highlights
+                    .iter()
+                    .filter(|keyword| text.contains(keyword.as_str()))
+                    .cloned()
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        if let Event::Message { highlights, .. }
+        | Event::Reply { highlights, .. }
+        | Event::Action { highlights, .. } = event
+        {
+            *highlights = found;
+        }
+        true
+    }
+
+    fn mutes(&self, event: &Event) -> bool {
+        let (sender, text) = match event {
+            Event::Message { sender, text, .. } | Event::Reply { sender, text, .. } => {
+                (sender.as_ref(), Some(text))
+            }
+            Event::Edited { text, .. } => (None, Some(text)),
+            Event::Action { username, text, .. }
+            | Event::History {
+                sender: username,
+                text,
+                ..
+            } => (Some(username), Some(text)),
+            Event::Status { username, .. } => (Some(username), None),
+            _ => return false,
+        };
+        let sender = sender.map(|sender| sender.to_lowercase());
+        let text = text.map(|text| text.to_lowercase());
+        self.muted.iter().any(|mute| match mute.strip_prefix('@') {
+            Some(user) => sender.as_deref() == Some(user),
+            None => text
+                .as_ref()
+                .is_some_and(|text| text.contains(mute.as_str())),
+        })
+    }
+}
+
+/// Colorizes the highlighted keywords in a text shown on the terminal.
+pub fn colorize(text: &str, highlights: &[String]) -> String {
+    if highlights.is_empty() {
+        return text.to_string();
+    }
+    // Lowercasing keeps byte offsets for almost all text; bail out where it does not
+    let lower = text.to_lowercase();
+    if lower.len() != text.len() {
+        return text.to_string();
+    }
+    let mut marked = vec![false; text.len()];
+    for keyword in highlights {
+        for (start, _) in lower.match_indices(keyword.as_str()) {
+            marked[start..start + keyword.len()].fill(true);
+        }
+    }
+
+    let mut colorized = String::with_capacity(text.len());
+    let mut highlighted = false;
+    for (index, c) in text.char_indices() {
+        if marked[index] != highlighted {
+            highlighted = marked[index];
+            colorized.push_str(if highlighted { HIGHLIGHT } else { RESET });
+        }
+        colorized.push(c);
+    }
+    if highlighted {
+        colorized.push_str(RESET);
+    }
+    colorized
+}
+
+/// Shows a desktop notification with `notify-send` or, on macOS, `osascript`.
+///
+/// Runs in the background; where neither is available nothing is shown.
+pub fn notify_desktop(summary: String, body: String) {
+    tokio::spawn(async move {
+        let mut command = if cfg!(target_os = "macos") {
+            let mut command = Command::new("osascript");
+            command.arg("-e").arg(format!(
+                "display notification {:?} with title {:?}",
+                body, summary
+            ));
+            command
+        } else {
+            let mut command = Command::new("notify-send");
+            command.arg("--").arg(summary).arg(body);
+            command
+        };
+        let status = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => debug!("Desktop notification failed: {}", status),
+            Err(e) => debug!("Desktop notifications are not available: {}", e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_common::{Availability, HistoricalKind, Priority};
+    use chrono::Utc;
+
+    fn message(text: &str) -> Event {
+        Event::Message {
+            sender: None,
+            text: text.to_string(),
+            priority: Priority::Normal,
+            expires_at: None,
+            sent_at: None,
+            highlights: Vec::new(),
+        }
+    }
+
+    fn filters(muted: &[&str], highlights: &[&str]) -> Filters {
+        let muted: Vec<String> = muted.iter().map(|mute| mute.to_string()).collect();
+        let highlights: Vec<String> = highlights.iter().map(|word| word.to_string()).collect();
+        Filters::new(&muted, &highlights)
+    }
+
+    #[test]
+    fn test_mutes_users_and_patterns() {
+        let filters = filters(&["@Bob", "Standup"], &[]);
+
+        assert!(!filters.apply(&mut message("standup moved to 10")));
+        assert!(filters.apply(&mut message("lunch?")));
+        // Messages without a sender are only muted by their text
+        assert!(filters.apply(&mut message("bob says hi")));
+        assert!(!filters.apply(&mut Event::Message {
+            sender: Some("Bob".to_string()),
+            text: "lunch?".to_string(),
+            priority: Priority::Normal,
+            expires_at: None,
+            sent_at: None,
+            highlights: Vec::new(),
+        }));
+        assert!(!filters.apply(&mut Event::Action {
+            username: "bob".to_string(),
+            text: "waves".to_string(),
+            sent_at: None,
+            highlights: Vec::new(),
+        }));
+        assert!(!filters.apply(&mut Event::Status {
+            username: "BOB".to_string(),
+            availability: Availability::Away,
+            text: None,
+        }));
+        assert!(!filters.apply(&mut Event::History {
+            id: 7,
+            sender: "bob".to_string(),
+            kind: HistoricalKind::Text,
+            text: "earlier".to_string(),
+            sent_at: Utc::now(),
+        }));
+        assert!(filters.apply(&mut Event::Disconnected));
+    }
+
+    #[test]
+    fn test_highlights_are_recorded_and_colorized() {
+        let filters = filters(&[], &["Deploy", "alice"]);
+        let mut event = message("Alice, the DEPLOY is done");
+        assert!(filters.apply(&mut event));
+        let Event::Message { highlights, .. } = &event else {
+            unreachable!()
+        };
+        assert_eq!(highlights, &["deploy".to_string(), "alice".to_string()]);
+
+        assert_eq!(
+            colorize("Alice, the DEPLOY is done", highlights),
+            "\x1b[1;33mAlice\x1b[0m, the \x1b[1;33mDEPLOY\x1b[0m is done"
+        );
+        assert_eq!(colorize("nothing here", highlights), "nothing here");
+        assert_eq!(colorize("deploy", &[]), "deploy");
+    }
+}
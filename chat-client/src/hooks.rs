@@ -0,0 +1,264 @@
+//! External commands run on events, configured with `--hook`.
+//!
+//! A hook is written as `EVENT[:PATTERN]=COMMAND`, e.g.
+//! `message:deploy=notify-send "Deploy talk"`. Whenever the client emits an
+//! event of that name, the command is started through the shell with the event
+//! on stdin as the same single line of JSON that `--output json` prints, and the
+//! event name in the `CHAT_EVENT` environment variable. With a pattern the hook
+//! only runs if the event's text (a message, a notification, the name of a
+//! received file, ...) contains it, ignoring case.
+//!
+//! Hooks run in the background, so a slow command never holds up the chat.
+//! Their stdout is discarded, their stderr goes to the client's, and a hook
+//! still running after [`HOOK_TIMEOUT`] is killed.
+
+use anyhow::{anyhow, Context, Result};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::output::Event;
+
+/// How long a hook may run before it is killed
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Events a hook can be attached to
+pub const HOOK_EVENTS: [&str; 17] = [
+    "connected",
+    "message",
+    "reply",
+    "action",
+    "poll",
+    "status",
+    "expired",
+    "missed",
+    "unread",
+    "draft",
+    "system",
+    "file",
+    "image",
+    "notification",
+    "auth",
+    "error",
+    "disconnected",
+];
+
+/// A command run when a matching event is emitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    event: String,
+    /// Lowercased text the event must contain
+    pattern: Option<String>,
+    command: String,
+}
+
+impl FromStr for Hook {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let (filter, command) = spec
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Hook '{}' must look like EVENT[:PATTERN]=COMMAND", spec))?;
+        let (event, pattern) = match filter.split_once(':') {
+            Some((event, pattern)) => (event, Some(pattern)),
+            None => (filter, None),
+        };
+        let event = event.trim().to_lowercase();
+        if !HOOK_EVENTS.contains(&event.as_str()) {
+            return Err(anyhow!(
+                "Unknown hook event '{}', expected one of: {}",
+                event,
+                HOOK_EVENTS.join(", ")
+            ));
+        }
+        let command = command.trim();
+        if command.is_empty() {
+            return Err(anyhow!("Hook for '{}' has no command", event));
+        }
+        Ok(Self {
+            event,
+            pattern: pattern
+                .map(|pattern| pattern.to_lowercase())
+                .filter(|pattern| !pattern.is_empty()),
+            command: command.to_string(),
+        })
+    }
+}
+
+impl Hook {
+    /// Whether the hook runs for an event with the given name and text.
+    fn matches(&self, event: &str, text: Option<&str>) -> bool {
+        if self.event != event {
+            return false;
+        }
+        match (&self.pattern, text) {
+            (None, _) => true,
+            (Some(pattern), Some(text)) => text.to_lowercase().contains(pattern.as_str()),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// The configured hooks, cheap to clone into every [`crate::output::Output`].
+#[derive(Debug, Clone, Default)]
+pub struct Hooks(Arc<[Hook]>);
+
+impl Hooks {
+    /// Parses the `--hook` arguments.
+    pub fn parse(specs: &[String]) -> Result<Self> {
+        let hooks = specs
+            .iter()
+            .map(|spec| spec.parse::<Hook>())
+            .collect::<Result<Vec<_>>>()
+            .context("Invalid --hook")?;
+        Ok(Self(hooks.into()))
+    }
+
+    /// Starts the hooks matching an event.
+    ///
+    /// # Arguments
+    /// * `event` - The emitted event
+    /// * `line` - The event as a line of JSON, built only if a hook matches
+    pub fn run(&self, event: &Event, line: impl FnOnce() -> String) {
+        if self.0.is_empty() {
+            return;
+        }
+        let name = event.name();
+        let text = event.text();
+        let mut matching = self
+            .0
+            .iter()
+            .filter(|hook| hook.matches(name, text))
+            .peekable();
+        if matching.peek().is_none() {
+            return;
+        }
+        let line = line();
+        for hook in matching {
+            spawn(hook.command.clone(), name, line.clone());
+        }
+    }
+}
+
+/// Runs a hook command in the background, feeding it the event.
+fn spawn(command: String, event: &'static str, line: String) {
+    tokio::spawn(async move {
+        if let Err(e) = run(&command, event, &line).await {
+            warn!("Hook '{}' failed: {:#}", command, e);
+        }
+    });
+}
+
+async fn run(command: &str, event: &str, line: &str) -> Result<()> {
+    let mut child = shell(command)
+        .env("CHAT_EVENT", event)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its input may exit before reading it
+        if let Err(e) = stdin.write_all(format!("{}\n", line).as_bytes()).await {
+            debug!("Hook '{}' did not read the event: {}", command, e);
+        }
+    }
+    let status = tokio::time::timeout(HOOK_TIMEOUT, child.wait())
+        .await
+        .map_err(|_| anyhow!("Killed after {} seconds", HOOK_TIMEOUT.as_secs()))??;
+    if !status.success() {
+        return Err(anyhow!("Exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_common::Priority;
+
+    fn message(text: &str) -> Event {
+        Event::Message {
+            sender: None,
+            text: text.to_string(),
+            priority: Priority::Normal,
+            expires_at: None,
+            sent_at: None,
+            highlights: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_hooks() {
+        let hook: Hook = "Message:Deploy=notify-send 'a=b'".parse().unwrap();
+        assert_eq!(
+            hook,
+            Hook {
+                event: "message".to_string(),
+                pattern: Some("deploy".to_string()),
+                command: "notify-send 'a=b'".to_string(),
+            }
+        );
+        assert_eq!(
+            "disconnected=./reconnect.sh"
+                .parse::<Hook>()
+                .unwrap()
+                .pattern,
+            None
+        );
+        assert!("message".parse::<Hook>().is_err());
+        assert!("message= ".parse::<Hook>().is_err());
+        assert!("summary=true".parse::<Hook>().is_err());
+    }
+
+    #[test]
+    fn test_hooks_match_event_and_pattern() {
+        let hook: Hook = "message:deploy=true".parse().unwrap();
+        let event = message("Who is DEPLOYING today?");
+        assert!(hook.matches(event.name(), event.text()));
+        let event = message("lunch?");
+        assert!(!hook.matches(event.name(), event.text()));
+        assert!(!hook.matches(Event::Disconnected.name(), Event::Disconnected.text()));
+
+        let hook: Hook = "disconnected=true".parse().unwrap();
+        assert!(hook.matches(Event::Disconnected.name(), Event::Disconnected.text()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hook_gets_event_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("event.json");
+        let command = format!(
+            "echo \"$CHAT_EVENT\" > '{0}' && cat >> '{0}'",
+            path.display()
+        );
+
+        run(&command, "message", "{\"event\":\"message\"}")
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "message\n{\"event\":\"message\"}\n"
+        );
+        assert!(run("exit 3", "message", "{}").await.is_err());
+    }
+}
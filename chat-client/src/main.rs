@@ -1,55 +1,171 @@
 mod commands;
+mod download;
+mod filters;
+mod hooks;
+mod markdown;
 mod message_handler;
 mod network;
+mod outcome;
+mod output;
+mod pending;
+mod replay;
+mod sequence;
+mod state;
+mod timestamps;
 mod ui;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
-use chat_common::{encryption::EncryptionService, Args};
+use chat_common::{
+    async_message_stream::client_handshake, encryption::EncryptionService, recording::Recorder,
+    throttle::RateLimiter, Args, OutputFormat,
+};
 use clap::Parser;
-use std::{fs, sync::Arc};
+use std::{fs, process::ExitCode, sync::Arc};
 use tokio::net::TcpStream;
-use tracing::{info, warn};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
 
+use hooks::Hooks;
+use message_handler::MessageHandler;
 use network::spawn_receiver_task;
+use outcome::{Failure, SessionOutcome};
+use output::{Event, Output};
+use pending::PendingRequests;
+use state::ClientState;
+use timestamps::Timestamps;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing first
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Initialize tracing first, keeping stdout for the events in JSON mode
+    match args.output {
+        OutputFormat::Text => tracing_subscriber::fmt::init(),
+        OutputFormat::Json => tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init(),
+    }
 
     match dotenvy::dotenv() {
         Ok(_) => info!("Successfully loaded .env file"),
         Err(e) => warn!("Failed to load .env file: {}", e),
     }
 
-    let args = Args::parse();
-    println!("Connecting to {}", args.addr());
-    let stream = TcpStream::connect(args.addr())
-        .await
-        .context("Failed to connect to server")?;
-    let (receiver_stream, writer_stream) = stream.into_split();
-    info!("Connected to {}", args.addr());
+    let outcome = SessionOutcome::new();
+    let result = run(&args, outcome.clone()).await;
+    if let Err(e) = &result {
+        error!("{:#}", e);
+    }
 
-    // Initialize encryption service
-    let key =
-        std::env::var("ENCRYPTION_KEY").expect("ENCRYPTION_KEY environment variable must be set");
+    let summary = outcome.summary(result.as_ref().err());
+    let exit_code = summary.exit_code;
+    Output::default()
+        .with_format(args.output)
+        .emit(Event::Summary(summary));
+    ExitCode::from(exit_code)
+}
 
+/// Decodes the base64 `ENCRYPTION_KEY` from the environment.
+fn load_key() -> Result<Vec<u8>> {
+    let key = std::env::var("ENCRYPTION_KEY")
+        .context("ENCRYPTION_KEY environment variable must be set")?;
     let key_bytes = BASE64
         .decode(key)
-        .expect("ENCRYPTION_KEY must be valid base64");
-
+        .context("ENCRYPTION_KEY must be valid base64")?;
     if key_bytes.len() != 32 {
-        panic!("ENCRYPTION_KEY must be exactly 32 bytes when decoded");
+        return Err(anyhow!(
+            "ENCRYPTION_KEY must be exactly 32 bytes when decoded"
+        ));
     }
+    Ok(key_bytes)
+}
 
-    let encryption = Arc::new(EncryptionService::new(&key_bytes)?);
+async fn run(args: &Args, outcome: SessionOutcome) -> Result<()> {
+    // Initialize encryption service
+    let key_bytes = load_key().context(Failure::InvalidKey)?;
+    let encryption = Arc::new(EncryptionService::new(&key_bytes).context(Failure::InvalidKey)?);
+    let hooks = Hooks::parse(&args.hooks)?;
+    let timestamps = Timestamps::new(args.timestamps, args.clock);
 
     // Create directories if they don't exist
     fs::create_dir_all("images").context("Failed to create images directory")?;
     fs::create_dir_all("files").context("Failed to create files directory")?;
 
-    spawn_receiver_task(receiver_stream, Arc::clone(&encryption));
+    let handler = MessageHandler::new(Arc::clone(&encryption))
+        .with_markdown(!args.plain_text)
+        .with_output(args.output)
+        .with_download_dirs(args.download_dirs())
+        .with_image_save_options(args.image_save_options())
+        .with_hooks(hooks)
+        .with_desktop_notifications(!args.no_desktop_notifications)
+        .with_timestamps(timestamps.clone())
+        .with_outcome(outcome.clone());
+
+    if let Some(path) = &args.replay {
+        return if args.replay_to_server {
+            replay::replay_to_server(
+                path,
+                &args.addr(),
+                handler,
+                args.replay_realtime,
+                args.replay_password.as_deref(),
+            )
+            .await
+        } else {
+            replay::replay_into_handler(path, handler, args.replay_realtime).await
+        };
+    }
+
+    let recorder = match &args.record {
+        Some(path) => Some(
+            Recorder::create(path, args.record_payloads)
+                .with_context(|| format!("Failed to create recording {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    if args.output == OutputFormat::Text {
+        println!("Connecting to {}", args.addr());
+    }
+    let mut stream = TcpStream::connect(args.addr())
+        .await
+        .context(Failure::ConnectionRefused)?;
+    // A server of another protocol version rejects the client here
+    client_handshake(&mut stream)
+        .await
+        .context(Failure::ConnectionRefused)?;
+    let (receiver_stream, writer_stream) = stream.into_split();
+    handler
+        .output()
+        .emit(Event::Connected { addr: args.addr() });
+
+    let writer_stream = Arc::new(Mutex::new(writer_stream));
+    let pending = PendingRequests::new();
+    let state = ClientState::open(&args.state_file);
+    spawn_receiver_task(
+        receiver_stream,
+        handler
+            .with_writer(Arc::clone(&writer_stream))
+            .with_pending_requests(pending.clone())
+            .with_state(state.clone())
+            .with_recorder(recorder.clone()),
+        RateLimiter::from_kib(args.download_limit),
+        recorder.clone(),
+    );
 
-    ui::run_input_loop(writer_stream, Arc::clone(&encryption)).await
+    ui::run_input_loop(
+        writer_stream,
+        Arc::clone(&encryption),
+        args.image_downscale(),
+        RateLimiter::from_kib(args.upload_limit),
+        ui::InputSession {
+            pending,
+            recorder,
+            outcome,
+            state,
+            timestamps,
+        },
+    )
+    .await
 }
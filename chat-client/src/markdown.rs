@@ -0,0 +1,194 @@
+//! Terminal rendering of Markdown in incoming text messages.
+//!
+//! Only a small, safe subset is rendered: bold, italics, inline code, code
+//! blocks and links. Everything else is shown as its plain text, raw HTML is
+//! printed literally, and control characters from the sender are stripped so a
+//! message cannot inject its own terminal escape sequences.
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+
+const RESET: &str = "\x1b[0m";
+
+/// A text style that can be active while rendering
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Bold,
+    Italic,
+    Code,
+    Link,
+}
+
+impl Style {
+    fn ansi(&self) -> &'static str {
+        match self {
+            Style::Bold => "\x1b[1m",
+            Style::Italic => "\x1b[3m",
+            Style::Code => "\x1b[36m",
+            Style::Link => "\x1b[4m",
+        }
+    }
+}
+
+/// Accumulates rendered output while keeping track of the active styles.
+#[derive(Default)]
+struct Renderer {
+    output: String,
+    styles: Vec<Style>,
+    links: Vec<String>,
+    in_code_block: bool,
+}
+
+impl Renderer {
+    fn push_style(&mut self, style: Style) {
+        self.styles.push(style);
+        self.output.push_str(style.ansi());
+    }
+
+    fn pop_style(&mut self, style: Style) {
+        if let Some(index) = self.styles.iter().rposition(|active| *active == style) {
+            self.styles.remove(index);
+        }
+        // ANSI has no way to turn off a single attribute portably, so reset and
+        // re-apply whatever is still active
+        self.output.push_str(RESET);
+        for active in &self.styles {
+            self.output.push_str(active.ansi());
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.in_code_block {
+            for line in sanitize(text).lines() {
+                self.output.push_str("    ");
+                self.output.push_str(line);
+                self.output.push('\n');
+            }
+        } else {
+            self.output.push_str(&sanitize(text));
+        }
+    }
+
+    fn end_block(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+    }
+}
+
+/// Removes control characters, keeping newlines and tabs.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .collect()
+}
+
+/// Renders the Markdown subset of a message for display in a terminal.
+///
+/// # Arguments
+/// * `text` - The message text as written by the sender
+///
+/// # Returns
+/// * `String` - The text with ANSI styling applied and Markdown syntax removed
+pub fn render(text: &str) -> String {
+    let mut renderer = Renderer::default();
+
+    for event in Parser::new(text) {
+        match event {
+            Event::Start(Tag::Strong) | Event::Start(Tag::Heading { .. }) => {
+                renderer.push_style(Style::Bold)
+            }
+            Event::End(TagEnd::Strong) => renderer.pop_style(Style::Bold),
+            Event::End(TagEnd::Heading(_)) => {
+                renderer.pop_style(Style::Bold);
+                renderer.end_block();
+            }
+            Event::Start(Tag::Emphasis) => renderer.push_style(Style::Italic),
+            Event::End(TagEnd::Emphasis) => renderer.pop_style(Style::Italic),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                renderer.end_block();
+                if let CodeBlockKind::Fenced(language) = kind {
+                    if !language.is_empty() {
+                        renderer
+                            .output
+                            .push_str(&format!("  [{}]\n", sanitize(&language)));
+                    }
+                }
+                renderer.push_style(Style::Code);
+                renderer.in_code_block = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                renderer.in_code_block = false;
+                renderer.pop_style(Style::Code);
+            }
+            Event::Code(code) => {
+                renderer.push_style(Style::Code);
+                renderer.push_text(&code);
+                renderer.pop_style(Style::Code);
+            }
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => {
+                renderer.links.push(dest_url.into_string());
+                renderer.push_style(Style::Link);
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                renderer.pop_style(Style::Link);
+                if let Some(url) = renderer.links.pop() {
+                    renderer.output.push_str(&format!(" ({})", sanitize(&url)));
+                }
+            }
+            Event::Start(Tag::Item) => renderer.output.push_str("- "),
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Item) => renderer.end_block(),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                renderer.push_text(&text)
+            }
+            Event::SoftBreak => renderer.output.push(' '),
+            Event::HardBreak => renderer.output.push('\n'),
+            Event::Rule => {
+                renderer.end_block();
+                renderer.output.push_str("---\n");
+            }
+            _ => {}
+        }
+    }
+
+    renderer.output.trim_end_matches('\n').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_inline_styles() {
+        assert_eq!(
+            render("**bold** and *italic* and `code`"),
+            "\x1b[1mbold\x1b[0m and \x1b[3mitalic\x1b[0m and \x1b[36mcode\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_render_nested_styles_reapplies_outer_style() {
+        assert_eq!(
+            render("**bold *both* bold**"),
+            "\x1b[1mbold \x1b[3mboth\x1b[0m\x1b[1m bold\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_render_link_and_code_block() {
+        assert_eq!(
+            render("see [docs](https://example.com)"),
+            "see \x1b[4mdocs\x1b[0m (https://example.com)"
+        );
+        assert_eq!(
+            render("```rust\nfn main() {}\n```"),
+            "  [rust]\n\x1b[36m    fn main() {}\n\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_render_strips_control_characters() {
+        assert_eq!(render("hi\x1b[2J there"), "hi[2J there");
+        assert_eq!(render("<b>raw</b>"), "<b>raw</b>");
+    }
+}
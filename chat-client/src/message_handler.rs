@@ -1,33 +1,271 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use chat_common::{
+    archive,
     async_message_stream::AsyncMessageStream,
-    encryption::{file::EncryptedFileMetadata, message::EncryptedMessage, EncryptionService},
+    encryption::{
+        file::EncryptedFileMetadata,
+        keyring::{KeyWrapper, WrappedKey},
+        message::EncryptedMessage,
+        EncryptionService,
+    },
     error::ChatError,
-    file_ops, Message,
+    file_ops::{self, DownloadDirs, ImageSaveOptions, Origin, PartialFile},
+    recording::{Direction, Recorder},
+    Message, OutputFormat, Priority,
 };
-use std::sync::Arc;
-use tokio::io::BufReader;
-use tracing::{error, info};
+use chrono::Utc;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::io::{AsyncWrite, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+use crate::download::Destination;
+use crate::filters;
+use crate::hooks::Hooks;
+use crate::outcome::{Failure, SessionOutcome};
+use crate::output::{Event, Output};
+use crate::pending::PendingRequests;
+use crate::sequence::SequenceTracker;
+use crate::state::ClientState;
+use crate::timestamps::Timestamps;
 
 pub struct MessageHandler {
     encryption: Arc<EncryptionService>,
+    writer: Option<Arc<Mutex<OwnedWriteHalf>>>,
+    output: Output,
+    pending: Option<PendingRequests>,
+    recorder: Option<Recorder>,
+    outcome: Option<SessionOutcome>,
+    /// Saved drafts, restored when a workspace is entered
+    state: Option<ClientState>,
+    /// Where received files and images are saved
+    download_dirs: DownloadDirs,
+    /// The format received images are saved in
+    image_save: ImageSaveOptions,
+    /// Whether highlighted messages raise a desktop notification
+    desktop_notifications: bool,
+    /// Session token from the last successful login, used to unwrap room keys
+    token: StdMutex<Option<String>>,
+    /// Sequence numbers seen per workspace, to notice missed messages
+    sequences: StdMutex<SequenceTracker>,
 }
 
 impl MessageHandler {
     pub fn new(encryption: Arc<EncryptionService>) -> Self {
-        Self { encryption }
+        Self {
+            encryption,
+            writer: None,
+            output: Output::default(),
+            pending: None,
+            recorder: None,
+            outcome: None,
+            state: None,
+            download_dirs: DownloadDirs::default(),
+            image_save: ImageSaveOptions::default(),
+            desktop_notifications: false,
+            token: StdMutex::new(None),
+            sequences: StdMutex::new(SequenceTracker::default()),
+        }
+    }
+
+    /// Attaches the connection's write half so the handler can answer heartbeats.
+    pub fn with_writer(mut self, writer: Arc<Mutex<OwnedWriteHalf>>) -> Self {
+        self.writer = Some(writer);
+        self
+    }
+
+    /// Attaches the sent requests so errors can name the send that failed.
+    pub fn with_pending_requests(mut self, pending: PendingRequests) -> Self {
+        self.pending = Some(pending);
+        self
+    }
+
+    /// Attaches the client state so entering a workspace restores its draft
+    /// and downloads are saved where they were requested.
+    pub fn with_state(mut self, state: ClientState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Chooses the directories received files and images are saved into.
+    pub fn with_download_dirs(mut self, download_dirs: DownloadDirs) -> Self {
+        self.download_dirs = download_dirs;
+        self
+    }
+
+    /// Chooses the format received images are saved in.
+    pub fn with_image_save_options(mut self, image_save: ImageSaveOptions) -> Self {
+        self.image_save = image_save;
+        self
+    }
+
+    /// Turns desktop notifications for highlighted messages on or off.
+    pub fn with_desktop_notifications(mut self, desktop_notifications: bool) -> Self {
+        self.desktop_notifications = desktop_notifications;
+        self
+    }
+
+    /// Records the heartbeat answers the handler sends.
+    pub fn with_recorder(mut self, recorder: Option<Recorder>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Reports failed logins and sends, and heartbeat answers, to the session outcome.
+    pub fn with_outcome(mut self, outcome: SessionOutcome) -> Self {
+        self.outcome = Some(outcome);
+        self
+    }
+
+    /// Turns Markdown rendering of incoming text messages on or off.
+    pub fn with_markdown(mut self, render_markdown: bool) -> Self {
+        self.output = self.output.with_markdown(render_markdown);
+        self
+    }
+
+    /// Chooses how received messages and events are printed.
+    pub fn with_output(mut self, format: OutputFormat) -> Self {
+        self.output = self.output.with_format(format);
+        self
+    }
+
+    /// Runs external commands on the events the handler emits.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.output = self.output.with_hooks(hooks);
+        self
+    }
+
+    /// Shows the timestamps of received messages with these settings.
+    pub fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.output = self.output.with_timestamps(timestamps);
+        self
+    }
+
+    /// Returns where the handler prints its events.
+    pub fn output(&self) -> Output {
+        self.output.clone()
+    }
+
+    /// Decrypts a received file or image into `writer`.
+    ///
+    /// # Returns
+    /// * `Result<bool, ChatError>` - `false` if the attachment failed its integrity
+    ///   check and must be discarded
+    async fn decrypt_attachment<W: AsyncWrite + Unpin>(
+        &self,
+        name: &str,
+        data: &[u8],
+        writer: W,
+        metadata: &EncryptedFileMetadata,
+    ) -> Result<bool, ChatError> {
+        let decrypted = match self.encryption.file_for(metadata.key_id.as_deref()) {
+            Ok(encryption) => {
+                encryption
+                    .decrypt_stream(BufReader::new(data), writer, metadata)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match decrypted.map_err(ChatError::from) {
+            Ok(()) => Ok(true),
+            Err(ChatError::IntegrityError(e)) => {
+                error!("Discarding corrupted attachment '{}': {}", name, e);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Describes where a received attachment comes from, for the download
+    /// directory templates. The sender is known from the `ChatMessage`
+    /// envelope of files and images, but not for downloaded attachments.
+    fn origin(&self, sender: Option<String>) -> Origin {
+        let room = self.state.as_ref().and_then(ClientState::current_workspace);
+        Origin::now(room, sender)
+    }
+
+    /// Prints a received message, action or status change unless it is muted,
+    /// colorizing its highlights and notifying about them.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the event was shown
+    fn emit_filtered(&self, mut event: Event) -> bool {
+        let filters = self
+            .state
+            .as_ref()
+            .map(ClientState::filters)
+            .unwrap_or_default();
+        if !filters.apply(&mut event) {
+            debug!("Hiding a muted {}", event.name());
+            return false;
+        }
+        if let Event::Message {
+            text, highlights, ..
+        }
+        | Event::Reply {
+            text, highlights, ..
+        }
+        | Event::Action {
+            text, highlights, ..
+        } = &event
+        {
+            if self.desktop_notifications && !highlights.is_empty() {
+                filters::notify_desktop(format!("Chat: {}", highlights.join(", ")), text.clone());
+            }
+        }
+        self.output.emit(event);
+        true
+    }
+
+    /// Decrypts a text message with the key it was encrypted with.
+    fn decrypt_text(&self, encrypted: &EncryptedMessage) -> Result<String> {
+        self.encryption
+            .message_for(encrypted.key_id.as_deref())?
+            .decrypt(encrypted)
+    }
+
+    /// Unwraps the key of the workspace the connection entered with the
+    /// session token and makes it the key new messages are encrypted with.
+    fn activate_room_key(&self, key_id: &str, wrapped: WrappedKey) -> Result<()> {
+        let token = self.token.lock().unwrap().clone();
+        let token = token.ok_or_else(|| anyhow!("Received a room key before logging in"))?;
+        let key = KeyWrapper::new(token.as_bytes()).unwrap(&wrapped)?;
+        self.encryption.add_key(key_id, &key)?;
+        self.encryption.set_active_key(Some(key_id))
     }
 
     /// Handles incoming messages from the chat server.
     ///
     /// This function processes different types of messages:
-    /// - Text messages: Decrypts and logs the content
+    /// - Text messages: Decrypts, renders Markdown and logs the content
+    /// - PriorityText messages: Like text messages, with urgent ones highlighted
+    /// - Ephemeral messages: Like text messages, with a notice once they expire
+    /// - Reply messages: Like text messages, preceded by the quoted message
+    /// - Action messages: Like text messages, shown as `* alice waves`
+    /// - PollTally messages: Logs the poll with the votes of each option
+    /// - StatusChanged messages: Logs the user's new status
     /// - System messages: Logs system notifications
     /// - File messages: Decrypts and saves received files
     /// - Image messages: Decrypts and saves received images
-    /// - Error messages: Logs server errors
+    /// - Error messages: Logs server errors, naming the failed request when known
     /// - Auth messages: Handles authentication responses
+    /// - RoomKey messages: Unwraps the workspace key and encrypts with it from now on,
+    ///   reporting messages stored since the workspace was last visited and
+    ///   showing the workspace's draft
+    /// - ChatMessage messages: Handles the message they carry, showing it with
+    ///   the username of its sender
+    /// - Sequenced messages: Reports skipped sequence numbers, then handles the
+    ///   message they carry
+    /// - Ping messages: Answers the server's heartbeat with a Pong
+    /// - Notification messages: Logs new inbox entries such as mentions
+    /// - Activity messages: Counts the unread messages of other workspaces
+    /// - History messages: Decrypts and logs the latest messages of the
+    ///   workspace, sent after logging in
+    /// - Edit messages: Decrypts and logs the new text of an edited message
+    /// - Delete messages: Logs that a message was deleted
     ///
     /// # Arguments
     /// * `stream` - A stream that implements AsyncMessageStream to read messages from
@@ -39,7 +277,8 @@ impl MessageHandler {
     ///
     /// ## Text Messages
     /// Text messages are encrypted and need to be decrypted using the encryption service.
-    /// The decrypted content is logged using the info level.
+    /// The decrypted content is rendered as Markdown, unless plain text output
+    /// was requested, and logged using the info level.
     ///
     /// ## System Messages
     /// System messages are plain text notifications from the server.
@@ -47,7 +286,8 @@ impl MessageHandler {
     ///
     /// ## File Messages
     /// File messages contain encrypted file data and metadata.
-    /// The file is decrypted and saved to the local filesystem.
+    /// The file is decrypted, checked against the SHA-256 in its metadata and
+    /// saved to the local filesystem; corrupted files are discarded.
     ///
     /// ## Image Messages
     /// Image messages are similar to file messages but are specifically for image files.
@@ -87,6 +327,7 @@ impl MessageHandler {
     ///
     /// # Notes
     /// * The function runs in a loop until the stream is closed or an error occurs
+    /// * Messages and events are printed through [`Output`], as log lines or as JSON
     /// * All errors are logged using the error level
     /// * Successful operations are logged using the info level
     /// * Files and images are saved to the local filesystem in the current directory
@@ -95,6 +336,32 @@ impl MessageHandler {
         mut stream: S,
     ) -> Result<(), ChatError> {
         while let Ok(message) = AsyncMessageStream::read_message(&mut stream).await {
+            let (message, sender, delivered_at) = match message {
+                Message::ChatMessage {
+                    sender,
+                    sent_at,
+                    body,
+                } => (*body, Some(sender), Some(sent_at)),
+                message => (message, None, None),
+            };
+            let (message, sent_at) = match message {
+                Message::Sequenced {
+                    seq,
+                    sent_at,
+                    message,
+                } => {
+                    let missed = self.sequences.lock().unwrap().observe(seq);
+                    if let Some((workspace, missed)) = missed {
+                        self.output.emit(Event::Missed {
+                            workspace,
+                            from: *missed.start(),
+                            to: *missed.end(),
+                        });
+                    }
+                    (*message, sent_at.or(delivered_at))
+                }
+                message => (message, delivered_at),
+            };
             match message {
                 Message::Text(encrypted) => {
                     // Decrypt the message
@@ -105,21 +372,160 @@ impl MessageHandler {
                                 e
                             ))
                         })?;
-                    match self.encryption.message().decrypt(&encrypted) {
-                        Ok(text) => info!("Received: {}", text),
+                    match self.decrypt_text(&encrypted) {
+                        Ok(text) => {
+                            self.emit_filtered(Event::Message {
+                                sender,
+                                text,
+                                priority: Priority::Normal,
+                                expires_at: None,
+                                sent_at,
+                                highlights: Vec::new(),
+                            });
+                        }
+                        Err(e) => error!("Failed to decrypt message: {}", e),
+                    }
+                }
+                Message::PriorityText { priority, content } => {
+                    let encrypted: EncryptedMessage =
+                        serde_json::from_str(&content).map_err(|e| {
+                            ChatError::SerializationError(format!(
+                                "Failed to parse encrypted message: {}",
+                                e
+                            ))
+                        })?;
+                    match self.decrypt_text(&encrypted) {
+                        Ok(text) => {
+                            self.emit_filtered(Event::Message {
+                                sender,
+                                text,
+                                priority,
+                                expires_at: None,
+                                sent_at,
+                                highlights: Vec::new(),
+                            });
+                        }
                         Err(e) => error!("Failed to decrypt message: {}", e),
                     }
                 }
-                Message::System(notification) => {
-                    info!("System: {}", notification);
+                Message::Ephemeral {
+                    content,
+                    expires_at,
+                    ..
+                } => {
+                    let encrypted: EncryptedMessage =
+                        serde_json::from_str(&content).map_err(|e| {
+                            ChatError::SerializationError(format!(
+                                "Failed to parse encrypted message: {}",
+                                e
+                            ))
+                        })?;
+                    let expires_at = expires_at.unwrap_or_else(Utc::now);
+                    let remaining = (expires_at - Utc::now()).to_std().unwrap_or_default();
+                    if remaining.is_zero() {
+                        // Delivered too late to be shown at all
+                        continue;
+                    }
+                    match self.decrypt_text(&encrypted) {
+                        Ok(text) => {
+                            let shown = self.emit_filtered(Event::Message {
+                                sender,
+                                text,
+                                priority: Priority::Normal,
+                                expires_at: Some(expires_at),
+                                sent_at,
+                                highlights: Vec::new(),
+                            });
+                            if !shown {
+                                continue;
+                            }
+                            // Printed lines cannot be taken back, so mark the expiry instead
+                            let output = self.output.clone();
+                            tokio::spawn(async move {
+                                tokio::time::sleep(remaining).await;
+                                output.emit(Event::Expired);
+                            });
+                        }
+                        Err(e) => error!("Failed to decrypt message: {}", e),
+                    }
+                }
+                Message::Reply { content, quote } => {
+                    let encrypted: EncryptedMessage =
+                        serde_json::from_str(&content).map_err(|e| {
+                            ChatError::SerializationError(format!(
+                                "Failed to parse encrypted message: {}",
+                                e
+                            ))
+                        })?;
+                    // A quote the server could not fill in is shown without its excerpt
+                    let excerpt = serde_json::from_str(&quote.excerpt)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|excerpt| self.decrypt_text(&excerpt))
+                        .unwrap_or_default();
+                    match self.decrypt_text(&encrypted) {
+                        Ok(text) => {
+                            self.emit_filtered(Event::Reply {
+                                sender,
+                                text,
+                                quoted_id: quote.id,
+                                quoted_author: quote.author,
+                                quoted_excerpt: excerpt,
+                                sent_at,
+                                highlights: Vec::new(),
+                            });
+                        }
+                        Err(e) => error!("Failed to decrypt message: {}", e),
+                    }
                 }
+                Message::Action { content, username } => {
+                    let encrypted: EncryptedMessage =
+                        serde_json::from_str(&content).map_err(|e| {
+                            ChatError::SerializationError(format!(
+                                "Failed to parse encrypted message: {}",
+                                e
+                            ))
+                        })?;
+                    match self.decrypt_text(&encrypted) {
+                        Ok(text) => {
+                            self.emit_filtered(Event::Action {
+                                username: username.unwrap_or_else(|| "someone".to_string()),
+                                text,
+                                sent_at,
+                                highlights: Vec::new(),
+                            });
+                        }
+                        Err(e) => error!("Failed to decrypt message: {}", e),
+                    }
+                }
+                Message::PollTally {
+                    poll_id,
+                    question,
+                    options,
+                    votes,
+                } => self.output.emit(Event::Poll {
+                    poll_id,
+                    question,
+                    options,
+                    votes,
+                }),
+                Message::StatusChanged {
+                    username,
+                    availability,
+                    text,
+                } => {
+                    self.emit_filtered(Event::Status {
+                        username,
+                        availability,
+                        text,
+                    });
+                }
+                Message::System(message) => self.output.emit(Event::System { message }),
                 Message::File {
                     name,
                     metadata,
                     data,
                 } => {
                     info!("Receiving encrypted file: {}", name);
-                    let mut buffer = Vec::new();
 
                     let metadata: EncryptedFileMetadata = serde_json::from_value(metadata)
                         .map_err(|e| {
@@ -129,13 +535,34 @@ impl MessageHandler {
                             ))
                         })?;
 
-                    self.encryption
-                        .file()
-                        .decrypt_stream(BufReader::new(&data[..]), &mut buffer, &metadata)
-                        .await?;
+                    let dir = self.download_dirs.files.expand(&self.origin(sender));
+                    let mut partial = match PartialFile::create(&dir).await {
+                        Ok(partial) => partial,
+                        Err(e) => {
+                            error!("Failed to save file '{}': {}", name, e);
+                            continue;
+                        }
+                    };
+                    if !self
+                        .decrypt_attachment(&name, &data, partial.file(), &metadata)
+                        .await?
+                    {
+                        continue;
+                    }
 
-                    if let Err(e) = file_ops::save_file(&name, buffer).await {
-                        error!("{}", e);
+                    match partial.commit(&name).await {
+                        Ok(path) => {
+                            if archive::is_archive_name(&name) {
+                                info!(
+                                    "Received directory archive '{}'. Use `.extract {}` to unpack it into {}/",
+                                    name,
+                                    name,
+                                    archive::EXTRACT_DIR
+                                );
+                            }
+                            self.output.emit(Event::File { name, path });
+                        }
+                        Err(e) => error!("{}", e),
                     }
                 }
                 Message::Image {
@@ -154,32 +581,231 @@ impl MessageHandler {
                             ))
                         })?;
 
-                    self.encryption
-                        .file()
-                        .decrypt_stream(BufReader::new(&data[..]), &mut buffer, &metadata)
-                        .await?;
+                    if !self
+                        .decrypt_attachment(&name, &data, &mut buffer, &metadata)
+                        .await?
+                    {
+                        continue;
+                    }
 
                     info!("Decrypted image size: {}", buffer.len());
-                    if let Err(e) = file_ops::save_image(&name, buffer).await {
-                        error!("Failed to save image: {}", e);
+                    let dir = self.download_dirs.images.expand(&self.origin(sender));
+                    match file_ops::save_image(&dir, &name, buffer, self.image_save).await {
+                        Ok(path) => self.output.emit(Event::Image { name, path }),
+                        Err(e) => error!("Failed to save image: {}", e),
+                    }
+                }
+                Message::Attachment {
+                    message_id,
+                    name,
+                    metadata,
+                    data,
+                } => {
+                    info!(
+                        "Receiving the attachment of message #{}: {}",
+                        message_id, name
+                    );
+
+                    let metadata: EncryptedFileMetadata = serde_json::from_value(metadata)
+                        .map_err(|e| {
+                            ChatError::SerializationError(format!(
+                                "Failed to parse attachment metadata: {}",
+                                e
+                            ))
+                        })?;
+
+                    let destination = self
+                        .state
+                        .as_ref()
+                        .and_then(|state| state.take_download(message_id))
+                        .unwrap_or_else(|| {
+                            Destination::Directory(
+                                self.download_dirs.files.expand(&self.origin(None)),
+                            )
+                        });
+                    let mut partial = match PartialFile::create(destination.dir()).await {
+                        Ok(partial) => partial,
+                        Err(e) => {
+                            error!(
+                                "Failed to save the attachment of message #{}: {}",
+                                message_id, e
+                            );
+                            continue;
+                        }
+                    };
+                    if !self
+                        .decrypt_attachment(&name, &data, partial.file(), &metadata)
+                        .await?
+                    {
+                        continue;
+                    }
+
+                    match destination.commit(partial, &name).await {
+                        Ok(path) => self.output.emit(Event::File { name, path }),
+                        Err(e) => error!(
+                            "Failed to save the attachment of message #{}: {:#}",
+                            message_id, e
+                        ),
                     }
                 }
-                Message::Error { code, message } => {
-                    error!("Server error [{}]: {}", format!("{:?}", code), message);
+                Message::Error {
+                    code,
+                    message,
+                    in_reply_to,
+                    details,
+                } => {
+                    let request = in_reply_to
+                        .zip(self.pending.as_ref())
+                        .and_then(|(id, pending)| pending.take(&id));
+                    if let Some(outcome) = &self.outcome {
+                        let reason = match &request {
+                            Some(request) => format!("{} failed: {}", request, message),
+                            None => message.clone(),
+                        };
+                        outcome.fail(Failure::SendFailed, reason);
+                    }
+                    self.output.emit(Event::Error {
+                        code,
+                        message,
+                        request,
+                        details,
+                    });
                 }
                 Message::AuthResponse {
                     success,
-                    token: _token,
+                    token,
                     message,
                 } => {
+                    if let Some(outcome) = self.outcome.as_ref().filter(|_| !success) {
+                        outcome.fail(Failure::AuthFailed, message.clone());
+                    }
                     if success {
-                        info!("Authentication successful: {}", message);
-                    } else {
-                        error!("Authentication failed: {}", message);
+                        *self.token.lock().unwrap() = token;
+                    }
+                    self.output.emit(Event::Auth { success, message })
+                }
+                Message::RoomKey {
+                    workspace,
+                    key_id,
+                    wrapped_key,
+                    nonce,
+                    last_seq,
+                } => {
+                    match self.activate_room_key(&key_id, WrappedKey { wrapped_key, nonce }) {
+                        Ok(()) => debug!("Encrypting with the key of workspace {}", workspace),
+                        Err(e) => error!("Failed to use the key of workspace {}: {}", workspace, e),
+                    }
+                    let draft = self
+                        .state
+                        .as_ref()
+                        .and_then(|state| state.enter(&workspace));
+                    let missed = self.sequences.lock().unwrap().enter(&workspace, last_seq);
+                    if let Some(missed) = missed {
+                        self.output.emit(Event::Missed {
+                            workspace: workspace.clone(),
+                            from: *missed.start(),
+                            to: *missed.end(),
+                        });
+                    }
+                    if let Some(text) = draft {
+                        self.output.emit(Event::Draft { workspace, text });
                     }
                 }
-                Message::Auth { .. } => {
-                    // Client doesn't need to handle incoming Auth messages
+                Message::History(history) => {
+                    for message in history {
+                        let text = match (message.content, message.file_name) {
+                            (Some(content), _) => {
+                                let decrypted = serde_json::from_str::<EncryptedMessage>(&content)
+                                    .map_err(|e| {
+                                        anyhow!("Failed to parse encrypted message: {}", e)
+                                    })
+                                    .and_then(|encrypted| self.decrypt_text(&encrypted));
+                                match decrypted {
+                                    Ok(text) => text,
+                                    Err(e) => {
+                                        error!("Failed to decrypt message #{}: {}", message.id, e);
+                                        continue;
+                                    }
+                                }
+                            }
+                            (None, Some(name)) => name,
+                            (None, None) => continue,
+                        };
+                        self.emit_filtered(Event::History {
+                            id: message.id,
+                            sender: message.sender,
+                            kind: message.kind,
+                            text,
+                            sent_at: message.sent_at,
+                        });
+                    }
+                }
+                Message::Edit {
+                    message_id,
+                    new_content,
+                } => {
+                    let encrypted: EncryptedMessage =
+                        serde_json::from_str(&new_content).map_err(|e| {
+                            ChatError::SerializationError(format!(
+                                "Failed to parse encrypted message: {}",
+                                e
+                            ))
+                        })?;
+                    match self.decrypt_text(&encrypted) {
+                        Ok(text) => {
+                            self.emit_filtered(Event::Edited {
+                                id: message_id,
+                                text,
+                            });
+                        }
+                        Err(e) => error!("Failed to decrypt message #{}: {}", message_id, e),
+                    }
+                }
+                Message::Delete { message_id } => {
+                    self.output.emit(Event::Deleted { id: message_id })
+                }
+                Message::Activity { workspace } => {
+                    let count = self
+                        .state
+                        .as_ref()
+                        .and_then(|state| state.count_unread(&workspace));
+                    if let Some(count) = count {
+                        self.output.emit(Event::Unread { workspace, count });
+                    }
+                }
+                Message::Auth { .. }
+                | Message::Register { .. }
+                | Message::SwitchWorkspace { .. }
+                | Message::ChangePassword { .. }
+                | Message::Request { .. }
+                | Message::Poll { .. }
+                | Message::Vote { .. }
+                | Message::SetStatus { .. }
+                | Message::Report { .. }
+                | Message::Download { .. }
+                | Message::Hello { .. }
+                | Message::Sequenced { .. }
+                | Message::ChatMessage { .. } => {
+                    // Client doesn't need to handle incoming requests or nested envelopes
+                }
+                Message::Ping { nonce } => {
+                    debug!("Heartbeat {} received", nonce);
+                    if let Some(writer) = &self.writer {
+                        let pong = Message::Pong { nonce };
+                        let mut writer = writer.lock().await;
+                        writer.write_message(&pong).await?;
+                        if let Some(recorder) = &self.recorder {
+                            recorder.record(Direction::Sent, &pong)?;
+                        }
+                    }
+                }
+                Message::Pong { nonce } => {
+                    if let Some(outcome) = &self.outcome {
+                        outcome.pong(nonce);
+                    }
+                }
+                Message::Notification { id, kind, content } => {
+                    self.output.emit(Event::Notification { id, kind, content })
                 }
             }
         }
@@ -243,7 +869,7 @@ mod tests {
         let message = file_ops::create_error_message(&error);
 
         match message {
-            Message::Error { code, message } => {
+            Message::Error { code, message, .. } => {
                 assert_eq!(code, ErrorCode::FileNotFound);
                 assert_eq!(message, "File not found: test.txt");
             }
@@ -277,6 +903,27 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_handle_reply_message() {
+        let encryption = Arc::new(EncryptionService::new(&[0u8; 32]).unwrap());
+        let handler = MessageHandler::new(encryption.clone());
+        let encrypt =
+            |text| serde_json::to_string(&encryption.message().encrypt(text).unwrap()).unwrap();
+
+        let message = Message::Reply {
+            content: encrypt("Agreed"),
+            quote: chat_common::QuotedMessage {
+                id: 3,
+                author: "alice".to_string(),
+                excerpt: encrypt("Lunch at noon?"),
+            },
+        };
+        let stream = TestStream::new(vec![message]);
+
+        let result = handler.handle_incoming(stream).await;
+        assert!(result.is_ok());
+    }
+
     #[tokio::test]
     async fn test_handle_system_message() {
         let encryption = Arc::new(EncryptionService::new(&[0u8; 32]).unwrap());
@@ -305,15 +952,62 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_room_key_becomes_active_key() {
+        let encryption = Arc::new(EncryptionService::new(&[0u8; 32]).unwrap());
+        let handler = MessageHandler::new(encryption.clone());
+
+        let wrapped = KeyWrapper::new(b"test_token").wrap(&[1u8; 32]).unwrap();
+        let stream = TestStream::new(vec![
+            Message::AuthResponse {
+                success: true,
+                token: Some("test_token".to_string()),
+                message: "Authentication successful".to_string(),
+            },
+            Message::RoomKey {
+                workspace: "default".to_string(),
+                key_id: "room-1".to_string(),
+                wrapped_key: wrapped.wrapped_key,
+                nonce: wrapped.nonce,
+                last_seq: 0,
+            },
+        ]);
+
+        handler.handle_incoming(stream).await.unwrap();
+        assert_eq!(encryption.active_key().as_deref(), Some("room-1"));
+    }
+
+    #[tokio::test]
+    async fn test_room_key_wrapped_for_other_session_is_ignored() {
+        let encryption = Arc::new(EncryptionService::new(&[0u8; 32]).unwrap());
+        let handler = MessageHandler::new(encryption.clone());
+
+        let wrapped = KeyWrapper::new(b"other_token").wrap(&[1u8; 32]).unwrap();
+        let stream = TestStream::new(vec![
+            Message::AuthResponse {
+                success: true,
+                token: Some("test_token".to_string()),
+                message: "Authentication successful".to_string(),
+            },
+            Message::RoomKey {
+                workspace: "default".to_string(),
+                key_id: "room-1".to_string(),
+                wrapped_key: wrapped.wrapped_key,
+                nonce: wrapped.nonce,
+                last_seq: 0,
+            },
+        ]);
+
+        handler.handle_incoming(stream).await.unwrap();
+        assert_eq!(encryption.active_key(), None);
+    }
+
     #[tokio::test]
     async fn test_handle_error_message() {
         let encryption = Arc::new(EncryptionService::new(&[0u8; 32]).unwrap());
         let handler = MessageHandler::new(encryption);
 
-        let message = Message::Error {
-            code: ErrorCode::PermissionDenied,
-            message: "Access denied".to_string(),
-        };
+        let message = Message::error(ErrorCode::PermissionDenied, "Access denied");
         let stream = TestStream::new(vec![message]);
 
         let result = handler.handle_incoming(stream).await;
@@ -332,10 +1026,7 @@ mod tests {
                 serde_json::to_string(&encryption.message().encrypt("Hello").unwrap()).unwrap(),
             ),
             Message::System("User joined".to_string()),
-            Message::Error {
-                code: ErrorCode::InvalidInput,
-                message: "Invalid command".to_string(),
-            },
+            Message::error(ErrorCode::InvalidInput, "Invalid command"),
         ];
 
         let stream = TestStream::new(messages);
@@ -1,15 +1,46 @@
-use chat_common::encryption::EncryptionService;
-use std::sync::Arc;
+use chat_common::async_message_stream::AsyncMessageStream;
+use chat_common::error::ChatError;
+use chat_common::recording::{Recorder, RecordingStream};
+use chat_common::throttle::{RateLimiter, ThrottledReader};
 use tokio::net::tcp::OwnedReadHalf;
 use tracing::error;
 
 use crate::message_handler::MessageHandler;
+use crate::output::Event;
 
-pub fn spawn_receiver_task(stream: OwnedReadHalf, encryption: Arc<EncryptionService>) {
+/// Hands the server's messages to `handler` until the connection closes.
+pub fn spawn_receiver_task(
+    stream: OwnedReadHalf,
+    handler: MessageHandler,
+    download_limiter: Option<RateLimiter>,
+    recorder: Option<Recorder>,
+) {
     tokio::spawn(async move {
-        let handler = MessageHandler::new(encryption);
-        if let Err(e) = handler.handle_incoming(stream).await {
+        let result = match download_limiter {
+            Some(limiter) => {
+                receive(&handler, ThrottledReader::new(stream, limiter), recorder).await
+            }
+            None => receive(&handler, stream, recorder).await,
+        };
+        if let Err(e) = result {
             error!("Error handling incoming messages: {}", e);
         }
+        handler.output().emit(Event::Disconnected);
     });
 }
+
+/// Hands incoming messages to the handler, recording them if requested.
+async fn receive<S: AsyncMessageStream + Send>(
+    handler: &MessageHandler,
+    stream: S,
+    recorder: Option<Recorder>,
+) -> Result<(), ChatError> {
+    match recorder {
+        Some(recorder) => {
+            handler
+                .handle_incoming(RecordingStream::new(stream, recorder))
+                .await
+        }
+        None => handler.handle_incoming(stream).await,
+    }
+}
@@ -0,0 +1,225 @@
+//! Exit codes and the end-of-session summary for scripts driving the client.
+//!
+//! Every failure has its own exit code. Errors that end the client early are
+//! tagged with a [`Failure`] as `anyhow` context, failures reported by the
+//! server during the session are collected in a [`SessionOutcome`]. When the
+//! client exits it prints a [`Summary`] so wrappers can branch on the result.
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Why the client failed. The discriminant is the exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Any error without a more specific code
+    Other = 1,
+    /// The server could not be reached
+    ConnectionRefused = 2,
+    /// The server rejected the login
+    AuthFailed = 3,
+    /// A message could not be sent or the server rejected it
+    SendFailed = 4,
+    /// `ENCRYPTION_KEY` is missing or not a base64-encoded 32-byte key
+    InvalidKey = 5,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> u8 {
+        self as u8
+    }
+
+    /// Name of the failure in the summary
+    pub fn status(self) -> &'static str {
+        match self {
+            Failure::Other => "error",
+            Failure::ConnectionRefused => "connection_refused",
+            Failure::AuthFailed => "auth_failed",
+            Failure::SendFailed => "send_failed",
+            Failure::InvalidKey => "invalid_key",
+        }
+    }
+
+    /// Returns the failure an error was tagged with.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<Failure>()
+            .copied()
+            .unwrap_or(Failure::Other)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Other => "Client error",
+            Failure::ConnectionRefused => "Failed to connect to server",
+            Failure::AuthFailed => "Authentication failed",
+            Failure::SendFailed => "Failed to send message",
+            Failure::InvalidKey => "Invalid encryption key",
+        })
+    }
+}
+
+/// The result of a session, printed when the client exits.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub status: &'static str,
+    pub exit_code: u8,
+    /// Messages written to the server
+    pub sent: usize,
+    /// Messages the client or the server reported as failed
+    pub failed: usize,
+    /// Description of the first failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for Summary {
+    /// Formats the summary as `key=value` pairs, quoting the reason.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "status={} exit_code={} sent={} failed={}",
+            self.status, self.exit_code, self.sent, self.failed
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, " reason={:?}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    sent: usize,
+    failed: usize,
+    failure: Option<(Failure, String)>,
+}
+
+/// Sent messages and failures of a session, shared between the input loop and
+/// the message handler.
+#[derive(Clone)]
+pub struct SessionOutcome {
+    inner: Arc<Mutex<Inner>>,
+    pong: Arc<watch::Sender<Option<u64>>>,
+}
+
+impl Default for SessionOutcome {
+    fn default() -> Self {
+        Self {
+            inner: Arc::default(),
+            pong: Arc::new(watch::channel(None).0),
+        }
+    }
+}
+
+impl SessionOutcome {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a message written to the server.
+    pub fn sent(&self) {
+        self.inner.lock().unwrap().sent += 1;
+    }
+
+    pub fn sent_count(&self) -> usize {
+        self.inner.lock().unwrap().sent
+    }
+
+    /// Records a failure. The first failure decides the exit code.
+    pub fn fail(&self, failure: Failure, reason: impl Into<String>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failed += 1;
+        inner.failure.get_or_insert((failure, reason.into()));
+    }
+
+    /// Records the server's answer to a heartbeat sent by the client.
+    pub fn pong(&self, nonce: u64) {
+        self.pong.send_replace(Some(nonce));
+    }
+
+    /// Waits until the server answers the heartbeat with `nonce`. The server
+    /// answers in order, so all replies to earlier messages have arrived by then.
+    ///
+    /// # Returns
+    /// * `bool` - `false` if no answer came within `timeout`
+    pub async fn wait_for_pong(&self, nonce: u64, timeout: Duration) -> bool {
+        let mut pong = self.pong.subscribe();
+        tokio::time::timeout(timeout, pong.wait_for(|pong| *pong == Some(nonce)))
+            .await
+            .is_ok_and(|answered| answered.is_ok())
+    }
+
+    /// Summarizes the session, given the error that ended it early, if any.
+    pub fn summary(&self, error: Option<&anyhow::Error>) -> Summary {
+        let inner = self.inner.lock().unwrap();
+        let failure = match error {
+            Some(error) => Some((Failure::of(error), format!("{:#}", error))),
+            None => inner.failure.clone(),
+        };
+        Summary {
+            status: failure
+                .as_ref()
+                .map_or("ok", |(failure, _)| failure.status()),
+            exit_code: failure
+                .as_ref()
+                .map_or(0, |(failure, _)| failure.exit_code()),
+            sent: inner.sent,
+            failed: inner.failed,
+            reason: failure.map(|(_, reason)| reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_summary() {
+        let outcome = SessionOutcome::new();
+        outcome.sent();
+        outcome.sent();
+        assert_eq!(
+            outcome.summary(None).to_string(),
+            "status=ok exit_code=0 sent=2 failed=0"
+        );
+
+        outcome.fail(Failure::AuthFailed, "Invalid credentials");
+        outcome.fail(Failure::SendFailed, "Not authenticated");
+        assert_eq!(
+            outcome.summary(None).to_string(),
+            "status=auth_failed exit_code=3 sent=2 failed=2 reason=\"Invalid credentials\""
+        );
+
+        // An error that ends the client takes precedence
+        let error = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::ConnectionRefused))
+            .context(Failure::ConnectionRefused)
+            .unwrap_err();
+        let summary = outcome.summary(Some(&error));
+        assert_eq!(summary.status, "connection_refused");
+        assert_eq!(summary.exit_code, 2);
+        assert!(summary
+            .reason
+            .unwrap()
+            .starts_with("Failed to connect to server: "));
+
+        let untagged = anyhow::anyhow!("boom");
+        assert_eq!(outcome.summary(Some(&untagged)).exit_code, 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_pong() {
+        let outcome = SessionOutcome::new();
+        assert!(!outcome.wait_for_pong(7, Duration::from_millis(10)).await);
+
+        let answering = outcome.clone();
+        tokio::spawn(async move { answering.pong(7) });
+        assert!(outcome.wait_for_pong(7, Duration::from_secs(5)).await);
+    }
+}
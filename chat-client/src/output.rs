@@ -0,0 +1,518 @@
+//! Printing of received messages and events.
+//!
+//! In text mode events are logged as human-readable lines. With `--output json`
+//! every event is printed to stdout as a single JSON object per line (NDJSON),
+//! so the client can be piped into `jq`, monitoring or a bot. Each object has
+//! an `event` field naming the event and an `at` timestamp. Every event can
+//! also start the commands configured with `--hook` (see [`crate::hooks`]).
+
+use chat_common::{error::ErrorCode, Availability, HistoricalKind, OutputFormat, Priority};
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+
+use crate::filters;
+use crate::hooks::Hooks;
+use crate::markdown;
+use crate::outcome::Summary;
+use crate::timestamps::Timestamps;
+
+/// Something the user should see, usually a received message.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The connection to the server was established
+    Connected { addr: String },
+    /// A decrypted text message
+    Message {
+        /// Username of the sender, if the server named it
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sender: Option<String>,
+        text: String,
+        priority: Priority,
+        /// When an ephemeral message disappears
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expires_at: Option<DateTime<Utc>>,
+        /// When the server stored the message, if it did
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sent_at: Option<DateTime<Utc>>,
+        /// Highlight keywords the text contains
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        highlights: Vec<String>,
+    },
+    /// A decrypted reply with the message it quotes
+    Reply {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sender: Option<String>,
+        text: String,
+        quoted_id: i32,
+        quoted_author: String,
+        quoted_excerpt: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sent_at: Option<DateTime<Utc>>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        highlights: Vec<String>,
+    },
+    /// A decrypted action, shown as `* alice waves`
+    Action {
+        username: String,
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sent_at: Option<DateTime<Utc>>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        highlights: Vec<String>,
+    },
+    /// An earlier message of the workspace, replayed after logging in
+    History {
+        id: i32,
+        sender: String,
+        kind: HistoricalKind,
+        /// Decrypted text, or the file name of an attachment
+        text: String,
+        sent_at: DateTime<Utc>,
+    },
+    /// A stored message was edited by its sender
+    Edited {
+        id: i32,
+        /// Decrypted new text
+        text: String,
+    },
+    /// A stored message was deleted by its sender
+    Deleted { id: i32 },
+    /// A poll was created or voted in
+    Poll {
+        poll_id: i32,
+        question: String,
+        options: Vec<String>,
+        /// Votes per option, in the order of `options`
+        votes: Vec<u32>,
+    },
+    /// A user's status changed
+    Status {
+        username: String,
+        availability: Availability,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    /// An ephemeral message shown earlier has expired
+    Expired,
+    /// Stored messages of a workspace that never reached the client; the
+    /// server lists them with `GET /messages?workspace_id=..&after_seq=..&until_seq=..`
+    Missed {
+        workspace: String,
+        from: u64,
+        to: u64,
+    },
+    /// Messages were stored in a workspace the connection is not in
+    Unread { workspace: String, count: u64 },
+    /// Unsent text of a workspace, saved when the client last left it
+    Draft { workspace: String, text: String },
+    /// A notification from the server
+    System { message: String },
+    /// A received file was saved
+    File { name: String, path: PathBuf },
+    /// A received image was saved
+    Image { name: String, path: PathBuf },
+    /// A new inbox entry such as a mention
+    Notification {
+        id: i32,
+        kind: String,
+        content: String,
+    },
+    /// The result of a login
+    Auth { success: bool, message: String },
+    /// An error reported by the server
+    Error {
+        code: ErrorCode,
+        message: String,
+        /// Description of the sent request that failed, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request: Option<String>,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        details: BTreeMap<String, String>,
+    },
+    /// The server closed the connection
+    Disconnected,
+    /// The result of the session, printed on exit
+    Summary(Summary),
+}
+
+impl Event {
+    /// Returns the name of the event, its `event` field in JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Connected { .. } => "connected",
+            Event::Message { .. } => "message",
+            Event::Reply { .. } => "reply",
+            Event::Action { .. } => "action",
+            Event::History { .. } => "history",
+            Event::Edited { .. } => "edited",
+            Event::Deleted { .. } => "deleted",
+            Event::Poll { .. } => "poll",
+            Event::Status { .. } => "status",
+            Event::Expired => "expired",
+            Event::Missed { .. } => "missed",
+            Event::Unread { .. } => "unread",
+            Event::Draft { .. } => "draft",
+            Event::System { .. } => "system",
+            Event::File { .. } => "file",
+            Event::Image { .. } => "image",
+            Event::Notification { .. } => "notification",
+            Event::Auth { .. } => "auth",
+            Event::Error { .. } => "error",
+            Event::Disconnected => "disconnected",
+            Event::Summary(_) => "summary",
+        }
+    }
+
+    /// Returns the text hook patterns are matched against, if the event has one.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Event::Message { text, .. }
+            | Event::Reply { text, .. }
+            | Event::Action { text, .. }
+            | Event::History { text, .. }
+            | Event::Edited { text, .. }
+            | Event::Draft { text, .. }
+            | Event::Status {
+                text: Some(text), ..
+            } => Some(text),
+            Event::Poll { question, .. } => Some(question),
+            Event::System { message }
+            | Event::Auth { message, .. }
+            | Event::Error { message, .. } => Some(message),
+            Event::File { name, .. } | Event::Image { name, .. } => Some(name),
+            Event::Notification { content, .. } => Some(content),
+            _ => None,
+        }
+    }
+}
+
+/// Prints events in the chosen output format and runs their hooks.
+#[derive(Clone, Debug)]
+pub struct Output {
+    format: OutputFormat,
+    render_markdown: bool,
+    hooks: Hooks,
+    timestamps: Timestamps,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Text,
+            render_markdown: true,
+            hooks: Hooks::default(),
+            timestamps: Timestamps::default(),
+        }
+    }
+}
+
+impl Output {
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Turns Markdown rendering of text messages on or off. JSON output always
+    /// carries the text as it was sent.
+    pub fn with_markdown(mut self, render_markdown: bool) -> Self {
+        self.render_markdown = render_markdown;
+        self
+    }
+
+    /// Runs these hooks for every emitted event.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Shows the timestamps of received messages with these settings.
+    pub fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Prints an event.
+    pub fn emit(&self, event: Event) {
+        self.hooks.run(&event, || json_line(&event, Utc::now()));
+        match self.format {
+            OutputFormat::Text => self.log(event),
+            OutputFormat::Json => println!("{}", json_line(&event, Utc::now())),
+        }
+    }
+
+    /// Renders the Markdown of a received text, unless turned off, and
+    /// colorizes its highlighted keywords.
+    fn render(&self, text: String, highlights: &[String]) -> String {
+        let text = if self.render_markdown {
+            markdown::render(&text)
+        } else {
+            text
+        };
+        filters::colorize(&text, highlights)
+    }
+
+    /// Formats the timestamp shown in front of a received message, stamping
+    /// messages without one with the time they arrived.
+    fn stamp(&self, sent_at: Option<DateTime<Utc>>) -> String {
+        match self.timestamps.format(sent_at.unwrap_or_else(Utc::now)) {
+            Some(time) => format!("[{}] ", time),
+            None => String::new(),
+        }
+    }
+
+    fn log(&self, event: Event) {
+        match event {
+            Event::Connected { addr } => info!("Connected to {}", addr),
+            Event::Message {
+                sender,
+                text,
+                priority,
+                expires_at,
+                sent_at,
+                highlights,
+            } => {
+                let stamp = self.stamp(sent_at);
+                let text = self.render(text, &highlights);
+                let from = sender
+                    .as_deref()
+                    .map(|sender| format!(" from {}", sender))
+                    .unwrap_or_default();
+                match (expires_at, priority) {
+                    (Some(expires_at), _) => info!(
+                        "{}Received{} (disappears at {}): {}",
+                        stamp,
+                        from,
+                        expires_at.with_timezone(&Local).format("%H:%M:%S"),
+                        text
+                    ),
+                    // Urgent messages are always shown, in bold red
+                    (None, Priority::Urgent) => {
+                        warn!("{}\x1b[1;31mURGENT{}: {}\x1b[0m", stamp, from, text)
+                    }
+                    (None, Priority::Normal) => match sender {
+                        Some(sender) => info!("{}{}: {}", stamp, sender, text),
+                        None => info!("{}Received: {}", stamp, text),
+                    },
+                    (None, Priority::Low) => {
+                        info!("{}Received{} (low priority): {}", stamp, from, text)
+                    }
+                }
+            }
+            Event::Reply {
+                sender,
+                text,
+                quoted_id,
+                quoted_author,
+                quoted_excerpt,
+                sent_at,
+                highlights,
+            } => {
+                let text = self.render(text, &highlights);
+                let from = sender
+                    .map(|sender| format!(" from {}", sender))
+                    .unwrap_or_default();
+                info!(
+                    "{}Received reply{} to #{}:\n> {}: {}\n{}",
+                    self.stamp(sent_at),
+                    from,
+                    quoted_id,
+                    quoted_author,
+                    quoted_excerpt,
+                    text
+                )
+            }
+            Event::Action {
+                username,
+                text,
+                sent_at,
+                highlights,
+            } => {
+                let text = self.render(text, &highlights);
+                info!("{}Received: * {} {}", self.stamp(sent_at), username, text)
+            }
+            Event::History {
+                id,
+                sender,
+                kind,
+                text,
+                sent_at,
+            } => {
+                let stamp = self.stamp(Some(sent_at));
+                match kind {
+                    HistoricalKind::Text => {
+                        info!("{}#{} {}: {}", stamp, id, sender, self.render(text, &[]))
+                    }
+                    HistoricalKind::Action => {
+                        info!("{}#{} * {} {}", stamp, id, sender, self.render(text, &[]))
+                    }
+                    HistoricalKind::File | HistoricalKind::Image => info!(
+                        "{}#{} {} sent {}, get it with .download {}",
+                        stamp, id, sender, text, id
+                    ),
+                }
+            }
+            Event::Edited { id, text } => {
+                info!("Message #{} was edited: {}", id, self.render(text, &[]))
+            }
+            Event::Deleted { id } => info!("Message #{} was deleted", id),
+            Event::Poll {
+                poll_id,
+                question,
+                options,
+                votes,
+            } => {
+                let lines = options
+                    .iter()
+                    .zip(&votes)
+                    .enumerate()
+                    .map(|(index, (option, votes))| {
+                        format!("  {}. {} ({})", index + 1, option, votes)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                info!("Poll #{}: {}\n{}", poll_id, question, lines)
+            }
+            Event::Status {
+                username,
+                availability,
+                text: Some(text),
+            } => info!("{} is {}: {}", username, availability, text),
+            Event::Status {
+                username,
+                availability,
+                ..
+            } => info!("{} is {}", username, availability),
+            Event::Expired => info!("An ephemeral message has expired"),
+            Event::Missed {
+                workspace,
+                from,
+                to,
+            } => warn!(
+                "Missed messages #{} to #{} of workspace '{}'",
+                from, to, workspace
+            ),
+            Event::Unread { workspace, count } => {
+                info!("{} unread in workspace '{}'", count, workspace)
+            }
+            Event::Draft { workspace, text } => info!(
+                "Draft for workspace '{}', send it with .draft: {}",
+                workspace, text
+            ),
+            Event::System { message } => info!("System: {}", message),
+            Event::File { path, .. } => info!("Saved file to {}", path.display()),
+            Event::Image { path, .. } => info!("Saved image to {}", path.display()),
+            Event::Notification { kind, content, .. } => {
+                info!("Notification [{}]: {}", kind, content)
+            }
+            Event::Auth {
+                success: true,
+                message,
+            } => {
+                info!("Authentication successful: {}", message)
+            }
+            Event::Auth { message, .. } => error!("Authentication failed: {}", message),
+            Event::Error {
+                code,
+                message,
+                request,
+                details,
+            } => {
+                let details = if details.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {:?}", details)
+                };
+                match request {
+                    Some(request) => {
+                        error!("{} failed [{:?}]: {}{}", request, code, message, details)
+                    }
+                    None => error!("Server error [{:?}]: {}{}", code, message, details),
+                }
+            }
+            Event::Disconnected => info!("Disconnected from server"),
+            // A bare line on stderr, so wrappers can parse it without the log prefix
+            Event::Summary(summary) => eprintln!("{}", summary),
+        }
+    }
+}
+
+/// Serializes an event as a single line of JSON with its timestamp.
+fn json_line(event: &Event, at: DateTime<Utc>) -> String {
+    let mut value = serde_json::to_value(event).expect("events serialize to JSON");
+    value["at"] = serde_json::json!(at);
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(event: Event) -> Value {
+        let at = "2024-05-01T12:00:00Z".parse().unwrap();
+        let line = json_line(&event, at);
+        assert!(!line.contains('\n'));
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn test_json_lines() {
+        assert_eq!(
+            parse(Event::Message {
+                sender: Some("alice".to_string()),
+                text: "**hi**\nthere".to_string(),
+                priority: Priority::Urgent,
+                expires_at: None,
+                sent_at: None,
+                highlights: Vec::new(),
+            }),
+            json!({
+                "event": "message",
+                "sender": "alice",
+                "text": "**hi**\nthere",
+                "priority": "urgent",
+                "at": "2024-05-01T12:00:00Z",
+            })
+        );
+        assert_eq!(
+            parse(Event::Summary(Summary {
+                status: "ok",
+                exit_code: 0,
+                sent: 1,
+                failed: 0,
+                reason: None,
+            })),
+            json!({
+                "event": "summary",
+                "status": "ok",
+                "exit_code": 0,
+                "sent": 1,
+                "failed": 0,
+                "at": "2024-05-01T12:00:00Z",
+            })
+        );
+        assert_eq!(
+            parse(Event::Disconnected),
+            json!({"event": "disconnected", "at": "2024-05-01T12:00:00Z"})
+        );
+        assert_eq!(
+            parse(Event::Error {
+                code: ErrorCode::FileNotFound,
+                message: "File not found".to_string(),
+                request: Some("Sending file 'a.txt'".to_string()),
+                details: BTreeMap::new(),
+            }),
+            json!({
+                "event": "error",
+                "code": "FileNotFound",
+                "message": "File not found",
+                "request": "Sending file 'a.txt'",
+                "at": "2024-05-01T12:00:00Z",
+            })
+        );
+    }
+}
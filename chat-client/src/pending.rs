@@ -0,0 +1,123 @@
+//! Tracking of sent requests so server errors can be attached to them.
+//!
+//! Every outgoing message is wrapped in a `Request` envelope carrying a
+//! `client_msg_id`. The server echoes that ID in `in_reply_to` when the request
+//! fails, and the description recorded here tells the user which send it was.
+
+use chat_common::Message;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Number of recent requests remembered for error correlation
+const MAX_PENDING: usize = 128;
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    requests: VecDeque<(String, String)>,
+}
+
+/// Recently sent requests, shared between the input loop and the message handler.
+#[derive(Clone, Default)]
+pub struct PendingRequests {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps a message in a request envelope and remembers what it was.
+    pub fn wrap(&self, message: Message) -> Message {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let client_msg_id = format!("c{}", inner.next_id);
+
+        if inner.requests.len() == MAX_PENDING {
+            inner.requests.pop_front();
+        }
+        inner
+            .requests
+            .push_back((client_msg_id.clone(), describe(&message)));
+
+        Message::Request {
+            client_msg_id,
+            message: Box::new(message),
+        }
+    }
+
+    /// Returns the description of a request the server replied to with an error.
+    pub fn take(&self, client_msg_id: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner
+            .requests
+            .iter()
+            .position(|(id, _)| id == client_msg_id)?;
+        inner
+            .requests
+            .remove(index)
+            .map(|(_, description)| description)
+    }
+}
+
+/// Describes an outgoing message for error reports.
+fn describe(message: &Message) -> String {
+    match message {
+        Message::Text(_) | Message::PriorityText { .. } => "Sending a message".to_string(),
+        Message::Action { .. } => "Sending an action".to_string(),
+        Message::Ephemeral { .. } => "Sending an ephemeral message".to_string(),
+        Message::Reply { quote, .. } => format!("Replying to message #{}", quote.id),
+        Message::Poll { question, .. } => format!("Creating poll '{}'", question),
+        Message::Vote { poll_id, .. } => format!("Voting in poll #{}", poll_id),
+        Message::Report { message_id, .. } => format!("Reporting message #{}", message_id),
+        Message::Edit { message_id, .. } => format!("Editing message #{}", message_id),
+        Message::Delete { message_id } => format!("Deleting message #{}", message_id),
+        Message::Download { message_id } => {
+            format!("Downloading the attachment of message #{}", message_id)
+        }
+        Message::File { name, .. } => format!("Sending file '{}'", name),
+        Message::Image { name, .. } => format!("Sending image '{}'", name),
+        Message::Auth { username, .. } => format!("Logging in as '{}'", username),
+        Message::Register { username, .. } => format!("Registering '{}'", username),
+        Message::SwitchWorkspace { slug } => format!("Switching to workspace '{}'", slug),
+        Message::ChangePassword { .. } => "Changing the password".to_string(),
+        Message::SetStatus { availability, .. } => {
+            format!("Setting the status to {}", availability)
+        }
+        _ => "Request".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_and_take() {
+        let pending = PendingRequests::new();
+
+        let Message::Request { client_msg_id, .. } = pending.wrap(Message::SwitchWorkspace {
+            slug: "team".to_string(),
+        }) else {
+            panic!("Expected Request envelope");
+        };
+
+        assert_eq!(
+            pending.take(&client_msg_id),
+            Some("Switching to workspace 'team'".to_string())
+        );
+        assert_eq!(pending.take(&client_msg_id), None);
+    }
+
+    #[test]
+    fn test_only_recent_requests_are_kept() {
+        let pending = PendingRequests::new();
+        for _ in 0..=MAX_PENDING {
+            pending.wrap(Message::Text(String::new()));
+        }
+
+        assert_eq!(pending.take("c1"), None);
+        assert!(pending.take(&format!("c{}", MAX_PENDING + 1)).is_some());
+    }
+}
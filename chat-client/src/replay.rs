@@ -0,0 +1,188 @@
+//! Replaying recorded sessions for reproducing bugs.
+//!
+//! A recording made with `--record` can be fed back in two ways: the received
+//! frames go through the message handler exactly as they did live, or the sent
+//! frames are sent to a server again while its replies are handled as usual.
+
+use anyhow::{Context, Result};
+use chat_common::async_message_stream::{client_handshake, AsyncMessageStream};
+use chat_common::recording::{self, Direction, RecordedFrame, ReplayStream};
+use chat_common::Message;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::message_handler::MessageHandler;
+use crate::network::spawn_receiver_task;
+use crate::outcome::Failure;
+
+/// How long to keep handling the server's replies after the last frame is sent
+const REPLY_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Feeds the received frames of a recording into the message handler.
+///
+/// # Arguments
+/// * `path` - The recording to replay
+/// * `handler` - Handler set up with the key the recorded session used
+/// * `realtime` - Replay with the recorded timing instead of as fast as possible
+pub async fn replay_into_handler(
+    path: &Path,
+    handler: MessageHandler,
+    realtime: bool,
+) -> Result<()> {
+    let frames = load(path)?;
+    let redacted = frames
+        .iter()
+        .filter(|frame| frame.direction == Direction::Received && frame.redacted)
+        .count();
+    if redacted > 0 {
+        warn!(
+            "Skipping {} received frames whose payloads were redacted, record with \
+             --record-payloads to replay them",
+            redacted
+        );
+    }
+
+    handler
+        .handle_incoming(ReplayStream::new(frames, realtime))
+        .await?;
+    info!("Replay finished");
+    Ok(())
+}
+
+/// Sends the sent frames of a recording to a server and handles its replies.
+///
+/// Heartbeat answers are not replayed, the handler answers the server's live
+/// heartbeats instead. Redacted frames are skipped, except logins, which are
+/// sent with `password` if one is given.
+///
+/// # Arguments
+/// * `path` - The recording to replay
+/// * `addr` - The server to send the frames to
+/// * `handler` - Handler set up with the key the recorded session used
+/// * `realtime` - Replay with the recorded timing instead of as fast as possible
+/// * `password` - Password for recorded logins
+pub async fn replay_to_server(
+    path: &Path,
+    addr: &str,
+    handler: MessageHandler,
+    realtime: bool,
+    password: Option<&str>,
+) -> Result<()> {
+    let frames = load(path)?;
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .context(Failure::ConnectionRefused)?;
+    client_handshake(&mut stream)
+        .await
+        .context(Failure::ConnectionRefused)?;
+    let (receiver_stream, writer_stream) = stream.into_split();
+    let writer_stream = Arc::new(Mutex::new(writer_stream));
+    spawn_receiver_task(
+        receiver_stream,
+        handler.with_writer(Arc::clone(&writer_stream)),
+        None,
+        None,
+    );
+
+    let started = Instant::now();
+    let mut sent = 0;
+    for frame in frames {
+        if frame.direction != Direction::Sent || matches!(frame.message, Message::Pong { .. }) {
+            continue;
+        }
+        let message = match (frame.redacted, password) {
+            (false, _) => frame.message,
+            (true, Some(password)) if is_login(&frame.message) => {
+                with_password(frame.message, password)
+            }
+            (true, _) if is_login(&frame.message) => {
+                warn!("Skipping a recorded login, pass --replay-password to replay it");
+                continue;
+            }
+            (true, _) => {
+                warn!(
+                    "Skipping a frame recorded at {} ms whose payload was redacted",
+                    frame.offset_ms
+                );
+                continue;
+            }
+        };
+
+        if realtime {
+            let due = Duration::from_millis(frame.offset_ms);
+            tokio::time::sleep(due.saturating_sub(started.elapsed())).await;
+        }
+        writer_stream.lock().await.write_message(&message).await?;
+        sent += 1;
+    }
+
+    info!("Replayed {} frames, waiting for replies", sent);
+    tokio::time::sleep(REPLY_GRACE_PERIOD).await;
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Vec<RecordedFrame>> {
+    recording::read_recording(path)
+        .with_context(|| format!("Failed to read recording {}", path.display()))
+}
+
+/// Returns `true` for a login, also when sent as a request.
+fn is_login(message: &Message) -> bool {
+    match message {
+        Message::Auth { .. } => true,
+        Message::Request { message, .. } => is_login(message),
+        _ => false,
+    }
+}
+
+/// Puts the password back into a recorded login.
+fn with_password(message: Message, password: &str) -> Message {
+    match message {
+        Message::Auth { username, .. } => Message::Auth {
+            username,
+            password: password.to_string(),
+        },
+        Message::Request {
+            client_msg_id,
+            message,
+        } => Message::Request {
+            client_msg_id,
+            message: Box::new(with_password(*message, password)),
+        },
+        message => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_common::recording::REDACTED;
+
+    #[test]
+    fn test_with_password() {
+        let login = Message::Request {
+            client_msg_id: "c1".to_string(),
+            message: Box::new(Message::Auth {
+                username: "alice".to_string(),
+                password: REDACTED.to_string(),
+            }),
+        };
+        assert!(is_login(&login));
+        assert!(!is_login(&Message::Text(String::new())));
+
+        match with_password(login, "password123") {
+            Message::Request { message, .. } => assert_eq!(
+                *message,
+                Message::Auth {
+                    username: "alice".to_string(),
+                    password: "password123".to_string(),
+                }
+            ),
+            message => panic!("Unexpected {:?}", message),
+        }
+    }
+}
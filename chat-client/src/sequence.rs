@@ -0,0 +1,70 @@
+//! Detection of missed messages.
+//!
+//! The server numbers the stored messages of every workspace without gaps.
+//! The client remembers the last number it saw per workspace, so it notices
+//! when messages went by without reaching it, for example while it was in
+//! another workspace, and can report exactly which ones to fetch.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// The last sequence number seen in each workspace.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    /// Slug of the workspace the connection is in
+    current: Option<String>,
+    last_seen: HashMap<String, u64>,
+}
+
+impl SequenceTracker {
+    /// Enters a workspace whose latest message has number `last_seq`.
+    ///
+    /// # Returns
+    /// * `Option<RangeInclusive<u64>>` - The numbers stored since the workspace
+    ///   was last seen, or None on the first visit or if nothing was missed
+    pub fn enter(&mut self, workspace: &str, last_seq: u64) -> Option<RangeInclusive<u64>> {
+        self.current = Some(workspace.to_string());
+        let seen = self.last_seen.insert(workspace.to_string(), last_seq)?;
+        if last_seq > seen {
+            Some(seen + 1..=last_seq)
+        } else {
+            // Never go back, in case the numbers arrived out of order
+            self.last_seen.insert(workspace.to_string(), seen);
+            None
+        }
+    }
+
+    /// Records a message of the current workspace.
+    ///
+    /// # Returns
+    /// * `Option<(String, RangeInclusive<u64>)>` - The workspace and the numbers
+    ///   skipped before `seq`, or None if nothing was missed
+    pub fn observe(&mut self, seq: u64) -> Option<(String, RangeInclusive<u64>)> {
+        let workspace = self.current.clone()?;
+        let seen = self.last_seen.entry(workspace.clone()).or_insert(seq);
+        let missed = (seq > *seen + 1).then(|| *seen + 1..=seq - 1);
+        *seen = (*seen).max(seq);
+        missed.map(|missed| (workspace, missed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missed_messages() {
+        let mut tracker = SequenceTracker::default();
+        assert_eq!(tracker.observe(1), None);
+
+        assert_eq!(tracker.enter("general", 10), None);
+        assert_eq!(tracker.observe(11), None);
+        assert_eq!(tracker.observe(14), Some(("general".to_string(), 12..=13)));
+        assert_eq!(tracker.observe(12), None);
+
+        // Messages stored while in another workspace
+        assert_eq!(tracker.enter("design", 3), None);
+        assert_eq!(tracker.enter("general", 20), Some(15..=20));
+        assert_eq!(tracker.enter("general", 20), None);
+    }
+}
@@ -0,0 +1,310 @@
+//! State the client keeps between runs.
+//!
+//! The state lives in a JSON file, by default `chat-client-state.json` in the
+//! working directory (see `--state-file`). It is written whenever it changes, through a temporary
+//! file that replaces the old one, so a crash never leaves half a file behind.
+//! A missing or unreadable file starts an empty state.
+//!
+//! The saved state holds the drafts: text typed for a workspace but not sent,
+//! which is shown again when the client next enters the workspace, and the
+//! mutes and highlights set with `.mute` and `.highlight`. The counts
+//! of unread messages in other workspaces and where requested downloads go
+//! only last for the session.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::download::Destination;
+use crate::filters::Filters;
+
+/// Contents of the state file.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct Saved {
+    /// Unsent text per workspace slug
+    #[serde(default)]
+    drafts: BTreeMap<String, String>,
+    /// Muted `@user`s and patterns
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    muted: BTreeSet<String>,
+    /// Highlighted keywords
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    highlights: BTreeSet<String>,
+}
+
+/// A list of filters kept in the state file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterList {
+    Muted,
+    Highlights,
+}
+
+struct Inner {
+    saved: Saved,
+    /// Slug of the workspace the connection is in
+    current: Option<String>,
+    /// Messages stored in other workspaces since they were last entered
+    unread: BTreeMap<String, u64>,
+    /// Where the attachments of messages requested with `.download` go
+    downloads: BTreeMap<i32, Destination>,
+}
+
+/// Client state shared between the input loop and the message handler.
+#[derive(Clone)]
+pub struct ClientState {
+    path: PathBuf,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ClientState {
+    /// Loads the state saved at `path`.
+    pub fn open(path: &Path) -> Self {
+        let saved = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+                Saved::default()
+            }),
+            Err(_) => Saved::default(),
+        };
+        Self {
+            path: path.to_path_buf(),
+            inner: Arc::new(Mutex::new(Inner {
+                saved,
+                current: None,
+                unread: BTreeMap::new(),
+                downloads: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Enters a workspace, which marks its messages as read.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The draft saved for the workspace, if any
+    pub fn enter(&self, workspace: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.current = Some(workspace.to_string());
+        inner.unread.remove(workspace);
+        inner.saved.drafts.get(workspace).cloned()
+    }
+
+    /// Counts a message stored in another workspace.
+    ///
+    /// # Returns
+    /// * `Option<u64>` - The number of unread messages in the workspace, or
+    ///   None if the connection is in it
+    pub fn count_unread(&self, workspace: &str) -> Option<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.current.as_deref() == Some(workspace) {
+            return None;
+        }
+        let count = inner.unread.entry(workspace.to_string()).or_default();
+        *count += 1;
+        Some(*count)
+    }
+
+    /// Returns the number of unread messages per workspace.
+    pub fn unread(&self) -> BTreeMap<String, u64> {
+        self.inner.lock().unwrap().unread.clone()
+    }
+
+    /// Remembers where the attachment of a requested message goes.
+    pub fn expect_download(&self, message_id: i32, destination: Destination) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.downloads.insert(message_id, destination);
+    }
+
+    /// Returns where the attachment of a message goes, or None if it was
+    /// requested without a destination.
+    pub fn take_download(&self, message_id: i32) -> Option<Destination> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.downloads.remove(&message_id)
+    }
+
+    /// Returns the slug of the workspace the connection is in.
+    pub fn current_workspace(&self) -> Option<String> {
+        self.inner.lock().unwrap().current.clone()
+    }
+
+    /// Saves the draft of the current workspace; blank text deletes it.
+    ///
+    /// # Returns
+    /// * `Result<Option<String>>` - The workspace the draft belongs to, or None
+    ///   before the client entered a workspace
+    pub fn save_draft(&self, text: &str) -> Result<Option<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(workspace) = inner.current.clone() else {
+            return Ok(None);
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            inner.saved.drafts.remove(&workspace);
+        } else {
+            inner
+                .saved
+                .drafts
+                .insert(workspace.clone(), text.to_string());
+        }
+        self.write(&inner.saved)?;
+        Ok(Some(workspace))
+    }
+
+    /// Removes and returns the draft of the current workspace.
+    pub fn take_draft(&self) -> Result<Option<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(workspace) = inner.current.clone() else {
+            return Ok(None);
+        };
+        let draft = inner.saved.drafts.remove(&workspace);
+        if draft.is_some() {
+            self.write(&inner.saved)?;
+        }
+        Ok(draft)
+    }
+
+    /// Adds an entry to a filter list.
+    ///
+    /// # Returns
+    /// * `Result<bool>` - `false` if the list already had the entry
+    pub fn add_filter(&self, list: FilterList, entry: &str) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        let added = Self::list(&mut inner.saved, list).insert(entry.to_lowercase());
+        if added {
+            self.write(&inner.saved)?;
+        }
+        Ok(added)
+    }
+
+    /// Removes an entry from a filter list.
+    ///
+    /// # Returns
+    /// * `Result<bool>` - `false` if the list did not have the entry
+    pub fn remove_filter(&self, list: FilterList, entry: &str) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        let removed = Self::list(&mut inner.saved, list).remove(&entry.to_lowercase());
+        if removed {
+            self.write(&inner.saved)?;
+        }
+        Ok(removed)
+    }
+
+    /// Returns the entries of a filter list.
+    pub fn filter_list(&self, list: FilterList) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        Self::list(&mut inner.saved, list).iter().cloned().collect()
+    }
+
+    /// Returns the mutes and highlights in effect.
+    pub fn filters(&self) -> Filters {
+        let inner = self.inner.lock().unwrap();
+        Filters::new(&inner.saved.muted, &inner.saved.highlights)
+    }
+
+    fn list(saved: &mut Saved, list: FilterList) -> &mut BTreeSet<String> {
+        match list {
+            FilterList::Muted => &mut saved.muted,
+            FilterList::Highlights => &mut saved.highlights,
+        }
+    }
+
+    fn write(&self, saved: &Saved) -> Result<()> {
+        let path = &self.path;
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(saved)?)
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        fs::rename(&temporary, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drafts_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        let state = ClientState::open(&path);
+        assert_eq!(state.save_draft("too early").unwrap(), None);
+        assert_eq!(state.enter("general"), None);
+        assert_eq!(
+            state.save_draft(" see you at ").unwrap(),
+            Some("general".to_string())
+        );
+        state.enter("random");
+
+        let state = ClientState::open(&path);
+        assert_eq!(state.enter("general"), Some("see you at".to_string()));
+        assert_eq!(state.take_draft().unwrap(), Some("see you at".to_string()));
+        assert_eq!(ClientState::open(&path).enter("general"), None);
+    }
+
+    #[test]
+    fn test_unread_counts_clear_on_enter() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ClientState::open(&dir.path().join("state.json"));
+        state.enter("general");
+
+        assert_eq!(state.count_unread("general"), None);
+        assert_eq!(state.count_unread("random"), Some(1));
+        assert_eq!(state.count_unread("random"), Some(2));
+        assert_eq!(state.count_unread("design"), Some(1));
+        assert_eq!(
+            state.unread(),
+            BTreeMap::from([("design".to_string(), 1), ("random".to_string(), 2)])
+        );
+
+        state.enter("random");
+        assert_eq!(state.unread().get("random"), None);
+        assert_eq!(state.count_unread("general"), Some(1));
+    }
+
+    #[test]
+    fn test_filters_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        let state = ClientState::open(&path);
+        assert!(state.add_filter(FilterList::Muted, "@Bob").unwrap());
+        assert!(!state.add_filter(FilterList::Muted, "@bob").unwrap());
+        assert!(state.add_filter(FilterList::Highlights, "deploy").unwrap());
+        assert!(!state
+            .remove_filter(FilterList::Highlights, "lunch")
+            .unwrap());
+
+        let state = ClientState::open(&path);
+        assert_eq!(
+            state.filter_list(FilterList::Muted),
+            vec!["@bob".to_string()]
+        );
+        assert_eq!(
+            state.filters(),
+            Filters::new(&["@bob".to_string()], &["deploy".to_string()])
+        );
+        assert!(state.remove_filter(FilterList::Muted, "@BOB").unwrap());
+        assert!(ClientState::open(&path)
+            .filter_list(FilterList::Muted)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unreadable_state_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, "not json").unwrap();
+
+        let state = ClientState::open(&path);
+        assert_eq!(state.enter("general"), None);
+        state.save_draft("hello").unwrap();
+        assert_eq!(
+            ClientState::open(&path).enter("general"),
+            Some("hello".to_string())
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Timestamps shown in front of received messages.
+//!
+//! Stored messages carry the time the server stored them; others, like
+//! ephemeral messages, are stamped when they arrive. The time is shown in the
+//! local timezone, either as a clock time (`14:02`, or `2:02 PM` with
+//! `--clock 12h`, with the date for older messages) or relative to now
+//! (`just now`, `5m ago`). `--timestamps` chooses the style and `.time`
+//! switches it during a session.
+
+use chat_common::{Clock, TimeStyle};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+struct Settings {
+    style: TimeStyle,
+    clock: Clock,
+    /// Style restored when `.time` turns timestamps back on
+    last_shown: TimeStyle,
+}
+
+/// The timestamp settings, shared by the output and the input loop.
+#[derive(Debug, Clone)]
+pub struct Timestamps(Arc<Mutex<Settings>>);
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Self::new(TimeStyle::default(), Clock::default())
+    }
+}
+
+impl Timestamps {
+    pub fn new(style: TimeStyle, clock: Clock) -> Self {
+        let last_shown = match style {
+            TimeStyle::Off => TimeStyle::Absolute,
+            style => style,
+        };
+        Self(Arc::new(Mutex::new(Settings {
+            style,
+            clock,
+            last_shown,
+        })))
+    }
+
+    /// Turns timestamps off, or back on in the style they were last shown in.
+    ///
+    /// # Returns
+    /// * `TimeStyle` - The style now in effect
+    pub fn toggle(&self) -> TimeStyle {
+        let mut settings = self.0.lock().unwrap();
+        settings.style = match settings.style {
+            TimeStyle::Off => settings.last_shown,
+            _ => TimeStyle::Off,
+        };
+        settings.style
+    }
+
+    /// Switches to a style.
+    pub fn set_style(&self, style: TimeStyle) {
+        let mut settings = self.0.lock().unwrap();
+        settings.style = style;
+        if style != TimeStyle::Off {
+            settings.last_shown = style;
+        }
+    }
+
+    /// Switches the clock of absolute timestamps.
+    pub fn set_clock(&self, clock: Clock) {
+        self.0.lock().unwrap().clock = clock;
+    }
+
+    /// Formats the time a message was sent, or None if timestamps are off.
+    pub fn format(&self, sent_at: DateTime<Utc>) -> Option<String> {
+        let settings = self.0.lock().unwrap();
+        format(
+            settings.style,
+            settings.clock,
+            sent_at.with_timezone(&Local),
+            Local::now(),
+        )
+    }
+}
+
+fn format<Tz: TimeZone>(
+    style: TimeStyle,
+    clock: Clock,
+    at: DateTime<Tz>,
+    now: DateTime<Tz>,
+) -> Option<String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    match style {
+        TimeStyle::Off => None,
+        TimeStyle::Absolute => {
+            let time = match clock {
+                Clock::H24 => "%H:%M",
+                Clock::H12 => "%-I:%M %p",
+            };
+            Some(if at.date_naive() == now.date_naive() {
+                at.format(time).to_string()
+            } else {
+                at.format(&format!("%Y-%m-%d {}", time)).to_string()
+            })
+        }
+        TimeStyle::Relative => {
+            let ago = now - at;
+            Some(match ago.num_seconds() {
+                ..=59 => "just now".to_string(),
+                60..=3599 => format!("{}m ago", ago.num_minutes()),
+                3600..=86399 => format!("{}h ago", ago.num_hours()),
+                _ => format!("{}d ago", ago.num_days()),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_absolute_timestamps() {
+        let now = at("2025-04-01T15:30:00Z");
+        let today = at("2025-04-01T14:02:00Z");
+        let earlier = at("2025-03-30T09:05:00Z");
+
+        assert_eq!(
+            format(TimeStyle::Absolute, Clock::H24, today, now).as_deref(),
+            Some("14:02")
+        );
+        assert_eq!(
+            format(TimeStyle::Absolute, Clock::H12, today, now).as_deref(),
+            Some("2:02 PM")
+        );
+        assert_eq!(
+            format(TimeStyle::Absolute, Clock::H12, earlier, now).as_deref(),
+            Some("2025-03-30 9:05 AM")
+        );
+        assert_eq!(format(TimeStyle::Off, Clock::H24, today, now), None);
+    }
+
+    #[test]
+    fn test_relative_timestamps() {
+        let now = at("2025-04-01T15:30:00Z");
+        let relative = |time| format(TimeStyle::Relative, Clock::H24, at(time), now).unwrap();
+
+        assert_eq!(relative("2025-04-01T15:29:30Z"), "just now");
+        // Clocks of the server and client may disagree a little
+        assert_eq!(relative("2025-04-01T15:30:05Z"), "just now");
+        assert_eq!(relative("2025-04-01T15:28:00Z"), "2m ago");
+        assert_eq!(relative("2025-04-01T12:00:00Z"), "3h ago");
+        assert_eq!(relative("2025-03-29T15:30:00Z"), "3d ago");
+    }
+
+    #[test]
+    fn test_toggle_restores_last_style() {
+        let timestamps = Timestamps::new(TimeStyle::Relative, Clock::H24);
+        assert_eq!(timestamps.toggle(), TimeStyle::Off);
+        assert_eq!(timestamps.toggle(), TimeStyle::Relative);
+
+        let timestamps = Timestamps::new(TimeStyle::Off, Clock::H24);
+        assert_eq!(timestamps.toggle(), TimeStyle::Absolute);
+        timestamps.set_style(TimeStyle::Relative);
+        timestamps.set_style(TimeStyle::Off);
+        assert_eq!(timestamps.toggle(), TimeStyle::Relative);
+    }
+}
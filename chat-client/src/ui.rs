@@ -1,22 +1,69 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chat_common::async_message_stream::AsyncMessageStream;
 use chat_common::encryption::EncryptionService;
+use chat_common::file_ops::ImageDownscale;
+use chat_common::recording::{Direction, Recorder};
+use chat_common::throttle::{self, RateLimiter};
+use chat_common::Message;
+use clap::ValueEnum;
+use std::io::IsTerminal;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::{
-    io::{self, AsyncBufReadExt, BufReader},
+    io::{self, AsyncBufRead, AsyncBufReadExt, BufReader},
     net::tcp::OwnedWriteHalf,
+    sync::Mutex,
 };
+use tracing::{error, info, warn};
 
-use crate::commands::{Command, CommandProcessor};
+use crate::commands::{Command, CommandProcessor, TimeSetting};
+use crate::download::Destination;
+use crate::outcome::{Failure, SessionOutcome};
+use crate::pending::PendingRequests;
+use crate::state::{ClientState, FilterList};
+use crate::timestamps::Timestamps;
 
+/// Nonce of the heartbeat sent to collect the server's last replies
+const DRAIN_NONCE: u64 = u64::MAX;
+/// How long to wait for the server's last replies before exiting
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the input loop shares with the receiver and the rest of the session.
+pub struct InputSession {
+    pub pending: PendingRequests,
+    pub recorder: Option<Recorder>,
+    pub outcome: SessionOutcome,
+    /// Drafts, unread counts and filters kept between runs
+    pub state: ClientState,
+    pub timestamps: Timestamps,
+}
+
+/// Sends the commands typed on stdin until `.quit` or the end of input.
+///
+/// Text typed on a terminal but not sent with Enter when the input ends, e.g.
+/// with Ctrl+D, is saved as the current workspace's draft instead.
+///
+/// Before returning, waits for the server to answer everything sent, so that
+/// failures reported by the server end up in the session outcome.
 pub async fn run_input_loop(
-    mut stream: OwnedWriteHalf,
+    stream: Arc<Mutex<OwnedWriteHalf>>,
     encryption: Arc<EncryptionService>,
+    image_downscale: Option<ImageDownscale>,
+    mut upload_limiter: Option<RateLimiter>,
+    session: InputSession,
 ) -> Result<()> {
+    let InputSession {
+        pending,
+        recorder,
+        outcome,
+        state,
+        timestamps,
+    } = session;
+    let interactive = std::io::stdin().is_terminal();
     let stdin = io::stdin();
     let mut reader = BufReader::new(stdin);
     let mut line = String::new();
-    let processor = CommandProcessor::new(encryption);
+    let processor = CommandProcessor::new(encryption).with_image_downscale(image_downscale);
 
     loop {
         line.clear();
@@ -24,18 +71,197 @@ pub async fn run_input_loop(
             break;
         }
 
-        let command = processor.parse_command(line.trim());
-
-        // Handle quit command directly
-        if matches!(command, Command::Quit) {
+        if interactive && !line.ends_with('\n') {
+            if !line.trim().is_empty() {
+                save_draft(&state, &line);
+            }
             break;
         }
 
+        // Handle quit and drafts directly
+        let command = match processor.parse_command(line.trim()) {
+            Command::Quit => break,
+            Command::Draft(Some(text)) => {
+                save_draft(&state, &text);
+                continue;
+            }
+            Command::Draft(None) => match state.take_draft() {
+                Ok(Some(text)) => processor.parse_command(&text),
+                Ok(None) => {
+                    warn!("There is no draft for this workspace");
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to take the draft: {:#}", e);
+                    continue;
+                }
+            },
+            Command::Unread => {
+                show_unread(&state);
+                continue;
+            }
+            Command::Mute(entry) => {
+                update_filter(&state, FilterList::Muted, entry, true);
+                continue;
+            }
+            Command::Unmute(entry) => {
+                update_filter(&state, FilterList::Muted, Some(entry), false);
+                continue;
+            }
+            Command::Highlight(keyword) => {
+                update_filter(&state, FilterList::Highlights, keyword, true);
+                continue;
+            }
+            Command::Unhighlight(keyword) => {
+                update_filter(&state, FilterList::Highlights, Some(keyword), false);
+                continue;
+            }
+            Command::Time(setting) => {
+                change_timestamps(&timestamps, setting);
+                continue;
+            }
+            Command::Download {
+                message_id,
+                path: Some(path),
+            } => {
+                let mut destination = Destination::parse(&path);
+                if let Some(existing) = destination.existing_file() {
+                    let existing = existing.display().to_string();
+                    if !interactive {
+                        warn!("{} already exists, not overwriting it", existing);
+                        continue;
+                    }
+                    warn!("{} already exists, overwrite it? [y/N]", existing);
+                    if !confirmed(&mut reader).await? {
+                        info!("Download of message #{} cancelled", message_id);
+                        continue;
+                    }
+                    destination = destination.overwriting();
+                }
+                state.expect_download(message_id, destination);
+                Command::Download {
+                    message_id,
+                    path: None,
+                }
+            }
+            command => command,
+        };
+
         // Process other commands
-        if let Ok(Some(message)) = processor.process_command(command).await {
-            AsyncMessageStream::write_message(&mut stream, &message).await?;
+        let message = match processor.process_command(command).await {
+            Ok(Some(message)) => pending.wrap(message),
+            Ok(None) => continue,
+            Err(e) => {
+                error!("{}", e);
+                outcome.fail(Failure::SendFailed, e.to_string());
+                continue;
+            }
+        };
+        let mut stream = stream.lock().await;
+        match upload_limiter.as_mut() {
+            Some(limiter) => {
+                throttle::write_message_throttled(&mut *stream, &message, limiter).await
+            }
+            None => AsyncMessageStream::write_message(&mut *stream, &message).await,
+        }
+        .context(Failure::SendFailed)?;
+        outcome.sent();
+        if let Some(recorder) = &recorder {
+            recorder.record(Direction::Sent, &message)?;
+        }
+    }
+
+    if outcome.sent_count() > 0 {
+        let ping = Message::Ping { nonce: DRAIN_NONCE };
+        AsyncMessageStream::write_message(&mut *stream.lock().await, &ping)
+            .await
+            .context(Failure::SendFailed)?;
+        if !outcome.wait_for_pong(DRAIN_NONCE, DRAIN_TIMEOUT).await {
+            warn!("The server did not answer all sent messages before exiting");
         }
     }
 
     Ok(())
 }
+
+/// Saves the draft of the current workspace, telling the user where it went.
+fn save_draft(state: &ClientState, text: &str) {
+    match state.save_draft(text) {
+        Ok(Some(workspace)) => info!("Saved the draft for workspace '{}'", workspace),
+        Ok(None) => warn!("Drafts are kept per workspace, log in before saving one"),
+        Err(e) => error!("Failed to save the draft: {:#}", e),
+    }
+}
+
+/// Reads a yes or no answer from stdin; anything but yes is no.
+async fn confirmed<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<bool> {
+    let mut answer = String::new();
+    reader.read_line(&mut answer).await?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Lists the workspaces with unread messages.
+fn show_unread(state: &ClientState) {
+    let unread = state.unread();
+    if unread.is_empty() {
+        info!("No unread messages in other workspaces");
+    }
+    for (workspace, count) in unread {
+        info!("{} unread in workspace '{}'", count, workspace);
+    }
+}
+
+/// Adds an entry to or removes it from a filter list, or lists the entries if
+/// none is given.
+fn update_filter(state: &ClientState, list: FilterList, entry: Option<String>, add: bool) {
+    let what = match list {
+        FilterList::Muted => "Muted",
+        FilterList::Highlights => "Highlighted",
+    };
+    let Some(entry) = entry else {
+        let entries = state.filter_list(list);
+        if entries.is_empty() {
+            info!("{}: nothing", what);
+        } else {
+            info!("{}: {}", what, entries.join(", "));
+        }
+        return;
+    };
+    let changed = if add {
+        state.add_filter(list, &entry)
+    } else {
+        state.remove_filter(list, &entry)
+    };
+    match changed {
+        Ok(true) if add => info!("{} '{}'", what, entry),
+        Ok(true) => info!("No longer {} '{}'", what.to_lowercase(), entry),
+        Ok(false) if add => warn!("'{}' is already {}", entry, what.to_lowercase()),
+        Ok(false) => warn!("'{}' is not {}", entry, what.to_lowercase()),
+        Err(e) => error!("Failed to save the filters: {:#}", e),
+    }
+}
+
+/// Applies a `.time` setting, or turns timestamps off and on without one.
+fn change_timestamps(timestamps: &Timestamps, setting: Option<TimeSetting>) {
+    let style = match setting {
+        None => timestamps.toggle(),
+        Some(TimeSetting::Style(style)) => {
+            timestamps.set_style(style);
+            style
+        }
+        Some(TimeSetting::Clock(clock)) => {
+            timestamps.set_clock(clock);
+            info!("Timestamps use the {} clock", value_name(clock));
+            return;
+        }
+    };
+    info!("Timestamps are {}", value_name(style));
+}
+
+/// Returns the name a setting is given by on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,209 @@
+//! Directory archives for sending whole folders through the file pipeline.
+//!
+//! A directory is packed into a tar archive that is capped in size while it is
+//! being written, so an oversized folder is rejected without buffering all of
+//! it. Received archives are only ever unpacked inside a sandbox directory.
+
+use crate::error::{ChatError, Result};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension that marks a received file as a directory archive
+pub const ARCHIVE_EXTENSION: &str = "tar";
+
+/// Default upper bound for the size of a directory archive (50 MiB)
+pub const DEFAULT_MAX_ARCHIVE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Directory received archives are extracted into
+pub const EXTRACT_DIR: &str = "extracted";
+
+/// A writer that fails once more than `limit` bytes were written.
+struct CappedWriter {
+    buffer: Vec<u8>,
+    limit: u64,
+}
+
+impl Write for CappedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() as u64 + data.len() as u64 > self.limit {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "archive size limit exceeded",
+            ));
+        }
+        self.buffer.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns `true` if a received file name denotes a directory archive.
+pub fn is_archive_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|extension| extension == ARCHIVE_EXTENSION)
+}
+
+/// Packs a directory into a tar archive.
+///
+/// Entries are stored relative to the directory itself, and symbolic links are
+/// archived as links rather than followed.
+///
+/// # Arguments
+/// * `path_str` - Path to the directory to pack
+/// * `max_size` - Maximum size of the archive in bytes
+///
+/// # Returns
+/// * `Result<(String, Vec<u8>)>` - The archive file name (`<dir>.tar`) and its contents
+pub async fn archive_directory(path_str: &str, max_size: u64) -> Result<(String, Vec<u8>)> {
+    let path = PathBuf::from(path_str.trim());
+
+    if !path.exists() {
+        return Err(ChatError::NotFound(path_str.to_string()));
+    }
+
+    if !path.is_dir() {
+        return Err(ChatError::InvalidInput(format!(
+            "Not a directory: {}",
+            path_str
+        )));
+    }
+
+    let dir_name = path
+        .canonicalize()?
+        .file_name()
+        .ok_or_else(|| ChatError::InvalidPath(path_str.to_string()))?
+        .to_string_lossy()
+        .into_owned();
+
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let writer = CappedWriter {
+            buffer: Vec::new(),
+            limit: max_size,
+        };
+        let mut builder = tar::Builder::new(writer);
+        builder.follow_symlinks(false);
+
+        let packed = builder
+            .append_dir_all(".", &path)
+            .and_then(|_| builder.into_inner());
+
+        match packed {
+            Ok(writer) => Ok(writer.buffer),
+            Err(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+                Err(ChatError::InvalidInput(format!(
+                    "Directory is larger than the {} byte archive limit",
+                    max_size
+                )))
+            }
+            Err(e) => Err(e.into()),
+        }
+    })
+    .await
+    .map_err(|e| ChatError::UnknownError(e.to_string()))??;
+
+    Ok((format!("{}.{}", dir_name, ARCHIVE_EXTENSION), data))
+}
+
+/// Extracts a received archive into its own folder inside the sandbox directory.
+///
+/// Only regular files and directories are unpacked; links and entries whose
+/// paths would escape the destination are skipped.
+///
+/// # Arguments
+/// * `archive_path` - Path to the archive to extract
+/// * `sandbox` - Directory that all extracted folders are placed in
+///
+/// # Returns
+/// * `Result<(PathBuf, usize)>` - The destination folder and the number of unpacked entries
+pub async fn extract_archive(archive_path: &Path, sandbox: &Path) -> Result<(PathBuf, usize)> {
+    let stem = archive_path
+        .file_stem()
+        .ok_or_else(|| ChatError::InvalidPath(archive_path.display().to_string()))?
+        .to_string_lossy()
+        .into_owned();
+    let destination = sandbox.join(stem);
+    let archive_path = archive_path.to_path_buf();
+    let target = destination.clone();
+
+    let unpacked = tokio::task::spawn_blocking(move || -> Result<usize> {
+        std::fs::create_dir_all(&target)?;
+        let file = std::fs::File::open(&archive_path)?;
+        let mut archive = tar::Archive::new(file);
+        let mut unpacked = 0;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+            if !(entry_type.is_file() || entry_type.is_dir()) {
+                continue;
+            }
+            // `unpack_in` refuses paths that would leave the target directory
+            if entry.unpack_in(&target)? {
+                unpacked += 1;
+            }
+        }
+
+        Ok(unpacked)
+    })
+    .await
+    .map_err(|e| ChatError::UnknownError(e.to_string()))??;
+
+    Ok((destination, unpacked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_archive_and_extract_roundtrip() {
+        let source = tempdir().unwrap();
+        let project = source.path().join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(project.join("README.md"), "hello").unwrap();
+        std::fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+
+        let (name, data) = archive_directory(project.to_str().unwrap(), DEFAULT_MAX_ARCHIVE_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(name, "project.tar");
+        assert!(is_archive_name(&name));
+
+        let received = tempdir().unwrap();
+        let archive_path = received.path().join(&name);
+        std::fs::write(&archive_path, data).unwrap();
+
+        let sandbox = received.path().join(EXTRACT_DIR);
+        let (destination, unpacked) = extract_archive(&archive_path, &sandbox).await.unwrap();
+
+        assert!(unpacked >= 3);
+        assert_eq!(
+            std::fs::read_to_string(destination.join("src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_archive_rejects_oversized_directory() {
+        let source = tempdir().unwrap();
+        std::fs::write(source.path().join("big.bin"), vec![0u8; 64 * 1024]).unwrap();
+
+        let result = archive_directory(source.path().to_str().unwrap(), 1024).await;
+        assert!(matches!(result, Err(ChatError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_archive_rejects_file() {
+        let source = tempdir().unwrap();
+        let file = source.path().join("file.txt");
+        std::fs::write(&file, "x").unwrap();
+
+        let result = archive_directory(file.to_str().unwrap(), DEFAULT_MAX_ARCHIVE_SIZE).await;
+        assert!(matches!(result, Err(ChatError::InvalidInput(_))));
+    }
+}
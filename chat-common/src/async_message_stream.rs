@@ -1,13 +1,32 @@
-use crate::{Message, Result};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::codec::{self, Cbor, Codec};
+use crate::{ChatError, Message, Result, CAPABILITIES, PROTOCOL_VERSION};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf, ReadHalf, WriteHalf};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
 
+/// Version of the frame layout, sent as the first byte of every frame
+pub const FRAME_VERSION: u8 = 1;
+
+/// Largest frame payload a stream reads unless configured otherwise, in bytes
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Payloads longer than this are read in chunks as they arrive instead of
+/// into a buffer of the announced length, in bytes
+pub const STREAMING_THRESHOLD: usize = 1024 * 1024;
+
 /// A trait for asynchronous message streaming over various network connections
 ///
 /// This trait provides a unified interface for reading and writing messages
-/// over different types of network streams. Messages are serialized using CBOR
-/// and prefixed with a 4-byte length in big-endian format.
+/// over different types of network streams. The streams below serialize
+/// messages with the [`Cbor`] codec and prefix them with the [`FRAME_VERSION`]
+/// byte and a 4-byte length in big-endian format; see [`crate::codec`] for
+/// streams speaking other codecs. The length is checked against
+/// [`max_frame_size`](Self::max_frame_size) before the payload is read.
 #[async_trait::async_trait]
 pub trait AsyncMessageStream {
     /// Reads a message from the stream
@@ -24,40 +43,185 @@ pub trait AsyncMessageStream {
     /// # Returns
     /// * `Result<()>` - Success or an error if writing fails
     async fn write_message(&mut self, message: &Message) -> Result<()>;
+
+    /// Returns the largest frame payload the stream reads, in bytes
+    ///
+    /// Longer frames are rejected with `FrameTooLarge` before any of their
+    /// payload is read.
+    fn max_frame_size(&self) -> usize {
+        DEFAULT_MAX_FRAME_SIZE
+    }
 }
 
-#[async_trait::async_trait]
-impl AsyncMessageStream for TcpStream {
-    async fn read_message(&mut self) -> Result<Message> {
-        let mut len_bytes = [0u8; 4];
-        self.read_exact(&mut len_bytes).await?;
-        let len = u32::from_be_bytes(len_bytes) as usize;
+/// Builds the header of a frame carrying `len` bytes of payload.
+pub fn frame_header(len: usize) -> [u8; 5] {
+    let mut header = [FRAME_VERSION, 0, 0, 0, 0];
+    header[1..].copy_from_slice(&(len as u32).to_be_bytes());
+    header
+}
+
+/// Reads the header of a frame, returning the length of its payload.
+///
+/// Frames of another layout version or longer than `max_frame_size` are
+/// rejected before their payload is read, so a peer speaking an incompatible
+/// protocol is not misparsed and a bogus length is never allocated.
+pub async fn read_frame_header<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_frame_size: usize,
+) -> Result<usize> {
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header[..1]).await?;
+    if header[0] != FRAME_VERSION {
+        return Err(ChatError::UnsupportedProtocol(format!(
+            "frame version {} instead of {}",
+            header[0], FRAME_VERSION
+        )));
+    }
+    reader.read_exact(&mut header[1..]).await?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    check_frame_size(len, max_frame_size)?;
+    Ok(len)
+}
 
+/// Rejects a frame whose payload is longer than `max_frame_size` bytes.
+pub fn check_frame_size(len: usize, max_frame_size: usize) -> Result<()> {
+    if len > max_frame_size {
+        return Err(ChatError::FrameTooLarge(format!(
+            "{} bytes announced, at most {} accepted",
+            len, max_frame_size
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the payload of a frame of `len` bytes.
+///
+/// Payloads up to [`STREAMING_THRESHOLD`] are read into a buffer of their
+/// length. Longer ones are read in chunks and the buffer grows with the bytes
+/// that actually arrive, so a peer announcing a large frame it never sends
+/// cannot make the reader allocate it.
+pub async fn read_frame_payload<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    len: usize,
+) -> Result<Vec<u8>> {
+    if len <= STREAMING_THRESHOLD {
         let mut buffer = vec![0u8; len];
-        self.read_exact(&mut buffer).await?;
+        reader.read_exact(&mut buffer).await?;
+        return Ok(buffer);
+    }
+
+    let mut buffer = Vec::with_capacity(STREAMING_THRESHOLD);
+    (&mut *reader)
+        .take(len as u64)
+        .read_to_end(&mut buffer)
+        .await?;
+    if buffer.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("frame ended after {} of {} bytes", buffer.len(), len),
+        )
+        .into());
+    }
+    Ok(buffer)
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_frame_size: usize,
+) -> Result<Message> {
+    let len = read_frame_header(reader, max_frame_size).await?;
+    let buffer = read_frame_payload(reader, len).await?;
+
+    Cbor.decode(&buffer)
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> Result<()> {
+    codec::write_frame_with(writer, message, &Cbor).await
+}
+
+/// Introduces a client to the server on a freshly connected stream.
+///
+/// Sends a `Hello` with this build's [`PROTOCOL_VERSION`] and waits for the
+/// server's.
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The capabilities the server advertised, or
+///   `UnsupportedProtocol` if the server rejected the client's version
+pub async fn client_handshake<S: AsyncMessageStream + Send>(stream: &mut S) -> Result<Vec<String>> {
+    stream
+        .write_message(&Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        })
+        .await?;
 
-        Ok(serde_cbor::from_slice(&buffer)?)
+    match stream.read_message().await? {
+        Message::Hello {
+            protocol_version,
+            capabilities,
+        } if protocol_version == PROTOCOL_VERSION => Ok(capabilities),
+        Message::Hello {
+            protocol_version, ..
+        } => Err(ChatError::UnsupportedProtocol(format!(
+            "the server speaks protocol version {} instead of {}",
+            protocol_version, PROTOCOL_VERSION
+        ))),
+        Message::Error { message, .. } => Err(ChatError::UnsupportedProtocol(message)),
+        _ => Err(ChatError::UnsupportedProtocol(
+            "the server did not answer the handshake".to_string(),
+        )),
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncMessageStream for TcpStream {
+    async fn read_message(&mut self) -> Result<Message> {
+        let max_frame_size = self.max_frame_size();
+        read_frame(self, max_frame_size).await
     }
 
     async fn write_message(&mut self, message: &Message) -> Result<()> {
-        let bytes = serde_cbor::to_vec(message)?;
-        self.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
-        self.write_all(&bytes).await?;
-        Ok(())
+        write_frame(self, message).await
     }
 }
 
 #[async_trait::async_trait]
 impl AsyncMessageStream for OwnedReadHalf {
     async fn read_message(&mut self) -> Result<Message> {
-        let mut len_bytes = [0u8; 4];
-        self.read_exact(&mut len_bytes).await?;
-        let len = u32::from_be_bytes(len_bytes) as usize;
+        let max_frame_size = self.max_frame_size();
+        read_frame(self, max_frame_size).await
+    }
 
-        let mut buffer = vec![0u8; len];
-        self.read_exact(&mut buffer).await?;
+    async fn write_message(&mut self, _message: &Message) -> Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot write messages with ReadHalf",
+        )
+        .into())
+    }
+}
 
-        Ok(serde_cbor::from_slice(&buffer)?)
+#[async_trait::async_trait]
+impl AsyncMessageStream for OwnedWriteHalf {
+    async fn read_message(&mut self) -> Result<Message> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot read messages with WriteHalf",
+        )
+        .into())
+    }
+
+    async fn write_message(&mut self, message: &Message) -> Result<()> {
+        write_frame(self, message).await
+    }
+}
+
+/// The read half of a stream split with [`tokio::io::split`], such as a TLS stream.
+#[async_trait::async_trait]
+impl<T: AsyncRead + Send> AsyncMessageStream for ReadHalf<T> {
+    async fn read_message(&mut self) -> Result<Message> {
+        let max_frame_size = self.max_frame_size();
+        read_frame(self, max_frame_size).await
     }
 
     async fn write_message(&mut self, _message: &Message) -> Result<()> {
@@ -69,8 +233,9 @@ impl AsyncMessageStream for OwnedReadHalf {
     }
 }
 
+/// The write half of a stream split with [`tokio::io::split`], such as a TLS stream.
 #[async_trait::async_trait]
-impl AsyncMessageStream for OwnedWriteHalf {
+impl<T: AsyncWrite + Send> AsyncMessageStream for WriteHalf<T> {
     async fn read_message(&mut self) -> Result<Message> {
         Err(std::io::Error::new(
             std::io::ErrorKind::Unsupported,
@@ -80,9 +245,233 @@ impl AsyncMessageStream for OwnedWriteHalf {
     }
 
     async fn write_message(&mut self, message: &Message) -> Result<()> {
-        let bytes = serde_cbor::to_vec(message)?;
-        self.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
-        self.write_all(&bytes).await?;
-        Ok(())
+        write_frame(self, message).await
+    }
+}
+
+/// Receives the number of bytes going through a [`Metered`] stream.
+pub trait ByteMeter: Send + Sync {
+    fn read(&self, bytes: u64);
+    fn written(&self, bytes: u64);
+}
+
+/// Bytes read from and written to a stream so far.
+#[derive(Debug, Default)]
+pub struct ByteCounters {
+    read: AtomicU64,
+    written: AtomicU64,
+}
+
+impl ByteCounters {
+    pub fn bytes_read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+impl ByteMeter for ByteCounters {
+    fn read(&self, bytes: u64) {
+        self.read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn written(&self, bytes: u64) {
+        self.written.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// A stream that reports the bytes read from and written to it to a meter.
+///
+/// Split it with [`tokio::io::split`] to read and write messages on separate
+/// tasks; both halves report to the same meter.
+pub struct Metered<T> {
+    inner: T,
+    meter: Arc<dyn ByteMeter>,
+}
+
+impl<T> Metered<T> {
+    pub fn new(inner: T, meter: Arc<dyn ByteMeter>) -> Self {
+        Self { inner, meter }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Metered<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = polled {
+            self.meter.read((buf.filled().len() - before) as u64);
+        }
+        polled
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Metered<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let polled = Pin::new(&mut self.inner).poll_write(cx, data);
+        if let Poll::Ready(Ok(written)) = polled {
+            self.meter.written(written as u64);
+        }
+        polled
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_split_stream_roundtrip() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (_, mut client_writer) = tokio::io::split(client);
+        let (mut server_reader, _) = tokio::io::split(server);
+
+        let message = Message::Text("Hello over a split stream".to_string());
+        client_writer.write_message(&message).await.unwrap();
+
+        assert_eq!(server_reader.read_message().await.unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_other_frame_versions() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (mut server_reader, _) = tokio::io::split(server);
+
+        // A frame without the version byte, as written by older clients
+        let bytes = serde_cbor::to_vec(&Message::Text("Hi".to_string())).unwrap();
+        client
+            .write_all(&(bytes.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        client.write_all(&bytes).await.unwrap();
+
+        assert!(matches!(
+            server_reader.read_message().await,
+            Err(ChatError::UnsupportedProtocol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_frames() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (mut server_reader, _) = tokio::io::split(server);
+
+        // Only the header is sent, the payload is never allocated
+        client
+            .write_all(&frame_header(u32::MAX as usize))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            server_reader.read_message().await,
+            Err(ChatError::FrameTooLarge(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_streams_large_frames() {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (_, mut client_writer) = tokio::io::split(client);
+        let (mut server_reader, _) = tokio::io::split(server);
+
+        let message = Message::File {
+            name: "backup.tar".to_string(),
+            metadata: serde_json::Value::Null,
+            data: vec![7; 3 * STREAMING_THRESHOLD],
+        };
+        let sent = message.clone();
+        let writer = tokio::spawn(async move { client_writer.write_message(&sent).await });
+
+        assert_eq!(server_reader.read_message().await.unwrap(), message);
+        writer.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_truncated_large_frame() {
+        let (mut client, mut server) = tokio::io::duplex(64 * 1024);
+        let len = 2 * STREAMING_THRESHOLD;
+        client.write_all(&frame_header(len)).await.unwrap();
+        client.write_all(&[0; 1024]).await.unwrap();
+        drop(client);
+
+        let len = read_frame_header(&mut server, DEFAULT_MAX_FRAME_SIZE)
+            .await
+            .unwrap();
+        match read_frame_payload(&mut server, len).await {
+            Err(ChatError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!(
+                "expected an unexpected EOF, got {:?}",
+                other.map(|b| b.len())
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_handshake() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let hello = stream.read_message().await.unwrap();
+            stream.write_message(&hello).await.unwrap();
+            hello
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        // The test server echoes the Hello, so it supports what the client does
+        assert_eq!(
+            client_handshake(&mut client).await.unwrap(),
+            crate::CAPABILITIES
+        );
+        assert!(matches!(
+            server.await.unwrap(),
+            Message::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_metered_stream_counts_bytes() {
+        let (client, server) = tokio::io::duplex(1024);
+        let counters = Arc::new(ByteCounters::default());
+        let (mut client_reader, mut client_writer) =
+            tokio::io::split(Metered::new(client, counters.clone()));
+        let (mut server_reader, mut server_writer) = tokio::io::split(server);
+
+        let message = Message::Text("Hello".to_string());
+        let size = 5 + serde_cbor::to_vec(&message).unwrap().len() as u64;
+        client_writer.write_message(&message).await.unwrap();
+        server_reader.read_message().await.unwrap();
+        assert_eq!(counters.bytes_written(), size);
+        assert_eq!(counters.bytes_read(), 0);
+
+        server_writer.write_message(&message).await.unwrap();
+        client_reader.read_message().await.unwrap();
+        assert_eq!(counters.bytes_read(), size);
     }
 }
@@ -0,0 +1,321 @@
+//! Wire formats of message frames.
+//!
+//! The first byte of a frame names the [`Codec`] its payload is encoded with.
+//! CBOR frames start with [`FRAME_VERSION`], so they keep their original
+//! layout and every [`AsyncMessageStream`] reads them. Other codecs, such as
+//! the server's Protobuf codec for clients not written in Rust, have their own
+//! tag. A [`CodecReader`] decodes the frames of any codec in its [`Codecs`],
+//! and the first frame it reads selects the codec the connection's
+//! [`CodecWriter`] encodes with, so a client chooses the codec by the one its
+//! `Hello` is encoded with.
+
+use crate::async_message_stream::{
+    check_frame_size, frame_header, read_frame_payload, DEFAULT_MAX_FRAME_SIZE, FRAME_VERSION,
+};
+use crate::{AsyncMessageStream, ChatError, Message, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Index of the selected codec before any frame was read
+const UNSELECTED: usize = usize::MAX;
+
+/// Encodes messages into frame payloads and decodes them back.
+pub trait Codec: Send + Sync {
+    /// Name of the format, for logs
+    fn name(&self) -> &'static str;
+
+    /// First byte of the frames carrying payloads of this codec
+    fn tag(&self) -> u8;
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message>;
+}
+
+/// CBOR through serde, the format of the chat client.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Cbor;
+
+impl Codec for Cbor {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn tag(&self) -> u8 {
+        FRAME_VERSION
+    }
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>> {
+        Ok(serde_cbor::to_vec(message)?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message> {
+        Ok(serde_cbor::from_slice(bytes)?)
+    }
+}
+
+/// The codecs a connection understands and the one it writes with, shared by
+/// its reader and writer.
+#[derive(Clone)]
+pub struct Codecs {
+    codecs: Arc<[Arc<dyn Codec>]>,
+    selected: Arc<AtomicUsize>,
+}
+
+impl Default for Codecs {
+    /// Only CBOR.
+    fn default() -> Self {
+        Self {
+            codecs: Arc::new([Arc::new(Cbor) as Arc<dyn Codec>]),
+            selected: Arc::new(AtomicUsize::new(UNSELECTED)),
+        }
+    }
+}
+
+impl Codecs {
+    /// Adds a codec; the first one is written with until a frame was read.
+    pub fn with_codec(self, codec: Arc<dyn Codec>) -> Self {
+        let mut codecs = self.codecs.to_vec();
+        codecs.retain(|existing| existing.tag() != codec.tag());
+        codecs.push(codec);
+        Self {
+            codecs: codecs.into(),
+            selected: Arc::new(AtomicUsize::new(UNSELECTED)),
+        }
+    }
+
+    /// Returns the codec outgoing frames are encoded with.
+    pub fn selected(&self) -> &dyn Codec {
+        let index = self.selected.load(Ordering::Acquire);
+        self.codecs.get(index).unwrap_or(&self.codecs[0]).as_ref()
+    }
+
+    /// Returns the index and codec of frames starting with `tag`.
+    fn by_tag(&self, tag: u8) -> Option<(usize, &dyn Codec)> {
+        self.codecs
+            .iter()
+            .enumerate()
+            .find(|(_, codec)| codec.tag() == tag)
+            .map(|(index, codec)| (index, codec.as_ref()))
+    }
+
+    /// Makes the codec at `index` the one written with, unless one was
+    /// selected before.
+    fn select(&self, index: usize) {
+        let _ =
+            self.selected
+                .compare_exchange(UNSELECTED, index, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+/// Reads a frame in any of the given codecs.
+///
+/// The codec of the first frame read becomes the one the connection writes
+/// with. Frames of an unknown codec or longer than `max_frame_size` are
+/// rejected before their payload is read.
+pub async fn read_frame_with<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    codecs: &Codecs,
+    max_frame_size: usize,
+) -> Result<Message> {
+    let tag = reader.read_u8().await?;
+    let Some((index, codec)) = codecs.by_tag(tag) else {
+        return Err(ChatError::UnsupportedProtocol(format!(
+            "frame tag {} of no known codec",
+            tag
+        )));
+    };
+    let len = reader.read_u32().await? as usize;
+    check_frame_size(len, max_frame_size)?;
+    let buffer = read_frame_payload(reader, len).await?;
+
+    let message = codec.decode(&buffer)?;
+    codecs.select(index);
+    Ok(message)
+}
+
+/// Writes a frame in the given codec.
+pub async fn write_frame_with<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &Message,
+    codec: &dyn Codec,
+) -> Result<()> {
+    let bytes = codec.encode(message)?;
+    let mut header = frame_header(bytes.len());
+    header[0] = codec.tag();
+    writer.write_all(&header).await?;
+    writer.write_all(&bytes).await?;
+    Ok(())
+}
+
+/// A read stream decoding frames of several codecs.
+pub struct CodecReader<R> {
+    inner: R,
+    codecs: Codecs,
+    max_frame_size: usize,
+}
+
+impl<R> CodecReader<R> {
+    pub fn new(inner: R, codecs: Codecs) -> Self {
+        Self {
+            inner,
+            codecs,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Sets the largest frame payload read, in bytes.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: AsyncRead + Unpin + Send> AsyncMessageStream for CodecReader<R> {
+    async fn read_message(&mut self) -> Result<Message> {
+        read_frame_with(&mut self.inner, &self.codecs, self.max_frame_size).await
+    }
+
+    async fn write_message(&mut self, _message: &Message) -> Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot write messages with CodecReader",
+        )
+        .into())
+    }
+
+    fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+/// A write stream encoding frames with the codec its reader selected.
+pub struct CodecWriter<W> {
+    inner: W,
+    codecs: Codecs,
+}
+
+impl<W> CodecWriter<W> {
+    pub fn new(inner: W, codecs: Codecs) -> Self {
+        Self { inner, codecs }
+    }
+}
+
+#[async_trait::async_trait]
+impl<W: AsyncWrite + Unpin + Send> AsyncMessageStream for CodecWriter<W> {
+    async fn read_message(&mut self) -> Result<Message> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot read messages with CodecWriter",
+        )
+        .into())
+    }
+
+    async fn write_message(&mut self, message: &Message) -> Result<()> {
+        write_frame_with(&mut self.inner, message, self.codecs.selected()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// JSON frames, standing in for a codec of another crate
+    struct Json;
+
+    impl Codec for Json {
+        fn name(&self) -> &'static str {
+            "json"
+        }
+
+        fn tag(&self) -> u8 {
+            9
+        }
+
+        fn encode(&self, message: &Message) -> Result<Vec<u8>> {
+            serde_json::to_vec(message).map_err(|e| ChatError::SerializationError(e.to_string()))
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<Message> {
+            serde_json::from_slice(bytes).map_err(|e| ChatError::SerializationError(e.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_with_the_codec_of_the_first_frame() {
+        let codecs = Codecs::default().with_codec(Arc::new(Json));
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (server_reader, server_writer) = tokio::io::split(server);
+        let mut reader = CodecReader::new(server_reader, codecs.clone());
+        let mut writer = CodecWriter::new(server_writer, codecs);
+
+        let hello = Message::Hello {
+            protocol_version: crate::PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+        };
+        write_frame_with(&mut client, &hello, &Json).await.unwrap();
+        assert_eq!(reader.read_message().await.unwrap(), hello);
+
+        // Later CBOR frames are still read, but the answers stay in JSON
+        write_frame_with(&mut client, &Message::Ping { nonce: 1 }, &Cbor)
+            .await
+            .unwrap();
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Message::Ping { nonce: 1 }
+        );
+        writer
+            .write_message(&Message::Pong { nonce: 1 })
+            .await
+            .unwrap();
+        let received = Codecs::default().with_codec(Arc::new(Json));
+        assert_eq!(
+            read_frame_with(&mut client, &received, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap(),
+            Message::Pong { nonce: 1 }
+        );
+        assert_eq!(received.selected().name(), "json");
+    }
+
+    #[tokio::test]
+    async fn test_cbor_by_default() {
+        let codecs = Codecs::default().with_codec(Arc::new(Json));
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut writer = CodecWriter::new(server, codecs);
+
+        // Nothing read yet, so the first codec is used
+        let message = Message::System("Welcome".to_string());
+        writer.write_message(&message).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), FRAME_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_frames_above_the_limit() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut reader = CodecReader::new(server, Codecs::default()).with_max_frame_size(16);
+
+        let text = Message::Text("More than sixteen bytes".to_string());
+        write_frame_with(&mut client, &text, &Cbor).await.unwrap();
+        assert!(matches!(
+            reader.read_message().await,
+            Err(ChatError::FrameTooLarge(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_codecs() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut reader = CodecReader::new(server, Codecs::default());
+
+        write_frame_with(&mut client, &Message::Ping { nonce: 1 }, &Json)
+            .await
+            .unwrap();
+        assert!(matches!(
+            reader.read_message().await,
+            Err(ChatError::UnsupportedProtocol(_))
+        ));
+    }
+}
@@ -6,6 +6,8 @@ use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 /// Size of chunks used for file encryption/decryption operations
@@ -18,11 +20,42 @@ pub struct EncryptedFileMetadata {
     pub nonce: String,
     /// Original size of the file before encryption
     pub original_size: u64,
+    /// Hex-encoded SHA-256 of the plaintext, verified after decryption
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Keyring entry the file was encrypted with; `None` for the shared key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+/// Returned when decrypted content does not match the hash in its metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityError {
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Integrity check failed: expected SHA-256 {}, got {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+/// Returns the hex-encoded SHA-256 of the given data
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
 }
 
 /// Handles file encryption and decryption using AES-256-GCM
 pub struct FileEncryption {
     cipher: Aes256Gcm,
+    key_id: Option<String>,
 }
 
 impl FileEncryption {
@@ -41,7 +74,16 @@ impl FileEncryption {
         let key = Key::<Aes256Gcm>::from_slice(key);
         let cipher = Aes256Gcm::new(key);
 
-        Ok(Self { cipher })
+        Ok(Self {
+            cipher,
+            key_id: None,
+        })
+    }
+
+    /// Tags the files this instance encrypts with a keyring entry
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
     }
 
     /// Encrypts a file stream using AES-256-GCM
@@ -66,14 +108,16 @@ impl FileEncryption {
         let nonce = Nonce::from_slice(&nonce_bytes);
 
         let mut total_size = 0u64;
+        let mut hasher = Sha256::new();
         let mut buffer = vec![0u8; CHUNK_SIZE];
 
         loop {
-            let n = reader.read(&mut buffer).await?;
+            let n = read_chunk(&mut reader, &mut buffer).await?;
             if n == 0 {
                 break;
             }
             total_size += n as u64;
+            hasher.update(&buffer[..n]);
 
             let encrypted = self
                 .cipher
@@ -88,6 +132,8 @@ impl FileEncryption {
         Ok(EncryptedFileMetadata {
             nonce: BASE64.encode(nonce_bytes),
             original_size: total_size,
+            sha256: Some(format!("{:x}", hasher.finalize())),
+            key_id: self.key_id.clone(),
         })
     }
 
@@ -96,10 +142,12 @@ impl FileEncryption {
     /// # Arguments
     /// * `reader` - Async reader providing the encrypted data
     /// * `writer` - Async writer for the decrypted output
-    /// * `metadata` - Metadata containing the nonce and original file size
+    /// * `metadata` - Metadata containing the nonce, original file size and plaintext hash
     ///
     /// # Returns
-    /// * `Result<()>` - Success or an error if decryption fails
+    /// * `Result<()>` - Success or an error if decryption fails. If the metadata carries
+    ///   a hash that the decrypted data does not match, the error is an [`IntegrityError`].
+    ///   The decrypted data has already been written to `writer` at that point.
     pub async fn decrypt_stream<R, W>(
         &self,
         mut reader: R,
@@ -117,9 +165,10 @@ impl FileEncryption {
 
         let mut buffer = vec![0u8; CHUNK_SIZE + 16];
         let mut bytes_remaining = metadata.original_size;
+        let mut hasher = Sha256::new();
 
         while bytes_remaining > 0 {
-            let n = reader.read(&mut buffer).await?;
+            let n = read_chunk(&mut reader, &mut buffer).await?;
             if n == 0 {
                 break;
             }
@@ -130,14 +179,41 @@ impl FileEncryption {
                 .map_err(|e| anyhow!("Decryption failed: {}", e))?;
 
             writer.write_all(&decrypted).await?;
-            bytes_remaining -= decrypted.len() as u64;
+            hasher.update(&decrypted);
+            bytes_remaining = bytes_remaining.saturating_sub(decrypted.len() as u64);
         }
 
         writer.flush().await?;
+
+        if let Some(expected) = &metadata.sha256 {
+            let actual = format!("{:x}", hasher.finalize());
+            if !expected.eq_ignore_ascii_case(&actual) {
+                return Err(IntegrityError {
+                    expected: expected.clone(),
+                    actual,
+                }
+                .into());
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Reads until the buffer is full or the reader ends, so chunks stay whole
+/// even when reading from a pipe, which delivers whatever is available.
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let n = reader.read(&mut buffer[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +240,49 @@ mod tests {
 
         assert_eq!(&original_data[..], &decrypted[..]);
     }
+
+    #[tokio::test]
+    async fn test_decrypt_detects_hash_mismatch() {
+        let encryption = FileEncryption::new(&[0u8; 32]).unwrap();
+
+        let mut encrypted = Vec::new();
+        let mut metadata = encryption
+            .encrypt_stream(BufReader::new(&b"Hello, World!"[..]), &mut encrypted)
+            .await
+            .unwrap();
+        assert_eq!(
+            metadata.sha256.as_deref(),
+            Some(sha256_hex(b"Hello, World!").as_str())
+        );
+
+        metadata.sha256 = Some(sha256_hex(b"something else"));
+        let mut decrypted = Vec::new();
+        let error = encryption
+            .decrypt_stream(BufReader::new(&encrypted[..]), &mut decrypted, &metadata)
+            .await
+            .unwrap_err();
+
+        assert!(error.downcast_ref::<IntegrityError>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_short_reads() {
+        let encryption = FileEncryption::new(&[0u8; 32]).unwrap();
+        let original_data = vec![7u8; CHUNK_SIZE + 100];
+
+        // A chained reader stops at the end of each part, like a pipe
+        let reader = (&original_data[..10]).chain(&original_data[10..]);
+        let mut encrypted = Vec::new();
+        let metadata = encryption
+            .encrypt_stream(reader, &mut encrypted)
+            .await
+            .unwrap();
+
+        let mut decrypted = Vec::new();
+        encryption
+            .decrypt_stream(&encrypted[..], &mut decrypted, &metadata)
+            .await
+            .unwrap();
+        assert_eq!(decrypted, original_data);
+    }
 }
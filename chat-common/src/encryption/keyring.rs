@@ -0,0 +1,117 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A symmetric key encrypted for a single holder
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// Base64 encoded encrypted key
+    pub wrapped_key: String,
+    /// Base64 encoded nonce used for wrapping
+    pub nonce: String,
+}
+
+/// Wraps and unwraps keys with a key derived from a shared secret
+///
+/// The wrapping key is the SHA-256 of the secret, so any secret both sides
+/// know can be used, such as the master key or a session token.
+pub struct KeyWrapper {
+    cipher: Aes256Gcm,
+}
+
+impl KeyWrapper {
+    /// Creates a new KeyWrapper for the given secret
+    ///
+    /// # Arguments
+    /// * `secret` - The secret the wrapping key is derived from
+    pub fn new(secret: &[u8]) -> Self {
+        let key = Sha256::digest(secret);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        Self { cipher }
+    }
+
+    /// Encrypts a key using AES-256-GCM
+    ///
+    /// # Arguments
+    /// * `key` - The 32-byte key to wrap
+    ///
+    /// # Returns
+    /// * `Result<WrappedKey>` - The wrapped key or an error if the key length is invalid
+    pub fn wrap(&self, key: &[u8]) -> Result<WrappedKey> {
+        if key.len() != 32 {
+            return Err(anyhow!("Key must be exactly 32 bytes"));
+        }
+
+        let mut nonce_bytes = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce_bytes);
+
+        let wrapped = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), key)
+            .map_err(|e| anyhow!("Key wrapping failed: {}", e))?;
+
+        Ok(WrappedKey {
+            wrapped_key: BASE64.encode(wrapped),
+            nonce: BASE64.encode(nonce_bytes),
+        })
+    }
+
+    /// Decrypts a key wrapped with the same secret
+    ///
+    /// # Arguments
+    /// * `wrapped` - The wrapped key with its nonce
+    ///
+    /// # Returns
+    /// * `Result<[u8; 32]>` - The key or an error if it was wrapped with another secret
+    pub fn unwrap(&self, wrapped: &WrappedKey) -> Result<[u8; 32]> {
+        let ciphertext = BASE64
+            .decode(&wrapped.wrapped_key)
+            .map_err(|e| anyhow!("Invalid base64 wrapped key: {}", e))?;
+        let nonce_bytes = BASE64
+            .decode(&wrapped.nonce)
+            .map_err(|e| anyhow!("Invalid base64 nonce: {}", e))?;
+        if nonce_bytes.len() != 12 {
+            return Err(anyhow!("Nonce must be exactly 12 bytes"));
+        }
+
+        let key = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce_bytes), ciphertext.as_ref())
+            .map_err(|e| anyhow!("Key unwrapping failed: {}", e))?;
+
+        key.try_into()
+            .map_err(|_| anyhow!("Unwrapped key must be exactly 32 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let key = [7u8; 32];
+        let wrapper = KeyWrapper::new(b"session-token");
+
+        let wrapped = wrapper.wrap(&key).unwrap();
+        assert_eq!(wrapper.unwrap(&wrapped).unwrap(), key);
+    }
+
+    #[test]
+    fn test_unwrap_with_other_secret_fails() {
+        let wrapped = KeyWrapper::new(b"alice-token").wrap(&[7u8; 32]).unwrap();
+
+        assert!(KeyWrapper::new(b"bob-token").unwrap(&wrapped).is_err());
+    }
+
+    #[test]
+    fn test_wrap_rejects_short_key() {
+        assert!(KeyWrapper::new(b"secret").wrap(&[0u8; 16]).is_err());
+    }
+}
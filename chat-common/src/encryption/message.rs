@@ -14,11 +14,15 @@ pub struct EncryptedMessage {
     pub ciphertext: String,
     /// Base64 encoded nonce used for encryption
     pub nonce: String,
+    /// Keyring entry the message was encrypted with; `None` for the shared key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
 }
 
 /// Handles message encryption and decryption using AES-256-GCM
 pub struct MessageEncryption {
     cipher: Aes256Gcm,
+    key_id: Option<String>,
 }
 
 impl MessageEncryption {
@@ -37,7 +41,16 @@ impl MessageEncryption {
         let key = Key::<Aes256Gcm>::from_slice(key);
         let cipher = Aes256Gcm::new(key);
 
-        Ok(Self { cipher })
+        Ok(Self {
+            cipher,
+            key_id: None,
+        })
+    }
+
+    /// Tags the messages this instance encrypts with a keyring entry
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
     }
 
     /// Generates a new random encryption key suitable for AES-256-GCM
@@ -70,6 +83,7 @@ impl MessageEncryption {
         Ok(EncryptedMessage {
             ciphertext: BASE64.encode(ciphertext),
             nonce: BASE64.encode(nonce_bytes),
+            key_id: self.key_id.clone(),
         })
     }
 
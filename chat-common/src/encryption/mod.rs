@@ -1,4 +1,5 @@
 pub mod file;
+pub mod keyring;
 pub mod message;
 pub mod service;
 
@@ -1,14 +1,32 @@
-use crate::encryption::{file::FileEncryption, message::MessageEncryption};
-use anyhow::Result;
-use std::sync::Arc;
+use crate::encryption::{
+    file::FileEncryption,
+    keyring::{KeyWrapper, WrappedKey},
+    message::MessageEncryption,
+};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Message and file encryption for a single key
+#[derive(Clone)]
+struct KeyEntry {
+    message: Arc<MessageEncryption>,
+    file: Arc<FileEncryption>,
+}
 
 /// A service that provides access to both message and file encryption capabilities
 ///
 /// This service wraps both message and file encryption implementations in thread-safe
 /// reference-counted containers, allowing them to be shared across threads.
+///
+/// Besides the shared key it was created with, the service holds a keyring of
+/// named keys, such as the keys of individual rooms. Content is encrypted with
+/// the active key and records its key ID, so it is decrypted with the same key.
 pub struct EncryptionService {
-    message_encryption: Arc<MessageEncryption>,
-    file_encryption: Arc<FileEncryption>,
+    shared: KeyEntry,
+    wrapper: KeyWrapper,
+    keys: RwLock<HashMap<String, KeyEntry>>,
+    active: RwLock<Option<String>>,
 }
 
 impl EncryptionService {
@@ -21,24 +39,167 @@ impl EncryptionService {
     /// * `Result<Self>` - A new EncryptionService instance or an error if key initialization fails
     pub fn new(key: &[u8]) -> Result<Self> {
         Ok(Self {
-            message_encryption: Arc::new(MessageEncryption::new(key)?),
-            file_encryption: Arc::new(FileEncryption::new(key)?),
+            shared: KeyEntry {
+                message: Arc::new(MessageEncryption::new(key)?),
+                file: Arc::new(FileEncryption::new(key)?),
+            },
+            wrapper: KeyWrapper::new(key),
+            keys: RwLock::new(HashMap::new()),
+            active: RwLock::new(None),
         })
     }
 
-    /// Returns a thread-safe reference to the message encryption service
+    /// Adds a key to the keyring, replacing any key with the same ID
+    ///
+    /// # Arguments
+    /// * `key_id` - The ID recorded in content encrypted with the key
+    /// * `key` - A 32-byte key
+    pub fn add_key(&self, key_id: &str, key: &[u8]) -> Result<()> {
+        let entry = KeyEntry {
+            message: Arc::new(MessageEncryption::new(key)?.with_key_id(key_id)),
+            file: Arc::new(FileEncryption::new(key)?.with_key_id(key_id)),
+        };
+        self.keys.write().unwrap().insert(key_id.to_string(), entry);
+        Ok(())
+    }
+
+    /// Returns `true` if the keyring holds a key with the given ID
+    pub fn has_key(&self, key_id: &str) -> bool {
+        self.keys.read().unwrap().contains_key(key_id)
+    }
+
+    /// Chooses the key new content is encrypted with
+    ///
+    /// # Arguments
+    /// * `key_id` - A key in the keyring, or `None` for the shared key
+    ///
+    /// # Returns
+    /// * `Result<()>` - An error if the keyring holds no such key
+    pub fn set_active_key(&self, key_id: Option<&str>) -> Result<()> {
+        if let Some(key_id) = key_id {
+            if !self.has_key(key_id) {
+                return Err(anyhow!("Unknown encryption key '{}'", key_id));
+            }
+        }
+        *self.active.write().unwrap() = key_id.map(str::to_string);
+        Ok(())
+    }
+
+    /// Returns the ID of the active key, `None` for the shared key
+    pub fn active_key(&self) -> Option<String> {
+        self.active.read().unwrap().clone()
+    }
+
+    fn entry(&self, key_id: Option<&str>) -> Result<KeyEntry> {
+        match key_id {
+            None => Ok(self.shared.clone()),
+            Some(key_id) => self
+                .keys
+                .read()
+                .unwrap()
+                .get(key_id)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown encryption key '{}'", key_id)),
+        }
+    }
+
+    fn active_entry(&self) -> KeyEntry {
+        let active = self.active.read().unwrap();
+        self.entry(active.as_deref())
+            .unwrap_or_else(|_| self.shared.clone())
+    }
+
+    /// Returns a thread-safe reference to the message encryption service of the active key
     ///
     /// # Returns
     /// * `Arc<MessageEncryption>` - A thread-safe reference to the message encryption service
     pub fn message(&self) -> Arc<MessageEncryption> {
-        Arc::clone(&self.message_encryption)
+        self.active_entry().message
     }
 
-    /// Returns a thread-safe reference to the file encryption service
+    /// Returns a thread-safe reference to the file encryption service of the active key
     ///
     /// # Returns
     /// * `Arc<FileEncryption>` - A thread-safe reference to the file encryption service
     pub fn file(&self) -> Arc<FileEncryption> {
-        Arc::clone(&self.file_encryption)
+        self.active_entry().file
+    }
+
+    /// Returns the message encryption service of a key
+    ///
+    /// # Arguments
+    /// * `key_id` - The key ID recorded in the content, or `None` for the shared key
+    ///
+    /// # Returns
+    /// * `Result<Arc<MessageEncryption>>` - The service or an error if the keyring holds no such key
+    pub fn message_for(&self, key_id: Option<&str>) -> Result<Arc<MessageEncryption>> {
+        Ok(self.entry(key_id)?.message)
+    }
+
+    /// Returns the file encryption service of a key
+    ///
+    /// # Arguments
+    /// * `key_id` - The key ID recorded in the metadata, or `None` for the shared key
+    ///
+    /// # Returns
+    /// * `Result<Arc<FileEncryption>>` - The service or an error if the keyring holds no such key
+    pub fn file_for(&self, key_id: Option<&str>) -> Result<Arc<FileEncryption>> {
+        Ok(self.entry(key_id)?.file)
+    }
+
+    /// Wraps a key with the shared key, for storing it
+    pub fn wrap_key(&self, key: &[u8]) -> Result<WrappedKey> {
+        self.wrapper.wrap(key)
+    }
+
+    /// Unwraps a key that was wrapped with the shared key
+    pub fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<[u8; 32]> {
+        self.wrapper.unwrap(wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_is_decrypted_with_its_key() {
+        let service = EncryptionService::new(&[0u8; 32]).unwrap();
+        service.add_key("room-1", &[1u8; 32]).unwrap();
+
+        let shared = service.message().encrypt("Hello").unwrap();
+        assert_eq!(shared.key_id, None);
+
+        service.set_active_key(Some("room-1")).unwrap();
+        let room = service.message().encrypt("Hello").unwrap();
+        assert_eq!(room.key_id.as_deref(), Some("room-1"));
+
+        // The shared key cannot read room content
+        assert!(service.message_for(None).unwrap().decrypt(&room).is_err());
+        for encrypted in [&shared, &room] {
+            let decrypted = service
+                .message_for(encrypted.key_id.as_deref())
+                .unwrap()
+                .decrypt(encrypted)
+                .unwrap();
+            assert_eq!(decrypted, "Hello");
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let service = EncryptionService::new(&[0u8; 32]).unwrap();
+
+        assert!(service.set_active_key(Some("room-1")).is_err());
+        assert!(service.message_for(Some("room-1")).is_err());
+        assert_eq!(service.active_key(), None);
+    }
+
+    #[test]
+    fn test_wrapped_keys_round_trip() {
+        let service = EncryptionService::new(&[0u8; 32]).unwrap();
+        let wrapped = service.wrap_key(&[9u8; 32]).unwrap();
+
+        assert_eq!(service.unwrap_key(&wrapped).unwrap(), [9u8; 32]);
     }
 }
@@ -21,6 +21,14 @@ pub enum ErrorCode {
     NetworkError,
     /// An error occurred while processing an image
     ImageProcessingError,
+    /// Transferred data did not match its integrity hash
+    IntegrityError,
+    /// The request conflicts with existing data, such as a taken username
+    Conflict,
+    /// The request would take the user over a storage quota
+    QuotaExceeded,
+    /// The peer speaks a protocol version this side does not support
+    UnsupportedProtocol,
     /// An unknown or unexpected error occurred
     UnknownError,
 }
@@ -64,6 +72,21 @@ pub enum ChatError {
 
     #[error("Invalid command: {0}")]
     InvalidCommand(String),
+
+    #[error("Corrupted transfer: {0}")]
+    IntegrityError(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Unsupported protocol: {0}")]
+    UnsupportedProtocol(String),
+
+    #[error("Frame too large: {0}")]
+    FrameTooLarge(String),
 }
 
 impl ChatError {
@@ -83,6 +106,11 @@ impl ChatError {
             ChatError::SerializationError(_) => ErrorCode::UnknownError,
             ChatError::InvalidPath(_) => ErrorCode::UnknownError,
             ChatError::InvalidCommand(_) => ErrorCode::UnknownError,
+            ChatError::IntegrityError(_) => ErrorCode::IntegrityError,
+            ChatError::Conflict(_) => ErrorCode::Conflict,
+            ChatError::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            ChatError::UnsupportedProtocol(_) => ErrorCode::UnsupportedProtocol,
+            ChatError::FrameTooLarge(_) => ErrorCode::InvalidInput,
         }
     }
 }
@@ -95,7 +123,10 @@ impl From<serde_cbor::Error> for ChatError {
 
 impl From<anyhow::Error> for ChatError {
     fn from(err: anyhow::Error) -> Self {
-        ChatError::UnknownError(err.to_string())
+        match err.downcast_ref::<crate::encryption::file::IntegrityError>() {
+            Some(integrity) => ChatError::IntegrityError(integrity.to_string()),
+            None => ChatError::UnknownError(err.to_string()),
+        }
     }
 }
 
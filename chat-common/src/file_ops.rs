@@ -1,12 +1,19 @@
 use crate::encryption::EncryptionService;
 use crate::error::{ChatError, Result};
 use crate::Message;
+use chrono::{Local, NaiveDate};
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageEncoder, ImageFormat, ImageOutputFormat};
 use serde_json;
-use std::path::Path;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
+use tempfile::NamedTempFile;
 use tokio::fs;
 use tokio::fs::File;
-use tokio::io::BufReader;
+use tokio::io::{AsyncWriteExt, BufReader};
 
 /// Processes a file command, handling file validation and optional encryption
 ///
@@ -133,51 +140,755 @@ pub async fn encrypt_file(
     }
 }
 
-/// Saves a file to the files directory
+/// Default longest edge, in pixels, images are downscaled to before sending
+pub const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 2048;
+
+/// Default size, in bytes, above which images are downscaled before sending (1 MiB)
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 1024 * 1024;
+
+/// Smallest longest edge the downscaler shrinks an image to while chasing the size limit
+const MIN_IMAGE_DIMENSION: u32 = 256;
+
+/// JPEG quality used when re-encoding downscaled photos
+const JPEG_QUALITY: u8 = 85;
+
+/// Thresholds above which `.image` uploads are downscaled before being sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageDownscale {
+    /// Longest edge of the sent image in pixels
+    pub max_dimension: u32,
+    /// Size of the sent image in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for ImageDownscale {
+    fn default() -> Self {
+        Self {
+            max_dimension: DEFAULT_MAX_IMAGE_DIMENSION,
+            max_bytes: DEFAULT_MAX_IMAGE_BYTES,
+        }
+    }
+}
+
+/// Processes an image command, downscaling the image before it is encrypted
+///
+/// Without downscale options this behaves exactly like `process_file_command(".image", ..)`.
+/// With them, images above either threshold are resized and re-encoded in their
+/// original format, so the encrypted payload is what actually gets smaller.
+///
+/// # Arguments
+/// * `path_str` - Path to the image to send
+/// * `downscale` - Optional thresholds above which the image is downscaled
+/// * `encryption` - Optional encryption service for encrypting the image
+///
+/// # Returns
+/// * `Result<Message>` - An image message or an error if processing fails
+pub async fn process_image_command(
+    path_str: &str,
+    downscale: Option<ImageDownscale>,
+    encryption: Option<Arc<EncryptionService>>,
+) -> Result<Message> {
+    let Some(downscale) = downscale else {
+        return process_file_command(".image", path_str, encryption).await;
+    };
+
+    let path = Path::new(path_str.trim());
+
+    if !path.exists() {
+        return Err(ChatError::NotFound(path_str.to_string()));
+    }
+
+    if !path.is_file() {
+        return Err(ChatError::InvalidInput(format!("Not a file: {}", path_str)));
+    }
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| ChatError::InvalidInput("Invalid file name".to_string()))?
+        .to_string_lossy()
+        .into();
+
+    let data = downscale_image(fs::read(path).await?, downscale).await?;
+
+    let Some(encryption) = encryption else {
+        return Ok(Message::Image {
+            name,
+            metadata: serde_json::json!({}),
+            data,
+        });
+    };
+
+    let mut encrypted = Vec::new();
+    let metadata = encryption
+        .file()
+        .encrypt_stream(BufReader::new(&data[..]), &mut encrypted)
+        .await?;
+
+    Ok(Message::Image {
+        name,
+        metadata: serde_json::to_value(metadata)?,
+        data: encrypted,
+    })
+}
+
+/// Downscales an image that exceeds the given thresholds
+///
+/// The image is resized so its longest edge fits `max_dimension` and then, while
+/// the re-encoded image is still larger than `max_bytes`, shrunk further down to
+/// a floor of 256 pixels. Decoding and encoding run on the blocking thread pool.
+/// Images within both thresholds, and images that would not get any smaller,
+/// are returned untouched.
+///
+/// # Arguments
+/// * `data` - The encoded image
+/// * `options` - The thresholds to apply
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The (possibly) downscaled image in its original format
+pub async fn downscale_image(data: Vec<u8>, options: ImageDownscale) -> Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let format = image::guess_format(&data)
+            .map_err(|e| ChatError::ImageProcessingError(format!("Invalid image format: {}", e)))?;
+        let img = image::load_from_memory_with_format(&data, format)
+            .map_err(|e| ChatError::ImageProcessingError(format!("Invalid image format: {}", e)))?;
+
+        let (width, height) = img.dimensions();
+        let longest_edge = width.max(height);
+        if longest_edge <= options.max_dimension && data.len() <= options.max_bytes {
+            return Ok(data);
+        }
+
+        let mut target = longest_edge.min(options.max_dimension);
+        let mut encoded = encode_image(&img.resize(target, target, FilterType::Lanczos3), format)?;
+
+        while encoded.len() > options.max_bytes && target > MIN_IMAGE_DIMENSION {
+            target = (target * 3 / 4).max(MIN_IMAGE_DIMENSION);
+            encoded = encode_image(&img.resize(target, target, FilterType::Lanczos3), format)?;
+        }
+
+        if encoded.len() >= data.len() && longest_edge <= options.max_dimension {
+            return Ok(data);
+        }
+
+        Ok(encoded)
+    })
+    .await
+    .map_err(|e| ChatError::UnknownError(e.to_string()))?
+}
+
+/// Formats `strip_image_metadata` re-encodes; cameras and phones write EXIF and
+/// GPS data into these
+const SANITIZED_FORMATS: [ImageFormat; 3] =
+    [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Tiff];
+
+/// EXIF tag holding the orientation of the camera
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Removes EXIF, GPS and any other metadata from an image
+///
+/// The image is decoded and re-encoded in its original format, which keeps
+/// nothing but the pixels. The EXIF orientation is applied to the pixels first,
+/// so photos taken with a turned camera still show upright. Decoding and
+/// encoding run on the blocking thread pool. Images in formats other than JPEG,
+/// PNG and TIFF are returned untouched.
+///
+/// # Arguments
+/// * `data` - The encoded image
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The image without metadata, or an error if it cannot be decoded
+pub async fn strip_image_metadata(data: Vec<u8>) -> Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let format = image::guess_format(&data)
+            .map_err(|e| ChatError::ImageProcessingError(format!("Invalid image format: {}", e)))?;
+        if !SANITIZED_FORMATS.contains(&format) {
+            return Ok(data);
+        }
+        let img = image::load_from_memory_with_format(&data, format)
+            .map_err(|e| ChatError::ImageProcessingError(format!("Invalid image format: {}", e)))?;
+
+        let orientation = match format {
+            ImageFormat::Jpeg => jpeg_exif(&data).and_then(tiff_orientation),
+            ImageFormat::Tiff => tiff_orientation(&data),
+            _ => None,
+        };
+        encode_image(&apply_orientation(img, orientation.unwrap_or(1)), format)
+    })
+    .await
+    .map_err(|e| ChatError::UnknownError(e.to_string()))?
+}
+
+/// Returns the EXIF data of a JPEG, which is laid out like a TIFF file
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    if data.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut offset = 2;
+    loop {
+        let marker = data.get(offset..offset + 2)?;
+        // The metadata segments precede the start of the image data
+        if marker[0] != 0xFF || marker[1] == 0xDA {
+            return None;
+        }
+        let length = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]) as usize;
+        let segment = data.get(offset + 4..offset + 2 + length)?;
+        if marker[1] == 0xE1 {
+            if let Some(exif) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(exif);
+            }
+        }
+        offset += 2 + length;
+    }
+}
+
+/// Reads the orientation from the first directory of a TIFF structure
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes: [u8; 4] = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let directory = u32_at(4)? as usize;
+    // Every entry has 12 bytes: tag, type, count and the value itself
+    (0..u16_at(directory)? as usize)
+        .map(|index| directory + 2 + index * 12)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// Turns an image so it shows as the EXIF `orientation` describes
+fn apply_orientation(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Encodes an image in the given format, using `JPEG_QUALITY` for JPEG output
+fn encode_image(img: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let output_format = match format {
+        ImageFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+        other => ImageOutputFormat::from(other),
+    };
+
+    let mut encoded = Vec::new();
+    img.write_to(&mut Cursor::new(&mut encoded), output_format)
+        .map_err(|e| ChatError::ImageProcessingError(format!("Failed to encode image: {}", e)))?;
+    Ok(encoded)
+}
+
+/// Packs a directory into an archive and wraps it in a file message
+///
+/// The archive is sent through the regular file pipeline; receivers recognize it
+/// by its `.tar` extension.
+///
+/// # Arguments
+/// * `path_str` - Path to the directory to send
+/// * `max_size` - Maximum size of the archive in bytes
+/// * `encryption` - Optional encryption service for encrypting the archive
+///
+/// # Returns
+/// * `Result<Message>` - A file message containing the archive or an error if packing fails
+pub async fn process_dir_command(
+    path_str: &str,
+    max_size: u64,
+    encryption: Option<Arc<EncryptionService>>,
+) -> Result<Message> {
+    let (name, archive) = crate::archive::archive_directory(path_str, max_size).await?;
+
+    let Some(encryption) = encryption else {
+        return Ok(Message::File {
+            name,
+            metadata: serde_json::json!({}),
+            data: archive,
+        });
+    };
+
+    let mut encrypted = Vec::new();
+    let metadata = encryption
+        .file()
+        .encrypt_stream(BufReader::new(&archive[..]), &mut encrypted)
+        .await?;
+
+    Ok(Message::File {
+        name,
+        metadata: serde_json::to_value(metadata)?,
+        data: encrypted,
+    })
+}
+
+/// Longest file name kept when saving, in bytes, which common file systems accept
+pub const MAX_FILE_NAME_BYTES: usize = 255;
+
+/// Most numbered variants tried before giving up on finding a free file name
+const MAX_NAME_SUFFIX: u32 = 10_000;
+
+/// Makes a file name chosen by the sender of an attachment safe to save
+///
+/// Names with path separators are rejected, as are names left empty or made
+/// of dots only, so a name can never reach outside the directory it is saved
+/// in. Control characters and surrounding whitespace are removed, and long
+/// names are shortened to `MAX_FILE_NAME_BYTES`, keeping their extension.
+///
+/// # Arguments
+/// * `name` - The name as sent
+///
+/// # Returns
+/// * `Result<String>` - The name to save the file under, or an error if it is unusable
+pub fn sanitize_file_name(name: &str) -> Result<String> {
+    if name.contains(['/', '\\']) {
+        return Err(ChatError::InvalidPath(format!(
+            "File name '{}' contains a path separator",
+            name.escape_debug()
+        )));
+    }
+    let cleaned: String = name.chars().filter(|c| !c.is_control()).collect();
+    let cleaned = cleaned.trim();
+    if cleaned.trim_matches('.').is_empty() {
+        return Err(ChatError::InvalidPath(format!(
+            "'{}' is not a usable file name",
+            name.escape_debug()
+        )));
+    }
+    Ok(truncate_file_name(cleaned, MAX_FILE_NAME_BYTES))
+}
+
+/// Shortens a name to at most `max` bytes, cutting the stem rather than the extension.
+fn truncate_file_name(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+    let (stem, extension) = split_extension(name);
+    let extension = extension.filter(|extension| extension.len() < max / 2);
+    let budget = max - extension.map_or(0, |extension| extension.len() + 1);
+    let mut end = budget.min(stem.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    match extension {
+        Some(extension) => format!("{}.{}", stem[..end].trim_end(), extension),
+        None => stem[..end].trim_end().to_string(),
+    }
+}
+
+/// Splits a name into its stem and extension; a leading dot does not start an extension.
+fn split_extension(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name, None),
+    }
+}
+
+/// Returns the `n`-th alternative of a taken name, e.g. `report (1).pdf`.
+fn numbered_file_name(name: &str, n: u32) -> String {
+    let suffix = format!(" ({})", n);
+    let (stem, extension) = match split_extension(name) {
+        (stem, Some(extension)) if extension.len() < MAX_FILE_NAME_BYTES / 2 => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    };
+    let extension_len = extension.map_or(0, |extension| extension.len() + 1);
+    let stem = truncate_file_name(stem, MAX_FILE_NAME_BYTES - suffix.len() - extension_len);
+    match extension {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension),
+        None => format!("{}{}", stem, suffix),
+    }
+}
+
+/// Writes a new file into a directory without replacing an existing one
+///
+/// If the name is taken, ` (1)`, ` (2)` and so on is added before the
+/// extension until a free name is found. Files are created exclusively, so two
+/// files saved at the same time cannot end up under the same name either.
 ///
 /// # Arguments
-/// * `name` - Name of the file to save
+/// * `dir` - Directory to save into, created if missing
+/// * `name` - Sanitized name of the file
 /// * `data` - File contents to save
 ///
 /// # Returns
-/// * `Result<()>` - Success or an error if saving fails
-pub async fn save_file(name: &str, data: Vec<u8>) -> Result<()> {
-    let path = Path::new("files").join(name);
-    create_directory("files").await?;
-    fs::write(path, data).await?;
-    Ok(())
+/// * `Result<PathBuf>` - Where the file was saved
+pub async fn save_unique(dir: &Path, name: &str, data: &[u8]) -> Result<PathBuf> {
+    fs::create_dir_all(dir).await?;
+    for n in 0..=MAX_NAME_SUFFIX {
+        let path = match n {
+            0 => dir.join(name),
+            n => dir.join(numbered_file_name(name, n)),
+        };
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(mut file) => {
+                file.write_all(data).await?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(ChatError::Conflict(format!(
+        "No free name for '{}' in {}",
+        name,
+        dir.display()
+    )))
 }
 
-/// Saves an image to the images directory with a timestamp
+/// A received file being written to disk
 ///
-/// The image is converted to PNG format and saved with a timestamp in the filename
-/// to prevent overwriting existing images.
+/// The content is written to a hidden temporary file next to its destination,
+/// so large attachments never have to be held in memory, and is moved into
+/// place in one step once it is complete. A partial file that is dropped
+/// without being committed, e.g. because it failed its integrity check, is
+/// deleted, so an incomplete file never shows up under the real name.
+pub struct PartialFile {
+    dir: PathBuf,
+    temp: NamedTempFile,
+    file: File,
+}
+
+impl PartialFile {
+    /// Creates a temporary file in `dir`, which is created if missing.
+    pub async fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).await?;
+        let temp = tempfile::Builder::new()
+            .prefix(".")
+            .suffix(".part")
+            .tempfile_in(dir)?;
+        let file = File::from_std(temp.as_file().try_clone()?);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            temp,
+            file,
+        })
+    }
+
+    /// Returns the file to write the content to.
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Moves the complete file into its directory under a sanitized name
+    ///
+    /// Like [`save_unique`], a taken name is never replaced; the file gets a
+    /// numbered name instead.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the file, as sent
+    ///
+    /// # Returns
+    /// * `Result<PathBuf>` - Where the file was saved
+    pub async fn commit(self, name: &str) -> Result<PathBuf> {
+        let name = sanitize_file_name(name)?;
+        let (dir, mut temp) = self.finish().await?;
+        for n in 0..=MAX_NAME_SUFFIX {
+            let path = match n {
+                0 => dir.join(&name),
+                n => dir.join(numbered_file_name(&name, n)),
+            };
+            match temp.persist_noclobber(&path) {
+                Ok(_) => return Ok(path),
+                Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => temp = e.file,
+                Err(e) => return Err(e.error.into()),
+            }
+        }
+        Err(ChatError::Conflict(format!(
+            "No free name for '{}' in {}",
+            name,
+            dir.display()
+        )))
+    }
+
+    /// Moves the complete file to `path`, which must be in the same directory
+    ///
+    /// # Arguments
+    /// * `path` - Where to save the file
+    /// * `overwrite` - Whether an existing file at `path` may be replaced
+    ///
+    /// # Returns
+    /// * `Result<PathBuf>` - Where the file was saved
+    pub async fn commit_to(self, path: &Path, overwrite: bool) -> Result<PathBuf> {
+        let (_, temp) = self.finish().await?;
+        let persisted = if overwrite {
+            temp.persist(path)
+        } else {
+            temp.persist_noclobber(path)
+        };
+        match persisted {
+            Ok(_) => Ok(path.to_path_buf()),
+            Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => Err(
+                ChatError::Conflict(format!("{} already exists", path.display())),
+            ),
+            Err(e) => Err(e.error.into()),
+        }
+    }
+
+    /// Flushes the content to disk before the file is moved into place.
+    async fn finish(mut self) -> Result<(PathBuf, NamedTempFile)> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        Ok((self.dir, self.temp))
+    }
+}
+
+/// Directory received files are saved into unless configured otherwise
+pub const DEFAULT_FILES_DIR: &str = "files";
+
+/// Directory received images are saved into unless configured otherwise
+pub const DEFAULT_IMAGES_DIR: &str = "images";
+
+/// Placeholders a [`DirTemplate`] may contain
+const DIR_PLACEHOLDERS: [&str; 3] = ["room", "sender", "date"];
+
+/// Value of a placeholder that is not known for an attachment
+const UNKNOWN_PLACEHOLDER: &str = "unknown";
+
+/// A directory path whose `{room}`, `{sender}` and `{date}` placeholders are
+/// filled in per received attachment, e.g. `downloads/{room}/{date}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirTemplate(String);
+
+impl FromStr for DirTemplate {
+    type Err = ChatError;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            let placeholder = rest[start..]
+                .strip_prefix('{')
+                .and_then(|tail| tail.split_once('}'))
+                .map(|(placeholder, _)| placeholder)
+                .filter(|placeholder| DIR_PLACEHOLDERS.contains(placeholder))
+                .ok_or_else(|| {
+                    ChatError::InvalidPath(format!(
+                        "'{}' may only contain the placeholders {{room}}, {{sender}} and {{date}}",
+                        template
+                    ))
+                })?;
+            rest = &rest[start + placeholder.len() + 2..];
+        }
+        if template.trim().is_empty() {
+            return Err(ChatError::InvalidPath(
+                "A download directory cannot be empty".to_string(),
+            ));
+        }
+        Ok(Self(template.to_string()))
+    }
+}
+
+impl DirTemplate {
+    /// Fills in the placeholders for an attachment.
+    ///
+    /// Values are sanitized like file names, so a room or sender name cannot
+    /// add path components; unknown or unusable values become `unknown`.
+    pub fn expand(&self, origin: &Origin) -> PathBuf {
+        let value = |value: Option<&str>| {
+            value
+                .and_then(|value| sanitize_file_name(value).ok())
+                .unwrap_or_else(|| UNKNOWN_PLACEHOLDER.to_string())
+        };
+        let path = self
+            .0
+            .replace("{room}", &value(origin.room.as_deref()))
+            .replace("{sender}", &value(origin.sender.as_deref()))
+            .replace("{date}", &origin.date.format("%Y-%m-%d").to_string());
+        PathBuf::from(path)
+    }
+}
+
+/// Where a received attachment comes from, filling in [`DirTemplate`] placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// Slug of the workspace the attachment was received in
+    pub room: Option<String>,
+    /// Username of the sender
+    pub sender: Option<String>,
+    /// Day the attachment was received on, in local time
+    pub date: NaiveDate,
+}
+
+impl Origin {
+    /// An attachment received today.
+    pub fn now(room: Option<String>, sender: Option<String>) -> Self {
+        Self {
+            room,
+            sender,
+            date: Local::now().date_naive(),
+        }
+    }
+}
+
+/// Directories received files and images are saved into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadDirs {
+    pub files: DirTemplate,
+    pub images: DirTemplate,
+}
+
+impl Default for DownloadDirs {
+    fn default() -> Self {
+        Self {
+            files: DirTemplate(DEFAULT_FILES_DIR.to_string()),
+            images: DirTemplate(DEFAULT_IMAGES_DIR.to_string()),
+        }
+    }
+}
+
+/// Saves a file to a directory
+///
+/// The sender's name is sanitized with [`sanitize_file_name`], and a file of
+/// the same name is never overwritten; the new one gets a numbered name instead.
+///
+/// # Arguments
+/// * `dir` - Directory to save into, created if missing
+/// * `name` - Name of the file to save, as sent
+/// * `data` - File contents to save
+///
+/// # Returns
+/// * `Result<PathBuf>` - Where the file was saved, or an error if saving fails
+pub async fn save_file(dir: &Path, name: &str, data: Vec<u8>) -> Result<PathBuf> {
+    let name = sanitize_file_name(name)?;
+    save_unique(dir, &name, &data).await
+}
+
+/// Format received images are saved in
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageSaveFormat {
+    /// The bytes as received, with the extension of their format
+    #[default]
+    Original,
+    /// Converted to PNG, which flattens animations to their first frame
+    Png,
+}
+
+/// Compression of received images converted to PNG
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    /// Larger files, written quickly
+    Fast,
+    /// A balance of size and speed
+    #[default]
+    Default,
+    /// The smallest files, written slowly
+    Best,
+}
+
+impl From<PngCompression> for CompressionType {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
+
+/// How received images are saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImageSaveOptions {
+    pub format: ImageSaveFormat,
+    /// Compression used when `format` is `Png`
+    pub png_compression: PngCompression,
+}
+
+/// Saves an image to a directory with a timestamp
+///
+/// The image keeps the format it was sent in, detected from its bytes, so
+/// JPEGs stay small and animated GIFs keep moving; it is only converted to
+/// PNG if `options` ask for it. The file is named after the sender's name with
+/// a timestamp and the extension of the saved format. The name is sanitized
+/// like in [`save_file`], and an image saved in the same second gets a numbered
+/// name instead of overwriting it.
 ///
 /// # Arguments
+/// * `dir` - Directory to save into, created if missing
 /// * `name` - Original name of the image
 /// * `data` - Image data to save
+/// * `options` - The format to save in
 ///
 /// # Returns
-/// * `Result<()>` - Success or an error if saving fails
-pub async fn save_image(name: &str, data: Vec<u8>) -> Result<()> {
-    let img = image::load_from_memory(&data)
+/// * `Result<PathBuf>` - Where the image was saved, or an error if saving fails
+pub async fn save_image(
+    dir: &Path,
+    name: &str,
+    data: Vec<u8>,
+    options: ImageSaveOptions,
+) -> Result<PathBuf> {
+    let name = sanitize_file_name(name)?;
+    let format = image::guess_format(&data)
         .map_err(|e| ChatError::ImageProcessingError(format!("Failed to process image: {}", e)))?;
 
-    let name_without_extension = name.split('.').next().unwrap_or(name);
+    let (data, extension) = match options.format {
+        ImageSaveFormat::Original => {
+            let extension = format.extensions_str().first().copied().unwrap_or("img");
+            (data, extension)
+        }
+        ImageSaveFormat::Png => {
+            let compression = options.png_compression;
+            let png =
+                tokio::task::spawn_blocking(move || convert_to_png(&data, format, compression))
+                    .await
+                    .map_err(|e| ChatError::UnknownError(e.to_string()))??;
+            (png, "png")
+        }
+    };
 
+    let name_without_extension = split_extension(&name).0;
     let timestamp = chrono::Utc::now().timestamp();
-    let path = Path::new("images").join(format!("{}_{}.png", name_without_extension, timestamp));
-
-    create_directory("images").await?;
+    let file_name = truncate_file_name(
+        &format!("{}_{}.{}", name_without_extension, timestamp, extension),
+        MAX_FILE_NAME_BYTES,
+    );
+    save_unique(dir, &file_name, &data).await
+}
 
-    tokio::task::spawn_blocking(move || {
-        img.save_with_format(&path, image::ImageFormat::Png)
-            .map_err(|e| ChatError::ImageProcessingError(e.to_string()))
-    })
-    .await
-    .unwrap()?;
+/// Decodes an image and encodes it as PNG with the given compression.
+fn convert_to_png(
+    data: &[u8],
+    format: ImageFormat,
+    compression: PngCompression,
+) -> Result<Vec<u8>> {
+    let img = image::load_from_memory_with_format(data, format)
+        .map_err(|e| ChatError::ImageProcessingError(format!("Failed to process image: {}", e)))?;
+    // PNG has no floating point samples
+    let img = match img {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            DynamicImage::ImageRgba16(img.to_rgba16())
+        }
+        img => img,
+    };
 
-    Ok(())
+    let mut png = Vec::new();
+    PngEncoder::new_with_quality(&mut png, compression.into(), PngFilterType::Adaptive)
+        .write_image(img.as_bytes(), img.width(), img.height(), img.color())
+        .map_err(|e| ChatError::ImageProcessingError(format!("Failed to encode image: {}", e)))?;
+    Ok(png)
 }
 
 /// Creates a directory if it doesn't exist
@@ -201,10 +912,7 @@ pub async fn create_directory(path: &str) -> Result<()> {
 /// # Returns
 /// * `Message` - An error message containing the error code and description
 pub fn create_error_message(error: &ChatError) -> Message {
-    Message::Error {
-        code: error.to_error_code(),
-        message: error.to_string(),
-    }
+    Message::error(error.to_error_code(), error.to_string())
 }
 
 #[cfg(test)]
@@ -247,12 +955,119 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn encode_png(width: u32, height: u32) -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        }));
+        encode_image(&img, ImageFormat::Png).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_downscale_image_resizes_large_image() {
+        let options = ImageDownscale {
+            max_dimension: 300,
+            max_bytes: usize::MAX,
+        };
+
+        let data = downscale_image(encode_png(1200, 600), options)
+            .await
+            .unwrap();
+        let img = image::load_from_memory(&data).unwrap();
+
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Png);
+        assert_eq!(img.dimensions(), (300, 150));
+    }
+
+    #[tokio::test]
+    async fn test_downscale_image_keeps_small_image() {
+        let original = encode_png(64, 32);
+
+        let data = downscale_image(original.clone(), ImageDownscale::default())
+            .await
+            .unwrap();
+
+        assert_eq!(data, original);
+    }
+
+    /// Builds a JPEG with an EXIF segment holding the given orientation
+    fn jpeg_with_orientation(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, _| {
+            image::Rgb([(x * 255 / width) as u8, 0, 0])
+        }));
+        let jpeg = encode_image(&img, ImageFormat::Jpeg).unwrap();
+
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&ORIENTATION_TAG.to_le_bytes());
+        tiff.extend_from_slice(&[3, 0, 1, 0, 0, 0]);
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(&tiff);
+
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&segment);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    #[tokio::test]
+    async fn test_strip_image_metadata_removes_exif() {
+        let data = jpeg_with_orientation(40, 20, 6);
+        assert_eq!(jpeg_exif(&data).and_then(tiff_orientation), Some(6));
+
+        let stripped = strip_image_metadata(data).await.unwrap();
+
+        assert!(jpeg_exif(&stripped).is_none());
+        // The orientation is applied to the pixels instead
+        let img = image::load_from_memory(&stripped).unwrap();
+        assert_eq!(img.dimensions(), (20, 40));
+    }
+
+    #[tokio::test]
+    async fn test_strip_image_metadata_rejects_broken_image() {
+        let mut data = encode_png(16, 16);
+        data.truncate(40);
+        assert!(strip_image_metadata(data).await.is_err());
+
+        // Formats without a re-encoder are passed through
+        let gif = b"GIF89a not really a gif".to_vec();
+        assert_eq!(strip_image_metadata(gif.clone()).await.unwrap(), gif);
+    }
+
+    #[tokio::test]
+    async fn test_process_image_command_downscales_before_sending() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("photo.png");
+        fs::write(&file_path, encode_png(800, 800)).await.unwrap();
+
+        let options = ImageDownscale {
+            max_dimension: 200,
+            max_bytes: usize::MAX,
+        };
+        let result = process_image_command(file_path.to_str().unwrap(), Some(options), None)
+            .await
+            .unwrap();
+
+        let Message::Image { name, data, .. } = result else {
+            panic!("Expected Image message");
+        };
+        assert_eq!(name, "photo.png");
+        assert_eq!(
+            image::load_from_memory(&data).unwrap().dimensions(),
+            (200, 200)
+        );
+    }
+
     #[tokio::test]
     async fn test_create_error_message() {
         let error = ChatError::NotFound("test.txt".to_string());
         let message = create_error_message(&error);
 
-        if let Message::Error { code, message: msg } = message {
+        if let Message::Error {
+            code, message: msg, ..
+        } = message
+        {
             assert_eq!(code, crate::error::ErrorCode::FileNotFound);
             assert_eq!(msg, "File not found: test.txt");
         } else {
@@ -260,6 +1075,167 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("report.pdf").unwrap(), "report.pdf");
+        assert_eq!(sanitize_file_name(" notes\n.txt ").unwrap(), "notes.txt");
+        assert_eq!(sanitize_file_name(".bashrc").unwrap(), ".bashrc");
+        for name in [
+            "../secret",
+            "docs/report.pdf",
+            "..\\boot.ini",
+            "..",
+            " . ",
+            "",
+        ] {
+            assert!(
+                matches!(sanitize_file_name(name), Err(ChatError::InvalidPath(_))),
+                "{:?} was accepted",
+                name
+            );
+        }
+
+        let long = format!("{}.pdf", "ř".repeat(200));
+        let sanitized = sanitize_file_name(&long).unwrap();
+        assert!(sanitized.len() <= MAX_FILE_NAME_BYTES);
+        assert!(sanitized.ends_with("ř.pdf"));
+    }
+
+    #[test]
+    fn test_numbered_file_name() {
+        assert_eq!(numbered_file_name("report.pdf", 1), "report (1).pdf");
+        assert_eq!(numbered_file_name("README", 2), "README (2)");
+        assert_eq!(numbered_file_name(".bashrc", 3), ".bashrc (3)");
+        let long = format!("{}.pdf", "a".repeat(MAX_FILE_NAME_BYTES - 4));
+        let numbered = numbered_file_name(&long, 12);
+        assert_eq!(numbered.len(), MAX_FILE_NAME_BYTES);
+        assert!(numbered.ends_with("a (12).pdf"));
+    }
+
+    #[tokio::test]
+    async fn test_save_unique_keeps_existing_files() {
+        let dir = tempdir().unwrap();
+        let first = save_unique(dir.path(), "report.pdf", b"first")
+            .await
+            .unwrap();
+        let second = save_unique(dir.path(), "report.pdf", b"second")
+            .await
+            .unwrap();
+
+        assert_eq!(first, dir.path().join("report.pdf"));
+        assert_eq!(second, dir.path().join("report (1).pdf"));
+        assert_eq!(fs::read(&first).await.unwrap(), b"first");
+        assert_eq!(fs::read(&second).await.unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_dir_template() {
+        let origin = Origin {
+            room: Some("design".to_string()),
+            sender: Some("../alice".to_string()),
+            date: NaiveDate::from_ymd_opt(2025, 4, 1).unwrap(),
+        };
+        let template: DirTemplate = "downloads/{room}/{date}/{sender}".parse().unwrap();
+        assert_eq!(
+            template.expand(&origin),
+            PathBuf::from("downloads/design/2025-04-01/unknown")
+        );
+        assert_eq!(
+            DownloadDirs::default().files.expand(&origin),
+            PathBuf::from(DEFAULT_FILES_DIR)
+        );
+
+        for template in ["downloads/{user}", "downloads/{room", "downloads}", " "] {
+            assert!(template.parse::<DirTemplate>().is_err(), "{}", template);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_image_keeps_original_format() {
+        let dir = tempdir().unwrap();
+        let img = DynamicImage::ImageRgb8(image::RgbImage::new(32, 16));
+        let jpeg = encode_image(&img, ImageFormat::Jpeg).unwrap();
+
+        let saved = save_image(
+            dir.path(),
+            "cat.png",
+            jpeg.clone(),
+            ImageSaveOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.extension().unwrap(), "jpg");
+        assert!(saved
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("cat_"));
+        assert_eq!(fs::read(&saved).await.unwrap(), jpeg);
+
+        let options = ImageSaveOptions {
+            format: ImageSaveFormat::Png,
+            png_compression: PngCompression::Best,
+        };
+        let saved = save_image(dir.path(), "cat.jpg", jpeg, options)
+            .await
+            .unwrap();
+        assert_eq!(saved.extension().unwrap(), "png");
+        let data = fs::read(&saved).await.unwrap();
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Png);
+        assert_eq!(
+            image::load_from_memory(&data).unwrap().dimensions(),
+            (32, 16)
+        );
+
+        let not_an_image = save_image(dir.path(), "cat.png", b"meow".to_vec(), options).await;
+        assert!(matches!(
+            not_an_image,
+            Err(ChatError::ImageProcessingError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_partial_file_appears_only_when_committed() {
+        let dir = tempdir().unwrap();
+        let listing = || {
+            let mut names: Vec<String> = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let mut partial = PartialFile::create(dir.path()).await.unwrap();
+        partial.file().write_all(b"half").await.unwrap();
+        assert!(listing()[0].ends_with(".part"));
+        drop(partial);
+        assert!(listing().is_empty());
+
+        save_unique(dir.path(), "report.pdf", b"first")
+            .await
+            .unwrap();
+        let mut partial = PartialFile::create(dir.path()).await.unwrap();
+        partial.file().write_all(b"second").await.unwrap();
+        let saved = partial.commit("report.pdf").await.unwrap();
+        assert_eq!(saved, dir.path().join("report (1).pdf"));
+        assert_eq!(fs::read(&saved).await.unwrap(), b"second");
+        assert_eq!(listing(), ["report (1).pdf", "report.pdf"]);
+
+        let target = dir.path().join("report.pdf");
+        let partial = PartialFile::create(dir.path()).await.unwrap();
+        assert!(matches!(
+            partial.commit_to(&target, false).await,
+            Err(ChatError::Conflict(_))
+        ));
+        let mut partial = PartialFile::create(dir.path()).await.unwrap();
+        partial.file().write_all(b"third").await.unwrap();
+        partial.commit_to(&target, true).await.unwrap();
+        assert_eq!(fs::read(&target).await.unwrap(), b"third");
+        assert_eq!(listing().len(), 2);
+    }
+
     #[tokio::test]
     async fn test_create_directory() {
         let dir = tempdir().unwrap();
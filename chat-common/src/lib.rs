@@ -1,14 +1,40 @@
 use chrono::{DateTime, Utc};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::str::FromStr;
 
 pub const DEFAULT_HOST: &str = "127.0.0.1";
 pub const DEFAULT_PORT: u16 = 8080;
+/// File the client keeps its state in between runs
+pub const DEFAULT_STATE_FILE: &str = "chat-client-state.json";
+/// Longest lifetime an ephemeral message may be given (24 hours)
+pub const MAX_EPHEMERAL_TTL_SECS: u64 = 24 * 60 * 60;
+/// Most options a poll may have; it needs at least two
+pub const MAX_POLL_OPTIONS: usize = 10;
+/// Longest custom status text, in characters
+pub const MAX_STATUS_TEXT_CHARS: usize = 100;
+/// Longest reason a report may give, in characters
+pub const MAX_REPORT_REASON_CHARS: usize = 500;
+/// Version of the chat protocol, exchanged in `Hello` when a client connects
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Capability of clients that accept a `History` of the workspace after logging in
+pub const HISTORY_CAPABILITY: &str = "history";
+/// Capability of clients that accept a `ChatMessage` envelope naming the sender
+pub const SENDER_CAPABILITY: &str = "sender";
+/// Optional protocol features this build supports, advertised in `Hello`
+pub const CAPABILITIES: &[&str] = &[HISTORY_CAPABILITY, SENDER_CAPABILITY];
 
+pub mod archive;
 pub mod async_message_stream;
+pub mod codec;
 pub mod encryption;
 pub mod error;
 pub mod file_ops;
+pub mod recording;
+pub mod throttle;
 
 // Re-export commonly used items
 pub use async_message_stream::AsyncMessageStream;
@@ -31,16 +57,352 @@ pub enum Message {
     Error {
         code: ErrorCode,
         message: String,
+        /// `client_msg_id` of the request that caused the error, if known
+        #[serde(default)]
+        in_reply_to: Option<String>,
+        /// Machine-readable context, such as the offending field or value
+        #[serde(default)]
+        details: BTreeMap<String, String>,
     },
     Auth {
         username: String,
         password: String,
     },
+    /// Creates an account and logs the connection in as it; answered with an
+    /// `AuthResponse` like `Auth`, or an `Error` if the account is rejected
+    Register {
+        username: String,
+        email: String,
+        password: String,
+        /// Needed if the server requires invites
+        #[serde(default)]
+        invite_code: Option<String>,
+    },
     AuthResponse {
         success: bool,
         token: Option<String>,
         message: String,
     },
+    /// First message of a TCP connection, sent by the client and answered by
+    /// the server with its own `Hello`, or an `Error` if it does not speak the
+    /// client's protocol version
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+    },
+    /// Liveness probe; the receiver must answer with a `Pong` carrying the same nonce
+    Ping {
+        nonce: u64,
+    },
+    /// Answer to a `Ping`
+    Pong {
+        nonce: u64,
+    },
+    /// Moves an authenticated connection into another workspace it belongs to
+    SwitchWorkspace {
+        slug: String,
+    },
+    /// Changes the password of the authenticated user
+    ChangePassword {
+        current_password: String,
+        new_password: String,
+    },
+    /// Live copy of an entry added to the user's notification inbox
+    Notification {
+        id: i32,
+        kind: String,
+        content: String,
+    },
+    /// Encrypted text message sent with an explicit priority; plain `Text` is `Normal`
+    PriorityText {
+        priority: Priority,
+        content: String,
+    },
+    /// Encrypted text message that is not stored and disappears after `ttl_secs`;
+    /// the server sets `expires_at` before delivering it
+    Ephemeral {
+        content: String,
+        ttl_secs: u64,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    /// Envelope for a client request whose errors should refer back to it
+    Request {
+        client_msg_id: String,
+        message: Box<Message>,
+    },
+    /// Encrypted text message quoting an earlier message; the sender only sets the
+    /// quote's `id` and the server fills in its author and excerpt
+    Reply {
+        content: String,
+        quote: QuotedMessage,
+    },
+    /// Creates a poll in the sender's workspace; the server answers with its first `PollTally`.
+    /// Polls are not encrypted, so the server can count the votes
+    Poll {
+        question: String,
+        options: Vec<String>,
+    },
+    /// Votes for the option at index `option` of a poll; every user votes once per poll
+    Vote {
+        poll_id: i32,
+        option: u32,
+    },
+    /// Current votes of a poll per option, sent to its workspace after it is created
+    /// and after every vote
+    PollTally {
+        poll_id: i32,
+        question: String,
+        options: Vec<String>,
+        votes: Vec<u32>,
+    },
+    /// Sets the sender's status, optionally with custom text such as "🌴 on vacation"
+    SetStatus {
+        availability: Availability,
+        #[serde(default)]
+        text: Option<String>,
+    },
+    /// A user's status changed; sent to the members of the user's workspaces
+    StatusChanged {
+        username: String,
+        availability: Availability,
+        #[serde(default)]
+        text: Option<String>,
+    },
+    /// Key of the workspace the connection is in, wrapped with the session token;
+    /// content encrypted with it carries `key_id`
+    RoomKey {
+        workspace: String,
+        key_id: String,
+        wrapped_key: String,
+        nonce: String,
+        /// Sequence number of the workspace's latest stored message
+        #[serde(default)]
+        last_seq: u64,
+    },
+    /// Envelope for a stored message, or the acknowledgment of one, carrying its
+    /// sequence number in the workspace; the numbers have no gaps, so a client
+    /// can tell which messages it missed; `sent_at` is when the server stored it
+    Sequenced {
+        seq: u64,
+        sent_at: Option<DateTime<Utc>>,
+        message: Box<Message>,
+    },
+    /// Reports a message to the moderators of its workspace
+    Report {
+        message_id: i32,
+        reason: String,
+    },
+    /// Encrypted action of the sender in the third person, shown as `* alice waves`;
+    /// the server sets `username` before delivering it
+    Action {
+        content: String,
+        #[serde(default)]
+        username: Option<String>,
+    },
+    /// A message was stored in another of the user's workspaces; sent to members
+    /// whose connection is elsewhere, so they can count unread messages
+    Activity {
+        workspace: String,
+    },
+    /// Asks for the attachment of a stored message in the sender's workspace;
+    /// the server answers with an `Attachment`
+    Download {
+        message_id: i32,
+    },
+    /// Attachment of a stored message, encrypted with the key of its workspace
+    Attachment {
+        message_id: i32,
+        name: String,
+        metadata: serde_json::Value,
+        data: Vec<u8>,
+    },
+    /// Replaces the text of one of the sender's stored messages; `new_content` is
+    /// encrypted like `Text`. The server delivers the edit to the message's
+    /// workspace, the sender included
+    Edit {
+        message_id: i32,
+        new_content: String,
+    },
+    /// Deletes one of the sender's stored messages. The server delivers the
+    /// deletion to the message's workspace, the sender included
+    Delete {
+        message_id: i32,
+    },
+    /// Envelope naming the sender of a message delivered to the workspace; the
+    /// server fills it in from the authenticated user. Only clients that
+    /// advertised [`SENDER_CAPABILITY`] get it, others get the `body` alone
+    ChatMessage {
+        sender: String,
+        sent_at: DateTime<Utc>,
+        body: Box<Message>,
+    },
+    /// Latest stored messages of the workspace, oldest first; sent after
+    /// logging in to clients that advertised [`HISTORY_CAPABILITY`], so they
+    /// have the context of the conversation they join
+    History(Vec<HistoricalMessage>),
+}
+
+/// Delivery priority of a message, from lowest to highest
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    Urgent,
+}
+
+/// Whether a user is around, shown to the members of their workspaces
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    #[default]
+    Available,
+    Away,
+    Busy,
+}
+
+impl Availability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Availability::Available => "available",
+            Availability::Away => "away",
+            Availability::Busy => "busy",
+        }
+    }
+}
+
+impl Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Availability {
+    type Err = ChatError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "available" => Ok(Availability::Available),
+            "away" => Ok(Availability::Away),
+            "busy" => Ok(Availability::Busy),
+            _ => Err(ChatError::InvalidInput(format!(
+                "Unknown availability '{}', expected available, away or busy",
+                value
+            ))),
+        }
+    }
+}
+
+/// What a message replayed in a `History` is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoricalKind {
+    Text,
+    Action,
+    File,
+    Image,
+}
+
+/// A stored message replayed in a `History`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoricalMessage {
+    /// ID of the stored message, for replies and downloads
+    pub id: i32,
+    /// Sequence number of the message in its workspace
+    pub seq: u64,
+    /// When the server stored the message
+    pub sent_at: DateTime<Utc>,
+    /// Username of the sender
+    pub sender: String,
+    pub kind: HistoricalKind,
+    /// Encrypted text of a text message or action, like the content of `Text`
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Name of an attachment; its content is fetched with `Download`
+    #[serde(default)]
+    pub file_name: Option<String>,
+}
+
+/// An earlier message quoted by a reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuotedMessage {
+    /// ID of the stored message
+    pub id: i32,
+    /// Username of its sender
+    #[serde(default)]
+    pub author: String,
+    /// Beginning of its text or its file name, encrypted like the reply's content
+    #[serde(default)]
+    pub excerpt: String,
+}
+
+impl QuotedMessage {
+    /// Creates a quote of the stored message `id` for the server to fill in.
+    pub fn new(id: i32) -> Self {
+        Self {
+            id,
+            author: String::new(),
+            excerpt: String::new(),
+        }
+    }
+}
+
+impl Message {
+    /// Creates an error message that does not refer to a specific request.
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Message::Error {
+            code,
+            message: message.into(),
+            in_reply_to: None,
+            details: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the delivery priority of the message, looking into `Sequenced`
+    /// and `ChatMessage` envelopes.
+    pub fn priority(&self) -> Priority {
+        match self {
+            Message::PriorityText { priority, .. } => *priority,
+            Message::Sequenced { message, .. } => message.priority(),
+            Message::ChatMessage { body, .. } => body.priority(),
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// How the client prints received messages and events
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable log lines
+    #[default]
+    Text,
+    /// One JSON object per line on stdout, with logs moved to stderr
+    Json,
+}
+
+/// How the client shows the timestamps of received messages
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// The local clock time, with the date if it is not today
+    #[default]
+    Absolute,
+    /// How long ago, e.g. `5m ago`
+    Relative,
+    /// No timestamps
+    Off,
+}
+
+/// Clock of absolute timestamps
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Clock {
+    /// `14:02`
+    #[default]
+    #[value(name = "24h")]
+    H24,
+    /// `2:02 PM`
+    #[value(name = "12h")]
+    H12,
 }
 
 #[derive(Parser)]
@@ -49,12 +411,105 @@ pub struct Args {
     pub host: String,
     #[arg(long, default_value_t = DEFAULT_PORT)]
     pub port: u16,
+    /// Downscale images sent with `.image` that exceed the size limits below
+    #[arg(long)]
+    pub downscale_images: bool,
+    /// Longest edge, in pixels, of downscaled images
+    #[arg(long, default_value_t = file_ops::DEFAULT_MAX_IMAGE_DIMENSION)]
+    pub max_image_dimension: u32,
+    /// Size, in bytes, above which images are downscaled
+    #[arg(long, default_value_t = file_ops::DEFAULT_MAX_IMAGE_BYTES)]
+    pub max_image_bytes: usize,
+    /// Show incoming messages as plain text instead of rendering Markdown
+    #[arg(long)]
+    pub plain_text: bool,
+    /// Format of received messages and events on stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Upload rate limit for outgoing messages in KiB/s
+    #[arg(long)]
+    pub upload_limit: Option<u64>,
+    /// Download rate limit for incoming messages in KiB/s
+    #[arg(long)]
+    pub download_limit: Option<u64>,
+    /// Record every sent and received frame to this file (JSON Lines)
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// Keep encrypted payloads in the recording instead of redacting them
+    #[arg(long, requires = "record")]
+    pub record_payloads: bool,
+    /// Replay a recording instead of starting an interactive session
+    #[arg(long, conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+    /// Send the recorded frames to the server instead of feeding the received
+    /// ones into the message handler
+    #[arg(long, requires = "replay")]
+    pub replay_to_server: bool,
+    /// Replay with the recorded timing instead of as fast as possible
+    #[arg(long, requires = "replay")]
+    pub replay_realtime: bool,
+    /// Password sent in place of the redacted one when replaying a login to the server
+    #[arg(long, requires = "replay_to_server")]
+    pub replay_password: Option<String>,
+    /// File the client keeps drafts in between runs
+    #[arg(long, default_value = DEFAULT_STATE_FILE)]
+    pub state_file: PathBuf,
+    /// Directory received files are saved into; `{room}`, `{sender}` and
+    /// `{date}` are filled in per file, e.g. `downloads/{room}/{date}`
+    #[arg(long, default_value = file_ops::DEFAULT_FILES_DIR)]
+    pub files_dir: file_ops::DirTemplate,
+    /// Directory received images are saved into, with the same placeholders
+    #[arg(long, default_value = file_ops::DEFAULT_IMAGES_DIR)]
+    pub images_dir: file_ops::DirTemplate,
+    /// Format received images are saved in
+    #[arg(long, value_enum, default_value_t = file_ops::ImageSaveFormat::Original)]
+    pub save_images_as: file_ops::ImageSaveFormat,
+    /// Compression of received images saved as PNG
+    #[arg(long, value_enum, default_value_t = file_ops::PngCompression::Default)]
+    pub png_compression: file_ops::PngCompression,
+    /// Run a command on an event, with the event as JSON on stdin, e.g.
+    /// `message:urgent=notify-send chat`; may be given more than once
+    #[arg(long = "hook", value_name = "EVENT[:PATTERN]=COMMAND")]
+    pub hooks: Vec<String>,
+    /// Do not raise desktop notifications for messages with highlighted keywords
+    #[arg(long)]
+    pub no_desktop_notifications: bool,
+    /// How the timestamps of received messages are shown
+    #[arg(long, value_enum, default_value_t = TimeStyle::Absolute)]
+    pub timestamps: TimeStyle,
+    /// Clock of absolute timestamps
+    #[arg(long, value_enum, default_value_t = Clock::H24)]
+    pub clock: Clock,
 }
 
 impl Args {
     pub fn addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    /// Returns the directories received attachments are saved into
+    pub fn download_dirs(&self) -> file_ops::DownloadDirs {
+        file_ops::DownloadDirs {
+            files: self.files_dir.clone(),
+            images: self.images_dir.clone(),
+        }
+    }
+
+    /// Returns how received images are saved
+    pub fn image_save_options(&self) -> file_ops::ImageSaveOptions {
+        file_ops::ImageSaveOptions {
+            format: self.save_images_as,
+            png_compression: self.png_compression,
+        }
+    }
+
+    /// Returns the image downscale thresholds, or `None` if downscaling is off
+    pub fn image_downscale(&self) -> Option<file_ops::ImageDownscale> {
+        self.downscale_images.then_some(file_ops::ImageDownscale {
+            max_dimension: self.max_image_dimension,
+            max_bytes: self.max_image_bytes,
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -88,4 +543,19 @@ mod tests {
 
         server.await.unwrap();
     }
+
+    #[test]
+    fn test_availability_round_trip() {
+        for availability in [
+            Availability::Available,
+            Availability::Away,
+            Availability::Busy,
+        ] {
+            assert_eq!(
+                availability.as_str().parse::<Availability>().unwrap(),
+                availability
+            );
+        }
+        assert!("offline".parse::<Availability>().is_err());
+    }
 }
@@ -0,0 +1,580 @@
+//! Recording of protocol frames for debugging.
+//!
+//! A recording is a JSON Lines file with one [`RecordedFrame`] per message sent
+//! or received, in the order they crossed the connection. Encrypted payloads
+//! are redacted unless the recording is made with payloads kept; passwords are
+//! always redacted. Recordings can be replayed through [`ReplayStream`], which
+//! yields the received frames as if they came from the server.
+
+use crate::async_message_stream::AsyncMessageStream;
+use crate::{HistoricalMessage, Message, QuotedMessage, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Placeholder for redacted passwords
+pub const REDACTED: &str = "<redacted>";
+
+/// Which way a frame crossed the connection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A single recorded message.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    /// When the frame was recorded
+    pub at: DateTime<Utc>,
+    /// Milliseconds since the recording started
+    pub offset_ms: u64,
+    pub direction: Direction,
+    /// Whether payloads or credentials were removed from the message
+    pub redacted: bool,
+    pub message: Message,
+}
+
+/// Removes encrypted payloads and passwords from a message.
+///
+/// Text content becomes empty, attachment data is dropped while the name and
+/// metadata are kept, wrapped room keys are dropped while their ID is kept, and
+/// passwords are replaced with [`REDACTED`]. Returns
+/// the message and whether anything was removed.
+pub fn redact(message: &Message, keep_payloads: bool) -> (Message, bool) {
+    match message {
+        Message::Auth { username, .. } => (
+            Message::Auth {
+                username: username.clone(),
+                password: REDACTED.to_string(),
+            },
+            true,
+        ),
+        Message::Register {
+            username,
+            email,
+            invite_code,
+            ..
+        } => (
+            Message::Register {
+                username: username.clone(),
+                email: email.clone(),
+                password: REDACTED.to_string(),
+                invite_code: invite_code.clone(),
+            },
+            true,
+        ),
+        Message::ChangePassword { .. } => (
+            Message::ChangePassword {
+                current_password: REDACTED.to_string(),
+                new_password: REDACTED.to_string(),
+            },
+            true,
+        ),
+        Message::Request {
+            client_msg_id,
+            message,
+        } => {
+            let (message, redacted) = redact(message, keep_payloads);
+            (
+                Message::Request {
+                    client_msg_id: client_msg_id.clone(),
+                    message: Box::new(message),
+                },
+                redacted,
+            )
+        }
+        Message::Sequenced {
+            seq,
+            sent_at,
+            message,
+        } => {
+            let (message, redacted) = redact(message, keep_payloads);
+            (
+                Message::Sequenced {
+                    seq: *seq,
+                    sent_at: *sent_at,
+                    message: Box::new(message),
+                },
+                redacted,
+            )
+        }
+        Message::ChatMessage {
+            sender,
+            sent_at,
+            body,
+        } => {
+            let (body, redacted) = redact(body, keep_payloads);
+            (
+                Message::ChatMessage {
+                    sender: sender.clone(),
+                    sent_at: *sent_at,
+                    body: Box::new(body),
+                },
+                redacted,
+            )
+        }
+        message if keep_payloads => (message.clone(), false),
+        Message::RoomKey {
+            workspace,
+            key_id,
+            last_seq,
+            ..
+        } => (
+            Message::RoomKey {
+                workspace: workspace.clone(),
+                key_id: key_id.clone(),
+                wrapped_key: String::new(),
+                nonce: String::new(),
+                last_seq: *last_seq,
+            },
+            true,
+        ),
+        Message::Text(_) => (Message::Text(String::new()), true),
+        Message::PriorityText { priority, .. } => (
+            Message::PriorityText {
+                priority: *priority,
+                content: String::new(),
+            },
+            true,
+        ),
+        Message::Reply { quote, .. } => (
+            Message::Reply {
+                content: String::new(),
+                quote: QuotedMessage {
+                    excerpt: String::new(),
+                    ..quote.clone()
+                },
+            },
+            true,
+        ),
+        Message::Action { username, .. } => (
+            Message::Action {
+                content: String::new(),
+                username: username.clone(),
+            },
+            true,
+        ),
+        Message::Ephemeral {
+            ttl_secs,
+            expires_at,
+            ..
+        } => (
+            Message::Ephemeral {
+                content: String::new(),
+                ttl_secs: *ttl_secs,
+                expires_at: *expires_at,
+            },
+            true,
+        ),
+        Message::File { name, metadata, .. } => (
+            Message::File {
+                name: name.clone(),
+                metadata: metadata.clone(),
+                data: Vec::new(),
+            },
+            true,
+        ),
+        Message::Image { name, metadata, .. } => (
+            Message::Image {
+                name: name.clone(),
+                metadata: metadata.clone(),
+                data: Vec::new(),
+            },
+            true,
+        ),
+        Message::Attachment {
+            message_id,
+            name,
+            metadata,
+            ..
+        } => (
+            Message::Attachment {
+                message_id: *message_id,
+                name: name.clone(),
+                metadata: metadata.clone(),
+                data: Vec::new(),
+            },
+            true,
+        ),
+        Message::Edit { message_id, .. } => (
+            Message::Edit {
+                message_id: *message_id,
+                new_content: String::new(),
+            },
+            true,
+        ),
+        Message::History(history) => (
+            Message::History(
+                history
+                    .iter()
+                    .map(|historical| HistoricalMessage {
+                        content: historical.content.as_ref().map(|_| String::new()),
+                        ..historical.clone()
+                    })
+                    .collect(),
+            ),
+            true,
+        ),
+        message => (message.clone(), false),
+    }
+}
+
+struct RecorderInner {
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+/// Appends frames to a recording file. Clones share the file.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<Mutex<RecorderInner>>,
+    keep_payloads: bool,
+}
+
+impl Recorder {
+    /// Creates a recording, replacing any existing file.
+    ///
+    /// # Arguments
+    /// * `path` - Where to write the recording
+    /// * `keep_payloads` - Keep encrypted payloads instead of redacting them
+    pub fn create(path: &Path, keep_payloads: bool) -> Result<Self> {
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                writer: BufWriter::new(File::create(path)?),
+                started: Instant::now(),
+            })),
+            keep_payloads,
+        })
+    }
+
+    /// Records a frame. Every frame is flushed, so a crash loses nothing.
+    pub fn record(&self, direction: Direction, message: &Message) -> Result<()> {
+        let (message, redacted) = redact(message, self.keep_payloads);
+        let mut inner = self.inner.lock().unwrap();
+        let frame = RecordedFrame {
+            at: Utc::now(),
+            offset_ms: inner.started.elapsed().as_millis() as u64,
+            direction,
+            redacted,
+            message,
+        };
+        serde_json::to_writer(&mut inner.writer, &frame)?;
+        inner.writer.write_all(b"\n")?;
+        inner.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads all frames of a recording.
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedFrame>> {
+    let mut frames = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            frames.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(frames)
+}
+
+/// Records the messages read from and written to a stream.
+pub struct RecordingStream<S> {
+    inner: S,
+    recorder: Recorder,
+}
+
+impl<S> RecordingStream<S> {
+    pub fn new(inner: S, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AsyncMessageStream + Send> AsyncMessageStream for RecordingStream<S> {
+    async fn read_message(&mut self) -> Result<Message> {
+        let message = self.inner.read_message().await?;
+        self.recorder.record(Direction::Received, &message)?;
+        Ok(message)
+    }
+
+    async fn write_message(&mut self, message: &Message) -> Result<()> {
+        self.inner.write_message(message).await?;
+        self.recorder.record(Direction::Sent, message)
+    }
+}
+
+/// Yields the received frames of a recording, optionally with their original
+/// timing. Redacted frames are skipped, since their payloads are gone.
+pub struct ReplayStream {
+    frames: std::vec::IntoIter<RecordedFrame>,
+    realtime: bool,
+    started: Instant,
+}
+
+impl ReplayStream {
+    pub fn new(frames: Vec<RecordedFrame>, realtime: bool) -> Self {
+        let received = frames
+            .into_iter()
+            .filter(|frame| frame.direction == Direction::Received && !frame.redacted)
+            .collect::<Vec<_>>();
+        Self {
+            frames: received.into_iter(),
+            realtime,
+            started: Instant::now(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncMessageStream for ReplayStream {
+    async fn read_message(&mut self) -> Result<Message> {
+        let frame = self
+            .frames
+            .next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::UnexpectedEof, "End of the recording"))?;
+        if self.realtime {
+            let due = Duration::from_millis(frame.offset_ms);
+            tokio::time::sleep(due.saturating_sub(self.started.elapsed())).await;
+        }
+        Ok(frame.message)
+    }
+
+    async fn write_message(&mut self, _message: &Message) -> Result<()> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "Cannot write messages to a replayed recording",
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HistoricalKind;
+
+    #[test]
+    fn test_redaction() {
+        let request = Message::Request {
+            client_msg_id: "c1".to_string(),
+            message: Box::new(Message::Text("ciphertext".to_string())),
+        };
+        let (redacted, was_redacted) = redact(&request, false);
+        assert!(was_redacted);
+        assert_eq!(
+            redacted,
+            Message::Request {
+                client_msg_id: "c1".to_string(),
+                message: Box::new(Message::Text(String::new())),
+            }
+        );
+        assert_eq!(redact(&request, true), (request, false));
+
+        let file = Message::File {
+            name: "notes.txt".to_string(),
+            metadata: serde_json::json!({"nonce": "abc"}),
+            data: vec![1, 2, 3],
+        };
+        match redact(&file, false).0 {
+            Message::File {
+                name,
+                metadata,
+                data,
+            } => {
+                assert_eq!(name, "notes.txt");
+                assert_eq!(metadata["nonce"], "abc");
+                assert!(data.is_empty());
+            }
+            message => panic!("Unexpected {:?}", message),
+        }
+
+        let reply = Message::Reply {
+            content: "ciphertext".to_string(),
+            quote: QuotedMessage {
+                id: 7,
+                author: "alice".to_string(),
+                excerpt: "ciphertext".to_string(),
+            },
+        };
+        assert_eq!(
+            redact(&reply, false).0,
+            Message::Reply {
+                content: String::new(),
+                quote: QuotedMessage {
+                    id: 7,
+                    author: "alice".to_string(),
+                    excerpt: String::new(),
+                },
+            }
+        );
+
+        let room_key = Message::RoomKey {
+            workspace: "general".to_string(),
+            key_id: "k1".to_string(),
+            wrapped_key: "wrapped".to_string(),
+            nonce: "nonce".to_string(),
+            last_seq: 12,
+        };
+        assert_eq!(
+            redact(&room_key, false),
+            (
+                Message::RoomKey {
+                    workspace: "general".to_string(),
+                    key_id: "k1".to_string(),
+                    wrapped_key: String::new(),
+                    nonce: String::new(),
+                    last_seq: 12,
+                },
+                true
+            )
+        );
+
+        let sequenced = Message::Sequenced {
+            seq: 13,
+            sent_at: None,
+            message: Box::new(Message::Text("ciphertext".to_string())),
+        };
+        assert_eq!(
+            redact(&sequenced, false).0,
+            Message::Sequenced {
+                seq: 13,
+                sent_at: None,
+                message: Box::new(Message::Text(String::new())),
+            }
+        );
+
+        let envelope = Message::ChatMessage {
+            sender: "alice".to_string(),
+            sent_at: Utc::now(),
+            body: Box::new(sequenced),
+        };
+        match redact(&envelope, false) {
+            (Message::ChatMessage { sender, body, .. }, true) => {
+                assert_eq!(sender, "alice");
+                assert_eq!(
+                    *body,
+                    Message::Sequenced {
+                        seq: 13,
+                        sent_at: None,
+                        message: Box::new(Message::Text(String::new())),
+                    }
+                );
+            }
+            message => panic!("Unexpected {:?}", message),
+        }
+
+        let history = Message::History(vec![HistoricalMessage {
+            id: 7,
+            seq: 3,
+            sent_at: Utc::now(),
+            sender: "alice".to_string(),
+            kind: HistoricalKind::Text,
+            content: Some("ciphertext".to_string()),
+            file_name: None,
+        }]);
+        match redact(&history, false) {
+            (Message::History(history), true) => {
+                assert_eq!(history[0].content.as_deref(), Some(""));
+                assert_eq!(history[0].sender, "alice");
+            }
+            message => panic!("Unexpected {:?}", message),
+        }
+
+        let edit = Message::Edit {
+            message_id: 7,
+            new_content: "ciphertext".to_string(),
+        };
+        assert_eq!(
+            redact(&edit, false),
+            (
+                Message::Edit {
+                    message_id: 7,
+                    new_content: String::new(),
+                },
+                true
+            )
+        );
+
+        // Passwords never end up in a recording
+        let auth = Message::Auth {
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+        };
+        assert_eq!(
+            redact(&auth, true).0,
+            Message::Auth {
+                username: "alice".to_string(),
+                password: REDACTED.to_string(),
+            }
+        );
+        let register = Message::Register {
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: "secret".to_string(),
+            invite_code: None,
+        };
+        match redact(&register, true).0 {
+            Message::Register { password, .. } => assert_eq!(password, REDACTED),
+            message => panic!("Unexpected {:?}", message),
+        }
+        assert_eq!(
+            redact(
+                &Message::ChangePassword {
+                    current_password: "old secret".to_string(),
+                    new_password: "new secret".to_string(),
+                },
+                true
+            ),
+            (
+                Message::ChangePassword {
+                    current_password: REDACTED.to_string(),
+                    new_password: REDACTED.to_string(),
+                },
+                true
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let recorder = Recorder::create(&path, false).unwrap();
+
+        recorder
+            .record(Direction::Sent, &Message::Text("out".to_string()))
+            .unwrap();
+        recorder
+            .record(Direction::Received, &Message::System("Welcome".to_string()))
+            .unwrap();
+        recorder
+            .record(Direction::Received, &Message::Text("in".to_string()))
+            .unwrap();
+        recorder
+            .record(Direction::Received, &Message::Pong { nonce: 7 })
+            .unwrap();
+
+        let frames = read_recording(&path).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].direction, Direction::Sent);
+        assert!(frames[0].redacted);
+
+        // Only received frames that still have their payloads are replayed
+        let mut replay = ReplayStream::new(frames, false);
+        assert_eq!(
+            replay.read_message().await.unwrap(),
+            Message::System("Welcome".to_string())
+        );
+        assert_eq!(
+            replay.read_message().await.unwrap(),
+            Message::Pong { nonce: 7 }
+        );
+        assert!(replay.read_message().await.is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Bandwidth throttling for large transfers.
+//!
+//! Frames are written and read in fixed-size chunks, and a token bucket paces
+//! the chunks so a big file transfer stays below a configured rate instead of
+//! saturating the link. Small frames such as text messages fit in the bucket's
+//! burst and are not delayed.
+
+use crate::async_message_stream::{frame_header, read_frame_header, DEFAULT_MAX_FRAME_SIZE};
+use crate::{AsyncMessageStream, Message, Result};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+/// Size of the chunks throttled frames are transferred in
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// A token bucket limiting the number of bytes transferred per second.
+///
+/// The bucket holds at most one second worth of bytes. Transfers may overdraw
+/// it, in which case the caller sleeps until the debt is paid back.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `bytes_per_second` bytes per second.
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1) as f64;
+        Self {
+            bytes_per_second,
+            available: bytes_per_second,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Creates a limiter from a rate in KiB/s, or `None` if no limit is set.
+    pub fn from_kib(limit_kib: Option<u64>) -> Option<Self> {
+        limit_kib.map(|kib| Self::new(kib.saturating_mul(1024)))
+    }
+
+    /// Accounts for `bytes` transferred bytes, waiting if the rate was exceeded.
+    pub async fn consume(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let refilled = now.duration_since(self.last_refill).as_secs_f64() * self.bytes_per_second;
+        self.available = (self.available + refilled).min(self.bytes_per_second);
+        self.last_refill = now;
+
+        self.available -= bytes as f64;
+        if self.available < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(
+                -self.available / self.bytes_per_second,
+            ))
+            .await;
+        }
+    }
+}
+
+/// Writes a message frame, pacing the payload with the given limiter.
+///
+/// The frame is identical to the one written by `AsyncMessageStream`.
+///
+/// # Arguments
+/// * `writer` - The stream to write the frame to
+/// * `message` - The message to write
+/// * `limiter` - The limiter pacing the upload
+///
+/// # Returns
+/// * `Result<()>` - Success or an error if writing fails
+pub async fn write_message_throttled<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &Message,
+    limiter: &mut RateLimiter,
+) -> Result<()> {
+    let bytes = serde_cbor::to_vec(message)?;
+    writer.write_all(&frame_header(bytes.len())).await?;
+
+    for chunk in bytes.chunks(CHUNK_SIZE) {
+        limiter.consume(chunk.len()).await;
+        writer.write_all(chunk).await?;
+    }
+
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a message frame, pacing the payload with the given limiter.
+///
+/// # Arguments
+/// * `reader` - The stream to read the frame from
+/// * `limiter` - The limiter pacing the download
+///
+/// # Returns
+/// * `Result<Message>` - The deserialized message or an error if reading fails
+pub async fn read_message_throttled<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    limiter: &mut RateLimiter,
+) -> Result<Message> {
+    let len = read_frame_header(reader, DEFAULT_MAX_FRAME_SIZE).await?;
+
+    let mut buffer = vec![0u8; len];
+    for chunk in buffer.chunks_mut(CHUNK_SIZE) {
+        limiter.consume(chunk.len()).await;
+        reader.read_exact(chunk).await?;
+    }
+
+    Ok(serde_cbor::from_slice(&buffer)?)
+}
+
+/// A read stream whose incoming frames are throttled.
+pub struct ThrottledReader<R> {
+    inner: R,
+    limiter: RateLimiter,
+}
+
+impl<R> ThrottledReader<R> {
+    pub fn new(inner: R, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: AsyncRead + Unpin + Send> AsyncMessageStream for ThrottledReader<R> {
+    async fn read_message(&mut self) -> Result<Message> {
+        read_message_throttled(&mut self.inner, &mut self.limiter).await
+    }
+
+    async fn write_message(&mut self, _message: &Message) -> Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot write messages with ThrottledReader",
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_paces_transfers() {
+        let mut limiter = RateLimiter::new(1000);
+        let started = Instant::now();
+
+        // The first second worth of bytes is available immediately
+        limiter.consume(1000).await;
+        assert!(started.elapsed() < Duration::from_millis(10));
+
+        limiter.consume(500).await;
+        assert!(started.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_roundtrip() {
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let message = Message::File {
+            name: "big.bin".to_string(),
+            metadata: serde_json::json!({}),
+            data: vec![7u8; 100 * 1024],
+        };
+
+        let mut upload = RateLimiter::new(50 * 1024);
+        let started = Instant::now();
+        let sent = message.clone();
+        let writer = tokio::spawn(async move {
+            write_message_throttled(&mut client, &sent, &mut upload)
+                .await
+                .unwrap();
+        });
+
+        let mut reader = ThrottledReader::new(server, RateLimiter::new(1024 * 1024));
+        let received = reader.read_message().await.unwrap();
+        writer.await.unwrap();
+
+        assert_eq!(received, message);
+        // 100 KiB at 50 KiB/s with a 50 KiB burst takes at least a second
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,54 @@
+use gloo_storage::{LocalStorage, Storage};
+use yew::prelude::*;
+use yew_hooks::use_interval;
+use yew_router::prelude::*;
+
+use crate::services::MessageService;
+use crate::store::{use_store, StoreAction};
+
+/// How often the download token is renewed; the server lets it live for 10
+/// minutes
+const RENEW_INTERVAL_MS: u32 = 5 * 60 * 1000;
+
+/// Keeps a download token for attachment URLs in the store while a user is
+/// logged in. Renders nothing.
+///
+/// A token is requested on the first page shown after logging in and renewed
+/// before it expires; logging out drops it.
+#[function_component(DownloadTokens)]
+pub fn download_tokens() -> Html {
+    let store = use_store();
+    let location = use_location();
+
+    let renew = {
+        let store = store.clone();
+        Callback::from(move |_: ()| {
+            if LocalStorage::get::<String>("token").is_err() {
+                return;
+            }
+            let store = store.clone();
+            MessageService::issue_download_token(Callback::from(move |result| {
+                if let Ok(token) = result {
+                    store.dispatch(StoreAction::DownloadTokenChanged(Some(token)));
+                }
+            }));
+        })
+    };
+
+    {
+        let renew = renew.clone();
+        let has_token = store.download_token.is_some();
+        use_effect_with(location, move |_| {
+            let logged_in = LocalStorage::get::<String>("token").is_ok();
+            if logged_in && !has_token {
+                renew.emit(());
+            } else if !logged_in && has_token {
+                store.dispatch(StoreAction::DownloadTokenChanged(None));
+            }
+        });
+    }
+
+    use_interval(move || renew.emit(()), RENEW_INTERVAL_MS);
+
+    html! {}
+}
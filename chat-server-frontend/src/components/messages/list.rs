@@ -1,66 +1,112 @@
-use crate::models::{Message, MessageType, User};
-use crate::services::{FetchError, MessageService, UserService};
+use crate::components::modal::{ConfirmModal, Lightbox};
+use crate::models::{Message, MessageRevision, MessageType, User};
+use crate::services::{ExportProgress, FetchError, MessageService, UserService};
+use crate::store::{use_store, StoreAction};
 use gloo_dialogs;
+use std::collections::HashMap;
 use web_sys::HtmlSelectElement;
 use yew::prelude::*;
 
+/// Extensions of videos the browser can preview
+const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "m4v", "webm", "ogv", "mov"];
+
+fn is_video(file_name: &str) -> bool {
+    extension(file_name).is_some_and(|extension| VIDEO_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Lowercase extension of a file name, like `pdf`
+fn extension(file_name: &str) -> Option<String> {
+    file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .filter(|extension| !extension.is_empty())
+}
+
+/// Bootstrap icon for a file, chosen by its extension
+pub(crate) fn file_icon(file_name: &str) -> &'static str {
+    match extension(file_name).as_deref() {
+        Some("pdf") => "bi-file-earmark-pdf",
+        Some("zip" | "tar" | "gz" | "tgz" | "7z" | "rar") => "bi-file-earmark-zip",
+        Some("txt" | "md" | "csv" | "log") => "bi-file-earmark-text",
+        Some("rs" | "js" | "ts" | "py" | "json" | "toml" | "html" | "css") => {
+            "bi-file-earmark-code"
+        }
+        Some("mp3" | "wav" | "ogg" | "flac") => "bi-file-earmark-music",
+        Some(extension) if VIDEO_EXTENSIONS.contains(&extension) => "bi-file-earmark-play",
+        _ => "bi-file-earmark",
+    }
+}
+
+/// Formats a size in bytes for display, like `1.5 MB`
+pub(crate) fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
 #[function_component(MessagesList)]
 pub fn messages_list() -> Html {
-    let messages = use_state(Vec::new);
-    let users = use_state(Vec::new);
-    let filtered_messages = use_state(Vec::new);
+    let store = use_store();
+    let download_token = store.download_token.clone().unwrap_or_default();
     let error = use_state(|| None::<String>);
-    let loading = use_state(|| true);
+    let export_progress = use_state(|| None::<ExportProgress>);
+    // Edit histories that are shown, by message ID
+    let revisions = use_state(HashMap::<i32, Vec<MessageRevision>>::new);
+    // Message whose deletion waits for confirmation
+    let confirm_delete = use_state(|| None::<i32>);
+    // Image shown at full size, as its URL and name
+    let lightbox = use_state(|| None::<(String, String)>);
 
     // Filter states
     let selected_user_id = use_state(|| None::<i32>);
     let selected_message_type = use_state(|| None::<MessageType>);
 
-    // Function to fetch messages
+    // Function to refresh the cached messages
     let fetch_messages = {
-        let messages = messages.clone();
-        let filtered_messages = filtered_messages.clone();
+        let store = store.clone();
         let error = error.clone();
-        let loading = loading.clone();
 
         Callback::from(move |_| {
-            loading.set(true);
             error.set(None);
 
             let callback = {
-                let messages = messages.clone();
-                let filtered_messages = filtered_messages.clone();
+                let store = store.clone();
                 let error = error.clone();
-                let loading = loading.clone();
 
-                Callback::from(move |result: Result<Vec<Message>, FetchError>| {
-                    match result {
+                Callback::from(
+                    move |result: Result<Vec<Message>, FetchError>| match result {
                         Ok(data) => {
-                            messages.set(data.clone());
-                            filtered_messages.set(data);
+                            store.dispatch(StoreAction::MessagesLoaded(data));
                         }
                         Err(e) => {
                             error.set(Some(e.to_string()));
                         }
-                    }
-                    loading.set(false);
-                })
+                    },
+                )
             };
 
             MessageService::fetch_messages(callback);
         })
     };
 
-    // Function to fetch users (for filter dropdown)
+    // Function to refresh the cached users (for filter dropdown)
     let fetch_users = {
-        let users = users.clone();
+        let store = store.clone();
 
         Callback::from(move |_| {
-            let users = users.clone();
+            let store = store.clone();
 
             let callback = Callback::from(move |result: Result<Vec<User>, FetchError>| {
                 if let Ok(data) = result {
-                    users.set(data);
+                    store.dispatch(StoreAction::UsersLoaded(data));
                 }
                 // We don't need to handle errors here as it's not critical for the main functionality
             });
@@ -71,43 +117,138 @@ pub fn messages_list() -> Html {
 
     // Delete message function
     let delete_message = {
-        let fetch_messages = fetch_messages.clone();
+        let store = store.clone();
 
         Callback::from(move |message_id: i32| {
-            let fetch_messages = fetch_messages.clone();
+            let Some((index, message)) = store
+                .messages
+                .iter()
+                .flatten()
+                .enumerate()
+                .find(|(_, message)| message.id == message_id)
+                .map(|(index, message)| (index, message.clone()))
+            else {
+                return;
+            };
+
+            // Remove the message right away and put it back if the server refuses
+            store.dispatch(StoreAction::MessageRemoved(message_id));
+
+            let callback = {
+                let store = store.clone();
+
+                Callback::from(move |result: Result<(), FetchError>| {
+                    if let Err(e) = result {
+                        store.dispatch(StoreAction::MessageRestored {
+                            message: message.clone(),
+                            index,
+                        });
+                        // Show error in alert
+                        gloo_dialogs::alert(&e.to_string());
+                    }
+                })
+            };
 
-            let confirm = gloo_dialogs::confirm("Are you sure you want to delete this message?");
-            if !confirm {
+            MessageService::delete_message(message_id, callback);
+        })
+    };
+
+    let on_confirm_delete = {
+        let confirm_delete = confirm_delete.clone();
+        let delete_message = delete_message.clone();
+        Callback::from(move |_| {
+            if let Some(message_id) = *confirm_delete {
+                delete_message.emit(message_id);
+            }
+            confirm_delete.set(None);
+        })
+    };
+
+    let on_cancel_delete = {
+        let confirm_delete = confirm_delete.clone();
+        Callback::from(move |_| confirm_delete.set(None))
+    };
+
+    let on_close_lightbox = {
+        let lightbox = lightbox.clone();
+        Callback::from(move |_| lightbox.set(None))
+    };
+
+    // Show or hide the earlier versions of an edited message
+    let toggle_revisions = {
+        let revisions = revisions.clone();
+
+        Callback::from(move |message_id: i32| {
+            if revisions.contains_key(&message_id) {
+                let mut shown = (*revisions).clone();
+                shown.remove(&message_id);
+                revisions.set(shown);
                 return;
             }
 
             let callback = {
-                let fetch_messages = fetch_messages.clone();
+                let revisions = revisions.clone();
 
-                Callback::from(move |result: Result<(), FetchError>| {
-                    match result {
-                        Ok(_) => {
-                            // Refresh message list
-                            fetch_messages.emit(());
+                Callback::from(
+                    move |result: Result<Vec<MessageRevision>, FetchError>| match result {
+                        Ok(data) => {
+                            let mut shown = (*revisions).clone();
+                            shown.insert(message_id, data);
+                            revisions.set(shown);
                         }
                         Err(e) => {
-                            // Show error in alert
-                            gloo_dialogs::alert(&e.to_string());
+                            gloo_dialogs::alert(&format!("Failed to load the edit history: {}", e));
                         }
+                    },
+                )
+            };
+
+            MessageService::fetch_revisions(message_id, callback);
+        })
+    };
+
+    // Export the messages matching the active filters
+    let on_export = {
+        let export_progress = export_progress.clone();
+        let selected_user_id = selected_user_id.clone();
+        let selected_message_type = selected_message_type.clone();
+
+        Callback::from(move |_| {
+            export_progress.set(Some(ExportProgress {
+                rows: 0,
+                total: None,
+                bytes: 0,
+            }));
+
+            let on_progress = {
+                let export_progress = export_progress.clone();
+                Callback::from(move |progress: ExportProgress| {
+                    export_progress.set(Some(progress));
+                })
+            };
+
+            let callback = {
+                let export_progress = export_progress.clone();
+                Callback::from(move |result: Result<(), FetchError>| {
+                    export_progress.set(None);
+                    if let Err(e) = result {
+                        gloo_dialogs::alert(&format!("Export failed: {}", e));
                     }
                 })
             };
 
-            MessageService::delete_message(message_id, callback);
+            MessageService::export_messages(
+                *selected_user_id,
+                (*selected_message_type).clone(),
+                on_progress,
+                callback,
+            );
         })
     };
 
     // Handle user filter change
     let on_user_filter_change = {
         let selected_user_id = selected_user_id.clone();
-        let messages = messages.clone();
-        let filtered_messages = filtered_messages.clone();
-        let selected_message_type = selected_message_type.clone();
 
         Callback::from(move |e: Event| {
             let target = e.target_dyn_into::<HtmlSelectElement>();
@@ -120,21 +261,6 @@ pub fn messages_list() -> Html {
                 };
 
                 selected_user_id.set(user_id);
-
-                // Apply filters
-                let filtered = messages
-                    .iter()
-                    .filter(|msg| {
-                        let user_match = user_id.map_or(true, |id| msg.sender_id == id);
-                        let type_match = selected_message_type
-                            .as_ref()
-                            .map_or(true, |t| &msg.message_type == t);
-                        user_match && type_match
-                    })
-                    .cloned()
-                    .collect::<Vec<Message>>();
-
-                filtered_messages.set(filtered);
             }
         })
     };
@@ -142,9 +268,6 @@ pub fn messages_list() -> Html {
     // Handle message type filter change
     let on_message_type_filter_change = {
         let selected_message_type = selected_message_type.clone();
-        let messages = messages.clone();
-        let filtered_messages = filtered_messages.clone();
-        let selected_user_id = selected_user_id.clone();
 
         Callback::from(move |e: Event| {
             let target = e.target_dyn_into::<HtmlSelectElement>();
@@ -155,30 +278,30 @@ pub fn messages_list() -> Html {
                     "Text" => Some(MessageType::Text),
                     "File" => Some(MessageType::File),
                     "Image" => Some(MessageType::Image),
+                    "Action" => Some(MessageType::Action),
                     _ => None,
                 };
 
-                selected_message_type.set(msg_type.clone());
-
-                // Apply filters
-                let filtered = messages
-                    .iter()
-                    .filter(|msg| {
-                        let user_match = selected_user_id
-                            .as_ref()
-                            .map_or(true, |id| msg.sender_id == *id);
-                        let type_match = msg_type.as_ref().map_or(true, |t| &msg.message_type == t);
-                        user_match && type_match
-                    })
-                    .cloned()
-                    .collect::<Vec<Message>>();
-
-                filtered_messages.set(filtered);
+                selected_message_type.set(msg_type);
             }
         })
     };
 
-    // Fetch data when component mounts
+    // Apply filters to the cached messages
+    let filtered_messages = store
+        .messages
+        .iter()
+        .flatten()
+        .filter(|msg| {
+            let user_match = selected_user_id.is_none_or(|id| msg.sender_id == id);
+            let type_match = selected_message_type
+                .as_ref()
+                .is_none_or(|t| &msg.message_type == t);
+            user_match && type_match
+        })
+        .collect::<Vec<&Message>>();
+
+    // Fetch data when component mounts; cached messages are shown meanwhile
     {
         let fetch_messages = fetch_messages.clone();
         let fetch_users = fetch_users.clone();
@@ -190,17 +313,15 @@ pub fn messages_list() -> Html {
         });
     }
 
-    // Helper function to get username by id
-    let get_username = {
-        let users = users.clone();
+    let users = store.users.as_deref().unwrap_or_default();
 
-        move |user_id: i32| -> String {
-            users
-                .iter()
-                .find(|u| u.id == user_id)
-                .map(|u| u.username.clone())
-                .unwrap_or_else(|| format!("User {}", user_id))
-        }
+    // Helper function to get username by id
+    let get_username = move |user_id: i32| -> String {
+        users
+            .iter()
+            .find(|u| u.id == user_id)
+            .map(|u| u.username.clone())
+            .unwrap_or_else(|| format!("User {}", user_id))
     };
 
     // Helper function to render message content based on type
@@ -211,31 +332,122 @@ pub fn messages_list() -> Html {
                     {message.content.clone().unwrap_or_default()}
                 </div>
             },
-            MessageType::File => html! {
-                <div class="message-content">
-                    <i class="bi bi-file-earmark me-2"></i>
-                    <a href="#" class="text-decoration-none">
-                        {message.file_name.clone().unwrap_or_else(|| "Unnamed file".to_string())}
-                    </a>
-                </div>
-            },
-            MessageType::Image => html! {
-                <div class="message-content">
-                    <i class="bi bi-image me-2"></i>
-                    <a href="#" class="text-decoration-none">
-                        {message.file_name.clone().unwrap_or_else(|| "Unnamed image".to_string())}
-                    </a>
+            MessageType::Action => html! {
+                <div class="message-content fst-italic">
+                    {format!(
+                        "* {} {}",
+                        get_username(message.sender_id),
+                        message.content.clone().unwrap_or_default()
+                    )}
                 </div>
             },
+            MessageType::File => {
+                let url = MessageService::attachment_url(message.id, &download_token);
+                let name = message
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "Unnamed file".to_string());
+                html! {
+                    <div class="message-content">
+                        <i class={classes!("bi", file_icon(&name), "me-2")}></i>
+                        <a href={url.clone()} target="_blank" rel="noopener" class="text-decoration-none">
+                            {name.clone()}
+                        </a>
+                        if let Some(extension) = extension(&name) {
+                            <span class="badge text-bg-light border ms-2">{extension.to_uppercase()}</span>
+                        }
+                        if let Some(size) = message.attachment_size {
+                            <span class="badge text-bg-light border ms-1">{format_size(size)}</span>
+                        }
+                        {
+                            if is_video(&name) {
+                                // Loaded in ranges, so playback starts before the whole file arrived
+                                html! {
+                                    <video class="d-block mt-2" style="max-width: 320px;" controls=true preload="metadata" src={url} />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </div>
+                }
+            }
+            MessageType::Image => {
+                let url = MessageService::attachment_url(message.id, &download_token);
+                let name = message
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "Unnamed image".to_string());
+                let on_open = {
+                    let lightbox = lightbox.clone();
+                    let (url, name) = (url.clone(), name.clone());
+                    Callback::from(move |e: MouseEvent| {
+                        e.prevent_default();
+                        lightbox.set(Some((url.clone(), name.clone())));
+                    })
+                };
+                html! {
+                    <div class="message-content">
+                        <i class="bi bi-image me-2"></i>
+                        <a href={url.clone()} target="_blank" rel="noopener" class="text-decoration-none">
+                            {name.clone()}
+                        </a>
+                        if let Some(size) = message.attachment_size {
+                            <span class="badge text-bg-light border ms-2">{format_size(size)}</span>
+                            // Only stored attachments have a thumbnail
+                            <button
+                                type="button"
+                                class="d-block mt-2 p-0 border-0 bg-transparent"
+                                onclick={on_open}
+                                title="Show full size"
+                            >
+                                <img
+                                    class="img-thumbnail"
+                                    style="max-height: 160px;"
+                                    loading="lazy"
+                                    src={MessageService::thumbnail_url(message.id, &download_token)}
+                                    alt={name}
+                                />
+                            </button>
+                        }
+                    </div>
+                }
+            }
         }
     };
 
     html! {
         <div class="container py-4">
+            if let Some((src, name)) = (*lightbox).clone() {
+                <Lightbox {src} {name} on_close={on_close_lightbox} />
+            }
+
+            if confirm_delete.is_some() {
+                <ConfirmModal
+                    title="Delete message"
+                    message="Are you sure you want to delete this message?"
+                    confirm_label="Delete"
+                    destructive=true
+                    on_confirm={on_confirm_delete}
+                    on_cancel={on_cancel_delete}
+                />
+            }
+
             <div class="card shadow-sm">
-                <div class="card-header bg-primary text-white d-flex justify-content-between align-items-center">
+                <div class="card-header text-bg-primary d-flex justify-content-between align-items-center">
                     <h3 class="mb-0">{"Messages"}</h3>
-                    <span class="badge bg-light text-primary">{format!("Total: {}", filtered_messages.len())}</span>
+                    <div class="d-flex align-items-center gap-2">
+                        <span class="badge bg-light text-primary">{format!("Total: {}", filtered_messages.len())}</span>
+                        <button
+                            class="btn btn-sm btn-light"
+                            onclick={on_export}
+                            disabled={export_progress.is_some()}
+                            title="Download the filtered messages as CSV"
+                        >
+                            <i class="bi bi-download me-1"></i>
+                            {"Export"}
+                        </button>
+                    </div>
                 </div>
 
                 <div class="card-body">
@@ -261,12 +473,47 @@ pub fn messages_list() -> Html {
                                 <option value="Text">{"Text"}</option>
                                 <option value="File">{"File"}</option>
                                 <option value="Image">{"Image"}</option>
+                                <option value="Action">{"Action"}</option>
                             </select>
                         </div>
                     </div>
 
+                    if let (Some(err), Some(_)) = (error.as_ref(), &store.messages) {
+                        <div class="alert alert-warning" role="alert">
+                            <i class="bi bi-exclamation-triangle me-2"></i>
+                            {"Showing cached messages, refreshing failed: "}{err}
+                        </div>
+                    }
+
+                    // Export progress
                     {
-                        if *loading {
+                        if let Some(progress) = *export_progress {
+                            let percent = progress.percent();
+                            let label = match progress.total {
+                                Some(total) => format!("Exporting {} of {} messages", progress.rows.min(total), total),
+                                None => format!("Exporting {} messages", progress.rows),
+                            };
+                            html! {
+                                <div class="mb-4">
+                                    <small class="text-muted">{label}</small>
+                                    <div class="progress">
+                                        <div
+                                            class={classes!("progress-bar", percent.is_none().then_some("progress-bar-striped progress-bar-animated"))}
+                                            role="progressbar"
+                                            style={format!("width: {}%", percent.unwrap_or(100))}
+                                        >
+                                            {percent.map(|percent| format!("{}%", percent)).unwrap_or_default()}
+                                        </div>
+                                    </div>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+
+                    {
+                        if store.messages.is_none() && error.is_none() {
                             html! {
                                 <div class="d-flex justify-content-center p-4">
                                     <div class="spinner-border text-primary" role="status">
@@ -274,7 +521,7 @@ pub fn messages_list() -> Html {
                                     </div>
                                 </div>
                             }
-                        } else if let Some(err) = error.as_ref() {
+                        } else if let (Some(err), None) = (error.as_ref(), &store.messages) {
                             html! {
                                 <div class="alert alert-danger" role="alert">
                                     <i class="bi bi-exclamation-triangle me-2"></i>
@@ -292,17 +539,22 @@ pub fn messages_list() -> Html {
                             html! {
                                 <div class="list-group list-group-flush">
                                     {
-                                        filtered_messages.iter().map(|message| {
+                                        filtered_messages.iter().map(|&message| {
                                             let message_id = message.id;
-                                            let delete_message = delete_message.clone();
+                                            let confirm_delete = confirm_delete.clone();
                                             let on_delete = Callback::from(move |_| {
-                                                delete_message.emit(message_id);
+                                                confirm_delete.set(Some(message_id));
+                                            });
+                                            let toggle_revisions = toggle_revisions.clone();
+                                            let on_toggle_revisions = Callback::from(move |_| {
+                                                toggle_revisions.emit(message_id);
                                             });
 
                                             let message_type_badge = match message.message_type {
-                                                MessageType::Text => html! { <span class="badge bg-primary">{"Text"}</span> },
-                                                MessageType::File => html! { <span class="badge bg-success">{"File"}</span> },
-                                                MessageType::Image => html! { <span class="badge bg-info">{"Image"}</span> },
+                                                MessageType::Text => html! { <span class="badge text-bg-primary"><i class="bi bi-chat-left-text me-1"></i>{"Text"}</span> },
+                                                MessageType::File => html! { <span class="badge text-bg-success"><i class="bi bi-paperclip me-1"></i>{"File"}</span> },
+                                                MessageType::Image => html! { <span class="badge text-bg-info"><i class="bi bi-image me-1"></i>{"Image"}</span> },
+                                                MessageType::Action => html! { <span class="badge text-bg-secondary"><i class="bi bi-person-arms-up me-1"></i>{"Action"}</span> },
                                             };
 
                                             html! {
@@ -318,9 +570,50 @@ pub fn messages_list() -> Html {
                                                                     <small class="text-muted">
                                                                         <i class="bi bi-clock me-1"></i>
                                                                         {message.created_at.split('T').next().unwrap_or(&message.created_at)}
+                                                                        {
+                                                                            if message.edited_at.is_some() {
+                                                                                html! {
+                                                                                    <button
+                                                                                        class="btn btn-link btn-sm p-0 ms-2 text-muted"
+                                                                                        onclick={on_toggle_revisions}
+                                                                                        title="Show or hide the edit history"
+                                                                                    >
+                                                                                        {"(edited)"}
+                                                                                    </button>
+                                                                                }
+                                                                            } else {
+                                                                                html! {}
+                                                                            }
+                                                                        }
                                                                     </small>
                                                                 </div>
                                                                 {render_message_content(message)}
+                                                                {
+                                                                    if let Some(history) = revisions.get(&message.id) {
+                                                                        html! {
+                                                                            <ul class="list-unstyled border-start ps-3 mt-2 mb-0 small text-muted">
+                                                                                {
+                                                                                    history.iter().map(|revision| {
+                                                                                        let text = revision.content.clone()
+                                                                                            .or_else(|| revision.file_name.clone())
+                                                                                            .unwrap_or_default();
+                                                                                        let editor = revision.edited_by
+                                                                                            .map(&get_username)
+                                                                                            .unwrap_or_else(|| "[deleted]".to_string());
+                                                                                        html! {
+                                                                                            <li key={revision.id.to_string()}>
+                                                                                                <s>{text}</s>
+                                                                                                {format!(" - replaced by {} on {}", editor, revision.edited_at.replace('T', " "))}
+                                                                                            </li>
+                                                                                        }
+                                                                                    }).collect::<Html>()
+                                                                                }
+                                                                            </ul>
+                                                                        }
+                                                                    } else {
+                                                                        html! {}
+                                                                    }
+                                                                }
                                                             </div>
                                                         </div>
                                                         <div class="col-md-2 d-flex align-items-center justify-content-end">
@@ -1,3 +1,6 @@
+mod download_token;
 mod list;
 
+pub use download_token::DownloadTokens;
 pub use list::MessagesList;
+pub(crate) use list::{file_icon, format_size};
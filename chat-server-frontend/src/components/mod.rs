@@ -1,3 +1,8 @@
 pub mod messages;
+pub mod modal;
 pub mod navigation;
+pub mod pagination;
+pub mod reports;
+pub mod shortcuts;
+pub mod theme;
 pub mod user;
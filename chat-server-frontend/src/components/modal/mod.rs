@@ -0,0 +1,225 @@
+use wasm_bindgen::JsCast;
+use web_sys::HtmlElement;
+use yew::prelude::*;
+
+/// Focuses `initial` when a dialog opens and returns the focus to where it was
+/// when the dialog closes.
+#[hook]
+pub(crate) fn use_modal_focus(initial: NodeRef) {
+    use_effect_with((), move |_| {
+        let previous = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.active_element())
+            .and_then(|element| element.dyn_into::<HtmlElement>().ok());
+        if let Some(initial) = initial.cast::<HtmlElement>() {
+            let _ = initial.focus();
+        }
+        move || {
+            if let Some(previous) = previous {
+                let _ = previous.focus();
+            }
+        }
+    });
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ConfirmModalProps {
+    pub title: AttrValue,
+    /// The question asked
+    pub message: AttrValue,
+    #[prop_or(AttrValue::Static("Confirm"))]
+    pub confirm_label: AttrValue,
+    /// Styles the confirm button as an action that cannot be undone
+    #[prop_or_default]
+    pub destructive: bool,
+    pub on_confirm: Callback<()>,
+    pub on_cancel: Callback<()>,
+}
+
+/// Modal dialog asking to confirm an action, shown while it is rendered.
+///
+/// The cancel button is focused when the dialog opens, so pressing Enter by
+/// accident does not delete anything. Tab keeps the focus on the dialog's
+/// buttons, Escape and clicking outside the dialog cancel, and the focus
+/// returns to where it was when the dialog closes.
+#[function_component(ConfirmModal)]
+pub fn confirm_modal(props: &ConfirmModalProps) -> Html {
+    let cancel_ref = use_node_ref();
+    let confirm_ref = use_node_ref();
+    use_modal_focus(cancel_ref.clone());
+
+    let onkeydown = {
+        let on_cancel = props.on_cancel.clone();
+        let cancel_ref = cancel_ref.clone();
+        let confirm_ref = confirm_ref.clone();
+
+        Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "Escape" => {
+                e.prevent_default();
+                on_cancel.emit(());
+            }
+            "Tab" => {
+                // The buttons are the only focusable elements, so Tab and
+                // Shift+Tab both move to the one without focus
+                e.prevent_default();
+                let (Some(cancel), Some(confirm)) = (
+                    cancel_ref.cast::<HtmlElement>(),
+                    confirm_ref.cast::<HtmlElement>(),
+                ) else {
+                    return;
+                };
+                let cancel_focused = web_sys::window()
+                    .and_then(|window| window.document())
+                    .and_then(|document| document.active_element())
+                    .is_some_and(|active| active == *cancel);
+                let _ = if cancel_focused {
+                    confirm.focus()
+                } else {
+                    cancel.focus()
+                };
+            }
+            _ => {}
+        })
+    };
+
+    // Clicks on the dialog itself bubble up too; only the area around it cancels
+    let on_backdrop_click = {
+        let on_cancel = props.on_cancel.clone();
+        Callback::from(move |e: MouseEvent| {
+            if e.target() == e.current_target() {
+                on_cancel.emit(());
+            }
+        })
+    };
+    let on_cancel = props.on_cancel.reform(|_: MouseEvent| ());
+    let on_confirm = props.on_confirm.reform(|_: MouseEvent| ());
+
+    let confirm_class = if props.destructive {
+        "btn btn-danger"
+    } else {
+        "btn btn-primary"
+    };
+
+    html! {
+        <>
+            <div
+                class="modal d-block"
+                tabindex="-1"
+                role="dialog"
+                aria-modal="true"
+                aria-labelledby="confirm-modal-title"
+                aria-describedby="confirm-modal-message"
+                onclick={on_backdrop_click}
+                {onkeydown}
+            >
+                <div class="modal-dialog modal-dialog-centered">
+                    <div class="modal-content">
+                        <div class="modal-header">
+                            <h5 class="modal-title" id="confirm-modal-title">
+                                if props.destructive {
+                                    <i class="bi bi-exclamation-triangle text-danger me-2"></i>
+                                }
+                                {props.title.clone()}
+                            </h5>
+                        </div>
+                        <div class="modal-body">
+                            <p class="mb-0" id="confirm-modal-message">{props.message.clone()}</p>
+                        </div>
+                        <div class="modal-footer">
+                            <button type="button" class="btn btn-secondary" ref={cancel_ref} onclick={on_cancel}>
+                                {"Cancel"}
+                            </button>
+                            <button type="button" class={confirm_class} ref={confirm_ref} onclick={on_confirm}>
+                                {props.confirm_label.clone()}
+                            </button>
+                        </div>
+                    </div>
+                </div>
+            </div>
+            <div class="modal-backdrop show"></div>
+        </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct LightboxProps {
+    /// URL of the full-size image
+    pub src: AttrValue,
+    /// Name of the image, shown as its caption
+    pub name: AttrValue,
+    pub on_close: Callback<()>,
+}
+
+/// Modal showing an image at full size, shown while it is rendered.
+///
+/// The close button is focused when it opens and keeps the focus; Escape, the
+/// close button and clicking outside the image close it.
+#[function_component(Lightbox)]
+pub fn lightbox(props: &LightboxProps) -> Html {
+    let close_ref = use_node_ref();
+    use_modal_focus(close_ref.clone());
+
+    let onkeydown = {
+        let on_close = props.on_close.clone();
+        Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "Escape" => {
+                e.prevent_default();
+                on_close.emit(());
+            }
+            // The close button is the only focusable element
+            "Tab" => e.prevent_default(),
+            _ => {}
+        })
+    };
+
+    let on_backdrop_click = {
+        let on_close = props.on_close.clone();
+        Callback::from(move |e: MouseEvent| {
+            if e.target() == e.current_target() {
+                on_close.emit(());
+            }
+        })
+    };
+    let on_close = props.on_close.reform(|_: MouseEvent| ());
+
+    html! {
+        <>
+            <div
+                class="modal d-block"
+                tabindex="-1"
+                role="dialog"
+                aria-modal="true"
+                aria-labelledby="lightbox-title"
+                onclick={on_backdrop_click}
+                {onkeydown}
+            >
+                <div class="modal-dialog modal-dialog-centered modal-xl">
+                    <div class="modal-content">
+                        <div class="modal-header">
+                            <h5 class="modal-title text-truncate" id="lightbox-title">
+                                <i class="bi bi-image me-2"></i>
+                                {props.name.clone()}
+                            </h5>
+                            <button
+                                type="button"
+                                class="btn-close"
+                                aria-label="Close"
+                                ref={close_ref}
+                                onclick={on_close}
+                            ></button>
+                        </div>
+                        <div class="modal-body text-center">
+                            <img
+                                class="img-fluid"
+                                style="max-height: 75vh;"
+                                src={props.src.clone()}
+                                alt={props.name.clone()}
+                            />
+                        </div>
+                    </div>
+                </div>
+            </div>
+            <div class="modal-backdrop show"></div>
+        </>
+    }
+}
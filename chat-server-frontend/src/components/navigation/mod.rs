@@ -2,6 +2,7 @@ use gloo_storage::{LocalStorage, Storage};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+use crate::components::theme::ThemeToggle;
 use crate::routes::AppRoute;
 
 #[function_component(Navbar)]
@@ -56,9 +57,16 @@ pub fn navbar() -> Html {
                                     {"Messages"}
                                 </Link<AppRoute>>
                             </li>
+                            <li class="nav-item">
+                                <Link<AppRoute> classes="nav-link" to={AppRoute::Moderation}>
+                                    <i class="bi bi-flag me-1"></i>
+                                    {"Moderation"}
+                                </Link<AppRoute>>
+                            </li>
                         }
                     </ul>
                     <div class="d-flex">
+                        <ThemeToggle />
                         if *is_logged_in {
                             <button class="btn btn-outline-light" onclick={logout}>
                                 <i class="bi bi-box-arrow-right me-1"></i>
@@ -0,0 +1,174 @@
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+/// Page sizes offered when the parent does not choose its own
+pub const PAGE_SIZES: &[u32] = &[10, 25, 50, 100];
+
+/// Pages linked on each side of the current page
+const PAGE_WINDOW: u32 = 2;
+
+#[derive(Properties, PartialEq)]
+pub struct PaginationProps {
+    /// Current page, starting at 1
+    pub page: u32,
+    pub per_page: u32,
+    /// Items on all pages
+    pub total: u32,
+    #[prop_or(PAGE_SIZES)]
+    pub page_sizes: &'static [u32],
+    pub on_page_change: Callback<u32>,
+    pub on_per_page_change: Callback<u32>,
+}
+
+/// Returns the number of pages `total` items fill, at least one.
+pub fn page_count(total: u32, per_page: u32) -> u32 {
+    total.div_ceil(per_page.max(1)).max(1)
+}
+
+/// Controls for paging through a list: the range shown, a page size selector,
+/// previous and next buttons, links to nearby pages and a jump-to-page field.
+#[function_component(Pagination)]
+pub fn pagination(props: &PaginationProps) -> Html {
+    let jump_to = use_state(String::new);
+    let pages = page_count(props.total, props.per_page);
+    let page = props.page.clamp(1, pages);
+
+    let go_to = {
+        let on_page_change = props.on_page_change.clone();
+        move |target: u32| {
+            let on_page_change = on_page_change.clone();
+            Callback::from(move |e: MouseEvent| {
+                e.prevent_default();
+                on_page_change.emit(target);
+            })
+        }
+    };
+
+    let on_per_page_change = {
+        let on_per_page_change = props.on_per_page_change.clone();
+        Callback::from(move |e: Event| {
+            if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                if let Ok(value) = select.value().parse() {
+                    on_per_page_change.emit(value);
+                }
+            }
+        })
+    };
+
+    let on_jump_input = {
+        let jump_to = jump_to.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                jump_to.set(input.value());
+            }
+        })
+    };
+
+    let on_jump = {
+        let jump_to = jump_to.clone();
+        let on_page_change = props.on_page_change.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            if let Ok(target) = jump_to.trim().parse::<u32>() {
+                on_page_change.emit(target.clamp(1, pages));
+            }
+            jump_to.set(String::new());
+        })
+    };
+
+    // The first and last page are always linked, gaps are shown as ellipses
+    let first = page.saturating_sub(PAGE_WINDOW).max(1);
+    let last = (page + PAGE_WINDOW).min(pages);
+    let page_link = |number: u32| {
+        let active = number == page;
+        html! {
+            <li class={classes!("page-item", active.then_some("active"))}>
+                <a
+                    class="page-link"
+                    href="#"
+                    aria-current={active.then_some("page")}
+                    onclick={go_to(number)}
+                >
+                    {number.to_string()}
+                </a>
+            </li>
+        }
+    };
+    let ellipsis = || {
+        html! {
+            <li class="page-item disabled"><span class="page-link">{"…"}</span></li>
+        }
+    };
+
+    let shown_from = if props.total == 0 {
+        0
+    } else {
+        (page - 1) * props.per_page + 1
+    };
+    let shown_to = (page * props.per_page).min(props.total);
+
+    html! {
+        <div class="d-flex flex-wrap justify-content-between align-items-center gap-2">
+            <div class="d-flex align-items-center gap-2">
+                <small class="text-muted text-nowrap">
+                    {format!("Showing {}–{} of {}", shown_from, shown_to, props.total)}
+                </small>
+                <select
+                    class="form-select form-select-sm w-auto"
+                    aria-label="Items per page"
+                    onchange={on_per_page_change}
+                >
+                    { for props.page_sizes.iter().map(|size| html! {
+                        <option value={size.to_string()} selected={*size == props.per_page}>
+                            {format!("{} per page", size)}
+                        </option>
+                    }) }
+                </select>
+            </div>
+
+            <nav aria-label="Pages">
+                <ul class="pagination pagination-sm mb-0">
+                    <li class={classes!("page-item", (page == 1).then_some("disabled"))}>
+                        <a class="page-link" href="#" aria-label="Previous page" onclick={go_to(page.saturating_sub(1).max(1))}>
+                            <i class="bi bi-chevron-left"></i>
+                        </a>
+                    </li>
+                    if first > 1 {
+                        { page_link(1) }
+                        if first > 2 {
+                            { ellipsis() }
+                        }
+                    }
+                    { for (first..=last).map(page_link) }
+                    if last < pages {
+                        if last < pages - 1 {
+                            { ellipsis() }
+                        }
+                        { page_link(pages) }
+                    }
+                    <li class={classes!("page-item", (page == pages).then_some("disabled"))}>
+                        <a class="page-link" href="#" aria-label="Next page" onclick={go_to((page + 1).min(pages))}>
+                            <i class="bi bi-chevron-right"></i>
+                        </a>
+                    </li>
+                </ul>
+            </nav>
+
+            <form class="d-flex align-items-center gap-1" onsubmit={on_jump}>
+                <label class="visually-hidden" for="pagination-jump">{"Go to page"}</label>
+                <input
+                    id="pagination-jump"
+                    class="form-control form-control-sm"
+                    style="width: 5rem"
+                    type="number"
+                    min="1"
+                    max={pages.to_string()}
+                    placeholder={format!("1–{}", pages)}
+                    value={(*jump_to).clone()}
+                    oninput={on_jump_input}
+                />
+                <button type="submit" class="btn btn-sm btn-outline-secondary">{"Go"}</button>
+            </form>
+        </div>
+    }
+}
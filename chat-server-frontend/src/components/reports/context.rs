@@ -0,0 +1,128 @@
+use crate::components::messages::file_icon;
+use crate::models::{Message, MessageType, User};
+use crate::services::{FetchError, MessageService};
+use crate::store::use_store;
+use yew::prelude::*;
+
+/// Messages shown before and after a reported message
+const CONTEXT_RADIUS: i64 = 3;
+
+#[derive(Properties, PartialEq)]
+pub struct ReportContextProps {
+    pub workspace_id: i32,
+    /// The reported message
+    pub message: Message,
+}
+
+/// Name of a user, or their ID while the users are not loaded.
+pub(super) fn username(users: &[User], user_id: Option<i32>) -> String {
+    match user_id {
+        Some(user_id) => users
+            .iter()
+            .find(|u| u.id == user_id)
+            .map(|u| u.username.clone())
+            .unwrap_or_else(|| format!("User {}", user_id)),
+        None => "[deleted]".to_string(),
+    }
+}
+
+fn render_content(message: &Message, download_token: &str) -> Html {
+    match message.message_type {
+        MessageType::Text => html! { {message.content.clone().unwrap_or_default()} },
+        MessageType::Action => html! {
+            <span class="fst-italic">
+                {format!("* {}", message.content.clone().unwrap_or_default())}
+            </span>
+        },
+        MessageType::File | MessageType::Image => {
+            let name = message.file_name.clone().unwrap_or_default();
+            let icon = match message.message_type {
+                MessageType::Image => "bi-image",
+                _ => file_icon(&name),
+            };
+            html! {
+                <a
+                    href={MessageService::attachment_url(message.id, download_token)}
+                    target="_blank"
+                    rel="noopener"
+                    class="text-decoration-none"
+                >
+                    <i class={classes!("bi", icon, "me-1")}></i>
+                    {name}
+                </a>
+            }
+        }
+    }
+}
+
+/// A reported message among the messages sent just before and after it in
+/// its workspace. The reported message is shown on its own until the others
+/// are loaded, and if they cannot be.
+#[function_component(ReportContext)]
+pub fn report_context(props: &ReportContextProps) -> Html {
+    let store = use_store();
+    let download_token = store.download_token.clone().unwrap_or_default();
+    let context = use_state(|| None::<Vec<Message>>);
+    let error = use_state(|| None::<String>);
+
+    {
+        let context = context.clone();
+        let error = error.clone();
+        use_effect_with(
+            (props.workspace_id, props.message.seq),
+            move |(workspace_id, seq)| {
+                MessageService::fetch_context(
+                    *workspace_id,
+                    *seq,
+                    CONTEXT_RADIUS,
+                    Callback::from(
+                        move |result: Result<Vec<Message>, FetchError>| match result {
+                            Ok(messages) => context.set(Some(messages)),
+                            Err(e) => error.set(Some(e.to_string())),
+                        },
+                    ),
+                );
+                || ()
+            },
+        );
+    }
+
+    let users = store.users.clone().unwrap_or_default();
+    let render_message = |message: &Message| -> Html {
+        let reported = message.id == props.message.id;
+        html! {
+            <div
+                key={message.id.to_string()}
+                class={classes!(
+                    "d-flex", "gap-2", "px-2", "py-1", "rounded",
+                    if reported { "bg-warning-subtle fw-semibold" } else { "text-muted" },
+                )}
+            >
+                <span class="text-nowrap">{username(&users, Some(message.sender_id))}</span>
+                <span class="flex-grow-1 text-break">{render_content(message, &download_token)}</span>
+                <small class="text-nowrap" title={message.created_at.clone()}>
+                    {message.created_at.replace('T', " ").chars().take(16).collect::<String>()}
+                </small>
+            </div>
+        }
+    };
+
+    let messages = match context.as_ref() {
+        Some(messages) if messages.iter().any(|m| m.id == props.message.id) => {
+            messages.iter().map(render_message).collect::<Html>()
+        }
+        _ => render_message(&props.message),
+    };
+
+    html! {
+        <div class="border-start border-3 ps-2 mb-2">
+            {messages}
+            if let Some(err) = error.as_ref() {
+                <small class="text-muted">
+                    <i class="bi bi-exclamation-triangle me-1"></i>
+                    {format!("Surrounding messages unavailable: {}", err)}
+                </small>
+            }
+        </div>
+    }
+}
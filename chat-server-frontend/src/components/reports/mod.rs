@@ -0,0 +1,4 @@
+mod context;
+mod queue;
+
+pub use queue::ReportQueue;
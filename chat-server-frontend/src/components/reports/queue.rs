@@ -0,0 +1,310 @@
+use super::context::{username, ReportContext};
+use crate::components::modal::ConfirmModal;
+use crate::models::{Report, ReportStatus, User};
+use crate::services::{FetchError, ReportService, UserService};
+use crate::store::{use_store, StoreAction};
+use gloo_dialogs;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+/// Resolutions offered for open reports, with their button label, style and
+/// confirmation question
+const RESOLUTIONS: [(ReportStatus, &str, &str, Option<&str>); 4] = [
+    (
+        ReportStatus::Dismissed,
+        "Dismiss",
+        "btn-outline-secondary",
+        None,
+    ),
+    (
+        ReportStatus::Deleted,
+        "Delete message",
+        "btn-outline-danger",
+        Some("Delete the reported message?"),
+    ),
+    (
+        ReportStatus::Warned,
+        "Warn sender",
+        "btn-outline-warning",
+        None,
+    ),
+    (
+        ReportStatus::Banned,
+        "Ban sender",
+        "btn-danger",
+        Some("Remove the sender from the workspace?"),
+    ),
+];
+
+fn status_badge(status: ReportStatus) -> Html {
+    let class = match status {
+        ReportStatus::Open => "text-bg-warning",
+        ReportStatus::Dismissed => "text-bg-secondary",
+        ReportStatus::Deleted | ReportStatus::Banned => "text-bg-danger",
+        ReportStatus::Warned => "text-bg-info",
+    };
+    html! { <span class={classes!("badge", class)}>{status.as_str()}</span> }
+}
+
+#[function_component(ReportQueue)]
+pub fn report_queue() -> Html {
+    let store = use_store();
+    let reports = use_state(Vec::<Report>::new);
+    let error = use_state(|| None::<String>);
+    let loading = use_state(|| true);
+    // Only open reports are shown at first
+    let selected_status = use_state(|| Some(ReportStatus::Open));
+    // Resolution waiting for confirmation, with its label and question
+    let pending_resolution = use_state(|| None::<(i32, ReportStatus, &'static str, &'static str)>);
+
+    let fetch_reports = {
+        let reports = reports.clone();
+        let error = error.clone();
+        let loading = loading.clone();
+
+        Callback::from(move |status: Option<ReportStatus>| {
+            loading.set(true);
+            error.set(None);
+
+            let callback = {
+                let reports = reports.clone();
+                let error = error.clone();
+                let loading = loading.clone();
+
+                Callback::from(move |result: Result<Vec<Report>, FetchError>| {
+                    match result {
+                        Ok(data) => reports.set(data),
+                        Err(FetchError::Status(403)) => error.set(Some(
+                            "Only workspace moderators can review reports".to_string(),
+                        )),
+                        Err(e) => error.set(Some(e.to_string())),
+                    }
+                    loading.set(false);
+                })
+            };
+
+            ReportService::fetch_reports(status, callback);
+        })
+    };
+
+    let resolve_report = {
+        let fetch_reports = fetch_reports.clone();
+        let selected_status = selected_status.clone();
+        let reports = reports.clone();
+        let store = store.clone();
+
+        Callback::from(move |(report_id, status): (i32, ReportStatus)| {
+            let message_id = reports
+                .iter()
+                .find(|report| report.id == report_id)
+                .and_then(|report| report.message_id);
+            let callback = {
+                let fetch_reports = fetch_reports.clone();
+                let selected_status = selected_status.clone();
+                let store = store.clone();
+
+                Callback::from(move |result: Result<(), FetchError>| match result {
+                    Ok(_) => {
+                        // Keep the cached messages list in step with the deletion
+                        if let (ReportStatus::Deleted, Some(message_id)) = (status, message_id) {
+                            store.dispatch(StoreAction::MessageRemoved(message_id));
+                        }
+                        fetch_reports.emit(*selected_status);
+                    }
+                    Err(e) => gloo_dialogs::alert(&format!("Failed to resolve the report: {}", e)),
+                })
+            };
+
+            ReportService::resolve_report(report_id, status, callback);
+        })
+    };
+
+    let on_confirm_resolution = {
+        let pending_resolution = pending_resolution.clone();
+        let resolve_report = resolve_report.clone();
+        Callback::from(move |_| {
+            if let Some((report_id, status, _, _)) = *pending_resolution {
+                resolve_report.emit((report_id, status));
+            }
+            pending_resolution.set(None);
+        })
+    };
+
+    let on_cancel_resolution = {
+        let pending_resolution = pending_resolution.clone();
+        Callback::from(move |_| pending_resolution.set(None))
+    };
+
+    let on_status_filter_change = {
+        let selected_status = selected_status.clone();
+        let fetch_reports = fetch_reports.clone();
+
+        Callback::from(move |e: Event| {
+            if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                let status = match select.value().as_str() {
+                    "open" => Some(ReportStatus::Open),
+                    "dismissed" => Some(ReportStatus::Dismissed),
+                    "deleted" => Some(ReportStatus::Deleted),
+                    "warned" => Some(ReportStatus::Warned),
+                    "banned" => Some(ReportStatus::Banned),
+                    _ => None,
+                };
+                selected_status.set(status);
+                fetch_reports.emit(status);
+            }
+        })
+    };
+
+    {
+        let fetch_reports = fetch_reports.clone();
+        let store = store.clone();
+
+        use_effect_with((), move |_| {
+            fetch_reports.emit(Some(ReportStatus::Open));
+            UserService::fetch_users(Callback::from(
+                move |result: Result<Vec<User>, FetchError>| {
+                    // Without the users, IDs are shown instead of names
+                    if let Ok(data) = result {
+                        store.dispatch(StoreAction::UsersLoaded(data));
+                    }
+                },
+            ));
+            || ()
+        });
+    }
+
+    let users = store.users.clone().unwrap_or_default();
+    let get_username = |user_id: Option<i32>| username(&users, user_id);
+
+    let render_report = |report: &Report| -> Html {
+        let message = match &report.message {
+            Some(message) => html! {
+                <ReportContext workspace_id={report.workspace_id} message={message.clone()} />
+            },
+            None => html! {
+                <blockquote class="border-start ps-3 mb-2 text-muted">{"[message deleted]"}</blockquote>
+            },
+        };
+        let actions = if report.status == ReportStatus::Open {
+            RESOLUTIONS
+                .iter()
+                .map(|(status, label, class, confirmation)| {
+                    let resolve_report = resolve_report.clone();
+                    let pending_resolution = pending_resolution.clone();
+                    let (report_id, status, label, confirmation) =
+                        (report.id, *status, *label, *confirmation);
+                    let onclick = Callback::from(move |_| match confirmation {
+                        Some(question) => {
+                            pending_resolution.set(Some((report_id, status, label, question)))
+                        }
+                        None => resolve_report.emit((report_id, status)),
+                    });
+                    html! {
+                        <button class={classes!("btn", "btn-sm", *class)} {onclick}>{label}</button>
+                    }
+                })
+                .collect::<Html>()
+        } else {
+            html! {
+                <small class="text-muted">
+                    {format!(
+                        "Resolved by {} on {}",
+                        get_username(report.resolved_by),
+                        report.resolved_at.as_deref().unwrap_or_default().replace('T', " "),
+                    )}
+                </small>
+            }
+        };
+
+        html! {
+            <div class="list-group-item p-3" key={report.id.to_string()}>
+                <div class="d-flex justify-content-between align-items-center mb-2">
+                    <h5 class="mb-0">
+                        <span class="text-primary me-2">{get_username(report.reported_user_id)}</span>
+                        {status_badge(report.status)}
+                    </h5>
+                    <small class="text-muted">
+                        <i class="bi bi-clock me-1"></i>
+                        {report.created_at.split('T').next().unwrap_or(&report.created_at)}
+                    </small>
+                </div>
+                {message}
+                <p class="mb-2">
+                    <i class="bi bi-flag me-1"></i>
+                    {format!("Reported by {}: {}", get_username(report.reporter_id), report.reason)}
+                </p>
+                <div class="d-flex gap-2">{actions}</div>
+            </div>
+        }
+    };
+
+    html! {
+        <div class="container py-4">
+            if let Some((_, _, label, question)) = *pending_resolution {
+                <ConfirmModal
+                    title={label}
+                    message={question}
+                    confirm_label={label}
+                    destructive=true
+                    on_confirm={on_confirm_resolution}
+                    on_cancel={on_cancel_resolution}
+                />
+            }
+
+            <div class="card shadow-sm">
+                <div class="card-header text-bg-primary d-flex justify-content-between align-items-center">
+                    <h3 class="mb-0">{"Reports"}</h3>
+                    <span class="badge bg-light text-primary">{format!("Total: {}", reports.len())}</span>
+                </div>
+
+                <div class="card-body">
+                    <div class="row mb-4">
+                        <div class="col-md-6">
+                            <label for="statusFilter" class="form-label">{"Filter by Status"}</label>
+                            <select id="statusFilter" class="form-select" onchange={on_status_filter_change}>
+                                <option value="open" selected=true>{"Open"}</option>
+                                <option value="dismissed">{"Dismissed"}</option>
+                                <option value="deleted">{"Deleted"}</option>
+                                <option value="warned">{"Warned"}</option>
+                                <option value="banned">{"Banned"}</option>
+                                <option value="all">{"All Reports"}</option>
+                            </select>
+                        </div>
+                    </div>
+
+                    {
+                        if *loading {
+                            html! {
+                                <div class="d-flex justify-content-center p-4">
+                                    <div class="spinner-border text-primary" role="status">
+                                        <span class="visually-hidden">{"Loading..."}</span>
+                                    </div>
+                                </div>
+                            }
+                        } else if let Some(err) = error.as_ref() {
+                            html! {
+                                <div class="alert alert-danger" role="alert">
+                                    <i class="bi bi-exclamation-triangle me-2"></i>
+                                    {"Error loading reports: "}{err}
+                                </div>
+                            }
+                        } else if reports.is_empty() {
+                            html! {
+                                <div class="alert alert-info" role="alert">
+                                    <i class="bi bi-info-circle me-2"></i>
+                                    {"No reports found with the selected filter."}
+                                </div>
+                            }
+                        } else {
+                            html! {
+                                <div class="list-group list-group-flush">
+                                    { reports.iter().map(render_report).collect::<Html>() }
+                                </div>
+                            }
+                        }
+                    }
+                </div>
+            </div>
+        </div>
+    }
+}
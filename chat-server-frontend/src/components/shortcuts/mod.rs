@@ -0,0 +1,320 @@
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlElement, HtmlInputElement};
+use yew::prelude::*;
+use yew_hooks::use_event_with_window;
+use yew_router::prelude::*;
+
+use crate::components::modal::use_modal_focus;
+use crate::components::theme::ThemeContext;
+use crate::routes::AppRoute;
+
+/// How long after `g` the second key of a go-to shortcut may follow
+const GO_TO_TIMEOUT_MS: f64 = 1500.0;
+
+/// What a command does when it is run.
+#[derive(Clone, PartialEq)]
+enum Action {
+    Go(AppRoute),
+    SwitchTheme,
+}
+
+/// An action of the command palette.
+#[derive(Clone, PartialEq)]
+struct Command {
+    label: &'static str,
+    icon: &'static str,
+    /// Key pressed after `g` to run the command without the palette
+    go_key: Option<char>,
+    action: Action,
+}
+
+fn commands() -> Vec<Command> {
+    vec![
+        Command {
+            label: "Go to Home",
+            icon: "bi-house-door",
+            go_key: Some('h'),
+            action: Action::Go(AppRoute::Home),
+        },
+        Command {
+            label: "Go to Users",
+            icon: "bi-people",
+            go_key: Some('u'),
+            action: Action::Go(AppRoute::Users),
+        },
+        Command {
+            label: "Go to Messages",
+            icon: "bi-chat-dots",
+            go_key: Some('m'),
+            action: Action::Go(AppRoute::Messages),
+        },
+        Command {
+            label: "Go to Moderation",
+            icon: "bi-flag",
+            go_key: Some('r'),
+            action: Action::Go(AppRoute::Moderation),
+        },
+        Command {
+            label: "Switch theme",
+            icon: "bi-circle-half",
+            go_key: None,
+            action: Action::SwitchTheme,
+        },
+    ]
+}
+
+/// Returns the commands whose label contains every word of `query`.
+fn matching(commands: &[Command], query: &str) -> Vec<Command> {
+    let query = query.to_lowercase();
+    commands
+        .iter()
+        .filter(|command| {
+            let label = command.label.to_lowercase();
+            query.split_whitespace().all(|word| label.contains(word))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Returns whether a key press goes to a field being typed in.
+fn is_typing(e: &KeyboardEvent) -> bool {
+    e.target()
+        .and_then(|target| target.dyn_into::<HtmlElement>().ok())
+        .is_some_and(|element| {
+            matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+                || element.is_content_editable()
+        })
+}
+
+/// Keyboard shortcuts of the whole admin UI and the command palette they open.
+///
+/// `/` or Ctrl+K (⌘K on macOS) opens the palette, and `g` followed by a
+/// command's key goes to its page, such as `g u` to the users. Shortcuts are
+/// ignored while typing in a field.
+#[function_component(Shortcuts)]
+pub fn shortcuts() -> Html {
+    let navigator = use_navigator().unwrap();
+    let theme = use_context::<ThemeContext>();
+    let palette_open = use_state(|| false);
+    // When `g` was pressed, while a go-to shortcut waits for its second key
+    let go_pressed_at = use_mut_ref(|| None::<f64>);
+
+    let run = {
+        let palette_open = palette_open.clone();
+        Callback::from(move |action: Action| {
+            palette_open.set(false);
+            match action {
+                Action::Go(route) => navigator.push(&route),
+                Action::SwitchTheme => {
+                    if let Some(theme) = &theme {
+                        theme.set_theme.emit(theme.theme.next());
+                    }
+                }
+            }
+        })
+    };
+
+    {
+        let palette_open = palette_open.clone();
+        let run = run.clone();
+        use_event_with_window("keydown", move |e: KeyboardEvent| {
+            if *palette_open || e.alt_key() || is_typing(&e) {
+                return;
+            }
+            if (e.ctrl_key() || e.meta_key()) && e.key().eq_ignore_ascii_case("k") {
+                e.prevent_default();
+                palette_open.set(true);
+                return;
+            }
+            if e.ctrl_key() || e.meta_key() {
+                return;
+            }
+
+            let now = js_sys::Date::now();
+            let go_pending = go_pressed_at
+                .borrow_mut()
+                .take()
+                .is_some_and(|pressed_at| now - pressed_at < GO_TO_TIMEOUT_MS);
+            let key = e.key();
+            if go_pending {
+                let command = commands().into_iter().find(|command| {
+                    command
+                        .go_key
+                        .is_some_and(|go_key| key.eq_ignore_ascii_case(&go_key.to_string()))
+                });
+                if let Some(command) = command {
+                    e.prevent_default();
+                    run.emit(command.action);
+                }
+                return;
+            }
+            match key.as_str() {
+                "/" => {
+                    e.prevent_default();
+                    palette_open.set(true);
+                }
+                "g" => *go_pressed_at.borrow_mut() = Some(now),
+                _ => {}
+            }
+        });
+    }
+
+    let on_close = {
+        let palette_open = palette_open.clone();
+        Callback::from(move |_| palette_open.set(false))
+    };
+
+    html! {
+        <>
+            if *palette_open {
+                <CommandPalette {run} {on_close} />
+            }
+        </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct CommandPaletteProps {
+    run: Callback<Action>,
+    on_close: Callback<()>,
+}
+
+/// Modal listing the commands, filtered by what is typed into its search
+/// field. Arrow keys choose a command, Enter runs it and Escape or clicking
+/// outside the dialog closes it.
+#[function_component(CommandPalette)]
+fn command_palette(props: &CommandPaletteProps) -> Html {
+    let search_ref = use_node_ref();
+    use_modal_focus(search_ref.clone());
+    let query = use_state(String::new);
+    let selected = use_state(|| 0usize);
+
+    let commands = matching(&commands(), &query);
+
+    let on_input = {
+        let query = query.clone();
+        let selected = selected.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                query.set(input.value());
+                selected.set(0);
+            }
+        })
+    };
+
+    let onkeydown = {
+        let selected = selected.clone();
+        let commands = commands.clone();
+        let run = props.run.clone();
+        let on_close = props.on_close.clone();
+
+        Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "Escape" => {
+                e.prevent_default();
+                on_close.emit(());
+            }
+            "ArrowDown" if !commands.is_empty() => {
+                e.prevent_default();
+                selected.set((*selected + 1) % commands.len());
+            }
+            "ArrowUp" if !commands.is_empty() => {
+                e.prevent_default();
+                selected.set((*selected + commands.len() - 1) % commands.len());
+            }
+            "Enter" => {
+                e.prevent_default();
+                if let Some(command) = commands.get(*selected) {
+                    run.emit(command.action.clone());
+                }
+            }
+            // The search field keeps the focus
+            "Tab" => e.prevent_default(),
+            _ => {}
+        })
+    };
+
+    let on_backdrop_click = {
+        let on_close = props.on_close.clone();
+        Callback::from(move |e: MouseEvent| {
+            if e.target() == e.current_target() {
+                on_close.emit(());
+            }
+        })
+    };
+
+    let items = commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| {
+            let run = props.run.clone();
+            let action = command.action.clone();
+            let onclick = Callback::from(move |_| run.emit(action.clone()));
+            html! {
+                <button
+                    type="button"
+                    key={command.label}
+                    class={classes!(
+                        "list-group-item", "list-group-item-action", "d-flex", "align-items-center",
+                        (index == *selected).then_some("active"),
+                    )}
+                    tabindex="-1"
+                    {onclick}
+                >
+                    <i class={classes!("bi", command.icon, "me-2")}></i>
+                    <span class="flex-grow-1 text-start">{command.label}</span>
+                    if let Some(go_key) = command.go_key {
+                        <span>
+                            <kbd>{"g"}</kbd>{" "}<kbd>{go_key.to_string()}</kbd>
+                        </span>
+                    }
+                </button>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <>
+            <div
+                class="modal d-block"
+                tabindex="-1"
+                role="dialog"
+                aria-modal="true"
+                aria-label="Command palette"
+                onclick={on_backdrop_click}
+                {onkeydown}
+            >
+                <div class="modal-dialog">
+                    <div class="modal-content">
+                        <div class="modal-header">
+                            <input
+                                type="search"
+                                class="form-control"
+                                placeholder="Type a command"
+                                aria-label="Search commands"
+                                ref={search_ref}
+                                value={(*query).clone()}
+                                oninput={on_input}
+                            />
+                        </div>
+                        <div class="list-group list-group-flush">
+                            if commands.is_empty() {
+                                <div class="list-group-item text-muted">{"No matching commands"}</div>
+                            } else {
+                                {items}
+                            }
+                        </div>
+                        <div class="modal-footer justify-content-start">
+                            <small class="text-muted">
+                                <kbd>{"/"}</kbd>{" or "}<kbd>{"Ctrl K"}</kbd>{" opens this palette, "}
+                                <kbd>{"↑"}</kbd><kbd>{"↓"}</kbd>{" choose, "}
+                                <kbd>{"Enter"}</kbd>{" runs, "}
+                                <kbd>{"Esc"}</kbd>{" closes"}
+                            </small>
+                        </div>
+                    </div>
+                </div>
+            </div>
+            <div class="modal-backdrop show"></div>
+        </>
+    }
+}
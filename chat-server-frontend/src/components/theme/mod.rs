@@ -0,0 +1,158 @@
+use gloo_storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::MediaQueryList;
+use yew::prelude::*;
+
+/// LocalStorage key the chosen theme is persisted under
+const THEME_KEY: &str = "theme";
+
+/// Media query matching browsers set to a dark color scheme
+const DARK_SCHEME_QUERY: &str = "(prefers-color-scheme: dark)";
+
+/// Color theme of the admin UI.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    /// Follows the browser's color scheme
+    #[default]
+    System,
+}
+
+impl Theme {
+    /// Returns the theme the navbar toggle switches to from this one.
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::System,
+            Theme::System => Theme::Light,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+            Theme::System => "System",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Theme::Light => "bi bi-sun",
+            Theme::Dark => "bi bi-moon-stars",
+            Theme::System => "bi bi-circle-half",
+        }
+    }
+
+    /// Returns the Bootstrap color mode to render, resolving `System` with
+    /// the browser's preference.
+    fn color_mode(self) -> &'static str {
+        let dark = match self {
+            Theme::Light => false,
+            Theme::Dark => true,
+            Theme::System => dark_scheme_query().is_some_and(|query| query.matches()),
+        };
+        if dark {
+            "dark"
+        } else {
+            "light"
+        }
+    }
+}
+
+/// The current theme and a callback to change it, provided by [`ThemeProvider`].
+#[derive(Clone, PartialEq)]
+pub struct ThemeContext {
+    pub theme: Theme,
+    pub set_theme: Callback<Theme>,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ThemeProviderProps {
+    pub children: Children,
+}
+
+/// Applies the theme persisted in LocalStorage to the page and provides it to
+/// its children.
+///
+/// Bootstrap styles the whole page from the `data-bs-theme` attribute of the
+/// root element, so components only need theme-aware classes such as
+/// `text-bg-warning` instead of fixed colors.
+#[function_component(ThemeProvider)]
+pub fn theme_provider(props: &ThemeProviderProps) -> Html {
+    let theme = use_state(|| LocalStorage::get::<Theme>(THEME_KEY).unwrap_or_default());
+
+    use_effect_with(*theme, |theme| {
+        let theme = *theme;
+        apply(theme);
+
+        // Follow the browser when its color scheme changes
+        let listener = (theme == Theme::System)
+            .then(dark_scheme_query)
+            .flatten()
+            .map(|query| {
+                let on_change = Closure::<dyn Fn()>::new(move || apply(theme));
+                query.set_onchange(Some(on_change.as_ref().unchecked_ref()));
+                (query, on_change)
+            });
+        move || {
+            if let Some((query, _on_change)) = listener {
+                query.set_onchange(None);
+            }
+        }
+    });
+
+    let set_theme = {
+        let theme = theme.clone();
+        Callback::from(move |new_theme: Theme| {
+            let _ = LocalStorage::set(THEME_KEY, new_theme);
+            theme.set(new_theme);
+        })
+    };
+    let context = ThemeContext {
+        theme: *theme,
+        set_theme,
+    };
+
+    html! {
+        <ContextProvider<ThemeContext> {context}>
+            { for props.children.iter() }
+        </ContextProvider<ThemeContext>>
+    }
+}
+
+/// Navbar button cycling through the light, dark and system themes.
+#[function_component(ThemeToggle)]
+pub fn theme_toggle() -> Html {
+    let context = use_context::<ThemeContext>().expect("ThemeToggle outside ThemeProvider");
+    let theme = context.theme;
+    let onclick = Callback::from(move |_| context.set_theme.emit(theme.next()));
+
+    html! {
+        <button
+            class="btn btn-outline-light me-2"
+            title={format!("Theme: {} (switch to {})", theme.label(), theme.next().label())}
+            {onclick}
+        >
+            <i class={theme.icon()}></i>
+        </button>
+    }
+}
+
+/// Sets the color mode Bootstrap renders the page in.
+fn apply(theme: Theme) {
+    if let Some(root) = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.document_element())
+    {
+        let _ = root.set_attribute("data-bs-theme", theme.color_mode());
+    }
+}
+
+fn dark_scheme_query() -> Option<MediaQueryList> {
+    web_sys::window()?.match_media(DARK_SCHEME_QUERY).ok()?
+}
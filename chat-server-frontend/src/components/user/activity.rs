@@ -0,0 +1,379 @@
+use crate::models::{User, UserConnection, UserSession, UserStats, UserUsage};
+use crate::services::{FetchError, UserService};
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+/// Periods the message chart can cover, in days
+const PERIODS: [u32; 3] = [7, 30, 90];
+
+#[derive(Properties, PartialEq)]
+pub struct UserActivityProps {
+    pub user_id: i32,
+}
+
+/// Shortens a server timestamp like `2024-05-01T12:30:45.123` to `2024-05-01 12:30:45`.
+fn format_time(timestamp: &str) -> String {
+    timestamp.replace('T', " ").chars().take(19).collect()
+}
+
+/// Formats a byte count in MiB with one decimal, e.g. `12.5 MiB`.
+fn format_mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[function_component(UserActivity)]
+pub fn user_activity(props: &UserActivityProps) -> Html {
+    let user = use_state(|| None::<User>);
+    let stats = use_state(|| None::<UserStats>);
+    let sessions = use_state(Vec::<UserSession>::new);
+    let connections = use_state(Vec::<UserConnection>::new);
+    let usage = use_state(|| None::<UserUsage>);
+    let error = use_state(|| None::<String>);
+    let days = use_state(|| 30u32);
+
+    // Function to fetch everything the panel shows
+    let fetch_activity = {
+        let user = user.clone();
+        let stats = stats.clone();
+        let sessions = sessions.clone();
+        let connections = connections.clone();
+        let usage = usage.clone();
+        let error = error.clone();
+        let user_id = props.user_id;
+
+        Callback::from(move |days: u32| {
+            error.set(None);
+            stats.set(None);
+
+            let on_error = {
+                let error = error.clone();
+                move |e: FetchError| {
+                    let message = match e {
+                        FetchError::Status(403) => {
+                            "Only server admins can view the activity of other users".to_string()
+                        }
+                        e => e.to_string(),
+                    };
+                    error.set(Some(message));
+                }
+            };
+
+            {
+                let user = user.clone();
+                let on_error = on_error.clone();
+                UserService::fetch_user(
+                    user_id,
+                    Callback::from(move |result| match result {
+                        Ok(data) => user.set(Some(data)),
+                        Err(e) => on_error(e),
+                    }),
+                );
+            }
+            {
+                let stats = stats.clone();
+                let on_error = on_error.clone();
+                UserService::fetch_user_stats(
+                    user_id,
+                    days,
+                    Callback::from(move |result| match result {
+                        Ok(data) => stats.set(Some(data)),
+                        Err(e) => on_error(e),
+                    }),
+                );
+            }
+            {
+                let sessions = sessions.clone();
+                let on_error = on_error.clone();
+                UserService::fetch_user_sessions(
+                    user_id,
+                    Callback::from(move |result| match result {
+                        Ok(data) => sessions.set(data),
+                        Err(e) => on_error(e),
+                    }),
+                );
+            }
+            {
+                let usage = usage.clone();
+                let on_error = on_error.clone();
+                UserService::fetch_user_usage(
+                    user_id,
+                    Callback::from(move |result| match result {
+                        Ok(data) => usage.set(Some(data)),
+                        Err(e) => on_error(e),
+                    }),
+                );
+            }
+            {
+                let connections = connections.clone();
+                UserService::fetch_user_connections(
+                    user_id,
+                    Callback::from(move |result| match result {
+                        Ok(data) => connections.set(data),
+                        Err(e) => on_error(e),
+                    }),
+                );
+            }
+        })
+    };
+
+    // Fetch data when the user or the period changes
+    {
+        let fetch_activity = fetch_activity.clone();
+        use_effect_with((props.user_id, *days), move |(_, days)| {
+            fetch_activity.emit(*days);
+            || () // Cleanup function
+        });
+    }
+
+    let on_refresh = {
+        let fetch_activity = fetch_activity.clone();
+        let days = days.clone();
+        Callback::from(move |_| fetch_activity.emit(*days))
+    };
+
+    let on_period_change = {
+        let days = days.clone();
+        Callback::from(move |e: Event| {
+            if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                if let Ok(value) = select.value().parse() {
+                    days.set(value);
+                }
+            }
+        })
+    };
+
+    // Online while connected, otherwise the latest login or message
+    let last_seen = if !connections.is_empty() {
+        html! { <span class="badge text-bg-success">{"Online now"}</span> }
+    } else {
+        let last_message = stats
+            .as_ref()
+            .and_then(|stats| stats.last_message_at.clone());
+        let last_login = sessions
+            .iter()
+            .map(|session| session.logged_in_at.clone())
+            .max();
+        match last_message.into_iter().chain(last_login).max() {
+            Some(time) => html! { <span>{format_time(&time)}</span> },
+            None => html! { <span class="text-muted">{"Never"}</span> },
+        }
+    };
+
+    let stat_card = |label: &str, value: Html, icon: &str| -> Html {
+        html! {
+            <div class="col-md-3">
+                <div class="card h-100 text-center">
+                    <div class="card-body">
+                        <i class={classes!("bi", icon.to_string(), "fs-3", "text-primary")}></i>
+                        <h4 class="mt-2 mb-0">{value}</h4>
+                        <small class="text-muted">{label.to_string()}</small>
+                    </div>
+                </div>
+            </div>
+        }
+    };
+
+    let render_usage = |usage: &UserUsage| -> Html {
+        match usage.quota_bytes {
+            Some(quota) => {
+                let percent = (usage.attachment_bytes * 100 / quota.max(1)).min(100);
+                let bar = if percent >= 90 {
+                    "bg-danger"
+                } else {
+                    "bg-primary"
+                };
+                html! {
+                    <>
+                        <div class="progress mb-1" style="height: 1rem;">
+                            <div
+                                class={classes!("progress-bar", bar)}
+                                role="progressbar"
+                                style={format!("width: {}%;", percent)}
+                            ></div>
+                        </div>
+                        <small class="text-muted">
+                            {format!("{} of {} used", format_mib(usage.attachment_bytes), format_mib(quota))}
+                        </small>
+                    </>
+                }
+            }
+            None => html! {
+                <small class="text-muted">
+                    {format!("{} used, no quota", format_mib(usage.attachment_bytes))}
+                </small>
+            },
+        }
+    };
+
+    let render_chart = |stats: &UserStats| -> Html {
+        let peak = stats
+            .daily
+            .iter()
+            .map(|day| day.count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        html! {
+            <div class="d-flex align-items-end gap-1 border-bottom" style="height: 160px;">
+                {
+                    stats.daily.iter().map(|day| {
+                        let height = day.count * 100 / peak;
+                        html! {
+                            <div
+                                class="flex-fill bg-primary rounded-top"
+                                style={format!("height: {}%; min-height: 1px;", height)}
+                                title={format!("{}: {} messages", day.day, day.count)}
+                            ></div>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        }
+    };
+
+    html! {
+        <div class="card shadow-sm">
+            <div class="card-header text-bg-primary d-flex justify-content-between align-items-center">
+                <h3 class="mb-0">
+                    {user.as_ref().map(|user| user.username.clone()).unwrap_or_else(|| format!("User {}", props.user_id))}
+                </h3>
+                <button class="btn btn-sm btn-light" onclick={on_refresh} title="Reload activity">
+                    <i class="bi bi-arrow-clockwise me-1"></i>
+                    {"Refresh"}
+                </button>
+            </div>
+
+            <div class="card-body">
+                {
+                    if let Some(err) = error.as_ref() {
+                        html! {
+                            <div class="alert alert-danger" role="alert">
+                                <i class="bi bi-exclamation-triangle me-2"></i>
+                                {"Error loading activity: "}{err}
+                            </div>
+                        }
+                    } else if let Some(stats) = stats.as_ref() {
+                        html! {
+                            <>
+                                <div class="row g-3 mb-4">
+                                    {stat_card("Last seen", last_seen, "bi-clock-history")}
+                                    {stat_card("Messages sent", html! { {stats.total_messages} }, "bi-chat-dots")}
+                                    {stat_card(
+                                        "Attachments sent",
+                                        html! {
+                                            <>
+                                                {stats.attachments_sent}
+                                                <small class="d-block fs-6 text-muted">
+                                                    {format!("{} files, {} images", stats.files_sent, stats.images_sent)}
+                                                </small>
+                                            </>
+                                        },
+                                        "bi-paperclip",
+                                    )}
+                                    {stat_card("Active sessions", html! { {sessions.len() + connections.len()} }, "bi-pc-display")}
+                                </div>
+
+                                {
+                                    usage.as_ref().map(|usage| html! {
+                                        <div class="mb-4">
+                                            <h5>{"Attachment storage"}</h5>
+                                            {render_usage(usage)}
+                                        </div>
+                                    }).unwrap_or_default()
+                                }
+
+                                <div class="d-flex justify-content-between align-items-center mb-2">
+                                    <h5 class="mb-0">{"Messages per day"}</h5>
+                                    <select class="form-select form-select-sm w-auto" onchange={on_period_change}>
+                                        {
+                                            PERIODS.iter().map(|period| html! {
+                                                <option value={period.to_string()} selected={*period == *days}>
+                                                    {format!("Last {} days", period)}
+                                                </option>
+                                            }).collect::<Html>()
+                                        }
+                                    </select>
+                                </div>
+                                {render_chart(stats)}
+                                <div class="d-flex justify-content-between text-muted small mb-4">
+                                    <span>{stats.daily.first().map(|day| day.day.clone()).unwrap_or_default()}</span>
+                                    <span>{stats.daily.last().map(|day| day.day.clone()).unwrap_or_default()}</span>
+                                </div>
+
+                                <h5>{"Chat connections"}</h5>
+                                {
+                                    if connections.is_empty() {
+                                        html! { <p class="text-muted">{"Not connected."}</p> }
+                                    } else {
+                                        html! {
+                                            <table class="table table-sm mb-4">
+                                                <thead>
+                                                    <tr>
+                                                        <th>{"Client"}</th>
+                                                        <th>{"Address"}</th>
+                                                        <th>{"Workspace"}</th>
+                                                        <th>{"Connected"}</th>
+                                                    </tr>
+                                                </thead>
+                                                <tbody>
+                                                    {
+                                                        connections.iter().map(|connection| html! {
+                                                            <tr key={connection.client_id.to_string()}>
+                                                                <td>{connection.client_id}</td>
+                                                                <td>{&connection.peer_addr}</td>
+                                                                <td>{connection.workspace_id.map(|id| id.to_string()).unwrap_or_default()}</td>
+                                                                <td>{format_time(&connection.connected_at)}</td>
+                                                            </tr>
+                                                        }).collect::<Html>()
+                                                    }
+                                                </tbody>
+                                            </table>
+                                        }
+                                    }
+                                }
+
+                                <h5>{"Web sessions"}</h5>
+                                {
+                                    if sessions.is_empty() {
+                                        html! { <p class="text-muted mb-0">{"No active sessions."}</p> }
+                                    } else {
+                                        html! {
+                                            <table class="table table-sm mb-0">
+                                                <thead>
+                                                    <tr>
+                                                        <th>{"Token"}</th>
+                                                        <th>{"Logged in"}</th>
+                                                        <th>{"Expires"}</th>
+                                                    </tr>
+                                                </thead>
+                                                <tbody>
+                                                    {
+                                                        sessions.iter().map(|session| html! {
+                                                            <tr key={session.token_prefix.clone()}>
+                                                                <td><code>{format!("{}…", session.token_prefix)}</code></td>
+                                                                <td>{format_time(&session.logged_in_at)}</td>
+                                                                <td>{format_time(&session.expires_at)}</td>
+                                                            </tr>
+                                                        }).collect::<Html>()
+                                                    }
+                                                </tbody>
+                                            </table>
+                                        }
+                                    }
+                                }
+                            </>
+                        }
+                    } else {
+                        html! {
+                            <div class="d-flex justify-content-center p-4">
+                                <div class="spinner-border text-primary" role="status">
+                                    <span class="visually-hidden">{"Loading..."}</span>
+                                </div>
+                            </div>
+                        }
+                    }
+                }
+            </div>
+        </div>
+    }
+}
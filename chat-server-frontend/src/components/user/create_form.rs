@@ -1,5 +1,7 @@
-use crate::models::NewUser;
+use crate::components::user::PasswordFields;
+use crate::models::{NewUser, User};
 use crate::services::{FetchError, UserService};
+use crate::store::{use_store, StoreAction};
 use web_sys::HtmlInputElement;
 use yew::prelude::*;
 
@@ -10,10 +12,13 @@ pub struct CreateUserFormProps {
 
 #[function_component(CreateUserForm)]
 pub fn create_user_form(props: &CreateUserFormProps) -> Html {
+    let store = use_store();
     let new_user = use_state(NewUser::default);
     let submitting = use_state(|| false);
     let error = use_state(|| None::<String>);
     let success = use_state(|| false);
+    // Whether the server accepts the password and it was confirmed
+    let password_valid = use_state(|| false);
 
     let on_username_change = {
         let new_user = new_user.clone();
@@ -41,21 +46,25 @@ pub fn create_user_form(props: &CreateUserFormProps) -> Html {
 
     let on_password_change = {
         let new_user = new_user.clone();
-        Callback::from(move |e: Event| {
-            let target = e.target_dyn_into::<HtmlInputElement>();
-            if let Some(input) = target {
-                let mut updated_user = (*new_user).clone();
-                updated_user.password = input.value();
-                new_user.set(updated_user);
-            }
+        Callback::from(move |password: String| {
+            let mut updated_user = (*new_user).clone();
+            updated_user.password = password;
+            new_user.set(updated_user);
         })
     };
 
+    let on_password_validity = {
+        let password_valid = password_valid.clone();
+        Callback::from(move |valid: bool| password_valid.set(valid))
+    };
+
     let on_submit = {
+        let store = store.clone();
         let new_user = new_user.clone();
         let submitting = submitting.clone();
         let error = error.clone();
         let success = success.clone();
+        let password_valid = password_valid.clone();
         let on_user_created = props.on_user_created.clone();
 
         Callback::from(move |e: SubmitEvent| {
@@ -70,19 +79,38 @@ pub fn create_user_form(props: &CreateUserFormProps) -> Html {
                 error.set(Some("All fields are required".to_string()));
                 return;
             }
+            if !*password_valid {
+                error.set(Some(
+                    "Choose a password the policy accepts and confirm it".to_string(),
+                ));
+                return;
+            }
 
             submitting.set(true);
 
+            // List the user right away; the server's response replaces the placeholder
+            let pending_id = store.pending_id();
+            store.dispatch(StoreAction::UserPending(User {
+                id: pending_id,
+                username: new_user_data.username.clone(),
+                email: new_user_data.email.clone(),
+                password_hash: String::new(),
+                created_at: String::new(),
+                updated_at: String::new(),
+            }));
+
             let callback = {
+                let store = store.clone();
                 let new_user = new_user.clone();
                 let error = error.clone();
                 let success = success.clone();
                 let submitting = submitting.clone();
                 let on_user_created = on_user_created.clone();
 
-                Callback::from(move |result: Result<_, FetchError>| {
+                Callback::from(move |result: Result<User, FetchError>| {
                     match result {
-                        Ok(_) => {
+                        Ok(user) => {
+                            store.dispatch(StoreAction::UserCreated { pending_id, user });
                             // Reset form
                             new_user.set(NewUser::default());
                             success.set(true);
@@ -90,6 +118,7 @@ pub fn create_user_form(props: &CreateUserFormProps) -> Html {
                             on_user_created.emit(());
                         }
                         Err(e) => {
+                            store.dispatch(StoreAction::UserRemoved(pending_id));
                             error.set(Some(e.to_string()));
                             success.set(false);
                         }
@@ -104,7 +133,7 @@ pub fn create_user_form(props: &CreateUserFormProps) -> Html {
 
     html! {
         <div class="card shadow-sm mb-4">
-            <div class="card-header bg-primary text-white">
+            <div class="card-header text-bg-primary">
                 <h4 class="mb-0">{"Create New User"}</h4>
             </div>
             <div class="card-body">
@@ -143,21 +172,18 @@ pub fn create_user_form(props: &CreateUserFormProps) -> Html {
                             disabled={*submitting}
                         />
                     </div>
-                    <div class="mb-3">
-                        <label for="password" class="form-label">{"Password"}</label>
-                        <input
-                            type="password"
-                            class="form-control"
-                            id="password"
-                            value={new_user.password.clone()}
-                            onchange={on_password_change}
-                            disabled={*submitting}
-                        />
-                    </div>
+                    <PasswordFields
+                        password={new_user.password.clone()}
+                        username={new_user.username.clone()}
+                        email={new_user.email.clone()}
+                        disabled={*submitting}
+                        on_change={on_password_change}
+                        on_validity={on_password_validity}
+                    />
                     <button
                         type="submit"
                         class="btn btn-primary"
-                        disabled={*submitting}
+                        disabled={*submitting || !*password_valid}
                     >
                         if *submitting {
                             <span class="spinner-border spinner-border-sm me-2" role="status" aria-hidden="true"></span>
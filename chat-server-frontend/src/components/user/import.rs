@@ -0,0 +1,223 @@
+use crate::models::{UserImportReport, UserImportRow};
+use crate::services::{FetchError, UserService};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct UserImportProps {
+    /// Called once users were created
+    pub on_imported: Callback<()>,
+}
+
+fn render_row(row: &UserImportRow) -> Html {
+    let status = if let Some(user_id) = row.user_id {
+        html! {
+            <span class="text-success">
+                <i class="bi bi-check-circle me-1"></i>
+                {format!("Created as user {}", user_id)}
+            </span>
+        }
+    } else if row.errors.is_empty() {
+        html! {
+            <span class="text-success">
+                <i class="bi bi-check me-1"></i>
+                {"Ready"}
+            </span>
+        }
+    } else {
+        html! {
+            <ul class="list-unstyled mb-0 text-danger">
+                { for row.errors.iter().map(|error| html! { <li>{error}</li> }) }
+            </ul>
+        }
+    };
+
+    html! {
+        <tr key={row.line.to_string()} class={classes!((!row.errors.is_empty()).then_some("table-danger"))}>
+            <td class="text-muted">{row.line}</td>
+            <td>{&row.username}</td>
+            <td>{&row.email}</td>
+            <td>{status}</td>
+        </tr>
+    }
+}
+
+/// Creates users from a CSV file with `username`, `email` and `password`
+/// columns. A chosen file is first checked without creating anyone, so every
+/// row's problems can be fixed before the valid rows are imported.
+#[function_component(UserImport)]
+pub fn user_import(props: &UserImportProps) -> Html {
+    let csv = use_state(|| None::<String>);
+    let report = use_state(|| None::<UserImportReport>);
+    let busy = use_state(|| false);
+    let error = use_state(|| None::<String>);
+
+    let send = {
+        let report = report.clone();
+        let busy = busy.clone();
+        let error = error.clone();
+        let on_imported = props.on_imported.clone();
+
+        Callback::from(move |(text, dry_run): (String, bool)| {
+            busy.set(true);
+            error.set(None);
+
+            let report = report.clone();
+            let busy = busy.clone();
+            let error = error.clone();
+            let on_imported = on_imported.clone();
+            UserService::import_users(
+                text,
+                dry_run,
+                Callback::from(move |result: Result<UserImportReport, FetchError>| {
+                    match result {
+                        Ok(data) => {
+                            if data.created > 0 {
+                                on_imported.emit(());
+                            }
+                            report.set(Some(data));
+                        }
+                        Err(FetchError::Status(403)) => {
+                            error.set(Some("Only server admins can import users".to_string()))
+                        }
+                        Err(e) => {
+                            report.set(None);
+                            error.set(Some(e.to_string()));
+                        }
+                    }
+                    busy.set(false);
+                }),
+            );
+        })
+    };
+
+    // Reads the chosen file and previews it
+    let on_file_change = {
+        let csv = csv.clone();
+        let report = report.clone();
+        let error = error.clone();
+        let send = send.clone();
+
+        Callback::from(move |e: Event| {
+            let Some(file) = e
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.files())
+                .and_then(|files| files.get(0))
+            else {
+                return;
+            };
+            report.set(None);
+
+            let csv = csv.clone();
+            let error = error.clone();
+            let send = send.clone();
+            spawn_local(async move {
+                match JsFuture::from(file.text()).await {
+                    Ok(text) => {
+                        let text = text.as_string().unwrap_or_default();
+                        csv.set(Some(text.clone()));
+                        send.emit((text, true));
+                    }
+                    Err(_) => error.set(Some("The file could not be read".to_string())),
+                }
+            });
+        })
+    };
+
+    let on_import = {
+        let csv = csv.clone();
+        let send = send.clone();
+        Callback::from(move |_| {
+            if let Some(text) = csv.as_ref() {
+                send.emit((text.clone(), false));
+            }
+        })
+    };
+
+    let summary = report.as_ref().map(|report| {
+        if report.dry_run {
+            html! {
+                <div class="d-flex align-items-center gap-2 mb-3">
+                    <span class="badge text-bg-success">{format!("{} valid", report.valid)}</span>
+                    if report.invalid > 0 {
+                        <span class="badge text-bg-danger">{format!("{} with errors", report.invalid)}</span>
+                    }
+                    <button
+                        class="btn btn-sm btn-primary ms-auto"
+                        onclick={on_import.clone()}
+                        disabled={*busy || report.valid == 0}
+                    >
+                        <i class="bi bi-upload me-1"></i>
+                        {format!("Import {} users", report.valid)}
+                    </button>
+                </div>
+            }
+        } else {
+            html! {
+                <div class="alert alert-success" role="alert">
+                    <i class="bi bi-check-circle me-2"></i>
+                    {format!("{} users created", report.created)}
+                    if report.rows.len() > report.created {
+                        {format!(", {} rows skipped", report.rows.len() - report.created)}
+                    }
+                </div>
+            }
+        }
+    });
+
+    html! {
+        <div class="card shadow-sm mb-4">
+            <div class="card-header text-bg-primary">
+                <h4 class="mb-0">{"Import Users"}</h4>
+            </div>
+            <div class="card-body">
+                if let Some(err) = error.as_ref() {
+                    <div class="alert alert-danger" role="alert">
+                        <i class="bi bi-exclamation-triangle me-2"></i>
+                        {err}
+                    </div>
+                }
+                <div class="mb-3">
+                    <label for="import-file" class="form-label">{"CSV file"}</label>
+                    <input
+                        type="file"
+                        class="form-control"
+                        id="import-file"
+                        accept=".csv,text/csv"
+                        onchange={on_file_change}
+                        disabled={*busy}
+                    />
+                    <div class="form-text">
+                        {"The header line names the username, email and password columns; other columns are ignored."}
+                    </div>
+                </div>
+                if *busy {
+                    <div class="d-flex justify-content-center p-3">
+                        <div class="spinner-border text-primary" role="status">
+                            <span class="visually-hidden">{"Checking..."}</span>
+                        </div>
+                    </div>
+                }
+                {summary.unwrap_or_default()}
+                if let Some(report) = report.as_ref() {
+                    <div class="table-responsive">
+                        <table class="table table-sm align-middle mb-0">
+                            <thead>
+                                <tr>
+                                    <th>{"Line"}</th>
+                                    <th>{"Username"}</th>
+                                    <th>{"Email"}</th>
+                                    <th>{"Status"}</th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                { for report.rows.iter().map(render_row) }
+                            </tbody>
+                        </table>
+                    </div>
+                }
+            </div>
+        </div>
+    }
+}
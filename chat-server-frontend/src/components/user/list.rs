@@ -1,83 +1,143 @@
-use crate::components::user::CreateUserForm;
-use crate::models::User;
-use crate::services::{FetchError, MessageService, UserService};
+use crate::components::modal::ConfirmModal;
+use crate::components::pagination::{page_count, Pagination};
+use crate::components::user::{CreateUserForm, UserImport};
+use crate::models::{UserPage, UserStatus};
+use crate::routes::AppRoute;
+use crate::services::{FetchError, UserService};
+use crate::store::{is_pending, use_store, StoreAction};
 use gloo_dialogs;
+use std::collections::HashMap;
 use yew::prelude::*;
+use yew_router::prelude::*;
+
+/// Users per page until another page size is chosen
+const DEFAULT_PER_PAGE: u32 = 25;
 
 #[function_component(UsersList)]
 pub fn users_list() -> Html {
-    let users = use_state(Vec::new);
+    let store = use_store();
+    let page = use_state(|| store.user_page.as_ref().map_or(1, |page| page.page));
+    let per_page = use_state(|| {
+        store
+            .user_page
+            .as_ref()
+            .map_or(DEFAULT_PER_PAGE, |page| page.per_page)
+    });
+    let statuses = use_state(HashMap::<i32, UserStatus>::new);
     let error = use_state(|| None::<String>);
-    let loading = use_state(|| true);
     let show_create_form = use_state(|| false);
+    let show_import = use_state(|| false);
+    let exporting = use_state(|| false);
+    // User whose deletion waits for confirmation
+    let confirm_delete = use_state(|| None::<i32>);
 
-    // Function to fetch users
+    // Function to refresh the cached page of users
     let fetch_users = {
-        let users = users.clone();
+        let store = store.clone();
+        let statuses = statuses.clone();
         let error = error.clone();
-        let loading = loading.clone();
+        let page = page.clone();
+        let per_page = per_page.clone();
 
         Callback::from(move |_| {
-            loading.set(true);
             error.set(None);
 
+            // Statuses are optional decoration, so failing to load them is not an error
+            let statuses = statuses.clone();
+            UserService::fetch_statuses(Callback::from(
+                move |result: Result<Vec<UserStatus>, FetchError>| {
+                    if let Ok(data) = result {
+                        statuses.set(
+                            data.into_iter()
+                                .map(|status| (status.user_id, status))
+                                .collect(),
+                        );
+                    }
+                },
+            ));
+
             let callback = {
-                let users = users.clone();
+                let store = store.clone();
                 let error = error.clone();
-                let loading = loading.clone();
+                let page = page.clone();
 
-                Callback::from(move |result: Result<Vec<User>, FetchError>| {
-                    match result {
-                        Ok(data) => {
-                            users.set(data);
-                        }
-                        Err(e) => {
-                            error.set(Some(e.to_string()));
-                        }
+                Callback::from(move |result: Result<UserPage, FetchError>| match result {
+                    // Past the last page, e.g. after deleting its only user
+                    Ok(data) if data.users.is_empty() && data.page > 1 => {
+                        page.set(page_count(data.total, data.per_page));
+                    }
+                    Ok(data) => {
+                        store.dispatch(StoreAction::UserPageLoaded(data));
+                    }
+                    Err(e) => {
+                        error.set(Some(e.to_string()));
                     }
-                    loading.set(false);
                 })
             };
 
-            UserService::fetch_users(callback);
+            UserService::fetch_user_page(*page, *per_page, callback);
         })
     };
 
     // Delete user function
     let delete_user = {
+        let store = store.clone();
         let fetch_users = fetch_users.clone();
 
         Callback::from(move |user_id: i32| {
-            let fetch_users = fetch_users.clone();
-
-            let confirm = gloo_dialogs::confirm("Are you sure you want to delete this user?");
-            if !confirm {
+            let Some((index, user)) = store
+                .user_page
+                .iter()
+                .flat_map(|page| &page.users)
+                .enumerate()
+                .find(|(_, user)| user.id == user_id)
+                .map(|(index, user)| (index, user.clone()))
+            else {
                 return;
-            }
+            };
+
+            // Remove the user right away and put it back if the server refuses
+            store.dispatch(StoreAction::UserRemoved(user_id));
 
             let callback = {
+                let store = store.clone();
                 let fetch_users = fetch_users.clone();
 
-                Callback::from(move |result: Result<(), FetchError>| {
-                    match result {
-                        Ok(_) => {
-                            // Refresh user list
-                            fetch_users.emit(());
-                        }
-                        Err(e) => {
-                            // Show error in alert
-                            gloo_dialogs::alert(&e.to_string());
-                        }
+                Callback::from(move |result: Result<(), FetchError>| match result {
+                    // Refill the page with the user that moved up from the next one
+                    Ok(()) => fetch_users.emit(()),
+                    Err(e) => {
+                        store.dispatch(StoreAction::UserRestored {
+                            user: user.clone(),
+                            index,
+                        });
+                        // Show error in alert
+                        gloo_dialogs::alert(&e.to_string());
                     }
                 })
             };
 
-            let callback2 = callback.clone();
-            MessageService::delete_messages_by_user(user_id, callback);
-            UserService::delete_user(user_id, callback2);
+            // The server deletes or anonymizes the user's messages
+            UserService::delete_user(user_id, callback);
         })
     };
 
+    let on_confirm_delete = {
+        let confirm_delete = confirm_delete.clone();
+        let delete_user = delete_user.clone();
+        Callback::from(move |_| {
+            if let Some(user_id) = *confirm_delete {
+                delete_user.emit(user_id);
+            }
+            confirm_delete.set(None);
+        })
+    };
+
+    let on_cancel_delete = {
+        let confirm_delete = confirm_delete.clone();
+        Callback::from(move |_| confirm_delete.set(None))
+    };
+
     // Toggle create form
     let toggle_create_form = {
         let show_create_form = show_create_form.clone();
@@ -86,39 +146,117 @@ pub fn users_list() -> Html {
         })
     };
 
-    // Handle user created event
+    // Handle user created event; the form already added the user to the store
     let on_user_created = {
-        let fetch_users = fetch_users.clone();
         let show_create_form = show_create_form.clone();
         Callback::from(move |_| {
-            // Refresh the user list
-            fetch_users.emit(());
             // Close the create form
             show_create_form.set(false);
         })
     };
 
-    // Fetch users when component mounts
+    let toggle_import = {
+        let show_import = show_import.clone();
+        Callback::from(move |_| {
+            show_import.set(!*show_import);
+        })
+    };
+
+    // Imported users may land on the page being shown
+    let on_imported = {
+        let fetch_users = fetch_users.clone();
+        Callback::from(move |_| fetch_users.emit(()))
+    };
+
+    let on_export = {
+        let exporting = exporting.clone();
+        Callback::from(move |_| {
+            exporting.set(true);
+            let exporting = exporting.clone();
+            UserService::export_users(Callback::from(move |result: Result<(), FetchError>| {
+                exporting.set(false);
+                if let Err(e) = result {
+                    gloo_dialogs::alert(&format!("Export failed: {}", e));
+                }
+            }));
+        })
+    };
+
+    let on_page_change = {
+        let page = page.clone();
+        Callback::from(move |number: u32| page.set(number))
+    };
+
+    // A new page size starts over at the first page
+    let on_per_page_change = {
+        let page = page.clone();
+        let per_page = per_page.clone();
+        Callback::from(move |size: u32| {
+            per_page.set(size);
+            page.set(1);
+        })
+    };
+
+    // Refresh the page when the component mounts or another page is chosen;
+    // the cached page is shown meanwhile
     {
         let fetch_users = fetch_users.clone();
-        use_effect_with((), move |_| {
+        use_effect_with((*page, *per_page), move |_| {
             fetch_users.emit(());
             || () // Cleanup function
         });
     }
 
+    let users = store
+        .user_page
+        .as_ref()
+        .map(|page| page.users.as_slice())
+        .unwrap_or_default();
+    let total = store.user_page.as_ref().map_or(0, |page| page.total);
+
     html! {
         <div class="container py-4">
+            if confirm_delete.is_some() {
+                <ConfirmModal
+                    title="Delete user"
+                    message="Are you sure you want to delete this user? Their messages are deleted or anonymized."
+                    confirm_label="Delete"
+                    destructive=true
+                    on_confirm={on_confirm_delete}
+                    on_cancel={on_cancel_delete}
+                />
+            }
+
             // Show create form if enabled
             if *show_create_form {
                 <CreateUserForm on_user_created={on_user_created} />
             }
 
+            if *show_import {
+                <UserImport {on_imported} />
+            }
+
             <div class="card shadow-sm">
-                <div class="card-header bg-primary text-white d-flex justify-content-between align-items-center">
+                <div class="card-header text-bg-primary d-flex justify-content-between align-items-center">
                     <h3 class="mb-0">{"Users"}</h3>
                     <div>
-                        <span class="badge bg-light text-primary me-2">{format!("Total: {}", users.len())}</span>
+                        <span class="badge bg-light text-primary me-2">{format!("Total: {}", total)}</span>
+                        <button
+                            class="btn btn-sm btn-light me-2"
+                            onclick={on_export}
+                            disabled={*exporting}
+                            title="Download all users as CSV"
+                        >
+                            <i class="bi bi-download me-1"></i>
+                            {"Export CSV"}
+                        </button>
+                        <button
+                            class="btn btn-sm btn-light me-2"
+                            onclick={toggle_import}
+                        >
+                            <i class="bi bi-upload me-1"></i>
+                            {"Import CSV"}
+                        </button>
                         <button
                             class="btn btn-sm btn-light"
                             onclick={toggle_create_form}
@@ -134,8 +272,14 @@ pub fn users_list() -> Html {
                     </div>
                 </div>
                 <div class="card-body">
+                    if let (Some(err), Some(_)) = (error.as_ref(), &store.user_page) {
+                        <div class="alert alert-warning" role="alert">
+                            <i class="bi bi-exclamation-triangle me-2"></i>
+                            {"Showing cached users, refreshing failed: "}{err}
+                        </div>
+                    }
                     {
-                        if *loading {
+                        if store.user_page.is_none() && error.is_none() {
                             html! {
                                 <div class="d-flex justify-content-center p-4">
                                     <div class="spinner-border text-primary" role="status">
@@ -143,7 +287,7 @@ pub fn users_list() -> Html {
                                     </div>
                                 </div>
                             }
-                        } else if let Some(err) = error.as_ref() {
+                        } else if let (Some(err), None) = (error.as_ref(), &store.user_page) {
                             html! {
                                 <div class="alert alert-danger" role="alert">
                                     <i class="bi bi-exclamation-triangle me-2"></i>
@@ -163,18 +307,22 @@ pub fn users_list() -> Html {
                                     {
                                         users.iter().map(|user| {
                                             let user_id = user.id;
-                                            let delete_user = delete_user.clone();
+                                            let pending = is_pending(user);
+                                            let confirm_delete = confirm_delete.clone();
                                             let on_delete = Callback::from(move |_| {
-                                                delete_user.emit(user_id);
+                                                confirm_delete.set(Some(user_id));
                                             });
 
                                             html! {
-                                                <div class="list-group-item p-3 hover-bg-light" key={user.id.to_string()}>
+                                                <div class="list-group-item list-group-item-action p-3" key={user.id.to_string()}>
                                                     <div class="row g-3">
                                                         <div class="col-md-10">
                                                             <div class="d-flex flex-column flex-md-row justify-content-between">
                                                                 <div>
-                                                                    <h5 class="mb-1">{&user.username}</h5>
+                                                                    <h5 class="mb-1">
+                                                                        {&user.username}
+                                                                        { status_badge(statuses.get(&user.id)) }
+                                                                    </h5>
                                                                     <div class="d-flex align-items-center text-muted">
                                                                         <i class="bi bi-envelope me-2"></i>
                                                                         <span>{&user.email}</span>
@@ -182,21 +330,42 @@ pub fn users_list() -> Html {
                                                                 </div>
                                                                 <div class="mt-2 mt-md-0">
                                                                     <small class="text-muted">
-                                                                        <i class="bi bi-clock me-1"></i>
-                                                                        {"Created: "}{user.created_at.split('T').next().unwrap_or(&user.created_at)}
+                                                                        if pending {
+                                                                            <span class="spinner-border spinner-border-sm me-1" role="status" aria-hidden="true"></span>
+                                                                            {"Creating..."}
+                                                                        } else {
+                                                                            <i class="bi bi-clock me-1"></i>
+                                                                            {"Created: "}{user.created_at.split('T').next().unwrap_or(&user.created_at)}
+                                                                        }
                                                                     </small>
                                                                 </div>
                                                             </div>
                                                         </div>
-                                                        <div class="col-md-2 d-flex align-items-center justify-content-end">
-                                                            <button
-                                                                class="btn btn-sm btn-outline-danger"
-                                                                onclick={on_delete}
-                                                                title="Delete user"
-                                                            >
-                                                                <i class="bi bi-trash me-1"></i>
-                                                                {"Delete"}
-                                                            </button>
+                                                        <div class="col-md-2 d-flex align-items-center justify-content-end gap-2">
+                                                            if !pending {
+                                                                <Link<AppRoute>
+                                                                    classes="btn btn-sm btn-outline-primary"
+                                                                    to={AppRoute::UserActivity { id: user_id }}
+                                                                >
+                                                                    <i class="bi bi-activity me-1"></i>
+                                                                    {"Activity"}
+                                                                </Link<AppRoute>>
+                                                                <Link<AppRoute>
+                                                                    classes="btn btn-sm btn-outline-primary"
+                                                                    to={AppRoute::UserMessages { id: user_id }}
+                                                                >
+                                                                    <i class="bi bi-chat-left-text me-1"></i>
+                                                                    {"Messages"}
+                                                                </Link<AppRoute>>
+                                                                <button
+                                                                    class="btn btn-sm btn-outline-danger"
+                                                                    onclick={on_delete}
+                                                                    title="Delete user"
+                                                                >
+                                                                    <i class="bi bi-trash me-1"></i>
+                                                                    {"Delete"}
+                                                                </button>
+                                                            }
                                                         </div>
                                                     </div>
                                                 </div>
@@ -208,7 +377,39 @@ pub fn users_list() -> Html {
                         }
                     }
                 </div>
+                if store.user_page.is_some() {
+                    <div class="card-footer">
+                        <Pagination
+                            page={*page}
+                            per_page={*per_page}
+                            {total}
+                            {on_page_change}
+                            {on_per_page_change}
+                        />
+                    </div>
+                }
             </div>
         </div>
     }
 }
+
+/// Shows a user's availability and custom status text; available users
+/// without text get no badge.
+fn status_badge(status: Option<&UserStatus>) -> Html {
+    let Some(status) = status else {
+        return html! {};
+    };
+    let class = match status.availability.as_str() {
+        "away" => "badge text-bg-warning ms-2",
+        "busy" => "badge text-bg-danger ms-2",
+        _ => "badge text-bg-success ms-2",
+    };
+    let label = match &status.text {
+        Some(text) => format!("{}: {}", status.availability, text),
+        None if status.availability == "available" => return html! {},
+        None => status.availability.clone(),
+    };
+    html! {
+        <span class={class}>{label}</span>
+    }
+}
@@ -0,0 +1,434 @@
+use crate::components::messages::{file_icon, format_size};
+use crate::components::modal::{ConfirmModal, Lightbox};
+use crate::models::{HistoryEntry, MessageType, UserMessagesPage};
+use crate::services::{FetchError, MessageService, UserService};
+use crate::store::{use_store, StoreAction};
+use std::rc::Rc;
+use yew::prelude::*;
+use yew_hooks::use_infinite_scroll;
+
+/// Messages fetched per request
+const PAGE_SIZE: u32 = 50;
+
+#[derive(Properties, PartialEq)]
+pub struct UserMessagesProps {
+    pub user_id: i32,
+}
+
+/// The loaded part of a user's history, newest first.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct HistoryState {
+    user_id: i32,
+    entries: Vec<HistoryEntry>,
+    /// Where the next, older page starts
+    next_before: Option<i32>,
+    /// Set once the oldest message is loaded
+    complete: bool,
+    loading: bool,
+    error: Option<String>,
+}
+
+enum HistoryAction {
+    /// Starts over with the history of another user
+    Reset(i32),
+    Loading,
+    Loaded(UserMessagesPage),
+    Failed(String),
+    Removed(i32),
+    /// A deletion failed; puts the entry back where it was
+    Restored {
+        entry: HistoryEntry,
+        index: usize,
+    },
+}
+
+impl Reducible for HistoryState {
+    type Action = HistoryAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut state = (*self).clone();
+        match action {
+            HistoryAction::Reset(user_id) => {
+                state = HistoryState {
+                    user_id,
+                    ..HistoryState::default()
+                }
+            }
+            HistoryAction::Loading => {
+                state.loading = true;
+                state.error = None;
+            }
+            // Pages still arriving for the previous user are dropped
+            HistoryAction::Loaded(page) if page.user_id == state.user_id => {
+                let known = |id: i32| state.entries.iter().any(|entry| entry.message.id == id);
+                let new: Vec<_> = page
+                    .messages
+                    .into_iter()
+                    .filter(|entry| !known(entry.message.id))
+                    .collect();
+                state.entries.extend(new);
+                state.next_before = page.next_before;
+                state.complete = page.next_before.is_none();
+                state.loading = false;
+            }
+            HistoryAction::Loaded(_) => {}
+            HistoryAction::Failed(error) => {
+                state.loading = false;
+                state.error = Some(error);
+            }
+            HistoryAction::Removed(message_id) => {
+                state.entries.retain(|entry| entry.message.id != message_id);
+            }
+            HistoryAction::Restored { entry, index } => {
+                if state
+                    .entries
+                    .iter()
+                    .all(|existing| existing.message.id != entry.message.id)
+                {
+                    let index = index.min(state.entries.len());
+                    state.entries.insert(index, entry);
+                }
+            }
+        }
+        Rc::new(state)
+    }
+}
+
+/// Shortens a server timestamp like `2024-05-01T12:30:45.123` to `12:30`.
+fn format_clock(timestamp: &str) -> String {
+    timestamp
+        .split_once('T')
+        .map(|(_, time)| time.chars().take(5).collect())
+        .unwrap_or_default()
+}
+
+/// Day of a server timestamp, like `2024-05-01`
+fn day_of(timestamp: &str) -> &str {
+    timestamp.split('T').next().unwrap_or(timestamp)
+}
+
+/// The messages one user sent, newest first and grouped by day. Older
+/// messages load as the list is scrolled to its end, and each message can be
+/// deleted in place.
+#[function_component(UserMessages)]
+pub fn user_messages(props: &UserMessagesProps) -> Html {
+    let store = use_store();
+    let download_token = store.download_token.clone().unwrap_or_default();
+    let history = use_reducer(HistoryState::default);
+    let confirm_delete = use_state(|| None::<i32>);
+    let lightbox = use_state(|| None::<(String, String)>);
+    let scroll_ref = use_node_ref();
+    // Scroll events keep coming while a page loads; only one request runs at a time
+    let in_flight = use_mut_ref(|| false);
+
+    let fetch_page = {
+        let history = history.clone();
+        let in_flight = in_flight.clone();
+        let user_id = props.user_id;
+
+        Callback::from(move |before: Option<i32>| {
+            if in_flight.replace(true) {
+                return;
+            }
+            history.dispatch(HistoryAction::Loading);
+
+            let history = history.clone();
+            let in_flight = in_flight.clone();
+            UserService::fetch_user_messages(
+                user_id,
+                before,
+                PAGE_SIZE,
+                Callback::from(move |result| {
+                    *in_flight.borrow_mut() = false;
+                    match result {
+                        Ok(page) => history.dispatch(HistoryAction::Loaded(page)),
+                        Err(FetchError::Status(403)) => history.dispatch(HistoryAction::Failed(
+                            "Only server admins can view the messages of other users".to_string(),
+                        )),
+                        Err(e) => history.dispatch(HistoryAction::Failed(e.to_string())),
+                    }
+                }),
+            );
+        })
+    };
+
+    // Start over when another user is shown
+    {
+        let history = history.clone();
+        let fetch_page = fetch_page.clone();
+        let in_flight = in_flight.clone();
+        use_effect_with(props.user_id, move |user_id| {
+            history.dispatch(HistoryAction::Reset(*user_id));
+            *in_flight.borrow_mut() = false;
+            fetch_page.emit(None);
+            || () // Cleanup function
+        });
+    }
+
+    let load_older = {
+        let fetch_page = fetch_page.clone();
+        let next_before = history.next_before;
+        let done = history.complete || history.error.is_some();
+        move || {
+            if !done {
+                if let Some(before) = next_before {
+                    fetch_page.emit(Some(before));
+                }
+            }
+        }
+    };
+
+    use_infinite_scroll(scroll_ref.clone(), load_older.clone());
+
+    let on_load_older = Callback::from(move |_: MouseEvent| load_older());
+
+    let on_retry = {
+        let fetch_page = fetch_page.clone();
+        let next_before = history.next_before;
+        Callback::from(move |_: MouseEvent| fetch_page.emit(next_before))
+    };
+
+    // Remove the message right away and put it back if the server refuses
+    let on_confirm_delete = {
+        let confirm_delete = confirm_delete.clone();
+        let history = history.clone();
+        let store = store.clone();
+
+        Callback::from(move |_| {
+            let Some(message_id) = *confirm_delete else {
+                return;
+            };
+            confirm_delete.set(None);
+            let Some((index, entry)) = history
+                .entries
+                .iter()
+                .enumerate()
+                .find(|(_, entry)| entry.message.id == message_id)
+                .map(|(index, entry)| (index, entry.clone()))
+            else {
+                return;
+            };
+
+            history.dispatch(HistoryAction::Removed(message_id));
+
+            let history = history.clone();
+            let store = store.clone();
+            MessageService::delete_message(
+                message_id,
+                Callback::from(move |result: Result<(), FetchError>| match result {
+                    // Keep the cached messages list in step
+                    Ok(()) => store.dispatch(StoreAction::MessageRemoved(message_id)),
+                    Err(e) => {
+                        history.dispatch(HistoryAction::Restored {
+                            entry: entry.clone(),
+                            index,
+                        });
+                        gloo_dialogs::alert(&e.to_string());
+                    }
+                }),
+            );
+        })
+    };
+
+    let on_cancel_delete = {
+        let confirm_delete = confirm_delete.clone();
+        Callback::from(move |_| confirm_delete.set(None))
+    };
+
+    let on_close_lightbox = {
+        let lightbox = lightbox.clone();
+        Callback::from(move |_| lightbox.set(None))
+    };
+
+    let render_content = |entry: &HistoryEntry| -> Html {
+        let message = &entry.message;
+        if entry.redacted {
+            return html! {
+                <div class="text-muted fst-italic">
+                    <i class="bi bi-lock me-1"></i>
+                    {"Content withheld: sent in a workspace you are not a member of"}
+                </div>
+            };
+        }
+        match message.message_type {
+            MessageType::Text => html! {
+                <div>{message.content.clone().unwrap_or_default()}</div>
+            },
+            MessageType::Action => html! {
+                <div class="fst-italic">
+                    {format!("* {}", message.content.clone().unwrap_or_default())}
+                </div>
+            },
+            MessageType::File => {
+                let name = message
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "Unnamed file".to_string());
+                html! {
+                    <div>
+                        <i class={classes!("bi", file_icon(&name), "me-2")}></i>
+                        <a
+                            href={MessageService::attachment_url(message.id, &download_token)}
+                            target="_blank"
+                            rel="noopener"
+                            class="text-decoration-none"
+                        >
+                            {name}
+                        </a>
+                        if let Some(size) = message.attachment_size {
+                            <span class="badge text-bg-light border ms-2">{format_size(size)}</span>
+                        }
+                    </div>
+                }
+            }
+            MessageType::Image => {
+                let url = MessageService::attachment_url(message.id, &download_token);
+                let name = message
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "Unnamed image".to_string());
+                let on_open = {
+                    let lightbox = lightbox.clone();
+                    let (url, name) = (url.clone(), name.clone());
+                    Callback::from(move |e: MouseEvent| {
+                        e.prevent_default();
+                        lightbox.set(Some((url.clone(), name.clone())));
+                    })
+                };
+                html! {
+                    <div>
+                        <i class="bi bi-image me-2"></i>
+                        <a href={url} target="_blank" rel="noopener" class="text-decoration-none">
+                            {name.clone()}
+                        </a>
+                        if message.attachment_size.is_some() {
+                            <button
+                                type="button"
+                                class="d-block mt-2 p-0 border-0 bg-transparent"
+                                onclick={on_open}
+                                title="Show full size"
+                            >
+                                <img
+                                    class="img-thumbnail"
+                                    style="max-height: 120px;"
+                                    loading="lazy"
+                                    src={MessageService::thumbnail_url(message.id, &download_token)}
+                                    alt={name}
+                                />
+                            </button>
+                        }
+                    </div>
+                }
+            }
+        }
+    };
+
+    // A heading starts each day
+    let mut rows = Vec::new();
+    let mut current_day = None;
+    for entry in &history.entries {
+        let message = &entry.message;
+        let day = day_of(&message.created_at);
+        if current_day != Some(day) {
+            current_day = Some(day);
+            rows.push(html! {
+                <li class="list-group-item bg-body-tertiary py-1" key={format!("day-{}", day)}>
+                    <small class="fw-semibold text-muted">{day.to_string()}</small>
+                </li>
+            });
+        }
+
+        let on_delete = {
+            let confirm_delete = confirm_delete.clone();
+            let message_id = message.id;
+            Callback::from(move |_: MouseEvent| confirm_delete.set(Some(message_id)))
+        };
+        rows.push(html! {
+            <li class="list-group-item d-flex gap-3 align-items-start" key={message.id.to_string()}>
+                <small class="text-muted text-nowrap pt-1" title={message.created_at.clone()}>
+                    {format_clock(&message.created_at)}
+                </small>
+                <div class="flex-grow-1 text-break">
+                    {render_content(entry)}
+                    if message.edited_at.is_some() {
+                        <small class="text-muted">{"(edited)"}</small>
+                    }
+                </div>
+                <small class="text-muted text-nowrap pt-1">{format!("#{}", message.id)}</small>
+                <button
+                    class="btn btn-sm btn-outline-danger"
+                    onclick={on_delete}
+                    title="Delete message"
+                >
+                    <i class="bi bi-trash"></i>
+                </button>
+            </li>
+        });
+    }
+
+    html! {
+        <>
+            if let Some((src, name)) = (*lightbox).clone() {
+                <Lightbox {src} {name} on_close={on_close_lightbox} />
+            }
+
+            if confirm_delete.is_some() {
+                <ConfirmModal
+                    title="Delete message"
+                    message="Are you sure you want to delete this message?"
+                    confirm_label="Delete"
+                    destructive=true
+                    on_confirm={on_confirm_delete}
+                    on_cancel={on_cancel_delete}
+                />
+            }
+
+            <div class="card shadow-sm">
+                <div class="card-header d-flex justify-content-between align-items-center">
+                    <h5 class="mb-0">
+                        <i class="bi bi-chat-left-text me-2"></i>
+                        {"Messages"}
+                    </h5>
+                    <span class="badge text-bg-primary">
+                        {format!(
+                            "{}{} loaded",
+                            history.entries.len(),
+                            if history.complete { "" } else { "+" }
+                        )}
+                    </span>
+                </div>
+                <div ref={scroll_ref} style="max-height: 70vh; overflow-y: auto;">
+                    <ul class="list-group list-group-flush">
+                        { for rows }
+                    </ul>
+
+                    if history.complete && history.entries.is_empty() {
+                        <div class="text-center text-muted py-5">
+                            <i class="bi bi-chat-square fs-1"></i>
+                            <p class="mt-2 mb-0">{"This user has not sent any messages"}</p>
+                        </div>
+                    }
+
+                    <div class="text-center py-3">
+                        if let Some(error) = history.error.as_ref() {
+                            <div class="alert alert-danger mx-3 mb-2" role="alert">{error.clone()}</div>
+                            <button class="btn btn-sm btn-outline-secondary" onclick={on_retry}>
+                                {"Try again"}
+                            </button>
+                        } else if history.loading {
+                            <div class="spinner-border spinner-border-sm text-primary" role="status">
+                                <span class="visually-hidden">{"Loading..."}</span>
+                            </div>
+                        } else if !history.complete {
+                            <button class="btn btn-sm btn-outline-primary" onclick={on_load_older}>
+                                {"Load older messages"}
+                            </button>
+                        } else if !history.entries.is_empty() {
+                            <small class="text-muted">{"No older messages"}</small>
+                        }
+                    </div>
+                </div>
+            </div>
+        </>
+    }
+}
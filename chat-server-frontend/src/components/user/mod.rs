@@ -1,5 +1,13 @@
+mod activity;
 mod create_form;
+mod import;
 mod list;
+mod messages;
+mod password_fields;
 
+pub use activity::UserActivity;
 pub use create_form::CreateUserForm;
+pub use import::UserImport;
 pub use list::UsersList;
+pub use messages::UserMessages;
+pub use password_fields::PasswordFields;
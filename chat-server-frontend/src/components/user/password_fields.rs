@@ -0,0 +1,227 @@
+use crate::models::PasswordCheck;
+use crate::services::{FetchError, UserService};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_hooks::use_debounce_effect_with_deps;
+
+/// Wait after the last keystroke before the password is checked
+const CHECK_DELAY_MS: u32 = 300;
+
+#[derive(Properties, PartialEq)]
+pub struct PasswordFieldsProps {
+    pub password: String,
+    /// Name and email of the user, which make a password easier to guess
+    #[prop_or_default]
+    pub username: String,
+    #[prop_or_default]
+    pub email: String,
+    #[prop_or_default]
+    pub disabled: bool,
+    pub on_change: Callback<String>,
+    /// Called with whether the server accepts the password and the
+    /// confirmation matches it
+    pub on_validity: Callback<bool>,
+}
+
+/// Label and progress bar color of each strength score.
+fn strength_label(score: u8, max_score: u8) -> (&'static str, &'static str) {
+    match score {
+        0 => ("Very weak", "bg-danger"),
+        1 => ("Weak", "bg-danger"),
+        2 => ("Fair", "bg-warning"),
+        score if score < max_score => ("Strong", "bg-info"),
+        _ => ("Very strong", "bg-success"),
+    }
+}
+
+/// A password field and its confirmation, with show/hide toggles and a
+/// strength meter. The password is checked against the server's password
+/// policy while it is typed, and the broken rules are listed.
+#[function_component(PasswordFields)]
+pub fn password_fields(props: &PasswordFieldsProps) -> Html {
+    let confirmation = use_state(String::new);
+    let show_password = use_state(|| false);
+    let show_confirmation = use_state(|| false);
+    // The latest check and the password it was for
+    let check = use_state(|| None::<(String, PasswordCheck)>);
+    let error = use_state(|| None::<String>);
+
+    {
+        let check = check.clone();
+        let error = error.clone();
+        let password = props.password.clone();
+        let username = props.username.clone();
+        let email = props.email.clone();
+        use_debounce_effect_with_deps(
+            move || {
+                if password.is_empty() {
+                    check.set(None);
+                    return;
+                }
+                let checked = password.clone();
+                let check = check.clone();
+                let error = error.clone();
+                UserService::check_password(
+                    password.clone(),
+                    username.clone(),
+                    email.clone(),
+                    Callback::from(
+                        move |result: Result<PasswordCheck, FetchError>| match result {
+                            Ok(data) => {
+                                error.set(None);
+                                check.set(Some((checked.clone(), data)));
+                            }
+                            Err(e) => error.set(Some(e.to_string())),
+                        },
+                    ),
+                );
+            },
+            CHECK_DELAY_MS,
+            (
+                props.password.clone(),
+                props.username.clone(),
+                props.email.clone(),
+            ),
+        );
+    }
+
+    // A check of a password typed over since is stale
+    let current = check
+        .as_ref()
+        .filter(|(checked, _)| *checked == props.password)
+        .map(|(_, check)| check);
+    let matches = *confirmation == props.password;
+    let valid = current.is_some_and(|check| check.accepted) && matches;
+
+    // Forms clear the password once submitted
+    {
+        let confirmation = confirmation.clone();
+        use_effect_with(props.password.is_empty(), move |cleared| {
+            if *cleared {
+                confirmation.set(String::new());
+            }
+            || ()
+        });
+    }
+
+    {
+        let on_validity = props.on_validity.clone();
+        use_effect_with(valid, move |valid| {
+            on_validity.emit(*valid);
+            || ()
+        });
+    }
+
+    let on_password_input = {
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                on_change.emit(input.value());
+            }
+        })
+    };
+
+    let on_confirmation_input = {
+        let confirmation = confirmation.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                confirmation.set(input.value());
+            }
+        })
+    };
+
+    let toggle = |shown: &UseStateHandle<bool>| {
+        let shown = shown.clone();
+        Callback::from(move |_| shown.set(!*shown))
+    };
+    let toggle_button = |shown: bool, onclick: Callback<MouseEvent>| {
+        html! {
+            <button
+                type="button"
+                class="btn btn-outline-secondary"
+                {onclick}
+                title={if shown { "Hide" } else { "Show" }}
+            >
+                <i class={classes!("bi", if shown { "bi-eye-slash" } else { "bi-eye" })}></i>
+            </button>
+        }
+    };
+
+    let meter = match current {
+        Some(check) => {
+            let (label, color) = strength_label(check.score, check.max_score);
+            let width = (u32::from(check.score) + 1) * 100 / (u32::from(check.max_score) + 1);
+            html! {
+                <>
+                    <div class="progress mt-2" style="height: 6px;">
+                        <div
+                            class={classes!("progress-bar", color)}
+                            role="progressbar"
+                            style={format!("width: {}%", width)}
+                            aria-valuenow={check.score.to_string()}
+                            aria-valuemin="0"
+                            aria-valuemax={check.max_score.to_string()}
+                        ></div>
+                    </div>
+                    <div class="form-text">{format!("Strength: {}", label)}</div>
+                    if !check.violations.is_empty() {
+                        <ul class="small text-danger mb-0 ps-3">
+                            { for check.violations.iter().map(|violation| html! {
+                                <li key={violation.message.clone()}>{&violation.message}</li>
+                            }) }
+                        </ul>
+                    }
+                </>
+            }
+        }
+        None if !props.password.is_empty() && error.is_none() => html! {
+            <div class="form-text">{"Checking..."}</div>
+        },
+        _ => html! {},
+    };
+
+    html! {
+        <>
+            <div class="mb-3">
+                <label for="password" class="form-label">{"Password"}</label>
+                <div class="input-group">
+                    <input
+                        type={if *show_password { "text" } else { "password" }}
+                        class="form-control"
+                        id="password"
+                        autocomplete="new-password"
+                        value={props.password.clone()}
+                        oninput={on_password_input}
+                        disabled={props.disabled}
+                    />
+                    {toggle_button(*show_password, toggle(&show_password))}
+                </div>
+                {meter}
+                if let Some(err) = error.as_ref() {
+                    <div class="form-text text-warning">
+                        {format!("The password could not be checked: {}", err)}
+                    </div>
+                }
+            </div>
+            <div class="mb-3">
+                <label for="confirm-password" class="form-label">{"Confirm Password"}</label>
+                <div class="input-group has-validation">
+                    <input
+                        type={if *show_confirmation { "text" } else { "password" }}
+                        class={classes!(
+                            "form-control",
+                            (!confirmation.is_empty() && !matches).then_some("is-invalid"),
+                        )}
+                        id="confirm-password"
+                        autocomplete="new-password"
+                        value={(*confirmation).clone()}
+                        oninput={on_confirmation_input}
+                        disabled={props.disabled}
+                    />
+                    {toggle_button(*show_confirmation, toggle(&show_confirmation))}
+                    <div class="invalid-feedback">{"The passwords do not match"}</div>
+                </div>
+            </div>
+        </>
+    }
+}
@@ -3,21 +3,32 @@ mod models;
 mod pages;
 mod routes;
 mod services;
+mod store;
 
+use components::messages::DownloadTokens;
 use components::navigation::Navbar;
+use components::shortcuts::Shortcuts;
+use components::theme::ThemeProvider;
 use routes::{switch, AppRoute};
+use store::StoreProvider;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 #[function_component(App)]
 fn app() -> Html {
     html! {
-        <BrowserRouter>
-            <Navbar />
-            <main>
-                <Switch<AppRoute> render={switch} />
-            </main>
-        </BrowserRouter>
+        <ThemeProvider>
+            <StoreProvider>
+                <BrowserRouter>
+                    <Navbar />
+                    <Shortcuts />
+                    <DownloadTokens />
+                    <main>
+                        <Switch<AppRoute> render={switch} />
+                    </main>
+                </BrowserRouter>
+            </StoreProvider>
+        </ThemeProvider>
     }
 }
 
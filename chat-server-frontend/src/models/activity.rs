@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DailyMessageCount {
+    pub day: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserStats {
+    pub user_id: i32,
+    pub total_messages: i64,
+    pub text_messages: i64,
+    pub attachments_sent: i64,
+    pub files_sent: i64,
+    pub images_sent: i64,
+    pub last_message_at: Option<String>,
+    pub days: u32,
+    pub daily: Vec<DailyMessageCount>,
+}
+
+/// Attachment storage of a user, in bytes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserUsage {
+    pub user_id: i32,
+    pub attachment_bytes: u64,
+    /// None if there is no quota
+    pub quota_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserSession {
+    pub token_prefix: String,
+    pub logged_in_at: String,
+    pub expires_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserConnection {
+    pub client_id: usize,
+    pub peer_addr: String,
+    pub workspace_id: Option<i32>,
+    pub connected_at: String,
+}
@@ -5,6 +5,8 @@ pub enum MessageType {
     Text,
     File,
     Image,
+    /// A `* alice waves` action; `content` holds the action
+    Action,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -14,6 +16,47 @@ pub struct Message {
     pub message_type: MessageType,
     pub content: Option<String>,
     pub file_name: Option<String>,
+    /// Size of the attachment in bytes, set once its content is stored
+    #[serde(default)]
+    pub attachment_size: Option<i64>,
     pub created_at: String,
     pub updated_at: String,
+    /// When the content was last edited, if it ever was
+    #[serde(default)]
+    pub edited_at: Option<String>,
+    /// Position in the workspace's messages, counting from 1
+    #[serde(default)]
+    pub seq: i64,
+}
+
+/// A version of a message before it was edited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageRevision {
+    pub id: i32,
+    pub message_id: i32,
+    pub content: Option<String>,
+    pub file_name: Option<String>,
+    pub edited_by: Option<i32>,
+    pub edited_at: String,
+}
+
+/// A message in a user's history.
+///
+/// The server withholds the content, file name and hash of messages in
+/// workspaces the requester is not a member of and sets `redacted`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub message: Message,
+    #[serde(default)]
+    pub redacted: bool,
+}
+
+/// One page of the messages a user sent, newest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserMessagesPage {
+    pub user_id: i32,
+    pub messages: Vec<HistoryEntry>,
+    /// Passed as `before` to fetch the next, older page; unset on the last page
+    pub next_before: Option<i32>,
 }
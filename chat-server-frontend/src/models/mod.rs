@@ -1,5 +1,11 @@
+mod activity;
 mod message;
+mod report;
 mod user;
 
-pub use message::{Message, MessageType};
-pub use user::{NewUser, User};
+pub use activity::{UserConnection, UserSession, UserStats, UserUsage};
+pub use message::{HistoryEntry, Message, MessageRevision, MessageType, UserMessagesPage};
+pub use report::{Report, ReportStatus};
+pub use user::{
+    NewUser, PasswordCheck, User, UserImportReport, UserImportRow, UserPage, UserStatus,
+};
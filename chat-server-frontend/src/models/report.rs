@@ -0,0 +1,42 @@
+use crate::models::Message;
+use serde::{Deserialize, Serialize};
+
+/// Where a report stands; every status but `Open` is a resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Open,
+    Dismissed,
+    Deleted,
+    Warned,
+    Banned,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Dismissed => "dismissed",
+            ReportStatus::Deleted => "deleted",
+            ReportStatus::Warned => "warned",
+            ReportStatus::Banned => "banned",
+        }
+    }
+}
+
+/// A reported message in the moderation queue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub id: i32,
+    pub workspace_id: i32,
+    /// None once the message was deleted
+    pub message_id: Option<i32>,
+    pub reported_user_id: Option<i32>,
+    pub reporter_id: Option<i32>,
+    pub reason: String,
+    pub status: ReportStatus,
+    pub resolved_by: Option<i32>,
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+    pub message: Option<Message>,
+}
@@ -10,9 +10,76 @@ pub struct User {
     pub updated_at: String,
 }
 
+/// One page of the user list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    /// Page number, starting at 1
+    pub page: u32,
+    pub per_page: u32,
+    /// Users on all pages
+    pub total: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct NewUser {
     pub username: String,
     pub email: String,
     pub password: String,
+    /// Needed to register when the server requires invites
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_code: Option<String>,
+}
+
+/// A rule of the password policy a password breaks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PasswordViolation {
+    /// `min_length`, `max_length`, `character_class`, `strength` or `breached`
+    pub rule: String,
+    pub message: String,
+}
+
+/// How the server rates a password against its password policy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PasswordCheck {
+    pub accepted: bool,
+    /// Estimated strength, from 0 to `max_score`
+    pub score: u8,
+    pub max_score: u8,
+    /// Strength the policy requires
+    pub min_score: u8,
+    pub violations: Vec<PasswordViolation>,
+}
+
+/// Status a user has set; users without one are available.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserStatus {
+    pub user_id: i32,
+    /// `available`, `away` or `busy`
+    pub availability: String,
+    pub text: Option<String>,
+    pub updated_at: String,
+}
+
+/// Outcome of one row of a user import.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserImportRow {
+    /// Line of the row in the file, counting from 1
+    pub line: usize,
+    pub username: String,
+    pub email: String,
+    /// Why the row is rejected, empty if it is valid
+    pub errors: Vec<String>,
+    /// The user created from the row
+    pub user_id: Option<i32>,
+}
+
+/// Outcome of a user import or of its dry run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserImportReport {
+    pub dry_run: bool,
+    pub valid: usize,
+    pub invalid: usize,
+    pub created: usize,
+    pub rows: Vec<UserImportRow>,
 }
@@ -0,0 +1,104 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::routes::AppRoute;
+use crate::services::{FetchError, UserService};
+
+/// Asks for a password reset link to be emailed. The server answers the same
+/// whether or not the address is registered.
+#[function_component(ForgotPasswordPage)]
+pub fn forgot_password_page() -> Html {
+    let email = use_state(String::new);
+    let submitting = use_state(|| false);
+    let sent = use_state(|| None::<String>);
+    let error = use_state(|| None::<String>);
+
+    let on_email_change = {
+        let email = email.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                email.set(input.value());
+            }
+        })
+    };
+
+    let onsubmit = {
+        let email = email.clone();
+        let submitting = submitting.clone();
+        let sent = sent.clone();
+        let error = error.clone();
+
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            if email.trim().is_empty() {
+                error.set(Some("Email is required".to_string()));
+                return;
+            }
+
+            submitting.set(true);
+            error.set(None);
+            let submitting = submitting.clone();
+            let sent = sent.clone();
+            let error = error.clone();
+            UserService::forgot_password(
+                (*email).clone(),
+                Callback::from(move |result: Result<String, FetchError>| {
+                    submitting.set(false);
+                    match result {
+                        Ok(message) => sent.set(Some(message)),
+                        Err(e) => error.set(Some(e.to_string())),
+                    }
+                }),
+            );
+        })
+    };
+
+    html! {
+        <div class="container py-5">
+            <div class="row justify-content-center">
+                <div class="col-md-6 col-lg-4">
+                    <div class="card shadow">
+                        <div class="card-body p-5">
+                            <h2 class="text-center mb-4">{"Forgot Password"}</h2>
+                            if let Some(err) = error.as_ref() {
+                                <div class="alert alert-danger" role="alert">
+                                    {err}
+                                </div>
+                            }
+                            if let Some(message) = sent.as_ref() {
+                                <div class="alert alert-success" role="alert">
+                                    <i class="bi bi-envelope-check me-2"></i>
+                                    {message}
+                                </div>
+                            } else {
+                                <form {onsubmit}>
+                                    <div class="mb-3">
+                                        <label for="email" class="form-label">{"Email"}</label>
+                                        <input
+                                            type="email"
+                                            class="form-control"
+                                            id="email"
+                                            autocomplete="email"
+                                            value={(*email).clone()}
+                                            onchange={on_email_change}
+                                            disabled={*submitting}
+                                            required=true
+                                        />
+                                        <div class="form-text">{"We will email you a link to choose a new password"}</div>
+                                    </div>
+                                    <button type="submit" class="btn btn-primary w-100" disabled={*submitting}>
+                                        {"Send Reset Link"}
+                                    </button>
+                                </form>
+                            }
+                            <div class="text-center mt-3">
+                                <Link<AppRoute> to={AppRoute::Login}>{"Back to login"}</Link<AppRoute>>
+                            </div>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -6,7 +6,7 @@ use web_sys::SubmitEvent;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
-use crate::routes::AppRoute;
+use crate::routes::{AppRoute, LoginQuery};
 
 const API_BASE_URL: &str = "http://127.0.0.1:8001";
 
@@ -16,6 +16,11 @@ pub fn login_page() -> Html {
     let password = use_state(String::new);
     let error = use_state(String::new);
     let navigator = use_navigator().unwrap();
+    // Where to go after logging in
+    let destination = use_location()
+        .and_then(|location| location.query::<LoginQuery>().ok())
+        .unwrap_or_default()
+        .destination();
 
     let username_changed = {
         let username = username.clone();
@@ -40,6 +45,7 @@ pub fn login_page() -> Html {
         let password = password.clone();
         let error = error.clone();
         let navigator = navigator.clone();
+        let destination = destination.clone();
 
         Callback::from(move |e: SubmitEvent| {
             e.prevent_default();
@@ -47,6 +53,7 @@ pub fn login_page() -> Html {
             let password = (*password).clone();
             let error = error.clone();
             let navigator = navigator.clone();
+            let destination = destination.clone();
 
             spawn_local(async move {
                 let client = reqwest::Client::new();
@@ -65,7 +72,7 @@ pub fn login_page() -> Html {
                                 if let Some(token) = json.get("token").and_then(|t| t.as_str()) {
                                     // Store the token
                                     if LocalStorage::set("token", token).is_ok() {
-                                        navigator.push(&AppRoute::Home);
+                                        navigator.push(&destination);
                                     }
                                 }
                             }
@@ -120,6 +127,12 @@ pub fn login_page() -> Html {
                                     {"Login"}
                                 </button>
                             </form>
+                            <div class="text-center mt-3">
+                                <Link<AppRoute> to={AppRoute::ForgotPassword}>{"Forgot your password?"}</Link<AppRoute>>
+                            </div>
+                            <div class="text-center mt-2">
+                                <Link<AppRoute> to={AppRoute::Register}>{"Create an account"}</Link<AppRoute>>
+                            </div>
                         </div>
                     </div>
                 </div>
@@ -1,4 +1,11 @@
+pub mod forgot_password;
 pub mod home;
 pub mod login;
 pub mod messages;
+pub mod moderation;
+pub mod register;
+pub mod reset_password;
+pub mod user_activity;
+pub mod user_messages;
 pub mod users;
+pub mod verify_email;
@@ -0,0 +1,15 @@
+use crate::components::reports::ReportQueue;
+use yew::prelude::*;
+
+#[function_component(ModerationPage)]
+pub fn moderation_page() -> Html {
+    html! {
+        <div class="container py-3">
+            <div class="d-flex justify-content-between align-items-center mb-4">
+                <h1>{"Moderation Queue"}</h1>
+            </div>
+
+            <ReportQueue />
+        </div>
+    }
+}
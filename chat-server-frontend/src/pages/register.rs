@@ -0,0 +1,160 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::components::user::PasswordFields;
+use crate::models::{NewUser, User};
+use crate::routes::AppRoute;
+use crate::services::{FetchError, UserService};
+
+#[function_component(RegisterPage)]
+pub fn register_page() -> Html {
+    let new_user = use_state(NewUser::default);
+    let password_valid = use_state(|| false);
+    let submitting = use_state(|| false);
+    let error = use_state(|| None::<String>);
+    let navigator = use_navigator().unwrap();
+
+    let on_field_change = |set: fn(&mut NewUser, String)| {
+        let new_user = new_user.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                let mut updated_user = (*new_user).clone();
+                set(&mut updated_user, input.value());
+                new_user.set(updated_user);
+            }
+        })
+    };
+    let on_username_change = on_field_change(|user, value| user.username = value);
+    let on_email_change = on_field_change(|user, value| user.email = value);
+    let on_invite_change = on_field_change(|user, value| {
+        user.invite_code = Some(value.trim().to_string()).filter(|code| !code.is_empty())
+    });
+
+    let on_password_change = {
+        let new_user = new_user.clone();
+        Callback::from(move |password: String| {
+            let mut updated_user = (*new_user).clone();
+            updated_user.password = password;
+            new_user.set(updated_user);
+        })
+    };
+
+    let on_password_validity = {
+        let password_valid = password_valid.clone();
+        Callback::from(move |valid: bool| password_valid.set(valid))
+    };
+
+    let onsubmit = {
+        let new_user = new_user.clone();
+        let password_valid = password_valid.clone();
+        let submitting = submitting.clone();
+        let error = error.clone();
+
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            if new_user.username.is_empty() || new_user.email.is_empty() {
+                error.set(Some("Username and email are required".to_string()));
+                return;
+            }
+            if !*password_valid {
+                error.set(Some(
+                    "Choose a password the policy accepts and confirm it".to_string(),
+                ));
+                return;
+            }
+
+            submitting.set(true);
+            let submitting = submitting.clone();
+            let error = error.clone();
+            let navigator = navigator.clone();
+            UserService::create_user(
+                (*new_user).clone(),
+                Callback::from(move |result: Result<User, FetchError>| {
+                    submitting.set(false);
+                    match result {
+                        Ok(_) => navigator.push(&AppRoute::Login),
+                        Err(e) => error.set(Some(e.to_string())),
+                    }
+                }),
+            );
+        })
+    };
+
+    html! {
+        <div class="container py-5">
+            <div class="row justify-content-center">
+                <div class="col-md-6 col-lg-4">
+                    <div class="card shadow">
+                        <div class="card-body p-5">
+                            <h2 class="text-center mb-4">{"Register"}</h2>
+                            if let Some(err) = error.as_ref() {
+                                <div class="alert alert-danger" role="alert">
+                                    {err}
+                                </div>
+                            }
+                            <form {onsubmit}>
+                                <div class="mb-3">
+                                    <label for="username" class="form-label">{"Username"}</label>
+                                    <input
+                                        type="text"
+                                        class="form-control"
+                                        id="username"
+                                        autocomplete="username"
+                                        value={new_user.username.clone()}
+                                        onchange={on_username_change}
+                                        disabled={*submitting}
+                                        required=true
+                                    />
+                                </div>
+                                <div class="mb-3">
+                                    <label for="email" class="form-label">{"Email"}</label>
+                                    <input
+                                        type="email"
+                                        class="form-control"
+                                        id="email"
+                                        autocomplete="email"
+                                        value={new_user.email.clone()}
+                                        onchange={on_email_change}
+                                        disabled={*submitting}
+                                        required=true
+                                    />
+                                </div>
+                                <PasswordFields
+                                    password={new_user.password.clone()}
+                                    username={new_user.username.clone()}
+                                    email={new_user.email.clone()}
+                                    disabled={*submitting}
+                                    on_change={on_password_change}
+                                    on_validity={on_password_validity}
+                                />
+                                <div class="mb-3">
+                                    <label for="invite-code" class="form-label">{"Invite Code"}</label>
+                                    <input
+                                        type="text"
+                                        class="form-control"
+                                        id="invite-code"
+                                        value={new_user.invite_code.clone().unwrap_or_default()}
+                                        onchange={on_invite_change}
+                                        disabled={*submitting}
+                                    />
+                                    <div class="form-text">{"Only needed if the server requires invites"}</div>
+                                </div>
+                                <button
+                                    type="submit"
+                                    class="btn btn-primary w-100"
+                                    disabled={*submitting || !*password_valid}
+                                >
+                                    {"Register"}
+                                </button>
+                            </form>
+                            <div class="text-center mt-3">
+                                <Link<AppRoute> to={AppRoute::Login}>{"Already registered? Log in"}</Link<AppRoute>>
+                            </div>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
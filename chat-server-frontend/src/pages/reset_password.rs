@@ -0,0 +1,108 @@
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::components::user::PasswordFields;
+use crate::routes::{AppRoute, TokenQuery};
+use crate::services::{FetchError, UserService};
+
+/// Page of the emailed password reset link, where a new password is chosen.
+/// Once it is set, the page goes on to the login.
+#[function_component(ResetPasswordPage)]
+pub fn reset_password_page() -> Html {
+    let token = use_location()
+        .and_then(|location| location.query::<TokenQuery>().ok())
+        .unwrap_or_default()
+        .token;
+    let password = use_state(String::new);
+    let password_valid = use_state(|| false);
+    let submitting = use_state(|| false);
+    let error = use_state(|| None::<String>);
+    let navigator = use_navigator().unwrap();
+
+    let on_password_change = {
+        let password = password.clone();
+        Callback::from(move |value: String| password.set(value))
+    };
+
+    let on_password_validity = {
+        let password_valid = password_valid.clone();
+        Callback::from(move |valid: bool| password_valid.set(valid))
+    };
+
+    let onsubmit = {
+        let token = token.clone();
+        let password = password.clone();
+        let password_valid = password_valid.clone();
+        let submitting = submitting.clone();
+        let error = error.clone();
+
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            if !*password_valid {
+                error.set(Some(
+                    "Choose a password the policy accepts and confirm it".to_string(),
+                ));
+                return;
+            }
+
+            submitting.set(true);
+            let submitting = submitting.clone();
+            let error = error.clone();
+            let navigator = navigator.clone();
+            UserService::reset_password(
+                token.clone(),
+                (*password).clone(),
+                Callback::from(move |result: Result<String, FetchError>| {
+                    submitting.set(false);
+                    match result {
+                        Ok(_) => navigator.push(&AppRoute::Login),
+                        Err(e) => error.set(Some(e.to_string())),
+                    }
+                }),
+            );
+        })
+    };
+
+    html! {
+        <div class="container py-5">
+            <div class="row justify-content-center">
+                <div class="col-md-6 col-lg-4">
+                    <div class="card shadow">
+                        <div class="card-body p-5">
+                            <h2 class="text-center mb-4">{"Reset Password"}</h2>
+                            if token.is_empty() {
+                                <div class="alert alert-danger" role="alert">
+                                    {"The link has no token"}
+                                </div>
+                            } else {
+                                if let Some(err) = error.as_ref() {
+                                    <div class="alert alert-danger" role="alert">
+                                        {err}
+                                    </div>
+                                }
+                                <form {onsubmit}>
+                                    <PasswordFields
+                                        password={(*password).clone()}
+                                        disabled={*submitting}
+                                        on_change={on_password_change}
+                                        on_validity={on_password_validity}
+                                    />
+                                    <button
+                                        type="submit"
+                                        class="btn btn-primary w-100"
+                                        disabled={*submitting || !*password_valid}
+                                    >
+                                        {"Set Password"}
+                                    </button>
+                                </form>
+                            }
+                            <div class="text-center mt-3">
+                                <Link<AppRoute> to={AppRoute::ForgotPassword}>{"Send a new link"}</Link<AppRoute>>
+                            </div>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
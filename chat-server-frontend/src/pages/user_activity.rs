@@ -0,0 +1,25 @@
+use crate::components::user::UserActivity;
+use crate::routes::AppRoute;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct UserActivityPageProps {
+    pub user_id: i32,
+}
+
+#[function_component(UserActivityPage)]
+pub fn user_activity_page(props: &UserActivityPageProps) -> Html {
+    html! {
+        <div class="container py-3">
+            <div class="d-flex justify-content-between align-items-center mb-4">
+                <h1 class="mb-0">{"User Activity"}</h1>
+                <Link<AppRoute> classes="btn btn-outline-primary" to={AppRoute::Users}>
+                    <i class="bi bi-arrow-left me-1"></i>
+                    {"Back to Users"}
+                </Link<AppRoute>>
+            </div>
+            <UserActivity user_id={props.user_id} />
+        </div>
+    }
+}
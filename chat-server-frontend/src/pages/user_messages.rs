@@ -0,0 +1,34 @@
+use crate::components::user::UserMessages;
+use crate::routes::AppRoute;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct UserMessagesPageProps {
+    pub user_id: i32,
+}
+
+#[function_component(UserMessagesPage)]
+pub fn user_messages_page(props: &UserMessagesPageProps) -> Html {
+    html! {
+        <div class="container py-3">
+            <div class="d-flex justify-content-between align-items-center mb-4">
+                <h1 class="mb-0">{"User Messages"}</h1>
+                <div class="d-flex gap-2">
+                    <Link<AppRoute>
+                        classes="btn btn-outline-secondary"
+                        to={AppRoute::UserActivity { id: props.user_id }}
+                    >
+                        <i class="bi bi-activity me-1"></i>
+                        {"Activity"}
+                    </Link<AppRoute>>
+                    <Link<AppRoute> classes="btn btn-outline-primary" to={AppRoute::Users}>
+                        <i class="bi bi-arrow-left me-1"></i>
+                        {"Back to Users"}
+                    </Link<AppRoute>>
+                </div>
+            </div>
+            <UserMessages user_id={props.user_id} />
+        </div>
+    }
+}
@@ -0,0 +1,73 @@
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::routes::{AppRoute, TokenQuery};
+use crate::services::{FetchError, UserService};
+
+/// Page of the link emailed to confirm an address. It sends the link's token
+/// to the server as soon as it opens.
+#[function_component(VerifyEmailPage)]
+pub fn verify_email_page() -> Html {
+    let token = use_location()
+        .and_then(|location| location.query::<TokenQuery>().ok())
+        .unwrap_or_default()
+        .token;
+    // The server's answer, once it gave one
+    let result = use_state(|| None::<Result<String, String>>);
+
+    {
+        let result = result.clone();
+        use_effect_with(token, move |token| {
+            if token.is_empty() {
+                result.set(Some(Err("The link has no token".to_string())));
+            } else {
+                UserService::verify_email(
+                    token.clone(),
+                    Callback::from(move |response: Result<String, FetchError>| {
+                        result.set(Some(response.map_err(|e| e.to_string())));
+                    }),
+                );
+            }
+            || ()
+        });
+    }
+
+    let content = match result.as_ref() {
+        None => html! {
+            <div class="d-flex justify-content-center p-3">
+                <div class="spinner-border text-primary" role="status">
+                    <span class="visually-hidden">{"Verifying..."}</span>
+                </div>
+            </div>
+        },
+        Some(Ok(message)) => html! {
+            <div class="alert alert-success" role="alert">
+                <i class="bi bi-check-circle me-2"></i>
+                {message}
+            </div>
+        },
+        Some(Err(err)) => html! {
+            <div class="alert alert-danger" role="alert">
+                {err}
+            </div>
+        },
+    };
+
+    html! {
+        <div class="container py-5">
+            <div class="row justify-content-center">
+                <div class="col-md-6 col-lg-4">
+                    <div class="card shadow">
+                        <div class="card-body p-5">
+                            <h2 class="text-center mb-4">{"Verify Email"}</h2>
+                            {content}
+                            <div class="text-center mt-3">
+                                <Link<AppRoute> to={AppRoute::Login}>{"Go to login"}</Link<AppRoute>>
+                            </div>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -1,37 +1,136 @@
 use gloo_storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 #[derive(Clone, Routable, PartialEq)]
 pub enum AppRoute {
     #[at("/")]
+    Root,
+    #[at("/login")]
     Login,
+    #[at("/register")]
+    Register,
+    #[at("/verify-email")]
+    VerifyEmail,
+    #[at("/forgot-password")]
+    ForgotPassword,
+    #[at("/reset-password")]
+    ResetPassword,
     #[at("/home")]
     Home,
     #[at("/users")]
     Users,
+    #[at("/users/:id/activity")]
+    UserActivity { id: i32 },
+    #[at("/users/:id/messages")]
+    UserMessages { id: i32 },
     #[at("/messages")]
     Messages,
+    #[at("/moderation")]
+    Moderation,
     #[not_found]
     #[at("/404")]
     NotFound,
 }
 
+/// Query of the pages emailed links lead to: `/reset-password?token=...`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenQuery {
+    #[serde(default)]
+    pub token: String,
+}
+
+/// Query of the login page: `/login?next=/users`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoginQuery {
+    /// Path the visitor was sent to log in from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+impl LoginQuery {
+    /// Returns the page to go to after logging in: the one the visitor came
+    /// from if it is a page of this app that needs a login, otherwise home.
+    pub fn destination(&self) -> AppRoute {
+        self.next
+            .as_deref()
+            .and_then(AppRoute::recognize)
+            .filter(|route| {
+                !matches!(
+                    route,
+                    AppRoute::Root
+                        | AppRoute::Login
+                        | AppRoute::Register
+                        | AppRoute::VerifyEmail
+                        | AppRoute::ForgotPassword
+                        | AppRoute::ResetPassword
+                        | AppRoute::NotFound
+                )
+            })
+            .unwrap_or(AppRoute::Home)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RequireAuthProps {
+    pub children: Children,
+}
+
+/// Renders its children only for logged-in visitors.
+///
+/// Others are sent to the login page with the current path in `next`, so
+/// they come back here after logging in.
+#[function_component(RequireAuth)]
+pub fn require_auth(props: &RequireAuthProps) -> Html {
+    let navigator = use_navigator().unwrap();
+    let location = use_location();
+    let logged_in = LocalStorage::get::<String>("token").is_ok();
+
+    use_effect_with(logged_in, move |logged_in| {
+        if !*logged_in {
+            let query = LoginQuery {
+                next: location.map(|location| location.path().to_string()),
+            };
+            let _ = navigator.replace_with_query(&AppRoute::Login, &query);
+        }
+    });
+
+    if logged_in {
+        html! { <>{ for props.children.iter() }</> }
+    } else {
+        html! {}
+    }
+}
+
 pub fn switch(route: AppRoute) -> Html {
     match route {
+        AppRoute::Root => html! { <Redirect<AppRoute> to={AppRoute::Home} /> },
         AppRoute::Login => html! { <crate::pages::login::LoginPage /> },
-        AppRoute::Home | AppRoute::Users | AppRoute::Messages => {
-            if LocalStorage::get::<String>("token").is_ok() {
-                match route {
-                    AppRoute::Home => html! { <crate::pages::home::HomePage /> },
-                    AppRoute::Users => html! { <crate::pages::users::UsersPage /> },
-                    AppRoute::Messages => html! { <crate::pages::messages::MessagesPage /> },
-                    _ => unreachable!(),
-                }
-            } else {
-                html! { <Redirect<AppRoute> to={AppRoute::Login} /> }
-            }
-        }
+        AppRoute::Register => html! { <crate::pages::register::RegisterPage /> },
+        AppRoute::VerifyEmail => html! { <crate::pages::verify_email::VerifyEmailPage /> },
+        AppRoute::ForgotPassword => html! {
+            <crate::pages::forgot_password::ForgotPasswordPage />
+        },
+        AppRoute::ResetPassword => html! { <crate::pages::reset_password::ResetPasswordPage /> },
+        AppRoute::Home => html! {
+            <RequireAuth><crate::pages::home::HomePage /></RequireAuth>
+        },
+        AppRoute::Users => html! {
+            <RequireAuth><crate::pages::users::UsersPage /></RequireAuth>
+        },
+        AppRoute::UserActivity { id } => html! {
+            <RequireAuth><crate::pages::user_activity::UserActivityPage user_id={id} /></RequireAuth>
+        },
+        AppRoute::UserMessages { id } => html! {
+            <RequireAuth><crate::pages::user_messages::UserMessagesPage user_id={id} /></RequireAuth>
+        },
+        AppRoute::Messages => html! {
+            <RequireAuth><crate::pages::messages::MessagesPage /></RequireAuth>
+        },
+        AppRoute::Moderation => html! {
+            <RequireAuth><crate::pages::moderation::ModerationPage /></RequireAuth>
+        },
         AppRoute::NotFound => html! { <h1>{"404 - Not Found"}</h1> },
     }
 }
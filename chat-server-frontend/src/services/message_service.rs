@@ -1,12 +1,60 @@
-use crate::models::Message;
+use crate::models::{Message, MessageRevision, MessageType};
 use crate::services::FetchError;
 use gloo_net::http::Request;
 use gloo_storage::{LocalStorage, Storage};
-use wasm_bindgen_futures::spawn_local;
+use js_sys::{Array, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, ReadableStreamDefaultReader, Url};
 use yew::Callback;
 
 const API_BASE_URL: &str = "http://localhost:8001";
 
+/// How far a running export has got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportProgress {
+    /// Rows received so far; approximate while a row is split across chunks
+    pub rows: usize,
+    /// Rows the server announced, if it did
+    pub total: Option<usize>,
+    pub bytes: usize,
+}
+
+impl ExportProgress {
+    /// Share of the rows received, between 0 and 100.
+    pub fn percent(&self) -> Option<usize> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.rows * 100 / total).min(100))
+    }
+}
+
+pub(super) fn js_error(value: JsValue) -> FetchError {
+    FetchError::Request(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+}
+
+/// Hands content to the browser as a file download.
+pub(super) fn save_file(
+    file_name: &str,
+    content_type: &str,
+    chunks: &Array,
+) -> Result<(), JsValue> {
+    let options = BlobPropertyBag::new();
+    options.set_type(content_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(chunks, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("No document"))?;
+    let link: HtmlAnchorElement = document.create_element("a")?.unchecked_into();
+    link.set_href(&url);
+    link.set_download(file_name);
+    link.click();
+
+    Url::revoke_object_url(&url)
+}
+
 pub struct MessageService;
 
 impl MessageService {
@@ -16,6 +64,57 @@ impl MessageService {
             .map(|token| ("Authorization".to_string(), format!("Bearer {}", token)))
     }
 
+    /// URL the browser can load a message's attachment from. It carries a
+    /// download token from [`Self::issue_download_token`], as links and media
+    /// elements cannot send headers.
+    pub fn attachment_url(message_id: i32, download_token: &str) -> String {
+        format!(
+            "{}/messages/{}/attachment?token={}",
+            API_BASE_URL, message_id, download_token
+        )
+    }
+
+    /// URL of a downscaled copy of an image message's attachment, for previews.
+    pub fn thumbnail_url(message_id: i32, download_token: &str) -> String {
+        format!(
+            "{}/messages/{}/thumbnail?token={}",
+            API_BASE_URL, message_id, download_token
+        )
+    }
+
+    /// Requests a short-lived token for attachment URLs, so the session token
+    /// stays out of them.
+    pub fn issue_download_token(callback: Callback<Result<String, FetchError>>) {
+        spawn_local(async move {
+            let mut request = Request::post(&format!("{}/messages/download-token", API_BASE_URL));
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        match response.json::<serde_json::Value>().await {
+                            Ok(json) => json
+                                .get("token")
+                                .and_then(|token| token.as_str())
+                                .map(str::to_string)
+                                .ok_or_else(|| {
+                                    FetchError::Deserialize("Missing token".to_string())
+                                }),
+                            Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                        }
+                    } else {
+                        Err(FetchError::Status(response.status()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
     pub fn fetch_messages(callback: Callback<Result<Vec<Message>, FetchError>>) {
         spawn_local(async move {
             let mut request = Request::get(&format!("{}/messages", API_BASE_URL));
@@ -41,9 +140,22 @@ impl MessageService {
         });
     }
 
-    pub fn delete_message(id: i32, callback: Callback<Result<(), FetchError>>) {
+    /// Fetches the messages of a workspace around the one numbered `seq`, at
+    /// most `radius` on each side, in order.
+    pub fn fetch_context(
+        workspace_id: i32,
+        seq: i64,
+        radius: i64,
+        callback: Callback<Result<Vec<Message>, FetchError>>,
+    ) {
         spawn_local(async move {
-            let mut request = Request::delete(&format!("{}/messages/{}", API_BASE_URL, id));
+            let mut request = Request::get(&format!(
+                "{}/messages?workspace_id={}&after_seq={}&until_seq={}",
+                API_BASE_URL,
+                workspace_id,
+                (seq - radius - 1).max(0),
+                seq + radius
+            ));
 
             if let Some((key, value)) = Self::get_auth_header() {
                 request = request.header(&key, &value);
@@ -52,7 +164,10 @@ impl MessageService {
             let result = match request.send().await {
                 Ok(response) => {
                     if response.ok() {
-                        Ok(())
+                        match response.json::<Vec<Message>>().await {
+                            Ok(data) => Ok(data),
+                            Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                        }
                     } else {
                         Err(FetchError::Status(response.status()))
                     }
@@ -63,10 +178,127 @@ impl MessageService {
         });
     }
 
-    pub fn delete_messages_by_user(user_id: i32, callback: Callback<Result<(), FetchError>>) {
+    /// Fetches the earlier versions of an edited message, oldest first.
+    pub fn fetch_revisions(
+        message_id: i32,
+        callback: Callback<Result<Vec<MessageRevision>, FetchError>>,
+    ) {
         spawn_local(async move {
-            let mut request =
-                Request::delete(&format!("{}/messages/user/{}", API_BASE_URL, user_id));
+            let mut request = Request::get(&format!(
+                "{}/messages/{}/revisions",
+                API_BASE_URL, message_id
+            ));
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        match response.json::<Vec<MessageRevision>>().await {
+                            Ok(data) => Ok(data),
+                            Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                        }
+                    } else {
+                        Err(FetchError::Status(response.status()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
+    /// Downloads the messages matching the filters as a CSV file, reporting
+    /// progress while the export streams in.
+    pub fn export_messages(
+        sender_id: Option<i32>,
+        message_type: Option<MessageType>,
+        on_progress: Callback<ExportProgress>,
+        callback: Callback<Result<(), FetchError>>,
+    ) {
+        spawn_local(async move {
+            let mut params = Vec::new();
+            if let Some(sender_id) = sender_id {
+                params.push(format!("sender_id={}", sender_id));
+            }
+            if let Some(message_type) = message_type {
+                params.push(format!("message_type={:?}", message_type));
+            }
+            let mut request = Request::get(&format!(
+                "{}/messages/export?{}",
+                API_BASE_URL,
+                params.join("&")
+            ));
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if !response.ok() {
+                        Err(FetchError::Status(response.status()))
+                    } else if let Some(body) = response.body() {
+                        let total = response
+                            .headers()
+                            .get("X-Export-Rows")
+                            .and_then(|rows| rows.parse().ok());
+                        let reader: ReadableStreamDefaultReader =
+                            body.get_reader().unchecked_into();
+                        Self::read_export(reader, total, on_progress).await
+                    } else {
+                        Err(FetchError::Request("Empty response".to_string()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
+    /// Reads the export chunk by chunk and saves it once complete.
+    async fn read_export(
+        reader: ReadableStreamDefaultReader,
+        total: Option<usize>,
+        on_progress: Callback<ExportProgress>,
+    ) -> Result<(), FetchError> {
+        let chunks = Array::new();
+        let mut progress = ExportProgress {
+            rows: 0,
+            total,
+            bytes: 0,
+        };
+        let mut lines = 0;
+
+        loop {
+            let read = JsFuture::from(reader.read()).await.map_err(js_error)?;
+            let done = Reflect::get(&read, &"done".into())
+                .map_err(js_error)?
+                .as_bool()
+                .unwrap_or(true);
+            if done {
+                break;
+            }
+
+            let chunk: Uint8Array = Reflect::get(&read, &"value".into())
+                .map_err(js_error)?
+                .unchecked_into();
+            lines += chunk.to_vec().iter().filter(|byte| **byte == b'\n').count();
+            // The first line is the header
+            progress.rows = lines.saturating_sub(1);
+            progress.bytes += chunk.length() as usize;
+            chunks.push(&chunk);
+            on_progress.emit(progress);
+        }
+
+        save_file("messages.csv", "text/csv", &chunks).map_err(js_error)
+    }
+
+    pub fn delete_message(id: i32, callback: Callback<Result<(), FetchError>>) {
+        spawn_local(async move {
+            let mut request = Request::delete(&format!("{}/messages/{}", API_BASE_URL, id));
 
             if let Some((key, value)) = Self::get_auth_header() {
                 request = request.header(&key, &value);
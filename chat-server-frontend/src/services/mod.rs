@@ -1,5 +1,7 @@
 mod message_service;
+mod report_service;
 mod user_service;
 
-pub use message_service::MessageService;
+pub use message_service::{ExportProgress, MessageService};
+pub use report_service::ReportService;
 pub use user_service::{FetchError, UserService};
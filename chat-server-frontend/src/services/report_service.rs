@@ -0,0 +1,83 @@
+use crate::models::{Report, ReportStatus};
+use crate::services::FetchError;
+use gloo_net::http::Request;
+use gloo_storage::{LocalStorage, Storage};
+use serde_json::json;
+use wasm_bindgen_futures::spawn_local;
+use yew::Callback;
+
+const API_BASE_URL: &str = "http://localhost:8001";
+
+pub struct ReportService;
+
+impl ReportService {
+    fn get_auth_header() -> Option<(String, String)> {
+        LocalStorage::get::<String>("token")
+            .ok()
+            .map(|token| ("Authorization".to_string(), format!("Bearer {}", token)))
+    }
+
+    /// Fetches the reports of the workspaces the user moderates, newest first,
+    /// optionally only those with the given status.
+    pub fn fetch_reports(
+        status: Option<ReportStatus>,
+        callback: Callback<Result<Vec<Report>, FetchError>>,
+    ) {
+        spawn_local(async move {
+            let url = match status {
+                Some(status) => format!("{}/reports?status={}", API_BASE_URL, status.as_str()),
+                None => format!("{}/reports", API_BASE_URL),
+            };
+            let mut request = Request::get(&url);
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        match response.json::<Vec<Report>>().await {
+                            Ok(data) => Ok(data),
+                            Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                        }
+                    } else {
+                        Err(FetchError::Status(response.status()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
+    /// Resolves an open report; the server carries out the resolution.
+    pub fn resolve_report(
+        id: i32,
+        status: ReportStatus,
+        callback: Callback<Result<(), FetchError>>,
+    ) {
+        spawn_local(async move {
+            let mut request = Request::put(&format!("{}/reports/{}", API_BASE_URL, id));
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.json(&json!({ "status": status })) {
+                Ok(request) => match request.send().await {
+                    Ok(response) => {
+                        if response.ok() {
+                            Ok(())
+                        } else {
+                            Err(FetchError::Status(response.status()))
+                        }
+                    }
+                    Err(e) => Err(FetchError::Request(e.to_string())),
+                },
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+}
@@ -1,6 +1,12 @@
-use crate::models::{NewUser, User};
+use crate::models::{
+    NewUser, PasswordCheck, User, UserConnection, UserImportReport, UserMessagesPage, UserPage,
+    UserSession, UserStats, UserStatus, UserUsage,
+};
+use crate::services::message_service::{js_error, save_file};
 use gloo_net::http::Request;
 use gloo_storage::{LocalStorage, Storage};
+use js_sys::{Array, Uint8Array};
+use serde::de::DeserializeOwned;
 use std::fmt;
 use wasm_bindgen_futures::spawn_local;
 use yew::Callback;
@@ -12,6 +18,8 @@ pub enum FetchError {
     Request(String),
     Deserialize(String),
     Status(u16),
+    /// The server refused the request and said why
+    Rejected(String),
 }
 
 impl fmt::Display for FetchError {
@@ -20,6 +28,7 @@ impl fmt::Display for FetchError {
             FetchError::Request(err) => write!(f, "Network error: {}", err),
             FetchError::Deserialize(err) => write!(f, "Failed to parse response: {}", err),
             FetchError::Status(status) => write!(f, "Error: {}", status),
+            FetchError::Rejected(reason) => write!(f, "{}", reason),
         }
     }
 }
@@ -58,6 +67,114 @@ impl UserService {
         });
     }
 
+    /// Fetches one page of users, `page` starting at 1.
+    pub fn fetch_user_page(
+        page: u32,
+        per_page: u32,
+        callback: Callback<Result<UserPage, FetchError>>,
+    ) {
+        Self::fetch_json(
+            format!("{}/users?page={}&per_page={}", API_BASE_URL, page, per_page),
+            callback,
+        );
+    }
+
+    pub fn fetch_user(user_id: i32, callback: Callback<Result<User, FetchError>>) {
+        Self::fetch_json(format!("{}/users/{}", API_BASE_URL, user_id), callback);
+    }
+
+    /// Fetches message statistics of a user with daily counts for the last `days` days.
+    pub fn fetch_user_stats(
+        user_id: i32,
+        days: u32,
+        callback: Callback<Result<UserStats, FetchError>>,
+    ) {
+        Self::fetch_json(
+            format!("{}/users/{}/stats?days={}", API_BASE_URL, user_id, days),
+            callback,
+        );
+    }
+
+    pub fn fetch_user_sessions(
+        user_id: i32,
+        callback: Callback<Result<Vec<UserSession>, FetchError>>,
+    ) {
+        Self::fetch_json(
+            format!("{}/users/{}/sessions", API_BASE_URL, user_id),
+            callback,
+        );
+    }
+
+    pub fn fetch_user_connections(
+        user_id: i32,
+        callback: Callback<Result<Vec<UserConnection>, FetchError>>,
+    ) {
+        Self::fetch_json(
+            format!("{}/users/{}/connections", API_BASE_URL, user_id),
+            callback,
+        );
+    }
+
+    /// Fetches how much attachment storage a user uses and their quota.
+    pub fn fetch_user_usage(user_id: i32, callback: Callback<Result<UserUsage, FetchError>>) {
+        Self::fetch_json(
+            format!("{}/users/{}/usage", API_BASE_URL, user_id),
+            callback,
+        );
+    }
+
+    /// Fetches one page of the messages a user sent, newest first. Pass the
+    /// `next_before` of the previous page as `before` to continue from there.
+    pub fn fetch_user_messages(
+        user_id: i32,
+        before: Option<i32>,
+        limit: u32,
+        callback: Callback<Result<UserMessagesPage, FetchError>>,
+    ) {
+        let mut url = format!(
+            "{}/users/{}/messages?limit={}",
+            API_BASE_URL, user_id, limit
+        );
+        if let Some(before) = before {
+            url.push_str(&format!("&before={}", before));
+        }
+        Self::fetch_json(url, callback);
+    }
+
+    /// Fetches the statuses users have set.
+    pub fn fetch_statuses(callback: Callback<Result<Vec<UserStatus>, FetchError>>) {
+        Self::fetch_json(format!("{}/users/statuses", API_BASE_URL), callback);
+    }
+
+    /// Sends an authenticated GET request and parses the JSON response.
+    fn fetch_json<T: DeserializeOwned + 'static>(
+        url: String,
+        callback: Callback<Result<T, FetchError>>,
+    ) {
+        spawn_local(async move {
+            let mut request = Request::get(&url);
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        match response.json::<T>().await {
+                            Ok(data) => Ok(data),
+                            Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                        }
+                    } else {
+                        Err(FetchError::Status(response.status()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
     pub fn create_user(new_user: NewUser, callback: Callback<Result<User, FetchError>>) {
         spawn_local(async move {
             let mut request = Request::post(&format!("{}/users", API_BASE_URL))
@@ -75,6 +192,55 @@ impl UserService {
                             Ok(user) => Ok(user),
                             Err(e) => Err(FetchError::Deserialize(e.to_string())),
                         }
+                    } else if matches!(response.status(), 400 | 409) {
+                        // Either a reason or a password policy error with its message
+                        let reason =
+                            response
+                                .json::<serde_json::Value>()
+                                .await
+                                .ok()
+                                .and_then(|body| match body {
+                                    serde_json::Value::String(reason) => Some(reason),
+                                    body => body["message"].as_str().map(str::to_string),
+                                });
+                        Err(reason
+                            .map(FetchError::Rejected)
+                            .unwrap_or(FetchError::Status(response.status())))
+                    } else {
+                        Err(FetchError::Status(response.status()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
+    /// Rates a password against the password policy without setting it.
+    /// The username and email, which make a password easier to guess, may be
+    /// empty.
+    pub fn check_password(
+        password: String,
+        username: String,
+        email: String,
+        callback: Callback<Result<PasswordCheck, FetchError>>,
+    ) {
+        spawn_local(async move {
+            let request = Request::post(&format!("{}/users/password/check", API_BASE_URL))
+                .json(&serde_json::json!({
+                    "password": password,
+                    "username": username,
+                    "email": email,
+                }))
+                .unwrap();
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        match response.json::<PasswordCheck>().await {
+                            Ok(check) => Ok(check),
+                            Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                        }
                     } else {
                         Err(FetchError::Status(response.status()))
                     }
@@ -85,6 +251,74 @@ impl UserService {
         });
     }
 
+    /// Confirms an email address with the token of a verification link.
+    pub fn verify_email(token: String, callback: Callback<Result<String, FetchError>>) {
+        Self::post_account_request(
+            "users/email/verify",
+            serde_json::json!({ "token": token }),
+            callback,
+        );
+    }
+
+    /// Asks for a password reset link to be emailed to an address.
+    pub fn forgot_password(email: String, callback: Callback<Result<String, FetchError>>) {
+        Self::post_account_request(
+            "users/password/forgot",
+            serde_json::json!({ "email": email }),
+            callback,
+        );
+    }
+
+    /// Sets a new password with the token of a password reset link.
+    pub fn reset_password(
+        token: String,
+        password: String,
+        callback: Callback<Result<String, FetchError>>,
+    ) {
+        Self::post_account_request(
+            "users/password/reset",
+            serde_json::json!({ "token": token, "password": password }),
+            callback,
+        );
+    }
+
+    /// Posts a request of the emailed-link flows, which need no login, and
+    /// returns the server's message, or its reason for a rejected request.
+    fn post_account_request(
+        path: &'static str,
+        body: serde_json::Value,
+        callback: Callback<Result<String, FetchError>>,
+    ) {
+        spawn_local(async move {
+            let request = Request::post(&format!("{}/{}", API_BASE_URL, path))
+                .json(&body)
+                .unwrap();
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    // Either a message or a password policy error with its message
+                    let message = response.json::<serde_json::Value>().await.ok().and_then(
+                        |body| match body {
+                            serde_json::Value::String(message) => Some(message),
+                            body => body["message"].as_str().map(str::to_string),
+                        },
+                    );
+                    match (status, message) {
+                        (200..=299, Some(message)) => Ok(message),
+                        (200..=299, None) => {
+                            Err(FetchError::Deserialize("Expected a message".to_string()))
+                        }
+                        (400 | 429, Some(reason)) => Err(FetchError::Rejected(reason)),
+                        (status, _) => Err(FetchError::Status(status)),
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
     pub fn delete_user(user_id: i32, callback: Callback<Result<(), FetchError>>) {
         spawn_local(async move {
             let mut request = Request::delete(&format!("{}/users/{}", API_BASE_URL, user_id));
@@ -106,4 +340,71 @@ impl UserService {
             callback.emit(result);
         });
     }
+
+    /// Checks a CSV file of users and, unless `dry_run` is set, creates the
+    /// users of its valid rows. Files the server cannot read at all fail with
+    /// its reason.
+    pub fn import_users(
+        csv: String,
+        dry_run: bool,
+        callback: Callback<Result<UserImportReport, FetchError>>,
+    ) {
+        spawn_local(async move {
+            let mut request = Request::post(&format!(
+                "{}/users/import?dry_run={}",
+                API_BASE_URL, dry_run
+            ))
+            .header("Content-Type", "text/csv")
+            .body(csv);
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => match response.status() {
+                    200..=299 => match response.json::<UserImportReport>().await {
+                        Ok(report) => Ok(report),
+                        Err(e) => Err(FetchError::Deserialize(e.to_string())),
+                    },
+                    400 | 413 => match response.json::<String>().await {
+                        Ok(reason) => Err(FetchError::Rejected(reason)),
+                        Err(_) => Err(FetchError::Status(response.status())),
+                    },
+                    status => Err(FetchError::Status(status)),
+                },
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
+
+    /// Downloads every user as a CSV file, without password hashes.
+    pub fn export_users(callback: Callback<Result<(), FetchError>>) {
+        spawn_local(async move {
+            let mut request = Request::get(&format!("{}/users/export", API_BASE_URL));
+
+            if let Some((key, value)) = Self::get_auth_header() {
+                request = request.header(&key, &value);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if response.ok() {
+                        match response.binary().await {
+                            Ok(bytes) => {
+                                let chunks = Array::of1(&Uint8Array::from(bytes.as_slice()));
+                                save_file("users.csv", "text/csv", &chunks).map_err(js_error)
+                            }
+                            Err(e) => Err(FetchError::Request(e.to_string())),
+                        }
+                    } else {
+                        Err(FetchError::Status(response.status()))
+                    }
+                }
+                Err(e) => Err(FetchError::Request(e.to_string())),
+            };
+            callback.emit(result);
+        });
+    }
 }
@@ -0,0 +1,182 @@
+//! Client-side cache of the users and messages lists, and of the token for
+//! attachment URLs.
+//!
+//! Pages read the lists from the [`Store`] instead of keeping their own copy,
+//! so returning to a page shows the cached list at once while it is refreshed
+//! in the background. The users page keeps the page of users it shows apart
+//! from the full list other pages use, and user changes apply to both.
+//! Mutations are applied to the store before the request is sent and
+//! reconciled with the server's response: a failed deletion puts the item
+//! back, and a created user replaces its placeholder.
+
+use crate::models::{Message, User, UserPage};
+use std::rc::Rc;
+use yew::prelude::*;
+
+/// The cached lists; `None` until first loaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StoreState {
+    pub users: Option<Vec<User>>,
+    /// Page of users shown on the users page
+    pub user_page: Option<UserPage>,
+    pub messages: Option<Vec<Message>>,
+    /// Token for attachment and thumbnail URLs, kept fresh by
+    /// [`DownloadTokens`](crate::components::messages::DownloadTokens)
+    pub download_token: Option<String>,
+    /// ID for the next placeholder of a user being created
+    next_pending_id: i32,
+}
+
+/// Changes to the cached lists.
+pub enum StoreAction {
+    UsersLoaded(Vec<User>),
+    UserPageLoaded(UserPage),
+    /// A user is being created; shown under the negative placeholder ID from
+    /// [`StoreState::pending_id`] until the server confirms it
+    UserPending(User),
+    /// The server created the user shown under `pending_id`
+    UserCreated {
+        pending_id: i32,
+        user: User,
+    },
+    UserRemoved(i32),
+    /// A removal failed; puts the user back where it was on the page, and in
+    /// ID order in the full list
+    UserRestored {
+        user: User,
+        index: usize,
+    },
+    MessagesLoaded(Vec<Message>),
+    MessageRemoved(i32),
+    /// A removal failed; puts the message back where it was
+    MessageRestored {
+        message: Message,
+        index: usize,
+    },
+    /// A download token was issued, or dropped on logout
+    DownloadTokenChanged(Option<String>),
+}
+
+impl StoreState {
+    /// Returns the ID the next [`StoreAction::UserPending`] placeholder gets.
+    pub fn pending_id(&self) -> i32 {
+        -(self.next_pending_id + 1)
+    }
+}
+
+/// Returns whether a user is a placeholder for one still being created.
+pub fn is_pending(user: &User) -> bool {
+    user.id < 0
+}
+
+impl Reducible for StoreState {
+    type Action = StoreAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut state = (*self).clone();
+        match action {
+            StoreAction::UsersLoaded(users) => {
+                // Keep placeholders of users still being created
+                let pending = state.users.iter().flatten().filter(|user| is_pending(user));
+                state.users = Some(pending.cloned().chain(users).collect());
+            }
+            StoreAction::UserPageLoaded(mut page) => {
+                let pending = state
+                    .user_page
+                    .iter()
+                    .flat_map(|page| &page.users)
+                    .filter(|user| is_pending(user));
+                page.users = pending.cloned().chain(page.users).collect();
+                state.user_page = Some(page);
+            }
+            StoreAction::UserPending(user) => {
+                state.next_pending_id = state.next_pending_id.max(-user.id);
+                if let Some(page) = &mut state.user_page {
+                    page.users.insert(0, user.clone());
+                    page.total += 1;
+                }
+                state.users.get_or_insert_with(Vec::new).push(user);
+            }
+            StoreAction::UserCreated { pending_id, user } => {
+                if let Some(page) = &mut state.user_page {
+                    if let Some(placeholder) = page.users.iter_mut().find(|u| u.id == pending_id) {
+                        *placeholder = user.clone();
+                    }
+                }
+                let users = state.users.get_or_insert_with(Vec::new);
+                users.retain(|existing| existing.id != pending_id && existing.id != user.id);
+                users.push(user);
+            }
+            StoreAction::UserRemoved(user_id) => {
+                if let Some(page) = &mut state.user_page {
+                    let before = page.users.len();
+                    page.users.retain(|user| user.id != user_id);
+                    if page.users.len() < before {
+                        page.total = page.total.saturating_sub(1);
+                    }
+                }
+                if let Some(users) = &mut state.users {
+                    users.retain(|user| user.id != user_id);
+                }
+            }
+            StoreAction::UserRestored { user, index } => {
+                if let Some(page) = &mut state.user_page {
+                    if page.users.iter().all(|existing| existing.id != user.id) {
+                        page.users.insert(index.min(page.users.len()), user.clone());
+                        page.total += 1;
+                    }
+                }
+                if let Some(users) = &mut state.users {
+                    if users.iter().all(|existing| existing.id != user.id) {
+                        let position = users
+                            .iter()
+                            .position(|existing| existing.id > user.id)
+                            .unwrap_or(users.len());
+                        users.insert(position, user);
+                    }
+                }
+            }
+            StoreAction::MessagesLoaded(messages) => state.messages = Some(messages),
+            StoreAction::MessageRemoved(message_id) => {
+                if let Some(messages) = &mut state.messages {
+                    messages.retain(|message| message.id != message_id);
+                }
+            }
+            StoreAction::MessageRestored { message, index } => {
+                let messages = state.messages.get_or_insert_with(Vec::new);
+                if messages.iter().all(|existing| existing.id != message.id) {
+                    messages.insert(index.min(messages.len()), message);
+                }
+            }
+            StoreAction::DownloadTokenChanged(token) => state.download_token = token,
+        }
+        Rc::new(state)
+    }
+}
+
+/// Handle to the cached lists, provided by [`StoreProvider`].
+pub type Store = UseReducerHandle<StoreState>;
+
+#[derive(Properties, PartialEq)]
+pub struct StoreProviderProps {
+    pub children: Children,
+}
+
+/// Keeps the cached lists for the lifetime of the app and provides them to
+/// its children.
+#[function_component(StoreProvider)]
+pub fn store_provider(props: &StoreProviderProps) -> Html {
+    let store = use_reducer(StoreState::default);
+
+    html! {
+        <ContextProvider<Store> context={store}>
+            { for props.children.iter() }
+        </ContextProvider<Store>>
+    }
+}
+
+/// Returns the store provided by [`StoreProvider`].
+#[hook]
+pub fn use_store() -> Store {
+    use_context::<Store>().expect("use_store outside StoreProvider")
+}
@@ -0,0 +1,136 @@
+## Authentication
+
+auth-success = Přihlášení proběhlo úspěšně
+auth-invalid-credentials = Neplatné přihlašovací údaje
+auth-no-workspace = Uživatel není členem žádného pracovního prostoru
+auth-required = Je vyžadováno přihlášení
+auth-locked = Příliš mnoho neúspěšných přihlášení tohoto uživatele, zkuste to znovu za { $seconds ->
+        [one] { $seconds } sekundu
+        [few] { $seconds } sekundy
+       *[other] { $seconds } sekund
+    }
+auth-session-replaced = Byli jste odhlášeni, protože jste se přihlásili z jiného připojení
+auth-certificate-unknown = Klientský certifikát není registrován, přihlaste se heslem
+
+## Registration
+
+register-logged-in = Již jste přihlášeni, pro registraci dalšího účtu se odhlaste
+register-invite-required = K registraci je potřeba kód pozvánky
+register-invite-invalid = Kód pozvánky je neplatný nebo vypršel
+register-password-rejected = Heslo nesplňuje pravidla pro hesla: { $violations }
+register-username-invalid = Uživatelské jméno není platné: { $error }
+register-username-taken = Uživatelské jméno je již obsazené
+register-email-taken = E-mailová adresa je již registrována
+
+## Acknowledgments
+
+message-sent = Zpráva byla odeslána
+ephemeral-sent = Dočasná zpráva byla odeslána, vyprší v { $expires_at }
+file-sent = Soubor '{ $name }' byl odeslán
+image-sent = Obrázek '{ $name }' byl odeslán
+
+## Connections and workspaces
+
+client-disconnected = Některý klient se odpojil
+protocol-unsupported = Tento server používá protokol verze { $supported }, aktualizujte prosím klienta
+frame-too-large = Zprávy mohou mít nejvýše { $limit } bajtů, spojení se ukončuje
+workspace-switched = Přepnuto do pracovního prostoru '{ $workspace }'
+workspace-not-member = Nejste členem pracovního prostoru '{ $slug }'
+workspace-read-only = Pracovní prostor '{ $workspace }' je jen pro čtení, psát mohou pouze správci a moderátoři
+workspace-switched-read-only = Přepnuto do pracovního prostoru '{ $workspace }', který je jen pro čtení
+workspace-announcement-only-on = Pracovní prostor '{ $workspace }' je nyní jen pro oznámení, psát mohou pouze správci a moderátoři
+workspace-announcement-only-off = Do pracovního prostoru '{ $workspace }' mohou opět psát všichni
+
+## Rejected messages
+
+feature-unavailable = Tato funkce není dostupná
+rate-limited = Posíláte zprávy příliš rychle, zkuste to znovu za { $seconds ->
+        [one] { $seconds } sekundu
+        [few] { $seconds } sekundy
+       *[other] { $seconds } sekund
+    }
+blocked-words = Vaše zpráva obsahuje zakázaná slova
+muted = Máte ztlumený chat ještě { $seconds ->
+        [one] { $seconds } sekundu
+        [few] { $seconds } sekundy
+       *[other] { $seconds } sekund
+    }
+spam-muted = Vaše zpráva vypadá jako spam ({ $kind ->
+        [duplicates] opakovaně stejná zpráva
+        [mentions] příliš mnoho zmínek
+       *[links] příliš mnoho odkazů
+    }), máte ztlumený chat na { $seconds ->
+        [one] { $seconds } sekundu
+        [few] { $seconds } sekundy
+       *[other] { $seconds } sekund
+    }
+attachment-corrupted = Příloha '{ $name }' byla při přenosu poškozena
+image-undecodable = Obrázek '{ $name }' nelze přečíst
+attachment-undecryptable = Přílohu '{ $name }' nelze dešifrovat: { $error }
+attachment-name-invalid = '{ $name }' nelze použít jako název souboru
+attachment-extension-not-allowed = Soubory s příponou '.{ $extension }' nejsou povoleny
+attachment-extension-missing = Soubory bez přípony nejsou povoleny
+attachment-type-not-allowed = Přílohy typu '{ $mime }' nejsou povoleny
+attachment-not-image = '{ $name }' není obrázek (zjištěno { $mime })
+attachment-image-mismatch = '{ $name }' má příponu obrázku, ale obsahuje data typu { $mime }
+attachment-quota-exceeded = Vaše přílohy zabírají { $used } z { $quota } MiB, na tuto už není místo
+attachment-transfer-cap-exceeded = Dnes jste přenesli { $used } z { $cap } MiB příloh, zkuste to znovu zítra
+attachment-not-stored = Přílohu '{ $name }' nelze uložit, pošlete ji prosím znovu
+quote-not-found = Zprávu #{ $id } nelze citovat, v tomto pracovním prostoru neexistuje
+poll-invalid = Anketa potřebuje otázku a 2 až { $max } neprázdných možností
+poll-not-found = Anketa #{ $id } v tomto pracovním prostoru neexistuje
+poll-invalid-option = Anketa #{ $id } má { $count } možností
+poll-already-voted = V anketě #{ $id } jste již hlasovali
+status-too-long = Text stavu může mít nejvýše { $max } znaků
+report-filed = Zpráva #{ $id } byla nahlášena moderátorům jako hlášení #{ $report }
+report-message-not-found = Zprávu #{ $id } nelze nahlásit, ve vašich pracovních prostorech neexistuje
+report-already-filed = Zprávu #{ $id } jste již nahlásili
+report-reason-invalid = Hlášení potřebuje důvod o nejvýše { $max } znacích
+download-not-found = Zpráva #{ $id } nemá v tomto pracovním prostoru uloženou přílohu
+edit-message-not-found = Zprávu #{ $id } nelze změnit, v tomto pracovním prostoru neexistuje
+edit-not-sender = Zprávu #{ $id } může změnit pouze její odesílatel
+edit-not-text = Zpráva #{ $id } je příloha, upravit lze pouze text
+
+## Terms
+
+terms-required = Před psaním si prosím přečtěte podmínky serveru a přijměte je příkazem /accept:
+    { $terms }
+terms-not-accepted = Před psaním musíte přijmout podmínky serveru příkazem /accept:
+    { $terms }
+terms-accepted = Děkujeme za přijetí podmínek
+
+## Slash commands
+
+command-unknown = Neznámý příkaz /{ $command }, seznam příkazů vypíše /help
+command-help = - vypíše dostupné příkazy
+command-me-usage = Použití: /me <činnost>
+
+## Notifications
+
+mention = { $sender } vás zmínil(a): { $preview }
+spam-moderator-notice = Uživatel { $user } má za spam ztlumený chat na { $seconds } s: { $kind ->
+        [duplicates] opakovaně stejná zpráva
+        [mentions] příliš mnoho zmínek
+       *[links] příliš mnoho odkazů
+    }
+report-moderator-notice = Uživatel { $reporter } nahlásil zprávu uživatele { $sender }: { $reason }
+report-warning = Moderátor pracovního prostoru { $workspace } vás varoval kvůli jedné z vašich zpráv
+report-banned = Moderátor vás kvůli jedné z vašich zpráv odebral z pracovního prostoru { $workspace }
+
+## Passwords
+
+password-changed = Vaše heslo bylo změněno
+password-wrong = Současné heslo je nesprávné
+password-rejected = Nové heslo nesplňuje pravidla pro hesla: { $violations }
+password-too-short = Heslo musí mít alespoň { $min ->
+        [one] { $min } znak
+        [few] { $min } znaky
+       *[other] { $min } znaků
+    }
+password-too-long = Heslo může mít nejvýše { $max_bytes } bajtů
+password-missing-lowercase = Heslo musí obsahovat malé písmeno
+password-missing-uppercase = Heslo musí obsahovat velké písmeno
+password-missing-digit = Heslo musí obsahovat číslici
+password-missing-symbol = Heslo musí obsahovat speciální znak
+password-too-weak = Heslo je příliš snadné uhodnout (síla { $score } z { $max_score }, požadováno alespoň { $min_score })
+password-breached = Heslo je známé z úniku dat
@@ -0,0 +1,128 @@
+## Authentication
+
+auth-success = Authentication successful
+auth-invalid-credentials = Invalid credentials
+auth-no-workspace = User is not a member of any workspace
+auth-required = Authentication required
+auth-locked = Too many failed logins for this user, try again in { $seconds ->
+        [one] { $seconds } second
+       *[other] { $seconds } seconds
+    }
+auth-session-replaced = You were signed out because you logged in on another connection
+auth-certificate-unknown = The client certificate is not registered, log in with a password
+
+## Registration
+
+register-logged-in = You are already logged in, log out to register another account
+register-invite-required = An invite code is required to register
+register-invite-invalid = The invite code is invalid or expired
+register-password-rejected = The password does not meet the password policy: { $violations }
+register-username-invalid = The username is not valid: { $error }
+register-username-taken = The username is already taken
+register-email-taken = The email address is already registered
+
+## Acknowledgments
+
+message-sent = Message sent successfully
+ephemeral-sent = Ephemeral message sent, it expires at { $expires_at }
+file-sent = File '{ $name }' sent successfully
+image-sent = Image '{ $name }' sent successfully
+
+## Connections and workspaces
+
+client-disconnected = A client has disconnected
+protocol-unsupported = This server speaks protocol version { $supported }, please update the client
+frame-too-large = Messages may be at most { $limit } bytes long, the connection is closed
+workspace-switched = Switched to workspace '{ $workspace }'
+workspace-not-member = You are not a member of workspace '{ $slug }'
+workspace-read-only = Workspace '{ $workspace }' is read-only, only admins and moderators can post
+workspace-switched-read-only = Switched to workspace '{ $workspace }', which is read-only
+workspace-announcement-only-on = Workspace '{ $workspace }' is now announcement-only, only admins and moderators can post
+workspace-announcement-only-off = Everyone can post in workspace '{ $workspace }' again
+
+## Rejected messages
+
+feature-unavailable = This feature is not available
+rate-limited = You are sending messages too quickly, try again in { $seconds ->
+        [one] { $seconds } second
+       *[other] { $seconds } seconds
+    }
+blocked-words = Your message contains blocked words
+muted = You are muted for { $seconds ->
+        [one] { $seconds } more second
+       *[other] { $seconds } more seconds
+    }
+spam-muted = Your message looks like spam ({ $kind ->
+        [duplicates] the same message sent repeatedly
+        [mentions] too many users mentioned
+       *[links] too many links
+    }), you are muted for { $seconds ->
+        [one] { $seconds } second
+       *[other] { $seconds } seconds
+    }
+attachment-corrupted = Attachment '{ $name }' was corrupted in transit
+image-undecodable = Image '{ $name }' could not be read
+attachment-undecryptable = Attachment '{ $name }' could not be decrypted: { $error }
+attachment-name-invalid = '{ $name }' cannot be used as a file name
+attachment-extension-not-allowed = Files with extension '.{ $extension }' are not allowed
+attachment-extension-missing = Files without an extension are not allowed
+attachment-type-not-allowed = Attachments of type '{ $mime }' are not allowed
+attachment-not-image = '{ $name }' is not an image (detected { $mime })
+attachment-image-mismatch = '{ $name }' has an image extension but contains { $mime } data
+attachment-quota-exceeded = Your attachments use { $used } of { $quota } MiB, there is no room for this one
+attachment-transfer-cap-exceeded = You have transferred { $used } of your { $cap } MiB of attachments today, try again tomorrow
+attachment-not-stored = Attachment '{ $name }' could not be stored, please send it again
+quote-not-found = Message #{ $id } cannot be quoted, it does not exist in this workspace
+poll-invalid = A poll needs a question and 2 to { $max } non-empty options
+poll-not-found = Poll #{ $id } does not exist in this workspace
+poll-invalid-option = Poll #{ $id } has { $count } options
+poll-already-voted = You have already voted in poll #{ $id }
+status-too-long = Status text may be at most { $max } characters long
+report-filed = Message #{ $id } was reported to the moderators as report #{ $report }
+report-message-not-found = Message #{ $id } cannot be reported, it does not exist in your workspaces
+report-already-filed = You have already reported message #{ $id }
+report-reason-invalid = A report needs a reason of at most { $max } characters
+download-not-found = Message #{ $id } has no stored attachment in this workspace
+edit-message-not-found = Message #{ $id } cannot be changed, it does not exist in this workspace
+edit-not-sender = Only the sender of message #{ $id } can change it
+edit-not-text = Message #{ $id } is an attachment, only text can be edited
+
+## Terms
+
+terms-required = Please read the server's terms and send /accept to accept them before posting:
+    { $terms }
+terms-not-accepted = You have to accept the server's terms with /accept before posting:
+    { $terms }
+terms-accepted = Thank you for accepting the terms
+
+## Slash commands
+
+command-unknown = Unknown command /{ $command }, type /help for a list
+command-help = - list the available commands
+command-me-usage = Usage: /me <action>
+
+## Notifications
+
+mention = { $sender } mentioned you: { $preview }
+spam-moderator-notice = { $user } was muted for { $seconds } seconds for spam: { $kind ->
+        [duplicates] the same message sent repeatedly
+        [mentions] too many users mentioned
+       *[links] too many links
+    }
+report-moderator-notice = { $reporter } reported a message by { $sender }: { $reason }
+report-warning = A moderator of { $workspace } warned you about one of your messages
+report-banned = You were removed from { $workspace } by a moderator because of one of your messages
+
+## Passwords
+
+password-changed = Your password has been changed
+password-wrong = The current password is wrong
+password-rejected = The new password does not meet the password policy: { $violations }
+password-too-short = The password must be at least { $min } characters long
+password-too-long = The password must be at most { $max_bytes } bytes long
+password-missing-lowercase = The password must contain a lowercase letter
+password-missing-uppercase = The password must contain an uppercase letter
+password-missing-digit = The password must contain a digit
+password-missing-symbol = The password must contain a symbol
+password-too-weak = The password is too easy to guess (strength { $score } of { $max_score }, at least { $min_score } required)
+password-breached = The password is known from a data breach
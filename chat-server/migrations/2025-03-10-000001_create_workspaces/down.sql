@@ -0,0 +1,3 @@
+ALTER TABLE messages DROP COLUMN workspace_id;
+DROP TABLE workspace_members;
+DROP TABLE workspaces;
@@ -0,0 +1,31 @@
+CREATE TABLE workspaces (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    slug VARCHAR(50) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+SELECT diesel_manage_updated_at('workspaces');
+
+CREATE TABLE workspace_members (
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'member',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (workspace_id, user_id)
+);
+
+CREATE INDEX workspace_members_user_id_idx ON workspace_members(user_id);
+
+-- Existing users and messages move into a default workspace
+INSERT INTO workspaces (name, slug) VALUES ('Default', 'default');
+
+INSERT INTO workspace_members (workspace_id, user_id)
+SELECT workspaces.id, users.id FROM workspaces, users WHERE workspaces.slug = 'default';
+
+ALTER TABLE messages ADD COLUMN workspace_id INTEGER REFERENCES workspaces(id) ON DELETE CASCADE;
+UPDATE messages SET workspace_id = (SELECT id FROM workspaces WHERE slug = 'default');
+ALTER TABLE messages ALTER COLUMN workspace_id SET NOT NULL;
+
+CREATE INDEX messages_workspace_id_idx ON messages(workspace_id);
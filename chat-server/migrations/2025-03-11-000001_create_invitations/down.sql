@@ -0,0 +1,3 @@
+UPDATE workspace_members SET role = 'member' WHERE role = 'admin';
+DROP TABLE invitation_redemptions;
+DROP TABLE invitations;
@@ -0,0 +1,25 @@
+CREATE TABLE invitations (
+    id SERIAL PRIMARY KEY,
+    code VARCHAR(32) NOT NULL UNIQUE,
+    created_by INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    workspace_id INTEGER REFERENCES workspaces(id) ON DELETE CASCADE,
+    max_uses INTEGER NOT NULL DEFAULT 1 CHECK (max_uses > 0),
+    uses INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE invitation_redemptions (
+    invitation_id INTEGER NOT NULL REFERENCES invitations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    redeemed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    first_login_at TIMESTAMP,
+    PRIMARY KEY (invitation_id, user_id)
+);
+
+CREATE INDEX invitation_redemptions_user_id_idx ON invitation_redemptions(user_id);
+
+-- The first test user administers the default workspace
+UPDATE workspace_members SET role = 'admin'
+WHERE user_id = (SELECT id FROM users WHERE username = 'alice')
+  AND workspace_id = (SELECT id FROM workspaces WHERE slug = 'default');
@@ -0,0 +1,3 @@
+ALTER TABLE messages DROP CONSTRAINT messages_sender_id_fkey;
+DELETE FROM messages WHERE sender_id = (SELECT id FROM users WHERE username = '[deleted]');
+DELETE FROM users WHERE username = '[deleted]';
@@ -0,0 +1,11 @@
+-- Sentinel that takes over messages of deleted accounts; its hash never verifies
+INSERT INTO users (username, email, password_hash)
+VALUES ('[deleted]', 'deleted-user@invalid', '!');
+
+-- Messages orphaned by earlier deletions belong to the sentinel
+UPDATE messages SET sender_id = (SELECT id FROM users WHERE username = '[deleted]')
+WHERE sender_id NOT IN (SELECT id FROM users);
+
+ALTER TABLE messages
+    ADD CONSTRAINT messages_sender_id_fkey
+    FOREIGN KEY (sender_id) REFERENCES users(id) ON DELETE CASCADE;
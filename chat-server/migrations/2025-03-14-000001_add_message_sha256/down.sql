@@ -0,0 +1,3 @@
+DROP INDEX messages_sha256_idx;
+
+ALTER TABLE messages DROP COLUMN sha256;
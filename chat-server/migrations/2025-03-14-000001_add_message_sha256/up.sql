@@ -0,0 +1,4 @@
+-- SHA-256 of the plaintext of file and image attachments
+ALTER TABLE messages ADD COLUMN sha256 VARCHAR(64);
+
+CREATE INDEX messages_sha256_idx ON messages(sha256) WHERE sha256 IS NOT NULL;
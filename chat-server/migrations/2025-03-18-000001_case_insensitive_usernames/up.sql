@@ -0,0 +1,4 @@
+-- Usernames are unique regardless of case. This fails if existing usernames
+-- differ only in case; rename those accounts first.
+ALTER TABLE users DROP CONSTRAINT users_username_key;
+CREATE UNIQUE INDEX users_username_lower_key ON users (LOWER(username));
@@ -0,0 +1,14 @@
+-- Earlier versions of edited messages, oldest first
+CREATE TABLE message_revisions (
+    id SERIAL PRIMARY KEY,
+    message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    content TEXT,
+    file_name VARCHAR(255),
+    edited_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    edited_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX message_revisions_message_id_idx ON message_revisions(message_id, id);
+
+-- When the content of a message was last changed
+ALTER TABLE messages ADD COLUMN edited_at TIMESTAMP;
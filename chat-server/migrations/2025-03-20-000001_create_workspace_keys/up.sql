@@ -0,0 +1,9 @@
+-- Encryption key of each workspace, wrapped with the server's ENCRYPTION_KEY
+CREATE TABLE workspace_keys (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL UNIQUE REFERENCES workspaces(id) ON DELETE CASCADE,
+    key_id VARCHAR(64) NOT NULL UNIQUE,
+    wrapped_key TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- Status users show to the members of their workspaces
+CREATE TABLE user_statuses (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    availability VARCHAR(16) NOT NULL DEFAULT 'available',
+    text VARCHAR(100),
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
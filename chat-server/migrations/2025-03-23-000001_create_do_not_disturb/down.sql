@@ -0,0 +1 @@
+DROP TABLE do_not_disturb;
@@ -0,0 +1,9 @@
+-- Do-not-disturb mode, switched on manually or daily between two UTC times
+CREATE TABLE do_not_disturb (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    starts_at TIME,
+    ends_at TIME,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((starts_at IS NULL) = (ends_at IS NULL))
+);
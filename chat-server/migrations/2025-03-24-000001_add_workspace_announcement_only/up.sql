@@ -0,0 +1,2 @@
+-- Announcement-only workspaces, where only admins and moderators can post
+ALTER TABLE workspaces ADD COLUMN announcement_only BOOLEAN NOT NULL DEFAULT FALSE;
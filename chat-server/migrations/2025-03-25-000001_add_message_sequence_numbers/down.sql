@@ -0,0 +1,3 @@
+DROP INDEX messages_workspace_seq_idx;
+ALTER TABLE messages DROP COLUMN seq;
+ALTER TABLE workspaces DROP COLUMN last_message_seq;
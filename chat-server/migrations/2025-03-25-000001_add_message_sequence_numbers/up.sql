@@ -0,0 +1,21 @@
+-- Per-workspace sequence numbers of stored messages, so clients can find
+-- the messages they missed
+ALTER TABLE workspaces ADD COLUMN last_message_seq BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN seq BIGINT;
+
+UPDATE messages
+SET seq = numbered.seq
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY workspace_id ORDER BY id) AS seq
+    FROM messages
+) AS numbered
+WHERE messages.id = numbered.id;
+
+UPDATE workspaces
+SET last_message_seq = COALESCE(
+    (SELECT MAX(seq) FROM messages WHERE messages.workspace_id = workspaces.id),
+    0
+);
+
+ALTER TABLE messages ALTER COLUMN seq SET NOT NULL;
+CREATE UNIQUE INDEX messages_workspace_seq_idx ON messages(workspace_id, seq);
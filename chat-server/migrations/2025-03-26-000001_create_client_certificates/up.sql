@@ -0,0 +1,9 @@
+-- TLS client certificates that log a connection in without a password,
+-- identified by the SHA-256 fingerprint of the DER-encoded certificate
+CREATE TABLE client_certificates (
+    fingerprint VARCHAR(64) PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX client_certificates_user_id_idx ON client_certificates(user_id);
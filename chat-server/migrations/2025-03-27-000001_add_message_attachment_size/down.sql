@@ -0,0 +1,3 @@
+DROP INDEX messages_sender_attachment_idx;
+
+ALTER TABLE messages DROP COLUMN attachment_size;
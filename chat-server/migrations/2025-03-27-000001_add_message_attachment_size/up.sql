@@ -0,0 +1,6 @@
+-- Size of an attachment's content in bytes, summed per sender for quotas.
+-- Attachments stored before this migration are not counted.
+ALTER TABLE messages ADD COLUMN attachment_size BIGINT;
+
+CREATE INDEX messages_sender_attachment_idx ON messages (sender_id)
+    WHERE attachment_size IS NOT NULL;
@@ -0,0 +1,19 @@
+-- Messages reported to the moderators of their workspace. Reports outlive the
+-- reported message, so deleting it can be recorded as their resolution.
+CREATE TABLE reports (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    message_id INTEGER REFERENCES messages(id) ON DELETE SET NULL,
+    -- Sender of the reported message
+    reported_user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reporter_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reason TEXT NOT NULL,
+    -- open, dismissed, deleted, warned or banned
+    status VARCHAR(20) NOT NULL DEFAULT 'open',
+    resolved_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    resolved_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (message_id, reporter_id)
+);
+
+CREATE INDEX reports_workspace_status_idx ON reports(workspace_id, status);
@@ -0,0 +1,11 @@
+CREATE TABLE user_onboarding (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    welcomed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- SHA-256 of the terms the user accepted, NULL if they never did
+    terms_sha256 VARCHAR(64),
+    terms_accepted_at TIMESTAMP
+);
+
+-- Existing users have joined already and are not welcomed again
+INSERT INTO user_onboarding (user_id)
+SELECT id FROM users;
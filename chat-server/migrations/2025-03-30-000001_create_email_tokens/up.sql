@@ -0,0 +1,19 @@
+CREATE TABLE email_tokens (
+    -- SHA-256 of the token; the token itself is only in the emailed link
+    token_sha256 VARCHAR(64) PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose VARCHAR(20) NOT NULL CHECK (purpose IN ('verification', 'password_reset')),
+    -- The address the link was sent to
+    email VARCHAR(255) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX email_tokens_user_id_idx ON email_tokens(user_id);
+
+CREATE TABLE email_verifications (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- The verified address, which no longer counts once the user changes it
+    email VARCHAR(255) NOT NULL,
+    verified_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,1315 @@
+//! Runtime configuration for the chat server.
+//!
+//! Settings are read from environment variables and fall back to sensible
+//! defaults when a variable is unset or cannot be parsed. A running server
+//! holds its settings in a [`SharedConfig`] so they can be reloaded without a
+//! restart.
+
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_OUTBOUND_QUEUE_CAPACITY: usize = 256;
+const DEFAULT_BACKPRESSURE_MAX_DROPPED: usize = 64;
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 30;
+const DEFAULT_HEARTBEAT_MAX_MISSED: u32 = 3;
+const DEFAULT_SLOW_WRITE_THRESHOLD_MS: u64 = 2000;
+const DEFAULT_SLOW_WRITE_MAX_CONSECUTIVE: u32 = 5;
+const DEFAULT_EXPORT_DIR: &str = "exports";
+const DEFAULT_PUBLIC_URL: &str = "http://localhost:3000";
+const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_STORAGE_DIR: &str = "storage";
+const DEFAULT_S3_REGION: &str = "us-east-1";
+const DEFAULT_BIND_RETRY_SECS: u64 = 30;
+const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+const DEFAULT_AUTH_MAX_FAILURES_PER_CONNECTION: u32 = 5;
+const DEFAULT_AUTH_MAX_FAILURES_PER_USERNAME: u32 = 10;
+const DEFAULT_AUTH_FAILURE_WINDOW_SECS: u64 = 900;
+const DEFAULT_AUTH_FAILURE_DELAY_MS: u64 = 250;
+const DEFAULT_PASSWORD_RESET_MAX_PER_ADDRESS: u32 = 3;
+const DEFAULT_PASSWORD_RESET_MAX_PER_IP: u32 = 10;
+const DEFAULT_PASSWORD_RESET_WINDOW_SECS: u64 = 3600;
+const DEFAULT_SPAM_DUPLICATE_LIMIT: u32 = 5;
+const DEFAULT_SPAM_MENTION_LIMIT: u32 = 10;
+const DEFAULT_SPAM_LINK_LIMIT: u32 = 10;
+const DEFAULT_SPAM_WINDOW_SECS: u64 = 30;
+const DEFAULT_SPAM_MUTE_SECS: u64 = 60;
+const DEFAULT_TLS_PORT: u16 = 8443;
+const DEFAULT_HTTP_JSON_LIMIT_KIB: u64 = 1024;
+const DEFAULT_MAX_FRAME_SIZE_MIB: usize = 64;
+const DEFAULT_HTTP_REQUEST_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_HISTORY_REPLAY_LIMIT: u32 = 50;
+/// Highest password strength score, on the zxcvbn scale
+pub const MAX_PASSWORD_SCORE: u8 = 4;
+
+/// Settings that are only read at startup and need a restart to change
+const RESTART_REQUIRED: &[&str] = &[
+    "export_dir",
+    "smtp",
+    "email_digest_interval",
+    "storage",
+    "bind_retry_period",
+    "port_file",
+    "password_denylist_file",
+    "log_format",
+    "tls",
+    "http_json_limit",
+];
+
+/// Strategy applied when a client's outbound queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackpressurePolicy {
+    /// Wait until the client has drained enough of its queue to accept the message
+    Block,
+    /// Discard the oldest queued message to make room for the new one
+    DropOldest,
+    /// Discard new messages and disconnect the client once `max_dropped` were lost
+    Disconnect { max_dropped: usize },
+}
+
+impl FromStr for BackpressurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "block" => Ok(BackpressurePolicy::Block),
+            "drop-oldest" | "drop_oldest" => Ok(BackpressurePolicy::DropOldest),
+            "disconnect" => Ok(BackpressurePolicy::Disconnect {
+                max_dropped: DEFAULT_BACKPRESSURE_MAX_DROPPED,
+            }),
+            other => Err(format!("Unknown backpressure policy: {}", other)),
+        }
+    }
+}
+
+/// What happens to a user's messages when their account is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserDeletionMode {
+    /// Delete the messages together with the account
+    Cascade,
+    /// Keep the messages but reassign them to the deleted-user sentinel
+    Anonymize,
+}
+
+impl FromStr for UserDeletionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cascade" => Ok(UserDeletionMode::Cascade),
+            "anonymize" => Ok(UserDeletionMode::Anonymize),
+            other => Err(format!("Unknown user deletion mode: {}", other)),
+        }
+    }
+}
+
+/// Format of the server's log output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per event, including the fields of its spans
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("Unknown log format: {}", other)),
+        }
+    }
+}
+
+/// What happens when a user logs in while already logged in on another connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateLoginPolicy {
+    /// Keep every session, so the user can chat from several devices at once
+    Allow,
+    /// Sign out the user's older sessions
+    Replace,
+}
+
+impl FromStr for DuplicateLoginPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "allow" => Ok(DuplicateLoginPolicy::Allow),
+            "replace" => Ok(DuplicateLoginPolicy::Replace),
+            other => Err(format!("Unknown duplicate login policy: {}", other)),
+        }
+    }
+}
+
+/// Which attachment types the server accepts.
+///
+/// MIME patterns are exact types (`application/pdf`) or whole top-level types
+/// (`image/*`); extensions are compared case-insensitively without the dot. An
+/// empty list allows everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttachmentPolicy {
+    pub allowed_types: Vec<String>,
+    pub allowed_extensions: Vec<String>,
+}
+
+impl AttachmentPolicy {
+    /// Returns `true` if attachments with the given MIME type are accepted.
+    pub fn allows_type(&self, mime: &str) -> bool {
+        self.allowed_types.is_empty()
+            || self
+                .allowed_types
+                .iter()
+                .any(|pattern| match pattern.strip_suffix("/*") {
+                    Some(top_level) => mime
+                        .split_once('/')
+                        .is_some_and(|(kind, _)| kind.eq_ignore_ascii_case(top_level)),
+                    None => pattern.eq_ignore_ascii_case(mime),
+                })
+    }
+
+    /// Returns `true` if attachments with the given extension are accepted.
+    pub fn allows_extension(&self, extension: Option<&str>) -> bool {
+        self.allowed_extensions.is_empty()
+            || extension.is_some_and(|extension| {
+                self.allowed_extensions
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(extension))
+            })
+    }
+}
+
+/// A kind of character passwords can be required to contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    /// Anything that is not a letter or digit
+    Symbol,
+}
+
+impl CharacterClass {
+    pub fn matches(&self, c: char) -> bool {
+        match self {
+            CharacterClass::Lowercase => c.is_lowercase(),
+            CharacterClass::Uppercase => c.is_uppercase(),
+            CharacterClass::Digit => c.is_numeric(),
+            CharacterClass::Symbol => !c.is_alphanumeric(),
+        }
+    }
+}
+
+impl FromStr for CharacterClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "lower" | "lowercase" => Ok(CharacterClass::Lowercase),
+            "upper" | "uppercase" => Ok(CharacterClass::Uppercase),
+            "digit" => Ok(CharacterClass::Digit),
+            "symbol" => Ok(CharacterClass::Symbol),
+            other => Err(format!("Unknown character class: {}", other)),
+        }
+    }
+}
+
+/// Rules new passwords must satisfy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Kinds of characters a password must contain at least one of each
+    pub required_classes: Vec<CharacterClass>,
+    /// Lowest accepted strength score from 0 to 4, where 0 accepts everything
+    pub min_score: u8,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_PASSWORD_MIN_LENGTH,
+            required_classes: Vec::new(),
+            min_score: 0,
+        }
+    }
+}
+
+/// Limits on failed chat logins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthThrottleConfig {
+    /// Failed logins after which a connection is closed, or 0 for no limit
+    pub max_failures_per_connection: u32,
+    /// Failed logins for one username within `failure_window` after which
+    /// further logins for it are refused, or 0 for no limit
+    pub max_failures_per_username: u32,
+    /// How long failed logins count against a username
+    pub failure_window: Duration,
+    /// Delay before answering the first failed login on a connection; it
+    /// doubles with every further failure
+    pub failure_delay: Duration,
+}
+
+impl Default for AuthThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_failures_per_connection: DEFAULT_AUTH_MAX_FAILURES_PER_CONNECTION,
+            max_failures_per_username: DEFAULT_AUTH_MAX_FAILURES_PER_USERNAME,
+            failure_window: Duration::from_secs(DEFAULT_AUTH_FAILURE_WINDOW_SECS),
+            failure_delay: Duration::from_millis(DEFAULT_AUTH_FAILURE_DELAY_MS),
+        }
+    }
+}
+
+/// Limits on requests for password reset links.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordResetThrottleConfig {
+    /// Requests for one email address within `window`, or 0 for no limit
+    pub max_per_address: u32,
+    /// Requests from one IP address within `window`, or 0 for no limit
+    pub max_per_ip: u32,
+    /// How long requests count against an address
+    pub window: Duration,
+}
+
+impl Default for PasswordResetThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_per_address: DEFAULT_PASSWORD_RESET_MAX_PER_ADDRESS,
+            max_per_ip: DEFAULT_PASSWORD_RESET_MAX_PER_IP,
+            window: Duration::from_secs(DEFAULT_PASSWORD_RESET_WINDOW_SECS),
+        }
+    }
+}
+
+/// Heuristics that detect spam in chat messages, and how spammers are muted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpamConfig {
+    /// Identical messages a user may send within `window`, or 0 for no limit
+    pub duplicate_limit: u32,
+    /// Users one message may mention, or 0 for no limit
+    pub mention_limit: u32,
+    /// Links a user may send within `window`, or 0 for no limit
+    pub link_limit: u32,
+    /// How far back messages are counted for the duplicate and link limits
+    pub window: Duration,
+    /// How long a user is muted for the first offense; it doubles with every
+    /// further one
+    pub mute_duration: Duration,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            duplicate_limit: DEFAULT_SPAM_DUPLICATE_LIMIT,
+            mention_limit: DEFAULT_SPAM_MENTION_LIMIT,
+            link_limit: DEFAULT_SPAM_LINK_LIMIT,
+            window: Duration::from_secs(DEFAULT_SPAM_WINDOW_SECS),
+            mute_duration: Duration::from_secs(DEFAULT_SPAM_MUTE_SECS),
+        }
+    }
+}
+
+/// What happens when a user joins the server by logging in for the first time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OnboardingConfig {
+    /// Sent to users joining the server as a notification, e.g. the server rules
+    pub welcome_message: Option<String>,
+    /// Slugs of workspaces users joining the server are added to, besides the
+    /// default workspace
+    pub default_workspaces: Vec<String>,
+    /// Terms users must accept with `/accept` before they may send chat
+    /// messages, or None if there are none
+    pub terms: Option<String>,
+}
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS
+    StartTls,
+    /// TLS from the start of the connection
+    Tls,
+    /// Unencrypted, for local test servers only
+    None,
+}
+
+impl SmtpTls {
+    /// The port usually used with this mode.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            "none" => Ok(SmtpTls::None),
+            other => Err(format!("Unknown SMTP TLS mode: {}", other)),
+        }
+    }
+}
+
+/// The TLS listener for chat connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub port: u16,
+    /// PEM file with the server's certificate chain
+    pub cert_file: PathBuf,
+    /// PEM file with the server's private key
+    pub key_file: PathBuf,
+    /// PEM file with the CAs that issue client certificates, or None to not
+    /// ask clients for certificates
+    pub client_ca_file: Option<PathBuf>,
+}
+
+/// The SMTP server outgoing email is delivered through.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender address, e.g. `Chat <chat@example.com>`
+    pub from: String,
+}
+
+impl fmt::Debug for SmtpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmtpConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "********"))
+            .field("from", &self.from)
+            .finish()
+    }
+}
+
+/// Where attachments and avatars are stored.
+#[derive(Clone, PartialEq, Eq)]
+pub enum StorageConfig {
+    /// A directory on the local disk
+    Local { root: PathBuf },
+    /// An S3-compatible object store
+    S3 {
+        bucket: String,
+        region: String,
+        /// Endpoint of a non-AWS store such as MinIO
+        endpoint: Option<String>,
+        access_key: Option<String>,
+        secret_key: Option<String>,
+        /// Address the bucket in the path instead of the host name
+        path_style: bool,
+    },
+}
+
+impl fmt::Debug for StorageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageConfig::Local { root } => f.debug_struct("Local").field("root", root).finish(),
+            StorageConfig::S3 {
+                bucket,
+                region,
+                endpoint,
+                access_key,
+                secret_key,
+                path_style,
+            } => f
+                .debug_struct("S3")
+                .field("bucket", bucket)
+                .field("region", region)
+                .field("endpoint", endpoint)
+                .field("access_key", access_key)
+                .field("secret_key", &secret_key.as_ref().map(|_| "********"))
+                .field("path_style", path_style)
+                .finish(),
+        }
+    }
+}
+
+/// Server settings shared by the TCP and REST subsystems.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Maximum number of messages buffered per client before backpressure applies
+    pub outbound_queue_capacity: usize,
+    /// What to do when a client's outbound queue is full
+    pub backpressure_policy: BackpressurePolicy,
+    /// How often the server pings each connection
+    pub heartbeat_interval: Duration,
+    /// Number of unanswered heartbeat intervals before a peer is considered dead
+    pub heartbeat_max_missed: u32,
+    /// A single write taking longer than this counts as a slow write
+    pub slow_write_threshold: Duration,
+    /// Number of consecutive slow writes before a client is disconnected
+    pub slow_write_max_consecutive: u32,
+    /// Whether registering a new account requires a valid invite code
+    pub require_invite: bool,
+    /// Directory that per-user data exports are written to
+    pub export_dir: PathBuf,
+    /// How messages of deleted accounts are handled
+    pub user_deletion_mode: UserDeletionMode,
+    /// Attachment types accepted in file and image messages
+    pub attachment_policy: AttachmentPolicy,
+    /// Attachment bytes each user may store, or None for no limit
+    pub attachment_quota: Option<u64>,
+    /// Attachment bytes each user may send and download per day, or None for no limit
+    pub attachment_daily_transfer: Option<u64>,
+    /// Log filter directives, e.g. `info` or `chat_server=debug,rocket=warn`
+    pub log_level: String,
+    /// Format of the log output
+    pub log_format: LogFormat,
+    /// Only one in this many occurrences of high-volume log events is logged
+    pub log_sample_rate: u32,
+    /// Chat messages a user may send per minute, or 0 for no limit
+    pub message_rate_limit: u32,
+    /// Latest messages of the workspace replayed to clients after logging in, or 0 for none
+    pub history_replay_limit: u32,
+    /// Lowercase words that are not allowed in text messages
+    pub blocked_words: Vec<String>,
+    /// Spam detection and the mutes it hands out
+    pub spam: SpamConfig,
+    /// Welcome message, default workspaces and terms for users joining the server
+    pub onboarding: OnboardingConfig,
+    /// SMTP server for outgoing email, or None if email is disabled
+    pub smtp: Option<SmtpConfig>,
+    /// How often users are emailed a digest of unread notifications, if at all
+    pub email_digest_interval: Option<Duration>,
+    /// Address of the web admin, which emailed links point to
+    pub public_url: String,
+    /// Backend storing attachments and avatars
+    pub storage: StorageConfig,
+    /// How long to keep retrying at startup while a port is in use
+    pub bind_retry_period: Duration,
+    /// File the bound TCP and HTTP ports are written to once listening
+    pub port_file: Option<PathBuf>,
+    /// Rules for new passwords
+    pub password_policy: PasswordPolicy,
+    /// File of breached or forbidden passwords, one per line
+    pub password_denylist_file: Option<PathBuf>,
+    /// Limits on failed chat logins
+    pub auth_throttle: AuthThrottleConfig,
+    /// Limits on requests for password reset links
+    pub password_reset_throttle: PasswordResetThrottleConfig,
+    /// Whether a user may be logged in on several connections at once
+    pub duplicate_login_policy: DuplicateLoginPolicy,
+    /// TLS listener for chat connections, or None if only plain TCP is served
+    pub tls: Option<TlsConfig>,
+    /// Largest JSON request body the REST API accepts, in bytes
+    pub http_json_limit: u64,
+    /// Largest frame payload read from chat connections, in bytes
+    pub max_frame_size: usize,
+    /// How long a REST request handler may run, or None for no limit
+    pub http_request_timeout: Option<Duration>,
+    /// How long users authenticated by REST requests are cached, or None to not cache them
+    pub user_cache_ttl: Option<Duration>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            outbound_queue_capacity: DEFAULT_OUTBOUND_QUEUE_CAPACITY,
+            backpressure_policy: BackpressurePolicy::DropOldest,
+            heartbeat_interval: Duration::from_secs(DEFAULT_HEARTBEAT_INTERVAL_SECS),
+            heartbeat_max_missed: DEFAULT_HEARTBEAT_MAX_MISSED,
+            slow_write_threshold: Duration::from_millis(DEFAULT_SLOW_WRITE_THRESHOLD_MS),
+            slow_write_max_consecutive: DEFAULT_SLOW_WRITE_MAX_CONSECUTIVE,
+            require_invite: false,
+            export_dir: PathBuf::from(DEFAULT_EXPORT_DIR),
+            user_deletion_mode: UserDeletionMode::Anonymize,
+            attachment_policy: AttachmentPolicy::default(),
+            attachment_quota: None,
+            attachment_daily_transfer: None,
+            log_level: DEFAULT_LOG_LEVEL.to_string(),
+            log_format: LogFormat::Text,
+            log_sample_rate: 1,
+            message_rate_limit: 0,
+            history_replay_limit: DEFAULT_HISTORY_REPLAY_LIMIT,
+            blocked_words: Vec::new(),
+            spam: SpamConfig::default(),
+            onboarding: OnboardingConfig::default(),
+            smtp: None,
+            email_digest_interval: None,
+            public_url: DEFAULT_PUBLIC_URL.to_string(),
+            storage: StorageConfig::Local {
+                root: PathBuf::from(DEFAULT_STORAGE_DIR),
+            },
+            bind_retry_period: Duration::from_secs(DEFAULT_BIND_RETRY_SECS),
+            port_file: None,
+            password_policy: PasswordPolicy::default(),
+            password_denylist_file: None,
+            auth_throttle: AuthThrottleConfig::default(),
+            password_reset_throttle: PasswordResetThrottleConfig::default(),
+            duplicate_login_policy: DuplicateLoginPolicy::Allow,
+            tls: None,
+            http_json_limit: DEFAULT_HTTP_JSON_LIMIT_KIB * 1024,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE_MIB * 1024 * 1024,
+            http_request_timeout: Some(Duration::from_secs(DEFAULT_HTTP_REQUEST_TIMEOUT_SECS)),
+            user_cache_ttl: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Builds the configuration from environment variables.
+    ///
+    /// # Environment
+    /// * `OUTBOUND_QUEUE_CAPACITY` - Per-client queue size (default 256)
+    /// * `BACKPRESSURE_POLICY` - `block`, `drop-oldest` or `disconnect` (default `drop-oldest`)
+    /// * `BACKPRESSURE_MAX_DROPPED` - Drops tolerated before disconnecting (default 64)
+    /// * `HEARTBEAT_INTERVAL_SECS` - Seconds between heartbeat pings (default 30)
+    /// * `HEARTBEAT_MAX_MISSED` - Unanswered pings before disconnecting (default 3)
+    /// * `SLOW_WRITE_THRESHOLD_MS` - Write latency considered slow (default 2000)
+    /// * `SLOW_WRITE_MAX_CONSECUTIVE` - Slow writes tolerated in a row (default 5)
+    /// * `REQUIRE_INVITE` - `true` to only allow registration with an invite code (default false)
+    /// * `EXPORT_DIR` - Directory for per-user data exports (default `exports`)
+    /// * `USER_DELETION_MODE` - `cascade` or `anonymize` (default `anonymize`)
+    /// * `ALLOWED_ATTACHMENT_TYPES` - Comma-separated MIME patterns such as `image/*` (default all)
+    /// * `ALLOWED_ATTACHMENT_EXTENSIONS` - Comma-separated file extensions (default all)
+    /// * `ATTACHMENT_QUOTA_MIB` - Attachment storage per user in MiB, 0 for unlimited (default 0)
+    /// * `ATTACHMENT_DAILY_TRANSFER_MIB` - Attachments each user may send and download per day in MiB, 0 for unlimited (default 0)
+    /// * `LOG_LEVEL` - Log filter directives (default `RUST_LOG`, or `info`)
+    /// * `LOG_FORMAT` - `text` or `json` (default `text`)
+    /// * `LOG_SAMPLE_RATE` - Log one in this many high-volume events such as rate-limit rejections (default 1)
+    /// * `MESSAGE_RATE_LIMIT` - Chat messages per user and minute, 0 for unlimited (default 0)
+    /// * `HISTORY_REPLAY_LIMIT` - Latest messages sent to clients after logging in, 0 to disable (default 50)
+    /// * `BLOCKED_WORDS` - Comma-separated words rejected in text messages (default none)
+    /// * `SPAM_DUPLICATE_LIMIT` - Identical messages per user within the spam window, 0 for unlimited (default 5)
+    /// * `SPAM_MENTION_LIMIT` - Mentions in one message, 0 for unlimited (default 10)
+    /// * `SPAM_LINK_LIMIT` - Links per user within the spam window, 0 for unlimited (default 10)
+    /// * `SPAM_WINDOW_SECS` - Seconds messages count towards the duplicate and link limits (default 30)
+    /// * `SPAM_MUTE_SECS` - Seconds a spammer is muted for the first time, doubling with every offense (default 60)
+    /// * `WELCOME_MESSAGE_FILE` - File with the welcome message sent to users joining the server (default none)
+    /// * `DEFAULT_WORKSPACES` - Comma-separated slugs of workspaces users joining the server are added to (default none)
+    /// * `TERMS_FILE` - File with terms users must accept before sending chat messages (default none)
+    /// * `SMTP_HOST` - SMTP server for outgoing email; email is disabled if unset
+    /// * `SMTP_PORT` - SMTP port (default depends on `SMTP_TLS`)
+    /// * `SMTP_TLS` - `starttls`, `tls` or `none` (default `starttls`)
+    /// * `SMTP_USERNAME` / `SMTP_PASSWORD` - SMTP credentials (default none)
+    /// * `SMTP_FROM` - Sender address (default `chat@<SMTP_HOST>`)
+    /// * `EMAIL_DIGEST_INTERVAL_HOURS` - Hours between notification digests, 0 to disable (default 0)
+    /// * `PUBLIC_URL` - Address of the web admin that emailed links point to (default `http://localhost:3000`)
+    /// * `STORAGE_BACKEND` - `local` or `s3` (default `local`)
+    /// * `STORAGE_DIR` - Directory of the local backend (default `storage`)
+    /// * `S3_BUCKET` - Bucket of the S3 backend (required for `s3`)
+    /// * `S3_REGION` - Region of the bucket (default `us-east-1`)
+    /// * `S3_ENDPOINT` - Endpoint of S3-compatible stores such as MinIO (default AWS)
+    /// * `S3_ACCESS_KEY` / `S3_SECRET_KEY` - Credentials (default from the AWS environment)
+    /// * `S3_PATH_STYLE` - `true` for path-style bucket URLs (default false)
+    /// * `BIND_RETRY_SECS` - Seconds to retry binding a port that is in use (default 30)
+    /// * `PORT_FILE` - File to write the bound ports to (default none)
+    /// * `PASSWORD_MIN_LENGTH` - Minimum password length in characters (default 8)
+    /// * `PASSWORD_REQUIRED_CLASSES` - Comma-separated `lower`, `upper`, `digit`, `symbol` (default none)
+    /// * `PASSWORD_MIN_SCORE` - Minimum strength score from 0 to 4 (default 0)
+    /// * `PASSWORD_DENYLIST_FILE` - File of forbidden passwords, one per line (default none)
+    /// * `AUTH_MAX_FAILURES_PER_CONNECTION` - Failed logins before a connection is closed (default 5)
+    /// * `AUTH_MAX_FAILURES_PER_USERNAME` - Failed logins per username before it is locked (default 10)
+    /// * `AUTH_FAILURE_WINDOW_SECS` - Seconds failed logins count against a username (default 900)
+    /// * `AUTH_FAILURE_DELAY_MS` - Delay after the first failed login, doubling after each (default 250)
+    /// * `PASSWORD_RESET_MAX_PER_ADDRESS` - Reset links requested per email address within the window, 0 for unlimited (default 3)
+    /// * `PASSWORD_RESET_MAX_PER_IP` - Reset links requested per IP address within the window, 0 for unlimited (default 10)
+    /// * `PASSWORD_RESET_WINDOW_SECS` - Seconds reset requests count towards the limits (default 3600)
+    /// * `DUPLICATE_LOGIN_POLICY` - `allow` several sessions per user or `replace` older ones (default `allow`)
+    /// * `TLS_CERT_FILE` / `TLS_KEY_FILE` - PEM certificate chain and key; the TLS listener is disabled if unset
+    /// * `TLS_PORT` - Port of the TLS listener (default 8443)
+    /// * `TLS_CLIENT_CA_FILE` - PEM CAs of client certificates; client certificates are not requested if unset
+    /// * `HTTP_JSON_LIMIT_KIB` - Largest JSON request body in KiB (default 1024)
+    /// * `MAX_FRAME_SIZE_MIB` - Largest frame read from chat connections in MiB (default 64)
+    /// * `HTTP_REQUEST_TIMEOUT_SECS` - Seconds a REST request may take, 0 for no limit (default 30)
+    /// * `USER_CACHE_TTL_SECS` - Seconds users authenticated by REST requests are cached, 0 to not cache them (default 0)
+    ///
+    /// Invalid values are logged and replaced by their defaults.
+    pub fn from_env() -> Self {
+        let mut errors = Vec::new();
+        let config = Self::read_env(&mut errors);
+        for error in errors {
+            warn!("{} (using default)", error);
+        }
+        config
+    }
+
+    /// Builds the configuration from environment variables, rejecting invalid values.
+    ///
+    /// # Returns
+    /// * `Result<ServerConfig, Vec<String>>` - The configuration, or every invalid setting
+    pub fn try_from_env() -> Result<Self, Vec<String>> {
+        let mut errors = Vec::new();
+        let config = Self::read_env(&mut errors);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Lists the settings that differ between this configuration and `new`.
+    pub fn changes(&self, new: &ServerConfig) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+        let mut compare = |setting: &'static str, old: &dyn fmt::Debug, new: &dyn fmt::Debug| {
+            let (old, new) = (format!("{:?}", old), format!("{:?}", new));
+            if old != new {
+                changes.push(ConfigChange {
+                    setting,
+                    old,
+                    new,
+                    restart_required: RESTART_REQUIRED.contains(&setting),
+                });
+            }
+        };
+
+        compare(
+            "outbound_queue_capacity",
+            &self.outbound_queue_capacity,
+            &new.outbound_queue_capacity,
+        );
+        compare(
+            "backpressure_policy",
+            &self.backpressure_policy,
+            &new.backpressure_policy,
+        );
+        compare(
+            "heartbeat_interval",
+            &self.heartbeat_interval,
+            &new.heartbeat_interval,
+        );
+        compare(
+            "heartbeat_max_missed",
+            &self.heartbeat_max_missed,
+            &new.heartbeat_max_missed,
+        );
+        compare(
+            "slow_write_threshold",
+            &self.slow_write_threshold,
+            &new.slow_write_threshold,
+        );
+        compare(
+            "slow_write_max_consecutive",
+            &self.slow_write_max_consecutive,
+            &new.slow_write_max_consecutive,
+        );
+        compare("require_invite", &self.require_invite, &new.require_invite);
+        compare("export_dir", &self.export_dir, &new.export_dir);
+        compare(
+            "user_deletion_mode",
+            &self.user_deletion_mode,
+            &new.user_deletion_mode,
+        );
+        compare(
+            "attachment_policy",
+            &self.attachment_policy,
+            &new.attachment_policy,
+        );
+        compare(
+            "attachment_quota",
+            &self.attachment_quota,
+            &new.attachment_quota,
+        );
+        compare(
+            "attachment_daily_transfer",
+            &self.attachment_daily_transfer,
+            &new.attachment_daily_transfer,
+        );
+        compare("log_level", &self.log_level, &new.log_level);
+        compare("log_format", &self.log_format, &new.log_format);
+        compare(
+            "log_sample_rate",
+            &self.log_sample_rate,
+            &new.log_sample_rate,
+        );
+        compare(
+            "message_rate_limit",
+            &self.message_rate_limit,
+            &new.message_rate_limit,
+        );
+        compare(
+            "history_replay_limit",
+            &self.history_replay_limit,
+            &new.history_replay_limit,
+        );
+        compare("blocked_words", &self.blocked_words, &new.blocked_words);
+        compare("spam", &self.spam, &new.spam);
+        compare("onboarding", &self.onboarding, &new.onboarding);
+        compare("smtp", &self.smtp, &new.smtp);
+        compare(
+            "email_digest_interval",
+            &self.email_digest_interval,
+            &new.email_digest_interval,
+        );
+        compare("public_url", &self.public_url, &new.public_url);
+        compare("storage", &self.storage, &new.storage);
+        compare(
+            "bind_retry_period",
+            &self.bind_retry_period,
+            &new.bind_retry_period,
+        );
+        compare("port_file", &self.port_file, &new.port_file);
+        compare(
+            "password_policy",
+            &self.password_policy,
+            &new.password_policy,
+        );
+        compare(
+            "password_denylist_file",
+            &self.password_denylist_file,
+            &new.password_denylist_file,
+        );
+        compare("auth_throttle", &self.auth_throttle, &new.auth_throttle);
+        compare(
+            "password_reset_throttle",
+            &self.password_reset_throttle,
+            &new.password_reset_throttle,
+        );
+        compare(
+            "duplicate_login_policy",
+            &self.duplicate_login_policy,
+            &new.duplicate_login_policy,
+        );
+        compare("tls", &self.tls, &new.tls);
+        compare(
+            "http_json_limit",
+            &self.http_json_limit,
+            &new.http_json_limit,
+        );
+        compare("max_frame_size", &self.max_frame_size, &new.max_frame_size);
+        compare(
+            "http_request_timeout",
+            &self.http_request_timeout,
+            &new.http_request_timeout,
+        );
+        compare("user_cache_ttl", &self.user_cache_ttl, &new.user_cache_ttl);
+
+        changes
+    }
+
+    /// Reads every setting, recording invalid values in `errors`.
+    fn read_env(errors: &mut Vec<String>) -> Self {
+        let defaults = Self::default();
+
+        let backpressure_policy =
+            match env_or("BACKPRESSURE_POLICY", defaults.backpressure_policy, errors) {
+                BackpressurePolicy::Disconnect { .. } => BackpressurePolicy::Disconnect {
+                    max_dropped: env_or(
+                        "BACKPRESSURE_MAX_DROPPED",
+                        DEFAULT_BACKPRESSURE_MAX_DROPPED,
+                        errors,
+                    ),
+                },
+                policy => policy,
+            };
+
+        let log_level = env::var("LOG_LEVEL")
+            .or_else(|_| env::var("RUST_LOG"))
+            .unwrap_or(defaults.log_level);
+        let log_level = match EnvFilter::try_new(&log_level) {
+            Ok(_) => log_level,
+            Err(e) => {
+                errors.push(format!("Invalid value for LOG_LEVEL: {}", e));
+                DEFAULT_LOG_LEVEL.to_string()
+            }
+        };
+
+        let smtp = match env::var("SMTP_HOST") {
+            Ok(host) if !host.trim().is_empty() => {
+                let tls = env_or("SMTP_TLS", SmtpTls::StartTls, errors);
+                let from = env::var("SMTP_FROM").unwrap_or_else(|_| format!("chat@{}", host));
+                if let Err(e) = from.parse::<lettre::message::Mailbox>() {
+                    errors.push(format!("Invalid value for SMTP_FROM: {}", e));
+                }
+                Some(SmtpConfig {
+                    port: env_or("SMTP_PORT", tls.default_port(), errors),
+                    tls,
+                    username: env::var("SMTP_USERNAME").ok(),
+                    password: env::var("SMTP_PASSWORD").ok(),
+                    from,
+                    host,
+                })
+            }
+            _ => None,
+        };
+        let attachment_quota = match env_or("ATTACHMENT_QUOTA_MIB", 0u64, errors) {
+            0 => None,
+            mib => Some(mib * 1024 * 1024),
+        };
+        let attachment_daily_transfer = match env_or("ATTACHMENT_DAILY_TRANSFER_MIB", 0u64, errors)
+        {
+            0 => None,
+            mib => Some(mib * 1024 * 1024),
+        };
+
+        let email_digest_interval = match env_or("EMAIL_DIGEST_INTERVAL_HOURS", 0u64, errors) {
+            0 => None,
+            hours => Some(Duration::from_secs(hours * 3600)),
+        };
+
+        let storage = match env::var("STORAGE_BACKEND")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "local" => StorageConfig::Local {
+                root: env_or("STORAGE_DIR", PathBuf::from(DEFAULT_STORAGE_DIR), errors),
+            },
+            "s3" => StorageConfig::S3 {
+                bucket: env::var("S3_BUCKET").unwrap_or_else(|_| {
+                    errors.push("S3_BUCKET is required for the s3 storage backend".to_string());
+                    String::new()
+                }),
+                region: env::var("S3_REGION").unwrap_or_else(|_| DEFAULT_S3_REGION.to_string()),
+                endpoint: env::var("S3_ENDPOINT").ok(),
+                access_key: env::var("S3_ACCESS_KEY").ok(),
+                secret_key: env::var("S3_SECRET_KEY").ok(),
+                path_style: env_or("S3_PATH_STYLE", false, errors),
+            },
+            other => {
+                errors.push(format!("Invalid value for STORAGE_BACKEND: {}", other));
+                defaults.storage.clone()
+            }
+        };
+
+        let tls = match env::var("TLS_CERT_FILE") {
+            Ok(cert_file) if !cert_file.trim().is_empty() => Some(TlsConfig {
+                port: env_or("TLS_PORT", DEFAULT_TLS_PORT, errors),
+                cert_file: PathBuf::from(cert_file),
+                key_file: env::var("TLS_KEY_FILE")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|_| {
+                        errors.push("TLS_KEY_FILE is required with TLS_CERT_FILE".to_string());
+                        PathBuf::new()
+                    }),
+                client_ca_file: env::var("TLS_CLIENT_CA_FILE").ok().map(PathBuf::from),
+            }),
+            _ => None,
+        };
+
+        let http_request_timeout = match env_or(
+            "HTTP_REQUEST_TIMEOUT_SECS",
+            DEFAULT_HTTP_REQUEST_TIMEOUT_SECS,
+            errors,
+        ) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        let user_cache_ttl = match env_or("USER_CACHE_TTL_SECS", 0u64, errors) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+
+        let mut required_classes = Vec::new();
+        for class in env_list("PASSWORD_REQUIRED_CLASSES") {
+            match class.parse() {
+                Ok(class) => required_classes.push(class),
+                Err(e) => errors.push(format!(
+                    "Invalid value for PASSWORD_REQUIRED_CLASSES: {}",
+                    e
+                )),
+            }
+        }
+        let min_score = env_or(
+            "PASSWORD_MIN_SCORE",
+            defaults.password_policy.min_score,
+            errors,
+        );
+        let min_score = if min_score > MAX_PASSWORD_SCORE {
+            errors.push(format!(
+                "Invalid value for PASSWORD_MIN_SCORE: {} is above {}",
+                min_score, MAX_PASSWORD_SCORE
+            ));
+            defaults.password_policy.min_score
+        } else {
+            min_score
+        };
+
+        Self {
+            outbound_queue_capacity: env_or(
+                "OUTBOUND_QUEUE_CAPACITY",
+                defaults.outbound_queue_capacity,
+                errors,
+            )
+            .max(1),
+            backpressure_policy,
+            heartbeat_interval: Duration::from_secs(
+                env_or(
+                    "HEARTBEAT_INTERVAL_SECS",
+                    DEFAULT_HEARTBEAT_INTERVAL_SECS,
+                    errors,
+                )
+                .max(1),
+            ),
+            heartbeat_max_missed: env_or(
+                "HEARTBEAT_MAX_MISSED",
+                DEFAULT_HEARTBEAT_MAX_MISSED,
+                errors,
+            )
+            .max(1),
+            slow_write_threshold: Duration::from_millis(env_or(
+                "SLOW_WRITE_THRESHOLD_MS",
+                DEFAULT_SLOW_WRITE_THRESHOLD_MS,
+                errors,
+            )),
+            slow_write_max_consecutive: env_or(
+                "SLOW_WRITE_MAX_CONSECUTIVE",
+                DEFAULT_SLOW_WRITE_MAX_CONSECUTIVE,
+                errors,
+            )
+            .max(1),
+            require_invite: env_or("REQUIRE_INVITE", defaults.require_invite, errors),
+            export_dir: env_or("EXPORT_DIR", defaults.export_dir, errors),
+            user_deletion_mode: env_or("USER_DELETION_MODE", defaults.user_deletion_mode, errors),
+            attachment_policy: AttachmentPolicy {
+                allowed_types: env_list("ALLOWED_ATTACHMENT_TYPES"),
+                allowed_extensions: env_list("ALLOWED_ATTACHMENT_EXTENSIONS")
+                    .into_iter()
+                    .map(|extension| extension.trim_start_matches('.').to_string())
+                    .collect(),
+            },
+            attachment_quota,
+            attachment_daily_transfer,
+            log_level,
+            log_format: env_or("LOG_FORMAT", defaults.log_format, errors),
+            log_sample_rate: env_or("LOG_SAMPLE_RATE", defaults.log_sample_rate, errors).max(1),
+            message_rate_limit: env_or("MESSAGE_RATE_LIMIT", defaults.message_rate_limit, errors),
+            history_replay_limit: env_or(
+                "HISTORY_REPLAY_LIMIT",
+                defaults.history_replay_limit,
+                errors,
+            ),
+            blocked_words: env_list("BLOCKED_WORDS")
+                .into_iter()
+                .map(|word| word.to_lowercase())
+                .collect(),
+            spam: SpamConfig {
+                duplicate_limit: env_or(
+                    "SPAM_DUPLICATE_LIMIT",
+                    DEFAULT_SPAM_DUPLICATE_LIMIT,
+                    errors,
+                ),
+                mention_limit: env_or("SPAM_MENTION_LIMIT", DEFAULT_SPAM_MENTION_LIMIT, errors),
+                link_limit: env_or("SPAM_LINK_LIMIT", DEFAULT_SPAM_LINK_LIMIT, errors),
+                window: Duration::from_secs(
+                    env_or("SPAM_WINDOW_SECS", DEFAULT_SPAM_WINDOW_SECS, errors).max(1),
+                ),
+                mute_duration: Duration::from_secs(
+                    env_or("SPAM_MUTE_SECS", DEFAULT_SPAM_MUTE_SECS, errors).max(1),
+                ),
+            },
+            onboarding: OnboardingConfig {
+                welcome_message: env_file("WELCOME_MESSAGE_FILE", errors),
+                default_workspaces: env_list("DEFAULT_WORKSPACES"),
+                terms: env_file("TERMS_FILE", errors),
+            },
+            smtp,
+            email_digest_interval,
+            public_url: env::var("PUBLIC_URL")
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .ok()
+                .filter(|url| !url.is_empty())
+                .unwrap_or(defaults.public_url),
+            storage,
+            bind_retry_period: Duration::from_secs(env_or(
+                "BIND_RETRY_SECS",
+                DEFAULT_BIND_RETRY_SECS,
+                errors,
+            )),
+            port_file: env::var("PORT_FILE").ok().map(PathBuf::from),
+            password_policy: PasswordPolicy {
+                min_length: env_or(
+                    "PASSWORD_MIN_LENGTH",
+                    defaults.password_policy.min_length,
+                    errors,
+                ),
+                required_classes,
+                min_score,
+            },
+            password_denylist_file: env::var("PASSWORD_DENYLIST_FILE").ok().map(PathBuf::from),
+            auth_throttle: AuthThrottleConfig {
+                max_failures_per_connection: env_or(
+                    "AUTH_MAX_FAILURES_PER_CONNECTION",
+                    DEFAULT_AUTH_MAX_FAILURES_PER_CONNECTION,
+                    errors,
+                ),
+                max_failures_per_username: env_or(
+                    "AUTH_MAX_FAILURES_PER_USERNAME",
+                    DEFAULT_AUTH_MAX_FAILURES_PER_USERNAME,
+                    errors,
+                ),
+                failure_window: Duration::from_secs(env_or(
+                    "AUTH_FAILURE_WINDOW_SECS",
+                    DEFAULT_AUTH_FAILURE_WINDOW_SECS,
+                    errors,
+                )),
+                failure_delay: Duration::from_millis(env_or(
+                    "AUTH_FAILURE_DELAY_MS",
+                    DEFAULT_AUTH_FAILURE_DELAY_MS,
+                    errors,
+                )),
+            },
+            password_reset_throttle: PasswordResetThrottleConfig {
+                max_per_address: env_or(
+                    "PASSWORD_RESET_MAX_PER_ADDRESS",
+                    DEFAULT_PASSWORD_RESET_MAX_PER_ADDRESS,
+                    errors,
+                ),
+                max_per_ip: env_or(
+                    "PASSWORD_RESET_MAX_PER_IP",
+                    DEFAULT_PASSWORD_RESET_MAX_PER_IP,
+                    errors,
+                ),
+                window: Duration::from_secs(
+                    env_or(
+                        "PASSWORD_RESET_WINDOW_SECS",
+                        DEFAULT_PASSWORD_RESET_WINDOW_SECS,
+                        errors,
+                    )
+                    .max(1),
+                ),
+            },
+            duplicate_login_policy: env_or(
+                "DUPLICATE_LOGIN_POLICY",
+                defaults.duplicate_login_policy,
+                errors,
+            ),
+            tls,
+            http_json_limit: env_or("HTTP_JSON_LIMIT_KIB", DEFAULT_HTTP_JSON_LIMIT_KIB, errors)
+                .max(1)
+                * 1024,
+            max_frame_size: env_or("MAX_FRAME_SIZE_MIB", DEFAULT_MAX_FRAME_SIZE_MIB, errors).max(1)
+                * 1024
+                * 1024,
+            http_request_timeout,
+            user_cache_ttl,
+        }
+    }
+}
+
+/// Reads and parses an environment variable, falling back to `default`.
+///
+/// Values that fail to parse are recorded in `errors` and replaced by the default.
+pub(crate) fn env_or<T>(key: &str, default: T, errors: &mut Vec<String>) -> T
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|e| {
+            errors.push(format!("Invalid value for {}: {}", key, e));
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Reads a comma-separated environment variable, returning an empty list if unset.
+fn env_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the text file named by an environment variable, returning None if
+/// the variable is unset or the file is blank.
+///
+/// Files that cannot be read are recorded in `errors`.
+fn env_file(key: &str, errors: &mut Vec<String>) -> Option<String> {
+    let path = env::var(key).ok().filter(|path| !path.trim().is_empty())?;
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Some(text.trim().to_string()).filter(|text| !text.is_empty()),
+        Err(e) => {
+            errors.push(format!(
+                "Invalid value for {}: cannot read {}: {}",
+                key, path, e
+            ));
+            None
+        }
+    }
+}
+
+/// A setting that differs between two configurations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub setting: &'static str,
+    pub old: String,
+    pub new: String,
+    /// Whether the new value only takes effect after a restart
+    pub restart_required: bool,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.setting, self.old, self.new)
+    }
+}
+
+/// The server configuration currently in effect, shared by all subsystems.
+///
+/// Readers take a snapshot with [`SharedConfig::current`], so a reload never
+/// changes settings halfway through handling a request.
+#[derive(Debug, Clone, Default)]
+pub struct SharedConfig {
+    current: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// Returns the configuration currently in effect.
+    pub fn current(&self) -> Arc<ServerConfig> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Replaces the configuration, returning the previous one.
+    pub fn replace(&self, config: ServerConfig) -> Arc<ServerConfig> {
+        std::mem::replace(&mut *self.current.write().unwrap(), Arc::new(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backpressure_policy() {
+        assert_eq!(
+            "block".parse::<BackpressurePolicy>(),
+            Ok(BackpressurePolicy::Block)
+        );
+        assert_eq!(
+            "Drop-Oldest".parse::<BackpressurePolicy>(),
+            Ok(BackpressurePolicy::DropOldest)
+        );
+        assert!(matches!(
+            "disconnect".parse::<BackpressurePolicy>(),
+            Ok(BackpressurePolicy::Disconnect { .. })
+        ));
+        assert!("unbounded".parse::<BackpressurePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_parse_user_deletion_mode() {
+        assert_eq!(
+            "Cascade".parse::<UserDeletionMode>(),
+            Ok(UserDeletionMode::Cascade)
+        );
+        assert_eq!(
+            "anonymize".parse::<UserDeletionMode>(),
+            Ok(UserDeletionMode::Anonymize)
+        );
+        assert!("keep".parse::<UserDeletionMode>().is_err());
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_parse_duplicate_login_policy() {
+        assert_eq!(
+            " Replace".parse::<DuplicateLoginPolicy>(),
+            Ok(DuplicateLoginPolicy::Replace)
+        );
+        assert_eq!(
+            "allow".parse::<DuplicateLoginPolicy>(),
+            Ok(DuplicateLoginPolicy::Allow)
+        );
+        assert!("kick".parse::<DuplicateLoginPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_attachment_policy() {
+        let policy = AttachmentPolicy {
+            allowed_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+            allowed_extensions: vec!["PNG".to_string(), "pdf".to_string()],
+        };
+
+        assert!(policy.allows_type("image/png"));
+        assert!(policy.allows_type("application/pdf"));
+        assert!(!policy.allows_type("application/zip"));
+        assert!(policy.allows_extension(Some("png")));
+        assert!(!policy.allows_extension(Some("exe")));
+        assert!(!policy.allows_extension(None));
+
+        let open = AttachmentPolicy::default();
+        assert!(open.allows_type("application/x-executable"));
+        assert!(open.allows_extension(None));
+    }
+
+    #[test]
+    fn test_character_classes() {
+        assert_eq!("Upper".parse(), Ok(CharacterClass::Uppercase));
+        assert_eq!("digit".parse(), Ok(CharacterClass::Digit));
+        assert!("emoji".parse::<CharacterClass>().is_err());
+
+        assert!(CharacterClass::Lowercase.matches('ž'));
+        assert!(CharacterClass::Uppercase.matches('Ř'));
+        assert!(CharacterClass::Symbol.matches(' '));
+        assert!(!CharacterClass::Symbol.matches('7'));
+    }
+
+    #[test]
+    fn test_config_changes() {
+        let old = ServerConfig::default();
+        let new = ServerConfig {
+            require_invite: true,
+            export_dir: PathBuf::from("/srv/exports"),
+            log_level: "debug".to_string(),
+            ..ServerConfig::default()
+        };
+
+        assert!(old.changes(&old.clone()).is_empty());
+
+        let changes = old.changes(&new);
+        let settings: Vec<_> = changes.iter().map(|change| change.setting).collect();
+        assert_eq!(settings, vec!["require_invite", "export_dir", "log_level"]);
+        assert_eq!(changes[0].to_string(), "require_invite: false -> true");
+        assert!(!changes[0].restart_required);
+        assert!(changes[1].restart_required);
+    }
+
+    #[test]
+    fn test_shared_config_replace() {
+        let shared = SharedConfig::new(ServerConfig::default());
+        let snapshot = shared.current();
+
+        let previous = shared.replace(ServerConfig {
+            heartbeat_max_missed: 7,
+            ..ServerConfig::default()
+        });
+
+        assert!(Arc::ptr_eq(&previous, &snapshot));
+        assert_eq!(snapshot.heartbeat_max_missed, DEFAULT_HEARTBEAT_MAX_MISSED);
+        assert_eq!(shared.current().heartbeat_max_missed, 7);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::repositories::user::UserWriteError;
+use ::std::error::Error;
+use tonic::Status;
+
+pub fn server_error(e: Box<dyn Error>) -> Status {
+    tracing::error!("{}", e);
+    Status::internal("Error")
+}
+
+pub fn not_found_error(e: Box<dyn Error>) -> Status {
+    tracing::error!("{}", e);
+    Status::not_found("Not found")
+}
+
+/// Maps a failed account write like the REST API, to invalid argument or
+/// already exists.
+pub fn user_write_error(e: UserWriteError) -> Status {
+    match e {
+        UserWriteError::InvalidUsername(_) => Status::invalid_argument(e.to_string()),
+        UserWriteError::UsernameTaken | UserWriteError::EmailTaken => {
+            Status::already_exists(e.to_string())
+        }
+        UserWriteError::Database(e) => query_error(e),
+    }
+}
+
+/// Maps a failed query to a status, reporting missing rows as not found.
+pub fn query_error(e: diesel::result::Error) -> Status {
+    match e {
+        diesel::result::Error::NotFound => not_found_error(e.into()),
+        e => server_error(e.into()),
+    }
+}
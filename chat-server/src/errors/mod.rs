@@ -1 +1,2 @@
+pub mod grpc_errors;
 pub mod rocket_server_errors;
@@ -1,7 +1,9 @@
+use crate::repositories::user::UserWriteError;
 use ::std::error::Error;
 use rocket::http::Status;
 use rocket::response::status::Custom;
 use rocket::serde::json::{json, Value};
+use rocket::{catch, catchers, Catcher, Request};
 
 pub fn server_error(e: Box<dyn Error>) -> Custom<Value> {
     rocket::error!("{}", e);
@@ -13,7 +15,39 @@ pub fn not_found_error(e: Box<dyn Error>) -> Custom<Value> {
     Custom(Status::NotFound, json!("Not found"))
 }
 
+/// Maps a failed account write to `400` for invalid usernames and `409` for
+/// usernames or emails already in use.
+pub fn user_write_error(e: UserWriteError) -> Custom<Value> {
+    match e {
+        UserWriteError::InvalidUsername(_) => Custom(Status::BadRequest, json!(e.to_string())),
+        UserWriteError::UsernameTaken | UserWriteError::EmailTaken => {
+            Custom(Status::Conflict, json!(e.to_string()))
+        }
+        UserWriteError::Database(e) => server_error(e.into()),
+    }
+}
+
 pub fn bad_request_error(e: Box<dyn Error>) -> Custom<Value> {
     rocket::error!("{}", e);
     Custom(Status::BadRequest, json!(format!("Bad request: {}", e)))
 }
+
+/// Answers requests whose handler took longer than `HTTP_REQUEST_TIMEOUT_SECS`.
+#[catch(408)]
+fn request_timeout() -> Value {
+    json!("The request took too long")
+}
+
+/// Answers requests whose body exceeds the limit of its type, e.g. JSON
+/// bodies above `HTTP_JSON_LIMIT_KIB`.
+#[catch(413)]
+fn payload_too_large(req: &Request<'_>) -> Value {
+    rocket::warn!("{} {}: request body too large", req.method(), req.uri());
+    json!("The request body is too large")
+}
+
+/// Catchers replacing Rocket's HTML error pages with JSON bodies like the
+/// routes' own errors.
+pub fn catchers() -> Vec<Catcher> {
+    catchers![request_timeout, payload_too_large]
+}
@@ -0,0 +1,93 @@
+use super::proto::chat_server::Chat;
+use super::proto::Frame;
+use crate::services::client_service::ClientService;
+use crate::services::outbound_queue;
+use crate::utils::metrics::Metrics;
+use chat_common::async_message_stream::AsyncMessageStream;
+use chat_common::error::{ChatError, Result};
+use chat_common::Message;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::info;
+
+/// Frames buffered for a client before the outbound queue's writer waits
+const FRAME_BUFFER: usize = 16;
+
+/// The `Chat` service, serving chat sessions like TCP connections.
+pub struct ChatApi {
+    clients: Arc<ClientService>,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl ChatApi {
+    pub fn new(clients: Arc<ClientService>, metrics: Arc<Mutex<Metrics>>) -> Self {
+        Self { clients, metrics }
+    }
+}
+
+#[tonic::async_trait]
+impl Chat for ChatApi {
+    type ChatStream = ReceiverStream<std::result::Result<Frame, Status>>;
+
+    async fn chat(
+        &self,
+        request: Request<Streaming<Frame>>,
+    ) -> std::result::Result<Response<Self::ChatStream>, Status> {
+        let addr = request
+            .remote_addr()
+            .unwrap_or_else(|| SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        info!("New gRPC chat stream from: {}", addr);
+        self.metrics.lock().await.active_connections.inc();
+
+        let (outbound, health) = self
+            .clients
+            .handle_stream(FrameReader(request.into_inner()), addr)
+            .await;
+        let (frames, receiver) = mpsc::channel(FRAME_BUFFER);
+        outbound_queue::spawn_writer(FrameWriter(frames), outbound, health);
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/// Reads the messages a client sends on a chat stream.
+struct FrameReader(Streaming<Frame>);
+
+#[tonic::async_trait]
+impl AsyncMessageStream for FrameReader {
+    async fn read_message(&mut self) -> Result<Message> {
+        match self.0.message().await {
+            Ok(Some(frame)) => Message::try_from(frame),
+            Ok(None) => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Err(status) => Err(ChatError::NetworkError(status.message().to_string())),
+        }
+    }
+
+    async fn write_message(&mut self, _message: &Message) -> Result<()> {
+        Err(ChatError::ServerError(
+            "The request stream cannot be written to".to_string(),
+        ))
+    }
+}
+
+/// Writes messages to the response of a chat stream.
+struct FrameWriter(mpsc::Sender<std::result::Result<Frame, Status>>);
+
+#[tonic::async_trait]
+impl AsyncMessageStream for FrameWriter {
+    async fn read_message(&mut self) -> Result<Message> {
+        Err(ChatError::ServerError(
+            "The response stream cannot be read from".to_string(),
+        ))
+    }
+
+    async fn write_message(&mut self, message: &Message) -> Result<()> {
+        self.0
+            .send(Ok(message.clone().into()))
+            .await
+            .map_err(|_| ChatError::NetworkError("The client closed the stream".to_string()))
+    }
+}
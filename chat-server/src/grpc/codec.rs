@@ -0,0 +1,76 @@
+//! Protobuf frames on the TCP chat protocol.
+//!
+//! Clients not written in Rust can encode their frames as the `Frame` message
+//! of `proto/chat.proto` instead of CBOR. The connection answers in the codec
+//! of the client's `Hello`.
+
+use super::proto;
+use chat_common::codec::Codec;
+use chat_common::error::{ChatError, Result};
+use chat_common::Message;
+use prost::Message as _;
+
+/// First byte of frames carrying a protobuf `Frame`
+pub const PROTOBUF_FRAME_TAG: u8 = 2;
+
+/// Encodes messages as the `Frame` of the gRPC chat stream.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Protobuf;
+
+impl Codec for Protobuf {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn tag(&self) -> u8 {
+        PROTOBUF_FRAME_TAG
+    }
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>> {
+        Ok(proto::Frame::from(message.clone()).encode_to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message> {
+        let frame = proto::Frame::decode(bytes)
+            .map_err(|e| ChatError::SerializationError(e.to_string()))?;
+        Message::try_from(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_common::async_message_stream::DEFAULT_MAX_FRAME_SIZE;
+    use chat_common::codec::{read_frame_with, write_frame_with, CodecReader, CodecWriter, Codecs};
+    use chat_common::{AsyncMessageStream, PROTOCOL_VERSION};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_answers_protobuf_hello_in_protobuf() {
+        let codecs = Codecs::default().with_codec(Arc::new(Protobuf));
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (server_reader, server_writer) = tokio::io::split(server);
+        let mut reader = CodecReader::new(server_reader, codecs.clone());
+        let mut writer = CodecWriter::new(server_writer, codecs);
+
+        let hello = Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec!["history".to_string()],
+        };
+        write_frame_with(&mut client, &hello, &Protobuf)
+            .await
+            .unwrap();
+        assert_eq!(reader.read_message().await.unwrap(), hello);
+
+        let welcome = Message::System("Welcome".to_string());
+        writer.write_message(&welcome).await.unwrap();
+        let received = Codecs::default().with_codec(Arc::new(Protobuf));
+        assert_eq!(
+            read_frame_with(&mut client, &received, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap(),
+            welcome
+        );
+        assert_eq!(received.selected().name(), "protobuf");
+    }
+}
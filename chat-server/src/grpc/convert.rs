@@ -0,0 +1,806 @@
+//! Conversions between the protobuf types and the server's models and messages.
+
+use super::proto::{self, frame::Kind};
+use crate::models::message::{Message as StoredMessage, MessageType};
+use crate::models::message_revision::MessageRevision;
+use crate::models::user::User;
+use chat_common::error::{ChatError, ErrorCode};
+use chat_common::{
+    Availability, HistoricalKind, HistoricalMessage, Message, Priority, QuotedMessage,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// Format of timestamps, matching their JSON serialization in the REST API
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+fn timestamp(at: NaiveDateTime) -> String {
+    at.format(TIMESTAMP_FORMAT).to_string()
+}
+
+impl From<User> for proto::User {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            created_at: timestamp(user.created_at),
+            updated_at: timestamp(user.updated_at),
+        }
+    }
+}
+
+impl From<StoredMessage> for proto::StoredMessage {
+    fn from(message: StoredMessage) -> Self {
+        Self {
+            id: message.id,
+            sender_id: message.sender_id,
+            message_type: proto::MessageType::from(message.message_type).into(),
+            content: message.content,
+            file_name: message.file_name,
+            workspace_id: message.workspace_id,
+            sha256: message.sha256,
+            created_at: timestamp(message.created_at),
+            updated_at: timestamp(message.updated_at),
+            edited_at: message.edited_at.map(timestamp),
+            seq: message.seq,
+        }
+    }
+}
+
+impl From<MessageRevision> for proto::MessageRevision {
+    fn from(revision: MessageRevision) -> Self {
+        Self {
+            id: revision.id,
+            message_id: revision.message_id,
+            content: revision.content,
+            file_name: revision.file_name,
+            edited_by: revision.edited_by,
+            edited_at: timestamp(revision.edited_at),
+        }
+    }
+}
+
+impl From<MessageType> for proto::MessageType {
+    fn from(message_type: MessageType) -> Self {
+        match message_type {
+            MessageType::Text => proto::MessageType::Text,
+            MessageType::File => proto::MessageType::File,
+            MessageType::Image => proto::MessageType::Image,
+            MessageType::Action => proto::MessageType::Action,
+        }
+    }
+}
+
+/// Returns the message type, or `None` if it is unspecified.
+pub fn message_type(message_type: proto::MessageType) -> Option<MessageType> {
+    match message_type {
+        proto::MessageType::Unspecified => None,
+        proto::MessageType::Text => Some(MessageType::Text),
+        proto::MessageType::File => Some(MessageType::File),
+        proto::MessageType::Image => Some(MessageType::Image),
+        proto::MessageType::Action => Some(MessageType::Action),
+    }
+}
+
+impl From<ErrorCode> for proto::ErrorCode {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::FileNotFound => proto::ErrorCode::FileNotFound,
+            ErrorCode::PermissionDenied => proto::ErrorCode::PermissionDenied,
+            ErrorCode::InvalidInput => proto::ErrorCode::InvalidInput,
+            ErrorCode::ServerError => proto::ErrorCode::ServerError,
+            ErrorCode::NetworkError => proto::ErrorCode::NetworkError,
+            ErrorCode::ImageProcessingError => proto::ErrorCode::ImageProcessingError,
+            ErrorCode::IntegrityError => proto::ErrorCode::IntegrityError,
+            ErrorCode::Conflict => proto::ErrorCode::Conflict,
+            ErrorCode::QuotaExceeded => proto::ErrorCode::QuotaExceeded,
+            ErrorCode::UnsupportedProtocol => proto::ErrorCode::UnsupportedProtocol,
+            ErrorCode::UnknownError => proto::ErrorCode::Unknown,
+        }
+    }
+}
+
+impl From<proto::ErrorCode> for ErrorCode {
+    fn from(code: proto::ErrorCode) -> Self {
+        match code {
+            proto::ErrorCode::FileNotFound => ErrorCode::FileNotFound,
+            proto::ErrorCode::PermissionDenied => ErrorCode::PermissionDenied,
+            proto::ErrorCode::InvalidInput => ErrorCode::InvalidInput,
+            proto::ErrorCode::ServerError => ErrorCode::ServerError,
+            proto::ErrorCode::NetworkError => ErrorCode::NetworkError,
+            proto::ErrorCode::ImageProcessingError => ErrorCode::ImageProcessingError,
+            proto::ErrorCode::IntegrityError => ErrorCode::IntegrityError,
+            proto::ErrorCode::Conflict => ErrorCode::Conflict,
+            proto::ErrorCode::QuotaExceeded => ErrorCode::QuotaExceeded,
+            proto::ErrorCode::UnsupportedProtocol => ErrorCode::UnsupportedProtocol,
+            proto::ErrorCode::Unknown => ErrorCode::UnknownError,
+        }
+    }
+}
+
+impl From<Priority> for proto::Priority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Low => proto::Priority::Low,
+            Priority::Normal => proto::Priority::Normal,
+            Priority::Urgent => proto::Priority::Urgent,
+        }
+    }
+}
+
+impl From<proto::Priority> for Priority {
+    fn from(priority: proto::Priority) -> Self {
+        match priority {
+            proto::Priority::Low => Priority::Low,
+            proto::Priority::Normal => Priority::Normal,
+            proto::Priority::Urgent => Priority::Urgent,
+        }
+    }
+}
+
+impl From<Availability> for proto::Availability {
+    fn from(availability: Availability) -> Self {
+        match availability {
+            Availability::Available => proto::Availability::Available,
+            Availability::Away => proto::Availability::Away,
+            Availability::Busy => proto::Availability::Busy,
+        }
+    }
+}
+
+impl From<proto::Availability> for Availability {
+    fn from(availability: proto::Availability) -> Self {
+        match availability {
+            proto::Availability::Available => Availability::Available,
+            proto::Availability::Away => Availability::Away,
+            proto::Availability::Busy => Availability::Busy,
+        }
+    }
+}
+
+impl From<HistoricalKind> for proto::MessageType {
+    fn from(kind: HistoricalKind) -> Self {
+        match kind {
+            HistoricalKind::Text => proto::MessageType::Text,
+            HistoricalKind::Action => proto::MessageType::Action,
+            HistoricalKind::File => proto::MessageType::File,
+            HistoricalKind::Image => proto::MessageType::Image,
+        }
+    }
+}
+
+impl From<HistoricalMessage> for proto::HistoricalMessage {
+    fn from(message: HistoricalMessage) -> Self {
+        Self {
+            id: message.id,
+            seq: message.seq,
+            sent_at: message.sent_at.to_rfc3339(),
+            sender: message.sender,
+            kind: proto::MessageType::from(message.kind).into(),
+            content: message.content,
+            file_name: message.file_name,
+        }
+    }
+}
+
+impl TryFrom<proto::HistoricalMessage> for HistoricalMessage {
+    type Error = ChatError;
+
+    fn try_from(message: proto::HistoricalMessage) -> Result<Self, ChatError> {
+        let kind = match message.kind() {
+            proto::MessageType::Text => HistoricalKind::Text,
+            proto::MessageType::Action => HistoricalKind::Action,
+            proto::MessageType::File => HistoricalKind::File,
+            proto::MessageType::Image => HistoricalKind::Image,
+            proto::MessageType::Unspecified => {
+                return Err(ChatError::InvalidInput(format!(
+                    "Historical message {} without a type",
+                    message.id
+                )))
+            }
+        };
+        Ok(Self {
+            id: message.id,
+            seq: message.seq,
+            sent_at: DateTime::parse_from_rfc3339(&message.sent_at)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|e| ChatError::InvalidInput(format!("Invalid sent_at: {}", e)))?,
+            sender: message.sender,
+            kind,
+            content: message.content,
+            file_name: message.file_name,
+        })
+    }
+}
+
+fn attachment(name: String, metadata: serde_json::Value, data: Vec<u8>) -> proto::Attachment {
+    proto::Attachment {
+        name,
+        metadata: metadata.to_string(),
+        data,
+    }
+}
+
+impl From<Message> for proto::Frame {
+    fn from(message: Message) -> Self {
+        let kind = match message {
+            Message::Text(content) => Kind::Text(content),
+            Message::System(content) => Kind::System(content),
+            Message::File {
+                name,
+                metadata,
+                data,
+            } => Kind::File(attachment(name, metadata, data)),
+            Message::Image {
+                name,
+                metadata,
+                data,
+            } => Kind::Image(attachment(name, metadata, data)),
+            Message::Error {
+                code,
+                message,
+                in_reply_to,
+                details,
+            } => Kind::Error(proto::Error {
+                code: proto::ErrorCode::from(code).into(),
+                message,
+                in_reply_to,
+                details: details.into_iter().collect(),
+            }),
+            Message::Auth { username, password } => Kind::Auth(proto::Auth { username, password }),
+            Message::Register {
+                username,
+                email,
+                password,
+                invite_code,
+            } => Kind::Register(proto::Register {
+                username,
+                email,
+                password,
+                invite_code,
+            }),
+            Message::AuthResponse {
+                success,
+                token,
+                message,
+            } => Kind::AuthResponse(proto::AuthResponse {
+                success,
+                token,
+                message,
+            }),
+            Message::Hello {
+                protocol_version,
+                capabilities,
+            } => Kind::Hello(proto::Hello {
+                protocol_version,
+                capabilities,
+            }),
+            Message::Ping { nonce } => Kind::Ping(nonce),
+            Message::Pong { nonce } => Kind::Pong(nonce),
+            Message::SwitchWorkspace { slug } => Kind::SwitchWorkspace(slug),
+            Message::ChangePassword {
+                current_password,
+                new_password,
+            } => Kind::ChangePassword(proto::ChangePassword {
+                current_password,
+                new_password,
+            }),
+            Message::Notification { id, kind, content } => {
+                Kind::Notification(proto::Notification { id, kind, content })
+            }
+            Message::PriorityText { priority, content } => {
+                Kind::PriorityText(proto::PriorityText {
+                    priority: proto::Priority::from(priority).into(),
+                    content,
+                })
+            }
+            Message::Ephemeral {
+                content,
+                ttl_secs,
+                expires_at,
+            } => Kind::Ephemeral(proto::Ephemeral {
+                content,
+                ttl_secs,
+                expires_at: expires_at.map(|at| at.to_rfc3339()),
+            }),
+            Message::Request {
+                client_msg_id,
+                message,
+            } => Kind::Request(Box::new(proto::Request {
+                client_msg_id,
+                message: Some(Box::new((*message).into())),
+            })),
+            Message::Reply { content, quote } => Kind::Reply(proto::Reply {
+                content,
+                quote: Some(proto::QuotedMessage {
+                    id: quote.id,
+                    author: quote.author,
+                    excerpt: quote.excerpt,
+                }),
+            }),
+            Message::Poll { question, options } => Kind::Poll(proto::Poll { question, options }),
+            Message::Vote { poll_id, option } => Kind::Vote(proto::Vote { poll_id, option }),
+            Message::PollTally {
+                poll_id,
+                question,
+                options,
+                votes,
+            } => Kind::PollTally(proto::PollTally {
+                poll_id,
+                question,
+                options,
+                votes,
+            }),
+            Message::SetStatus { availability, text } => Kind::SetStatus(proto::SetStatus {
+                availability: proto::Availability::from(availability).into(),
+                text,
+            }),
+            Message::StatusChanged {
+                username,
+                availability,
+                text,
+            } => Kind::StatusChanged(proto::StatusChanged {
+                username,
+                availability: proto::Availability::from(availability).into(),
+                text,
+            }),
+            Message::RoomKey {
+                workspace,
+                key_id,
+                wrapped_key,
+                nonce,
+                last_seq,
+            } => Kind::RoomKey(proto::RoomKey {
+                workspace,
+                key_id,
+                wrapped_key,
+                nonce,
+                last_seq,
+            }),
+            Message::Sequenced {
+                seq,
+                sent_at,
+                message,
+            } => Kind::Sequenced(Box::new(proto::Sequenced {
+                seq,
+                message: Some(Box::new((*message).into())),
+                sent_at: sent_at.map(|at| at.to_rfc3339()),
+            })),
+            Message::Report { message_id, reason } => {
+                Kind::Report(proto::Report { message_id, reason })
+            }
+            Message::Action { content, username } => {
+                Kind::Action(proto::Action { content, username })
+            }
+            Message::Activity { workspace } => Kind::Activity(proto::Activity { workspace }),
+            Message::Download { message_id } => Kind::Download(proto::Download { message_id }),
+            Message::Attachment {
+                message_id,
+                name,
+                metadata,
+                data,
+            } => Kind::Attachment(proto::StoredAttachment {
+                message_id,
+                attachment: Some(attachment(name, metadata, data)),
+            }),
+            Message::History(history) => Kind::History(proto::History {
+                messages: history.into_iter().map(Into::into).collect(),
+            }),
+            Message::Edit {
+                message_id,
+                new_content,
+            } => Kind::Edit(proto::Edit {
+                message_id,
+                new_content,
+            }),
+            Message::Delete { message_id } => Kind::Delete(proto::Delete { message_id }),
+            Message::ChatMessage {
+                sender,
+                sent_at,
+                body,
+            } => Kind::ChatMessage(Box::new(proto::ChatMessage {
+                sender,
+                sent_at: sent_at.to_rfc3339(),
+                body: Some(Box::new((*body).into())),
+            })),
+        };
+        Self { kind: Some(kind) }
+    }
+}
+
+fn metadata(attachment: &proto::Attachment) -> Result<serde_json::Value, ChatError> {
+    serde_json::from_str(&attachment.metadata).map_err(|e| {
+        ChatError::InvalidInput(format!("Invalid metadata of '{}': {}", attachment.name, e))
+    })
+}
+
+impl TryFrom<proto::Frame> for Message {
+    type Error = ChatError;
+
+    fn try_from(frame: proto::Frame) -> Result<Self, ChatError> {
+        let kind = frame
+            .kind
+            .ok_or_else(|| ChatError::InvalidInput("Empty frame".to_string()))?;
+        Ok(match kind {
+            Kind::Text(content) => Message::Text(content),
+            Kind::System(content) => Message::System(content),
+            Kind::File(file) => Message::File {
+                metadata: metadata(&file)?,
+                name: file.name,
+                data: file.data,
+            },
+            Kind::Image(image) => Message::Image {
+                metadata: metadata(&image)?,
+                name: image.name,
+                data: image.data,
+            },
+            Kind::Error(error) => Message::Error {
+                code: error.code().into(),
+                message: error.message,
+                in_reply_to: error.in_reply_to,
+                details: error.details.into_iter().collect(),
+            },
+            Kind::Auth(auth) => Message::Auth {
+                username: auth.username,
+                password: auth.password,
+            },
+            Kind::Register(register) => Message::Register {
+                username: register.username,
+                email: register.email,
+                password: register.password,
+                invite_code: register.invite_code,
+            },
+            Kind::AuthResponse(response) => Message::AuthResponse {
+                success: response.success,
+                token: response.token,
+                message: response.message,
+            },
+            Kind::Hello(hello) => Message::Hello {
+                protocol_version: hello.protocol_version,
+                capabilities: hello.capabilities,
+            },
+            Kind::Ping(nonce) => Message::Ping { nonce },
+            Kind::Pong(nonce) => Message::Pong { nonce },
+            Kind::SwitchWorkspace(slug) => Message::SwitchWorkspace { slug },
+            Kind::ChangePassword(change) => Message::ChangePassword {
+                current_password: change.current_password,
+                new_password: change.new_password,
+            },
+            Kind::Notification(notification) => Message::Notification {
+                id: notification.id,
+                kind: notification.kind,
+                content: notification.content,
+            },
+            Kind::PriorityText(text) => Message::PriorityText {
+                priority: text.priority().into(),
+                content: text.content,
+            },
+            Kind::Ephemeral(ephemeral) => Message::Ephemeral {
+                expires_at: ephemeral
+                    .expires_at
+                    .map(|at| {
+                        DateTime::parse_from_rfc3339(&at)
+                            .map(|at| at.with_timezone(&Utc))
+                            .map_err(|e| {
+                                ChatError::InvalidInput(format!("Invalid expires_at: {}", e))
+                            })
+                    })
+                    .transpose()?,
+                content: ephemeral.content,
+                ttl_secs: ephemeral.ttl_secs,
+            },
+            Kind::Request(request) => {
+                let message = request.message.ok_or_else(|| {
+                    ChatError::InvalidInput("Request without a message".to_string())
+                })?;
+                Message::Request {
+                    client_msg_id: request.client_msg_id,
+                    message: Box::new(Message::try_from(*message)?),
+                }
+            }
+            Kind::Reply(reply) => {
+                let quote = reply
+                    .quote
+                    .ok_or_else(|| ChatError::InvalidInput("Reply without a quote".to_string()))?;
+                Message::Reply {
+                    content: reply.content,
+                    quote: QuotedMessage {
+                        id: quote.id,
+                        author: quote.author,
+                        excerpt: quote.excerpt,
+                    },
+                }
+            }
+            Kind::Poll(poll) => Message::Poll {
+                question: poll.question,
+                options: poll.options,
+            },
+            Kind::Vote(vote) => Message::Vote {
+                poll_id: vote.poll_id,
+                option: vote.option,
+            },
+            Kind::PollTally(tally) => Message::PollTally {
+                poll_id: tally.poll_id,
+                question: tally.question,
+                options: tally.options,
+                votes: tally.votes,
+            },
+            Kind::SetStatus(status) => Message::SetStatus {
+                availability: status.availability().into(),
+                text: status.text,
+            },
+            Kind::StatusChanged(status) => Message::StatusChanged {
+                availability: status.availability().into(),
+                username: status.username,
+                text: status.text,
+            },
+            Kind::RoomKey(key) => Message::RoomKey {
+                workspace: key.workspace,
+                key_id: key.key_id,
+                wrapped_key: key.wrapped_key,
+                nonce: key.nonce,
+                last_seq: key.last_seq,
+            },
+            Kind::Sequenced(sequenced) => {
+                let message = sequenced.message.ok_or_else(|| {
+                    ChatError::InvalidInput("Sequenced frame without a message".to_string())
+                })?;
+                Message::Sequenced {
+                    seq: sequenced.seq,
+                    sent_at: sequenced
+                        .sent_at
+                        .map(|at| {
+                            DateTime::parse_from_rfc3339(&at)
+                                .map(|at| at.with_timezone(&Utc))
+                                .map_err(|e| {
+                                    ChatError::InvalidInput(format!("Invalid sent_at: {}", e))
+                                })
+                        })
+                        .transpose()?,
+                    message: Box::new(Message::try_from(*message)?),
+                }
+            }
+            Kind::Report(report) => Message::Report {
+                message_id: report.message_id,
+                reason: report.reason,
+            },
+            Kind::Action(action) => Message::Action {
+                content: action.content,
+                username: action.username,
+            },
+            Kind::Activity(activity) => Message::Activity {
+                workspace: activity.workspace,
+            },
+            Kind::Download(download) => Message::Download {
+                message_id: download.message_id,
+            },
+            Kind::Attachment(stored) => {
+                let attachment = stored.attachment.ok_or_else(|| {
+                    ChatError::InvalidInput("Attachment frame without content".to_string())
+                })?;
+                Message::Attachment {
+                    message_id: stored.message_id,
+                    metadata: metadata(&attachment)?,
+                    name: attachment.name,
+                    data: attachment.data,
+                }
+            }
+            Kind::History(history) => Message::History(
+                history
+                    .messages
+                    .into_iter()
+                    .map(HistoricalMessage::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Kind::Edit(edit) => Message::Edit {
+                message_id: edit.message_id,
+                new_content: edit.new_content,
+            },
+            Kind::Delete(delete) => Message::Delete {
+                message_id: delete.message_id,
+            },
+            Kind::ChatMessage(envelope) => {
+                let body = envelope.body.ok_or_else(|| {
+                    ChatError::InvalidInput("ChatMessage frame without a body".to_string())
+                })?;
+                Message::ChatMessage {
+                    sender: envelope.sender,
+                    sent_at: DateTime::parse_from_rfc3339(&envelope.sent_at)
+                        .map(|at| at.with_timezone(&Utc))
+                        .map_err(|e| ChatError::InvalidInput(format!("Invalid sent_at: {}", e)))?,
+                    body: Box::new(Message::try_from(*body)?),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn round_trip(message: Message) {
+        let frame = proto::Frame::from(message.clone());
+        assert_eq!(Message::try_from(frame).unwrap(), message);
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        round_trip(Message::Text("{\"ciphertext\":\"abc\"}".to_string()));
+        round_trip(Message::File {
+            name: "report.pdf".to_string(),
+            metadata: serde_json::json!({"nonce": "xyz", "sha256": "00ff"}),
+            data: vec![1, 2, 3],
+        });
+        round_trip(Message::Error {
+            code: ErrorCode::IntegrityError,
+            message: "Hash mismatch".to_string(),
+            in_reply_to: Some("42".to_string()),
+            details: BTreeMap::from([("field".to_string(), "sha256".to_string())]),
+        });
+        round_trip(Message::Ping { nonce: u64::MAX });
+        round_trip(Message::Register {
+            username: "dave".to_string(),
+            email: "dave@example.com".to_string(),
+            password: "Plum-Orbit-Cactus-42".to_string(),
+            invite_code: Some("welcome".to_string()),
+        });
+        round_trip(Message::ChangePassword {
+            current_password: "password123".to_string(),
+            new_password: "Plum-Orbit-Cactus-42".to_string(),
+        });
+        round_trip(Message::PriorityText {
+            priority: Priority::Urgent,
+            content: "fire".to_string(),
+        });
+        round_trip(Message::Ephemeral {
+            content: "secret".to_string(),
+            ttl_secs: 30,
+            expires_at: Some("2024-05-01T12:00:30Z".parse().unwrap()),
+        });
+        round_trip(Message::Request {
+            client_msg_id: "7".to_string(),
+            message: Box::new(Message::SwitchWorkspace {
+                slug: "design".to_string(),
+            }),
+        });
+        round_trip(Message::Reply {
+            content: "{\"ciphertext\":\"def\"}".to_string(),
+            quote: QuotedMessage {
+                id: 12,
+                author: "alice".to_string(),
+                excerpt: "{\"ciphertext\":\"abc\"}".to_string(),
+            },
+        });
+        round_trip(Message::Poll {
+            question: "Lunch?".to_string(),
+            options: vec!["Pizza".to_string(), "Sushi".to_string()],
+        });
+        round_trip(Message::Vote {
+            poll_id: 4,
+            option: 1,
+        });
+        round_trip(Message::PollTally {
+            poll_id: 4,
+            question: "Lunch?".to_string(),
+            options: vec!["Pizza".to_string(), "Sushi".to_string()],
+            votes: vec![0, 3],
+        });
+        round_trip(Message::SetStatus {
+            availability: Availability::Away,
+            text: Some("🌴 on vacation".to_string()),
+        });
+        round_trip(Message::StatusChanged {
+            username: "alice".to_string(),
+            availability: Availability::Busy,
+            text: None,
+        });
+        round_trip(Message::RoomKey {
+            workspace: "design".to_string(),
+            key_id: "3f9a".to_string(),
+            wrapped_key: "d3JhcHBlZA==".to_string(),
+            nonce: "bm9uY2U=".to_string(),
+            last_seq: 41,
+        });
+        round_trip(Message::Sequenced {
+            seq: 42,
+            sent_at: Some("2024-05-01T12:00:00Z".parse().unwrap()),
+            message: Box::new(Message::Text("{\"ciphertext\":\"ghi\"}".to_string())),
+        });
+        round_trip(Message::ChatMessage {
+            sender: "alice".to_string(),
+            sent_at: "2024-05-01T12:00:00Z".parse().unwrap(),
+            body: Box::new(Message::Sequenced {
+                seq: 42,
+                sent_at: Some("2024-05-01T12:00:00Z".parse().unwrap()),
+                message: Box::new(Message::Text("{\"ciphertext\":\"ghi\"}".to_string())),
+            }),
+        });
+        round_trip(Message::Report {
+            message_id: 12,
+            reason: "Spam".to_string(),
+        });
+        round_trip(Message::Action {
+            content: "{\"ciphertext\":\"ghi\"}".to_string(),
+            username: Some("alice".to_string()),
+        });
+        round_trip(Message::Activity {
+            workspace: "design".to_string(),
+        });
+        round_trip(Message::Download { message_id: 12 });
+        round_trip(Message::Edit {
+            message_id: 12,
+            new_content: "{\"ciphertext\":\"jkl\"}".to_string(),
+        });
+        round_trip(Message::Delete { message_id: 12 });
+        round_trip(Message::Hello {
+            protocol_version: 1,
+            capabilities: vec!["compression".to_string()],
+        });
+        round_trip(Message::Attachment {
+            message_id: 12,
+            name: "report.pdf".to_string(),
+            metadata: serde_json::json!({"nonce": "xyz", "sha256": "00ff"}),
+            data: vec![1, 2, 3],
+        });
+        round_trip(Message::History(vec![
+            HistoricalMessage {
+                id: 12,
+                seq: 41,
+                sent_at: "2024-05-01T12:00:00Z".parse().unwrap(),
+                sender: "alice".to_string(),
+                kind: HistoricalKind::Text,
+                content: Some("{\"ciphertext\":\"ghi\"}".to_string()),
+                file_name: None,
+            },
+            HistoricalMessage {
+                id: 13,
+                seq: 42,
+                sent_at: "2024-05-01T12:01:00Z".parse().unwrap(),
+                sender: "bob".to_string(),
+                kind: HistoricalKind::File,
+                content: None,
+                file_name: Some("report.pdf".to_string()),
+            },
+        ]));
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        assert!(Message::try_from(proto::Frame { kind: None }).is_err());
+        assert!(Message::try_from(proto::Frame {
+            kind: Some(Kind::Image(proto::Attachment {
+                name: "cat.png".to_string(),
+                metadata: "not json".to_string(),
+                data: Vec::new(),
+            })),
+        })
+        .is_err());
+        assert!(Message::try_from(proto::Frame {
+            kind: Some(Kind::Request(Box::new(proto::Request {
+                client_msg_id: "1".to_string(),
+                message: None,
+            }))),
+        })
+        .is_err());
+        assert!(Message::try_from(proto::Frame {
+            kind: Some(Kind::Reply(proto::Reply {
+                content: "{}".to_string(),
+                quote: None,
+            })),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_timestamp_matches_json() {
+        let at =
+            NaiveDateTime::parse_from_str("2024-05-01 12:30:45.123456", "%Y-%m-%d %H:%M:%S%.f")
+                .unwrap();
+        assert_eq!(
+            timestamp(at),
+            serde_json::to_value(at).unwrap().as_str().unwrap()
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use super::convert;
+use super::proto::messages_server::Messages;
+use super::proto::{
+    CreateMessageRequest, DeleteMessageRequest, DeleteResponse, GetMessageRequest,
+    ListMessageRevisionsRequest, ListMessageRevisionsResponse, ListMessagesRequest,
+    ListMessagesResponse, StoredMessage, UpdateMessageRequest,
+};
+use super::GrpcState;
+use crate::errors::grpc_errors::{query_error, server_error};
+use crate::models::message::{MessageFilter, NewMessage};
+use crate::repositories::message::MessageRepository;
+use crate::repositories::message_revision::MessageRevisionRepository;
+use tonic::{Request, Response, Status};
+
+/// Messages per page of `ListMessages` by default
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+
+/// The `Messages` service, mirroring the `/messages` REST endpoints.
+pub struct MessagesApi {
+    state: GrpcState,
+}
+
+impl MessagesApi {
+    pub fn new(state: GrpcState) -> Self {
+        Self { state }
+    }
+}
+
+#[tonic::async_trait]
+impl Messages for MessagesApi {
+    async fn get_message(
+        &self,
+        request: Request<GetMessageRequest>,
+    ) -> Result<Response<StoredMessage>, Status> {
+        self.state.authenticate(&request).await?;
+        let id = request.into_inner().id;
+
+        MessageRepository::find_by_id(&mut *self.state.conn().await?, id)
+            .await
+            .map(|message| Response::new(message.into()))
+            .map_err(query_error)
+    }
+
+    async fn list_messages(
+        &self,
+        request: Request<ListMessagesRequest>,
+    ) -> Result<Response<ListMessagesResponse>, Status> {
+        self.state.authenticate(&request).await?;
+        let request = request.into_inner();
+        let filter = MessageFilter {
+            sender_id: request.sender_id,
+            message_type: convert::message_type(request.message_type()),
+            workspace_id: request.workspace_id,
+            after_seq: request.after_seq,
+            until_seq: request.until_seq,
+            ..MessageFilter::default()
+        };
+        let limit = match request.limit {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => limit.min(MAX_PAGE_SIZE),
+        };
+
+        MessageRepository::find_filtered_after(
+            &mut *self.state.read_conn().await?,
+            &filter,
+            request.after_id,
+            limit as i64,
+        )
+        .await
+        .map(|messages| {
+            Response::new(ListMessagesResponse {
+                messages: messages.into_iter().map(Into::into).collect(),
+            })
+        })
+        .map_err(|e| server_error(e.into()))
+    }
+
+    async fn create_message(
+        &self,
+        request: Request<CreateMessageRequest>,
+    ) -> Result<Response<StoredMessage>, Status> {
+        self.state.authenticate(&request).await?;
+        let request = request.into_inner();
+        let message_type = convert::message_type(request.message_type())
+            .ok_or_else(|| Status::invalid_argument("A message type is required"))?;
+        let new_message = NewMessage {
+            sender_id: request.sender_id,
+            message_type,
+            content: request.content,
+            file_name: request.file_name,
+            workspace_id: request.workspace_id,
+            sha256: request.sha256,
+            attachment_size: None,
+        };
+
+        MessageRepository::create(&mut *self.state.conn().await?, new_message)
+            .await
+            .map(|message| Response::new(message.into()))
+            .map_err(|e| server_error(e.into()))
+    }
+
+    async fn update_message(
+        &self,
+        request: Request<UpdateMessageRequest>,
+    ) -> Result<Response<StoredMessage>, Status> {
+        let user = self.state.authenticate(&request).await?;
+        let request = request.into_inner();
+
+        let mut conn = self.state.conn().await?;
+        let mut message = MessageRepository::find_by_id(&mut conn, request.id)
+            .await
+            .map_err(query_error)?;
+        if request.content.is_some() {
+            message.content = request.content;
+        }
+        if request.file_name.is_some() {
+            message.file_name = request.file_name;
+        }
+
+        MessageRepository::update(&mut conn, request.id, message, user.id)
+            .await
+            .map(|message| Response::new(message.into()))
+            .map_err(query_error)
+    }
+
+    async fn delete_message(
+        &self,
+        request: Request<DeleteMessageRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        self.state.authenticate(&request).await?;
+        let id = request.into_inner().id;
+
+        MessageRepository::delete(&mut *self.state.conn().await?, id)
+            .await
+            .map(|deleted| {
+                Response::new(DeleteResponse {
+                    deleted: deleted as u64,
+                })
+            })
+            .map_err(|e| server_error(e.into()))
+    }
+
+    async fn list_message_revisions(
+        &self,
+        request: Request<ListMessageRevisionsRequest>,
+    ) -> Result<Response<ListMessageRevisionsResponse>, Status> {
+        self.state.authenticate(&request).await?;
+        let message_id = request.into_inner().message_id;
+
+        let mut conn = self.state.conn().await?;
+        MessageRepository::find_by_id(&mut conn, message_id)
+            .await
+            .map_err(query_error)?;
+
+        MessageRevisionRepository::find_by_message(&mut conn, message_id)
+            .await
+            .map(|revisions| {
+                Response::new(ListMessageRevisionsResponse {
+                    revisions: revisions.into_iter().map(Into::into).collect(),
+                })
+            })
+            .map_err(|e| server_error(e.into()))
+    }
+}
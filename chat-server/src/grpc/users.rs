@@ -0,0 +1,193 @@
+use super::proto::users_server::Users;
+use super::proto::{
+    CreateUserRequest, DeleteResponse, DeleteUserRequest, GetUserRequest, ListUsersRequest,
+    ListUsersResponse, UpdateUserRequest, User as UserMessage,
+};
+use super::GrpcState;
+use crate::errors::grpc_errors::{query_error, server_error, user_write_error};
+use crate::models::user::NewUserRequest;
+use crate::repositories::user::UserRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::account::{self, DeleteAccountError};
+use crate::services::email_links;
+use crate::services::password::PasswordViolation;
+use tonic::{Request, Response, Status};
+
+/// Rejects a password that breaks the password policy. The broken rules are
+/// listed in the `violations` metadata, like in chat protocol errors.
+fn password_policy_error(violations: &[PasswordViolation]) -> Status {
+    let messages = violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    let rules = violations
+        .iter()
+        .map(PasswordViolation::rule)
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut status = Status::invalid_argument(format!(
+        "The password does not meet the password policy: {}",
+        messages
+    ));
+    if let Ok(rules) = rules.parse() {
+        status.metadata_mut().insert("violations", rules);
+    }
+    status
+}
+
+/// The `Users` service, mirroring the `/users` REST endpoints.
+pub struct UsersApi {
+    state: GrpcState,
+}
+
+impl UsersApi {
+    pub fn new(state: GrpcState) -> Self {
+        Self { state }
+    }
+}
+
+#[tonic::async_trait]
+impl Users for UsersApi {
+    async fn get_user(
+        &self,
+        request: Request<GetUserRequest>,
+    ) -> Result<Response<UserMessage>, Status> {
+        self.state.authenticate(&request).await?;
+        let id = request.into_inner().id;
+
+        UserRepository::find_by_id(&mut *self.state.conn().await?, id)
+            .await
+            .map(|user| Response::new(user.into()))
+            .map_err(query_error)
+    }
+
+    async fn list_users(
+        &self,
+        request: Request<ListUsersRequest>,
+    ) -> Result<Response<ListUsersResponse>, Status> {
+        self.state.authenticate(&request).await?;
+        let workspace_id = request.into_inner().workspace_id;
+
+        let mut conn = self.state.read_conn().await?;
+        match workspace_id {
+            Some(workspace_id) => UserRepository::find_by_workspace(&mut conn, workspace_id).await,
+            None => UserRepository::find_all(&mut conn).await,
+        }
+        .map(|users| {
+            Response::new(ListUsersResponse {
+                users: users.into_iter().map(Into::into).collect(),
+            })
+        })
+        .map_err(|e| server_error(e.into()))
+    }
+
+    async fn create_user(
+        &self,
+        request: Request<CreateUserRequest>,
+    ) -> Result<Response<UserMessage>, Status> {
+        let admin = self.state.user(&request).await?;
+        let request = request.into_inner();
+
+        let violations = self
+            .state
+            .passwords
+            .validate(&request.password, &[&request.username, &request.email])
+            .await;
+        if !violations.is_empty() {
+            return Err(password_policy_error(&violations));
+        }
+        let new_user = NewUserRequest {
+            username: request.username,
+            email: request.email,
+            password: request.password,
+            invite_code: None,
+        };
+
+        let mut conn = self.state.conn().await?;
+        let user = match request.invite_code {
+            Some(code) => UserRepository::create_with_invite(&mut conn, new_user, &code)
+                .await
+                .map_err(user_write_error)?
+                .ok_or_else(|| Status::invalid_argument("Invalid or expired invite code"))?,
+            None => {
+                // Server admins may still create accounts without an invite
+                let is_admin = match admin {
+                    Some(admin) => WorkspaceRepository::is_server_admin(&mut conn, admin.id)
+                        .await
+                        .map_err(|e| server_error(e.into()))?,
+                    None => false,
+                };
+
+                if self.state.config.current().require_invite && !is_admin {
+                    return Err(Status::invalid_argument("Invite code required"));
+                }
+
+                UserRepository::create(&mut conn, new_user)
+                    .await
+                    .map_err(user_write_error)?
+            }
+        };
+
+        // The account is usable even if the verification email cannot be queued
+        let public_url = self.state.config.current().public_url.clone();
+        if let Err(e) =
+            email_links::send_verification(&mut conn, &self.state.email, &public_url, &user).await
+        {
+            tracing::error!(
+                "Failed to send a verification email to user {}: {}",
+                user.id,
+                e
+            );
+        }
+        Ok(Response::new(user.into()))
+    }
+
+    async fn update_user(
+        &self,
+        request: Request<UpdateUserRequest>,
+    ) -> Result<Response<UserMessage>, Status> {
+        self.state.authenticate(&request).await?;
+        let request = request.into_inner();
+
+        let mut conn = self.state.conn().await?;
+        let mut user = UserRepository::find_by_id(&mut conn, request.id)
+            .await
+            .map_err(query_error)?;
+        user.username = request.username;
+        user.email = request.email;
+
+        UserRepository::update(&mut conn, request.id, &user)
+            .await
+            .map(|user| Response::new(user.into()))
+            .map_err(user_write_error)
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        self.state.authenticate(&request).await?;
+        let id = request.into_inner().id;
+
+        let mode = self.state.config.current().user_deletion_mode;
+        match account::delete_account(
+            &mut *self.state.conn().await?,
+            id,
+            mode,
+            &self.state.exports,
+            self.state.storage.as_ref(),
+        )
+        .await
+        {
+            Ok(deleted) => Ok(Response::new(DeleteResponse {
+                deleted: deleted as u64,
+            })),
+            Err(e @ DeleteAccountError::Placeholder) => {
+                Err(Status::failed_precondition(e.to_string()))
+            }
+            Err(DeleteAccountError::Database(e)) => Err(server_error(e.into())),
+        }
+    }
+}
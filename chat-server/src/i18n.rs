@@ -0,0 +1,186 @@
+//! Localization of the texts the server sends to chat clients.
+//!
+//! System messages, acknowledgments, error replies and notifications are
+//! Fluent messages looked up by key in `locales/<locale>/server.ftl`. The
+//! translations are compiled into the binary. Users pick their locale with
+//! `PUT /users/me/preferences`; everyone else gets [`DEFAULT_LOCALE`].
+//! Messages missing from a translation fall back to the default locale.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+/// Locale of users without a preference
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// The bundled translations as `(locale, Fluent source)`
+const TRANSLATIONS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en/server.ftl")),
+    ("cs", include_str!("../locales/cs/server.ftl")),
+];
+
+static LOCALIZER: LazyLock<Localizer> =
+    LazyLock::new(|| Localizer::new(TRANSLATIONS).expect("the bundled translations are valid"));
+
+/// Fluent bundles by locale.
+pub struct Localizer {
+    bundles: HashMap<&'static str, FluentBundle<FluentResource>>,
+}
+
+impl Localizer {
+    /// Parses translations given as `(locale, Fluent source)`.
+    ///
+    /// Fails on syntax errors and on messages defined twice.
+    pub fn new(translations: &[(&'static str, &str)]) -> Result<Self, String> {
+        let mut bundles = HashMap::new();
+        for (locale, source) in translations {
+            let language: LanguageIdentifier = locale
+                .parse()
+                .map_err(|e| format!("Invalid locale '{}': {}", locale, e))?;
+            let resource = FluentResource::try_new(source.to_string())
+                .map_err(|(_, errors)| format!("Invalid translation '{}': {:?}", locale, errors))?;
+
+            let mut bundle = FluentBundle::new_concurrent(vec![language]);
+            // Unicode isolation marks around arguments show up in terminals
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .map_err(|errors| format!("Invalid translation '{}': {:?}", locale, errors))?;
+            bundles.insert(*locale, bundle);
+        }
+        Ok(Self { bundles })
+    }
+
+    /// Returns the supported locale matching `locale`, trying its language
+    /// alone if there is no exact match, so `cs-CZ` resolves to `cs`.
+    pub fn resolve(&self, locale: &str) -> Option<&'static str> {
+        let language = locale.split(['-', '_']).next().unwrap_or(locale);
+        [locale, language]
+            .into_iter()
+            .find_map(|candidate| self.bundles.get_key_value(candidate))
+            .map(|(locale, _)| *locale)
+    }
+
+    /// Supported locales in alphabetical order.
+    pub fn locales(&self) -> Vec<&'static str> {
+        let mut locales: Vec<_> = self.bundles.keys().copied().collect();
+        locales.sort_unstable();
+        locales
+    }
+
+    /// Formats the message `key` in `locale`.
+    ///
+    /// Falls back to the default locale for unsupported locales and missing
+    /// messages, and to the key itself if the default locale lacks it too.
+    pub fn text(&self, locale: &str, key: &str, args: &[(&str, FluentValue<'_>)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+
+        let locale = self.resolve(locale).unwrap_or(DEFAULT_LOCALE);
+        for candidate in [locale, DEFAULT_LOCALE] {
+            let Some(bundle) = self.bundles.get(candidate) else {
+                continue;
+            };
+            let Some(pattern) = bundle.get_message(key).and_then(|message| message.value()) else {
+                continue;
+            };
+
+            let mut errors = Vec::new();
+            let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+            if !errors.is_empty() {
+                warn!(
+                    "Failed to format '{}' in '{}': {:?}",
+                    key, candidate, errors
+                );
+            }
+            return text.into_owned();
+        }
+
+        warn!("Missing translation of '{}'", key);
+        key.to_string()
+    }
+}
+
+/// Formats the message `key` in `locale` using the bundled translations.
+///
+/// # Arguments
+/// * `locale` - The recipient's locale
+/// * `key` - The message key in the `.ftl` files
+/// * `args` - Values of the message's `$variables`
+pub fn text(locale: &str, key: &str, args: &[(&str, FluentValue<'_>)]) -> String {
+    LOCALIZER.text(locale, key, args)
+}
+
+/// Returns the bundled locale matching `locale`, if there is one.
+pub fn resolve(locale: &str) -> Option<&'static str> {
+    LOCALIZER.resolve(locale)
+}
+
+/// Locales there are bundled translations for.
+pub fn locales() -> Vec<&'static str> {
+    LOCALIZER.locales()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            text("en", "file-sent", &[("name", "report.pdf".into())]),
+            "File 'report.pdf' sent successfully"
+        );
+        assert_eq!(
+            text("cs", "file-sent", &[("name", "report.pdf".into())]),
+            "Soubor 'report.pdf' byl odeslán"
+        );
+        assert_eq!(text("cs-CZ", "message-sent", &[]), "Zpráva byla odeslána");
+        assert_eq!(text("de", "message-sent", &[]), "Message sent successfully");
+        assert_eq!(text("en", "no-such-message", &[]), "no-such-message");
+    }
+
+    #[test]
+    fn test_plurals() {
+        let rate_limited =
+            |locale, seconds: u64| text(locale, "rate-limited", &[("seconds", seconds.into())]);
+        assert!(rate_limited("en", 1).ends_with("in 1 second"));
+        assert!(rate_limited("en", 5).ends_with("in 5 seconds"));
+        assert!(rate_limited("cs", 1).ends_with("za 1 sekundu"));
+        assert!(rate_limited("cs", 3).ends_with("za 3 sekundy"));
+        assert!(rate_limited("cs", 10).ends_with("za 10 sekund"));
+    }
+
+    #[test]
+    fn test_translations_are_complete() {
+        // Messages start at the beginning of a line with their key
+        let keys = |source: &'static str| {
+            let mut keys: Vec<&str> = source
+                .lines()
+                .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+                .filter_map(|line| line.split_once(" =").map(|(key, _)| key))
+                .collect();
+            keys.sort_unstable();
+            keys
+        };
+
+        let (_, default) = TRANSLATIONS[0];
+        for (locale, source) in TRANSLATIONS {
+            assert_eq!(keys(source), keys(default), "{} differs from en", locale);
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("cs"), Some("cs"));
+        assert_eq!(resolve("en_US"), Some("en"));
+        assert_eq!(resolve("fr"), None);
+        assert_eq!(locales(), vec!["cs", "en"]);
+    }
+}
@@ -1,4 +1,7 @@
+pub mod config;
 pub mod errors;
+pub mod grpc;
+pub mod i18n;
 pub mod models;
 pub mod repositories;
 pub mod routes;
@@ -1,30 +1,105 @@
 use anyhow::{Context, Result as AnyhowResult};
 use chat_common::error::ChatError;
+use chat_server::config::{LogFormat, ServerConfig, SharedConfig};
+use chat_server::errors::rocket_server_errors;
+use chat_server::grpc::{self, GrpcState};
+use chat_server::routes::admin;
 use chat_server::routes::authorization;
+use chat_server::routes::bots;
+use chat_server::routes::feature_flags;
+use chat_server::routes::invitations;
 use chat_server::routes::messages;
 use chat_server::routes::metrics;
+use chat_server::routes::notifications;
+use chat_server::routes::reports;
 use chat_server::routes::users;
+use chat_server::routes::workspaces;
+use chat_server::services::backup;
+use chat_server::services::bot::{HttpTransport, WebhookService};
+use chat_server::services::chat_handle::ChatHandle;
 use chat_server::services::client_service::ClientService;
+use chat_server::services::config_reload::ConfigReloader;
+use chat_server::services::email::EmailService;
+use chat_server::services::export::ExportService;
+use chat_server::services::feature_flags::FeatureFlags;
+use chat_server::services::health::PoolMonitor;
+use chat_server::services::message::pipeline::{PasswordChange, Pipeline, Registration, Webhooks};
+use chat_server::services::password::{Denylist, PasswordService};
+use chat_server::services::password_reset_throttle::PasswordResetThrottle;
+use chat_server::services::registration::RegistrationService;
+use chat_server::services::storage;
+use chat_server::services::tls;
+use chat_server::utils::bind;
 use chat_server::utils::cors::Cors;
 use chat_server::utils::db_connection::CacheConn;
-use chat_server::utils::db_connection::{self, DbConn};
-use chat_server::utils::metrics::Metrics;
+use chat_server::utils::db_connection::{self, DbConn, ReplicaPool};
+use chat_server::utils::metrics::{install_panic_hook, Metrics};
+use chat_server::utils::timeout::RequestTimeout;
+use chat_server::utils::user_cache::UserCache;
+use rocket::data::{Limits, ToByteUnit};
+use rocket::fairing::AdHoc;
 use rocket_db_pools::Database;
 use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter};
 
 const DEFAULT_ADDRESS: &str = "0.0.0.0";
 const DEFAULT_TCP_PORT: &str = "8080";
 
 #[tokio::main]
 async fn main() -> AnyhowResult<()> {
-    tracing_subscriber::fmt::init();
+    // The config file may choose the log format, so it is read before logging
+    // starts; an invalid LOG_FORMAT is reported once the configuration is read
+    let config_file = ConfigReloader::load_config_file();
+    let log_format = env::var("LOG_FORMAT")
+        .ok()
+        .and_then(|format| format.parse().ok())
+        .unwrap_or(LogFormat::Text);
+
+    // The log filter sits behind a reload layer so LOG_LEVEL can change at runtime
+    let (filter, log_filter) = reload::Layer::new(EnvFilter::new("info"));
+    let (text, json) = match log_format {
+        LogFormat::Text => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (None, Some(tracing_subscriber::fmt::layer().json())),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .init();
+
+    if let Err(e) = config_file {
+        warn!("{}", e);
+    }
+    let config = SharedConfig::new(ServerConfig::from_env());
+    let _ = log_filter.reload(EnvFilter::new(&config.current().log_level));
+
+    // `chat-server backup <archive>` and `chat-server restore <archive>` run
+    // instead of the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return backup::run_command(&args, &config.current()).await;
+    }
+
+    let reloader = ConfigReloader::new(
+        config.clone(),
+        Box::new(move |level| {
+            let filter = EnvFilter::try_new(level).map_err(|e| e.to_string())?;
+            log_filter.reload(filter).map_err(|e| e.to_string())
+        }),
+    );
+    reloader
+        .spawn_on_sighup()
+        .context("Failed to install SIGHUP handler")?;
 
     // Initialize metrics
     let metrics = Metrics::new();
+    install_panic_hook(metrics.lock().await.panics.clone());
     let metrics_for_rocket = metrics.clone();
 
     // Initialize database pool for the TCP server
@@ -32,31 +107,245 @@ async fn main() -> AnyhowResult<()> {
     let pool = Arc::new(pool);
     info!("Database connection pool established");
 
+    // Listings read from the replica if one is configured
+    let replica = ReplicaPool::from_env().context("Invalid DATABASE_REPLICA_URL")?;
+    if replica.is_configured() {
+        info!("Read replica configured for read-only queries");
+    }
+
+    // Sample the TCP server's pools here; Rocket's pools are added once they exist
+    let mut monitor = PoolMonitor::new(metrics.clone()).with_db_pool("tcp", (*pool).clone());
+    if let Some(replica) = replica.pool() {
+        monitor = monitor.with_db_pool("replica", replica.clone());
+    }
+
+    // Load the feature flags and keep them in sync with the database
+    let flags = FeatureFlags::new();
+    flags
+        .refresh(&mut *pool.get().await?)
+        .await
+        .context("Failed to load feature flags")?;
+    flags.spawn_refresh(pool.clone());
+
     // Set up the TCP server
     let addr = env::var("SERVER_ADDRESS").unwrap_or_else(|_| DEFAULT_ADDRESS.to_string());
     let tcp_port = env::var("TCP_PORT").unwrap_or_else(|_| DEFAULT_TCP_PORT.to_string());
     let tcp_addr = format!("{}:{}", addr, tcp_port);
-    let listener = tokio::net::TcpListener::bind(&tcp_addr)
+    let bind_retry_period = config.current().bind_retry_period;
+    let listener = bind::bind_with_retry(&tcp_addr, bind_retry_period)
         .await
         .context("Failed to bind to TCP address")?;
+    let tcp_port = listener.local_addr()?.port();
+
+    info!("TCP Server listening on {}", listener.local_addr()?);
+
+    // Chat connections over TLS are only served if a certificate is configured
+    let tls_listener = match &config.current().tls {
+        Some(tls_config) => {
+            let acceptor = tls::acceptor(tls_config).context("Failed to set up TLS")?;
+            let tls_addr = format!("{}:{}", addr, tls_config.port);
+            let listener = bind::bind_with_retry(&tls_addr, bind_retry_period)
+                .await
+                .context("Failed to bind to TLS address")?;
+            info!("TLS server listening on {}", listener.local_addr()?);
+            Some((listener, acceptor))
+        }
+        None => None,
+    };
 
-    info!("TCP Server listening on {}", tcp_addr);
+    // Set up outgoing email and the notification digests
+    let email = EmailService::from_config(&config.current()).context("Invalid SMTP settings")?;
+    if config.current().smtp.is_none() {
+        info!("SMTP_HOST is not set, outgoing email will only be logged");
+    }
+    if let Some(interval) = config.current().email_digest_interval {
+        email.spawn_digests(pool.clone(), interval);
+    }
+
+    // Set up the object storage for attachments and avatars
+    let storage = storage::from_config(&config.current().storage)
+        .context("Failed to set up object storage")?;
+
+    // Messages mentioning bots are delivered to their webhooks
+    let webhooks = WebhookService::new(Arc::new(
+        HttpTransport::new().context("Failed to set up webhook delivery")?,
+    ));
+
+    // New passwords are checked against the policy and any breached-password list
+    let mut passwords = PasswordService::new(config.clone());
+    if let Some(path) = &config.current().password_denylist_file {
+        let denylist =
+            Denylist::load(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        info!("Loaded {} denied passwords", denylist.len());
+        passwords = passwords.with_breach_check(Arc::new(denylist));
+    }
+    let passwords = Arc::new(passwords);
 
     // Initialize client handler
     let clients = Arc::new(Mutex::new(HashMap::new()));
-    let client_handler = ClientService::new(clients, pool.clone(), metrics.clone())?;
+    let exports = Arc::new(ExportService::new(
+        pool.clone(),
+        clients.clone(),
+        config.current().export_dir.clone(),
+    ));
+    let client_handler = Arc::new(
+        ClientService::new(
+            clients.clone(),
+            pool.clone(),
+            metrics.clone(),
+            config.clone(),
+        )?
+        .with_feature_flags(flags.clone())
+        .with_storage(storage.clone())
+        .with_pipeline(
+            Pipeline::standard(config.clone())
+                .register_before(
+                    "auth",
+                    Registration::new(
+                        RegistrationService::new(config.clone(), passwords.clone())
+                            .with_email(email.clone()),
+                    ),
+                )
+                .register_before("feature_flags", PasswordChange::new(passwords.clone()))
+                .register(Webhooks::new(webhooks.clone())),
+        ),
+    );
+    let encryption = client_handler.encryption();
+    // Lets REST handlers reach the live chat connections
+    let chat = ChatHandle::spawn(clients.clone());
+
+    if let Some((tls_listener, acceptor)) = tls_listener {
+        tokio::spawn(tls::serve(
+            tls_listener,
+            acceptor,
+            client_handler.clone(),
+            metrics.clone(),
+        ));
+    }
+
+    // The gRPC API is only served if a port is configured
+    let grpc_listener = match env::var("GRPC_PORT") {
+        Ok(grpc_port) => {
+            let grpc_addr = format!("{}:{}", addr, grpc_port);
+            let listener = bind::bind_with_retry(&grpc_addr, bind_retry_period)
+                .await
+                .context("Failed to bind to gRPC address")?;
+            info!("gRPC server listening on {}", listener.local_addr()?);
+            Some(listener)
+        }
+        Err(_) => None,
+    };
+    let grpc_clients = client_handler.clone();
+    let grpc_pool = pool.clone();
+    let grpc_replica = replica.clone();
+    let grpc_config = config.clone();
+    let grpc_exports = exports.clone();
+    let grpc_passwords = passwords.clone();
+    let grpc_email = email.clone();
+    let grpc_storage = storage.clone();
+    let grpc_metrics = metrics.clone();
+    let rocket_pool = pool.clone();
 
     // Start Rocket server in a separate task
+    let port_file = config.current().port_file.clone();
+    let limits = Limits::default().limit("json", config.current().http_json_limit.bytes());
+    let timeout = RequestTimeout::new(config.clone());
     tokio::spawn(async move {
-        let _rocket = rocket::build()
+        let rocket = rocket::custom(rocket::Config::figment().merge(("limits", limits)))
             .attach(DbConn::init())
             .attach(CacheConn::init())
+            .attach(AdHoc::on_ignite("Pool monitor", |rocket| async move {
+                let mut monitor = monitor;
+                if let Some(db) = DbConn::fetch(&rocket) {
+                    monitor = monitor.with_db_pool("rocket", (**db).clone());
+                }
+                if let Some(cache) = CacheConn::fetch(&rocket) {
+                    monitor = monitor.with_redis((**cache).clone());
+                }
+                monitor.spawn();
+                rocket.manage(monitor)
+            }))
             .attach(Cors)
             .manage(metrics_for_rocket)
-            .mount("/users", users::routes())
-            .mount("/messages", messages::routes())
-            .mount("/auth", authorization::routes())
-            .mount("/", metrics::routes())
+            .manage(PasswordResetThrottle::new(config.clone()))
+            .manage(config)
+            .manage(exports)
+            .manage(passwords)
+            .manage(flags)
+            .manage(email)
+            .manage(storage)
+            .manage(replica)
+            .manage(clients)
+            .manage(rocket_pool)
+            .manage(encryption)
+            .manage(webhooks)
+            .manage(chat)
+            .manage(UserCache::new())
+            .mount("/users", timeout.wrap(users::routes()))
+            .mount("/messages", timeout.wrap(messages::routes()))
+            .mount("/workspaces", timeout.wrap(workspaces::routes()))
+            .mount("/invitations", timeout.wrap(invitations::routes()))
+            .mount("/notifications", timeout.wrap(notifications::routes()))
+            .mount("/reports", timeout.wrap(reports::routes()))
+            .mount("/auth", timeout.wrap(authorization::routes()))
+            .mount("/bots", timeout.wrap(bots::routes()))
+            .mount("/feature-flags", timeout.wrap(feature_flags::routes()))
+            .mount("/admin", timeout.wrap(admin::routes()))
+            .mount("/", timeout.wrap(metrics::routes()))
+            .register("/", rocket_server_errors::catchers())
+            .attach(AdHoc::on_liftoff("gRPC server", move |rocket| {
+                Box::pin(async move {
+                    let Some(listener) = grpc_listener else {
+                        return;
+                    };
+                    // Calls are authenticated against the sessions in Rocket's Redis
+                    let Some(cache) = CacheConn::fetch(rocket) else {
+                        error!("Not starting the gRPC server without Redis");
+                        return;
+                    };
+                    let state = GrpcState {
+                        pool: grpc_pool,
+                        replica: grpc_replica,
+                        cache: (**cache).clone(),
+                        config: grpc_config,
+                        exports: grpc_exports,
+                        passwords: grpc_passwords,
+                        email: grpc_email,
+                        storage: grpc_storage,
+                        metrics: grpc_metrics,
+                    };
+                    tokio::spawn(async move {
+                        if let Err(e) = grpc::serve(listener, state, grpc_clients).await {
+                            error!("gRPC server failed: {}", e);
+                        }
+                    });
+                })
+            }))
+            .attach(AdHoc::on_liftoff("Port report", move |rocket| {
+                Box::pin(async move {
+                    let http_port = rocket.config().port;
+                    info!("HTTP server listening on port {}", http_port);
+                    if let Some(path) = port_file {
+                        if let Err(e) = bind::write_port_file(&path, tcp_port, http_port) {
+                            error!("Failed to write {}: {}", path.display(), e);
+                        }
+                    }
+                })
+            }));
+
+        // Rocket binds its port itself, so wait here until it is free
+        let http_config = rocket
+            .figment()
+            .extract::<rocket::Config>()
+            .expect("Invalid Rocket configuration");
+        let http_addr =
+            std::net::SocketAddr::new(http_config.address, http_config.port).to_string();
+        if let Err(e) = bind::wait_until_free(&http_addr, bind_retry_period).await {
+            error!("Failed to bind the HTTP server: {}", e);
+            return;
+        }
+
+        let _rocket = rocket
             .launch()
             .await
             .expect("Failed to launch Rocket server");
@@ -0,0 +1,117 @@
+use crate::schema::bots;
+use chat_common::encryption::file::sha256_hex;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use rand::{distr::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of bot API tokens, so leaked tokens are easy to recognize
+pub const BOT_TOKEN_PREFIX: &str = "bot_";
+/// Random characters of a bot API token after the prefix
+const BOT_TOKEN_LENGTH: usize = 48;
+/// Password hash of bot accounts; it never verifies, so bots cannot log in
+pub const BOT_PASSWORD_HASH: &str = "!";
+
+#[derive(Queryable, Identifiable, Selectable, Serialize, Debug)]
+#[diesel(table_name = bots, primary_key(user_id))]
+pub struct Bot {
+    /// The bot's user account, which it posts messages as
+    pub user_id: i32,
+    /// The user who created the bot and manages it
+    pub owner_id: i32,
+    /// The workspace the bot posts to and is mentioned in
+    pub workspace_id: i32,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    /// URL messages mentioning the bot are POSTed to
+    pub webhook_url: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A bot with the username of its account, as returned by the API.
+#[derive(Serialize, Debug)]
+pub struct BotProfile {
+    #[serde(flatten)]
+    pub bot: Bot,
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewBotRequest {
+    pub username: String,
+    /// The default workspace if not set
+    pub workspace_id: Option<i32>,
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateBotRequest {
+    /// The new webhook URL, or None to stop webhook delivery
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BotMessageRequest {
+    pub content: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = bots)]
+pub struct NewBot {
+    pub owner_id: i32,
+    pub workspace_id: i32,
+    pub token_hash: String,
+    pub webhook_url: Option<String>,
+}
+
+/// Generates a new bot API token. Only its hash is stored.
+pub fn generate_token() -> String {
+    let random: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(BOT_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{}{}", BOT_TOKEN_PREFIX, random)
+}
+
+/// Returns the hash a bot API token is stored and looked up by.
+pub fn hash_token(token: &str) -> String {
+    sha256_hex(token.as_bytes())
+}
+
+/// Checks that a webhook URL is an absolute `http` or `https` URL.
+pub fn validate_webhook_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(()),
+        scheme => Err(format!(
+            "Webhook URLs must use http or https, not {}",
+            scheme
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let token = generate_token();
+        assert!(token.starts_with(BOT_TOKEN_PREFIX));
+        assert_eq!(token.len(), BOT_TOKEN_PREFIX.len() + BOT_TOKEN_LENGTH);
+        assert_ne!(token, generate_token());
+
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_eq!(hash_token(&token).len(), 64);
+        assert!(!hash_token(&token).contains(&token));
+    }
+
+    #[test]
+    fn test_validate_webhook_url() {
+        assert!(validate_webhook_url("https://example.com/hooks/chat").is_ok());
+        assert!(validate_webhook_url("http://localhost:9000").is_ok());
+        assert!(validate_webhook_url("ftp://example.com").is_err());
+        assert!(validate_webhook_url("example.com/hook").is_err());
+    }
+}
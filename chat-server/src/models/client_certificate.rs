@@ -0,0 +1,70 @@
+use crate::schema::client_certificates;
+use chat_common::encryption::file::sha256_hex;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A TLS client certificate that logs its connections in as a user.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = client_certificates)]
+pub struct ClientCertificate {
+    /// Lowercase hex SHA-256 of the DER-encoded certificate
+    pub fingerprint: String,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = client_certificates)]
+pub struct NewClientCertificate {
+    pub fingerprint: String,
+    pub user_id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct NewClientCertificateRequest {
+    /// SHA-256 fingerprint in hex, optionally separated by colons
+    pub fingerprint: String,
+}
+
+/// Returns the fingerprint a DER-encoded certificate is registered under.
+pub fn fingerprint(der: &[u8]) -> String {
+    sha256_hex(der)
+}
+
+/// Brings a fingerprint as printed by common tools, e.g.
+/// `openssl x509 -fingerprint -sha256`, into its stored form.
+///
+/// # Returns
+/// * `Option<String>` - The lowercase hex fingerprint, or None if the input
+///   is not a SHA-256 fingerprint
+pub fn normalize_fingerprint(fingerprint: &str) -> Option<String> {
+    let hex: String = fingerprint
+        .trim()
+        .chars()
+        .filter(|c| *c != ':')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_fingerprint() {
+        let hex = "ab".repeat(32);
+        let openssl = vec!["AB"; 32].join(":");
+        assert_eq!(normalize_fingerprint(&openssl), Some(hex.clone()));
+        assert_eq!(normalize_fingerprint(&format!(" {} ", hex)), Some(hex));
+        assert_eq!(normalize_fingerprint("ab:cd"), None);
+        assert_eq!(normalize_fingerprint(&"zz".repeat(32)), None);
+    }
+
+    #[test]
+    fn test_fingerprint_is_normalized() {
+        let fingerprint = fingerprint(b"certificate");
+        assert_eq!(normalize_fingerprint(&fingerprint), Some(fingerprint));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::schema::do_not_disturb;
+use chrono::{NaiveDateTime, NaiveTime};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Stored do-not-disturb settings of a user. Users without a row are never
+/// in do-not-disturb mode.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = do_not_disturb)]
+pub struct DoNotDisturb {
+    pub user_id: i32,
+    /// Switched on manually, regardless of the schedule
+    pub enabled: bool,
+    /// Daily window in UTC; both ends are set or neither
+    pub starts_at: Option<NaiveTime>,
+    pub ends_at: Option<NaiveTime>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateDoNotDisturbRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub starts_at: Option<NaiveTime>,
+    #[serde(default)]
+    pub ends_at: Option<NaiveTime>,
+}
+
+/// Do-not-disturb settings cached on a user's connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DndSettings {
+    pub enabled: bool,
+    /// Daily window in UTC, which may wrap around midnight
+    pub schedule: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl DndSettings {
+    /// Returns `true` if the user does not want to be disturbed at `now` (UTC).
+    pub fn is_active(&self, now: NaiveTime) -> bool {
+        match self.schedule {
+            _ if self.enabled => true,
+            Some((starts_at, ends_at)) if starts_at <= ends_at => starts_at <= now && now < ends_at,
+            // The window wraps around midnight, e.g. 22:00 - 07:00
+            Some((starts_at, ends_at)) => now >= starts_at || now < ends_at,
+            None => false,
+        }
+    }
+}
+
+impl From<&DoNotDisturb> for DndSettings {
+    fn from(settings: &DoNotDisturb) -> Self {
+        Self {
+            enabled: settings.enabled,
+            schedule: settings.starts_at.zip(settings.ends_at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_dnd_schedule() {
+        assert!(!DndSettings::default().is_active(time(12, 0)));
+
+        let office = DndSettings {
+            enabled: false,
+            schedule: Some((time(9, 0), time(17, 0))),
+        };
+        assert!(office.is_active(time(9, 0)));
+        assert!(!office.is_active(time(17, 0)));
+        assert!(!office.is_active(time(20, 0)));
+
+        let night = DndSettings {
+            enabled: false,
+            schedule: Some((time(22, 0), time(7, 0))),
+        };
+        assert!(night.is_active(time(23, 30)));
+        assert!(night.is_active(time(6, 59)));
+        assert!(!night.is_active(time(12, 0)));
+
+        // Switching it on manually overrides the schedule
+        let manual = DndSettings {
+            enabled: true,
+            ..night
+        };
+        assert!(manual.is_active(time(12, 0)));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::schema::email_tokens;
+use chat_common::encryption::file::sha256_hex;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use rand::{distr::Alphanumeric, Rng};
+use serde::Deserialize;
+
+/// Purpose of a token confirming an email address
+pub const VERIFICATION_PURPOSE: &str = "verification";
+/// Purpose of a token letting a user choose a new password
+pub const PASSWORD_RESET_PURPOSE: &str = "password_reset";
+
+/// Length of generated email tokens
+pub const EMAIL_TOKEN_LENGTH: usize = 48;
+
+/// A token emailed to a user as part of a link. Only its SHA-256 is stored,
+/// so the database alone cannot be used to take over an account.
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = email_tokens)]
+pub struct EmailToken {
+    pub token_sha256: String,
+    pub user_id: i32,
+    pub purpose: String,
+    /// The address the link was sent to
+    pub email: String,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = email_tokens)]
+pub struct NewEmailToken {
+    pub token_sha256: String,
+    pub user_id: i32,
+    pub purpose: String,
+    pub email: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl NewEmailToken {
+    /// Generates a token for a user.
+    ///
+    /// # Arguments
+    /// * `user_id` - The user the token is for
+    /// * `purpose` - [`VERIFICATION_PURPOSE`] or [`PASSWORD_RESET_PURPOSE`]
+    /// * `email` - The address the token is sent to
+    /// * `valid_for` - How long the token can be used
+    ///
+    /// # Returns
+    /// * `(String, Self)` - The token for the link and the row to store
+    pub fn generate(
+        user_id: i32,
+        purpose: &str,
+        email: &str,
+        valid_for: Duration,
+    ) -> (String, Self) {
+        let token: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(EMAIL_TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let new_token = Self {
+            token_sha256: sha256_hex(token.as_bytes()),
+            user_id,
+            purpose: purpose.to_string(),
+            email: email.to_string(),
+            expires_at: Utc::now().naive_utc() + valid_for,
+        };
+        (token, new_token)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_hash_is_stored() {
+        let (token, new_token) = NewEmailToken::generate(
+            7,
+            PASSWORD_RESET_PURPOSE,
+            "alice@example.com",
+            Duration::hours(1),
+        );
+
+        assert_eq!(token.len(), EMAIL_TOKEN_LENGTH);
+        assert_ne!(new_token.token_sha256, token);
+        assert_eq!(new_token.token_sha256, sha256_hex(token.as_bytes()));
+        assert!(new_token.expires_at > Utc::now().naive_utc());
+    }
+}
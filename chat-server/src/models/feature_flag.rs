@@ -0,0 +1,43 @@
+use crate::schema::feature_flags;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Identifiable, Selectable, Serialize, Debug, Clone, PartialEq)]
+#[diesel(table_name = feature_flags, primary_key(name))]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    /// Percentage of users the flag is enabled for while it is enabled
+    pub rollout_percentage: i32,
+    pub description: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct FeatureFlagRequest {
+    pub enabled: bool,
+    pub rollout_percentage: Option<i32>,
+    pub description: Option<String>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = feature_flags)]
+pub struct NewFeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub rollout_percentage: i32,
+    pub description: String,
+}
+
+impl NewFeatureFlag {
+    /// Builds a flag from an admin request, rolling out to everyone by default.
+    pub fn new(name: &str, request: FeatureFlagRequest) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: request.enabled,
+            rollout_percentage: request.rollout_percentage.unwrap_or(100),
+            description: request.description.unwrap_or_default(),
+        }
+    }
+}
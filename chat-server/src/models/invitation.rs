@@ -0,0 +1,131 @@
+use crate::schema::{invitation_redemptions, invitations};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use rand::{distr::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Length of generated invite codes
+pub const INVITE_CODE_LENGTH: usize = 12;
+
+#[derive(Queryable, Identifiable, Selectable, Serialize, Debug)]
+#[diesel(table_name = invitations)]
+pub struct Invitation {
+    pub id: i32,
+    pub code: String,
+    pub created_by: i32,
+    /// Workspace the invite leads into, or None for a server-wide invite
+    pub workspace_id: Option<i32>,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Invitation {
+    /// Returns `true` if the invite has uses left and has not expired at `now`.
+    pub fn is_usable(&self, now: NaiveDateTime) -> bool {
+        self.uses < self.max_uses && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewInvitationRequest {
+    pub workspace_id: Option<i32>,
+    pub max_uses: Option<i32>,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = invitations)]
+pub struct NewInvitation {
+    pub code: String,
+    pub created_by: i32,
+    pub workspace_id: Option<i32>,
+    pub max_uses: i32,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl NewInvitation {
+    /// Builds an invitation with a freshly generated code.
+    ///
+    /// # Arguments
+    /// * `request` - The invite settings requested by the admin
+    /// * `created_by` - The ID of the admin creating the invite
+    pub fn new(request: NewInvitationRequest, created_by: i32) -> Self {
+        let code = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(INVITE_CODE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self {
+            code,
+            created_by,
+            workspace_id: request.workspace_id,
+            max_uses: request.max_uses.unwrap_or(1).max(1),
+            expires_at: request
+                .expires_in_hours
+                .map(|hours| Utc::now().naive_utc() + Duration::hours(hours)),
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = invitation_redemptions)]
+pub struct InvitationRedemption {
+    pub invitation_id: i32,
+    pub user_id: i32,
+    pub redeemed_at: NaiveDateTime,
+    /// Set once the user logged in after redeeming the invite
+    pub first_login_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = invitation_redemptions)]
+pub struct NewInvitationRedemption {
+    pub invitation_id: i32,
+    pub user_id: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invitation(max_uses: i32, uses: i32, expires_at: Option<NaiveDateTime>) -> Invitation {
+        Invitation {
+            id: 1,
+            code: "code".to_string(),
+            created_by: 1,
+            workspace_id: None,
+            max_uses,
+            uses,
+            expires_at,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_invitation_usability() {
+        let now = Utc::now().naive_utc();
+
+        assert!(invitation(2, 1, None).is_usable(now));
+        assert!(!invitation(1, 1, None).is_usable(now));
+        assert!(invitation(1, 0, Some(now + Duration::hours(1))).is_usable(now));
+        assert!(!invitation(1, 0, Some(now - Duration::hours(1))).is_usable(now));
+    }
+
+    #[test]
+    fn test_new_invitation_generates_code() {
+        let request = NewInvitationRequest {
+            workspace_id: Some(3),
+            max_uses: Some(0),
+            expires_in_hours: None,
+        };
+        let invitation = NewInvitation::new(request, 7);
+
+        assert_eq!(invitation.code.len(), INVITE_CODE_LENGTH);
+        assert_eq!(invitation.max_uses, 1);
+        assert_eq!(invitation.workspace_id, Some(3));
+        assert!(invitation.expires_at.is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::schema::messages;
-use chrono::NaiveDateTime;
+use crate::utils::csv;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::deserialize::FromSqlRow;
 use diesel::expression::AsExpression;
 use diesel::pg::Pg;
@@ -23,6 +24,18 @@ pub struct Message {
     pub created_at: NaiveDateTime,
     #[serde(skip_deserializing)]
     pub updated_at: NaiveDateTime,
+    pub workspace_id: i32,
+    /// SHA-256 of an attachment's plaintext, for deduplication and audits
+    pub sha256: Option<String>,
+    /// When the content was last edited, or None if it never was
+    #[serde(skip_deserializing)]
+    pub edited_at: Option<NaiveDateTime>,
+    /// Position in the workspace's messages, counting from 1 without gaps
+    #[serde(skip_deserializing)]
+    pub seq: i64,
+    /// Size of an attachment's content in bytes, counted against the sender's quota
+    #[serde(skip_deserializing)]
+    pub attachment_size: Option<i64>,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -32,14 +45,93 @@ pub struct NewMessage {
     pub message_type: MessageType,
     pub content: Option<String>,
     pub file_name: Option<String>,
+    pub workspace_id: i32,
+    pub sha256: Option<String>,
+    /// Only set by the chat server, which knows the size of what it stores
+    #[serde(skip_deserializing)]
+    pub attachment_size: Option<i64>,
+}
+
+/// Filters applied when listing or exporting messages; unset fields match all.
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+    pub sender_id: Option<i32>,
+    pub message_type: Option<MessageType>,
+    pub workspace_id: Option<i32>,
+    /// Only messages with a higher sequence number
+    pub after_seq: Option<i64>,
+    /// Only messages up to and including this sequence number
+    pub until_seq: Option<i64>,
+    /// Only messages sent at or after this time
+    pub since: Option<NaiveDateTime>,
+    /// Only messages sent before this time
+    pub until: Option<NaiveDateTime>,
+}
+
+/// A message in a user's history.
+///
+/// Content is stored as the clients encrypted it, so the server never decrypts
+/// it; it hands the ciphertext only to requesters who may read it. For anyone
+/// else the content, file name and hash are withheld and `redacted` is set.
+#[derive(Serialize, Debug)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub message: Message,
+    pub redacted: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(mut message: Message, readable: bool) -> Self {
+        if !readable {
+            message.content = None;
+            message.file_name = None;
+            message.sha256 = None;
+        }
+        Self {
+            message,
+            redacted: !readable,
+        }
+    }
+}
+
+/// Number of messages a user sent on one day.
+#[derive(QueryableByName, Serialize, Debug, PartialEq)]
+pub struct DailyMessageCount {
+    #[diesel(sql_type = diesel::sql_types::Date)]
+    pub day: NaiveDate,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub count: i64,
 }
 
-#[derive(AsExpression, Debug, FromSqlRow, Serialize, Deserialize)]
+/// First line of a CSV export of messages
+pub const CSV_HEADER: &str =
+    "id,sender_id,workspace_id,message_type,content,file_name,sha256,created_at\n";
+
+impl Message {
+    /// Renders the message as a line of CSV matching [`CSV_HEADER`].
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}\n",
+            self.id,
+            self.sender_id,
+            self.workspace_id,
+            self.message_type,
+            csv::escape(self.content.as_deref().unwrap_or_default()),
+            csv::escape(self.file_name.as_deref().unwrap_or_default()),
+            self.sha256.as_deref().unwrap_or_default(),
+            self.created_at.format("%Y-%m-%dT%H:%M:%S"),
+        )
+    }
+}
+
+#[derive(AsExpression, Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, Serialize, Deserialize)]
 #[diesel(sql_type = Text)]
 pub enum MessageType {
     Text,
     File,
     Image,
+    /// A `* alice waves` action, stored with the action's text as content
+    Action,
 }
 
 impl Display for MessageType {
@@ -48,6 +140,7 @@ impl Display for MessageType {
             MessageType::Text => write!(f, "text"),
             MessageType::File => write!(f, "file"),
             MessageType::Image => write!(f, "image"),
+            MessageType::Action => write!(f, "action"),
         }
     }
 }
@@ -60,6 +153,7 @@ impl FromStr for MessageType {
             "text" => Ok(MessageType::Text),
             "file" => Ok(MessageType::File),
             "image" => Ok(MessageType::Image),
+            "action" => Ok(MessageType::Action),
             _ => Err(()),
         }
     }
@@ -71,6 +165,7 @@ impl FromSql<Text, Pg> for MessageType {
             b"text" => Ok(MessageType::Text),
             b"file" => Ok(MessageType::File),
             b"image" => Ok(MessageType::Image),
+            b"action" => Ok(MessageType::Action),
             _ => Err("Unrecognized message type".into()),
         }
     }
@@ -85,7 +180,71 @@ impl ToSql<Text, Pg> for MessageType {
             MessageType::Text => out.write_all(b"text")?,
             MessageType::File => out.write_all(b"file")?,
             MessageType::Image => out.write_all(b"image")?,
+            MessageType::Action => out.write_all(b"action")?,
         }
         Ok(diesel::serialize::IsNull::No)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_csv_row_escapes_fields() {
+        let message = Message {
+            id: 7,
+            sender_id: 2,
+            message_type: MessageType::Text,
+            content: Some("Hello, \"world\"\nbye".to_string()),
+            file_name: None,
+            created_at: NaiveDate::from_ymd_opt(2025, 3, 1)
+                .unwrap()
+                .and_hms_opt(12, 30, 0)
+                .unwrap(),
+            updated_at: NaiveDateTime::default(),
+            workspace_id: 1,
+            sha256: None,
+            edited_at: None,
+            seq: 3,
+            attachment_size: None,
+        };
+
+        assert_eq!(
+            message.to_csv_row(),
+            "7,2,1,text,\"Hello, \"\"world\"\"\nbye\",,,2025-03-01T12:30:00\n"
+        );
+    }
+
+    #[test]
+    fn test_history_entry_withholds_unreadable_content() {
+        let message = || Message {
+            id: 8,
+            sender_id: 2,
+            message_type: MessageType::File,
+            content: Some("{\"ciphertext\":\"abc\"}".to_string()),
+            file_name: Some("report.pdf".to_string()),
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+            workspace_id: 1,
+            sha256: Some("00ff".to_string()),
+            edited_at: None,
+            seq: 4,
+            attachment_size: Some(1024),
+        };
+
+        let readable = serde_json::to_value(HistoryEntry::new(message(), true)).unwrap();
+        assert_eq!(readable["content"], "{\"ciphertext\":\"abc\"}");
+        assert_eq!(readable["file_name"], "report.pdf");
+        assert_eq!(readable["redacted"], false);
+
+        let redacted = serde_json::to_value(HistoryEntry::new(message(), false)).unwrap();
+        assert!(redacted["content"].is_null());
+        assert!(redacted["file_name"].is_null());
+        assert!(redacted["sha256"].is_null());
+        assert_eq!(redacted["seq"], 4);
+        assert_eq!(redacted["attachment_size"], 1024);
+        assert_eq!(redacted["redacted"], true);
+    }
+}
@@ -0,0 +1,28 @@
+use crate::schema::message_revisions;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// A version of a message before it was edited.
+#[derive(Queryable, Identifiable, Selectable, Serialize, Debug)]
+#[diesel(table_name = message_revisions)]
+pub struct MessageRevision {
+    pub id: i32,
+    pub message_id: i32,
+    pub content: Option<String>,
+    pub file_name: Option<String>,
+    /// The user whose edit replaced this version, if they still exist
+    pub edited_by: Option<i32>,
+    /// When this version was replaced
+    pub edited_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = message_revisions)]
+pub struct NewMessageRevision {
+    pub message_id: i32,
+    pub content: Option<String>,
+    pub file_name: Option<String>,
+    pub edited_by: Option<i32>,
+    pub edited_at: NaiveDateTime,
+}
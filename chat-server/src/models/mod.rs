@@ -1,2 +1,17 @@
+pub mod bot;
+pub mod client_certificate;
+pub mod do_not_disturb;
+pub mod email_token;
+pub mod feature_flag;
+pub mod invitation;
 pub mod message;
+pub mod message_revision;
+pub mod notification;
+pub mod onboarding;
+pub mod poll;
+pub mod preference;
+pub mod report;
 pub mod user;
+pub mod user_status;
+pub mod workspace;
+pub mod workspace_key;
@@ -0,0 +1,86 @@
+use crate::schema::notifications;
+use chrono::NaiveDateTime;
+use diesel::deserialize::FromSqlRow;
+use diesel::expression::AsExpression;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::ToSql;
+use diesel::sql_types::Text;
+use diesel::{deserialize::FromSql, pg::PgValue};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::io::Write;
+
+#[derive(Queryable, Identifiable, Selectable, Serialize, Debug)]
+#[diesel(table_name = notifications)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    /// The user who caused the notification, if any
+    pub actor_id: Option<i32>,
+    /// The message the notification refers to, if any
+    pub message_id: Option<i32>,
+    pub content: String,
+    pub read_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = notifications)]
+pub struct NewNotification {
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub actor_id: Option<i32>,
+    pub message_id: Option<i32>,
+    pub content: String,
+}
+
+#[derive(AsExpression, Debug, Clone, Copy, PartialEq, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Mention,
+    DirectMessage,
+    Reaction,
+    System,
+}
+
+impl NotificationKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Mention => "mention",
+            NotificationKind::DirectMessage => "direct_message",
+            NotificationKind::Reaction => "reaction",
+            NotificationKind::System => "system",
+        }
+    }
+}
+
+impl Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromSql<Text, Pg> for NotificationKind {
+    fn from_sql(value: PgValue) -> diesel::deserialize::Result<Self> {
+        match value.as_bytes() {
+            b"mention" => Ok(NotificationKind::Mention),
+            b"direct_message" => Ok(NotificationKind::DirectMessage),
+            b"reaction" => Ok(NotificationKind::Reaction),
+            b"system" => Ok(NotificationKind::System),
+            _ => Err("Unrecognized notification kind".into()),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for NotificationKind {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
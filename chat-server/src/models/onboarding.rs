@@ -0,0 +1,42 @@
+use crate::schema::user_onboarding;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// How far a user got through joining the server.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = user_onboarding)]
+pub struct UserOnboarding {
+    pub user_id: i32,
+    /// When the user was welcomed on their first login
+    pub welcomed_at: NaiveDateTime,
+    /// SHA-256 of the terms the user accepted, None if they never did
+    pub terms_sha256: Option<String>,
+    pub terms_accepted_at: Option<NaiveDateTime>,
+}
+
+impl UserOnboarding {
+    /// Returns `true` if the user accepted the terms with this SHA-256.
+    pub fn accepted(&self, terms_sha256: &str) -> bool {
+        self.terms_sha256.as_deref() == Some(terms_sha256)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepted_needs_current_terms() {
+        let mut onboarding = UserOnboarding {
+            user_id: 1,
+            welcomed_at: NaiveDateTime::default(),
+            terms_sha256: None,
+            terms_accepted_at: None,
+        };
+        assert!(!onboarding.accepted("abc"));
+        onboarding.terms_sha256 = Some("abc".to_string());
+        assert!(onboarding.accepted("abc"));
+        assert!(!onboarding.accepted("def"));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::schema::{poll_votes, polls};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+/// A poll created in a workspace.
+#[derive(Queryable, Identifiable, Selectable, Debug)]
+#[diesel(table_name = polls)]
+pub struct Poll {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub creator_id: i32,
+    pub question: String,
+    pub options: Vec<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = polls)]
+pub struct NewPoll {
+    pub workspace_id: i32,
+    pub creator_id: i32,
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = poll_votes)]
+pub struct NewPollVote {
+    pub poll_id: i32,
+    pub user_id: i32,
+    /// Zero-based index into the poll's options
+    pub option_index: i32,
+}
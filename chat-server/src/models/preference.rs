@@ -0,0 +1,18 @@
+use crate::schema::user_preferences;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = user_preferences)]
+pub struct UserPreferences {
+    pub user_id: i32,
+    /// Locale of the texts the server sends the user, e.g. `cs`
+    pub locale: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePreferencesRequest {
+    pub locale: String,
+}
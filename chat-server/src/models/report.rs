@@ -0,0 +1,155 @@
+use crate::models::message::Message;
+use crate::schema::reports;
+use chrono::NaiveDateTime;
+use diesel::deserialize::FromSqlRow;
+use diesel::expression::AsExpression;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::ToSql;
+use diesel::sql_types::Text;
+use diesel::{deserialize::FromSql, pg::PgValue};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Queryable, Identifiable, Selectable, Serialize, Debug)]
+#[diesel(table_name = reports)]
+pub struct Report {
+    pub id: i32,
+    pub workspace_id: i32,
+    /// The reported message, None once it was deleted
+    pub message_id: Option<i32>,
+    /// The sender of the reported message
+    pub reported_user_id: Option<i32>,
+    pub reporter_id: Option<i32>,
+    pub reason: String,
+    pub status: ReportStatus,
+    /// The moderator who resolved the report
+    pub resolved_by: Option<i32>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = reports)]
+pub struct NewReport {
+    pub workspace_id: i32,
+    pub message_id: Option<i32>,
+    pub reported_user_id: Option<i32>,
+    pub reporter_id: Option<i32>,
+    pub reason: String,
+}
+
+/// A report together with the reported message, for the moderation queue.
+#[derive(Serialize, Debug)]
+pub struct ReportWithMessage {
+    #[serde(flatten)]
+    pub report: Report,
+    pub message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+pub struct NewReportRequest {
+    pub message_id: i32,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveReportRequest {
+    pub status: ReportStatus,
+}
+
+/// Where a report stands; every status but `Open` is a resolution.
+#[derive(AsExpression, Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    /// Waiting for a moderator
+    Open,
+    /// No action was needed
+    Dismissed,
+    /// The reported message was deleted
+    Deleted,
+    /// The sender of the reported message was warned
+    Warned,
+    /// The sender of the reported message was removed from the workspace
+    Banned,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Dismissed => "dismissed",
+            ReportStatus::Deleted => "deleted",
+            ReportStatus::Warned => "warned",
+            ReportStatus::Banned => "banned",
+        }
+    }
+}
+
+impl Display for ReportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ReportStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "open" => Ok(ReportStatus::Open),
+            "dismissed" => Ok(ReportStatus::Dismissed),
+            "deleted" => Ok(ReportStatus::Deleted),
+            "warned" => Ok(ReportStatus::Warned),
+            "banned" => Ok(ReportStatus::Banned),
+            _ => Err(format!(
+                "Unknown report status '{}', expected open, dismissed, deleted, warned or banned",
+                value
+            )),
+        }
+    }
+}
+
+impl FromSql<Text, Pg> for ReportStatus {
+    fn from_sql(value: PgValue) -> diesel::deserialize::Result<Self> {
+        std::str::from_utf8(value.as_bytes())?
+            .parse()
+            .map_err(Into::into)
+    }
+}
+
+impl ToSql<Text, Pg> for ReportStatus {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_round_trip() {
+        for status in [
+            ReportStatus::Open,
+            ReportStatus::Dismissed,
+            ReportStatus::Deleted,
+            ReportStatus::Warned,
+            ReportStatus::Banned,
+        ] {
+            assert_eq!(status.as_str().parse::<ReportStatus>(), Ok(status));
+            assert_eq!(
+                serde_json::to_value(status).unwrap(),
+                serde_json::json!(status.as_str())
+            );
+        }
+        assert!("closed".parse::<ReportStatus>().is_err());
+    }
+}
@@ -1,9 +1,35 @@
 use crate::schema::users;
+use crate::utils::csv;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Queryable, Identifiable, AsChangeset, Serialize, Deserialize, Selectable, Debug)]
+/// Username of the sentinel account that keeps messages of deleted users
+pub const DELETED_USER_USERNAME: &str = "[deleted]";
+/// Shortest and longest accepted username, in characters
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 32;
+/// Usernames that could be mistaken for the server, its staff or a group
+/// mention, compared case-insensitively
+pub const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "anonymous",
+    "channel",
+    "deleted",
+    "everyone",
+    "here",
+    "moderator",
+    "root",
+    "server",
+    "support",
+    "system",
+];
+
+#[derive(
+    Queryable, Identifiable, AsChangeset, Serialize, Deserialize, Selectable, Debug, Clone,
+)]
 #[diesel(table_name = users)]
 pub struct User {
     pub id: i32,
@@ -16,11 +42,94 @@ pub struct User {
     pub updated_at: NaiveDateTime,
 }
 
+/// First line of a CSV export of users
+pub const USER_CSV_HEADER: &str = "id,username,email,created_at\n";
+
+impl User {
+    /// Renders the user as a line of CSV matching [`USER_CSV_HEADER`]; the
+    /// password hash is left out.
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{}\n",
+            self.id,
+            csv::escape(&self.username),
+            csv::escape(&self.email),
+            self.created_at.format("%Y-%m-%dT%H:%M:%S"),
+        )
+    }
+}
+
 #[derive(Deserialize)]
 pub struct NewUserRequest {
     pub username: String,
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// A password checked against the password policy before it is submitted.
+#[derive(Deserialize)]
+pub struct CheckPasswordRequest {
+    pub password: String,
+    /// Name and email of the user, which make a password easier to guess
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub email: String,
+}
+
+/// Why a username is not accepted.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum UsernameError {
+    #[error("Usernames must be {USERNAME_MIN_LENGTH} to {USERNAME_MAX_LENGTH} characters long")]
+    Length,
+    #[error("Usernames may only contain letters, digits, '_', '-' and '.', and must start with a letter or digit")]
+    InvalidCharacter,
+    #[error("The username '{0}' is reserved")]
+    Reserved(String),
+}
+
+impl UsernameError {
+    /// Machine-readable name of the broken rule.
+    pub fn rule(&self) -> &'static str {
+        match self {
+            UsernameError::Length => "length",
+            UsernameError::InvalidCharacter => "charset",
+            UsernameError::Reserved(_) => "reserved",
+        }
+    }
+}
+
+/// Checks a new username and returns it without surrounding whitespace.
+///
+/// Usernames keep the case they were registered with, but are unique
+/// regardless of case and looked up case-insensitively.
+pub fn normalize_username(username: &str) -> Result<String, UsernameError> {
+    let username = username.trim();
+
+    let length = username.chars().count();
+    if !(USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&length) {
+        return Err(UsernameError::Length);
+    }
+    let valid_start = username.starts_with(|c: char| c.is_ascii_alphanumeric());
+    let valid_chars = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid_start || !valid_chars {
+        return Err(UsernameError::InvalidCharacter);
+    }
+    if RESERVED_USERNAMES.contains(&username.to_ascii_lowercase().as_str()) {
+        return Err(UsernameError::Reserved(username.to_string()));
+    }
+
+    Ok(username.to_string())
 }
 
 #[derive(Insertable)]
@@ -40,3 +149,54 @@ impl From<NewUserRequest> for NewUser {
         }
     }
 }
+
+/// What was moved over when a duplicate account was merged into a primary one.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct MergedAccount {
+    /// Messages now sent by the primary account
+    pub messages: usize,
+    /// Workspaces the primary account joined or got a stronger role in
+    pub workspaces: usize,
+    /// Bots now owned by the primary account
+    pub bots: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_username() {
+        assert_eq!(normalize_username("  Alice.B "), Ok("Alice.B".to_string()));
+        assert_eq!(
+            normalize_username("deploy-bot_2"),
+            Ok("deploy-bot_2".to_string())
+        );
+
+        assert_eq!(normalize_username("al"), Err(UsernameError::Length));
+        assert_eq!(
+            normalize_username(&"a".repeat(33)),
+            Err(UsernameError::Length)
+        );
+        assert_eq!(
+            normalize_username("bob smith"),
+            Err(UsernameError::InvalidCharacter)
+        );
+        assert_eq!(
+            normalize_username("_bob"),
+            Err(UsernameError::InvalidCharacter)
+        );
+        assert_eq!(
+            normalize_username("žofie"),
+            Err(UsernameError::InvalidCharacter)
+        );
+        assert_eq!(
+            normalize_username("Admin"),
+            Err(UsernameError::Reserved("Admin".to_string()))
+        );
+        assert_eq!(
+            normalize_username(DELETED_USER_USERNAME),
+            Err(UsernameError::InvalidCharacter)
+        );
+    }
+}
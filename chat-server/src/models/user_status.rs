@@ -0,0 +1,25 @@
+use crate::schema::user_statuses;
+use chat_common::Availability;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Status a user shows to the members of their workspaces. Users without a
+/// row are available.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = user_statuses)]
+pub struct UserStatus {
+    pub user_id: i32,
+    /// `available`, `away` or `busy`
+    pub availability: String,
+    /// Custom text, which may contain emoji
+    pub text: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateStatusRequest {
+    pub availability: Availability,
+    #[serde(default)]
+    pub text: Option<String>,
+}
@@ -0,0 +1,59 @@
+use crate::schema::{workspace_members, workspaces};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Slug of the workspace every user belongs to unless moved elsewhere
+pub const DEFAULT_WORKSPACE_SLUG: &str = "default";
+
+/// Membership role allowed to administer a workspace
+pub const WORKSPACE_ADMIN_ROLE: &str = "admin";
+
+/// Membership role allowed to post in an announcement-only workspace
+pub const WORKSPACE_MODERATOR_ROLE: &str = "moderator";
+
+/// Membership role of everyone else
+pub const WORKSPACE_MEMBER_ROLE: &str = "member";
+
+#[derive(Queryable, Identifiable, Selectable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = workspaces)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+    #[serde(skip_deserializing)]
+    pub created_at: NaiveDateTime,
+    #[serde(skip_deserializing)]
+    pub updated_at: NaiveDateTime,
+    /// Only admins and moderators may post; everyone else can just read
+    #[serde(default)]
+    pub announcement_only: bool,
+    /// Sequence number of the latest message, 0 before the first one
+    #[serde(skip_deserializing)]
+    pub last_message_seq: i64,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = workspaces)]
+pub struct NewWorkspace {
+    pub name: String,
+    pub slug: String,
+    #[serde(default)]
+    pub announcement_only: bool,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = workspace_members)]
+pub struct WorkspaceMember {
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = workspace_members)]
+pub struct NewWorkspaceMember {
+    pub workspace_id: i32,
+    pub user_id: i32,
+}
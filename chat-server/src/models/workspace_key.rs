@@ -0,0 +1,25 @@
+use crate::schema::workspace_keys;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+/// Encryption key of a workspace, wrapped with the server's key.
+#[derive(Queryable, Identifiable, Selectable, Debug)]
+#[diesel(table_name = workspace_keys)]
+pub struct WorkspaceKey {
+    pub id: i32,
+    pub workspace_id: i32,
+    /// ID recorded in content encrypted with the key
+    pub key_id: String,
+    pub wrapped_key: String,
+    pub nonce: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workspace_keys)]
+pub struct NewWorkspaceKey {
+    pub workspace_id: i32,
+    pub key_id: String,
+    pub wrapped_key: String,
+    pub nonce: String,
+}
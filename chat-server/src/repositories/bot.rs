@@ -0,0 +1,148 @@
+use crate::models::bot::{Bot, BotProfile, NewBot, BOT_PASSWORD_HASH};
+use crate::models::user::{normalize_username, NewUser, User};
+use crate::models::workspace::NewWorkspaceMember;
+use crate::repositories::user::UserWriteError;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::schema::{bots, users};
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+pub struct BotRepository;
+
+impl BotRepository {
+    pub async fn find_by_owner(
+        conn: &mut AsyncPgConnection,
+        owner_id: i32,
+    ) -> QueryResult<Vec<BotProfile>> {
+        let rows: Vec<(Bot, String)> = bots::table
+            .inner_join(users::table.on(users::id.eq(bots::user_id)))
+            .filter(bots::owner_id.eq(owner_id))
+            .order(bots::created_at.desc())
+            .select((Bot::as_select(), users::username))
+            .load(conn)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(bot, username)| BotProfile { bot, username })
+            .collect())
+    }
+
+    pub async fn find_by_id(conn: &mut AsyncPgConnection, user_id: i32) -> QueryResult<Bot> {
+        bots::table
+            .filter(bots::user_id.eq(user_id))
+            .first(conn)
+            .await
+    }
+
+    pub async fn find_by_token_hash(
+        conn: &mut AsyncPgConnection,
+        token_hash: &str,
+    ) -> QueryResult<Bot> {
+        bots::table
+            .filter(bots::token_hash.eq(token_hash))
+            .first(conn)
+            .await
+    }
+
+    /// Returns the bots of a workspace with one of the given usernames that
+    /// have a webhook URL.
+    pub async fn find_webhooks(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        usernames: &[String],
+    ) -> QueryResult<Vec<BotProfile>> {
+        let rows: Vec<(Bot, String)> = bots::table
+            .inner_join(users::table.on(users::id.eq(bots::user_id)))
+            .filter(bots::workspace_id.eq(workspace_id))
+            .filter(users::username.eq_any(usernames))
+            .filter(bots::webhook_url.is_not_null())
+            .select((Bot::as_select(), users::username))
+            .load(conn)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(bot, username)| BotProfile { bot, username })
+            .collect())
+    }
+
+    /// Creates a bot's account, makes it a member of its workspace and stores
+    /// the bot in one transaction.
+    ///
+    /// Bot accounts get a placeholder email address and a password hash that
+    /// never verifies, so they can only act through their API token. Their
+    /// usernames follow the same rules as those of users.
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        username: String,
+        new_bot: NewBot,
+    ) -> Result<BotProfile, UserWriteError> {
+        let username = normalize_username(&username)?;
+        conn.transaction(|conn| {
+            async move {
+                let user: User = diesel::insert_into(users::table)
+                    .values(NewUser {
+                        email: format!("{}@bots.invalid", username),
+                        username,
+                        password_hash: BOT_PASSWORD_HASH.to_string(),
+                    })
+                    .get_result(conn)
+                    .await?;
+
+                WorkspaceRepository::add_member(
+                    conn,
+                    NewWorkspaceMember {
+                        workspace_id: new_bot.workspace_id,
+                        user_id: user.id,
+                    },
+                )
+                .await?;
+
+                let bot = diesel::insert_into(bots::table)
+                    .values((bots::user_id.eq(user.id), new_bot))
+                    .get_result(conn)
+                    .await?;
+                Ok(BotProfile {
+                    bot,
+                    username: user.username,
+                })
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn set_webhook_url(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        webhook_url: Option<String>,
+    ) -> QueryResult<Bot> {
+        diesel::update(bots::table.filter(bots::user_id.eq(user_id)))
+            .set(bots::webhook_url.eq(webhook_url))
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn set_token_hash(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        token_hash: String,
+    ) -> QueryResult<Bot> {
+        diesel::update(bots::table.filter(bots::user_id.eq(user_id)))
+            .set(bots::token_hash.eq(token_hash))
+            .get_result(conn)
+            .await
+    }
+
+    /// Returns the IDs of the bots a user owns.
+    pub async fn find_ids_by_owner(
+        conn: &mut AsyncPgConnection,
+        owner_id: i32,
+    ) -> QueryResult<Vec<i32>> {
+        bots::table
+            .filter(bots::owner_id.eq(owner_id))
+            .select(bots::user_id)
+            .load(conn)
+            .await
+    }
+}
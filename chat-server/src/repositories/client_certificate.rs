@@ -0,0 +1,59 @@
+use crate::models::client_certificate::{ClientCertificate, NewClientCertificate};
+use crate::models::user::User;
+use crate::schema::{client_certificates, users};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct ClientCertificateRepository;
+
+impl ClientCertificateRepository {
+    /// Returns the user a certificate fingerprint is registered for.
+    pub async fn find_user(
+        conn: &mut AsyncPgConnection,
+        fingerprint: &str,
+    ) -> QueryResult<Option<User>> {
+        client_certificates::table
+            .inner_join(users::table)
+            .filter(client_certificates::fingerprint.eq(fingerprint))
+            .select(User::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn find_by_user(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Vec<ClientCertificate>> {
+        client_certificates::table
+            .filter(client_certificates::user_id.eq(user_id))
+            .order(client_certificates::created_at.asc())
+            .load(conn)
+            .await
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        new_certificate: NewClientCertificate,
+    ) -> QueryResult<ClientCertificate> {
+        diesel::insert_into(client_certificates::table)
+            .values(new_certificate)
+            .get_result(conn)
+            .await
+    }
+
+    /// Removes a certificate of a user, returning the number of deleted rows.
+    pub async fn delete(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        fingerprint: &str,
+    ) -> QueryResult<usize> {
+        diesel::delete(
+            client_certificates::table
+                .filter(client_certificates::user_id.eq(user_id))
+                .filter(client_certificates::fingerprint.eq(fingerprint)),
+        )
+        .execute(conn)
+        .await
+    }
+}
@@ -0,0 +1,47 @@
+use crate::models::do_not_disturb::DoNotDisturb;
+use crate::schema::do_not_disturb;
+use chrono::NaiveTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct DoNotDisturbRepository;
+
+impl DoNotDisturbRepository {
+    pub async fn find(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<DoNotDisturb>> {
+        do_not_disturb::table
+            .filter(do_not_disturb::user_id.eq(user_id))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn set(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        enabled: bool,
+        schedule: Option<(NaiveTime, NaiveTime)>,
+    ) -> QueryResult<DoNotDisturb> {
+        let (starts_at, ends_at) = schedule.unzip();
+        diesel::insert_into(do_not_disturb::table)
+            .values((
+                do_not_disturb::user_id.eq(user_id),
+                do_not_disturb::enabled.eq(enabled),
+                do_not_disturb::starts_at.eq(starts_at),
+                do_not_disturb::ends_at.eq(ends_at),
+            ))
+            .on_conflict(do_not_disturb::user_id)
+            .do_update()
+            .set((
+                do_not_disturb::enabled.eq(enabled),
+                do_not_disturb::starts_at.eq(starts_at),
+                do_not_disturb::ends_at.eq(ends_at),
+                do_not_disturb::updated_at.eq(now),
+            ))
+            .get_result(conn)
+            .await
+    }
+}
@@ -0,0 +1,87 @@
+use crate::models::email_token::{EmailToken, NewEmailToken};
+use crate::schema::{email_tokens, email_verifications};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct EmailTokenRepository;
+
+impl EmailTokenRepository {
+    /// Stores a token, replacing the user's earlier tokens for the same
+    /// purpose so only the most recently emailed link works.
+    pub async fn replace(
+        conn: &mut AsyncPgConnection,
+        new_token: NewEmailToken,
+    ) -> QueryResult<EmailToken> {
+        diesel::delete(
+            email_tokens::table
+                .filter(email_tokens::user_id.eq(new_token.user_id))
+                .filter(email_tokens::purpose.eq(&new_token.purpose)),
+        )
+        .execute(conn)
+        .await?;
+
+        diesel::insert_into(email_tokens::table)
+            .values(new_token)
+            .get_result(conn)
+            .await
+    }
+
+    /// Uses up an unexpired token.
+    ///
+    /// # Returns
+    /// * `QueryResult<Option<EmailToken>>` - The token, or None if it is unknown,
+    ///   expired or was used already
+    pub async fn take(
+        conn: &mut AsyncPgConnection,
+        token_sha256: &str,
+        purpose: &str,
+    ) -> QueryResult<Option<EmailToken>> {
+        diesel::delete(
+            email_tokens::table
+                .filter(email_tokens::token_sha256.eq(token_sha256))
+                .filter(email_tokens::purpose.eq(purpose))
+                .filter(email_tokens::expires_at.gt(now)),
+        )
+        .get_result(conn)
+        .await
+        .optional()
+    }
+
+    /// Records that a user confirmed an email address.
+    pub async fn mark_verified(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        email: &str,
+    ) -> QueryResult<usize> {
+        diesel::insert_into(email_verifications::table)
+            .values((
+                email_verifications::user_id.eq(user_id),
+                email_verifications::email.eq(email),
+            ))
+            .on_conflict(email_verifications::user_id)
+            .do_update()
+            .set((
+                email_verifications::email.eq(excluded(email_verifications::email)),
+                email_verifications::verified_at.eq(now),
+            ))
+            .execute(conn)
+            .await
+    }
+
+    /// Returns `true` if the user confirmed this email address.
+    pub async fn is_verified(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        email: &str,
+    ) -> QueryResult<bool> {
+        diesel::select(diesel::dsl::exists(
+            email_verifications::table
+                .filter(email_verifications::user_id.eq(user_id))
+                .filter(email_verifications::email.eq(email)),
+        ))
+        .get_result(conn)
+        .await
+    }
+}
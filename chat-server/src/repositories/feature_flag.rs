@@ -0,0 +1,36 @@
+use crate::models::feature_flag::{FeatureFlag, NewFeatureFlag};
+use crate::schema::feature_flags;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct FeatureFlagRepository;
+
+impl FeatureFlagRepository {
+    pub async fn find_all(conn: &mut AsyncPgConnection) -> QueryResult<Vec<FeatureFlag>> {
+        feature_flags::table
+            .order(feature_flags::name.asc())
+            .load(conn)
+            .await
+    }
+
+    /// Creates a flag or replaces the settings of an existing one.
+    pub async fn upsert(
+        conn: &mut AsyncPgConnection,
+        flag: NewFeatureFlag,
+    ) -> QueryResult<FeatureFlag> {
+        diesel::insert_into(feature_flags::table)
+            .values(&flag)
+            .on_conflict(feature_flags::name)
+            .do_update()
+            .set((&flag, feature_flags::updated_at.eq(now)))
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn delete(conn: &mut AsyncPgConnection, name: &str) -> QueryResult<usize> {
+        diesel::delete(feature_flags::table.filter(feature_flags::name.eq(name)))
+            .execute(conn)
+            .await
+    }
+}
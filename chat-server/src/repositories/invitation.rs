@@ -0,0 +1,158 @@
+use crate::models::invitation::{Invitation, NewInvitation, NewInvitationRedemption};
+use crate::models::workspace::NewWorkspaceMember;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::schema::{invitation_redemptions, invitations};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+pub struct InvitationRepository;
+
+impl InvitationRepository {
+    pub async fn find_by_creator(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Vec<Invitation>> {
+        invitations::table
+            .filter(invitations::created_by.eq(user_id))
+            .order(invitations::created_at.desc())
+            .load(conn)
+            .await
+    }
+
+    pub async fn find_by_id(
+        conn: &mut AsyncPgConnection,
+        invitation_id: i32,
+    ) -> QueryResult<Invitation> {
+        invitations::table
+            .filter(invitations::id.eq(invitation_id))
+            .first(conn)
+            .await
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        new_invitation: NewInvitation,
+    ) -> QueryResult<Invitation> {
+        diesel::insert_into(invitations::table)
+            .values(new_invitation)
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn delete(conn: &mut AsyncPgConnection, invitation_id: i32) -> QueryResult<usize> {
+        diesel::delete(invitations::table.filter(invitations::id.eq(invitation_id)))
+            .execute(conn)
+            .await
+    }
+
+    /// Consumes one use of an invite code on behalf of a user.
+    ///
+    /// The use counter is only incremented while the code is unexpired and has
+    /// uses left, so concurrent redemptions cannot exceed `max_uses`. Workspace
+    /// invites also make the user a member of the workspace.
+    ///
+    /// # Returns
+    /// * `QueryResult<Option<Invitation>>` - The redeemed invitation, or None if the
+    ///   code is unknown, expired, used up, or was already redeemed by this user
+    pub async fn redeem(
+        conn: &mut AsyncPgConnection,
+        code: &str,
+        user_id: i32,
+    ) -> QueryResult<Option<Invitation>> {
+        let code = code.to_string();
+
+        conn.transaction(|conn| {
+            async move {
+                let already_redeemed: bool = diesel::select(diesel::dsl::exists(
+                    invitation_redemptions::table
+                        .inner_join(invitations::table)
+                        .filter(invitations::code.eq(&code))
+                        .filter(invitation_redemptions::user_id.eq(user_id)),
+                ))
+                .get_result(conn)
+                .await?;
+
+                if already_redeemed {
+                    return Ok(None);
+                }
+
+                let invitation: Option<Invitation> = diesel::update(
+                    invitations::table
+                        .filter(invitations::code.eq(&code))
+                        .filter(invitations::uses.lt(invitations::max_uses))
+                        .filter(
+                            invitations::expires_at
+                                .is_null()
+                                .or(invitations::expires_at.gt(now)),
+                        ),
+                )
+                .set(invitations::uses.eq(invitations::uses + 1))
+                .get_result(conn)
+                .await
+                .optional()?;
+
+                let Some(invitation) = invitation else {
+                    return Ok(None);
+                };
+
+                diesel::insert_into(invitation_redemptions::table)
+                    .values(NewInvitationRedemption {
+                        invitation_id: invitation.id,
+                        user_id,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                if let Some(workspace_id) = invitation.workspace_id {
+                    if !WorkspaceRepository::is_member(conn, workspace_id, user_id).await? {
+                        WorkspaceRepository::add_member(
+                            conn,
+                            NewWorkspaceMember {
+                                workspace_id,
+                                user_id,
+                            },
+                        )
+                        .await?;
+                    }
+                }
+
+                Ok(Some(invitation))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Marks the user's pending redemptions as used for a login.
+    ///
+    /// # Returns
+    /// * `QueryResult<Option<i32>>` - The workspace of the most recently redeemed
+    ///   workspace invite the user has not logged in with yet, if any
+    pub async fn take_first_login_workspace(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<i32>> {
+        let pending = invitation_redemptions::table
+            .filter(invitation_redemptions::user_id.eq(user_id))
+            .filter(invitation_redemptions::first_login_at.is_null());
+
+        let workspace_id = pending
+            .inner_join(invitations::table)
+            .filter(invitations::workspace_id.is_not_null())
+            .order(invitation_redemptions::redeemed_at.desc())
+            .select(invitations::workspace_id)
+            .first::<Option<i32>>(conn)
+            .await
+            .optional()?
+            .flatten();
+
+        diesel::update(pending)
+            .set(invitation_redemptions::first_login_at.eq(now))
+            .execute(conn)
+            .await?;
+
+        Ok(workspace_id)
+    }
+}
@@ -1,8 +1,15 @@
-use crate::models::message::{Message, NewMessage};
+use crate::models::message::{DailyMessageCount, Message, MessageFilter, MessageType, NewMessage};
+use crate::models::message_revision::NewMessageRevision;
+use crate::repositories::message_revision::MessageRevisionRepository;
 use crate::schema::messages::*;
 use crate::schema::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::count_star;
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel::sql_types::{BigInt, Integer, Timestamp};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 
 pub struct MessageRepository;
 
@@ -25,25 +32,272 @@ impl MessageRepository {
             .await
     }
 
+    pub async fn find_by_workspace(
+        conn: &mut AsyncPgConnection,
+        workspace_id_param: i32,
+    ) -> QueryResult<Vec<Message>> {
+        messages::table
+            .filter(workspace_id.eq(workspace_id_param))
+            .load(conn)
+            .await
+    }
+
+    /// Counts the messages matching a filter.
+    pub async fn count_filtered(
+        conn: &mut AsyncPgConnection,
+        filter: &MessageFilter,
+    ) -> QueryResult<i64> {
+        Self::filtered(filter).count().get_result(conn).await
+    }
+
+    /// Loads the messages matching a filter, in sequence order.
+    pub async fn find_filtered(
+        conn: &mut AsyncPgConnection,
+        filter: &MessageFilter,
+    ) -> QueryResult<Vec<Message>> {
+        Self::filtered(filter)
+            .order((workspace_id.asc(), seq.asc()))
+            .load(conn)
+            .await
+    }
+
+    /// Loads up to `limit` messages matching a filter with an ID above
+    /// `after_id`, in ID order, so large result sets can be read in pages.
+    pub async fn find_filtered_after(
+        conn: &mut AsyncPgConnection,
+        filter: &MessageFilter,
+        after_id: i32,
+        limit: i64,
+    ) -> QueryResult<Vec<Message>> {
+        Self::filtered(filter)
+            .filter(id.gt(after_id))
+            .order(id.asc())
+            .limit(limit)
+            .load(conn)
+            .await
+    }
+
+    /// Loads up to `limit` messages matching a filter, newest first, starting
+    /// below `before_id` if given, so a history can be read backwards in pages.
+    pub async fn find_filtered_before(
+        conn: &mut AsyncPgConnection,
+        filter: &MessageFilter,
+        before_id: Option<i32>,
+        limit: i64,
+    ) -> QueryResult<Vec<Message>> {
+        let mut query = Self::filtered(filter);
+        if let Some(before_id) = before_id {
+            query = query.filter(id.lt(before_id));
+        }
+        query.order(id.desc()).limit(limit).load(conn).await
+    }
+
+    /// Loads the latest `limit` messages of a workspace with the usernames of
+    /// their senders, oldest first.
+    pub async fn find_latest_with_senders(
+        conn: &mut AsyncPgConnection,
+        workspace_id_param: i32,
+        limit: i64,
+    ) -> QueryResult<Vec<(Message, String)>> {
+        let mut latest: Vec<(Message, String)> = messages::table
+            .inner_join(users::table)
+            .filter(workspace_id.eq(workspace_id_param))
+            .order(seq.desc())
+            .limit(limit)
+            .select((messages::all_columns, users::username))
+            .load(conn)
+            .await?;
+        latest.reverse();
+        Ok(latest)
+    }
+
+    /// Counts a sender's messages per day since `since`. Days without messages
+    /// are left out.
+    pub async fn count_by_day(
+        conn: &mut AsyncPgConnection,
+        sender_id_param: i32,
+        since: NaiveDateTime,
+    ) -> QueryResult<Vec<DailyMessageCount>> {
+        diesel::sql_query(
+            "SELECT created_at::date AS day, COUNT(*) AS count FROM messages \
+             WHERE sender_id = $1 AND created_at >= $2 GROUP BY day ORDER BY day",
+        )
+        .bind::<Integer, _>(sender_id_param)
+        .bind::<Timestamp, _>(since)
+        .load(conn)
+        .await
+    }
+
+    /// Counts a sender's messages of each type.
+    pub async fn count_by_type(
+        conn: &mut AsyncPgConnection,
+        sender_id_param: i32,
+    ) -> QueryResult<Vec<(MessageType, i64)>> {
+        messages::table
+            .filter(sender_id.eq(sender_id_param))
+            .group_by(message_type)
+            .select((message_type, count_star()))
+            .load(conn)
+            .await
+    }
+
+    /// Returns when a sender last sent a message, or None if they never did.
+    pub async fn last_sent_at(
+        conn: &mut AsyncPgConnection,
+        sender_id_param: i32,
+    ) -> QueryResult<Option<NaiveDateTime>> {
+        messages::table
+            .filter(sender_id.eq(sender_id_param))
+            .select(diesel::dsl::max(created_at))
+            .first(conn)
+            .await
+    }
+
+    /// Sums the size of the attachments a sender has stored, in bytes.
+    pub async fn attachment_bytes(
+        conn: &mut AsyncPgConnection,
+        sender_id_param: i32,
+    ) -> QueryResult<i64> {
+        messages::table
+            .filter(sender_id.eq(sender_id_param))
+            .select(diesel::dsl::sql::<BigInt>(
+                "COALESCE(SUM(attachment_size), 0)::BIGINT",
+            ))
+            .first(conn)
+            .await
+    }
+
+    /// Lists the SHA-256 of every stored attachment, each once.
+    pub async fn attachment_hashes(conn: &mut AsyncPgConnection) -> QueryResult<Vec<String>> {
+        messages::table
+            .filter(sha256.is_not_null())
+            .select(sha256.assume_not_null())
+            .distinct()
+            .order(sha256)
+            .load(conn)
+            .await
+    }
+
+    fn filtered(filter: &MessageFilter) -> messages::BoxedQuery<'static, Pg> {
+        let mut query = messages::table.into_boxed();
+        if let Some(sender) = filter.sender_id {
+            query = query.filter(sender_id.eq(sender));
+        }
+        if let Some(kind) = filter.message_type {
+            query = query.filter(message_type.eq(kind));
+        }
+        if let Some(workspace) = filter.workspace_id {
+            query = query.filter(workspace_id.eq(workspace));
+        }
+        if let Some(after) = filter.after_seq {
+            query = query.filter(seq.gt(after));
+        }
+        if let Some(until) = filter.until_seq {
+            query = query.filter(seq.le(until));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(created_at.ge(since));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(created_at.lt(until));
+        }
+        query
+    }
+
+    /// Stores a message with the next sequence number of its workspace.
+    ///
+    /// The number is reserved by incrementing the workspace's counter in the
+    /// same transaction, so concurrent messages are numbered without gaps.
     pub async fn create(
         conn: &mut AsyncPgConnection,
         new_message: NewMessage,
     ) -> QueryResult<Message> {
-        diesel::insert_into(messages::table)
-            .values(new_message)
-            .get_result(conn)
-            .await
+        conn.transaction(|conn| {
+            async move {
+                let next_seq: i64 = diesel::update(
+                    workspaces::table.filter(workspaces::id.eq(new_message.workspace_id)),
+                )
+                .set(workspaces::last_message_seq.eq(workspaces::last_message_seq + 1))
+                .returning(workspaces::last_message_seq)
+                .get_result(conn)
+                .await?;
+
+                diesel::insert_into(messages::table)
+                    .values((new_message, seq.eq(next_seq)))
+                    .get_result(conn)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
     }
 
+    /// Updates a message. If its content or file name changes, the previous
+    /// version is kept as a revision and the message is marked as edited, all
+    /// in one transaction.
+    ///
+    /// # Arguments
+    /// * `message_id` - The ID of the message to update
+    /// * `message` - The new state of the message
+    /// * `editor_id` - The user making the change
     pub async fn update(
         conn: &mut AsyncPgConnection,
         message_id: i32,
-        message: &Message,
+        mut message: Message,
+        editor_id: i32,
     ) -> QueryResult<Message> {
-        diesel::update(messages::table.filter(id.eq(message_id)))
-            .set(message)
-            .get_result(conn)
-            .await
+        conn.transaction(|conn| {
+            async move {
+                let current: Message = messages::table
+                    .filter(id.eq(message_id))
+                    .for_update()
+                    .first(conn)
+                    .await?;
+                // The position in the workspace never changes
+                message.seq = current.seq;
+
+                if current.content != message.content || current.file_name != message.file_name {
+                    let now = Utc::now().naive_utc();
+                    MessageRevisionRepository::create(
+                        conn,
+                        NewMessageRevision {
+                            message_id,
+                            content: current.content,
+                            file_name: current.file_name,
+                            edited_by: Some(editor_id),
+                            edited_at: now,
+                        },
+                    )
+                    .await?;
+                    message.edited_at = Some(now);
+                }
+
+                diesel::update(messages::table.filter(id.eq(message_id)))
+                    .set(&message)
+                    .get_result(conn)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Replaces the text of a message, keeping the previous text as a revision
+    /// like [`Self::update`].
+    ///
+    /// # Arguments
+    /// * `message_id` - The ID of the message to edit
+    /// * `new_content` - The new text of the message
+    /// * `editor_id` - The user making the change
+    pub async fn update_content(
+        conn: &mut AsyncPgConnection,
+        message_id: i32,
+        new_content: String,
+        editor_id: i32,
+    ) -> QueryResult<Message> {
+        let mut message = Self::find_by_id(conn, message_id).await?;
+        message.content = Some(new_content);
+        Self::update(conn, message_id, message, editor_id).await
     }
 
     pub async fn delete(conn: &mut AsyncPgConnection, message_id: i32) -> QueryResult<usize> {
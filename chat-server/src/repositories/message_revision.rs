@@ -0,0 +1,30 @@
+use crate::models::message_revision::{MessageRevision, NewMessageRevision};
+use crate::schema::message_revisions;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct MessageRevisionRepository;
+
+impl MessageRevisionRepository {
+    /// Returns the earlier versions of a message, oldest first.
+    pub async fn find_by_message(
+        conn: &mut AsyncPgConnection,
+        message_id: i32,
+    ) -> QueryResult<Vec<MessageRevision>> {
+        message_revisions::table
+            .filter(message_revisions::message_id.eq(message_id))
+            .order(message_revisions::id.asc())
+            .load(conn)
+            .await
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        revision: NewMessageRevision,
+    ) -> QueryResult<MessageRevision> {
+        diesel::insert_into(message_revisions::table)
+            .values(revision)
+            .get_result(conn)
+            .await
+    }
+}
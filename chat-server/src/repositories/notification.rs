@@ -0,0 +1,93 @@
+use crate::models::notification::{NewNotification, Notification};
+use crate::schema::notifications;
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct NotificationRepository;
+
+impl NotificationRepository {
+    /// Returns the user's notifications, newest first.
+    pub async fn find_for_user(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        unread_only: bool,
+    ) -> QueryResult<Vec<Notification>> {
+        let mut query = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .order(notifications::created_at.desc())
+            .into_boxed();
+
+        if unread_only {
+            query = query.filter(notifications::read_at.is_null());
+        }
+
+        query.load(conn).await
+    }
+
+    /// Returns unread notifications of all users created after `since`, grouped
+    /// by user and oldest first.
+    pub async fn find_unread_since(
+        conn: &mut AsyncPgConnection,
+        since: NaiveDateTime,
+    ) -> QueryResult<Vec<Notification>> {
+        notifications::table
+            .filter(notifications::read_at.is_null())
+            .filter(notifications::created_at.gt(since))
+            .order((notifications::user_id, notifications::created_at.asc()))
+            .load(conn)
+            .await
+    }
+
+    pub async fn count_unread(conn: &mut AsyncPgConnection, user_id: i32) -> QueryResult<i64> {
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null())
+            .count()
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        new_notification: NewNotification,
+    ) -> QueryResult<Notification> {
+        diesel::insert_into(notifications::table)
+            .values(new_notification)
+            .get_result(conn)
+            .await
+    }
+
+    /// Marks one of the user's notifications as read.
+    ///
+    /// # Returns
+    /// * `QueryResult<usize>` - 1 if the notification was marked, 0 if it does not
+    ///   exist, belongs to another user or was already read
+    pub async fn mark_read(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        notification_id: i32,
+    ) -> QueryResult<usize> {
+        diesel::update(
+            notifications::table
+                .filter(notifications::id.eq(notification_id))
+                .filter(notifications::user_id.eq(user_id))
+                .filter(notifications::read_at.is_null()),
+        )
+        .set(notifications::read_at.eq(now))
+        .execute(conn)
+        .await
+    }
+
+    pub async fn mark_all_read(conn: &mut AsyncPgConnection, user_id: i32) -> QueryResult<usize> {
+        diesel::update(
+            notifications::table
+                .filter(notifications::user_id.eq(user_id))
+                .filter(notifications::read_at.is_null()),
+        )
+        .set(notifications::read_at.eq(now))
+        .execute(conn)
+        .await
+    }
+}
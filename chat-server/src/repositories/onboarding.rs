@@ -0,0 +1,56 @@
+use crate::models::onboarding::UserOnboarding;
+use crate::schema::user_onboarding;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct OnboardingRepository;
+
+impl OnboardingRepository {
+    pub async fn find(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<UserOnboarding>> {
+        user_onboarding::table
+            .find(user_id)
+            .select(UserOnboarding::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Records that a user was welcomed.
+    ///
+    /// # Returns
+    /// * `QueryResult<bool>` - `true` if the user had not been welcomed before
+    pub async fn mark_welcomed(conn: &mut AsyncPgConnection, user_id: i32) -> QueryResult<bool> {
+        let inserted = diesel::insert_into(user_onboarding::table)
+            .values(user_onboarding::user_id.eq(user_id))
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await?;
+        Ok(inserted > 0)
+    }
+
+    /// Records that a user accepted the terms with the given SHA-256.
+    pub async fn accept_terms(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        terms_sha256: &str,
+    ) -> QueryResult<UserOnboarding> {
+        diesel::insert_into(user_onboarding::table)
+            .values((
+                user_onboarding::user_id.eq(user_id),
+                user_onboarding::terms_sha256.eq(terms_sha256),
+                user_onboarding::terms_accepted_at.eq(now),
+            ))
+            .on_conflict(user_onboarding::user_id)
+            .do_update()
+            .set((
+                user_onboarding::terms_sha256.eq(terms_sha256),
+                user_onboarding::terms_accepted_at.eq(now),
+            ))
+            .get_result(conn)
+            .await
+    }
+}
@@ -0,0 +1,54 @@
+use crate::models::poll::{NewPoll, NewPollVote, Poll};
+use crate::schema::{poll_votes, polls};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct PollRepository;
+
+impl PollRepository {
+    pub async fn find_by_id(conn: &mut AsyncPgConnection, id: i32) -> QueryResult<Option<Poll>> {
+        polls::table.find(id).first(conn).await.optional()
+    }
+
+    pub async fn create(conn: &mut AsyncPgConnection, poll: NewPoll) -> QueryResult<Poll> {
+        diesel::insert_into(polls::table)
+            .values(poll)
+            .get_result(conn)
+            .await
+    }
+
+    /// Records a vote unless the user already voted in the poll.
+    ///
+    /// # Returns
+    /// * `QueryResult<usize>` - 1 if the vote was recorded, 0 if the user had voted
+    pub async fn vote(conn: &mut AsyncPgConnection, vote: NewPollVote) -> QueryResult<usize> {
+        diesel::insert_into(poll_votes::table)
+            .values(vote)
+            .on_conflict((poll_votes::poll_id, poll_votes::user_id))
+            .do_nothing()
+            .execute(conn)
+            .await
+    }
+
+    /// Counts the votes of each option.
+    ///
+    /// # Returns
+    /// * `QueryResult<Vec<u32>>` - One count per option, in the poll's order
+    pub async fn tally(conn: &mut AsyncPgConnection, poll: &Poll) -> QueryResult<Vec<u32>> {
+        let counts: Vec<(i32, i64)> = poll_votes::table
+            .filter(poll_votes::poll_id.eq(poll.id))
+            .group_by(poll_votes::option_index)
+            .select((poll_votes::option_index, count_star()))
+            .load(conn)
+            .await?;
+
+        let mut votes = vec![0; poll.options.len()];
+        for (index, count) in counts {
+            if let Some(votes) = votes.get_mut(index as usize) {
+                *votes = count as u32;
+            }
+        }
+        Ok(votes)
+    }
+}
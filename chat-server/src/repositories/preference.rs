@@ -0,0 +1,54 @@
+use crate::models::preference::UserPreferences;
+use crate::schema::user_preferences;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use std::collections::HashMap;
+
+pub struct PreferenceRepository;
+
+impl PreferenceRepository {
+    pub async fn find(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<UserPreferences>> {
+        user_preferences::table
+            .filter(user_preferences::user_id.eq(user_id))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Returns the locales of those of the given users who have chosen one.
+    pub async fn find_locales(
+        conn: &mut AsyncPgConnection,
+        user_ids: &[i32],
+    ) -> QueryResult<HashMap<i32, String>> {
+        let rows: Vec<(i32, String)> = user_preferences::table
+            .filter(user_preferences::user_id.eq_any(user_ids))
+            .select((user_preferences::user_id, user_preferences::locale))
+            .load(conn)
+            .await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    pub async fn set_locale(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        locale: &str,
+    ) -> QueryResult<UserPreferences> {
+        diesel::insert_into(user_preferences::table)
+            .values((
+                user_preferences::user_id.eq(user_id),
+                user_preferences::locale.eq(locale),
+            ))
+            .on_conflict(user_preferences::user_id)
+            .do_update()
+            .set((
+                user_preferences::locale.eq(locale),
+                user_preferences::updated_at.eq(now),
+            ))
+            .get_result(conn)
+            .await
+    }
+}
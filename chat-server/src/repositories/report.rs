@@ -0,0 +1,86 @@
+use crate::models::message::Message;
+use crate::models::report::{NewReport, Report, ReportStatus, ReportWithMessage};
+use crate::schema::{messages, reports};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct ReportRepository;
+
+impl ReportRepository {
+    /// Files a report.
+    ///
+    /// # Returns
+    /// * `QueryResult<Option<Report>>` - The report, or None if the reporter
+    ///   already reported the message
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        new_report: NewReport,
+    ) -> QueryResult<Option<Report>> {
+        diesel::insert_into(reports::table)
+            .values(new_report)
+            .on_conflict_do_nothing()
+            .get_result(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn find_by_id(conn: &mut AsyncPgConnection, report_id: i32) -> QueryResult<Report> {
+        reports::table.find(report_id).first(conn).await
+    }
+
+    /// Lists reports with their messages, newest first.
+    ///
+    /// # Arguments
+    /// * `workspace_ids` - Restricts the list to these workspaces (all if None)
+    /// * `status` - Restricts the list to reports with this status (all if None)
+    pub async fn find_with_messages(
+        conn: &mut AsyncPgConnection,
+        workspace_ids: Option<&[i32]>,
+        status: Option<ReportStatus>,
+    ) -> QueryResult<Vec<ReportWithMessage>> {
+        let mut query = reports::table
+            .left_join(messages::table)
+            .select((reports::all_columns, messages::all_columns.nullable()))
+            .order(reports::created_at.desc())
+            .into_boxed();
+        if let Some(workspace_ids) = workspace_ids {
+            query = query.filter(reports::workspace_id.eq_any(workspace_ids.to_vec()));
+        }
+        if let Some(status) = status {
+            query = query.filter(reports::status.eq(status));
+        }
+
+        let rows: Vec<(Report, Option<Message>)> = query.load(conn).await?;
+        Ok(rows
+            .into_iter()
+            .map(|(report, message)| ReportWithMessage { report, message })
+            .collect())
+    }
+
+    /// Records the resolution of an open report.
+    ///
+    /// # Returns
+    /// * `QueryResult<Option<Report>>` - The resolved report, or None if it was
+    ///   already resolved
+    pub async fn resolve(
+        conn: &mut AsyncPgConnection,
+        report_id: i32,
+        status: ReportStatus,
+        moderator_id: i32,
+    ) -> QueryResult<Option<Report>> {
+        diesel::update(
+            reports::table
+                .filter(reports::id.eq(report_id))
+                .filter(reports::status.eq(ReportStatus::Open)),
+        )
+        .set((
+            reports::status.eq(status),
+            reports::resolved_by.eq(moderator_id),
+            reports::resolved_at.eq(now),
+        ))
+        .get_result(conn)
+        .await
+        .optional()
+    }
+}
@@ -1,49 +1,249 @@
-use crate::models::user::{NewUser, NewUserRequest, User};
+use crate::config::UserDeletionMode;
+use crate::models::user::{
+    normalize_username, MergedAccount, NewUser, NewUserRequest, User, UsernameError,
+    DELETED_USER_USERNAME,
+};
+use crate::models::workspace::{
+    NewWorkspaceMember, WORKSPACE_ADMIN_ROLE, WORKSPACE_MODERATOR_ROLE,
+};
+use crate::repositories::invitation::InvitationRepository;
+use crate::repositories::workspace::WorkspaceRepository;
 use crate::schema::users::dsl::*;
+use crate::schema::{
+    bots, client_certificates, do_not_disturb, invitation_redemptions, invitations,
+    message_revisions, messages, notifications, poll_votes, polls, reports, user_onboarding,
+    user_preferences, user_statuses, workspace_members,
+};
+use chat_common::error::ChatError;
 use diesel::prelude::*;
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::Text;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use thiserror::Error;
+
+/// Unique index on `LOWER(username)`
+const USERNAME_INDEX: &str = "users_username_lower_key";
+const EMAIL_CONSTRAINT: &str = "users_email_key";
+
+sql_function!(fn lower(x: Text) -> Text);
+
+/// Why an account could not be created or renamed.
+#[derive(Debug, Error)]
+pub enum UserWriteError {
+    #[error(transparent)]
+    InvalidUsername(#[from] UsernameError),
+    #[error("The username is already taken")]
+    UsernameTaken,
+    #[error("The email address is already registered")]
+    EmailTaken,
+    #[error(transparent)]
+    Database(DieselError),
+}
+
+impl From<DieselError> for UserWriteError {
+    fn from(e: DieselError) -> Self {
+        match &e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                match info.constraint_name() {
+                    Some(USERNAME_INDEX) => UserWriteError::UsernameTaken,
+                    Some(EMAIL_CONSTRAINT) => UserWriteError::EmailTaken,
+                    _ => UserWriteError::Database(e),
+                }
+            }
+            _ => UserWriteError::Database(e),
+        }
+    }
+}
+
+/// Chat protocol errors report taken usernames and emails as conflicts.
+impl From<UserWriteError> for ChatError {
+    fn from(e: UserWriteError) -> Self {
+        match e {
+            UserWriteError::InvalidUsername(_) => ChatError::InvalidInput(e.to_string()),
+            UserWriteError::UsernameTaken | UserWriteError::EmailTaken => {
+                ChatError::Conflict(e.to_string())
+            }
+            UserWriteError::Database(e) => ChatError::ServerError(e.to_string()),
+        }
+    }
+}
 
 pub struct UserRepository;
 
 impl UserRepository {
+    /// Finds a user by username, ignoring case.
     pub async fn find_by_username(
         conn: &mut AsyncPgConnection,
         user_name: &str,
     ) -> QueryResult<User> {
-        users.filter(username.eq(user_name)).first(conn).await
+        users
+            .filter(lower(username).eq(user_name.to_lowercase()))
+            .first(conn)
+            .await
+    }
+
+    /// Finds a user by email address, ignoring case.
+    pub async fn find_by_email(
+        conn: &mut AsyncPgConnection,
+        address: &str,
+    ) -> QueryResult<Option<User>> {
+        users
+            .filter(lower(email).eq(address.trim().to_lowercase()))
+            .first(conn)
+            .await
+            .optional()
     }
 
     pub async fn find_all(conn: &mut AsyncPgConnection) -> QueryResult<Vec<User>> {
         users.load(conn).await
     }
 
+    /// Finds one page of users ordered by ID, only the members of a workspace
+    /// if one is given, and counts the users on all pages.
+    ///
+    /// # Returns
+    /// * `QueryResult<(Vec<User>, i64)>` - Users of the page and the total number of users
+    pub async fn find_page(
+        conn: &mut AsyncPgConnection,
+        workspace_id: Option<i32>,
+        offset: i64,
+        limit: i64,
+    ) -> QueryResult<(Vec<User>, i64)> {
+        let filtered = || {
+            let mut query = users.into_boxed();
+            if let Some(workspace_id) = workspace_id {
+                query = query.filter(
+                    id.eq_any(
+                        workspace_members::table
+                            .filter(workspace_members::workspace_id.eq(workspace_id))
+                            .select(workspace_members::user_id),
+                    ),
+                );
+            }
+            query
+        };
+
+        let total = filtered().count().get_result(conn).await?;
+        let page = filtered()
+            .order(id.asc())
+            .offset(offset)
+            .limit(limit)
+            .load(conn)
+            .await?;
+        Ok((page, total))
+    }
+
+    pub async fn find_by_workspace(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Vec<User>> {
+        WorkspaceRepository::find_members(conn, workspace_id).await
+    }
+
     pub async fn find_by_id(conn: &mut AsyncPgConnection, user_id: i32) -> QueryResult<User> {
         users.filter(id.eq(user_id)).first(conn).await
     }
 
+    /// Creates a user in the default workspace.
+    ///
+    /// The username is checked with [`normalize_username`]; usernames differing
+    /// only in case from an existing one are rejected as taken.
     pub async fn create(
         conn: &mut AsyncPgConnection,
         request: NewUserRequest,
-    ) -> QueryResult<User> {
+    ) -> Result<User, UserWriteError> {
+        let new_username = normalize_username(&request.username)?;
         let hashed = bcrypt::hash(&request.password, 10).unwrap();
         let new_user = NewUser {
-            username: request.username,
+            username: new_username,
             email: request.email,
             password_hash: hashed,
         };
-        diesel::insert_into(users)
-            .values(&new_user)
-            .get_result(conn)
-            .await
+
+        // New users join the default workspace so they can chat right away
+        conn.transaction(|conn| {
+            async move {
+                let user: User = diesel::insert_into(users)
+                    .values(&new_user)
+                    .get_result(conn)
+                    .await?;
+
+                let workspace = WorkspaceRepository::find_default(conn).await?;
+                WorkspaceRepository::add_member(
+                    conn,
+                    NewWorkspaceMember {
+                        workspace_id: workspace.id,
+                        user_id: user.id,
+                    },
+                )
+                .await?;
+
+                Ok(user)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Creates a user and redeems an invite code for them in one transaction.
+    ///
+    /// # Returns
+    /// * `Result<Option<User>, UserWriteError>` - The new user, or None (and nothing
+    ///   created) if the invite code is not valid
+    pub async fn create_with_invite(
+        conn: &mut AsyncPgConnection,
+        request: NewUserRequest,
+        code: &str,
+    ) -> Result<Option<User>, UserWriteError> {
+        let code = code.to_string();
+
+        let result = conn
+            .transaction(|conn| {
+                async move {
+                    let user = Self::create(conn, request).await?;
+                    match InvitationRepository::redeem(conn, &code, user.id).await? {
+                        Some(_) => Ok(user),
+                        None => Err(DieselError::RollbackTransaction.into()),
+                    }
+                }
+                .scope_boxed()
+            })
+            .await;
+
+        match result {
+            Ok(user) => Ok(Some(user)),
+            Err(UserWriteError::Database(DieselError::RollbackTransaction)) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
+    /// Updates a user. A changed username is checked like a new one and must
+    /// not have surrounding whitespace.
     pub async fn update(
         conn: &mut AsyncPgConnection,
         user_id: i32,
         user: &User,
+    ) -> Result<User, UserWriteError> {
+        let current = Self::find_by_id(conn, user_id).await?;
+        if current.username != user.username && normalize_username(&user.username)? != user.username
+        {
+            return Err(UsernameError::InvalidCharacter.into());
+        }
+
+        Ok(diesel::update(users.filter(id.eq(user_id)))
+            .set(user)
+            .get_result(conn)
+            .await?)
+    }
+
+    pub async fn set_password_hash(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        new_hash: String,
     ) -> QueryResult<User> {
         diesel::update(users.filter(id.eq(user_id)))
-            .set(user)
+            .set(password_hash.eq(new_hash))
             .get_result(conn)
             .await
     }
@@ -53,4 +253,229 @@ impl UserRepository {
             .execute(conn)
             .await
     }
+
+    /// Deletes an account according to the configured deletion mode.
+    ///
+    /// In `Cascade` mode the user's messages are deleted with the account. In
+    /// `Anonymize` mode they are reassigned to the deleted-user sentinel first,
+    /// so the conversation stays intact while the profile (username, email,
+    /// password hash) and memberships are removed.
+    ///
+    /// # Returns
+    /// * `QueryResult<usize>` - The number of deleted accounts
+    pub async fn delete_account(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        mode: UserDeletionMode,
+    ) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            async move {
+                if mode == UserDeletionMode::Anonymize {
+                    let sentinel = Self::find_by_username(conn, DELETED_USER_USERNAME).await?;
+                    diesel::update(messages::table.filter(messages::sender_id.eq(user_id)))
+                        .set(messages::sender_id.eq(sentinel.id))
+                        .execute(conn)
+                        .await?;
+                }
+
+                Self::delete(conn, user_id).await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Merges a duplicate account into a primary one and deletes the duplicate.
+    ///
+    /// Whatever the duplicate sent, owns or took part in is reassigned to the
+    /// primary account. Where both accounts have a row that may exist only once
+    /// per user, like a vote in the same poll or a status, the primary's row is
+    /// kept; of two memberships in the same workspace the stronger role is kept.
+    /// Everything happens in one transaction.
+    pub async fn merge_into(
+        conn: &mut AsyncPgConnection,
+        duplicate: i32,
+        primary: i32,
+    ) -> QueryResult<MergedAccount> {
+        conn.transaction(|conn| {
+            async move {
+                let mut merged = MergedAccount {
+                    messages: diesel::update(
+                        messages::table.filter(messages::sender_id.eq(duplicate)),
+                    )
+                    .set(messages::sender_id.eq(primary))
+                    .execute(conn)
+                    .await?,
+                    ..MergedAccount::default()
+                };
+                diesel::update(
+                    message_revisions::table.filter(message_revisions::edited_by.eq(duplicate)),
+                )
+                .set(message_revisions::edited_by.eq(primary))
+                .execute(conn)
+                .await?;
+
+                let memberships: Vec<(i32, String)> = workspace_members::table
+                    .filter(workspace_members::user_id.eq(duplicate))
+                    .select((workspace_members::workspace_id, workspace_members::role))
+                    .load(conn)
+                    .await?;
+                for (workspace_id, role) in memberships {
+                    let primary_role: Option<String> = workspace_members::table
+                        .find((workspace_id, primary))
+                        .select(workspace_members::role)
+                        .first(conn)
+                        .await
+                        .optional()?;
+                    // Either move the duplicate's membership or upgrade the primary's
+                    let member = match primary_role {
+                        None => duplicate,
+                        Some(primary_role) if role_rank(&role) > role_rank(&primary_role) => {
+                            primary
+                        }
+                        Some(_) => continue,
+                    };
+                    diesel::update(workspace_members::table.find((workspace_id, member)))
+                        .set((
+                            workspace_members::user_id.eq(primary),
+                            workspace_members::role.eq(&role),
+                        ))
+                        .execute(conn)
+                        .await?;
+                    merged.workspaces += 1;
+                }
+
+                merged.bots = diesel::update(bots::table.filter(bots::owner_id.eq(duplicate)))
+                    .set(bots::owner_id.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(
+                    client_certificates::table.filter(client_certificates::user_id.eq(duplicate)),
+                )
+                .set(client_certificates::user_id.eq(primary))
+                .execute(conn)
+                .await?;
+                diesel::update(invitations::table.filter(invitations::created_by.eq(duplicate)))
+                    .set(invitations::created_by.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(polls::table.filter(polls::creator_id.eq(duplicate)))
+                    .set(polls::creator_id.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(notifications::table.filter(notifications::user_id.eq(duplicate)))
+                    .set(notifications::user_id.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(notifications::table.filter(notifications::actor_id.eq(duplicate)))
+                    .set(notifications::actor_id.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(reports::table.filter(reports::reporter_id.eq(duplicate)))
+                    .set(reports::reporter_id.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(reports::table.filter(reports::reported_user_id.eq(duplicate)))
+                    .set(reports::reported_user_id.eq(primary))
+                    .execute(conn)
+                    .await?;
+                diesel::update(reports::table.filter(reports::resolved_by.eq(duplicate)))
+                    .set(reports::resolved_by.eq(primary))
+                    .execute(conn)
+                    .await?;
+
+                // Rows the primary account already has are deleted with the duplicate
+                let voted: Vec<i32> = poll_votes::table
+                    .filter(poll_votes::user_id.eq(primary))
+                    .select(poll_votes::poll_id)
+                    .load(conn)
+                    .await?;
+                diesel::update(
+                    poll_votes::table
+                        .filter(poll_votes::user_id.eq(duplicate))
+                        .filter(diesel::dsl::not(poll_votes::poll_id.eq_any(voted))),
+                )
+                .set(poll_votes::user_id.eq(primary))
+                .execute(conn)
+                .await?;
+                let redeemed: Vec<i32> = invitation_redemptions::table
+                    .filter(invitation_redemptions::user_id.eq(primary))
+                    .select(invitation_redemptions::invitation_id)
+                    .load(conn)
+                    .await?;
+                diesel::update(
+                    invitation_redemptions::table
+                        .filter(invitation_redemptions::user_id.eq(duplicate))
+                        .filter(diesel::dsl::not(
+                            invitation_redemptions::invitation_id.eq_any(redeemed),
+                        )),
+                )
+                .set(invitation_redemptions::user_id.eq(primary))
+                .execute(conn)
+                .await?;
+
+                let has_do_not_disturb: bool =
+                    diesel::select(diesel::dsl::exists(do_not_disturb::table.find(primary)))
+                        .get_result(conn)
+                        .await?;
+                if !has_do_not_disturb {
+                    diesel::update(do_not_disturb::table.find(duplicate))
+                        .set(do_not_disturb::user_id.eq(primary))
+                        .execute(conn)
+                        .await?;
+                }
+                let has_onboarding: bool =
+                    diesel::select(diesel::dsl::exists(user_onboarding::table.find(primary)))
+                        .get_result(conn)
+                        .await?;
+                if !has_onboarding {
+                    diesel::update(user_onboarding::table.find(duplicate))
+                        .set(user_onboarding::user_id.eq(primary))
+                        .execute(conn)
+                        .await?;
+                }
+                let has_preferences: bool =
+                    diesel::select(diesel::dsl::exists(user_preferences::table.find(primary)))
+                        .get_result(conn)
+                        .await?;
+                if !has_preferences {
+                    diesel::update(user_preferences::table.find(duplicate))
+                        .set(user_preferences::user_id.eq(primary))
+                        .execute(conn)
+                        .await?;
+                }
+                let has_status: bool =
+                    diesel::select(diesel::dsl::exists(user_statuses::table.find(primary)))
+                        .get_result(conn)
+                        .await?;
+                if !has_status {
+                    diesel::update(user_statuses::table.find(duplicate))
+                        .set(user_statuses::user_id.eq(primary))
+                        .execute(conn)
+                        .await?;
+                }
+
+                if Self::delete(conn, duplicate).await? == 0 {
+                    return Err(DieselError::NotFound);
+                }
+                diesel::update(users.filter(id.eq(primary)))
+                    .set(updated_at.eq(diesel::dsl::now))
+                    .execute(conn)
+                    .await?;
+
+                Ok(merged)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+/// Orders workspace roles from the weakest to the strongest.
+fn role_rank(role: &str) -> u8 {
+    match role {
+        WORKSPACE_ADMIN_ROLE => 2,
+        WORKSPACE_MODERATOR_ROLE => 1,
+        _ => 0,
+    }
 }
@@ -0,0 +1,67 @@
+use crate::models::user_status::UserStatus;
+use crate::schema::{user_statuses, workspace_members};
+use chat_common::Availability;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct UserStatusRepository;
+
+impl UserStatusRepository {
+    pub async fn find(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<UserStatus>> {
+        user_statuses::table
+            .filter(user_statuses::user_id.eq(user_id))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn find_all(conn: &mut AsyncPgConnection) -> QueryResult<Vec<UserStatus>> {
+        user_statuses::table
+            .order(user_statuses::user_id.asc())
+            .load(conn)
+            .await
+    }
+
+    /// Returns the statuses of the members of a workspace who have set one.
+    pub async fn find_by_workspace(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Vec<UserStatus>> {
+        user_statuses::table
+            .inner_join(
+                workspace_members::table.on(workspace_members::user_id.eq(user_statuses::user_id)),
+            )
+            .filter(workspace_members::workspace_id.eq(workspace_id))
+            .select(UserStatus::as_select())
+            .order(user_statuses::user_id.asc())
+            .load(conn)
+            .await
+    }
+
+    pub async fn set(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        availability: Availability,
+        text: Option<&str>,
+    ) -> QueryResult<UserStatus> {
+        diesel::insert_into(user_statuses::table)
+            .values((
+                user_statuses::user_id.eq(user_id),
+                user_statuses::availability.eq(availability.as_str()),
+                user_statuses::text.eq(text),
+            ))
+            .on_conflict(user_statuses::user_id)
+            .do_update()
+            .set((
+                user_statuses::availability.eq(availability.as_str()),
+                user_statuses::text.eq(text),
+                user_statuses::updated_at.eq(now),
+            ))
+            .get_result(conn)
+            .await
+    }
+}
@@ -0,0 +1,288 @@
+use crate::models::user::User;
+use crate::models::workspace::{
+    NewWorkspace, NewWorkspaceMember, Workspace, WorkspaceMember, DEFAULT_WORKSPACE_SLUG,
+    WORKSPACE_ADMIN_ROLE, WORKSPACE_MODERATOR_ROLE,
+};
+use crate::schema::{users, workspace_members, workspaces};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+pub struct WorkspaceRepository;
+
+impl WorkspaceRepository {
+    pub async fn find_all(conn: &mut AsyncPgConnection) -> QueryResult<Vec<Workspace>> {
+        workspaces::table.load(conn).await
+    }
+
+    pub async fn find_by_id(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Workspace> {
+        workspaces::table
+            .filter(workspaces::id.eq(workspace_id))
+            .first(conn)
+            .await
+    }
+
+    pub async fn find_by_slug(conn: &mut AsyncPgConnection, slug: &str) -> QueryResult<Workspace> {
+        workspaces::table
+            .filter(workspaces::slug.eq(slug))
+            .first(conn)
+            .await
+    }
+
+    pub async fn find_default(conn: &mut AsyncPgConnection) -> QueryResult<Workspace> {
+        Self::find_by_slug(conn, DEFAULT_WORKSPACE_SLUG).await
+    }
+
+    /// Returns the workspaces a user is a member of, oldest first.
+    pub async fn find_for_user(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Vec<Workspace>> {
+        workspaces::table
+            .inner_join(workspace_members::table)
+            .filter(workspace_members::user_id.eq(user_id))
+            .select(Workspace::as_select())
+            .order(workspaces::id.asc())
+            .load(conn)
+            .await
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        new_workspace: NewWorkspace,
+    ) -> QueryResult<Workspace> {
+        diesel::insert_into(workspaces::table)
+            .values(new_workspace)
+            .get_result(conn)
+            .await
+    }
+
+    /// Creates a workspace administered by its creator, in one transaction.
+    pub async fn create_with_admin(
+        conn: &mut AsyncPgConnection,
+        new_workspace: NewWorkspace,
+        admin_id: i32,
+    ) -> QueryResult<Workspace> {
+        conn.transaction(|conn| {
+            async move {
+                let workspace = Self::create(conn, new_workspace).await?;
+                Self::add_member(
+                    conn,
+                    NewWorkspaceMember {
+                        workspace_id: workspace.id,
+                        user_id: admin_id,
+                    },
+                )
+                .await?;
+                Self::set_role(conn, workspace.id, admin_id, WORKSPACE_ADMIN_ROLE).await?;
+                Ok(workspace)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Makes a workspace announcement-only, or lets every member post again.
+    pub async fn set_announcement_only(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        announcement_only: bool,
+    ) -> QueryResult<Workspace> {
+        diesel::update(workspaces::table.filter(workspaces::id.eq(workspace_id)))
+            .set((
+                workspaces::announcement_only.eq(announcement_only),
+                workspaces::updated_at.eq(now),
+            ))
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn delete(conn: &mut AsyncPgConnection, workspace_id: i32) -> QueryResult<usize> {
+        diesel::delete(workspaces::table.filter(workspaces::id.eq(workspace_id)))
+            .execute(conn)
+            .await
+    }
+
+    pub async fn find_members(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Vec<User>> {
+        users::table
+            .inner_join(workspace_members::table)
+            .filter(workspace_members::workspace_id.eq(workspace_id))
+            .select(User::as_select())
+            .load(conn)
+            .await
+    }
+
+    pub async fn find_member_ids(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Vec<i32>> {
+        workspace_members::table
+            .filter(workspace_members::workspace_id.eq(workspace_id))
+            .select(workspace_members::user_id)
+            .load(conn)
+            .await
+    }
+
+    pub async fn is_member(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        user_id: i32,
+    ) -> QueryResult<bool> {
+        diesel::select(diesel::dsl::exists(
+            workspace_members::table
+                .filter(workspace_members::workspace_id.eq(workspace_id))
+                .filter(workspace_members::user_id.eq(user_id)),
+        ))
+        .get_result(conn)
+        .await
+    }
+
+    pub async fn is_admin(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        user_id: i32,
+    ) -> QueryResult<bool> {
+        diesel::select(diesel::dsl::exists(
+            workspace_members::table
+                .filter(workspace_members::workspace_id.eq(workspace_id))
+                .filter(workspace_members::user_id.eq(user_id))
+                .filter(workspace_members::role.eq(WORKSPACE_ADMIN_ROLE)),
+        ))
+        .get_result(conn)
+        .await
+    }
+
+    /// Returns the role of a member, or None if the user is not a member.
+    pub async fn find_role(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        user_id: i32,
+    ) -> QueryResult<Option<String>> {
+        workspace_members::table
+            .filter(workspace_members::workspace_id.eq(workspace_id))
+            .filter(workspace_members::user_id.eq(user_id))
+            .select(workspace_members::role)
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Returns `true` if the user may post in the workspace. Every member may
+    /// post unless the workspace is announcement-only, where only admins and
+    /// moderators may.
+    pub async fn can_post(
+        conn: &mut AsyncPgConnection,
+        workspace: &Workspace,
+        user_id: i32,
+    ) -> QueryResult<bool> {
+        if !workspace.announcement_only {
+            return Ok(true);
+        }
+        let role = Self::find_role(conn, workspace.id, user_id).await?;
+        Ok(matches!(
+            role.as_deref(),
+            Some(WORKSPACE_ADMIN_ROLE | WORKSPACE_MODERATOR_ROLE)
+        ))
+    }
+
+    /// Returns the IDs of the admins and moderators of a workspace.
+    pub async fn find_moderators(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Vec<i32>> {
+        workspace_members::table
+            .filter(workspace_members::workspace_id.eq(workspace_id))
+            .filter(
+                workspace_members::role.eq_any([WORKSPACE_ADMIN_ROLE, WORKSPACE_MODERATOR_ROLE]),
+            )
+            .select(workspace_members::user_id)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the IDs of the workspaces the user administers or moderates.
+    pub async fn find_moderated(
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+    ) -> QueryResult<Vec<i32>> {
+        workspace_members::table
+            .filter(workspace_members::user_id.eq(user_id))
+            .filter(
+                workspace_members::role.eq_any([WORKSPACE_ADMIN_ROLE, WORKSPACE_MODERATOR_ROLE]),
+            )
+            .select(workspace_members::workspace_id)
+            .load(conn)
+            .await
+    }
+
+    /// Returns `true` if the user may moderate the workspace: its admins and
+    /// moderators may, and so may server administrators.
+    pub async fn can_moderate(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        user_id: i32,
+    ) -> QueryResult<bool> {
+        let role = Self::find_role(conn, workspace_id, user_id).await?;
+        if matches!(
+            role.as_deref(),
+            Some(WORKSPACE_ADMIN_ROLE | WORKSPACE_MODERATOR_ROLE)
+        ) {
+            return Ok(true);
+        }
+        Self::is_server_admin(conn, user_id).await
+    }
+
+    /// Returns `true` if the user administers the default workspace, which makes
+    /// them an administrator of the whole server.
+    pub async fn is_server_admin(conn: &mut AsyncPgConnection, user_id: i32) -> QueryResult<bool> {
+        let workspace = Self::find_default(conn).await?;
+        Self::is_admin(conn, workspace.id, user_id).await
+    }
+
+    pub async fn add_member(
+        conn: &mut AsyncPgConnection,
+        member: NewWorkspaceMember,
+    ) -> QueryResult<WorkspaceMember> {
+        diesel::insert_into(workspace_members::table)
+            .values(member)
+            .get_result(conn)
+            .await
+    }
+
+    pub async fn set_role(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        user_id: i32,
+        role: &str,
+    ) -> QueryResult<WorkspaceMember> {
+        diesel::update(
+            workspace_members::table
+                .filter(workspace_members::workspace_id.eq(workspace_id))
+                .filter(workspace_members::user_id.eq(user_id)),
+        )
+        .set(workspace_members::role.eq(role))
+        .get_result(conn)
+        .await
+    }
+
+    pub async fn remove_member(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+        user_id: i32,
+    ) -> QueryResult<usize> {
+        diesel::delete(
+            workspace_members::table
+                .filter(workspace_members::workspace_id.eq(workspace_id))
+                .filter(workspace_members::user_id.eq(user_id)),
+        )
+        .execute(conn)
+        .await
+    }
+}
@@ -0,0 +1,32 @@
+use crate::models::workspace_key::{NewWorkspaceKey, WorkspaceKey};
+use crate::schema::workspace_keys;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+pub struct WorkspaceKeyRepository;
+
+impl WorkspaceKeyRepository {
+    pub async fn find_by_workspace(
+        conn: &mut AsyncPgConnection,
+        workspace_id: i32,
+    ) -> QueryResult<Option<WorkspaceKey>> {
+        workspace_keys::table
+            .filter(workspace_keys::workspace_id.eq(workspace_id))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Stores the key unless the workspace already has one.
+    ///
+    /// # Returns
+    /// * `QueryResult<usize>` - 1 if the key was stored, 0 if another key won
+    pub async fn create(conn: &mut AsyncPgConnection, key: NewWorkspaceKey) -> QueryResult<usize> {
+        diesel::insert_into(workspace_keys::table)
+            .values(key)
+            .on_conflict(workspace_keys::workspace_id)
+            .do_nothing()
+            .execute(conn)
+            .await
+    }
+}
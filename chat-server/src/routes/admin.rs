@@ -0,0 +1,172 @@
+use crate::errors::rocket_server_errors::{not_found_error, server_error};
+use crate::models::user::User;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::account::{self, MergeAccountsError};
+use crate::services::chat_handle::ChatHandle;
+use crate::services::export::ExportService;
+use crate::services::storage::Storage;
+use crate::utils::db_connection::{CacheConn, DbConn};
+use crate::utils::sessions;
+use crate::utils::user_cache::UserCache;
+use diesel::result::Error as DieselError;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{options, post, routes, State};
+use rocket_db_pools::Connection;
+use std::sync::Arc;
+
+/// Maximum length of a system message in characters
+const MAX_SYSTEM_MESSAGE_CHARS: usize = 1000;
+
+#[derive(serde::Deserialize)]
+pub struct SystemMessageRequest {
+    pub text: String,
+    /// Only sends the message to this workspace if set
+    #[serde(default)]
+    pub workspace_id: Option<i32>,
+}
+
+/// Sends a System message, e.g. "Maintenance in 10 minutes", to the live chat
+/// connections. Only server admins may send it. The message is not attributed
+/// to anyone in the chat; who sent it is recorded in the audit log.
+#[post("/system-messages", data = "<request>")]
+pub async fn send_system_message(
+    request: Json<SystemMessageRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    chat: &State<ChatHandle>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let is_admin = WorkspaceRepository::is_server_admin(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !is_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only server admins can send system messages"),
+        ));
+    }
+
+    let SystemMessageRequest { text, workspace_id } = request.into_inner();
+    let text = text.trim().to_string();
+    if text.is_empty() || text.chars().count() > MAX_SYSTEM_MESSAGE_CHARS {
+        return Err(Custom(
+            Status::BadRequest,
+            json!(format!(
+                "System messages must be 1 to {} characters long",
+                MAX_SYSTEM_MESSAGE_CHARS
+            )),
+        ));
+    }
+    if let Some(workspace_id) = workspace_id {
+        WorkspaceRepository::find_by_id(&mut db, workspace_id)
+            .await
+            .map_err(|e| match e {
+                DieselError::NotFound => not_found_error(e.into()),
+                e => server_error(e.into()),
+            })?;
+    }
+
+    tracing::info!(
+        target: "audit",
+        event = "system_message",
+        user_id = user.id,
+        workspace_id,
+        text = text.as_str(),
+        "System message sent"
+    );
+    let recipients = chat
+        .announce(text, workspace_id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    Ok(Custom(
+        Status::Accepted,
+        json!({ "recipients": recipients }),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct MergeUsersRequest {
+    /// The account that is kept
+    pub primary_id: i32,
+    /// The account merged into the primary one and deleted
+    pub duplicate_id: i32,
+}
+
+/// Merges a duplicate account into a primary one. Only server admins may do
+/// it. The duplicate's messages, memberships, bots and other records move to
+/// the primary account in one transaction, its web sessions are moved too, and
+/// the duplicate is deleted. The merge is recorded in the audit log.
+#[post("/users/merge", data = "<request>")]
+pub async fn merge_users(
+    request: Json<MergeUsersRequest>,
+    mut db: Connection<DbConn>,
+    mut cache: Connection<CacheConn>,
+    user: User,
+    exports: &State<Arc<ExportService>>,
+    storage: &State<Arc<dyn Storage>>,
+    users: &State<UserCache>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let is_admin = WorkspaceRepository::is_server_admin(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !is_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only server admins can merge users"),
+        ));
+    }
+
+    let MergeUsersRequest {
+        primary_id,
+        duplicate_id,
+    } = request.into_inner();
+    let merged =
+        match account::merge_accounts(&mut db, primary_id, duplicate_id, exports, storage.as_ref())
+            .await
+        {
+            Ok(merged) => merged,
+            Err(MergeAccountsError::Database(e @ DieselError::NotFound)) => {
+                return Err(not_found_error(e.into()))
+            }
+            Err(MergeAccountsError::Database(e)) => return Err(server_error(e.into())),
+            Err(e) => return Err(Custom(Status::BadRequest, json!(e.to_string()))),
+        };
+    users.invalidate(primary_id);
+    users.invalidate(duplicate_id);
+    let sessions = sessions::move_all(&mut cache, duplicate_id, primary_id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    tracing::info!(
+        target: "audit",
+        event = "users_merged",
+        user_id = user.id,
+        primary_id,
+        duplicate_id,
+        messages = merged.messages,
+        workspaces = merged.workspaces,
+        bots = merged.bots,
+        sessions,
+        "Users merged"
+    );
+
+    Ok(Custom(
+        Status::Ok,
+        json!({
+            "primary_id": primary_id,
+            "merged": merged,
+            "sessions": sessions,
+        }),
+    ))
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![send_system_message, merge_users, options]
+}
@@ -1,16 +1,19 @@
 use rocket::http::Status;
 use rocket::response::status::Custom;
 use rocket::serde::json::{json, Json, Value};
-use rocket_db_pools::deadpool_redis::redis::AsyncCommands;
 use rocket_db_pools::Connection;
 
 use crate::errors::rocket_server_errors::server_error;
 use crate::repositories::user::UserRepository;
 use crate::utils::db_connection::{CacheConn, DbConn};
+use crate::utils::sessions;
 use bcrypt::verify;
 use rand::{distr::Alphanumeric, Rng};
 use rocket::{options, post, routes};
 
+/// How long a login session stays valid
+pub const SESSION_TTL_SECS: u64 = 3 * 60 * 60;
+
 #[derive(serde::Deserialize)]
 pub struct Credentials {
     pub username: String,
@@ -44,8 +47,7 @@ pub async fn login(
             .map(char::from)
             .collect::<String>();
 
-        cache
-            .set_ex::<String, i32, ()>(format!("sessions/{}", token), user.id, 3 * 60 * 60)
+        sessions::create(&mut cache, user.id, &token)
             .await
             .map_err(|e| server_error(e.into()))?;
 
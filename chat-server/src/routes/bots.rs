@@ -0,0 +1,299 @@
+use crate::config::SharedConfig;
+use crate::errors::rocket_server_errors::{not_found_error, server_error, user_write_error};
+use crate::models::bot::{
+    self, Bot, BotMessageRequest, BotProfile, NewBot, NewBotRequest, UpdateBotRequest,
+};
+use crate::models::client_certificate::{self, NewClientCertificate, NewClientCertificateRequest};
+use crate::models::user::User;
+use crate::repositories::bot::BotRepository;
+use crate::repositories::client_certificate::ClientCertificateRepository;
+use crate::repositories::user::UserRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::account::{self, DeleteAccountError};
+use crate::services::bot::{self as bot_service, WebhookService};
+use crate::services::export::ExportService;
+use crate::services::message::pipeline::contains_blocked_word;
+use crate::services::storage::Storage;
+use crate::types::Clients;
+use crate::utils::db_connection::{DbConn, DbPool};
+use crate::utils::metrics::Metrics;
+use chat_common::encryption::EncryptionService;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{delete, get, options, post, put, routes, State};
+use rocket_db_pools::Connection;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Loads a bot, rejecting users other than its owner.
+async fn find_owned_bot(
+    db: &mut Connection<DbConn>,
+    id: i32,
+    user: &User,
+) -> Result<Bot, Custom<Value>> {
+    let bot = BotRepository::find_by_id(db, id)
+        .await
+        .map_err(|e| match e {
+            DieselError::NotFound => not_found_error(e.into()),
+            e => server_error(e.into()),
+        })?;
+
+    if bot.owner_id != user.id {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only the owner can manage a bot"),
+        ));
+    }
+    Ok(bot)
+}
+
+fn validate_webhook_url(webhook_url: &Option<String>) -> Result<(), Custom<Value>> {
+    match webhook_url {
+        Some(url) => bot::validate_webhook_url(url)
+            .map_err(|message| Custom(Status::BadRequest, json!(message))),
+        None => Ok(()),
+    }
+}
+
+#[get("/")]
+pub async fn get_bots(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    BotRepository::find_by_owner(&mut db, user.id)
+        .await
+        .map(|bots| Custom(Status::Ok, json!(bots)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Creates a bot in a workspace the caller administers. The API token is only
+/// returned here; it cannot be read again, only replaced.
+#[post("/", data = "<request>")]
+pub async fn create_bot(
+    request: Json<NewBotRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let request = request.into_inner();
+    validate_webhook_url(&request.webhook_url)?;
+
+    let workspace_id = match request.workspace_id {
+        Some(workspace_id) => workspace_id,
+        None => {
+            WorkspaceRepository::find_default(&mut db)
+                .await
+                .map_err(|e| server_error(e.into()))?
+                .id
+        }
+    };
+    let is_admin = WorkspaceRepository::is_admin(&mut db, workspace_id, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !is_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only workspace admins can add bots"),
+        ));
+    }
+
+    let token = bot::generate_token();
+    let new_bot = NewBot {
+        owner_id: user.id,
+        workspace_id,
+        token_hash: bot::hash_token(&token),
+        webhook_url: request.webhook_url,
+    };
+    BotRepository::create(&mut db, request.username, new_bot)
+        .await
+        .map(|profile| Custom(Status::Created, json!({ "bot": profile, "token": token })))
+        .map_err(user_write_error)
+}
+
+#[put("/<id>", data = "<request>")]
+pub async fn update_bot(
+    id: i32,
+    request: Json<UpdateBotRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    find_owned_bot(&mut db, id, &user).await?;
+    let webhook_url = request.into_inner().webhook_url;
+    validate_webhook_url(&webhook_url)?;
+
+    BotRepository::set_webhook_url(&mut db, id, webhook_url)
+        .await
+        .map(|bot| Custom(Status::Ok, json!(bot)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Replaces a bot's API token, revoking the old one.
+#[post("/<id>/token")]
+pub async fn rotate_token(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    find_owned_bot(&mut db, id, &user).await?;
+
+    let token = bot::generate_token();
+    BotRepository::set_token_hash(&mut db, id, bot::hash_token(&token))
+        .await
+        .map(|_| Custom(Status::Ok, json!({ "token": token })))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Lists the TLS client certificates a bot can log in to the chat with.
+#[get("/<id>/certificates")]
+pub async fn get_certificates(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    find_owned_bot(&mut db, id, &user).await?;
+
+    ClientCertificateRepository::find_by_user(&mut db, id)
+        .await
+        .map(|certificates| Custom(Status::Ok, json!(certificates)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Registers a TLS client certificate by its SHA-256 fingerprint, so the bot's
+/// chat connections presenting it are logged in without a password.
+#[post("/<id>/certificates", data = "<request>")]
+pub async fn add_certificate(
+    id: i32,
+    request: Json<NewClientCertificateRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    find_owned_bot(&mut db, id, &user).await?;
+    let fingerprint =
+        client_certificate::normalize_fingerprint(&request.fingerprint).ok_or_else(|| {
+            Custom(
+                Status::BadRequest,
+                json!("The fingerprint must be a SHA-256 hash in hex"),
+            )
+        })?;
+
+    let new_certificate = NewClientCertificate {
+        fingerprint,
+        user_id: id,
+    };
+    match ClientCertificateRepository::create(&mut db, new_certificate).await {
+        Ok(certificate) => Ok(Custom(Status::Created, json!(certificate))),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(Custom(
+            Status::Conflict,
+            json!("The certificate is already registered"),
+        )),
+        Err(e) => Err(server_error(e.into())),
+    }
+}
+
+#[delete("/<id>/certificates/<fingerprint>")]
+pub async fn delete_certificate(
+    id: i32,
+    fingerprint: &str,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    find_owned_bot(&mut db, id, &user).await?;
+    let fingerprint = client_certificate::normalize_fingerprint(fingerprint).unwrap_or_default();
+
+    match ClientCertificateRepository::delete(&mut db, id, &fingerprint).await {
+        Ok(0) => Err(Custom(
+            Status::NotFound,
+            json!("The certificate is not registered"),
+        )),
+        Ok(_) => Ok(Custom(Status::Ok, json!({ "deleted": fingerprint }))),
+        Err(e) => Err(server_error(e.into())),
+    }
+}
+
+#[delete("/<id>")]
+pub async fn delete_bot(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+    config: &State<SharedConfig>,
+    exports: &State<Arc<ExportService>>,
+    storage: &State<Arc<dyn Storage>>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    find_owned_bot(&mut db, id, &user).await?;
+
+    let mode = config.current().user_deletion_mode;
+    match account::delete_account(&mut db, id, mode, exports, storage.as_ref()).await {
+        Ok(result) => Ok(Custom(Status::Ok, json!(result))),
+        Err(e @ DeleteAccountError::Placeholder) => {
+            Err(Custom(Status::BadRequest, json!(e.to_string())))
+        }
+        Err(DeleteAccountError::Database(e)) => Err(server_error(e.into())),
+    }
+}
+
+/// Posts a text message as the bot authenticated by its API token.
+#[allow(clippy::too_many_arguments)]
+#[post("/<id>/messages", data = "<request>")]
+pub async fn post_message(
+    id: i32,
+    request: Json<BotMessageRequest>,
+    bot: Bot,
+    mut db: Connection<DbConn>,
+    config: &State<SharedConfig>,
+    pool: &State<Arc<DbPool>>,
+    clients: &State<Clients>,
+    encryption: &State<Arc<EncryptionService>>,
+    webhooks: &State<WebhookService>,
+    metrics: &State<Arc<Mutex<Metrics>>>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    if bot.user_id != id {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("The token belongs to another bot"),
+        ));
+    }
+
+    let content = request.into_inner().content;
+    if content.trim().is_empty() {
+        return Err(Custom(Status::BadRequest, json!("The message is empty")));
+    }
+    if contains_blocked_word(&content, &config.current().blocked_words) {
+        return Err(Custom(
+            Status::BadRequest,
+            json!("The message contains blocked words"),
+        ));
+    }
+
+    let username = UserRepository::find_by_id(&mut db, bot.user_id)
+        .await
+        .map_err(|e| server_error(e.into()))?
+        .username;
+    let profile = BotProfile { bot, username };
+    let stored = bot_service::post_message(pool, clients, encryption, webhooks, &profile, content)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    metrics.lock().await.messages_sent.inc();
+
+    Ok(Custom(Status::Created, json!(stored)))
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_bots,
+        create_bot,
+        update_bot,
+        rotate_token,
+        delete_bot,
+        post_message,
+        get_certificates,
+        add_certificate,
+        delete_certificate,
+        options
+    ]
+}
@@ -0,0 +1,132 @@
+use crate::errors::rocket_server_errors::{not_found_error, server_error};
+use crate::models::feature_flag::{FeatureFlagRequest, NewFeatureFlag};
+use crate::models::user::User;
+use crate::repositories::feature_flag::FeatureFlagRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::feature_flags::FeatureFlags;
+use crate::utils::db_connection::DbConn;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{delete, get, options, put, routes, State};
+use rocket_db_pools::Connection;
+
+/// Maximum length of a flag name
+const MAX_FLAG_NAME_LENGTH: usize = 64;
+
+/// Rejects requests from users who are not server admins.
+async fn require_server_admin(
+    db: &mut Connection<DbConn>,
+    user: &User,
+) -> Result<(), Custom<Value>> {
+    let is_admin = WorkspaceRepository::is_server_admin(db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    if !is_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only server admins can manage feature flags"),
+        ));
+    }
+    Ok(())
+}
+
+#[get("/")]
+pub async fn get_feature_flags(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_server_admin(&mut db, &user).await?;
+
+    FeatureFlagRepository::find_all(&mut db)
+        .await
+        .map(|flags| Custom(Status::Ok, json!(flags)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[get("/enabled")]
+pub async fn get_enabled_flags(
+    user: User,
+    flags: &State<FeatureFlags>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    Ok(Custom(Status::Ok, json!(flags.enabled_for(user.id))))
+}
+
+#[put("/<name>", data = "<request>")]
+pub async fn set_feature_flag(
+    name: &str,
+    request: Json<FeatureFlagRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    flags: &State<FeatureFlags>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_server_admin(&mut db, &user).await?;
+
+    let flag = NewFeatureFlag::new(name, request.into_inner());
+    if flag.name.is_empty() || flag.name.len() > MAX_FLAG_NAME_LENGTH {
+        return Err(Custom(
+            Status::BadRequest,
+            json!(format!(
+                "Flag names must be 1 to {} characters long",
+                MAX_FLAG_NAME_LENGTH
+            )),
+        ));
+    }
+    if !(0..=100).contains(&flag.rollout_percentage) {
+        return Err(Custom(
+            Status::BadRequest,
+            json!("rollout_percentage must be between 0 and 100"),
+        ));
+    }
+
+    let flag = FeatureFlagRepository::upsert(&mut db, flag)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    rocket::info!(
+        "Feature flag '{}' set to enabled={} rollout={}% by user {}",
+        flag.name,
+        flag.enabled,
+        flag.rollout_percentage,
+        user.id
+    );
+    flags.set(flag.clone());
+
+    Ok(Custom(Status::Ok, json!(flag)))
+}
+
+#[delete("/<name>")]
+pub async fn delete_feature_flag(
+    name: &str,
+    mut db: Connection<DbConn>,
+    user: User,
+    flags: &State<FeatureFlags>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_server_admin(&mut db, &user).await?;
+
+    match FeatureFlagRepository::delete(&mut db, name).await {
+        Ok(0) => Err(not_found_error(
+            format!("Feature flag '{}' does not exist", name).into(),
+        )),
+        Ok(result) => {
+            flags.remove(name);
+            Ok(Custom(Status::Ok, json!(result)))
+        }
+        Err(e) => Err(server_error(e.into())),
+    }
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_feature_flags,
+        get_enabled_flags,
+        set_feature_flag,
+        delete_feature_flag,
+        options
+    ]
+}
@@ -0,0 +1,105 @@
+use crate::errors::rocket_server_errors::{not_found_error, server_error};
+use crate::models::invitation::{NewInvitation, NewInvitationRequest};
+use crate::models::user::User;
+use crate::repositories::invitation::InvitationRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::utils::db_connection::DbConn;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{delete, get, options, post, routes};
+use rocket_db_pools::Connection;
+
+#[get("/")]
+pub async fn get_invitations(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    InvitationRepository::find_by_creator(&mut db, user.id)
+        .await
+        .map(|invitations| Custom(Status::Ok, json!(invitations)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[post("/", data = "<request>")]
+pub async fn create_invitation(
+    request: Json<NewInvitationRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    // Server invites need a server admin, workspace invites an admin of that workspace
+    let is_admin = match request.workspace_id {
+        Some(workspace_id) => WorkspaceRepository::is_admin(&mut db, workspace_id, user.id).await,
+        None => WorkspaceRepository::is_server_admin(&mut db, user.id).await,
+    }
+    .map_err(|e| server_error(e.into()))?;
+
+    if !is_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only admins can create invitations"),
+        ));
+    }
+
+    InvitationRepository::create(&mut db, NewInvitation::new(request.into_inner(), user.id))
+        .await
+        .map(|invitation| Custom(Status::Created, json!(invitation)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[delete("/<id>")]
+pub async fn delete_invitation(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let invitation = InvitationRepository::find_by_id(&mut db, id)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })?;
+
+    if invitation.created_by != user.id {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only the creator can revoke an invitation"),
+        ));
+    }
+
+    InvitationRepository::delete(&mut db, id)
+        .await
+        .map(|result| Custom(Status::Ok, json!(result)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[post("/<code>/redeem")]
+pub async fn redeem_invitation(
+    code: &str,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    match InvitationRepository::redeem(&mut db, code, user.id).await {
+        Ok(Some(invitation)) => Ok(Custom(Status::Ok, json!(invitation))),
+        Ok(None) => Err(Custom(
+            Status::BadRequest,
+            json!("Invalid or expired invite code"),
+        )),
+        Err(e) => Err(server_error(e.into())),
+    }
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_invitations,
+        create_invitation,
+        delete_invitation,
+        redeem_invitation,
+        options
+    ]
+}
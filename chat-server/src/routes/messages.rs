@@ -1,23 +1,421 @@
-use crate::errors::rocket_server_errors::server_error;
-use crate::models::message::{Message, NewMessage};
+use crate::errors::rocket_server_errors::{not_found_error, server_error};
+use crate::models::message::{Message, MessageFilter, MessageType, NewMessage, CSV_HEADER};
 use crate::models::user::User;
 use crate::repositories::message::MessageRepository;
-use crate::utils::db_connection::DbConn;
-use rocket::http::Status;
+use crate::repositories::message_revision::MessageRevisionRepository;
+use crate::repositories::user::UserRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::storage::{attachment_key, ByteStream, ObjectNotFound, Storage};
+use crate::utils::db_connection::{CacheConn, DbConn, ReadConn};
+use crate::utils::download::{ByteRange, Download, DownloadRequest};
+use chat_common::file_ops::{downscale_image, ImageDownscale};
+use diesel_async::AsyncPgConnection;
+use rand::{distr::Alphanumeric, Rng};
+use rocket::http::{ContentType, Header, Status};
 use rocket::response::status::Custom;
+use rocket::response::stream::TextStream;
 use rocket::serde::json::{json, Json, Value};
-use rocket::{delete, get, options, post, put, routes};
+use rocket::{delete, get, options, post, put, routes, Responder, State};
+use rocket_db_pools::deadpool_redis::redis::AsyncCommands;
 use rocket_db_pools::Connection;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
 
-#[get("/")]
+/// Messages loaded per query while streaming an export
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// A CSV export streamed to the client.
+#[derive(Responder)]
+#[response(content_type = "text/csv")]
+pub struct CsvExport<R> {
+    body: R,
+    /// Number of rows in the export, so clients can show progress
+    rows: Header<'static>,
+    disposition: Header<'static>,
+}
+
+impl<R> CsvExport<R> {
+    /// Wraps an export of `rows` rows, downloaded as `file_name`.
+    pub fn new(body: R, rows: i64, file_name: &str) -> Self {
+        Self {
+            body,
+            rows: Header::new("X-Export-Rows", rows.to_string()),
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        }
+    }
+}
+
+/// Lists messages, optionally of a workspace. With `after_seq` and/or
+/// `until_seq` only the workspace's messages in that range of sequence
+/// numbers are listed, in order, so clients can fetch the messages they missed.
+#[get("/?<workspace_id>&<after_seq>&<until_seq>")]
 pub async fn get_messages(
-    mut db: Connection<DbConn>,
+    workspace_id: Option<i32>,
+    after_seq: Option<i64>,
+    until_seq: Option<i64>,
+    mut db: ReadConn,
     _user: User,
 ) -> Result<Custom<Value>, Custom<Value>> {
-    MessageRepository::find_all(&mut db)
+    if after_seq.is_some() || until_seq.is_some() {
+        let Some(workspace_id) = workspace_id else {
+            return Err(Custom(
+                Status::BadRequest,
+                json!("Sequence numbers need a workspace_id"),
+            ));
+        };
+        let filter = MessageFilter {
+            workspace_id: Some(workspace_id),
+            after_seq,
+            until_seq,
+            ..MessageFilter::default()
+        };
+        return MessageRepository::find_filtered(&mut db, &filter)
+            .await
+            .map(|messages| Custom(Status::Ok, json!(messages)))
+            .map_err(|e| server_error(e.into()));
+    }
+
+    match workspace_id {
+        Some(workspace_id) => MessageRepository::find_by_workspace(&mut db, workspace_id).await,
+        None => MessageRepository::find_all(&mut db).await,
+    }
+    .map(|event| Custom(Status::Ok, json!(event)))
+    .map_err(|e| server_error(e.into()))
+}
+
+/// Streams the messages matching the filters as CSV.
+///
+/// Messages are read page by page, so exports of any size use constant memory.
+#[get("/export?<sender_id>&<message_type>&<workspace_id>")]
+pub async fn export_messages(
+    sender_id: Option<i32>,
+    message_type: Option<&str>,
+    workspace_id: Option<i32>,
+    mut db: ReadConn,
+    _user: User,
+) -> Result<CsvExport<TextStream![String]>, Custom<Value>> {
+    let message_type = match message_type {
+        Some(value) => Some(value.to_lowercase().parse::<MessageType>().map_err(|_| {
+            Custom(
+                Status::BadRequest,
+                json!(format!("Unknown message type '{}'", value)),
+            )
+        })?),
+        None => None,
+    };
+    let filter = MessageFilter {
+        sender_id,
+        message_type,
+        workspace_id,
+        ..MessageFilter::default()
+    };
+
+    let rows = MessageRepository::count_filtered(&mut db, &filter)
         .await
-        .map(|event| Custom(Status::Ok, json!(event)))
-        .map_err(|e| server_error(e.into()))
+        .map_err(|e| server_error(e.into()))?;
+
+    let body = TextStream! {
+        yield CSV_HEADER.to_string();
+
+        let mut after_id = 0;
+        loop {
+            let page = match MessageRepository::find_filtered_after(
+                &mut db,
+                &filter,
+                after_id,
+                EXPORT_PAGE_SIZE,
+            )
+            .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    rocket::error!("Message export failed after message {}: {}", after_id, e);
+                    break;
+                }
+            };
+            let Some(last) = page.last() else {
+                break;
+            };
+            after_id = last.id;
+            yield page.iter().map(Message::to_csv_row).collect::<String>();
+        }
+    };
+
+    Ok(CsvExport::new(body, rows, "messages.csv"))
+}
+
+/// Longest edge of attachment thumbnails, in pixels
+const THUMBNAIL_DIMENSION: u32 = 320;
+/// How long a download token can be used
+pub const DOWNLOAD_TOKEN_TTL_SECS: u64 = 10 * 60;
+/// Types browsers display without running anything. Attachments of other
+/// types, HTML and SVG among them, are only offered for saving, so they cannot
+/// run scripts on the API's origin.
+const INLINE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+    "audio/mpeg",
+    "audio/ogg",
+    "audio/wav",
+    "audio/webm",
+    "audio/flac",
+    "audio/aac",
+    "video/mp4",
+    "video/webm",
+    "video/ogg",
+];
+
+/// The stored attachment of a message.
+struct StoredAttachment {
+    file_name: String,
+    sha256: String,
+    message_type: MessageType,
+    /// Key of the content in the storage backend
+    key: String,
+    size: u64,
+}
+
+/// Finds the attachment of a message for a member of its workspace.
+///
+/// Browsers cannot set headers on links, `<img>` and `<video>` elements, so
+/// a download token from `POST /messages/download-token` may be given as
+/// `token` instead of logging in.
+async fn find_attachment(
+    id: i32,
+    token: Option<&str>,
+    user: Option<User>,
+    db: &mut AsyncPgConnection,
+    cache: &mut Connection<CacheConn>,
+    storage: &dyn Storage,
+) -> Result<StoredAttachment, Custom<Value>> {
+    let user = match (user, token) {
+        (Some(user), _) => Some(user),
+        (None, Some(token)) => match cache
+            .get::<_, i32>(format!("download_tokens/{}", token))
+            .await
+        {
+            Ok(user_id) => UserRepository::find_by_id(db, user_id).await.ok(),
+            Err(_) => None,
+        },
+        (None, None) => None,
+    };
+    let Some(user) = user else {
+        return Err(Custom(Status::Unauthorized, json!("Not logged in")));
+    };
+
+    let message = MessageRepository::find_by_id(db, id)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            e => server_error(e.into()),
+        })?;
+    let is_member = WorkspaceRepository::is_member(db, message.workspace_id, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !is_member {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only members of the workspace can download its attachments"),
+        ));
+    }
+    let (Some(file_name), Some(sha256)) = (message.file_name, message.sha256) else {
+        return Err(Custom(
+            Status::NotFound,
+            json!(format!("Message {} has no attachment", id)),
+        ));
+    };
+
+    // Attachments stored before they were keyed by hash are under the message ID
+    let mut key = attachment_key(&sha256);
+    let size = match storage.size(&key).await {
+        Err(e) if e.is::<ObjectNotFound>() => {
+            key = format!("attachments/{}", id);
+            storage.size(&key).await
+        }
+        size => size,
+    }
+    .map_err(|e| match e {
+        e if e.is::<ObjectNotFound>() => Custom(
+            Status::NotFound,
+            json!(format!("The attachment of message {} is not stored", id)),
+        ),
+        e => server_error(e.into()),
+    })?;
+
+    Ok(StoredAttachment {
+        file_name,
+        sha256,
+        message_type: message.message_type,
+        key,
+        size,
+    })
+}
+
+/// Returns the content type of a file from the extension of its name.
+fn content_type_of(file_name: &str) -> ContentType {
+    Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ContentType::from_extension)
+        .unwrap_or(ContentType::Binary)
+}
+
+/// Returns whether content of a type may be shown in the browser.
+fn is_inline(content_type: &ContentType) -> bool {
+    let media_type = format!("{}/{}", content_type.top(), content_type.sub()).to_lowercase();
+    INLINE_TYPES.contains(&media_type.as_str())
+}
+
+/// Lets the browser show inert content and makes it save everything else.
+fn with_disposition(download: Download, file_name: &str) -> Download {
+    if is_inline(&content_type_of(file_name)) {
+        download.inline(file_name)
+    } else {
+        download.attachment(file_name)
+    }
+}
+
+/// Issues a token for the `?token=` of attachment and thumbnail links. Unlike
+/// the session token it only grants downloads and expires after
+/// `DOWNLOAD_TOKEN_TTL_SECS`, so a link that leaks is of little use.
+#[post("/download-token")]
+pub async fn issue_download_token(
+    user: User,
+    mut cache: Connection<CacheConn>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let token: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect();
+    cache
+        .set_ex::<String, i32, ()>(
+            format!("download_tokens/{}", token),
+            user.id,
+            DOWNLOAD_TOKEN_TTL_SECS,
+        )
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    Ok(Custom(
+        Status::Ok,
+        json!({ "token": token, "expires_in": DOWNLOAD_TOKEN_TTL_SECS }),
+    ))
+}
+
+/// Streams the content of a file or image message to members of its workspace.
+///
+/// `Range` requests are answered with the requested part, so browsers can seek
+/// in videos, and the content's SHA-256 serves as ETag for `If-None-Match`.
+/// Browsers cannot set headers on links and `<video>` elements, so a download
+/// token may also be passed as `?token=`. Only [`INLINE_TYPES`] are shown in
+/// the browser, everything else is downloaded.
+#[get("/<id>/attachment?<token>")]
+pub async fn get_attachment(
+    id: i32,
+    token: Option<&str>,
+    user: Option<User>,
+    request: DownloadRequest,
+    mut db: ReadConn,
+    mut cache: Connection<CacheConn>,
+    storage: &State<Arc<dyn Storage>>,
+) -> Result<Download, Custom<Value>> {
+    let attachment = find_attachment(
+        id,
+        token,
+        user,
+        &mut db,
+        &mut cache,
+        storage.inner().as_ref(),
+    )
+    .await?;
+    let sha256 = &attachment.sha256;
+    if request.is_cached(sha256) {
+        return Ok(Download::not_modified(sha256));
+    }
+
+    let content_type = content_type_of(&attachment.file_name);
+    let download = match request.range(attachment.size) {
+        ByteRange::Full => {
+            let body = storage
+                .stream(&attachment.key)
+                .await
+                .map_err(|e| server_error(e.into()))?;
+            Download::full(content_type, sha256, body)
+        }
+        ByteRange::Partial(range) => {
+            let body = storage
+                .stream_range(&attachment.key, range.clone())
+                .await
+                .map_err(|e| server_error(e.into()))?;
+            Download::partial(content_type, sha256, &range, attachment.size, body)
+        }
+        ByteRange::Unsatisfiable => return Ok(Download::unsatisfiable(sha256, attachment.size)),
+    };
+    Ok(with_disposition(download, &attachment.file_name))
+}
+
+/// Sends a copy of an image message's attachment downscaled to at most
+/// `THUMBNAIL_DIMENSION` pixels on its longest edge, for previews in lists.
+/// Smaller images are sent as they are.
+///
+/// Access works like for the attachment itself, `?token=` included.
+#[get("/<id>/thumbnail?<token>")]
+pub async fn get_thumbnail(
+    id: i32,
+    token: Option<&str>,
+    user: Option<User>,
+    request: DownloadRequest,
+    mut db: ReadConn,
+    mut cache: Connection<CacheConn>,
+    storage: &State<Arc<dyn Storage>>,
+) -> Result<Download, Custom<Value>> {
+    let attachment = find_attachment(
+        id,
+        token,
+        user,
+        &mut db,
+        &mut cache,
+        storage.inner().as_ref(),
+    )
+    .await?;
+    if attachment.message_type != MessageType::Image {
+        return Err(Custom(
+            Status::NotFound,
+            json!(format!("Message {} is not an image", id)),
+        ));
+    }
+    let etag = format!("{}-thumbnail-{}", attachment.sha256, THUMBNAIL_DIMENSION);
+    if request.is_cached(&etag) {
+        return Ok(Download::not_modified(&etag));
+    }
+
+    let content = storage
+        .get(&attachment.key)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let options = ImageDownscale {
+        max_dimension: THUMBNAIL_DIMENSION,
+        max_bytes: usize::MAX,
+    };
+    let thumbnail = downscale_image(content, options).await.map_err(|e| {
+        Custom(
+            Status::UnprocessableEntity,
+            json!(format!("Cannot make a thumbnail of message {}: {}", id, e)),
+        )
+    })?;
+
+    let body: ByteStream = Box::pin(Cursor::new(thumbnail));
+    let download = Download::full(content_type_of(&attachment.file_name), &etag, body);
+    Ok(with_disposition(download, &attachment.file_name))
 }
 
 #[get("/<id>")]
@@ -31,10 +429,30 @@ pub async fn get_message(
         .map_err(|e| server_error(e.into()))
 }
 
+/// Lists the earlier versions of an edited message, oldest first.
+#[get("/<id>/revisions")]
+pub async fn get_message_revisions(
+    id: i32,
+    mut db: Connection<DbConn>,
+    _user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    MessageRepository::find_by_id(&mut db, id)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })?;
+
+    MessageRevisionRepository::find_by_message(&mut db, id)
+        .await
+        .map(|revisions| Custom(Status::Ok, json!(revisions)))
+        .map_err(|e| server_error(e.into()))
+}
+
 #[get("/user/<user_id>")]
 pub async fn get_messages_by_user(
     user_id: i32,
-    mut db: Connection<DbConn>,
+    mut db: ReadConn,
 ) -> Result<Custom<Value>, Custom<Value>> {
     MessageRepository::find_by_sender(&mut db, user_id)
         .await
@@ -53,16 +471,21 @@ pub async fn create_message(
         .map_err(|e| server_error(e.into()))
 }
 
+/// Updates a message, keeping its previous content as a revision.
 #[put("/<id>", data = "<message>")]
 pub async fn update_message(
     id: i32,
     message: Json<Message>,
     mut db: Connection<DbConn>,
+    user: User,
 ) -> Result<Custom<Value>, Custom<Value>> {
-    MessageRepository::update(&mut db, id, &message.into_inner())
+    MessageRepository::update(&mut db, id, message.into_inner(), user.id)
         .await
         .map(|event| Custom(Status::Ok, json!(event)))
-        .map_err(|e| server_error(e.into()))
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })
 }
 
 #[delete("/<id>")]
@@ -95,7 +518,12 @@ pub fn options() -> &'static str {
 pub fn routes() -> Vec<rocket::Route> {
     routes![
         get_messages,
+        export_messages,
         get_message,
+        issue_download_token,
+        get_attachment,
+        get_thumbnail,
+        get_message_revisions,
         get_messages_by_user,
         create_message,
         update_message,
@@ -104,3 +532,18 @@ pub fn routes() -> Vec<rocket::Route> {
         options
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_inert_types_are_inline() {
+        assert!(is_inline(&content_type_of("holiday.JPG")));
+        assert!(is_inline(&content_type_of("talk.mp4")));
+        assert!(!is_inline(&content_type_of("page.html")));
+        assert!(!is_inline(&content_type_of("logo.svg")));
+        assert!(!is_inline(&content_type_of("notes.txt")));
+        assert!(!is_inline(&content_type_of("no-extension")));
+    }
+}
@@ -1,16 +1,36 @@
 use std::sync::Arc;
 
 use rocket::get;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Value};
 use rocket::State;
 
+use crate::services::health::PoolMonitor;
 use crate::utils::metrics::Metrics;
 
 #[get("/metrics")]
-pub async fn get_metrics(metrics: &State<Arc<tokio::sync::Mutex<Metrics>>>) -> String {
+pub async fn get_metrics(
+    metrics: &State<Arc<tokio::sync::Mutex<Metrics>>>,
+    monitor: &State<PoolMonitor>,
+) -> String {
+    monitor.record_stats().await;
     let metrics = metrics.lock().await;
     metrics.get_metrics()
 }
 
+/// Readiness check: probes the database and Redis pools and answers 503 if
+/// any of them cannot hand out a working connection.
+#[get("/ready")]
+pub async fn get_ready(monitor: &State<PoolMonitor>) -> Custom<Value> {
+    let readiness = monitor.check().await;
+    let status = match readiness.ready {
+        true => Status::Ok,
+        false => Status::ServiceUnavailable,
+    };
+    Custom(status, json!(readiness))
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![get_metrics]
+    rocket::routes![get_metrics, get_ready]
 }
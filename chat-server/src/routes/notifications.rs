@@ -0,0 +1,78 @@
+use crate::errors::rocket_server_errors::server_error;
+use crate::models::user::User;
+use crate::repositories::notification::NotificationRepository;
+use crate::utils::db_connection::DbConn;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Value};
+use rocket::{get, options, put, routes};
+use rocket_db_pools::Connection;
+
+#[get("/?<unread>")]
+pub async fn get_notifications(
+    unread: Option<bool>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let notifications =
+        NotificationRepository::find_for_user(&mut db, user.id, unread.unwrap_or(false))
+            .await
+            .map_err(|e| server_error(e.into()))?;
+    let unread_count = NotificationRepository::count_unread(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    Ok(Custom(
+        Status::Ok,
+        json!({ "unread_count": unread_count, "notifications": notifications }),
+    ))
+}
+
+#[get("/unread-count")]
+pub async fn get_unread_count(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    NotificationRepository::count_unread(&mut db, user.id)
+        .await
+        .map(|count| Custom(Status::Ok, json!({ "unread_count": count })))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[put("/<id>/read")]
+pub async fn mark_read(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    NotificationRepository::mark_read(&mut db, user.id, id)
+        .await
+        .map(|result| Custom(Status::Ok, json!(result)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[put("/read")]
+pub async fn mark_all_read(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    NotificationRepository::mark_all_read(&mut db, user.id)
+        .await
+        .map(|result| Custom(Status::Ok, json!(result)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_notifications,
+        get_unread_count,
+        mark_read,
+        mark_all_read,
+        options
+    ]
+}
@@ -0,0 +1,133 @@
+use crate::errors::rocket_server_errors::{not_found_error, server_error};
+use crate::models::report::{NewReportRequest, ReportStatus, ResolveReportRequest};
+use crate::models::user::User;
+use crate::repositories::report::ReportRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::report::{ReportError, ReportService};
+use crate::types::Clients;
+use crate::utils::db_connection::{DbConn, DbPool};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{get, options, post, put, routes, State};
+use rocket_db_pools::Connection;
+use std::sync::Arc;
+
+/// Maps a refused report to `400`, `404` or `409`.
+fn report_error(e: ReportError) -> Custom<Value> {
+    let status = match e {
+        ReportError::EmptyReason | ReportError::ReasonTooLong | ReportError::NotAResolution => {
+            Status::BadRequest
+        }
+        ReportError::MessageNotFound => Status::NotFound,
+        ReportError::AlreadyReported | ReportError::AlreadyResolved => Status::Conflict,
+    };
+    Custom(status, json!(e.to_string()))
+}
+
+/// Reports a message of one of the caller's workspaces to its moderators.
+#[post("/", data = "<request>")]
+pub async fn create_report(
+    request: Json<NewReportRequest>,
+    user: User,
+    pool: &State<Arc<DbPool>>,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let request = request.into_inner();
+    ReportService::new(pool.inner().clone(), clients.inner().clone())
+        .file(user.id, request.message_id, &request.reason)
+        .await
+        .map_err(|e| server_error(e.into()))?
+        .map(|report| Custom(Status::Created, json!(report)))
+        .map_err(report_error)
+}
+
+/// Lists reports with their messages, newest first. Server admins see the
+/// reports of every workspace, everyone else those of the workspaces they
+/// administer or moderate.
+#[get("/?<status>&<workspace_id>")]
+pub async fn get_reports(
+    status: Option<&str>,
+    workspace_id: Option<i32>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let status = status
+        .map(str::parse::<ReportStatus>)
+        .transpose()
+        .map_err(|e| Custom(Status::BadRequest, json!(e)))?;
+
+    let is_server_admin = WorkspaceRepository::is_server_admin(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let mut workspace_ids = if is_server_admin {
+        None
+    } else {
+        Some(
+            WorkspaceRepository::find_moderated(&mut db, user.id)
+                .await
+                .map_err(|e| server_error(e.into()))?,
+        )
+    };
+    if let Some(workspace_id) = workspace_id {
+        match &mut workspace_ids {
+            Some(ids) => ids.retain(|id| *id == workspace_id),
+            None => workspace_ids = Some(vec![workspace_id]),
+        }
+    }
+    if workspace_ids.as_ref().is_some_and(|ids| ids.is_empty()) {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only workspace moderators can review reports"),
+        ));
+    }
+
+    ReportRepository::find_with_messages(&mut db, workspace_ids.as_deref(), status)
+        .await
+        .map(|reports| Custom(Status::Ok, json!(reports)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Resolves an open report as dismissed, deleted, warned or banned, carrying
+/// out the resolution.
+#[put("/<id>", data = "<request>")]
+pub async fn resolve_report(
+    id: i32,
+    request: Json<ResolveReportRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    pool: &State<Arc<DbPool>>,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let report = ReportRepository::find_by_id(&mut db, id)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })?;
+    let can_moderate = WorkspaceRepository::can_moderate(&mut db, report.workspace_id, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !can_moderate {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only workspace moderators can resolve reports"),
+        ));
+    }
+
+    ReportService::new(pool.inner().clone(), clients.inner().clone())
+        .resolve(id, user.id, request.status)
+        .await
+        .map_err(|e| server_error(e.into()))?
+        .map(|report| Custom(Status::Ok, json!(report)))
+        .map_err(report_error)
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![create_report, get_reports, resolve_report, options]
+}
@@ -1,19 +1,111 @@
-use crate::errors::rocket_server_errors::server_error;
-use crate::models::user::{NewUserRequest, User};
+use crate::config::{ServerConfig, SharedConfig, MAX_PASSWORD_SCORE};
+use crate::errors::rocket_server_errors::{not_found_error, server_error, user_write_error};
+use crate::i18n;
+use crate::models::do_not_disturb::{DndSettings, UpdateDoNotDisturbRequest};
+use crate::models::email_token::{ForgotPasswordRequest, ResetPasswordRequest, VerifyEmailRequest};
+use crate::models::message::{DailyMessageCount, HistoryEntry, MessageFilter, MessageType};
+use crate::models::preference::UpdatePreferencesRequest;
+use crate::models::user::{
+    ChangePasswordRequest, CheckPasswordRequest, NewUserRequest, User, USER_CSV_HEADER,
+};
+use crate::models::user_status::UpdateStatusRequest;
+use crate::repositories::do_not_disturb::DoNotDisturbRepository;
+use crate::repositories::email_token::EmailTokenRepository;
+use crate::repositories::message::MessageRepository;
+use crate::repositories::preference::PreferenceRepository;
 use crate::repositories::user::UserRepository;
-use crate::utils::db_connection::DbConn;
-use rocket::http::Status;
+use crate::repositories::user_status::UserStatusRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::routes::authorization::SESSION_TTL_SECS;
+use crate::routes::messages::CsvExport;
+use crate::services::account::{self, avatar_key, DeleteAccountError};
+use crate::services::email::EmailService;
+use crate::services::email_links::{self, PasswordResetError};
+use crate::services::export::{ExportService, ExportStatus};
+use crate::services::feature_flags::{self, FeatureFlags};
+use crate::services::password::{self, PasswordChangeError, PasswordService, PasswordViolation};
+use crate::services::password_reset_throttle::PasswordResetThrottle;
+use crate::services::presence::{self, PresenceService};
+use crate::services::storage::{ObjectNotFound, Storage};
+use crate::services::user_import::UserImportService;
+use crate::types::{AuthState, Clients};
+use crate::utils::db_connection::{CacheConn, DbConn, DbPool, ReadConn};
+use crate::utils::sessions;
+use crate::utils::user_cache::UserCache;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use rocket::data::{Data, ToByteUnit};
+use rocket::fs::NamedFile;
+use rocket::http::{ContentType, Header, Status};
 use rocket::response::status::Custom;
+use rocket::response::Redirect;
 use rocket::serde::json::{json, Json, Value};
-use rocket::{delete, get, options, post, put, routes};
+use rocket::{delete, get, options, post, put, routes, Responder, State};
 use rocket_db_pools::Connection;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[get("/")]
-pub async fn get_users(mut db: Connection<DbConn>) -> Result<Custom<Value>, Custom<Value>> {
-    UserRepository::find_all(&mut db)
+/// Largest accepted avatar image
+const MAX_AVATAR_SIZE_MIB: u64 = 2;
+/// Largest accepted user import file
+const MAX_IMPORT_SIZE_MIB: u64 = 1;
+/// How long presigned avatar URLs stay valid
+const AVATAR_URL_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// Days of daily message counts in the activity stats by default
+const DEFAULT_STATS_DAYS: u32 = 30;
+const MAX_STATS_DAYS: u32 = 365;
+/// Messages per page of a user's history by default
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+const MAX_HISTORY_LIMIT: i64 = 200;
+/// Users per page of the paged user list by default
+const DEFAULT_USERS_PER_PAGE: i64 = 25;
+const MAX_USERS_PER_PAGE: i64 = 100;
+/// Characters of a session token shown to identify the session
+const TOKEN_PREFIX_LEN: usize = 8;
+
+/// Lists users, or the members of a workspace with `workspace_id`.
+///
+/// With `page` (starting at 1) or `per_page`, only that page of users ordered
+/// by ID is returned, wrapped with the paging and the total number of users.
+#[get("/?<workspace_id>&<page>&<per_page>")]
+pub async fn get_users(
+    workspace_id: Option<i32>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+    mut db: ReadConn,
+) -> Result<Custom<Value>, Custom<Value>> {
+    if page.is_some() || per_page.is_some() {
+        let page = page.unwrap_or(1).max(1);
+        let per_page = per_page
+            .unwrap_or(DEFAULT_USERS_PER_PAGE)
+            .clamp(1, MAX_USERS_PER_PAGE);
+        let (users, total) = UserRepository::find_page(
+            &mut db,
+            workspace_id,
+            (page - 1).saturating_mul(per_page),
+            per_page,
+        )
         .await
-        .map(|users| Custom(Status::Ok, json!(users)))
-        .map_err(|e| server_error(e.into()))
+        .map_err(|e| server_error(e.into()))?;
+        return Ok(Custom(
+            Status::Ok,
+            json!({
+                "users": users,
+                "page": page,
+                "per_page": per_page,
+                "total": total,
+            }),
+        ));
+    }
+
+    match workspace_id {
+        Some(workspace_id) => UserRepository::find_by_workspace(&mut db, workspace_id).await,
+        None => UserRepository::find_all(&mut db).await,
+    }
+    .map(|users| Custom(Status::Ok, json!(users)))
+    .map_err(|e| server_error(e.into()))
 }
 
 #[get("/<id>")]
@@ -24,38 +116,960 @@ pub async fn get_user(id: i32, mut db: Connection<DbConn>) -> Result<Custom<Valu
         .map_err(|e| server_error(e.into()))
 }
 
+/// Lists broken password rules with their parameters and a description.
+fn violation_values(violations: &[PasswordViolation]) -> Vec<Value> {
+    violations
+        .iter()
+        .map(|violation| {
+            let mut value = json!(violation);
+            value["message"] = json!(violation.to_string());
+            value
+        })
+        .collect()
+}
+
+/// Rejects a password that breaks the password policy, listing every broken
+/// rule with its parameters and a description.
+fn password_policy_error(violations: &[PasswordViolation]) -> Custom<Value> {
+    Custom(
+        Status::BadRequest,
+        json!({
+            "message": "The password does not meet the password policy",
+            "violations": violation_values(violations),
+        }),
+    )
+}
+
+/// Checks a password against the password policy without setting it, so
+/// forms can rate a password while it is typed. Like registration, it needs
+/// no login.
+#[post("/password/check", data = "<request>")]
+pub async fn check_password(
+    request: Json<CheckPasswordRequest>,
+    config: &State<SharedConfig>,
+    passwords: &State<Arc<PasswordService>>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let user_inputs = [request.username.as_str(), request.email.as_str()];
+    let violations = passwords.validate(&request.password, &user_inputs).await;
+
+    Ok(Custom(
+        Status::Ok,
+        json!({
+            "accepted": violations.is_empty(),
+            "score": password::strength(&request.password, &user_inputs),
+            "max_score": MAX_PASSWORD_SCORE,
+            "min_score": config.current().password_policy.min_score,
+            "violations": violation_values(&violations),
+        }),
+    ))
+}
+
 #[post("/", data = "<new_user>")]
 pub async fn create_user(
     new_user: Json<NewUserRequest>,
     mut db: Connection<DbConn>,
+    config: &State<SharedConfig>,
+    passwords: &State<Arc<PasswordService>>,
+    email: &State<EmailService>,
+    admin: Option<User>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let mut new_user = new_user.into_inner();
+
+    let violations = passwords
+        .validate(&new_user.password, &[&new_user.username, &new_user.email])
+        .await;
+    if !violations.is_empty() {
+        return Err(password_policy_error(&violations));
+    }
+
+    let user = match new_user.invite_code.take() {
+        Some(code) => UserRepository::create_with_invite(&mut db, new_user, &code)
+            .await
+            .map_err(user_write_error)?
+            .ok_or_else(|| Custom(Status::BadRequest, json!("Invalid or expired invite code")))?,
+        None => {
+            // Server admins may still create accounts without an invite
+            let is_admin = match admin {
+                Some(admin) => WorkspaceRepository::is_server_admin(&mut db, admin.id)
+                    .await
+                    .map_err(|e| server_error(e.into()))?,
+                None => false,
+            };
+
+            if config.current().require_invite && !is_admin {
+                return Err(Custom(Status::BadRequest, json!("Invite code required")));
+            }
+
+            UserRepository::create(&mut db, new_user)
+                .await
+                .map_err(user_write_error)?
+        }
+    };
+
+    // The account is usable even if the verification email cannot be queued
+    let public_url = config.current().public_url.clone();
+    if let Err(e) = email_links::send_verification(&mut db, email, &public_url, &user).await {
+        rocket::error!(
+            "Failed to send a verification email to user {}: {}",
+            user.id,
+            e
+        );
+    }
+    Ok(Custom(Status::Ok, json!(user)))
+}
+
+/// Emails the caller a new link confirming their email address.
+#[post("/me/verification")]
+pub async fn send_my_verification(
+    mut db: Connection<DbConn>,
+    user: User,
+    config: &State<SharedConfig>,
+    email: &State<EmailService>,
 ) -> Result<Custom<Value>, Custom<Value>> {
-    UserRepository::create(&mut db, new_user.into_inner())
+    let verified = EmailTokenRepository::is_verified(&mut db, user.id, &user.email)
         .await
-        .map(|user| Custom(Status::Ok, json!(user)))
-        .map_err(|e| server_error(e.into()))
+        .map_err(|e| server_error(e.into()))?;
+    if verified {
+        return Err(Custom(
+            Status::BadRequest,
+            json!("The email address is already verified"),
+        ));
+    }
+
+    let public_url = config.current().public_url.clone();
+    email_links::send_verification(&mut db, email, &public_url, &user)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(Status::Ok, json!("Verification email sent")))
+}
+
+/// Confirms an email address with the token of a verification link. Like
+/// following the link, it needs no login.
+#[post("/email/verify", data = "<request>")]
+pub async fn verify_email(
+    request: Json<VerifyEmailRequest>,
+    mut db: Connection<DbConn>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    match email_links::verify_email(&mut db, &request.token).await {
+        Ok(Some(_)) => Ok(Custom(Status::Ok, json!("Email address verified"))),
+        Ok(None) => Err(Custom(
+            Status::BadRequest,
+            json!("The verification link is invalid or has expired"),
+        )),
+        Err(e) => Err(server_error(e.into())),
+    }
+}
+
+/// Emails a password reset link to the account with the given address. The
+/// answer is the same whether or not the address is registered, and requests
+/// beyond the limits per address and per IP are refused with `429`.
+#[post("/password/forgot", data = "<request>")]
+pub async fn forgot_password(
+    request: Json<ForgotPasswordRequest>,
+    ip: Option<IpAddr>,
+    mut db: Connection<DbConn>,
+    config: &State<SharedConfig>,
+    email: &State<EmailService>,
+    throttle: &State<PasswordResetThrottle>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    if let Err(retry_after) = throttle.check(&request.email, ip, Instant::now()) {
+        return Err(Custom(
+            Status::TooManyRequests,
+            json!(format!(
+                "Too many password reset requests, try again in {} seconds",
+                retry_after.as_secs().max(1)
+            )),
+        ));
+    }
+
+    let public_url = config.current().public_url.clone();
+    email_links::send_password_reset(&mut db, email, &public_url, &request.email)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(
+        Status::Ok,
+        json!("If the address is registered, a reset link was sent to it"),
+    ))
+}
+
+/// Sets a new password with the token of a password reset link and ends the
+/// user's web sessions, so whoever knew the old password is logged out.
+#[post("/password/reset", data = "<request>")]
+pub async fn reset_password(
+    request: Json<ResetPasswordRequest>,
+    mut db: Connection<DbConn>,
+    mut cache: Connection<CacheConn>,
+    passwords: &State<Arc<PasswordService>>,
+    users: &State<UserCache>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let request = request.into_inner();
+    let user_id =
+        match email_links::reset_password(&mut db, passwords, &request.token, &request.password)
+            .await
+        {
+            Ok(user_id) => user_id,
+            Err(e @ PasswordResetError::InvalidToken) => {
+                return Err(Custom(Status::BadRequest, json!(e.to_string())))
+            }
+            Err(PasswordResetError::Password(PasswordChangeError::Policy(violations))) => {
+                return Err(password_policy_error(&violations))
+            }
+            Err(PasswordResetError::Password(e)) => return Err(server_error(e.into())),
+        };
+    users.invalidate(user_id);
+    sessions::end_all(&mut cache, user_id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    Ok(Custom(Status::Ok, json!("Password reset")))
 }
 
 #[put("/<id>", data = "<user>")]
 pub async fn update_user(
     id: i32,
     user: Json<User>,
+    caller: User,
     mut db: Connection<DbConn>,
+    users: &State<UserCache>,
 ) -> Result<Custom<Value>, Custom<Value>> {
-    UserRepository::update(&mut db, id, &user.into_inner())
-        .await
+    require_self_or_admin(&mut db, &caller, id).await?;
+
+    let result = UserRepository::update(&mut db, id, &user.into_inner()).await;
+    users.invalidate(id);
+    result
         .map(|user| Custom(Status::Ok, json!(user)))
-        .map_err(|e| server_error(e.into()))
+        .map_err(user_write_error)
 }
 
 #[delete("/<id>")]
 pub async fn delete_user(
     id: i32,
+    caller: User,
+    mut db: Connection<DbConn>,
+    config: &State<SharedConfig>,
+    exports: &State<Arc<ExportService>>,
+    storage: &State<Arc<dyn Storage>>,
+    users: &State<UserCache>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_self_or_admin(&mut db, &caller, id).await?;
+
+    let result = delete_account(&mut db, id, &config.current(), exports, storage.as_ref()).await;
+    users.invalidate(id);
+    result
+}
+
+#[delete("/me")]
+pub async fn delete_me(
+    user: User,
+    mut db: Connection<DbConn>,
+    config: &State<SharedConfig>,
+    exports: &State<Arc<ExportService>>,
+    storage: &State<Arc<dyn Storage>>,
+    users: &State<UserCache>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let result = delete_account(
+        &mut db,
+        user.id,
+        &config.current(),
+        exports,
+        storage.as_ref(),
+    )
+    .await;
+    users.invalidate(user.id);
+    result
+}
+
+/// Deletes an account, its messages according to the configured deletion mode,
+/// and any data export or avatar left in storage.
+async fn delete_account(
+    db: &mut Connection<DbConn>,
+    id: i32,
+    config: &ServerConfig,
+    exports: &ExportService,
+    storage: &dyn Storage,
+) -> Result<Custom<Value>, Custom<Value>> {
+    match account::delete_account(db, id, config.user_deletion_mode, exports, storage).await {
+        Ok(result) => Ok(Custom(Status::Ok, json!(result))),
+        Err(e @ DeleteAccountError::Placeholder) => {
+            Err(Custom(Status::BadRequest, json!(e.to_string())))
+        }
+        Err(DeleteAccountError::Database(e)) => Err(server_error(e.into())),
+    }
+}
+
+/// Rejects callers that are not server admins, naming what only they may do.
+async fn require_server_admin(
+    db: &mut diesel_async::AsyncPgConnection,
+    caller: &User,
+    action: &str,
+) -> Result<(), Custom<Value>> {
+    let is_admin = WorkspaceRepository::is_server_admin(db, caller.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !is_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!(format!("Only server admins can {}", action)),
+        ));
+    }
+    Ok(())
+}
+
+/// Lists every user as CSV, without password hashes. Only server admins may
+/// export the users.
+#[get("/export")]
+pub async fn export_users(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<CsvExport<String>, Custom<Value>> {
+    require_server_admin(&mut db, &user, "export users").await?;
+
+    let users = UserRepository::find_all(&mut db)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let mut body = USER_CSV_HEADER.to_string();
+    body.extend(users.iter().map(User::to_csv_row));
+
+    Ok(CsvExport::new(body, users.len() as i64, "users.csv"))
+}
+
+/// Creates users from a CSV file with `username`, `email` and `password`
+/// columns. Only server admins may import users.
+///
+/// Every row is checked and reported with its errors; rows with errors are
+/// skipped. With `dry_run`, the rows are only checked, so the file can be
+/// previewed before anything is created.
+#[post("/import?<dry_run>", data = "<file>")]
+pub async fn import_users(
+    dry_run: Option<bool>,
+    file: Data<'_>,
     mut db: Connection<DbConn>,
+    user: User,
+    passwords: &State<Arc<PasswordService>>,
 ) -> Result<Custom<Value>, Custom<Value>> {
-    UserRepository::delete(&mut db, id)
+    require_server_admin(&mut db, &user, "import users").await?;
+
+    let file = file
+        .open(MAX_IMPORT_SIZE_MIB.mebibytes())
+        .into_string()
         .await
-        .map(|result| Custom(Status::Ok, json!(result)))
-        .map_err(|e| server_error(e.into()))
+        .map_err(|e| Custom(Status::BadRequest, json!(format!("Unreadable file: {}", e))))?;
+    if !file.is_complete() {
+        return Err(Custom(
+            Status::PayloadTooLarge,
+            json!(format!(
+                "Import files can be at most {} MiB",
+                MAX_IMPORT_SIZE_MIB
+            )),
+        ));
+    }
+
+    UserImportService::new(passwords.inner().clone())
+        .import(&mut db, &file, dry_run.unwrap_or(false), user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?
+        .map(|report| Custom(Status::Ok, json!(report)))
+        .map_err(|e| Custom(Status::BadRequest, json!(e.to_string())))
+}
+
+/// Rejects requests concerning another user unless the caller is a server
+/// admin, and requests for users that do not exist.
+async fn require_self_or_admin(
+    db: &mut diesel_async::AsyncPgConnection,
+    caller: &User,
+    id: i32,
+) -> Result<(), Custom<Value>> {
+    if caller.id != id {
+        let is_admin = WorkspaceRepository::is_server_admin(db, caller.id)
+            .await
+            .map_err(|e| server_error(e.into()))?;
+        if !is_admin {
+            return Err(Custom(
+                Status::Forbidden,
+                json!("Only server admins can access other users"),
+            ));
+        }
+    }
+
+    UserRepository::find_by_id(db, id)
+        .await
+        .map(drop)
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            e => server_error(e.into()),
+        })
+}
+
+/// Message statistics of a user: totals per type, attachments sent, the time
+/// of the last message and daily counts for the last `days` days.
+#[get("/<id>/stats?<days>")]
+pub async fn get_user_stats(
+    id: i32,
+    days: Option<u32>,
+    caller: User,
+    mut db: ReadConn,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_self_or_admin(&mut db, &caller, id).await?;
+
+    let days = days.unwrap_or(DEFAULT_STATS_DAYS).clamp(1, MAX_STATS_DAYS);
+    let first_day = Utc::now().date_naive() - chrono::Duration::days(days as i64 - 1);
+    let since = first_day.and_hms_opt(0, 0, 0).unwrap_or_default();
+
+    let by_type = MessageRepository::count_by_type(&mut db, id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let counted = MessageRepository::count_by_day(&mut db, id, since)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    // Include days without messages so clients can chart the counts directly
+    let daily = first_day
+        .iter_days()
+        .take(days as usize)
+        .map(|day| DailyMessageCount {
+            day,
+            count: counted
+                .iter()
+                .find(|counted| counted.day == day)
+                .map_or(0, |counted| counted.count),
+        })
+        .collect::<Vec<_>>();
+    let last_message_at = MessageRepository::last_sent_at(&mut db, id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    let count = |kind: MessageType| {
+        by_type
+            .iter()
+            .find(|(message_type, _)| *message_type == kind)
+            .map_or(0, |(_, count)| *count)
+    };
+    let (text, actions, files, images) = (
+        count(MessageType::Text),
+        count(MessageType::Action),
+        count(MessageType::File),
+        count(MessageType::Image),
+    );
+
+    Ok(Custom(
+        Status::Ok,
+        json!({
+            "user_id": id,
+            "total_messages": text + actions + files + images,
+            "text_messages": text,
+            "action_messages": actions,
+            "attachments_sent": files + images,
+            "files_sent": files,
+            "images_sent": images,
+            "last_message_at": last_message_at,
+            "days": days,
+            "daily": daily,
+        }),
+    ))
+}
+
+/// Parses a `YYYY-MM-DD` date of a query, as the start of that day.
+fn parse_day(name: &str, value: Option<&str>) -> Result<Option<NaiveDateTime>, Custom<Value>> {
+    value
+        .map(|value| {
+            value
+                .parse::<NaiveDate>()
+                .map(|day| day.and_hms_opt(0, 0, 0).unwrap_or_default())
+                .map_err(|_| {
+                    Custom(
+                        Status::BadRequest,
+                        json!(format!("{} must be a date like 2025-04-01", name)),
+                    )
+                })
+        })
+        .transpose()
+}
+
+/// Messages a user sent, newest first, `limit` at a time. The next page is
+/// fetched with `before` set to the returned `next_before`; `since` and `until`
+/// limit the history to the days from `since` up to and including `until`.
+///
+/// Users can read their own history. Server admins can read anyone's, but the
+/// stored content of messages in workspaces the admin is not a member of is
+/// withheld (see [`HistoryEntry`]).
+#[get("/<id>/messages?<before>&<limit>&<since>&<until>")]
+pub async fn get_user_messages(
+    id: i32,
+    before: Option<i32>,
+    limit: Option<i64>,
+    since: Option<&str>,
+    until: Option<&str>,
+    caller: User,
+    mut db: ReadConn,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_self_or_admin(&mut db, &caller, id).await?;
+
+    let filter = MessageFilter {
+        sender_id: Some(id),
+        since: parse_day("since", since)?,
+        until: parse_day("until", until)?.map(|day| day + chrono::Duration::days(1)),
+        ..MessageFilter::default()
+    };
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let messages = MessageRepository::find_filtered_before(&mut db, &filter, before, limit)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    // Only members of a workspace hold its keys, so only they get its ciphertext
+    let readable: Option<HashSet<i32>> = if caller.id == id {
+        None
+    } else {
+        let workspaces = WorkspaceRepository::find_for_user(&mut db, caller.id)
+            .await
+            .map_err(|e| server_error(e.into()))?;
+        Some(workspaces.iter().map(|workspace| workspace.id).collect())
+    };
+    let next_before = (messages.len() as i64 == limit)
+        .then(|| messages.last().map(|message| message.id))
+        .flatten();
+    let messages = messages
+        .into_iter()
+        .map(|message| {
+            let is_readable = readable
+                .as_ref()
+                .is_none_or(|readable| readable.contains(&message.workspace_id));
+            HistoryEntry::new(message, is_readable)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Custom(
+        Status::Ok,
+        json!({
+            "user_id": id,
+            "messages": messages,
+            "next_before": next_before,
+        }),
+    ))
+}
+
+/// Web sessions of a user that have not expired, identified by a token prefix.
+#[get("/<id>/sessions")]
+pub async fn get_user_sessions(
+    id: i32,
+    caller: User,
+    mut db: ReadConn,
+    mut cache: Connection<CacheConn>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_self_or_admin(&mut db, &caller, id).await?;
+
+    let now = Utc::now().naive_utc();
+    let sessions = sessions::list(&mut cache, id)
+        .await
+        .map_err(|e| server_error(e.into()))?
+        .into_iter()
+        .map(|session| {
+            let remaining = chrono::Duration::seconds(session.ttl_secs as i64);
+            json!({
+                "token_prefix": session.token.chars().take(TOKEN_PREFIX_LEN).collect::<String>(),
+                "logged_in_at": now + remaining - chrono::Duration::seconds(SESSION_TTL_SECS as i64),
+                "expires_at": now + remaining,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Custom(Status::Ok, json!(sessions)))
+}
+
+/// Chat clients the user is connected with over TCP.
+#[get("/<id>/connections")]
+pub async fn get_user_connections(
+    id: i32,
+    caller: User,
+    mut db: ReadConn,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_self_or_admin(&mut db, &caller, id).await?;
+
+    let clients = clients.lock().await;
+    let mut connections = clients
+        .iter()
+        .filter(|(_, connection)| {
+            matches!(connection.auth_state, AuthState::Authenticated { user_id, .. } if user_id == id)
+        })
+        .map(|(client_id, connection)| {
+            json!({
+                "client_id": client_id,
+                "peer_addr": connection.peer_addr.to_string(),
+                "workspace_id": connection.workspace_id,
+                "connected_at": connection.connected_at,
+                "bytes_read": connection.transfer.bytes_read(),
+                "bytes_written": connection.transfer.bytes_written(),
+            })
+        })
+        .collect::<Vec<_>>();
+    connections.sort_by_key(|connection| connection["client_id"].as_u64());
+
+    Ok(Custom(Status::Ok, json!(connections)))
+}
+
+/// Attachment storage of a user: the bytes stored and the quota, which is
+/// null if there is none.
+#[get("/<id>/usage")]
+pub async fn get_user_usage(
+    id: i32,
+    caller: User,
+    mut db: ReadConn,
+    config: &State<SharedConfig>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_self_or_admin(&mut db, &caller, id).await?;
+    storage_usage(&mut db, id, config).await
+}
+
+/// Attachment storage of the logged in user.
+#[get("/me/usage")]
+pub async fn get_my_usage(
+    user: User,
+    mut db: ReadConn,
+    config: &State<SharedConfig>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    storage_usage(&mut db, user.id, config).await
+}
+
+async fn storage_usage(
+    db: &mut diesel_async::AsyncPgConnection,
+    user_id: i32,
+    config: &SharedConfig,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let used = MessageRepository::attachment_bytes(db, user_id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(
+        Status::Ok,
+        json!({
+            "user_id": user_id,
+            "attachment_bytes": used,
+            "quota_bytes": config.current().attachment_quota,
+        }),
+    ))
+}
+
+#[derive(Responder)]
+pub enum AvatarResponse {
+    Redirect(Redirect),
+    Image((ContentType, Vec<u8>)),
+}
+
+#[get("/<id>/avatar")]
+pub async fn get_avatar(
+    id: i32,
+    storage: &State<Arc<dyn Storage>>,
+) -> Result<AvatarResponse, Custom<Value>> {
+    let key = avatar_key(id);
+    let url = storage
+        .presigned_url(&key, AVATAR_URL_EXPIRY)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if let Some(url) = url {
+        return Ok(AvatarResponse::Redirect(Redirect::temporary(url)));
+    }
+
+    match storage.get(&key).await {
+        Ok(image) => {
+            let content_type = infer::get(&image)
+                .and_then(|kind| ContentType::parse_flexible(kind.mime_type()))
+                .unwrap_or(ContentType::Binary);
+            Ok(AvatarResponse::Image((content_type, image)))
+        }
+        Err(e) if e.is::<ObjectNotFound>() => Err(Custom(
+            Status::NotFound,
+            json!(format!("User {} has no avatar", id)),
+        )),
+        Err(e) => Err(server_error(e.into())),
+    }
+}
+
+#[put("/me/avatar", data = "<image>")]
+pub async fn set_avatar(
+    user: User,
+    image: Data<'_>,
+    storage: &State<Arc<dyn Storage>>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let image = image
+        .open(MAX_AVATAR_SIZE_MIB.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !image.is_complete() {
+        return Err(Custom(
+            Status::PayloadTooLarge,
+            json!(format!(
+                "Avatars can be at most {} MiB",
+                MAX_AVATAR_SIZE_MIB
+            )),
+        ));
+    }
+    if !infer::is_image(&image) {
+        return Err(Custom(
+            Status::UnsupportedMediaType,
+            json!("Avatars must be images"),
+        ));
+    }
+
+    storage
+        .put(&avatar_key(user.id), &image)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(
+        Status::Ok,
+        json!(format!("/users/{}/avatar", user.id)),
+    ))
+}
+
+#[delete("/me/avatar")]
+pub async fn delete_avatar(
+    user: User,
+    storage: &State<Arc<dyn Storage>>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    storage
+        .delete(&avatar_key(user.id))
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(Status::Ok, json!("Avatar removed")))
+}
+
+/// Changes the caller's password. The current password is required so a
+/// stolen session cannot lock the owner out.
+#[put("/me/password", data = "<request>")]
+pub async fn change_password(
+    request: Json<ChangePasswordRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    passwords: &State<Arc<PasswordService>>,
+    users: &State<UserCache>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let request = request.into_inner();
+    let result = passwords
+        .change(
+            &mut db,
+            user.id,
+            &request.current_password,
+            &request.new_password,
+        )
+        .await;
+    users.invalidate(user.id);
+    match result {
+        Ok(()) => Ok(Custom(Status::Ok, json!("Password changed"))),
+        Err(e @ PasswordChangeError::WrongPassword) => {
+            Err(Custom(Status::Forbidden, json!(e.to_string())))
+        }
+        Err(PasswordChangeError::Policy(violations)) => Err(password_policy_error(&violations)),
+        Err(PasswordChangeError::Database(e)) => Err(server_error(e.into())),
+        Err(PasswordChangeError::Hash(e)) => Err(server_error(e.into())),
+    }
+}
+
+#[get("/me/preferences")]
+pub async fn get_preferences(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let preferences = PreferenceRepository::find(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let locale = preferences
+        .map(|preferences| preferences.locale)
+        .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_string());
+    Ok(Custom(
+        Status::Ok,
+        json!({ "locale": locale, "available_locales": i18n::locales() }),
+    ))
+}
+
+/// Sets the locale of the texts the server sends the user. Connected chat
+/// sessions switch immediately.
+#[put("/me/preferences", data = "<request>")]
+pub async fn update_preferences(
+    request: Json<UpdatePreferencesRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let Some(locale) = i18n::resolve(&request.locale) else {
+        return Err(Custom(
+            Status::BadRequest,
+            json!(format!(
+                "Unsupported locale '{}', available locales are: {}",
+                request.locale,
+                i18n::locales().join(", ")
+            )),
+        ));
+    };
+
+    let preferences = PreferenceRepository::set_locale(&mut db, user.id, locale)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    let mut clients = clients.lock().await;
+    for connection in clients.values_mut() {
+        if connection.user_id == Some(user.id) {
+            connection.locale = locale.to_string();
+        }
+    }
+    Ok(Custom(Status::Ok, json!(preferences)))
+}
+
+#[get("/me/dnd")]
+pub async fn get_do_not_disturb(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let settings = DoNotDisturbRepository::find(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let active = settings
+        .as_ref()
+        .map(DndSettings::from)
+        .unwrap_or_default()
+        .is_active(Utc::now().time());
+    Ok(Custom(
+        Status::Ok,
+        json!({ "settings": settings, "active": active }),
+    ))
+}
+
+/// Switches do-not-disturb mode on or off and sets its daily schedule (UTC).
+/// While it is active, notifications are kept in the inbox instead of being
+/// pushed. Connected chat sessions follow immediately.
+#[put("/me/dnd", data = "<request>")]
+pub async fn update_do_not_disturb(
+    request: Json<UpdateDoNotDisturbRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let schedule = match (request.starts_at, request.ends_at) {
+        (Some(starts_at), Some(ends_at)) if starts_at != ends_at => Some((starts_at, ends_at)),
+        (None, None) => None,
+        _ => {
+            return Err(Custom(
+                Status::BadRequest,
+                json!("A schedule needs both starts_at and ends_at, and they must differ"),
+            ))
+        }
+    };
+
+    let settings = DoNotDisturbRepository::set(&mut db, user.id, request.enabled, schedule)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+
+    let mut clients = clients.lock().await;
+    for connection in clients.values_mut() {
+        if connection.user_id == Some(user.id) {
+            connection.do_not_disturb = DndSettings::from(&settings);
+        }
+    }
+    Ok(Custom(Status::Ok, json!(settings)))
+}
+
+/// Lists the statuses users have set, optionally only of a workspace's
+/// members. Users without a status are available.
+#[get("/statuses?<workspace_id>")]
+pub async fn get_statuses(
+    workspace_id: Option<i32>,
+    mut db: ReadConn,
+) -> Result<Custom<Value>, Custom<Value>> {
+    match workspace_id {
+        Some(workspace_id) => UserStatusRepository::find_by_workspace(&mut db, workspace_id).await,
+        None => UserStatusRepository::find_all(&mut db).await,
+    }
+    .map(|statuses| Custom(Status::Ok, json!(statuses)))
+    .map_err(|e| server_error(e.into()))
+}
+
+#[get("/me/status")]
+pub async fn get_status(
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let status = UserStatusRepository::find(&mut db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(
+        Status::Ok,
+        match status {
+            Some(status) => json!(status),
+            None => json!({ "user_id": user.id, "availability": "available", "text": null }),
+        },
+    ))
+}
+
+/// Sets the caller's status and announces it to the connected members of
+/// their workspaces.
+#[put("/me/status", data = "<request>")]
+pub async fn update_status(
+    request: Json<UpdateStatusRequest>,
+    user: User,
+    pool: &State<Arc<DbPool>>,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let request = request.into_inner();
+    let text = presence::normalize_text(request.text.as_deref())
+        .map_err(|e| Custom(Status::BadRequest, json!(e.to_string())))?;
+
+    let status = PresenceService::new(pool.inner().clone(), clients.inner().clone())
+        .set_status(user.id, request.availability, text.as_deref())
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    Ok(Custom(Status::Ok, json!(status)))
+}
+
+#[derive(Responder)]
+pub enum ExportResponse {
+    File(NamedFile, Header<'static>),
+    Status(Custom<Value>),
+}
+
+#[post("/me/export")]
+pub async fn request_export(
+    user: User,
+    exports: &State<Arc<ExportService>>,
+    flags: &State<FeatureFlags>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    if !flags.is_enabled(feature_flags::DATA_EXPORT, Some(user.id)) {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Data exports are not available"),
+        ));
+    }
+
+    let status = exports.start(user.id).await;
+    Ok(Custom(Status::Accepted, json!(status)))
+}
+
+#[get("/me/export")]
+pub async fn get_export(
+    user: User,
+    exports: &State<Arc<ExportService>>,
+) -> Result<ExportResponse, Custom<Value>> {
+    match exports.status(user.id).await {
+        Some(ExportStatus::Ready { path, .. }) => {
+            let file = NamedFile::open(&path)
+                .await
+                .map_err(|e| server_error(e.into()))?;
+            let disposition = Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}-export.json\"", user.username),
+            );
+            Ok(ExportResponse::File(file, disposition))
+        }
+        Some(status @ ExportStatus::Pending { .. }) => Ok(ExportResponse::Status(Custom(
+            Status::Accepted,
+            json!(status),
+        ))),
+        Some(status @ ExportStatus::Failed { .. }) => {
+            Err(Custom(Status::InternalServerError, json!(status)))
+        }
+        None => Err(Custom(
+            Status::NotFound,
+            json!("No export requested, POST /users/me/export first"),
+        )),
+    }
 }
 
 #[options("/<_..>")]
@@ -68,8 +1082,35 @@ pub fn routes() -> Vec<rocket::Route> {
         get_users,
         get_user,
         create_user,
+        send_my_verification,
+        verify_email,
+        forgot_password,
+        reset_password,
         update_user,
         delete_user,
+        delete_me,
+        export_users,
+        import_users,
+        get_avatar,
+        set_avatar,
+        delete_avatar,
+        change_password,
+        check_password,
+        get_preferences,
+        update_preferences,
+        get_do_not_disturb,
+        update_do_not_disturb,
+        get_statuses,
+        get_status,
+        update_status,
+        request_export,
+        get_export,
+        get_user_stats,
+        get_user_messages,
+        get_user_sessions,
+        get_user_connections,
+        get_user_usage,
+        get_my_usage,
         options
     ]
 }
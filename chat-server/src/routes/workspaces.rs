@@ -0,0 +1,273 @@
+use crate::errors::rocket_server_errors::{not_found_error, server_error};
+use crate::i18n;
+use crate::models::user::User;
+use crate::models::workspace::{
+    NewWorkspace, NewWorkspaceMember, DEFAULT_WORKSPACE_SLUG, WORKSPACE_ADMIN_ROLE,
+    WORKSPACE_MEMBER_ROLE, WORKSPACE_MODERATOR_ROLE,
+};
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::outbound_queue::OutboundQueue;
+use crate::types::Clients;
+use crate::utils::db_connection::{DbConn, ReadConn};
+use chat_common::Message;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{delete, get, options, post, put, routes, State};
+use rocket_db_pools::Connection;
+
+#[derive(serde::Deserialize)]
+pub struct AddMemberRequest {
+    pub user_id: i32,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AnnouncementOnlyRequest {
+    pub enabled: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateRoleRequest {
+    pub role: String,
+}
+
+/// Fails with `403` unless the user administers the workspace or the server.
+async fn require_workspace_admin(
+    db: &mut diesel_async::AsyncPgConnection,
+    workspace_id: i32,
+    user: &User,
+) -> Result<(), Custom<Value>> {
+    let is_admin = WorkspaceRepository::is_admin(db, workspace_id, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    let is_server_admin = WorkspaceRepository::is_server_admin(db, user.id)
+        .await
+        .map_err(|e| server_error(e.into()))?;
+    if !is_admin && !is_server_admin {
+        return Err(Custom(
+            Status::Forbidden,
+            json!("Only workspace admins can change this"),
+        ));
+    }
+    Ok(())
+}
+
+#[get("/")]
+pub async fn get_workspaces(mut db: ReadConn, _user: User) -> Result<Custom<Value>, Custom<Value>> {
+    WorkspaceRepository::find_all(&mut db)
+        .await
+        .map(|workspaces| Custom(Status::Ok, json!(workspaces)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[get("/<id>")]
+pub async fn get_workspace(
+    id: i32,
+    mut db: Connection<DbConn>,
+    _user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    WorkspaceRepository::find_by_id(&mut db, id)
+        .await
+        .map(|workspace| Custom(Status::Ok, json!(workspace)))
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })
+}
+
+/// Creates a workspace whose first member and admin is its creator.
+#[post("/", data = "<new_workspace>")]
+pub async fn create_workspace(
+    new_workspace: Json<NewWorkspace>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    WorkspaceRepository::create_with_admin(&mut db, new_workspace.into_inner(), user.id)
+        .await
+        .map(|workspace| Custom(Status::Created, json!(workspace)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Deletes a workspace. The default workspace, whose admins administer the
+/// server, cannot be deleted.
+#[delete("/<id>")]
+pub async fn delete_workspace(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_workspace_admin(&mut db, id, &user).await?;
+    let workspace = WorkspaceRepository::find_by_id(&mut db, id)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })?;
+    if workspace.slug == DEFAULT_WORKSPACE_SLUG {
+        return Err(Custom(
+            Status::Conflict,
+            json!("The default workspace cannot be deleted"),
+        ));
+    }
+
+    WorkspaceRepository::delete(&mut db, id)
+        .await
+        .map(|result| Custom(Status::Ok, json!(result)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Makes a workspace announcement-only, where only admins and moderators can
+/// post, or lets every member post again. Connected members are told.
+#[put("/<id>/announcement-only", data = "<request>")]
+pub async fn update_announcement_only(
+    id: i32,
+    request: Json<AnnouncementOnlyRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+    clients: &State<Clients>,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_workspace_admin(&mut db, id, &user).await?;
+
+    let workspace = WorkspaceRepository::set_announcement_only(&mut db, id, request.enabled)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })?;
+
+    let key = if workspace.announcement_only {
+        "workspace-announcement-only-on"
+    } else {
+        "workspace-announcement-only-off"
+    };
+    // The clients are only locked to pick the recipients, so a member whose
+    // queue is full does not hold up connections joining or leaving
+    let recipients: Vec<(String, OutboundQueue)> = clients
+        .lock()
+        .await
+        .values()
+        .filter(|connection| {
+            connection.is_authenticated() && connection.workspace_id == Some(workspace.id)
+        })
+        .map(|connection| (connection.locale.clone(), connection.outbound.clone()))
+        .collect();
+    for (locale, outbound) in recipients {
+        let notice = i18n::text(
+            &locale,
+            key,
+            &[("workspace", workspace.name.as_str().into())],
+        );
+        // A connection that went away is cleaned up by its own handler
+        let _ = outbound.push_or_defer(Message::System(notice)).await;
+    }
+    Ok(Custom(Status::Ok, json!(workspace)))
+}
+
+#[get("/<id>/members")]
+pub async fn get_members(
+    id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_workspace_admin(&mut db, id, &user).await?;
+
+    WorkspaceRepository::find_members(&mut db, id)
+        .await
+        .map(|users| Custom(Status::Ok, json!(users)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[post("/<id>/members", data = "<member>")]
+pub async fn add_member(
+    id: i32,
+    member: Json<AddMemberRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    require_workspace_admin(&mut db, id, &user).await?;
+
+    let new_member = NewWorkspaceMember {
+        workspace_id: id,
+        user_id: member.user_id,
+    };
+
+    WorkspaceRepository::add_member(&mut db, new_member)
+        .await
+        .map(|member| Custom(Status::Created, json!(member)))
+        .map_err(|e| server_error(e.into()))
+}
+
+/// Changes the role of a member to `admin`, `moderator` or `member`.
+#[put("/<id>/members/<user_id>/role", data = "<request>")]
+pub async fn update_member_role(
+    id: i32,
+    user_id: i32,
+    request: Json<UpdateRoleRequest>,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    let role = request.role.as_str();
+    if ![
+        WORKSPACE_ADMIN_ROLE,
+        WORKSPACE_MODERATOR_ROLE,
+        WORKSPACE_MEMBER_ROLE,
+    ]
+    .contains(&role)
+    {
+        return Err(Custom(
+            Status::BadRequest,
+            json!(format!(
+                "Unknown role '{}', expected admin, moderator or member",
+                role
+            )),
+        ));
+    }
+    require_workspace_admin(&mut db, id, &user).await?;
+
+    WorkspaceRepository::set_role(&mut db, id, user_id, role)
+        .await
+        .map(|member| Custom(Status::Ok, json!(member)))
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => not_found_error(e.into()),
+            _ => server_error(e.into()),
+        })
+}
+
+/// Removes a member. Members can leave on their own, everyone else needs to
+/// administer the workspace.
+#[delete("/<id>/members/<user_id>")]
+pub async fn remove_member(
+    id: i32,
+    user_id: i32,
+    mut db: Connection<DbConn>,
+    user: User,
+) -> Result<Custom<Value>, Custom<Value>> {
+    if user_id != user.id {
+        require_workspace_admin(&mut db, id, &user).await?;
+    }
+
+    WorkspaceRepository::remove_member(&mut db, id, user_id)
+        .await
+        .map(|result| Custom(Status::Ok, json!(result)))
+        .map_err(|e| server_error(e.into()))
+}
+
+#[options("/<_..>")]
+pub fn options() -> &'static str {
+    ""
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_workspaces,
+        get_workspace,
+        create_workspace,
+        delete_workspace,
+        update_announcement_only,
+        get_members,
+        add_member,
+        update_member_role,
+        remove_member,
+        options
+    ]
+}
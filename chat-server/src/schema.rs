@@ -1,5 +1,105 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    bots (user_id) {
+        user_id -> Int4,
+        owner_id -> Int4,
+        workspace_id -> Int4,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        webhook_url -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    client_certificates (fingerprint) {
+        #[max_length = 64]
+        fingerprint -> Varchar,
+        user_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    do_not_disturb (user_id) {
+        user_id -> Int4,
+        enabled -> Bool,
+        starts_at -> Nullable<Time>,
+        ends_at -> Nullable<Time>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    email_tokens (token_sha256) {
+        #[max_length = 64]
+        token_sha256 -> Varchar,
+        user_id -> Int4,
+        #[max_length = 20]
+        purpose -> Varchar,
+        #[max_length = 255]
+        email -> Varchar,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    email_verifications (user_id) {
+        user_id -> Int4,
+        #[max_length = 255]
+        email -> Varchar,
+        verified_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    feature_flags (name) {
+        #[max_length = 64]
+        name -> Varchar,
+        enabled -> Bool,
+        rollout_percentage -> Int4,
+        description -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    invitation_redemptions (invitation_id, user_id) {
+        invitation_id -> Int4,
+        user_id -> Int4,
+        redeemed_at -> Timestamp,
+        first_login_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    invitations (id) {
+        id -> Int4,
+        #[max_length = 32]
+        code -> Varchar,
+        created_by -> Int4,
+        workspace_id -> Nullable<Int4>,
+        max_uses -> Int4,
+        uses -> Int4,
+        expires_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    message_revisions (id) {
+        id -> Int4,
+        message_id -> Int4,
+        content -> Nullable<Text>,
+        #[max_length = 255]
+        file_name -> Nullable<Varchar>,
+        edited_by -> Nullable<Int4>,
+        edited_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     messages (id) {
         id -> Int4,
@@ -10,6 +110,92 @@ diesel::table! {
         file_name -> Nullable<Varchar>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        workspace_id -> Int4,
+        #[max_length = 64]
+        sha256 -> Nullable<Varchar>,
+        edited_at -> Nullable<Timestamp>,
+        seq -> Int8,
+        attachment_size -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 20]
+        kind -> Varchar,
+        actor_id -> Nullable<Int4>,
+        message_id -> Nullable<Int4>,
+        content -> Text,
+        read_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    poll_votes (poll_id, user_id) {
+        poll_id -> Int4,
+        user_id -> Int4,
+        option_index -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    polls (id) {
+        id -> Int4,
+        workspace_id -> Int4,
+        creator_id -> Int4,
+        question -> Text,
+        options -> Array<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    reports (id) {
+        id -> Int4,
+        workspace_id -> Int4,
+        message_id -> Nullable<Int4>,
+        reported_user_id -> Nullable<Int4>,
+        reporter_id -> Nullable<Int4>,
+        reason -> Text,
+        #[max_length = 20]
+        status -> Varchar,
+        resolved_by -> Nullable<Int4>,
+        resolved_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_onboarding (user_id) {
+        user_id -> Int4,
+        welcomed_at -> Timestamp,
+        #[max_length = 64]
+        terms_sha256 -> Nullable<Varchar>,
+        terms_accepted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_preferences (user_id) {
+        user_id -> Int4,
+        #[max_length = 16]
+        locale -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_statuses (user_id) {
+        user_id -> Int4,
+        #[max_length = 16]
+        availability -> Varchar,
+        #[max_length = 100]
+        text -> Nullable<Varchar>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -27,4 +213,89 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(messages, users,);
+diesel::table! {
+    workspace_keys (id) {
+        id -> Int4,
+        workspace_id -> Int4,
+        #[max_length = 64]
+        key_id -> Varchar,
+        wrapped_key -> Text,
+        nonce -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    workspace_members (workspace_id, user_id) {
+        workspace_id -> Int4,
+        user_id -> Int4,
+        #[max_length = 20]
+        role -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    workspaces (id) {
+        id -> Int4,
+        #[max_length = 100]
+        name -> Varchar,
+        #[max_length = 50]
+        slug -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        announcement_only -> Bool,
+        last_message_seq -> Int8,
+    }
+}
+
+diesel::joinable!(bots -> workspaces (workspace_id));
+diesel::joinable!(client_certificates -> users (user_id));
+diesel::joinable!(do_not_disturb -> users (user_id));
+diesel::joinable!(email_tokens -> users (user_id));
+diesel::joinable!(email_verifications -> users (user_id));
+diesel::joinable!(invitation_redemptions -> invitations (invitation_id));
+diesel::joinable!(invitation_redemptions -> users (user_id));
+diesel::joinable!(invitations -> users (created_by));
+diesel::joinable!(invitations -> workspaces (workspace_id));
+diesel::joinable!(message_revisions -> messages (message_id));
+diesel::joinable!(message_revisions -> users (edited_by));
+diesel::joinable!(messages -> users (sender_id));
+diesel::joinable!(messages -> workspaces (workspace_id));
+diesel::joinable!(notifications -> messages (message_id));
+diesel::joinable!(poll_votes -> polls (poll_id));
+diesel::joinable!(poll_votes -> users (user_id));
+diesel::joinable!(polls -> users (creator_id));
+diesel::joinable!(polls -> workspaces (workspace_id));
+diesel::joinable!(reports -> messages (message_id));
+diesel::joinable!(reports -> workspaces (workspace_id));
+diesel::joinable!(user_onboarding -> users (user_id));
+diesel::joinable!(user_preferences -> users (user_id));
+diesel::joinable!(user_statuses -> users (user_id));
+diesel::joinable!(workspace_keys -> workspaces (workspace_id));
+diesel::joinable!(workspace_members -> users (user_id));
+diesel::joinable!(workspace_members -> workspaces (workspace_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    bots,
+    client_certificates,
+    do_not_disturb,
+    email_tokens,
+    email_verifications,
+    feature_flags,
+    invitation_redemptions,
+    invitations,
+    message_revisions,
+    messages,
+    notifications,
+    poll_votes,
+    polls,
+    reports,
+    user_onboarding,
+    user_preferences,
+    user_statuses,
+    users,
+    workspace_keys,
+    workspace_members,
+    workspaces,
+);
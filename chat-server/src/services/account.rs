@@ -0,0 +1,115 @@
+//! Account management shared by the REST and gRPC APIs.
+
+use crate::config::UserDeletionMode;
+use crate::models::user::{MergedAccount, DELETED_USER_USERNAME};
+use crate::repositories::bot::BotRepository;
+use crate::repositories::user::UserRepository;
+use crate::services::export::ExportService;
+use crate::services::storage::Storage;
+use diesel::OptionalExtension;
+use diesel_async::AsyncPgConnection;
+use thiserror::Error;
+use tracing::error;
+
+/// Why an account could not be deleted.
+#[derive(Debug, Error)]
+pub enum DeleteAccountError {
+    #[error("The deleted-user placeholder cannot be deleted")]
+    Placeholder,
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Why two accounts could not be merged.
+#[derive(Debug, Error)]
+pub enum MergeAccountsError {
+    #[error("An account cannot be merged into itself")]
+    SameAccount,
+    #[error("The deleted-user placeholder cannot be merged")]
+    Placeholder,
+    #[error("Bot accounts cannot be merged")]
+    Bot,
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Storage key of a user's avatar.
+pub fn avatar_key(user_id: i32) -> String {
+    format!("avatars/{}", user_id)
+}
+
+/// Deletes an account, its messages according to the deletion mode, the bots
+/// it owns, and any data export or avatar left in storage.
+///
+/// # Returns
+/// * `Result<usize, DeleteAccountError>` - The number of deleted accounts
+pub async fn delete_account(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    mode: UserDeletionMode,
+    exports: &ExportService,
+    storage: &dyn Storage,
+) -> Result<usize, DeleteAccountError> {
+    let is_sentinel = UserRepository::find_by_id(conn, id)
+        .await
+        .is_ok_and(|user| user.username == DELETED_USER_USERNAME);
+    if is_sentinel {
+        return Err(DeleteAccountError::Placeholder);
+    }
+
+    // Bots cannot outlive their owner
+    for bot_id in BotRepository::find_ids_by_owner(conn, id).await? {
+        UserRepository::delete_account(conn, bot_id, mode).await?;
+    }
+
+    let result = UserRepository::delete_account(conn, id, mode).await?;
+
+    exports.discard(id).await;
+    if let Err(e) = storage.delete(&avatar_key(id)).await {
+        error!("Failed to delete avatar of user {}: {}", id, e);
+    }
+
+    Ok(result)
+}
+
+/// Merges a duplicate account into a primary one: the duplicate's messages,
+/// memberships, bots and other records move to the primary account and the
+/// duplicate is deleted, along with any data export or avatar it left in
+/// storage.
+///
+/// # Errors
+/// * `MergeAccountsError::Database` with `NotFound` if either account does not
+///   exist
+pub async fn merge_accounts(
+    conn: &mut AsyncPgConnection,
+    primary: i32,
+    duplicate: i32,
+    exports: &ExportService,
+    storage: &dyn Storage,
+) -> Result<MergedAccount, MergeAccountsError> {
+    if primary == duplicate {
+        return Err(MergeAccountsError::SameAccount);
+    }
+    for user_id in [primary, duplicate] {
+        let user = UserRepository::find_by_id(conn, user_id).await?;
+        if user.username == DELETED_USER_USERNAME {
+            return Err(MergeAccountsError::Placeholder);
+        }
+        if BotRepository::find_by_id(conn, user_id)
+            .await
+            .optional()?
+            .is_some()
+        {
+            return Err(MergeAccountsError::Bot);
+        }
+    }
+
+    let merged = UserRepository::merge_into(conn, duplicate, primary).await?;
+
+    exports.discard(duplicate).await;
+    if let Err(e) = storage.delete(&avatar_key(duplicate)).await {
+        error!("Failed to delete avatar of user {}: {}", duplicate, e);
+    }
+
+    Ok(merged)
+}
@@ -0,0 +1,221 @@
+//! Server-side inspection of file and image attachments.
+//!
+//! The client's choice between sending a file or an image is not trusted: the
+//! decrypted content is sniffed by its magic bytes, checked against the
+//! configured [`AttachmentPolicy`] and re-classified when it turns out to be an
+//! image. Attachments whose content contradicts their claimed type are rejected,
+//! and so are names that clients could not save, such as names with paths.
+
+use std::path::Path;
+
+use crate::config::AttachmentPolicy;
+use crate::i18n;
+use chat_common::file_ops;
+use infer::MatcherType;
+
+/// MIME type reported for content that cannot be recognized
+const UNKNOWN_MIME: &str = "application/octet-stream";
+
+/// Extensions that announce an image
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff", "ico",
+];
+
+/// How an accepted attachment is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentKind {
+    File,
+    Image,
+}
+
+/// An accepted attachment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inspection {
+    pub kind: AttachmentKind,
+    /// The sender's file name, sanitized for saving
+    pub name: String,
+    /// MIME type detected from the content
+    pub mime: String,
+}
+
+/// The reason an attachment was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    /// Message key of the reason
+    pub key: &'static str,
+    /// Values of the message's `$variables`
+    pub args: Vec<(&'static str, String)>,
+    /// MIME type detected from the content
+    pub mime: String,
+}
+
+impl Rejection {
+    /// Describes the reason in `locale`.
+    pub fn message(&self, locale: &str) -> String {
+        let args: Vec<_> = self
+            .args
+            .iter()
+            .map(|(name, value)| (*name, value.as_str().into()))
+            .collect();
+        i18n::text(locale, self.key, &args)
+    }
+}
+
+/// Classifies an attachment by its content and checks it against the policy.
+///
+/// # Arguments
+/// * `name` - The file name chosen by the sender
+/// * `claimed_image` - Whether the sender sent the attachment as an image
+/// * `data` - The decrypted content
+/// * `policy` - The accepted attachment types
+///
+/// # Returns
+/// * `Result<Inspection, Rejection>` - The detected kind, or why the attachment is refused
+pub fn inspect(
+    name: &str,
+    claimed_image: bool,
+    data: &[u8],
+    policy: &AttachmentPolicy,
+) -> Result<Inspection, Rejection> {
+    let detected = infer::get(data);
+    let mime = detected
+        .map(|kind| kind.mime_type())
+        .unwrap_or(UNKNOWN_MIME)
+        .to_string();
+    let is_image = detected.is_some_and(|kind| kind.matcher_type() == MatcherType::Image);
+
+    let reject = |key: &'static str, args: &[(&'static str, &str)]| {
+        Err(Rejection {
+            key,
+            args: args
+                .iter()
+                .map(|(name, value)| (*name, value.to_string()))
+                .collect(),
+            mime: mime.clone(),
+        })
+    };
+
+    let Ok(sanitized) = file_ops::sanitize_file_name(name) else {
+        return reject("attachment-name-invalid", &[("name", name)]);
+    };
+    // Checked on the name clients save, which may have lost trailing whitespace
+    let extension = Path::new(&sanitized)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    if !policy.allows_extension(extension.as_deref()) {
+        return match &extension {
+            Some(extension) => reject(
+                "attachment-extension-not-allowed",
+                &[("extension", extension)],
+            ),
+            None => reject("attachment-extension-missing", &[]),
+        };
+    }
+
+    if !policy.allows_type(&mime) {
+        return reject("attachment-type-not-allowed", &[("mime", &mime)]);
+    }
+
+    if claimed_image && !is_image {
+        return reject("attachment-not-image", &[("name", name), ("mime", &mime)]);
+    }
+
+    if !is_image
+        && extension
+            .as_deref()
+            .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension))
+    {
+        return reject(
+            "attachment-image-mismatch",
+            &[("name", name), ("mime", &mime)],
+        );
+    }
+
+    Ok(Inspection {
+        kind: if is_image {
+            AttachmentKind::Image
+        } else {
+            AttachmentKind::File
+        },
+        name: sanitized,
+        mime,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_MAGIC: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0];
+    const PDF_MAGIC: &[u8] = b"%PDF-1.7\n";
+
+    #[test]
+    fn test_file_with_image_content_is_reclassified() {
+        let inspection = inspect("photo.png", false, PNG_MAGIC, &AttachmentPolicy::default());
+        assert_eq!(
+            inspection,
+            Ok(Inspection {
+                kind: AttachmentKind::Image,
+                name: "photo.png".to_string(),
+                mime: "image/png".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_mismatched_content_is_rejected() {
+        let policy = AttachmentPolicy::default();
+
+        let rejection = inspect("report.pdf", true, PDF_MAGIC, &policy).unwrap_err();
+        assert_eq!(rejection.mime, "application/pdf");
+        assert_eq!(
+            rejection.message("en"),
+            "'report.pdf' is not an image (detected application/pdf)"
+        );
+
+        assert!(inspect("cat.jpg", false, PDF_MAGIC, &policy).is_err());
+        assert_eq!(
+            inspect("notes.txt", false, b"plain text", &policy).map(|i| i.kind),
+            Ok(AttachmentKind::File)
+        );
+    }
+
+    #[test]
+    fn test_unsafe_names_are_rejected() {
+        let policy = AttachmentPolicy::default();
+
+        let rejection = inspect("../../.ssh/authorized_keys", false, b"key", &policy).unwrap_err();
+        assert_eq!(
+            rejection.message("en"),
+            "'../../.ssh/authorized_keys' cannot be used as a file name"
+        );
+        assert!(inspect("..", false, b"dots", &policy).is_err());
+        assert_eq!(
+            inspect(" notes\t.txt ", false, b"plain text", &policy).map(|i| i.name),
+            Ok("notes.txt".to_string())
+        );
+        let policy = AttachmentPolicy {
+            allowed_types: Vec::new(),
+            allowed_extensions: vec!["txt".to_string()],
+        };
+        assert!(inspect("notes.txt  ", false, b"plain text", &policy).is_ok());
+    }
+
+    #[test]
+    fn test_policy_is_enforced() {
+        let policy = AttachmentPolicy {
+            allowed_types: vec!["image/*".to_string()],
+            allowed_extensions: Vec::new(),
+        };
+        assert!(inspect("photo.png", true, PNG_MAGIC, &policy).is_ok());
+        assert!(inspect("report.pdf", false, PDF_MAGIC, &policy).is_err());
+
+        let policy = AttachmentPolicy {
+            allowed_types: Vec::new(),
+            allowed_extensions: vec!["pdf".to_string()],
+        };
+        assert!(inspect("report.pdf", false, PDF_MAGIC, &policy).is_ok());
+        assert!(inspect("photo.png", true, PNG_MAGIC, &policy).is_err());
+    }
+}
@@ -36,14 +36,18 @@ impl AuthService {
     ///
     /// # Returns
     /// * `Result<Option<(i32, String)>>` - If successful, returns Some with (user_id, token).
-    ///   If authentication fails, returns None. Returns Err if there's a database or verification error.
+    ///   If the user does not exist or the password is wrong, returns None. Returns Err if there's a database or verification error.
     pub async fn authenticate(
         &self,
         username: &str,
         password: &str,
     ) -> Result<Option<(i32, String)>> {
         let conn = &mut *self.pool.get().await?;
-        let user = UserRepository::find_by_username(conn, username).await?;
+        let user = match UserRepository::find_by_username(conn, username).await {
+            Ok(user) => user,
+            Err(diesel::result::Error::NotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
 
         if verify(password, &user.password_hash)? {
             let token = self.generate_token();
@@ -57,7 +61,7 @@ impl AuthService {
     ///
     /// # Returns
     /// * `String` - A randomly generated token suitable for authentication
-    fn generate_token(&self) -> String {
+    pub fn generate_token(&self) -> String {
         rand::rng()
             .sample_iter(&Alphanumeric)
             .take(128)
@@ -0,0 +1,207 @@
+//! Throttling of failed chat logins.
+//!
+//! Failed logins are counted per connection and per username. The reply to a
+//! failed login is delayed, and the delay doubles with every further failure
+//! on the same connection. A connection that keeps failing is closed, and a
+//! username that failed too often within the failure window is locked until
+//! its oldest failures age out of the window.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::SharedConfig;
+
+/// Longest delay before answering a failed login
+const MAX_FAILURE_DELAY: Duration = Duration::from_secs(30);
+
+/// Why a login failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// Unknown username or wrong password
+    InvalidCredentials,
+    /// The credentials are valid but the user belongs to no workspace
+    NoWorkspace,
+    /// The username failed too often and is locked for `retry_after`
+    Locked { retry_after: Duration },
+}
+
+impl AuthFailure {
+    /// Returns the label used for logs and metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthFailure::InvalidCredentials => "invalid_credentials",
+            AuthFailure::NoWorkspace => "no_workspace",
+            AuthFailure::Locked { .. } => "locked",
+        }
+    }
+}
+
+/// Failed-login bookkeeping shared by all connections.
+///
+/// The limits are read from the configuration on every login, so they can
+/// be reloaded.
+pub struct AuthThrottle {
+    config: SharedConfig,
+    /// Times of recent failed logins per lowercased username, oldest first
+    failures: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl AuthThrottle {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how long logins for `username` stay locked, if they are.
+    pub fn locked_for(&self, username: &str, now: Instant) -> Option<Duration> {
+        let config = self.config.current();
+        let limit = config.auth_throttle.max_failures_per_username as usize;
+        let window = config.auth_throttle.failure_window;
+        if limit == 0 {
+            return None;
+        }
+
+        let mut failures = self.failures.lock().unwrap();
+        let times = failures.get_mut(&username_key(username))?;
+        times.retain(|failed_at| now.duration_since(*failed_at) < window);
+        if times.len() < limit {
+            return None;
+        }
+
+        // Locked until enough failures age out to drop below the limit
+        let unlocking = times[times.len() - limit];
+        Some(window - now.duration_since(unlocking))
+    }
+
+    /// Counts a failed login against `username`.
+    pub fn record_failure(&self, username: &str, now: Instant) {
+        let window = self.config.current().auth_throttle.failure_window;
+
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, times| {
+            times.retain(|failed_at| now.duration_since(*failed_at) < window);
+            !times.is_empty()
+        });
+        failures
+            .entry(username_key(username))
+            .or_default()
+            .push_back(now);
+    }
+
+    /// Forgets the failed logins of `username` after it logged in.
+    pub fn record_success(&self, username: &str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&username_key(username));
+    }
+
+    /// Returns how long to wait before answering the `failures`-th failed
+    /// login of a connection.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let base = self.config.current().auth_throttle.failure_delay;
+        let doublings = failures.saturating_sub(1).min(16);
+        base.checked_mul(2u32.pow(doublings))
+            .map_or(MAX_FAILURE_DELAY, |delay| delay.min(MAX_FAILURE_DELAY))
+    }
+
+    /// Returns `true` if a connection with `failures` failed logins is closed.
+    pub fn exceeds_connection_limit(&self, failures: u32) -> bool {
+        let limit = self
+            .config
+            .current()
+            .auth_throttle
+            .max_failures_per_connection;
+        limit > 0 && failures >= limit
+    }
+}
+
+/// Usernames are matched case-insensitively, like logins.
+fn username_key(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthThrottleConfig, ServerConfig};
+
+    fn throttle_with(auth_throttle: AuthThrottleConfig) -> AuthThrottle {
+        AuthThrottle::new(SharedConfig::new(ServerConfig {
+            auth_throttle,
+            ..ServerConfig::default()
+        }))
+    }
+
+    #[test]
+    fn test_username_locked_until_failures_age_out() {
+        let throttle = throttle_with(AuthThrottleConfig {
+            max_failures_per_username: 2,
+            failure_window: Duration::from_secs(60),
+            ..AuthThrottleConfig::default()
+        });
+        let start = Instant::now();
+
+        throttle.record_failure("alice", start);
+        assert_eq!(throttle.locked_for("alice", start), None);
+
+        throttle.record_failure("Alice ", start + Duration::from_secs(10));
+        assert_eq!(
+            throttle.locked_for("ALICE", start + Duration::from_secs(20)),
+            Some(Duration::from_secs(40))
+        );
+        // Other usernames are not affected
+        assert_eq!(throttle.locked_for("bob", start), None);
+        // The lock ends once the first failure leaves the window
+        assert_eq!(
+            throttle.locked_for("alice", start + Duration::from_secs(60)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_success_clears_failures() {
+        let throttle = throttle_with(AuthThrottleConfig {
+            max_failures_per_username: 1,
+            ..AuthThrottleConfig::default()
+        });
+        let now = Instant::now();
+
+        throttle.record_failure("alice", now);
+        assert!(throttle.locked_for("alice", now).is_some());
+
+        throttle.record_success("alice");
+        assert_eq!(throttle.locked_for("alice", now), None);
+    }
+
+    #[test]
+    fn test_delay_doubles_up_to_cap() {
+        let throttle = throttle_with(AuthThrottleConfig {
+            failure_delay: Duration::from_millis(100),
+            ..AuthThrottleConfig::default()
+        });
+
+        assert_eq!(throttle.delay(1), Duration::from_millis(100));
+        assert_eq!(throttle.delay(3), Duration::from_millis(400));
+        assert_eq!(throttle.delay(u32::MAX), MAX_FAILURE_DELAY);
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        let throttle = throttle_with(AuthThrottleConfig {
+            max_failures_per_connection: 3,
+            ..AuthThrottleConfig::default()
+        });
+        assert!(!throttle.exceeds_connection_limit(2));
+        assert!(throttle.exceeds_connection_limit(3));
+
+        let unlimited = throttle_with(AuthThrottleConfig {
+            max_failures_per_connection: 0,
+            ..AuthThrottleConfig::default()
+        });
+        assert!(!unlimited.exceeds_connection_limit(100));
+    }
+}
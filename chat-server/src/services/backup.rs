@@ -0,0 +1,389 @@
+//! Backups of the database, run as `chat-server backup <archive>` and
+//! `chat-server restore <archive>`.
+//!
+//! The database is dumped with `pg_dump`, which reads it from a single
+//! snapshot, so the dump is consistent even while the server is running.
+//! Attachments and avatars stay in the storage backend, which is backed up
+//! separately; the archive only lists them in a manifest, so a restore can
+//! report the ones missing from the store it is restored next to.
+//!
+//! The archive is encrypted with the server's `ENCRYPTION_KEY`, so it can only
+//! be restored by a server with the same key. It starts with magic bytes,
+//! followed by the encrypted content and a [`BackupHeader`] with what is needed
+//! to decrypt it; the header's length ends the archive. The content is the
+//! length and JSON of the [`BackupManifest`] followed by the dump.
+//!
+//! The dump is encrypted as `pg_dump` writes it, so it never reaches the disk
+//! in the clear. A restore decrypts it to a temporary file without a name,
+//! which only the server can read and which is gone once the restore is done,
+//! so the whole archive is verified before `pg_restore` reads any of it.
+
+use crate::config::ServerConfig;
+use crate::repositories::message::MessageRepository;
+use crate::repositories::user::UserRepository;
+use crate::services::account::avatar_key;
+use crate::services::storage::{self, attachment_key, Storage};
+use crate::utils::db_connection;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chat_common::encryption::file::{EncryptedFileMetadata, FileEncryption};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// First bytes of every backup archive
+const MAGIC: &[u8; 8] = b"CHATBKP\0";
+/// Version of the archive layout
+const FORMAT_VERSION: u32 = 1;
+/// Largest header or manifest accepted when reading an archive (64 MiB)
+const MAX_SECTION_LEN: u32 = 64 * 1024 * 1024;
+
+/// Unencrypted end of an archive, needed to decrypt the content.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupHeader {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub encryption: EncryptedFileMetadata,
+}
+
+/// What the dumped database refers to in the storage backend.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct BackupManifest {
+    pub objects: Vec<StoredObject>,
+}
+
+/// An object in the storage backend.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StoredObject {
+    pub key: String,
+    /// Size in bytes, or None if the object was already missing from the store
+    pub size: Option<u64>,
+}
+
+/// Runs the `backup` or `restore` command given on the command line.
+///
+/// # Arguments
+/// * `args` - The command line arguments after the program name
+/// * `config` - The server configuration, for the storage backend
+pub async fn run_command(args: &[String], config: &ServerConfig) -> Result<()> {
+    let (command, path) = match args {
+        [command, path] => (command.as_str(), Path::new(path)),
+        _ => bail!("Usage: chat-server [backup|restore] <archive>"),
+    };
+    let encryption = FileEncryption::new(&server_key()?)?;
+    let storage = storage::from_config(&config.storage).context("Failed to set up storage")?;
+    match command {
+        "backup" => backup(path, &encryption, storage.as_ref()).await,
+        "restore" => restore(path, &encryption, storage.as_ref()).await,
+        _ => bail!("Unknown command '{}', expected backup or restore", command),
+    }
+}
+
+/// Dumps the database and writes it with the storage manifest to an archive.
+///
+/// The archive is written next to its final path and only renamed into place
+/// once complete, so an interrupted backup never leaves a truncated archive.
+pub async fn backup(path: &Path, encryption: &FileEncryption, storage: &dyn Storage) -> Result<()> {
+    let manifest = manifest(storage).await?;
+    let objects = manifest.objects.len();
+    let missing = manifest.objects.iter().filter(|o| o.size.is_none()).count();
+    if missing > 0 {
+        warn!("{} objects are missing from storage already", missing);
+    }
+
+    let part_path = with_suffix(path, ".part");
+    let written = async {
+        let mut section = Vec::new();
+        write_section(&mut section, &serde_json::to_vec(&manifest)?).await?;
+        let mut dump = Command::new("pg_dump")
+            .args(["--format=custom", "--no-owner", "--dbname"])
+            .arg(database_url()?)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to run pg_dump")?;
+        let stdout = dump.stdout.take().context("pg_dump has no output")?;
+
+        let mut archive = File::create(&part_path).await?;
+        archive.write_all(MAGIC).await?;
+        let metadata = encryption
+            .encrypt_stream((&section[..]).chain(stdout), &mut archive)
+            .await?;
+        let status = dump.wait().await?;
+        if !status.success() {
+            bail!("pg_dump failed: {}", status);
+        }
+        let header = serde_json::to_vec(&BackupHeader {
+            version: FORMAT_VERSION,
+            created_at: Utc::now(),
+            encryption: metadata,
+        })?;
+        archive.write_all(&header).await?;
+        archive.write_u32(header.len() as u32).await?;
+        archive.sync_all().await?;
+        fs::rename(&part_path, path).await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = written {
+        let _ = fs::remove_file(&part_path).await;
+        return Err(e.context(format!("Failed to back up to {}", path.display())));
+    }
+
+    info!(
+        "Backed up the database and a manifest of {} storage objects to {}",
+        objects,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Restores the database from an archive, replacing its current content, and
+/// reports the objects of the manifest that are missing from storage.
+///
+/// The archive is decrypted and verified before the database is touched, and
+/// `pg_restore` runs in a single transaction, so a failed restore leaves the
+/// database as it was.
+pub async fn restore(
+    path: &Path,
+    encryption: &FileEncryption,
+    storage: &dyn Storage,
+) -> Result<()> {
+    let mut archive = File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let (header, content_len) = read_header(&mut archive)
+        .await
+        .with_context(|| format!("{} is not a valid backup", path.display()))?;
+
+    let restored = async {
+        let mut content = File::from_std(tempfile::tempfile()?);
+        archive.seek(SeekFrom::Start(MAGIC.len() as u64)).await?;
+        encryption
+            .decrypt_stream(archive.take(content_len), &mut content, &header.encryption)
+            .await
+            .context("Failed to decrypt the backup, was it made with another ENCRYPTION_KEY?")?;
+        content.seek(SeekFrom::Start(0)).await?;
+
+        let manifest: BackupManifest = serde_json::from_slice(&read_section(&mut content).await?)
+            .context("Invalid storage manifest")?;
+        let mut pg_restore = Command::new("pg_restore")
+            .args([
+                "--clean",
+                "--if-exists",
+                "--no-owner",
+                "--single-transaction",
+            ])
+            .arg("--dbname")
+            .arg(database_url()?)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("Failed to run pg_restore")?;
+        let mut stdin = pg_restore.stdin.take().context("pg_restore has no input")?;
+        tokio::io::copy(&mut content, &mut stdin).await?;
+        drop(stdin);
+        let status = pg_restore.wait().await?;
+        if !status.success() {
+            bail!("pg_restore failed: {}", status);
+        }
+        Ok::<_, anyhow::Error>(manifest)
+    }
+    .await;
+    let manifest = restored?;
+
+    let mut missing = 0;
+    for object in &manifest.objects {
+        if object.size.is_some() && !storage.exists(&object.key).await? {
+            warn!("{} is missing from storage", object.key);
+            missing += 1;
+        }
+    }
+    info!(
+        "Restored the database from the backup of {}; {} of {} storage objects are missing",
+        header.created_at,
+        missing,
+        manifest.objects.len()
+    );
+    Ok(())
+}
+
+/// Lists the attachments and avatars in storage that the database refers to.
+async fn manifest(storage: &dyn Storage) -> Result<BackupManifest> {
+    let pool = db_connection::create_pool().await?;
+    let mut conn = pool.get().await?;
+    let mut keys: Vec<String> = MessageRepository::attachment_hashes(&mut conn)
+        .await?
+        .iter()
+        .map(|sha256| attachment_key(sha256))
+        .collect();
+    for user in UserRepository::find_all(&mut conn).await? {
+        let key = avatar_key(user.id);
+        if storage.exists(&key).await? {
+            keys.push(key);
+        }
+    }
+
+    let mut objects = Vec::with_capacity(keys.len());
+    for key in keys {
+        let size = storage.size(&key).await.ok();
+        objects.push(StoredObject { key, size });
+    }
+    Ok(BackupManifest { objects })
+}
+
+/// Reads the header at the end of an archive.
+///
+/// # Returns
+/// * `Result<(BackupHeader, u64)>` - The header and the length of the
+///   encrypted content between the magic bytes and the header
+async fn read_header(archive: &mut File) -> Result<(BackupHeader, u64)> {
+    let mut magic = [0u8; MAGIC.len()];
+    archive.read_exact(&mut magic).await?;
+    if &magic != MAGIC {
+        bail!("Not a chat server backup");
+    }
+    let len = archive.metadata().await?.len();
+    archive.seek(SeekFrom::End(-4)).await?;
+    let header_len = archive.read_u32().await?;
+    if header_len > MAX_SECTION_LEN {
+        bail!("Backup header of {} bytes is too large", header_len);
+    }
+    let content_end = len
+        .checked_sub(4 + header_len as u64)
+        .filter(|end| *end >= MAGIC.len() as u64)
+        .context("The backup is truncated")?;
+    archive.seek(SeekFrom::Start(content_end)).await?;
+    let mut header = vec![0u8; header_len as usize];
+    archive.read_exact(&mut header).await?;
+    let header: BackupHeader = serde_json::from_slice(&header).context("Invalid header")?;
+    if header.version != FORMAT_VERSION {
+        bail!("Unsupported backup format version {}", header.version);
+    }
+    Ok((header, content_end - MAGIC.len() as u64))
+}
+
+/// Writes a length-prefixed section.
+async fn write_section<W: AsyncWrite + Unpin>(writer: &mut W, section: &[u8]) -> Result<()> {
+    writer.write_u32(section.len() as u32).await?;
+    writer.write_all(section).await?;
+    Ok(())
+}
+
+/// Reads a length-prefixed section.
+async fn read_section<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = reader.read_u32().await?;
+    if len > MAX_SECTION_LEN {
+        bail!("Backup section of {} bytes is too large", len);
+    }
+    let mut section = vec![0u8; len as usize];
+    reader.read_exact(&mut section).await?;
+    Ok(section)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn database_url() -> Result<String> {
+    env::var("DATABASE_URL").context("DATABASE_URL must be set")
+}
+
+/// The server's `ENCRYPTION_KEY`, which backups are encrypted with.
+fn server_key() -> Result<Vec<u8>> {
+    let key = env::var("ENCRYPTION_KEY").context("ENCRYPTION_KEY must be set")?;
+    let key = BASE64
+        .decode(key)
+        .map_err(|e| anyhow!("ENCRYPTION_KEY must be base64 encoded: {}", e))?;
+    if key.len() != 32 {
+        bail!("ENCRYPTION_KEY must be exactly 32 bytes when decoded");
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn archive_with(header: &BackupHeader, content: &[u8]) -> (tempfile::TempDir, File) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+        let header = serde_json::to_vec(header).unwrap();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(content);
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        fs::write(&path, bytes).await.unwrap();
+        let file = File::open(&path).await.unwrap();
+        (dir, file)
+    }
+
+    #[tokio::test]
+    async fn test_archive_roundtrip() {
+        let encryption = FileEncryption::new(&[7u8; 32]).unwrap();
+        let mut content = Vec::new();
+        write_section(&mut content, b"{\"objects\":[]}")
+            .await
+            .unwrap();
+        content.extend_from_slice(b"dump");
+        let mut encrypted = Vec::new();
+        let metadata = encryption
+            .encrypt_stream(&content[..], &mut encrypted)
+            .await
+            .unwrap();
+        let header = BackupHeader {
+            version: FORMAT_VERSION,
+            created_at: Utc::now(),
+            encryption: metadata,
+        };
+
+        let (_dir, mut archive) = archive_with(&header, &encrypted).await;
+        let (header, content_len) = read_header(&mut archive).await.unwrap();
+        assert_eq!(content_len, encrypted.len() as u64);
+
+        archive
+            .seek(SeekFrom::Start(MAGIC.len() as u64))
+            .await
+            .unwrap();
+        let mut decrypted = Vec::new();
+        encryption
+            .decrypt_stream(
+                archive.take(content_len),
+                &mut decrypted,
+                &header.encryption,
+            )
+            .await
+            .unwrap();
+        let mut decrypted = &decrypted[..];
+        let manifest: BackupManifest =
+            serde_json::from_slice(&read_section(&mut decrypted).await.unwrap()).unwrap();
+        assert_eq!(manifest, BackupManifest::default());
+        assert_eq!(decrypted, b"dump");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, b"not a backup at all").await.unwrap();
+        assert!(read_header(&mut File::open(&path).await.unwrap())
+            .await
+            .is_err());
+
+        let mut truncated = MAGIC.to_vec();
+        truncated.extend_from_slice(&1000u32.to_be_bytes());
+        fs::write(&path, truncated).await.unwrap();
+        assert!(read_header(&mut File::open(&path).await.unwrap())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Bot accounts.
+//!
+//! Bots post messages through `POST /bots/<id>/messages` instead of holding a
+//! chat connection. Messages mentioning a bot that has a webhook URL are
+//! POSTed to that URL as JSON. Deliveries go through a [`RetryQueue`], like
+//! email.
+
+use crate::models::bot::BotProfile;
+use crate::models::message::{Message as StoredMessage, MessageType, NewMessage};
+use crate::repositories::bot::BotRepository;
+use crate::repositories::message::MessageRepository;
+use crate::repositories::user::UserRepository;
+use crate::services::message::broadcast::MessageBroadcaster;
+use crate::services::notification::{extract_mentions, NotificationService};
+use crate::services::retry_queue::{RetryQueue, Transport};
+use crate::services::room_keys;
+use crate::types::Clients;
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chat_common::encryption::EncryptionService;
+use chat_common::Message;
+use chrono::NaiveDateTime;
+use diesel_async::AsyncPgConnection;
+use rocket::async_trait;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+/// How long a webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The JSON body POSTed to a bot's webhook when a message mentions the bot.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WebhookPayload {
+    /// Always `mention`
+    pub event: &'static str,
+    pub bot_id: i32,
+    pub message_id: i32,
+    pub workspace_id: i32,
+    pub sender_id: i32,
+    pub sender: String,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A webhook call waiting for delivery.
+#[derive(Debug, Clone)]
+pub struct WebhookCall {
+    pub url: String,
+    pub payload: WebhookPayload,
+}
+
+impl fmt::Display for WebhookCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "webhook of bot {} at {}", self.payload.bot_id, self.url)
+    }
+}
+
+/// Delivers webhook calls over HTTP, failing on non-success statuses.
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport<WebhookCall> for HttpTransport {
+    async fn deliver(&self, call: &WebhookCall) -> Result<()> {
+        self.client
+            .post(&call.url)
+            .json(&call.payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Queues webhook calls for delivery by a background worker.
+#[derive(Clone)]
+pub struct WebhookService {
+    queue: RetryQueue<WebhookCall>,
+}
+
+impl WebhookService {
+    /// Creates the service and spawns its delivery worker.
+    pub fn new(transport: Arc<dyn Transport<WebhookCall>>) -> Self {
+        Self {
+            queue: RetryQueue::new(transport),
+        }
+    }
+
+    /// Queues calls to the webhooks of the bots mentioned in a stored text
+    /// message. Bots are only called for messages in their own workspace.
+    ///
+    /// # Arguments
+    /// * `conn` - Connection used to look up the sender and the bots
+    /// * `message` - The stored message
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of queued calls
+    pub async fn deliver_mentions(
+        &self,
+        conn: &mut AsyncPgConnection,
+        message: &StoredMessage,
+    ) -> Result<usize> {
+        let Some(text) = &message.content else {
+            return Ok(0);
+        };
+        let mentions = extract_mentions(text);
+        if mentions.is_empty() {
+            return Ok(0);
+        }
+
+        let bots = BotRepository::find_webhooks(conn, message.workspace_id, &mentions).await?;
+        let bots: Vec<BotProfile> = bots
+            .into_iter()
+            .filter(|profile| profile.bot.user_id != message.sender_id)
+            .collect();
+        if bots.is_empty() {
+            return Ok(0);
+        }
+
+        let sender = UserRepository::find_by_id(conn, message.sender_id).await?;
+        for profile in &bots {
+            let Some(url) = &profile.bot.webhook_url else {
+                continue;
+            };
+            let call = WebhookCall {
+                url: url.clone(),
+                payload: WebhookPayload {
+                    event: "mention",
+                    bot_id: profile.bot.user_id,
+                    message_id: message.id,
+                    workspace_id: message.workspace_id,
+                    sender_id: sender.id,
+                    sender: sender.username.clone(),
+                    content: text.clone(),
+                    created_at: message.created_at,
+                },
+            };
+            self.queue.push(call).await?;
+        }
+        Ok(bots.len())
+    }
+}
+
+/// Posts a text message as a bot.
+///
+/// The message is stored, delivered to the chat clients of the bot's
+/// workspace, and mentioned users and bots are notified like for messages
+/// sent over a chat connection.
+///
+/// # Arguments
+/// * `pool` - Shared database connection pool
+/// * `clients` - Connected chat clients
+/// * `encryption` - Encrypts the text with the key of the bot's workspace
+/// * `webhooks` - Calls the webhooks of mentioned bots
+/// * `bot` - The posting bot
+/// * `content` - The plain text of the message
+///
+/// # Returns
+/// * `Result<StoredMessage>` - The stored message
+pub async fn post_message(
+    pool: &Arc<DbPool>,
+    clients: &Clients,
+    encryption: &EncryptionService,
+    webhooks: &WebhookService,
+    bot: &BotProfile,
+    content: String,
+) -> Result<StoredMessage> {
+    let conn = &mut *pool.get().await?;
+    let stored = MessageRepository::create(
+        conn,
+        NewMessage {
+            sender_id: bot.bot.user_id,
+            message_type: MessageType::Text,
+            content: Some(content.clone()),
+            file_name: None,
+            workspace_id: bot.bot.workspace_id,
+            sha256: None,
+            attachment_size: None,
+        },
+    )
+    .await?;
+
+    let key_id = room_keys::load(conn, encryption, bot.bot.workspace_id).await?;
+    let encrypted = encryption.message_for(Some(&key_id))?.encrypt(&content)?;
+    let message = Message::Text(serde_json::to_string(&encrypted)?);
+    MessageBroadcaster::new(clients.clone())
+        .broadcast_message(&message, None, Some(bot.bot.workspace_id))
+        .await?;
+
+    let notifications = NotificationService::new(pool.clone(), clients.clone());
+    if let Err(e) = notifications
+        .notify_mentions(bot.bot.user_id, stored.id, bot.bot.workspace_id, &content)
+        .await
+    {
+        error!("Failed to deliver mention notifications: {}", e);
+    }
+    if let Err(e) = webhooks.deliver_mentions(conn, &stored).await {
+        error!("Failed to queue webhook calls: {}", e);
+    }
+
+    Ok(stored)
+}
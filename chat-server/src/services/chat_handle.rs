@@ -0,0 +1,136 @@
+//! Handle for acting on the live chat connections from outside the TCP server.
+//!
+//! The REST API runs in Rocket, apart from the TCP server that owns the chat
+//! connections. Rocket gets a [`ChatHandle`] in its state, which sends
+//! commands to a task next to the TCP server. The task carries them out with
+//! the same broadcaster chat messages are delivered with.
+
+use anyhow::{anyhow, Result};
+use chat_common::Message;
+use tokio::sync::{mpsc, oneshot};
+use tracing::error;
+
+use crate::services::message::broadcast::MessageBroadcaster;
+use crate::types::Clients;
+
+/// Commands waiting for the task are limited, so a stuck task cannot make
+/// callers queue up without bound
+const COMMAND_QUEUE_CAPACITY: usize = 32;
+
+/// A command for the chat connections.
+enum ChatCommand {
+    /// Sends a System message to every connection, or those of one workspace
+    Announce {
+        text: String,
+        workspace_id: Option<i32>,
+        /// Receives the number of connections the message was sent to
+        reached: oneshot::Sender<usize>,
+    },
+}
+
+/// Sends commands to the task that owns the chat connections.
+#[derive(Clone)]
+pub struct ChatHandle {
+    commands: mpsc::Sender<ChatCommand>,
+}
+
+impl ChatHandle {
+    /// Spawns the task carrying out commands on the given connections.
+    ///
+    /// # Arguments
+    /// * `clients` - The connections of the TCP server
+    pub fn spawn(clients: Clients) -> Self {
+        let (commands, mut receiver) = mpsc::channel(COMMAND_QUEUE_CAPACITY);
+        let broadcaster = MessageBroadcaster::new(clients.clone());
+
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                match command {
+                    ChatCommand::Announce {
+                        text,
+                        workspace_id,
+                        reached,
+                    } => {
+                        let count = clients
+                            .lock()
+                            .await
+                            .values()
+                            .filter(|connection| {
+                                workspace_id.is_none() || connection.workspace_id == workspace_id
+                            })
+                            .count();
+                        let message = Message::System(text);
+                        if let Err(e) = broadcaster
+                            .broadcast_message(&message, None, workspace_id)
+                            .await
+                        {
+                            error!("Failed to broadcast announcement: {}", e);
+                        }
+                        let _ = reached.send(count);
+                    }
+                }
+            }
+        });
+
+        Self { commands }
+    }
+
+    /// Sends a System message to the live connections.
+    ///
+    /// The message is not attributed to any user, so it cannot be mistaken for
+    /// one written by the admin who sent it or by anyone else.
+    ///
+    /// # Arguments
+    /// * `text` - The message
+    /// * `workspace_id` - Restricts the message to one workspace (all if None)
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of connections the message was sent to,
+    ///   or an error if the task is gone
+    pub async fn announce(&self, text: String, workspace_id: Option<i32>) -> Result<usize> {
+        let (reached, count) = oneshot::channel();
+        self.commands
+            .send(ChatCommand::Announce {
+                text,
+                workspace_id,
+                reached,
+            })
+            .await
+            .map_err(|_| anyhow!("The chat server is not running"))?;
+        count
+            .await
+            .map_err(|_| anyhow!("The chat server did not answer"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ChatRoomConnection;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_announce_to_workspace() {
+        let (inside, outside) = (
+            ChatRoomConnection::for_test(1, 1),
+            ChatRoomConnection::for_test(2, 2),
+        );
+        let (inside_queue, outside_queue) = (inside.outbound.clone(), outside.outbound.clone());
+        let clients = Arc::new(Mutex::new(HashMap::from([(1, inside), (2, outside)])));
+
+        let handle = ChatHandle::spawn(clients);
+        let reached = handle
+            .announce("Maintenance in 10 minutes".to_string(), Some(1))
+            .await
+            .unwrap();
+
+        assert_eq!(reached, 1);
+        assert_eq!(
+            inside_queue.pop().await,
+            Some(Message::System("Maintenance in 10 minutes".to_string()))
+        );
+        assert!(outside_queue.is_empty());
+    }
+}
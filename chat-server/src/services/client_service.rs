@@ -6,18 +6,32 @@
 //! - Managing client authentication states
 //! - Providing encryption services for secure communication
 
-use crate::services::connection_service::ConnectionService;
-use crate::types::{AuthState, ChatRoomConnection, Clients};
+use crate::config::SharedConfig;
+use crate::grpc::codec::Protobuf;
+use crate::i18n;
+use crate::models::do_not_disturb::DndSettings;
+use crate::services::auth_throttle::AuthThrottle;
+use crate::services::connection_service::{ConnectionHealth, ConnectionService};
+use crate::services::feature_flags::FeatureFlags;
+use crate::services::message::pipeline::Pipeline;
+use crate::services::outbound_queue::{self, OutboundQueue};
+use crate::services::storage::Storage;
+use crate::services::tls;
+use crate::types::{AuthState, ChatRoomConnection, Clients, ConnectionTransfer};
 use crate::utils::db_connection::DbPool;
 use crate::utils::metrics::Metrics;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chat_common::async_message_stream::{AsyncMessageStream, Metered};
+use chat_common::codec::{CodecReader, CodecWriter, Codecs};
 use chat_common::encryption::EncryptionService;
 use chat_common::error::Result;
+use std::net::SocketAddr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tokio_rustls::server::TlsStream;
+use tracing::{error, field, info, info_span, Instrument};
 
 /// Service responsible for managing client connections in the chat server.
 ///
@@ -36,6 +50,16 @@ pub struct ClientService {
     /// Shared encryption service for secure communication
     encryption: Arc<EncryptionService>,
     metrics: Arc<Mutex<Metrics>>,
+    /// Server configuration, reloadable at runtime
+    config: SharedConfig,
+    /// Runtime feature flags
+    feature_flags: FeatureFlags,
+    /// Storage for attachment content
+    storage: Option<Arc<dyn Storage>>,
+    /// Middleware pipeline shared by all connections
+    pipeline: Arc<Pipeline>,
+    /// Failed-login limits shared by all connections
+    auth_throttle: Arc<AuthThrottle>,
 }
 
 impl ClientService {
@@ -45,6 +69,7 @@ impl ClientService {
     /// * `clients` - Shared map of all connected clients
     /// * `pool` - Shared database connection pool
     /// * `metrics` - Shared metrics for monitoring
+    /// * `config` - Server configuration, reloadable at runtime
     ///
     /// # Returns
     /// * `Result<Self>` - The new ClientService instance or an error if initialization fails
@@ -53,7 +78,12 @@ impl ClientService {
     /// * If ENCRYPTION_KEY environment variable is not set
     /// * If ENCRYPTION_KEY is not valid base64
     /// * If decoded ENCRYPTION_KEY is not exactly 32 bytes
-    pub fn new(clients: Clients, pool: Arc<DbPool>, metrics: Arc<Mutex<Metrics>>) -> Result<Self> {
+    pub fn new(
+        clients: Clients,
+        pool: Arc<DbPool>,
+        metrics: Arc<Mutex<Metrics>>,
+        config: SharedConfig,
+    ) -> Result<Self> {
         let key = std::env::var("ENCRYPTION_KEY")
             .expect("ENCRYPTION_KEY environment variable must be set");
 
@@ -71,15 +101,49 @@ impl ClientService {
             pool,
             encryption: Arc::new(EncryptionService::new(&key_bytes)?),
             metrics,
+            feature_flags: FeatureFlags::default(),
+            storage: None,
+            pipeline: Arc::new(Pipeline::standard(config.clone())),
+            auth_throttle: Arc::new(AuthThrottle::new(config.clone())),
+            config,
         })
     }
 
+    /// Replaces the standard middleware pipeline, e.g. to register extra middlewares.
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = Arc::new(pipeline);
+        self
+    }
+
+    /// Sets the storage attachment content is kept in.
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Sets the feature flags consulted for messages of new connections.
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlags) -> Self {
+        self.feature_flags = feature_flags;
+        self
+    }
+
+    /// Returns the encryption service shared with the clients.
+    pub fn encryption(&self) -> Arc<EncryptionService> {
+        Arc::clone(&self.encryption)
+    }
+
     /// Handles a new client connection.
     ///
     /// This method:
     /// 1. Assigns a unique ID to the client
-    /// 2. Creates a new connection record
-    /// 3. Spawns a new task to handle the connection
+    /// 2. Creates a new connection record with its outbound queue
+    /// 3. Spawns a writer task draining the queue into the socket
+    /// 4. Spawns a new task to handle the connection, which first expects the
+    ///    client's `Hello` and closes the connection if the client speaks
+    ///    another protocol version
+    ///
+    /// Frames are read in CBOR or Protobuf, and written in the codec of the
+    /// client's `Hello`. Frames above `max_frame_size` close the connection.
     ///
     /// # Arguments
     /// * `stream` - The TCP stream for the new client connection
@@ -88,18 +152,124 @@ impl ClientService {
     /// * `Result<()>` - Success or error handling the connection
     pub async fn handle_new_client(&self, stream: TcpStream) -> Result<()> {
         let addr = stream.peer_addr()?;
+        let transfer = self.metered_transfer().await;
+        let (read_half, write_half) = tokio::io::split(Metered::new(stream, transfer.clone()));
+        let codecs = Codecs::default().with_codec(Arc::new(Protobuf));
+        let read_half = CodecReader::new(read_half, codecs.clone())
+            .with_max_frame_size(self.config.current().max_frame_size);
+        let write_half = CodecWriter::new(write_half, codecs);
+
+        let (outbound, health) = self.register(read_half, addr, None, transfer, true).await;
+        outbound_queue::spawn_writer(write_half, outbound, health);
+        Ok(())
+    }
+
+    /// Handles a new client connection after its TLS handshake.
+    ///
+    /// Like [`handle_new_client`](Self::handle_new_client), but a client that
+    /// presented a registered certificate is logged in without a password.
+    ///
+    /// # Arguments
+    /// * `stream` - The TLS stream for the new client connection
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success or error handling the connection
+    pub async fn handle_new_tls_client(&self, stream: TlsStream<TcpStream>) -> Result<()> {
+        let addr = stream.get_ref().0.peer_addr()?;
+        let fingerprint = tls::peer_fingerprint(&stream);
+        let transfer = self.metered_transfer().await;
+        let (read_half, write_half) = tokio::io::split(Metered::new(stream, transfer.clone()));
+        let codecs = Codecs::default().with_codec(Arc::new(Protobuf));
+        let read_half = CodecReader::new(read_half, codecs.clone())
+            .with_max_frame_size(self.config.current().max_frame_size);
+        let write_half = CodecWriter::new(write_half, codecs);
+
+        let (outbound, health) = self
+            .register(read_half, addr, fingerprint, transfer, true)
+            .await;
+        outbound_queue::spawn_writer(write_half, outbound, health);
+        Ok(())
+    }
+
+    /// Serves a client over any message stream, such as a gRPC call.
+    ///
+    /// The connection is registered and handled like a TCP client, but the
+    /// caller delivers the messages queued for the client. No `Hello` is
+    /// expected, as the stream's own protocol is versioned.
+    ///
+    /// # Arguments
+    /// * `stream` - The stream the client's messages are read from
+    /// * `addr` - Remote address of the client
+    ///
+    /// # Returns
+    /// * `(OutboundQueue, Arc<ConnectionHealth>)` - The client's outbound queue,
+    ///   and the statistics the caller records its writes in
+    pub async fn handle_stream<S>(
+        &self,
+        stream: S,
+        addr: SocketAddr,
+    ) -> (OutboundQueue, Arc<ConnectionHealth>)
+    where
+        S: AsyncMessageStream + Send + 'static,
+    {
+        self.register(stream, addr, None, Default::default(), false)
+            .await
+    }
+
+    /// Creates the transfer counters of a connection served over a socket.
+    async fn metered_transfer(&self) -> Arc<ConnectionTransfer> {
+        let metrics = self.metrics.lock().await;
+        Arc::new(ConnectionTransfer::new(
+            metrics.bytes_read.clone(),
+            metrics.bytes_written.clone(),
+        ))
+    }
+
+    /// Registers a connection and spawns the task handling it.
+    ///
+    /// # Arguments
+    /// * `stream` - The stream the client's messages are read from
+    /// * `addr` - Remote address of the client
+    /// * `client_certificate` - Fingerprint of the TLS client certificate, if any
+    /// * `transfer` - Counters of the bytes transferred over the connection
+    /// * `handshake` - Whether the client has to open with a `Hello`
+    async fn register<S>(
+        &self,
+        stream: S,
+        addr: SocketAddr,
+        client_certificate: Option<String>,
+        transfer: Arc<ConnectionTransfer>,
+        handshake: bool,
+    ) -> (OutboundQueue, Arc<ConnectionHealth>)
+    where
+        S: AsyncMessageStream + Send + 'static,
+    {
         let clients = Arc::clone(&self.clients);
         let pool = Arc::clone(&self.pool);
         let metrics = self.metrics.clone();
 
-        let (read_half, write_half) = stream.into_split();
-
         let client_id = self.next_id.fetch_add(1, Ordering::SeqCst);
 
+        let config = self.config.current();
+        let outbound = OutboundQueue::new(
+            config.outbound_queue_capacity,
+            config.backpressure_policy,
+            metrics.clone(),
+        );
+        let health = Arc::new(ConnectionHealth::new(config.slow_write_threshold));
+
         let connection = ChatRoomConnection {
             user_id: None,
-            writer: write_half,
+            workspace_id: None,
+            outbound: outbound.clone(),
             auth_state: AuthState::NotAuthenticated,
+            peer_addr: addr,
+            connected_at: chrono::Utc::now().naive_utc(),
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            failed_logins: 0,
+            do_not_disturb: DndSettings::default(),
+            transfer,
+            capabilities: Vec::new(),
         };
 
         {
@@ -109,18 +279,36 @@ impl ClientService {
 
         info!("New client connected: {} with ID: {}", addr, client_id);
 
-        let mut connection_service =
-            ConnectionService::new(clients, pool, Arc::clone(&self.encryption), metrics);
+        let mut connection_service = ConnectionService::new(
+            clients,
+            pool,
+            Arc::clone(&self.encryption),
+            metrics,
+            self.config.clone(),
+        )
+        .with_feature_flags(self.feature_flags.clone())
+        .with_storage(self.storage.clone())
+        .with_pipeline(Arc::clone(&self.pipeline))
+        .with_auth_throttle(Arc::clone(&self.auth_throttle))
+        .with_client_certificate(client_certificate)
+        .with_handshake(handshake);
 
-        tokio::spawn(async move {
-            if let Err(e) = connection_service
-                .handle_connection(client_id, read_half)
-                .await
-            {
-                error!("Error handling connection from {}: {}", addr, e);
+        // Everything logged while handling the connection carries its context;
+        // the user is recorded once the client logs in
+        let span = info_span!("connection", client_id, %addr, user = field::Empty);
+        let connection_health = Arc::clone(&health);
+        tokio::spawn(
+            async move {
+                if let Err(e) = connection_service
+                    .handle_connection(client_id, stream, addr, connection_health)
+                    .await
+                {
+                    error!("Error handling connection from {}: {}", addr, e);
+                }
             }
-        });
+            .instrument(span),
+        );
 
-        Ok(())
+        (outbound, health)
     }
 }
@@ -0,0 +1,140 @@
+//! Reloading of the server configuration without a restart.
+//!
+//! Settings may be kept in a dotenv-style file named by `CONFIG_FILE`. When the
+//! server receives `SIGHUP` it re-reads that file into the environment and
+//! parses the settings again. A configuration containing invalid values is
+//! rejected as a whole and the running one is kept; otherwise every changed
+//! setting is logged and the new configuration replaces the old one.
+
+use crate::config::{ConfigChange, ServerConfig, SharedConfig};
+use std::env;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Environment variable naming the configuration file
+pub const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+
+/// Applies a new log filter, e.g. by reloading the tracing subscriber.
+pub type LogLevelHandler = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+pub struct ConfigReloader {
+    config: SharedConfig,
+    set_log_level: LogLevelHandler,
+}
+
+impl ConfigReloader {
+    pub fn new(config: SharedConfig, set_log_level: LogLevelHandler) -> Self {
+        Self {
+            config,
+            set_log_level,
+        }
+    }
+
+    /// Loads the configuration file into the environment, if one is configured.
+    ///
+    /// Values in the file override variables that are already set.
+    pub fn load_config_file() -> Result<(), String> {
+        match env::var(CONFIG_FILE_VAR) {
+            Ok(path) => dotenvy::from_path_override(&path)
+                .map_err(|e| format!("Failed to read config file {}: {}", path, e)),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Re-reads the configuration and applies it if it is valid.
+    ///
+    /// # Returns
+    /// * `Result<Vec<ConfigChange>, Vec<String>>` - The applied changes, or why
+    ///   the new configuration was rejected
+    pub fn reload(&self) -> Result<Vec<ConfigChange>, Vec<String>> {
+        Self::load_config_file().map_err(|e| vec![e])?;
+        let new = ServerConfig::try_from_env()?;
+
+        let changes = self.config.current().changes(&new);
+        if changes.iter().any(|change| change.setting == "log_level") {
+            (self.set_log_level)(&new.log_level).map_err(|e| vec![e])?;
+        }
+
+        self.config.replace(new);
+        Ok(changes)
+    }
+
+    /// Reloads the configuration and logs the outcome.
+    pub fn reload_and_log(&self) {
+        match self.reload() {
+            Ok(changes) if changes.is_empty() => {
+                info!("Configuration reloaded, no settings changed");
+            }
+            Ok(changes) => {
+                for change in changes {
+                    if change.restart_required {
+                        warn!(
+                            "Configuration changed: {} (takes effect after a restart)",
+                            change
+                        );
+                    } else {
+                        info!("Configuration changed: {}", change);
+                    }
+                }
+            }
+            Err(errors) => {
+                for e in errors {
+                    error!("Configuration reload rejected: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Spawns a task reloading the configuration whenever the process receives `SIGHUP`.
+    #[cfg(unix)]
+    pub fn spawn_on_sighup(self) -> std::io::Result<JoinHandle<()>> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup())?;
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                info!("Received SIGHUP, reloading configuration");
+                self.reload_and_log();
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_reload_applies_valid_config_only() {
+        let path = env::temp_dir().join(format!("chat-server-reload-{}.env", std::process::id()));
+        env::set_var(CONFIG_FILE_VAR, &path);
+
+        let shared = SharedConfig::new(ServerConfig::default());
+        let log_levels = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&log_levels);
+        let reloader = ConfigReloader::new(
+            shared.clone(),
+            Box::new(move |level| {
+                recorded.lock().unwrap().push(level.to_string());
+                Ok(())
+            }),
+        );
+
+        std::fs::write(&path, "HEARTBEAT_MAX_MISSED=9\nLOG_LEVEL=debug\n").unwrap();
+        let changes = reloader.reload().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(shared.current().heartbeat_max_missed, 9);
+        assert_eq!(*log_levels.lock().unwrap(), vec!["debug".to_string()]);
+
+        std::fs::write(&path, "HEARTBEAT_MAX_MISSED=many\n").unwrap();
+        let errors = reloader.reload().unwrap_err();
+        assert!(errors[0].contains("HEARTBEAT_MAX_MISSED"));
+        assert_eq!(shared.current().heartbeat_max_missed, 9);
+
+        std::fs::remove_file(&path).unwrap();
+        env::remove_var(CONFIG_FILE_VAR);
+        env::remove_var("HEARTBEAT_MAX_MISSED");
+        env::remove_var("LOG_LEVEL");
+    }
+}
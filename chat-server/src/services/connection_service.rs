@@ -1,21 +1,145 @@
+use crate::config::SharedConfig;
+use crate::i18n;
+use crate::services::auth_throttle::AuthThrottle;
+use crate::services::feature_flags::FeatureFlags;
+use crate::services::message::pipeline::Pipeline;
+use crate::services::storage::Storage;
 use crate::types::Clients;
 use crate::utils::db_connection::DbPool;
-use crate::utils::metrics::Metrics;
+use crate::utils::log_sampling::LogSampler;
+use crate::utils::metrics::{ErrorClass, Metrics};
 use anyhow::Result;
 use chat_common::async_message_stream::AsyncMessageStream;
+use chat_common::{ChatError, ErrorCode, Message, CAPABILITIES, PROTOCOL_VERSION};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::net::tcp::OwnedReadHalf;
-use tokio::sync::Mutex;
-use tracing::error;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, error, warn};
 
 use super::message::handler::MessageService;
 use chat_common::encryption::EncryptionService;
 
+/// Samples the per-message log event shared by all connections.
+static RECEIVED_MESSAGES: LogSampler = LogSampler::new();
+
+/// Time a client has to send its `Hello` after connecting
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for a rejected client's error to be written before closing
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Reason for the health monitor to drop a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnhealthyReason {
+    /// The peer stopped answering heartbeats
+    MissedHeartbeats,
+    /// Writes to the peer repeatedly exceeded the latency threshold
+    SlowConsumer,
+}
+
+impl UnhealthyReason {
+    /// Returns the label used for logs and metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnhealthyReason::MissedHeartbeats => "missed_heartbeats",
+            UnhealthyReason::SlowConsumer => "slow_consumer",
+        }
+    }
+}
+
+/// Write-latency statistics for a single connection.
+///
+/// The writer task records every write; the connection service inspects the
+/// statistics on each heartbeat tick.
+#[derive(Debug)]
+pub struct ConnectionHealth {
+    slow_write_threshold: Duration,
+    last_write_latency_ms: AtomicU64,
+    consecutive_slow_writes: AtomicU32,
+    /// Milliseconds since `created_at` at which the in-flight write started, or 0 if idle
+    write_started_ms: AtomicU64,
+    created_at: Instant,
+}
+
+impl ConnectionHealth {
+    /// Creates health statistics using the given slow-write threshold.
+    pub fn new(slow_write_threshold: Duration) -> Self {
+        Self {
+            slow_write_threshold,
+            last_write_latency_ms: AtomicU64::new(0),
+            consecutive_slow_writes: AtomicU32::new(0),
+            write_started_ms: AtomicU64::new(0),
+            created_at: Instant::now(),
+        }
+    }
+
+    /// Marks the start of a write to the socket.
+    pub fn write_started(&self) {
+        let elapsed = self.created_at.elapsed().as_millis() as u64;
+        self.write_started_ms
+            .store(elapsed.max(1), Ordering::SeqCst);
+    }
+
+    /// Records a completed write and its latency.
+    pub fn write_finished(&self, latency: Duration) {
+        self.write_started_ms.store(0, Ordering::SeqCst);
+        self.last_write_latency_ms
+            .store(latency.as_millis() as u64, Ordering::SeqCst);
+
+        if latency > self.slow_write_threshold {
+            self.consecutive_slow_writes.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.consecutive_slow_writes.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns the latency of the most recently completed write.
+    pub fn last_write_latency(&self) -> Duration {
+        Duration::from_millis(self.last_write_latency_ms.load(Ordering::SeqCst))
+    }
+
+    /// Returns how long the current write has been blocked, if one is in flight.
+    pub fn pending_write_duration(&self) -> Option<Duration> {
+        match self.write_started_ms.load(Ordering::SeqCst) {
+            0 => None,
+            started => {
+                let now = self.created_at.elapsed().as_millis() as u64;
+                Some(Duration::from_millis(now.saturating_sub(started)))
+            }
+        }
+    }
+
+    /// Returns `true` if the peer exceeded the slow-consumer allowance.
+    ///
+    /// A peer is slow when `max_consecutive` writes in a row took longer than the
+    /// threshold, or when a single write has been stuck for that many thresholds.
+    pub fn is_slow(&self, max_consecutive: u32) -> bool {
+        if self.consecutive_slow_writes.load(Ordering::SeqCst) >= max_consecutive {
+            return true;
+        }
+
+        self.pending_write_duration()
+            .is_some_and(|pending| pending > self.slow_write_threshold * max_consecutive)
+    }
+}
+
 pub struct ConnectionService {
     clients: Clients,
     pool: Arc<DbPool>,
     encryption: Arc<EncryptionService>,
     metrics: Arc<Mutex<Metrics>>,
+    config: SharedConfig,
+    feature_flags: FeatureFlags,
+    storage: Option<Arc<dyn Storage>>,
+    pipeline: Arc<Pipeline>,
+    auth_throttle: Arc<AuthThrottle>,
+    /// Fingerprint of the TLS client certificate the peer presented
+    client_certificate: Option<String>,
+    /// Whether the peer has to open the connection with a `Hello`
+    requires_handshake: bool,
 }
 
 impl ConnectionService {
@@ -24,39 +148,405 @@ impl ConnectionService {
         pool: Arc<DbPool>,
         encryption: Arc<EncryptionService>,
         metrics: Arc<Mutex<Metrics>>,
+        config: SharedConfig,
     ) -> Self {
         Self {
             clients,
             pool,
             encryption,
             metrics,
+            feature_flags: FeatureFlags::default(),
+            storage: None,
+            pipeline: Arc::new(Pipeline::standard(config.clone())),
+            auth_throttle: Arc::new(AuthThrottle::new(config.clone())),
+            client_certificate: None,
+            requires_handshake: false,
+            config,
         }
     }
 
-    pub async fn handle_connection(
+    /// Requires the peer to open the connection with a `Hello` of the
+    /// server's protocol version.
+    pub fn with_handshake(mut self, handshake: bool) -> Self {
+        self.requires_handshake = handshake;
+        self
+    }
+
+    /// Sets the fingerprint of the TLS client certificate the peer presented,
+    /// which logs the connection in if it is registered for an account.
+    pub fn with_client_certificate(mut self, fingerprint: Option<String>) -> Self {
+        self.client_certificate = fingerprint;
+        self
+    }
+
+    /// Sets the middleware pipeline incoming messages are processed with.
+    pub fn with_pipeline(mut self, pipeline: Arc<Pipeline>) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Sets the failed-login limits shared with other connections.
+    pub fn with_auth_throttle(mut self, auth_throttle: Arc<AuthThrottle>) -> Self {
+        self.auth_throttle = auth_throttle;
+        self
+    }
+
+    /// Sets the storage attachment content is kept in.
+    pub fn with_storage(mut self, storage: Option<Arc<dyn Storage>>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Sets the feature flags consulted for incoming messages.
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlags) -> Self {
+        self.feature_flags = feature_flags;
+        self
+    }
+
+    /// Runs the read loop for a connection until it closes or becomes unhealthy.
+    ///
+    /// Incoming frames are read on a separate task so the heartbeat timer never
+    /// interrupts a partially read frame. On every heartbeat tick the peer is
+    /// pinged, and it is disconnected if it missed too many heartbeats or its
+    /// writes are persistently slow. If a handshake is required, the peer's
+    /// `Hello` is read first and the connection is closed if it does not
+    /// speak the server's protocol version. A peer that presented a TLS client
+    /// certificate is then logged in with it.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the connection
+    /// * `stream` - The read half of the client's stream, such as a TCP socket
+    /// * `addr` - Remote address of the client, for logs
+    /// * `health` - Write statistics recorded by the connection's writer task
+    pub async fn handle_connection<S>(
         &mut self,
         client_id: usize,
-        mut stream: OwnedReadHalf,
-    ) -> Result<()> {
-        let addr = stream.peer_addr()?;
-        let message_service = MessageService::new(
-            self.clients.clone(),
-            Arc::clone(&self.pool),
-            Arc::clone(&self.encryption),
-            self.metrics.clone(),
-        );
+        mut stream: S,
+        addr: SocketAddr,
+        health: Arc<ConnectionHealth>,
+    ) -> Result<()>
+    where
+        S: AsyncMessageStream + Send + 'static,
+    {
+        if self.requires_handshake && !self.handshake(client_id, &mut stream, addr).await {
+            self.message_service().handle_disconnect(client_id).await?;
+            return Ok(());
+        }
 
-        while let Ok(message) = stream.read_message().await {
-            if let Err(e) = message_service
-                .process_message(Some(&stream), client_id, &message)
+        if let Some(fingerprint) = self.client_certificate.clone() {
+            if let Err(e) = self
+                .message_service()
+                .login_with_certificate(client_id, &fingerprint)
                 .await
             {
-                error!("Error processing message from {}: {}", addr, e);
-                break;
+                error!("Certificate login of {} failed: {}", addr, e);
             }
         }
 
-        message_service.handle_disconnect(client_id).await?;
+        // The reader stops at the first error, which is passed on so an
+        // oversized frame can be answered before the connection is closed
+        let (tx, mut rx) = mpsc::channel(16);
+        let max_frame_size = stream.max_frame_size();
+        let reader = tokio::spawn(async move {
+            loop {
+                let read = stream.read_message().await;
+                let failed = read.is_err();
+                if tx.send(read).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let mut heartbeat = tokio::time::interval(self.config.current().heartbeat_interval);
+        heartbeat.tick().await;
+        let mut missed_heartbeats = 0;
+        let mut seen_since_ping = true;
+        let mut nonce = 0u64;
+
+        loop {
+            tokio::select! {
+                message = rx.recv() => {
+                    let message = match message {
+                        Some(Ok(message)) => message,
+                        Some(Err(ChatError::FrameTooLarge(reason))) => {
+                            self.reject_oversized_frame(client_id, addr, max_frame_size, &reason)
+                                .await;
+                            break;
+                        }
+                        _ => break,
+                    };
+                    seen_since_ping = true;
+                    missed_heartbeats = 0;
+
+                    match message {
+                        Message::Pong { .. } => {}
+                        Message::Ping { nonce } => {
+                            self.send_to(client_id, Message::Pong { nonce }).await;
+                        }
+                        message => {
+                            let sample_rate = self.config.current().log_sample_rate;
+                            if let Some(occurrences) = RECEIVED_MESSAGES.sample(sample_rate) {
+                                debug!(occurrences, "Received message");
+                            }
+                            if let Err(e) = self
+                                .message_service()
+                                .process_message(None, client_id, &message)
+                                .await
+                            {
+                                error!("Error processing message from {}: {}", addr, e);
+                                self.metrics.lock().await.count_error(ErrorClass::of(&e));
+                                break;
+                            }
+                        }
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if !seen_since_ping {
+                        missed_heartbeats += 1;
+                    }
+
+                    let config = self.config.current();
+                    let unhealthy = if missed_heartbeats >= config.heartbeat_max_missed {
+                        Some(UnhealthyReason::MissedHeartbeats)
+                    } else if health.is_slow(config.slow_write_max_consecutive) {
+                        Some(UnhealthyReason::SlowConsumer)
+                    } else {
+                        None
+                    };
+
+                    if let Some(reason) = unhealthy {
+                        warn!(
+                            client_id,
+                            %addr,
+                            reason = reason.as_str(),
+                            missed_heartbeats,
+                            last_write_latency_ms = health.last_write_latency().as_millis() as u64,
+                            "Disconnecting unhealthy client"
+                        );
+                        self.metrics
+                            .lock()
+                            .await
+                            .unhealthy_disconnects
+                            .with_label_values(&[reason.as_str()])
+                            .inc();
+                        break;
+                    }
+
+                    nonce += 1;
+                    seen_since_ping = false;
+                    self.send_to(client_id, Message::Ping { nonce }).await;
+                }
+            }
+        }
+
+        reader.abort();
+        self.message_service().handle_disconnect(client_id).await?;
         Ok(())
     }
+
+    /// Reads the peer's `Hello` and answers it with the server's, or with an
+    /// `UnsupportedProtocol` error if the peer speaks another version. The
+    /// capabilities the peer advertised are kept on its connection.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the peer may go on using the connection
+    async fn handshake<S>(&self, client_id: usize, stream: &mut S, addr: SocketAddr) -> bool
+    where
+        S: AsyncMessageStream + Send,
+    {
+        let version = match tokio::time::timeout(HANDSHAKE_TIMEOUT, stream.read_message()).await {
+            Ok(Ok(Message::Hello {
+                protocol_version,
+                capabilities,
+            })) => {
+                if protocol_version == PROTOCOL_VERSION {
+                    debug!(?capabilities, "Handshake completed");
+                    if let Some(client) = self.clients.lock().await.get_mut(&client_id) {
+                        client.capabilities = capabilities;
+                    }
+                    let hello = Message::Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+                    };
+                    self.send_to(client_id, hello).await;
+                    return true;
+                }
+                Some(protocol_version)
+            }
+            // Anything else, including frames of an older layout
+            Ok(Ok(_)) | Ok(Err(ChatError::UnsupportedProtocol(_))) => None,
+            Ok(Err(ChatError::FrameTooLarge(reason))) => {
+                let max_frame_size = stream.max_frame_size();
+                self.reject_oversized_frame(client_id, addr, max_frame_size, &reason)
+                    .await;
+                return false;
+            }
+            Ok(Err(e)) => {
+                debug!(
+                    "Connection from {} closed before the handshake: {}",
+                    addr, e
+                );
+                return false;
+            }
+            Err(_) => {
+                warn!(
+                    "Client {} sent no Hello within {:?}",
+                    addr, HANDSHAKE_TIMEOUT
+                );
+                return false;
+            }
+        };
+
+        warn!(
+            %addr,
+            protocol_version = version,
+            "Rejected client speaking another protocol version"
+        );
+        self.metrics.lock().await.count_error(ErrorClass::Protocol);
+        let details = BTreeMap::from([
+            ("reason".to_string(), "unsupported_protocol".to_string()),
+            (
+                "supported_version".to_string(),
+                PROTOCOL_VERSION.to_string(),
+            ),
+        ]);
+        let message = Message::Error {
+            code: ErrorCode::UnsupportedProtocol,
+            message: i18n::text(
+                i18n::DEFAULT_LOCALE,
+                "protocol-unsupported",
+                &[("supported", PROTOCOL_VERSION.into())],
+            ),
+            in_reply_to: None,
+            details,
+        };
+        self.send_to(client_id, message).await;
+        self.flush(client_id).await;
+        false
+    }
+
+    /// Tells a client that sent a frame above `max_frame_size` bytes why its
+    /// connection is about to be closed.
+    async fn reject_oversized_frame(
+        &self,
+        client_id: usize,
+        addr: SocketAddr,
+        max_frame_size: usize,
+        reason: &str,
+    ) {
+        warn!(%addr, reason, "Rejected oversized frame");
+        self.metrics.lock().await.count_error(ErrorClass::Protocol);
+        let locale = match self.clients.lock().await.get(&client_id) {
+            Some(connection) => connection.locale.clone(),
+            None => i18n::DEFAULT_LOCALE.to_string(),
+        };
+        let details = BTreeMap::from([
+            ("reason".to_string(), "frame_too_large".to_string()),
+            ("max_frame_size".to_string(), max_frame_size.to_string()),
+        ]);
+        let message = Message::Error {
+            code: ErrorCode::InvalidInput,
+            message: i18n::text(
+                &locale,
+                "frame-too-large",
+                &[("limit", max_frame_size.into())],
+            ),
+            in_reply_to: None,
+            details,
+        };
+        self.send_to(client_id, message).await;
+        self.flush(client_id).await;
+    }
+
+    /// Waits briefly for the messages queued for a client to be written, so
+    /// a last error reaches it before the connection is closed.
+    async fn flush(&self, client_id: usize) {
+        let Some(outbound) = self
+            .clients
+            .lock()
+            .await
+            .get(&client_id)
+            .map(|client| client.outbound.clone())
+        else {
+            return;
+        };
+        let _ = tokio::time::timeout(FLUSH_TIMEOUT, async {
+            while !outbound.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+    }
+
+    /// Creates a message service using the configuration currently in effect.
+    fn message_service(&self) -> MessageService {
+        let config = self.config.current();
+        let service = MessageService::new(
+            self.clients.clone(),
+            Arc::clone(&self.pool),
+            Arc::clone(&self.encryption),
+            self.metrics.clone(),
+        )
+        .with_attachment_policy(config.attachment_policy.clone())
+        .with_duplicate_login_policy(config.duplicate_login_policy)
+        .with_onboarding(config.onboarding.clone())
+        .with_history_limit(config.history_replay_limit)
+        .with_feature_flags(self.feature_flags.clone())
+        .with_pipeline(Arc::clone(&self.pipeline))
+        .with_auth_throttle(Arc::clone(&self.auth_throttle));
+        match &self.storage {
+            Some(storage) => service.with_storage(Arc::clone(storage)),
+            None => service,
+        }
+    }
+
+    /// Queues a message for a single connection, ignoring delivery failures.
+    async fn send_to(&self, client_id: usize, message: Message) {
+        let clients = self.clients.lock().await;
+        if let Some(client) = clients.get(&client_id) {
+            let _ = client.send(&message).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consecutive_slow_writes_mark_client_slow() {
+        let health = ConnectionHealth::new(Duration::from_millis(10));
+
+        health.write_finished(Duration::from_millis(50));
+        health.write_finished(Duration::from_millis(50));
+        assert!(!health.is_slow(3));
+
+        health.write_finished(Duration::from_millis(50));
+        assert!(health.is_slow(3));
+        assert_eq!(health.last_write_latency(), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_fast_write_resets_slow_streak() {
+        let health = ConnectionHealth::new(Duration::from_millis(10));
+
+        health.write_finished(Duration::from_millis(50));
+        health.write_finished(Duration::from_millis(50));
+        health.write_finished(Duration::from_millis(1));
+        health.write_finished(Duration::from_millis(50));
+
+        assert!(!health.is_slow(3));
+    }
+
+    #[tokio::test]
+    async fn test_stuck_write_marks_client_slow() {
+        let health = ConnectionHealth::new(Duration::from_millis(5));
+
+        health.write_started();
+        assert!(!health.is_slow(2));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(health.pending_write_duration().is_some());
+        assert!(health.is_slow(2));
+    }
 }
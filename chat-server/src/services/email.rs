@@ -0,0 +1,251 @@
+//! Outgoing email.
+//!
+//! Emails are rendered from templates and put on a [`RetryQueue`], which
+//! delivers them through the configured transport and retries failed
+//! deliveries. Without SMTP settings emails are only logged, so development
+//! setups need no mail server.
+
+use crate::config::{ServerConfig, SmtpConfig, SmtpTls};
+use crate::repositories::notification::NotificationRepository;
+use crate::repositories::user::UserRepository;
+use crate::services::retry_queue::{RetryQueue, Transport};
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chrono::Utc;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use rocket::async_trait;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// The kinds of email the server sends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailTemplate {
+    /// Asks a new user to confirm their email address
+    Verification { username: String, link: String },
+    /// Lets a user choose a new password
+    PasswordReset { username: String, link: String },
+    /// Summarizes notifications the user has not read yet
+    Digest {
+        username: String,
+        notifications: Vec<String>,
+    },
+}
+
+impl EmailTemplate {
+    pub fn subject(&self) -> String {
+        match self {
+            EmailTemplate::Verification { .. } => "Confirm your email address".to_string(),
+            EmailTemplate::PasswordReset { .. } => "Reset your password".to_string(),
+            EmailTemplate::Digest { notifications, .. } => match notifications.len() {
+                1 => "You have 1 unread notification".to_string(),
+                count => format!("You have {} unread notifications", count),
+            },
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self {
+            EmailTemplate::Verification { username, link } => format!(
+                "Hi {},\n\nplease confirm your email address by opening this link:\n\n{}\n\n\
+                 If you did not create an account, you can ignore this email.\n",
+                username, link
+            ),
+            EmailTemplate::PasswordReset { username, link } => format!(
+                "Hi {},\n\nsomeone asked to reset the password of your account. Open this link \
+                 to choose a new one:\n\n{}\n\nIf it was not you, you can ignore this email.\n",
+                username, link
+            ),
+            EmailTemplate::Digest {
+                username,
+                notifications,
+            } => {
+                let items: String = notifications
+                    .iter()
+                    .map(|notification| format!("- {}\n", notification))
+                    .collect();
+                format!(
+                    "Hi {},\n\nhere is what you missed:\n\n{}\nLog in to read the full conversations.\n",
+                    username, items
+                )
+            }
+        }
+    }
+}
+
+/// A rendered email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl fmt::Display for Email {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "email '{}' to {}", self.subject, self.to)
+    }
+}
+
+/// Delivers email through an SMTP server.
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpTransport {
+    pub fn new(config: &SmtpConfig) -> Result<Self> {
+        let builder = match config.tls {
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        }
+        .port(config.port);
+
+        let builder = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport<Email> for SmtpTransport {
+    async fn deliver(&self, email: &Email) -> Result<()> {
+        let message = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(&email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+/// Logs emails instead of sending them, used when SMTP is not configured.
+pub struct LogTransport;
+
+#[async_trait]
+impl Transport<Email> for LogTransport {
+    async fn deliver(&self, email: &Email) -> Result<()> {
+        info!(
+            "Email delivery is disabled, not sending '{}' to {}",
+            email.subject, email.to
+        );
+        Ok(())
+    }
+}
+
+/// Queues emails for delivery by a background worker.
+#[derive(Clone)]
+pub struct EmailService {
+    queue: RetryQueue<Email>,
+}
+
+impl EmailService {
+    /// Creates the service and spawns its delivery worker.
+    pub fn new(transport: Arc<dyn Transport<Email>>) -> Self {
+        Self {
+            queue: RetryQueue::new(transport),
+        }
+    }
+
+    /// Creates the service for the configured SMTP server, or one that only
+    /// logs emails if none is configured.
+    pub fn from_config(config: &ServerConfig) -> Result<Self> {
+        let transport: Arc<dyn Transport<Email>> = match &config.smtp {
+            Some(smtp) => Arc::new(SmtpTransport::new(smtp)?),
+            None => Arc::new(LogTransport),
+        };
+        Ok(Self::new(transport))
+    }
+
+    /// Renders a template and queues the email for delivery.
+    ///
+    /// # Arguments
+    /// * `to` - The recipient's address
+    /// * `template` - The email to send
+    pub async fn send(&self, to: &str, template: EmailTemplate) -> Result<()> {
+        let email = Email {
+            to: to.to_string(),
+            subject: template.subject(),
+            body: template.body(),
+        };
+        self.queue.push(email).await
+    }
+
+    /// Spawns a task emailing every user a digest of the notifications they
+    /// received and have not read during each interval.
+    pub fn spawn_digests(&self, pool: Arc<DbPool>, interval: Duration) -> JoinHandle<()> {
+        let email = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = email.send_digests(&pool, interval).await {
+                    error!("Failed to send notification digests: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Emails a digest of the unread notifications created in the last `period`.
+    async fn send_digests(&self, pool: &DbPool, period: Duration) -> Result<()> {
+        let conn = &mut *pool.get().await?;
+        let since = (Utc::now() - chrono::Duration::from_std(period)?).naive_utc();
+        let notifications = NotificationRepository::find_unread_since(conn, since).await?;
+
+        for batch in notifications.chunk_by(|a, b| a.user_id == b.user_id) {
+            let user = UserRepository::find_by_id(conn, batch[0].user_id).await?;
+            let template = EmailTemplate::Digest {
+                username: user.username,
+                notifications: batch
+                    .iter()
+                    .map(|notification| notification.content.clone())
+                    .collect(),
+            };
+            self.send(&user.email, template).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates() {
+        let digest = EmailTemplate::Digest {
+            username: "alice".to_string(),
+            notifications: vec!["bob mentioned you".to_string(), "Export ready".to_string()],
+        };
+        assert_eq!(digest.subject(), "You have 2 unread notifications");
+        assert!(digest
+            .body()
+            .contains("- bob mentioned you\n- Export ready\n"));
+
+        let reset = EmailTemplate::PasswordReset {
+            username: "alice".to_string(),
+            link: "https://chat.example.com/reset/abc".to_string(),
+        };
+        assert!(reset.body().starts_with("Hi alice,"));
+        assert!(reset.body().contains("https://chat.example.com/reset/abc"));
+    }
+}
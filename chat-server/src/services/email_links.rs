@@ -0,0 +1,183 @@
+//! Emailed links that confirm an email address or reset a forgotten password.
+//!
+//! Each link carries a random token. Only its SHA-256 is stored, together with
+//! the user, what the token is for and when it expires; emailing a new link
+//! replaces the user's earlier one for the same purpose, and a token works
+//! only once. Links point to the web admin at `PUBLIC_URL`, which sends the
+//! token back to the REST API.
+
+use crate::models::email_token::{NewEmailToken, PASSWORD_RESET_PURPOSE, VERIFICATION_PURPOSE};
+use crate::models::user::User;
+use crate::repositories::email_token::EmailTokenRepository;
+use crate::repositories::user::UserRepository;
+use crate::services::email::{EmailService, EmailTemplate};
+use crate::services::password::{PasswordChangeError, PasswordService};
+use anyhow::Result;
+use chat_common::encryption::file::sha256_hex;
+use chrono::Duration;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use thiserror::Error;
+use tracing::info;
+
+/// How long a link confirming an email address can be used
+const VERIFICATION_TTL_HOURS: i64 = 48;
+/// How long a password reset link can be used
+const PASSWORD_RESET_TTL_HOURS: i64 = 1;
+
+/// Why a password could not be reset.
+#[derive(Debug, Error)]
+pub enum PasswordResetError {
+    #[error("The reset link is invalid or has expired")]
+    InvalidToken,
+    #[error(transparent)]
+    Password(#[from] PasswordChangeError),
+}
+
+impl From<diesel::result::Error> for PasswordResetError {
+    fn from(e: diesel::result::Error) -> Self {
+        PasswordResetError::Password(e.into())
+    }
+}
+
+/// Builds the link of a page of the web admin that is given a token.
+fn link(public_url: &str, page: &str, token: &str) -> String {
+    format!("{}/{}?token={}", public_url, page, token)
+}
+
+/// Emails a user a link confirming their email address.
+///
+/// # Arguments
+/// * `conn` - Connection the token is stored with
+/// * `email` - Service the email is queued with
+/// * `public_url` - Address of the web admin
+/// * `user` - The user whose address is confirmed
+pub async fn send_verification(
+    conn: &mut AsyncPgConnection,
+    email: &EmailService,
+    public_url: &str,
+    user: &User,
+) -> Result<()> {
+    let (token, new_token) = NewEmailToken::generate(
+        user.id,
+        VERIFICATION_PURPOSE,
+        &user.email,
+        Duration::hours(VERIFICATION_TTL_HOURS),
+    );
+    EmailTokenRepository::replace(conn, new_token).await?;
+
+    let template = EmailTemplate::Verification {
+        username: user.username.clone(),
+        link: link(public_url, "verify-email", &token),
+    };
+    email.send(&user.email, template).await
+}
+
+/// Confirms the email address a verification link was sent to.
+///
+/// # Returns
+/// * `Result<Option<i32>>` - The user whose address was confirmed, or None if
+///   the token is invalid, expired or the user changed their address since
+pub async fn verify_email(conn: &mut AsyncPgConnection, token: &str) -> Result<Option<i32>> {
+    let Some(token) =
+        EmailTokenRepository::take(conn, &sha256_hex(token.as_bytes()), VERIFICATION_PURPOSE)
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    let user = UserRepository::find_by_id(conn, token.user_id).await?;
+    if !user.email.eq_ignore_ascii_case(&token.email) {
+        return Ok(None);
+    }
+    EmailTokenRepository::mark_verified(conn, user.id, &user.email).await?;
+    Ok(Some(user.id))
+}
+
+/// Emails a password reset link to the user with this address, if there is
+/// one. Callers answer the same either way, so the request does not reveal
+/// which addresses are registered.
+///
+/// # Arguments
+/// * `conn` - Connection the user is looked up and the token stored with
+/// * `email` - Service the email is queued with
+/// * `public_url` - Address of the web admin
+/// * `address` - The address the reset was requested for
+pub async fn send_password_reset(
+    conn: &mut AsyncPgConnection,
+    email: &EmailService,
+    public_url: &str,
+    address: &str,
+) -> Result<()> {
+    let Some(user) = UserRepository::find_by_email(conn, address).await? else {
+        info!("Password reset requested for an unknown address");
+        return Ok(());
+    };
+
+    let (token, new_token) = NewEmailToken::generate(
+        user.id,
+        PASSWORD_RESET_PURPOSE,
+        &user.email,
+        Duration::hours(PASSWORD_RESET_TTL_HOURS),
+    );
+    EmailTokenRepository::replace(conn, new_token).await?;
+
+    let template = EmailTemplate::PasswordReset {
+        username: user.username,
+        link: link(public_url, "reset-password", &token),
+    };
+    email.send(&user.email, template).await
+}
+
+/// Sets a new password with a token from a password reset link.
+///
+/// The token is used up in the same transaction that sets the password, so
+/// concurrent requests cannot both use it, while a rejected password rolls
+/// the transaction back and can be corrected with the same link. Tokens sent
+/// before the user changed their email address are refused.
+///
+/// # Arguments
+/// * `conn` - Connection the token is used up and the password set with
+/// * `passwords` - Policy the new password is checked against
+/// * `token` - The token from the link
+/// * `new_password` - The password to set
+///
+/// # Returns
+/// * `Result<i32, PasswordResetError>` - The user whose password was reset
+pub async fn reset_password(
+    conn: &mut AsyncPgConnection,
+    passwords: &PasswordService,
+    token: &str,
+    new_password: &str,
+) -> Result<i32, PasswordResetError> {
+    let token_sha256 = sha256_hex(token.as_bytes());
+    conn.transaction(|conn| {
+        async move {
+            let token = EmailTokenRepository::take(conn, &token_sha256, PASSWORD_RESET_PURPOSE)
+                .await?
+                .ok_or(PasswordResetError::InvalidToken)?;
+
+            let user = UserRepository::find_by_id(conn, token.user_id).await?;
+            if !user.email.eq_ignore_ascii_case(&token.email) {
+                return Err(PasswordResetError::InvalidToken);
+            }
+            passwords.reset(conn, &user, new_password).await?;
+            Ok(user.id)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link() {
+        assert_eq!(
+            link("https://chat.example.com", "reset-password", "abc"),
+            "https://chat.example.com/reset-password?token=abc"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Per-user data export.
+//!
+//! Exports are generated by a background job that gathers the user's profile,
+//! workspaces, messages and attachment metadata into a JSON document on disk.
+//! Once the document is written, a notification is added to the user's inbox
+//! and the file can be downloaded through the REST API.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::models::message::{Message, MessageType};
+use crate::models::notification::{NewNotification, NotificationKind};
+use crate::models::user::User;
+use crate::models::workspace::Workspace;
+use crate::repositories::message::MessageRepository;
+use crate::repositories::user::UserRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::notification::NotificationService;
+use crate::types::Clients;
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/// Progress of a user's most recent export request.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ExportStatus {
+    Pending {
+        requested_at: NaiveDateTime,
+    },
+    Ready {
+        completed_at: NaiveDateTime,
+        #[serde(skip)]
+        path: PathBuf,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Profile fields included in an export; the password hash is never exported.
+#[derive(Serialize, Debug)]
+pub struct ExportedProfile {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Metadata of a file or image the user sent.
+#[derive(Serialize, Debug)]
+pub struct ExportedAttachment {
+    pub message_id: i32,
+    pub message_type: String,
+    pub file_name: Option<String>,
+    pub workspace_id: i32,
+    pub created_at: NaiveDateTime,
+    /// SHA-256 of the attachment's content
+    pub sha256: Option<String>,
+}
+
+/// The complete export document of one user.
+#[derive(Serialize, Debug)]
+pub struct UserExport {
+    pub exported_at: NaiveDateTime,
+    pub profile: ExportedProfile,
+    pub workspaces: Vec<Workspace>,
+    pub messages: Vec<Message>,
+    pub attachments: Vec<ExportedAttachment>,
+}
+
+impl UserExport {
+    /// Assembles an export from the user's records.
+    pub fn new(user: User, workspaces: Vec<Workspace>, messages: Vec<Message>) -> Self {
+        let attachments = messages
+            .iter()
+            .filter(|message| {
+                matches!(message.message_type, MessageType::File | MessageType::Image)
+            })
+            .map(|message| ExportedAttachment {
+                message_id: message.id,
+                message_type: message.message_type.to_string(),
+                file_name: message.file_name.clone(),
+                workspace_id: message.workspace_id,
+                created_at: message.created_at,
+                sha256: message.sha256.clone(),
+            })
+            .collect();
+
+        Self {
+            exported_at: Utc::now().naive_utc(),
+            profile: ExportedProfile {
+                id: user.id,
+                username: user.username,
+                email: user.email,
+                created_at: user.created_at,
+                updated_at: user.updated_at,
+            },
+            workspaces,
+            messages,
+            attachments,
+        }
+    }
+}
+
+/// Runs export jobs and tracks their status per user.
+pub struct ExportService {
+    pool: Arc<DbPool>,
+    notifications: Arc<NotificationService>,
+    export_dir: PathBuf,
+    jobs: Arc<Mutex<HashMap<i32, ExportStatus>>>,
+}
+
+impl ExportService {
+    /// Creates a new `ExportService` instance.
+    ///
+    /// # Arguments
+    /// * `pool` - A shared database connection pool
+    /// * `clients` - Connected chat clients, used to announce finished exports
+    /// * `export_dir` - Directory the export documents are written to
+    pub fn new(pool: Arc<DbPool>, clients: Clients, export_dir: PathBuf) -> Self {
+        Self {
+            notifications: Arc::new(NotificationService::new(Arc::clone(&pool), clients)),
+            pool,
+            export_dir,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the status of the user's latest export, if one was requested.
+    pub async fn status(&self, user_id: i32) -> Option<ExportStatus> {
+        self.jobs.lock().await.get(&user_id).cloned()
+    }
+
+    /// Starts a background export for the user.
+    ///
+    /// If an export for the user is already running, its status is returned
+    /// instead of starting another one.
+    ///
+    /// # Returns
+    /// * `ExportStatus` - The status of the running export
+    pub async fn start(&self, user_id: i32) -> ExportStatus {
+        let mut jobs = self.jobs.lock().await;
+        if let Some(status @ ExportStatus::Pending { .. }) = jobs.get(&user_id) {
+            return status.clone();
+        }
+
+        let status = ExportStatus::Pending {
+            requested_at: Utc::now().naive_utc(),
+        };
+        jobs.insert(user_id, status.clone());
+
+        let pool = Arc::clone(&self.pool);
+        let notifications = Arc::clone(&self.notifications);
+        let jobs = Arc::clone(&self.jobs);
+        let path = self.export_path(user_id);
+
+        tokio::spawn(async move {
+            let status = match Self::generate(&pool, user_id, &path).await {
+                Ok(()) => {
+                    info!("Export for user {} written to {}", user_id, path.display());
+                    Self::notify(&notifications, user_id).await;
+                    ExportStatus::Ready {
+                        completed_at: Utc::now().naive_utc(),
+                        path,
+                    }
+                }
+                Err(e) => {
+                    error!("Export for user {} failed: {}", user_id, e);
+                    ExportStatus::Failed {
+                        error: e.to_string(),
+                    }
+                }
+            };
+            jobs.lock().await.insert(user_id, status);
+        });
+
+        status
+    }
+
+    /// Forgets the user's export and removes its document from disk.
+    pub async fn discard(&self, user_id: i32) {
+        self.jobs.lock().await.remove(&user_id);
+
+        let path = self.export_path(user_id);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => info!("Removed export {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => error!("Failed to remove export {}: {}", path.display(), e),
+        }
+    }
+
+    fn export_path(&self, user_id: i32) -> PathBuf {
+        self.export_dir.join(format!("user-{}.json", user_id))
+    }
+
+    /// Gathers the user's data and writes the export document.
+    async fn generate(pool: &DbPool, user_id: i32, path: &PathBuf) -> Result<()> {
+        let export = {
+            let conn = &mut *pool.get().await?;
+            let user = UserRepository::find_by_id(conn, user_id).await?;
+            let workspaces = WorkspaceRepository::find_for_user(conn, user_id).await?;
+            let messages = MessageRepository::find_by_sender(conn, user_id).await?;
+            UserExport::new(user, workspaces, messages)
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_vec_pretty(&export)?).await?;
+        Ok(())
+    }
+
+    /// Adds a system notification about the finished export to the user's inbox.
+    async fn notify(notifications: &NotificationService, user_id: i32) {
+        let notification = NewNotification {
+            user_id,
+            kind: NotificationKind::System,
+            actor_id: None,
+            message_id: None,
+            content: "Your data export is ready: GET /users/me/export".to_string(),
+        };
+
+        if let Err(e) = notifications.notify(notification).await {
+            error!("Failed to notify user {} about export: {}", user_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i32, message_type: MessageType, file_name: Option<&str>) -> Message {
+        let now = Utc::now().naive_utc();
+        Message {
+            id,
+            sender_id: 1,
+            message_type,
+            content: None,
+            file_name: file_name.map(str::to_string),
+            created_at: now,
+            updated_at: now,
+            workspace_id: 1,
+            sha256: None,
+            edited_at: None,
+            seq: id as i64,
+            attachment_size: None,
+        }
+    }
+
+    #[test]
+    fn test_export_collects_attachments_without_password() {
+        let now = Utc::now().naive_utc();
+        let user = User {
+            id: 1,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: "secret-hash".to_string(),
+            created_at: now,
+            updated_at: now,
+        };
+        let messages = vec![
+            message(1, MessageType::Text, None),
+            message(2, MessageType::File, Some("report.pdf")),
+            message(3, MessageType::Image, Some("cat.png")),
+        ];
+
+        let export = UserExport::new(user, Vec::new(), messages);
+        let json = serde_json::to_string(&export).unwrap();
+
+        assert_eq!(export.messages.len(), 3);
+        assert_eq!(export.attachments.len(), 2);
+        assert_eq!(export.attachments[1].message_type, "image");
+        assert!(!json.contains("secret-hash"));
+    }
+}
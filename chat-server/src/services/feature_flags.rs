@@ -0,0 +1,187 @@
+//! Runtime feature flags.
+//!
+//! Flags live in the `feature_flags` table and are cached in memory, so the TCP
+//! processor and the REST routes can consult them on every request without a
+//! query. A flag can be enabled for a percentage of users to roll a subsystem
+//! out gradually: each user falls into a stable bucket derived from the flag
+//! name and user ID, so raising the percentage only ever adds users. Flags
+//! that are not in the table fall back to their built-in default.
+
+use crate::models::feature_flag::FeatureFlag;
+use crate::repositories::feature_flag::FeatureFlagRepository;
+use crate::utils::db_connection::DbPool;
+use diesel::QueryResult;
+use diesel_async::AsyncPgConnection;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// Ephemeral messages with a TTL
+pub const EPHEMERAL_MESSAGES: &str = "ephemeral_messages";
+/// Low and urgent message priorities
+pub const MESSAGE_PRIORITIES: &str = "message_priorities";
+/// File and image attachments
+pub const ATTACHMENTS: &str = "attachments";
+/// Per-user data exports
+pub const DATA_EXPORT: &str = "data_export";
+/// Polls and votes
+pub const POLLS: &str = "polls";
+
+/// Known flags and whether they are enabled when not set in the database
+pub const DEFAULT_FLAGS: &[(&str, bool)] = &[
+    (EPHEMERAL_MESSAGES, true),
+    (MESSAGE_PRIORITIES, true),
+    (ATTACHMENTS, true),
+    (DATA_EXPORT, true),
+    (POLLS, true),
+];
+
+/// How often the cache is reloaded to pick up changes made elsewhere
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// In-process cache of the feature flags, shared by the TCP server and Rocket.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureFlags {
+    flags: Arc<RwLock<HashMap<String, FeatureFlag>>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the flag is enabled for the given user.
+    ///
+    /// Without a user only flags rolled out to everyone are enabled.
+    pub fn is_enabled(&self, name: &str, user_id: Option<i32>) -> bool {
+        match self.flags.read().unwrap().get(name) {
+            Some(flag) => {
+                flag.enabled
+                    && (flag.rollout_percentage >= 100
+                        || user_id.is_some_and(|user_id| {
+                            rollout_bucket(name, user_id) < flag.rollout_percentage
+                        }))
+            }
+            None => DEFAULT_FLAGS
+                .iter()
+                .any(|(known, enabled)| *known == name && *enabled),
+        }
+    }
+
+    /// Names of all flags enabled for the given user.
+    pub fn enabled_for(&self, user_id: i32) -> Vec<String> {
+        let mut names: Vec<String> = DEFAULT_FLAGS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .chain(self.flags.read().unwrap().keys().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        names.retain(|name| self.is_enabled(name, Some(user_id)));
+        names
+    }
+
+    /// Stores a flag in the cache after it was written to the database.
+    pub fn set(&self, flag: FeatureFlag) {
+        self.flags.write().unwrap().insert(flag.name.clone(), flag);
+    }
+
+    /// Removes a flag from the cache after it was deleted from the database.
+    pub fn remove(&self, name: &str) {
+        self.flags.write().unwrap().remove(name);
+    }
+
+    /// Replaces the cache with the flags stored in the database.
+    pub async fn refresh(&self, conn: &mut AsyncPgConnection) -> QueryResult<()> {
+        let flags = FeatureFlagRepository::find_all(conn).await?;
+        *self.flags.write().unwrap() = flags
+            .into_iter()
+            .map(|flag| (flag.name.clone(), flag))
+            .collect();
+        Ok(())
+    }
+
+    /// Spawns a task refreshing the cache every [`REFRESH_INTERVAL`].
+    pub fn spawn_refresh(&self, pool: Arc<DbPool>) -> JoinHandle<()> {
+        let flags = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let result = match pool.get().await {
+                    Ok(mut conn) => flags.refresh(&mut conn).await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = result {
+                    error!("Failed to refresh feature flags: {}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Places a user in one of 100 rollout buckets for a flag.
+///
+/// Uses FNV-1a so the bucket is stable across restarts and Rust versions.
+fn rollout_bucket(name: &str, user_id: i32) -> i32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in name.bytes().chain(user_id.to_be_bytes()) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    (hash % 100) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn flag(name: &str, enabled: bool, rollout_percentage: i32) -> FeatureFlag {
+        FeatureFlag {
+            name: name.to_string(),
+            enabled,
+            rollout_percentage,
+            description: String::new(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_defaults_apply_to_unset_flags() {
+        let flags = FeatureFlags::new();
+        assert!(flags.is_enabled(ATTACHMENTS, None));
+        assert!(!flags.is_enabled("reactions", Some(1)));
+
+        flags.set(flag(ATTACHMENTS, false, 100));
+        assert!(!flags.is_enabled(ATTACHMENTS, Some(1)));
+
+        flags.remove(ATTACHMENTS);
+        assert!(flags.is_enabled(ATTACHMENTS, Some(1)));
+    }
+
+    #[test]
+    fn test_gradual_rollout() {
+        let flags = FeatureFlags::new();
+        flags.set(flag("reactions", true, 0));
+        assert!((1..=1000).all(|user_id| !flags.is_enabled("reactions", Some(user_id))));
+
+        flags.set(flag("reactions", true, 30));
+        let enabled: Vec<i32> = (1..=1000)
+            .filter(|user_id| flags.is_enabled("reactions", Some(*user_id)))
+            .collect();
+        assert!((200..400).contains(&enabled.len()));
+        assert!(!flags.is_enabled("reactions", None));
+
+        // Raising the percentage keeps every user that already had the flag
+        flags.set(flag("reactions", true, 60));
+        assert!(enabled
+            .iter()
+            .all(|user_id| flags.is_enabled("reactions", Some(*user_id))));
+        assert!(flags
+            .enabled_for(enabled[0])
+            .contains(&"reactions".to_string()));
+    }
+}
@@ -0,0 +1,276 @@
+//! Connection pool and Redis health.
+//!
+//! The monitor samples every database pool and the Redis pool: it records how
+//! many connections are available, in use and waited for, and times a probe
+//! that checks out a connection (and pings Redis). The same probes back the
+//! readiness check, so an exhausted pool shows up in the metrics before
+//! requests start failing.
+
+use crate::utils::db_connection::DbPool;
+use crate::utils::metrics::Metrics;
+use diesel_async::RunQueryDsl;
+use rocket_db_pools::deadpool_redis;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// How long a probe waits for a connection before counting a timeout
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the pools are sampled in the background
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Occupancy of a connection pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PoolStats {
+    pub max_size: usize,
+    pub available: usize,
+    pub in_use: usize,
+    pub waiting: usize,
+}
+
+impl PoolStats {
+    fn from_diesel(pool: &DbPool) -> Self {
+        let status = pool.status();
+        Self::from_signed(status.max_size, status.size, status.available)
+    }
+
+    /// Converts a status whose `available` count turns negative to report
+    /// waiting tasks, as the diesel pools do.
+    fn from_signed(max_size: usize, size: usize, available: isize) -> Self {
+        let waiting = (-available).max(0) as usize;
+        let available = available.max(0) as usize;
+        Self {
+            max_size,
+            available,
+            in_use: size.saturating_sub(available),
+            waiting,
+        }
+    }
+
+    fn from_redis(pool: &deadpool_redis::Pool) -> Self {
+        let status = pool.status();
+        Self {
+            max_size: status.max_size,
+            available: status.available,
+            in_use: status.size.saturating_sub(status.available),
+            waiting: status.waiting,
+        }
+    }
+
+    fn record(&self, metrics: &Metrics, pool: &str) {
+        let connections = &metrics.pool_connections;
+        connections
+            .with_label_values(&[pool, "available"])
+            .set(self.available as f64);
+        connections
+            .with_label_values(&[pool, "in_use"])
+            .set(self.in_use as f64);
+        metrics
+            .pool_max_size
+            .with_label_values(&[pool])
+            .set(self.max_size as f64);
+        metrics
+            .pool_waiting
+            .with_label_values(&[pool])
+            .set(self.waiting as f64);
+    }
+}
+
+/// Result of probing one pool.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolCheck {
+    pub pool: String,
+    pub healthy: bool,
+    #[serde(flatten)]
+    pub stats: PoolStats,
+    /// Time spent getting a connection (and running the probe command)
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of the readiness check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<PoolCheck>,
+}
+
+/// Samples the server's connection pools.
+#[derive(Clone)]
+pub struct PoolMonitor {
+    db_pools: Vec<(String, DbPool)>,
+    redis: Option<deadpool_redis::Pool>,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl PoolMonitor {
+    pub fn new(metrics: Arc<Mutex<Metrics>>) -> Self {
+        Self {
+            db_pools: Vec::new(),
+            redis: None,
+            metrics,
+        }
+    }
+
+    /// Adds a database pool, labelled `name` in metrics and checks.
+    pub fn with_db_pool(mut self, name: &str, pool: DbPool) -> Self {
+        self.db_pools.push((name.to_string(), pool));
+        self
+    }
+
+    /// Adds the Redis pool.
+    pub fn with_redis(mut self, pool: deadpool_redis::Pool) -> Self {
+        self.redis = Some(pool);
+        self
+    }
+
+    /// Updates the pool gauges without probing, e.g. right before a scrape.
+    pub async fn record_stats(&self) {
+        let metrics = self.metrics.lock().await;
+        for (name, pool) in &self.db_pools {
+            PoolStats::from_diesel(pool).record(&metrics, name);
+        }
+        if let Some(redis) = &self.redis {
+            PoolStats::from_redis(redis).record(&metrics, "redis");
+        }
+    }
+
+    /// Probes every pool and records the results.
+    pub async fn check(&self) -> Readiness {
+        let mut checks = Vec::new();
+        for (name, pool) in &self.db_pools {
+            checks.push(self.check_db_pool(name, pool).await);
+        }
+        if let Some(redis) = &self.redis {
+            checks.push(self.check_redis(redis).await);
+        }
+
+        Readiness {
+            ready: checks.iter().all(|check| check.healthy),
+            checks,
+        }
+    }
+
+    /// Spawns a task probing the pools every [`SAMPLE_INTERVAL`].
+    pub fn spawn(&self) -> JoinHandle<()> {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                for check in monitor.check().await.checks {
+                    if let Some(error) = &check.error {
+                        warn!("Pool '{}' is unhealthy: {}", check.pool, error);
+                    }
+                }
+            }
+        })
+    }
+
+    async fn check_db_pool(&self, name: &str, pool: &DbPool) -> PoolCheck {
+        let started = Instant::now();
+        let conn = tokio::time::timeout(PROBE_TIMEOUT, pool.get()).await;
+        let waited = started.elapsed();
+        let timed_out = conn.is_err();
+        let result = match conn {
+            Ok(Ok(mut conn)) => diesel::sql_query("SELECT 1")
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err(format!("No connection within {:?}", PROBE_TIMEOUT)),
+        };
+
+        let stats = PoolStats::from_diesel(pool);
+        self.record_probe(name, stats, waited, timed_out).await;
+        PoolCheck {
+            pool: name.to_string(),
+            healthy: result.is_ok(),
+            stats,
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: result.err(),
+        }
+    }
+
+    async fn check_redis(&self, pool: &deadpool_redis::Pool) -> PoolCheck {
+        let started = Instant::now();
+        let conn = tokio::time::timeout(PROBE_TIMEOUT, pool.get()).await;
+        let waited = started.elapsed();
+        let timed_out = conn.is_err();
+        let result = match conn {
+            Ok(Ok(mut conn)) => {
+                let sent = Instant::now();
+                let pong = deadpool_redis::redis::cmd("PING")
+                    .query_async::<_, String>(&mut conn)
+                    .await;
+                let metrics = self.metrics.lock().await;
+                match pong {
+                    Ok(_) => {
+                        metrics
+                            .redis_command_seconds
+                            .observe(sent.elapsed().as_secs_f64());
+                        Ok(())
+                    }
+                    Err(e) => {
+                        metrics.redis_errors.inc();
+                        Err(e.to_string())
+                    }
+                }
+            }
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err(format!("No connection within {:?}", PROBE_TIMEOUT)),
+        };
+
+        let stats = PoolStats::from_redis(pool);
+        self.record_probe("redis", stats, waited, timed_out).await;
+        PoolCheck {
+            pool: "redis".to_string(),
+            healthy: result.is_ok(),
+            stats,
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: result.err(),
+        }
+    }
+
+    async fn record_probe(&self, pool: &str, stats: PoolStats, waited: Duration, timed_out: bool) {
+        let metrics = self.metrics.lock().await;
+        stats.record(&metrics, pool);
+        metrics
+            .pool_wait_seconds
+            .with_label_values(&[pool])
+            .observe(waited.as_secs_f64());
+        if timed_out {
+            metrics.pool_timeouts.with_label_values(&[pool]).inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diesel_pool_stats() {
+        let idle = PoolStats::from_signed(5, 3, 2);
+        assert_eq!(
+            idle,
+            PoolStats {
+                max_size: 5,
+                available: 2,
+                in_use: 1,
+                waiting: 0
+            }
+        );
+
+        // An exhausted pool reports waiting tasks as negative availability
+        let exhausted = PoolStats::from_signed(5, 5, -3);
+        assert_eq!(exhausted.available, 0);
+        assert_eq!(exhausted.in_use, 5);
+        assert_eq!(exhausted.waiting, 3);
+    }
+}
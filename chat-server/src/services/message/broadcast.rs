@@ -4,17 +4,18 @@
 //! such as authentication status and sender information.
 
 use anyhow::Result;
-use chat_common::async_message_stream::AsyncMessageStream;
-use chat_common::Message;
+use chat_common::{Message, SENDER_CAPABILITY};
+use chrono::Utc;
 use tracing::error;
 
-use crate::types::Clients;
+use crate::services::outbound_queue::OutboundQueue;
+use crate::types::{ChatRoomConnection, Clients};
 
 /// A service responsible for broadcasting messages to connected clients.
 ///
 /// The `MessageBroadcaster` handles different types of messages and ensures they are
 /// delivered to the appropriate clients based on message type and client authentication status.
-pub(super) struct MessageBroadcaster {
+pub(crate) struct MessageBroadcaster {
     clients: Clients,
 }
 
@@ -31,71 +32,221 @@ impl MessageBroadcaster {
     ///
     /// # Arguments
     /// * `message` - The message to send
-    /// * `should_send` - A predicate function that determines if a message should be sent to a client
+    /// * `should_send` - A predicate on a client's connection ID and connection that
+    ///   determines if the message should be sent to it
     ///
     /// # Returns
     /// * `Result<()>` - Ok if the operation completed successfully, Err otherwise
     ///
     /// # Note
-    /// This method automatically removes disconnected clients from the client list.
+    /// The client list is only locked to pick the recipients and to remove
+    /// failed ones, never while delivering. Nothing waits for a recipient: a
+    /// queue that is full under the `Block` policy keeps the message until its
+    /// writer catches up, so a slow client only delays itself and neither the
+    /// sender nor connections joining or leaving meanwhile are held up.
+    ///
+    /// This method automatically removes disconnected clients from the client list,
+    /// including clients whose outbound queue overflowed under the backpressure policy.
     async fn send_to_clients<F>(&self, message: &Message, should_send: F) -> Result<()>
     where
-        F: Fn(&mut crate::types::ChatRoomConnection) -> bool,
+        F: Fn(usize, &ChatRoomConnection) -> bool,
     {
-        let mut clients = self.clients.lock().await;
-        let mut failed_clients = Vec::new();
+        let recipients: Vec<(usize, OutboundQueue)> = self
+            .clients
+            .lock()
+            .await
+            .iter()
+            .filter(|(client_id, connection)| should_send(**client_id, connection))
+            .map(|(client_id, connection)| (*client_id, connection.outbound.clone()))
+            .collect();
 
-        for (client_id, connection) in clients.iter_mut() {
-            if should_send(connection) && (connection.writer.write_message(message).await).is_err()
-            {
-                failed_clients.push(*client_id);
+        let mut failed_clients = Vec::new();
+        for (client_id, outbound) in recipients {
+            if outbound.push_or_defer(message.clone()).await.is_err() {
+                failed_clients.push(client_id);
             }
         }
 
+        if failed_clients.is_empty() {
+            return Ok(());
+        }
+        let mut clients = self.clients.lock().await;
         for client_id in failed_clients {
-            clients.remove(&client_id);
+            if let Some(connection) = clients.remove(&client_id) {
+                connection.outbound.close().await;
+            }
             error!("Removed disconnected client {}", client_id);
         }
 
         Ok(())
     }
 
+    /// Pushes a message to every authenticated connection of a user, unless
+    /// the user is in do-not-disturb mode.
+    ///
+    /// # Arguments
+    /// * `user_id` - The recipient
+    /// * `message` - The message to push
+    ///
+    /// # Returns
+    /// * `Result<bool>` - Whether the message was pushed rather than held back
+    pub async fn push_to_user(&self, user_id: i32, message: &Message) -> Result<bool> {
+        let now = Utc::now().time();
+        let do_not_disturb = self.clients.lock().await.values().any(|connection| {
+            connection.user_id == Some(user_id) && connection.do_not_disturb.is_active(now)
+        });
+        if do_not_disturb {
+            return Ok(false);
+        }
+
+        self.send_to_clients(message, |_, connection| {
+            connection.is_authenticated() && connection.user_id == Some(user_id)
+        })
+        .await?;
+        Ok(true)
+    }
+
+    /// Tells the members of a workspace whose connections are in other
+    /// workspaces that a message was stored in it.
+    ///
+    /// # Arguments
+    /// * `workspace_id` - The workspace the message was stored in
+    /// * `slug` - The workspace's slug, which the clients know it by
+    /// * `members` - IDs of the workspace's members
+    pub async fn send_activity(
+        &self,
+        workspace_id: i32,
+        slug: &str,
+        members: &[i32],
+    ) -> Result<()> {
+        let message = Message::Activity {
+            workspace: slug.to_string(),
+        };
+        self.send_to_clients(&message, |_, connection| {
+            connection.is_authenticated()
+                && connection.workspace_id != Some(workspace_id)
+                && connection
+                    .user_id
+                    .is_some_and(|user_id| members.contains(&user_id))
+        })
+        .await
+    }
+
     /// Broadcasts a message to appropriate clients based on message type and sender.
     ///
+    /// Only the sending connection is excluded; other connections of the same
+    /// user, e.g. on another device, receive the message like everyone else.
+    ///
     /// # Arguments
     /// * `message` - The message to broadcast
-    /// * `sender_id` - The ID of the message sender (if any)
+    /// * `sender_client_id` - The connection ID of the message sender (if any)
+    /// * `workspace_id` - Restricts delivery to clients in this workspace (all clients if None)
     ///
     /// # Returns
     /// * `Result<()>` - Ok if the operation completed successfully, Err otherwise
     ///
     /// # Message Type Behavior
-    /// * Text/File/Image messages: Only sent to authenticated clients, excluding the sender
+    /// * Text/PriorityText/Ephemeral/Reply/Action/File/Image messages: Only sent to authenticated clients, excluding the sender
+    /// * Sequenced messages: Like the stored message they carry
+    /// * ChatMessage messages: Like the message they carry; clients without the
+    ///   `sender` capability get the carried message instead of the envelope
     /// * System messages: Sent to all clients, excluding the sender
-    /// * Auth/AuthResponse/Error messages: Not broadcast (handled separately)
+    /// * PollTally messages: Sent to all authenticated clients, including the voter
+    /// * StatusChanged messages: Sent to all authenticated clients, including the user
+    /// * Edit/Delete messages: Sent to all authenticated clients, including the author
+    /// * Auth/Register/AuthResponse/Error messages: Not broadcast (handled separately)
+    /// * Hello messages: Not broadcast (connection-level handshake)
+    /// * Ping/Pong messages: Not broadcast (connection-level heartbeats)
+    /// * SwitchWorkspace/ChangePassword messages: Not broadcast (handled by the processor)
+    /// * Notification messages: Not broadcast (delivered to their recipient only)
+    /// * Request messages: Not broadcast (unwrapped before processing)
+    /// * RoomKey messages: Not broadcast (wrapped for a single connection)
+    /// * History messages: Not broadcast (sent to a single connection after logging in)
+    /// * Poll/Vote messages: Not broadcast (answered with a PollTally)
+    /// * SetStatus messages: Not broadcast (answered with a StatusChanged)
+    /// * Report messages: Not broadcast (moderators are notified instead)
+    /// * Activity messages: Not broadcast (sent with `send_activity`)
     pub async fn broadcast_message(
         &self,
         message: &Message,
-        sender_id: Option<usize>,
+        sender_client_id: Option<usize>,
+        workspace_id: Option<i32>,
     ) -> Result<()> {
+        let in_workspace = |connection: &ChatRoomConnection| {
+            workspace_id.is_none() || connection.workspace_id == workspace_id
+        };
+        let is_sender = |client_id: usize| Some(client_id) == sender_client_id;
+
         match message {
-            Message::Text(_) | Message::File { .. } | Message::Image { .. } => {
-                // Only send to authenticated clients, excluding the sender
-                self.send_to_clients(message, |connection| {
+            Message::Text(_)
+            | Message::PriorityText { .. }
+            | Message::Ephemeral { .. }
+            | Message::Reply { .. }
+            | Message::Action { .. }
+            | Message::File { .. }
+            | Message::Image { .. }
+            | Message::Sequenced { .. } => {
+                // Only send to authenticated clients of the workspace, excluding the sender
+                self.send_to_clients(message, |client_id, connection| {
                     connection.is_authenticated()
-                        && Some(connection.user_id.unwrap_or_default() as usize) != sender_id
+                        && in_workspace(connection)
+                        && !is_sender(client_id)
+                })
+                .await
+            }
+            Message::ChatMessage { body, .. } => {
+                let is_recipient = |client_id: usize, connection: &ChatRoomConnection| {
+                    connection.is_authenticated()
+                        && in_workspace(connection)
+                        && !is_sender(client_id)
+                };
+                self.send_to_clients(message, |client_id, connection| {
+                    is_recipient(client_id, connection) && connection.supports(SENDER_CAPABILITY)
+                })
+                .await?;
+                // Older clients do not know the envelope
+                self.send_to_clients(body, |client_id, connection| {
+                    is_recipient(client_id, connection) && !connection.supports(SENDER_CAPABILITY)
                 })
                 .await
             }
             Message::System(_) => {
-                // Send to all clients, excluding the sender
-                self.send_to_clients(message, |connection| {
-                    Some(connection.user_id.unwrap_or_default() as usize) != sender_id
+                // Send to all clients of the workspace, excluding the sender
+                self.send_to_clients(message, |client_id, connection| {
+                    in_workspace(connection) && !is_sender(client_id)
+                })
+                .await
+            }
+            Message::PollTally { .. }
+            | Message::StatusChanged { .. }
+            | Message::Edit { .. }
+            | Message::Delete { .. } => {
+                // Everyone sees the change, including whoever caused it
+                self.send_to_clients(message, |_, connection| {
+                    connection.is_authenticated() && in_workspace(connection)
                 })
                 .await
             }
             // Don't broadcast auth-related messages
-            Message::Auth { .. } | Message::AuthResponse { .. } | Message::Error { .. } => Ok(()),
+            Message::Auth { .. }
+            | Message::Register { .. }
+            | Message::AuthResponse { .. }
+            | Message::Error { .. } => Ok(()),
+            // Handshakes and heartbeats only concern a single connection
+            Message::Hello { .. } | Message::Ping { .. } | Message::Pong { .. } => Ok(()),
+            Message::SwitchWorkspace { .. }
+            | Message::ChangePassword { .. }
+            | Message::Notification { .. }
+            | Message::Request { .. }
+            | Message::RoomKey { .. }
+            | Message::Poll { .. }
+            | Message::Vote { .. }
+            | Message::SetStatus { .. }
+            | Message::Report { .. }
+            | Message::Activity { .. }
+            | Message::Download { .. }
+            | Message::Attachment { .. }
+            | Message::History(_) => Ok(()),
         }
     }
 }
@@ -114,7 +265,7 @@ mod tests {
         let broadcaster = MessageBroadcaster::new(clients.clone());
 
         let message = Message::Text("Hello, World!".to_string());
-        let result = broadcaster.broadcast_message(&message, Some(1)).await;
+        let result = broadcaster.broadcast_message(&message, Some(1), None).await;
 
         assert!(result.is_ok());
     }
@@ -125,11 +276,221 @@ mod tests {
         let broadcaster = MessageBroadcaster::new(clients.clone());
 
         let message = Message::System("System message".to_string());
-        let result = broadcaster.broadcast_message(&message, Some(1)).await;
+        let result = broadcaster.broadcast_message(&message, Some(1), None).await;
 
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_broadcast_scoped_to_workspace() {
+        let same_workspace = ChatRoomConnection::for_test(2, 1);
+        let other_workspace = ChatRoomConnection::for_test(3, 2);
+        let (same_queue, other_queue) = (
+            same_workspace.outbound.clone(),
+            other_workspace.outbound.clone(),
+        );
+
+        let clients = Arc::new(Mutex::new(HashMap::from([
+            (2, same_workspace),
+            (3, other_workspace),
+        ])));
+        let broadcaster = MessageBroadcaster::new(clients.clone());
+
+        let message = Message::Text("Hello, workspace!".to_string());
+        broadcaster
+            .broadcast_message(&message, Some(1), Some(1))
+            .await
+            .unwrap();
+
+        assert_eq!(same_queue.len(), 1);
+        assert!(other_queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_activity_reaches_members_elsewhere() {
+        // Member 2 is in the workspace, member 3 elsewhere, user 4 no member
+        let connections = HashMap::from([
+            (1, ChatRoomConnection::for_test(2, 1)),
+            (2, ChatRoomConnection::for_test(3, 2)),
+            (3, ChatRoomConnection::for_test(4, 2)),
+        ]);
+        let queues: HashMap<usize, OutboundQueue> = connections
+            .iter()
+            .map(|(client_id, connection)| (*client_id, connection.outbound.clone()))
+            .collect();
+        let broadcaster = MessageBroadcaster::new(Arc::new(Mutex::new(connections)));
+
+        broadcaster
+            .send_activity(1, "general", &[2, 3])
+            .await
+            .unwrap();
+
+        assert!(queues[&1].is_empty());
+        assert_eq!(queues[&2].len(), 1);
+        assert!(queues[&3].is_empty());
+    }
+
+    /// Connections keyed by connection ID: user 3 on connection 2, user 2 on
+    /// connections 3 and 4. Connection IDs and user IDs deliberately overlap.
+    fn multi_device_clients() -> (Clients, HashMap<usize, OutboundQueue>) {
+        let connections = HashMap::from([
+            (2, ChatRoomConnection::for_test(3, 1)),
+            (3, ChatRoomConnection::for_test(2, 1)),
+            (4, ChatRoomConnection::for_test(2, 1)),
+        ]);
+        let queues = connections
+            .iter()
+            .map(|(client_id, connection)| (*client_id, connection.outbound.clone()))
+            .collect();
+        (Arc::new(Mutex::new(connections)), queues)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_excludes_only_sending_connection() {
+        let (clients, queues) = multi_device_clients();
+        let broadcaster = MessageBroadcaster::new(clients);
+
+        let message = Message::Text("Sent from connection 3".to_string());
+        broadcaster
+            .broadcast_message(&message, Some(3), Some(1))
+            .await
+            .unwrap();
+
+        // The sender's other device still gets the message, and the user whose
+        // ID equals the sending connection's ID is not skipped
+        assert!(queues[&3].is_empty());
+        assert_eq!(queues[&4].len(), 1);
+        assert_eq!(queues[&2].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_system_message_to_other_connections() {
+        let (clients, queues) = multi_device_clients();
+        let broadcaster = MessageBroadcaster::new(clients);
+
+        let message = Message::System("Joined".to_string());
+        broadcaster
+            .broadcast_message(&message, Some(2), Some(1))
+            .await
+            .unwrap();
+
+        assert!(queues[&2].is_empty());
+        assert_eq!(queues[&3].len(), 1);
+        assert_eq!(queues[&4].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deletion_reaches_all_connections() {
+        let (clients, queues) = multi_device_clients();
+        let broadcaster = MessageBroadcaster::new(clients);
+
+        let message = Message::Delete { message_id: 7 };
+        broadcaster
+            .broadcast_message(&message, Some(3), Some(1))
+            .await
+            .unwrap();
+
+        // The author's own connection learns about the deletion too
+        assert!(queues.values().all(|queue| queue.len() == 1));
+    }
+
+    #[tokio::test]
+    async fn test_sender_envelope_only_to_capable_clients() {
+        let (clients, queues) = multi_device_clients();
+        clients
+            .lock()
+            .await
+            .get_mut(&4)
+            .unwrap()
+            .capabilities
+            .push(SENDER_CAPABILITY.to_string());
+        let broadcaster = MessageBroadcaster::new(clients);
+
+        let text = Message::Text("ciphertext".to_string());
+        let message = Message::ChatMessage {
+            sender: "carol".to_string(),
+            sent_at: Utc::now(),
+            body: Box::new(text.clone()),
+        };
+        broadcaster
+            .broadcast_message(&message, Some(3), Some(1))
+            .await
+            .unwrap();
+
+        assert!(queues[&3].is_empty());
+        assert_eq!(queues[&4].pop().await, Some(message));
+        assert_eq!(queues[&2].pop().await, Some(text));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_does_not_block_other_clients() {
+        let (clients, queues) = multi_device_clients();
+        for _ in 0..4 {
+            queues[&2]
+                .push(Message::Text("Backlog".to_string()))
+                .await
+                .unwrap();
+        }
+
+        // Nobody drains connection 2, so waiting on it would never return
+        let message = Message::Text("Sent from connection 3".to_string());
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            MessageBroadcaster::new(clients.clone()).broadcast_message(&message, Some(3), Some(1)),
+        )
+        .await
+        .expect("broadcast waited for a full queue")
+        .unwrap();
+
+        assert_eq!(queues[&4].len(), 1);
+        assert_eq!(clients.lock().await.len(), 3);
+
+        // Connection 2 gets the message once its writer catches up
+        for _ in 0..4 {
+            queues[&2].pop().await;
+        }
+        assert_eq!(queues[&2].pop().await, Some(message));
+    }
+
+    #[tokio::test]
+    async fn test_push_to_every_connection_of_user() {
+        let (clients, queues) = multi_device_clients();
+        let broadcaster = MessageBroadcaster::new(clients);
+
+        let message = Message::System("You were mentioned".to_string());
+        assert!(broadcaster.push_to_user(2, &message).await.unwrap());
+
+        assert!(queues[&2].is_empty());
+        assert_eq!(queues[&3].len(), 1);
+        assert_eq!(queues[&4].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_push_held_back_during_do_not_disturb() {
+        use crate::models::do_not_disturb::DndSettings;
+
+        let connection = |user_id: i32, enabled: bool| ChatRoomConnection {
+            do_not_disturb: DndSettings {
+                enabled,
+                schedule: None,
+            },
+            ..ChatRoomConnection::for_test(user_id, 1)
+        };
+
+        let available = connection(2, false);
+        let busy = connection(3, true);
+        let (available_queue, busy_queue) = (available.outbound.clone(), busy.outbound.clone());
+        let clients = Arc::new(Mutex::new(HashMap::from([(2, available), (3, busy)])));
+        let broadcaster = MessageBroadcaster::new(clients);
+
+        let message = Message::System("You were mentioned".to_string());
+        assert!(broadcaster.push_to_user(2, &message).await.unwrap());
+        assert!(!broadcaster.push_to_user(3, &message).await.unwrap());
+
+        assert_eq!(available_queue.len(), 1);
+        assert!(busy_queue.is_empty());
+    }
+
     #[tokio::test]
     async fn test_broadcast_auth_message() {
         let clients = Arc::new(Mutex::new(HashMap::new()));
@@ -139,7 +500,7 @@ mod tests {
             username: "test".to_string(),
             password: "test".to_string(),
         };
-        let result = broadcaster.broadcast_message(&message, Some(1)).await;
+        let result = broadcaster.broadcast_message(&message, Some(1), None).await;
 
         assert!(result.is_ok());
     }
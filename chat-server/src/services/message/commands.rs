@@ -0,0 +1,371 @@
+//! Slash commands.
+//!
+//! Text messages starting with `/`, like `/me waves`, are commands. The
+//! `commands` middleware looks the command up in a [`CommandRegistry`] and
+//! runs it instead of sending the text as typed. A command either rewrites
+//! the message, which is then sent as usual, or answers only the sender.
+//!
+//! Messages starting with `//` are not commands; one slash is removed and the
+//! rest is sent as typed. New commands are added by implementing
+//! [`SlashCommand`] and registering it:
+//!
+//! ```ignore
+//! let commands = CommandRegistry::standard().register(Roll);
+//! let pipeline = Pipeline::standard(config)
+//!     .remove("commands")
+//!     .register_before("expiry", SlashCommands::new(commands));
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::i18n;
+use crate::repositories::user::UserRepository;
+use anyhow::Result;
+use chat_common::encryption::message::EncryptedMessage;
+use chat_common::{ErrorCode, Message};
+use rocket::async_trait;
+
+use super::pipeline::{Flow, MessageContext, Middleware};
+use super::processor::MessageProcessor;
+
+/// The command listing the available commands, answered by the middleware
+const HELP: &str = "help";
+
+/// What a command does with the message that invoked it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /// Send this text in place of the command
+    Send(String),
+    /// Answer only the sender and drop the message
+    Reply(String),
+    /// Reject the message, e.g. for missing arguments, telling the sender why
+    Reject(String),
+}
+
+/// A command invocation.
+#[derive(Debug)]
+pub struct Invocation<'a> {
+    pub user_id: i32,
+    pub username: &'a str,
+    pub workspace_id: i32,
+    /// Locale of the sender, for replies
+    pub locale: &'a str,
+    /// Everything after the command name, trimmed
+    pub args: &'a str,
+}
+
+/// A command users invoke with `/<name>`.
+#[async_trait]
+pub trait SlashCommand: Send + Sync {
+    /// Name the command is invoked with, without the slash
+    fn name(&self) -> &'static str;
+
+    /// One line describing the arguments and what the command does, for `/help`
+    fn usage(&self) -> &'static str;
+
+    /// Runs the command.
+    ///
+    /// # Arguments
+    /// * `processor` - Access to the database, clients and replies
+    /// * `invocation` - The sender and the command's arguments
+    async fn run(
+        &self,
+        processor: &MessageProcessor,
+        invocation: &Invocation<'_>,
+    ) -> Result<CommandOutcome>;
+}
+
+/// The commands available to users, by name.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Arc<dyn SlashCommand>>,
+}
+
+impl CommandRegistry {
+    /// Creates a registry without commands.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with the built-in commands `/me` and `/shrug`.
+    pub fn standard() -> Self {
+        Self::new().register(Me).register(Shrug)
+    }
+
+    /// Adds a command, replacing any command with the same name.
+    pub fn register(mut self, command: impl SlashCommand + 'static) -> Self {
+        self.commands.insert(command.name(), Arc::new(command));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn SlashCommand>> {
+        self.commands.get(name)
+    }
+
+    /// Names of the registered commands in alphabetical order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.commands.keys().copied().collect()
+    }
+
+    /// Lists `/help` and the registered commands with their usage, one per line.
+    pub fn help(&self, locale: &str) -> String {
+        let mut lines = vec![format!(
+            "/{} {}",
+            HELP,
+            i18n::text(locale, "command-help", &[])
+        )];
+        lines.extend(
+            self.commands
+                .values()
+                .map(|command| format!("/{} {}", command.name(), command.usage())),
+        );
+        lines.join("\n")
+    }
+}
+
+/// How a text message starts.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Parsed<'a> {
+    /// A command with its trimmed arguments
+    Command { name: &'a str, args: &'a str },
+    /// An escaped slash; the text to send without it
+    Escaped(&'a str),
+    /// Ordinary text
+    Text,
+}
+
+/// Parses the start of a text message.
+///
+/// Command names are ASCII letters, digits, `_` and `-`, so text like
+/// `/usr/bin is full` is not mistaken for a command.
+pub fn parse(text: &str) -> Parsed<'_> {
+    if text.starts_with("//") {
+        return Parsed::Escaped(&text[1..]);
+    }
+    let Some(rest) = text.strip_prefix('/') else {
+        return Parsed::Text;
+    };
+
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Parsed::Text;
+    }
+    Parsed::Command {
+        name,
+        args: args.trim(),
+    }
+}
+
+/// Sends an action in the third person: `/me waves` becomes `* alice waves`.
+pub struct Me;
+
+#[async_trait]
+impl SlashCommand for Me {
+    fn name(&self) -> &'static str {
+        "me"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<action> - describe what you are doing"
+    }
+
+    async fn run(
+        &self,
+        _processor: &MessageProcessor,
+        invocation: &Invocation<'_>,
+    ) -> Result<CommandOutcome> {
+        if invocation.args.is_empty() {
+            return Ok(CommandOutcome::Reject(i18n::text(
+                invocation.locale,
+                "command-me-usage",
+                &[],
+            )));
+        }
+        Ok(CommandOutcome::Send(format!(
+            "* {} {}",
+            invocation.username, invocation.args
+        )))
+    }
+}
+
+/// Appends a shrug to the message.
+pub struct Shrug;
+
+/// The shrug appended by `/shrug`
+const SHRUG: &str = "¯\\_(ツ)_/¯";
+
+#[async_trait]
+impl SlashCommand for Shrug {
+    fn name(&self) -> &'static str {
+        "shrug"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[message] - append ¯\\_(ツ)_/¯ to the message"
+    }
+
+    async fn run(
+        &self,
+        _processor: &MessageProcessor,
+        invocation: &Invocation<'_>,
+    ) -> Result<CommandOutcome> {
+        Ok(CommandOutcome::Send(shrug(invocation.args)))
+    }
+}
+
+fn shrug(text: &str) -> String {
+    if text.is_empty() {
+        SHRUG.to_string()
+    } else {
+        format!("{} {}", text, SHRUG)
+    }
+}
+
+/// Runs slash commands in text messages.
+pub struct SlashCommands {
+    registry: CommandRegistry,
+}
+
+impl SlashCommands {
+    pub fn new(registry: CommandRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+/// Returns the encrypted text of a text message.
+fn text_content(message: &mut Message) -> Option<&mut String> {
+    match message {
+        Message::Text(content)
+        | Message::PriorityText { content, .. }
+        | Message::Ephemeral { content, .. }
+        | Message::Reply { content, .. } => Some(content),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl Middleware for SlashCommands {
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (user_id, workspace_id) = ctx.sender()?;
+        let Some(content) = text_content(&mut ctx.message) else {
+            return Ok(Flow::Continue);
+        };
+        let encrypted: EncryptedMessage = serde_json::from_str(content)?;
+        let encryption = processor
+            .encryption()
+            .message_for(encrypted.key_id.as_deref())?;
+        let text = encryption.decrypt(&encrypted)?;
+
+        let outcome = match parse(&text) {
+            Parsed::Text => return Ok(Flow::Continue),
+            Parsed::Escaped(text) => CommandOutcome::Send(text.to_string()),
+            Parsed::Command { name: HELP, .. } => {
+                CommandOutcome::Reply(self.registry.help(processor.locale()))
+            }
+            Parsed::Command { name, args } => {
+                let Some(command) = self.registry.get(name) else {
+                    let reply = processor.error_reply(
+                        ErrorCode::InvalidInput,
+                        processor.text("command-unknown", &[("command", name.into())]),
+                        &[("reason", "unknown_command"), ("command", name)],
+                    );
+                    processor.reply(ctx.client_id, &reply).await?;
+                    return Ok(Flow::Stop);
+                };
+
+                let username = {
+                    let conn = &mut *processor.pool().get().await?;
+                    UserRepository::find_by_id(conn, user_id).await?.username
+                };
+                let invocation = Invocation {
+                    user_id,
+                    username: &username,
+                    workspace_id,
+                    locale: processor.locale(),
+                    args,
+                };
+                command.run(processor, &invocation).await?
+            }
+        };
+
+        match outcome {
+            CommandOutcome::Send(text) => {
+                let encrypted = encryption.encrypt(&text)?;
+                *content = serde_json::to_string(&encrypted)?;
+                Ok(Flow::Continue)
+            }
+            CommandOutcome::Reply(text) => {
+                processor
+                    .reply(ctx.client_id, &Message::System(text))
+                    .await?;
+                Ok(Flow::Stop)
+            }
+            CommandOutcome::Reject(reason) => {
+                let reply = processor.error_reply(
+                    ErrorCode::InvalidInput,
+                    reason,
+                    &[("reason", "invalid_command")],
+                );
+                processor.reply(ctx.client_id, &reply).await?;
+                Ok(Flow::Stop)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("/me  waves at everyone "),
+            Parsed::Command {
+                name: "me",
+                args: "waves at everyone"
+            }
+        );
+        assert_eq!(
+            parse("/shrug"),
+            Parsed::Command {
+                name: "shrug",
+                args: ""
+            }
+        );
+        assert_eq!(
+            parse("//me is not a command"),
+            Parsed::Escaped("/me is not a command")
+        );
+        assert_eq!(parse("/usr/bin is full"), Parsed::Text);
+        assert_eq!(parse("/ spaced"), Parsed::Text);
+        assert_eq!(parse("hello /me"), Parsed::Text);
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = CommandRegistry::standard();
+        assert_eq!(registry.names(), vec!["me", "shrug"]);
+        assert!(registry.get("poll").is_none());
+
+        let help = registry.help("en");
+        assert_eq!(help.lines().count(), 3);
+        assert!(help.starts_with("/help "));
+        assert!(help.contains("/me <action>"));
+    }
+
+    #[test]
+    fn test_shrug() {
+        assert_eq!(shrug(""), SHRUG);
+        assert_eq!(shrug("no idea"), "no idea ¯\\_(ツ)_/¯");
+    }
+}
@@ -6,11 +6,15 @@
 
 use std::sync::Arc;
 
+use crate::config::{AttachmentPolicy, DuplicateLoginPolicy, OnboardingConfig, SharedConfig};
+use crate::i18n;
+use crate::services::auth_throttle::AuthThrottle;
+use crate::services::feature_flags::FeatureFlags;
+use crate::services::storage::Storage;
 use crate::types::Clients;
 use crate::utils::db_connection::DbPool;
 use crate::utils::metrics::Metrics;
 use anyhow::Result;
-use chat_common::async_message_stream::AsyncMessageStream;
 use chat_common::encryption::file::EncryptedFileMetadata;
 use chat_common::encryption::message::EncryptedMessage;
 use chat_common::encryption::EncryptionService;
@@ -20,6 +24,7 @@ use tokio::net::tcp::OwnedReadHalf;
 use tokio::sync::Mutex;
 use tracing::{info, warn};
 
+use super::pipeline::Pipeline;
 use super::processor::MessageProcessor;
 
 /// Service responsible for handling incoming messages and managing client connections.
@@ -32,6 +37,14 @@ pub struct MessageService {
     pool: Arc<DbPool>,
     encryption: Arc<EncryptionService>,
     metrics: Arc<Mutex<Metrics>>,
+    attachment_policy: AttachmentPolicy,
+    duplicate_login_policy: DuplicateLoginPolicy,
+    onboarding: OnboardingConfig,
+    feature_flags: FeatureFlags,
+    storage: Option<Arc<dyn Storage>>,
+    pipeline: Arc<Pipeline>,
+    auth_throttle: Arc<AuthThrottle>,
+    history_limit: u32,
 }
 
 impl MessageService {
@@ -53,11 +66,71 @@ impl MessageService {
             pool,
             encryption,
             metrics,
+            attachment_policy: AttachmentPolicy::default(),
+            duplicate_login_policy: DuplicateLoginPolicy::Allow,
+            onboarding: OnboardingConfig::default(),
+            feature_flags: FeatureFlags::default(),
+            storage: None,
+            pipeline: Arc::new(Pipeline::standard(SharedConfig::default())),
+            auth_throttle: Arc::new(AuthThrottle::new(SharedConfig::default())),
+            history_limit: 0,
         }
     }
 
+    /// Sets the middleware pipeline messages are processed with.
+    pub fn with_pipeline(mut self, pipeline: Arc<Pipeline>) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Sets the failed-login limits shared with other connections.
+    pub fn with_auth_throttle(mut self, auth_throttle: Arc<AuthThrottle>) -> Self {
+        self.auth_throttle = auth_throttle;
+        self
+    }
+
+    /// Sets the feature flags deciding which message types are available.
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlags) -> Self {
+        self.feature_flags = feature_flags;
+        self
+    }
+
+    /// Sets the storage attachment content is kept in.
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Restricts the attachment types accepted in file and image messages.
+    pub fn with_attachment_policy(mut self, attachment_policy: AttachmentPolicy) -> Self {
+        self.attachment_policy = attachment_policy;
+        self
+    }
+
+    /// Sets whether a login signs out the user's other sessions.
+    pub fn with_duplicate_login_policy(mut self, policy: DuplicateLoginPolicy) -> Self {
+        self.duplicate_login_policy = policy;
+        self
+    }
+
+    /// Sets what happens when users join the server.
+    pub fn with_onboarding(mut self, onboarding: OnboardingConfig) -> Self {
+        self.onboarding = onboarding;
+        self
+    }
+
+    /// Sets how many of the latest messages of the workspace clients that
+    /// support it are sent after logging in; 0 sends none.
+    pub fn with_history_limit(mut self, history_limit: u32) -> Self {
+        self.history_limit = history_limit;
+        self
+    }
+
     /// Processes an incoming message using the message processor.
     ///
+    /// Messages wrapped in a `Request` envelope are unwrapped first, and errors
+    /// caused by them refer back to the envelope's `client_msg_id`.
+    ///
     /// # Arguments
     /// * `stream` - Optional TCP stream for reading additional data (used for file/image transfers)
     /// * `client_id` - The ID of the client sending the message
@@ -71,13 +144,60 @@ impl MessageService {
         client_id: usize,
         message: &Message,
     ) -> Result<()> {
+        let (in_reply_to, message) = match message {
+            Message::Request {
+                client_msg_id,
+                message,
+            } => (Some(client_msg_id.clone()), message.as_ref()),
+            message => (None, message),
+        };
+
+        self.processor(client_id)
+            .await
+            .with_reply_to(in_reply_to)
+            .process(stream, client_id, message)
+            .await
+    }
+
+    /// Logs in a client by the fingerprint of its TLS client certificate.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client
+    /// * `fingerprint` - Fingerprint of the certificate the client presented
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the certificate was processed, Err otherwise
+    pub async fn login_with_certificate(&self, client_id: usize, fingerprint: &str) -> Result<()> {
+        self.processor(client_id)
+            .await
+            .handle_certificate_auth(client_id, fingerprint)
+            .await
+    }
+
+    /// Creates a message processor replying in the client's locale.
+    async fn processor(&self, client_id: usize) -> MessageProcessor {
+        let locale = match self.clients.lock().await.get(&client_id) {
+            Some(connection) => connection.locale.clone(),
+            None => i18n::DEFAULT_LOCALE.to_string(),
+        };
         let processor = MessageProcessor::new(
             self.clients.clone(),
             Arc::clone(&self.pool),
             Arc::clone(&self.encryption),
             self.metrics.clone(),
-        );
-        processor.process(stream, client_id, message).await
+        )
+        .with_locale(locale)
+        .with_attachment_policy(self.attachment_policy.clone())
+        .with_duplicate_login_policy(self.duplicate_login_policy)
+        .with_onboarding(self.onboarding.clone())
+        .with_history_limit(self.history_limit)
+        .with_feature_flags(self.feature_flags.clone())
+        .with_pipeline(Arc::clone(&self.pipeline))
+        .with_auth_throttle(Arc::clone(&self.auth_throttle));
+        match &self.storage {
+            Some(storage) => processor.with_storage(Arc::clone(storage)),
+            None => processor,
+        }
     }
 
     /// Handles client disconnection and notifies other clients.
@@ -89,17 +209,23 @@ impl MessageService {
     /// * `Result<()>` - Ok if the disconnection was handled successfully, Err otherwise
     pub async fn handle_disconnect(&self, client_id: usize) -> Result<()> {
         let mut clients = self.clients.lock().await;
-        clients.remove(&client_id);
+        let mut workspace_id = None;
+        if let Some(connection) = clients.remove(&client_id) {
+            workspace_id = connection.workspace_id;
+            connection.outbound.close().await;
+        }
 
         // Decrement active connections
         self.metrics.lock().await.active_connections.dec();
 
         // TODO: get the username of the disconnected client
-        let disconnect_msg = Message::System("A client has disconnected".to_string());
-
-        // Broadcast disconnect message to remaining clients
-        for connection in clients.values_mut() {
-            let _ = connection.writer.write_message(&disconnect_msg).await;
+        // Broadcast disconnect message to remaining clients of the same workspace
+        for connection in clients.values() {
+            if workspace_id.is_none() || connection.workspace_id == workspace_id {
+                let disconnect_msg =
+                    Message::System(i18n::text(&connection.locale, "client-disconnected", &[]));
+                let _ = connection.send(&disconnect_msg).await;
+            }
         }
 
         info!("Client {} disconnected", client_id);
@@ -126,17 +252,15 @@ impl MessageService {
         // Decrypt the incoming data
         let mut decrypted = Vec::new();
         let metadata_typed: EncryptedFileMetadata = serde_json::from_value(metadata)?;
+        let encryption = self.encryption.file_for(metadata_typed.key_id.as_deref())?;
 
-        self.encryption
-            .file()
+        encryption
             .decrypt_stream(BufReader::new(&data[..]), &mut decrypted, &metadata_typed)
             .await?;
 
-        // Re-encrypt for broadcast
+        // Re-encrypt for broadcast, with the key of the room
         let mut encrypted_data = Vec::new();
-        let new_metadata = self
-            .encryption
-            .file()
+        let new_metadata = encryption
             .encrypt_stream(BufReader::new(&decrypted[..]), &mut encrypted_data)
             .await?;
 
@@ -165,24 +289,77 @@ impl MessageService {
     /// * `Result<Message>` - The processed message ready for broadcasting, or an error
     ///
     /// # Message Type Behavior
-    /// * Text messages: Decrypted and re-encrypted for each recipient
+    /// * Text/PriorityText/Ephemeral/Reply/Action messages: Decrypted and re-encrypted for each recipient
     /// * File/Image messages: Decrypted, processed, and re-encrypted
     /// * System messages: Passed through without encryption
-    /// * Auth messages: Passed through for processing
-    /// * AuthResponse/Error messages: Logged as unexpected
+    /// * Auth/Register/SwitchWorkspace/ChangePassword/Request messages: Passed through for processing
+    /// * Edit/Delete messages: Passed through, the pipeline checks and applies them
+    /// * AuthResponse/Error/Notification/RoomKey/Sequenced/ChatMessage/Activity/History messages: Logged as unexpected
+    /// * Ping/Pong messages: Passed through (answered by the connection service)
     pub async fn handle_message(&self, message: Message) -> Result<Message> {
         match message {
             Message::Text(encrypted) => {
                 // Decrypt incoming message
                 let encrypted: EncryptedMessage = serde_json::from_str(&encrypted)?;
-                let text = self.encryption.message().decrypt(&encrypted)?;
+                let encryption = self.encryption.message_for(encrypted.key_id.as_deref())?;
+                let text = encryption.decrypt(&encrypted)?;
 
-                // Re-encrypt for each recipient
-                let encrypted = self.encryption.message().encrypt(&text)?;
+                // Re-encrypt for each recipient, with the key of the room
+                let encrypted = encryption.encrypt(&text)?;
                 let encrypted_str = serde_json::to_string(&encrypted)?;
 
                 Ok(Message::Text(encrypted_str))
             }
+            Message::PriorityText { priority, content } => {
+                let encrypted: EncryptedMessage = serde_json::from_str(&content)?;
+                let encryption = self.encryption.message_for(encrypted.key_id.as_deref())?;
+                let text = encryption.decrypt(&encrypted)?;
+
+                let encrypted = encryption.encrypt(&text)?;
+                Ok(Message::PriorityText {
+                    priority,
+                    content: serde_json::to_string(&encrypted)?,
+                })
+            }
+            Message::Ephemeral {
+                content,
+                ttl_secs,
+                expires_at,
+            } => {
+                let encrypted: EncryptedMessage = serde_json::from_str(&content)?;
+                let encryption = self.encryption.message_for(encrypted.key_id.as_deref())?;
+                let text = encryption.decrypt(&encrypted)?;
+
+                let encrypted = encryption.encrypt(&text)?;
+                Ok(Message::Ephemeral {
+                    content: serde_json::to_string(&encrypted)?,
+                    ttl_secs,
+                    expires_at,
+                })
+            }
+            Message::Reply { content, quote } => {
+                let encrypted: EncryptedMessage = serde_json::from_str(&content)?;
+                let encryption = self.encryption.message_for(encrypted.key_id.as_deref())?;
+                let text = encryption.decrypt(&encrypted)?;
+
+                // The quote's excerpt is already encrypted with the same key
+                let encrypted = encryption.encrypt(&text)?;
+                Ok(Message::Reply {
+                    content: serde_json::to_string(&encrypted)?,
+                    quote,
+                })
+            }
+            Message::Action { content, username } => {
+                let encrypted: EncryptedMessage = serde_json::from_str(&content)?;
+                let encryption = self.encryption.message_for(encrypted.key_id.as_deref())?;
+                let text = encryption.decrypt(&encrypted)?;
+
+                let encrypted = encryption.encrypt(&text)?;
+                Ok(Message::Action {
+                    content: serde_json::to_string(&encrypted)?,
+                    username,
+                })
+            }
             Message::File {
                 name,
                 metadata,
@@ -204,11 +381,39 @@ impl MessageService {
                 // System messages are broadcast without encryption
                 Ok(Message::System(notification))
             }
-            Message::Auth { .. } => {
-                // Auth messages are handled by the processor
+            Message::Auth { .. }
+            | Message::Register { .. }
+            | Message::SwitchWorkspace { .. }
+            | Message::ChangePassword { .. }
+            | Message::Request { .. } => {
+                // Account, workspace and request messages are handled by the processor
+                Ok(message)
+            }
+            Message::Poll { .. }
+            | Message::Vote { .. }
+            | Message::SetStatus { .. }
+            | Message::Report { .. }
+            | Message::Download { .. }
+            | Message::Edit { .. }
+            | Message::Delete { .. } => {
+                // Polls, statuses, reports, downloads and edits are handled by the pipeline
                 Ok(message)
             }
-            Message::AuthResponse { .. } | Message::Error { .. } => {
+            Message::Hello { .. } | Message::Ping { .. } | Message::Pong { .. } => {
+                // Handshakes and heartbeats are answered at the connection level
+                Ok(message)
+            }
+            Message::AuthResponse { .. }
+            | Message::Error { .. }
+            | Message::Notification { .. }
+            | Message::RoomKey { .. }
+            | Message::PollTally { .. }
+            | Message::StatusChanged { .. }
+            | Message::Sequenced { .. }
+            | Message::ChatMessage { .. }
+            | Message::Activity { .. }
+            | Message::Attachment { .. }
+            | Message::History(_) => {
                 // These messages are typically sent by the server, not received
                 warn!("Unexpected message type received from client");
                 Ok(message)
@@ -298,7 +503,7 @@ mod tests {
         let service = MessageService::new(clients, pool, encryption, metrics);
 
         // Create test data and encrypt it
-        let test_data = vec![1, 2, 3, 4, 5];
+        let test_data = [1, 2, 3, 4, 5];
         let mut encrypted_data = Vec::new();
         let metadata = encryption_clone
             .file()
@@ -325,7 +530,7 @@ mod tests {
         let service = MessageService::new(clients, pool, encryption, metrics);
 
         // Create test data and encrypt it
-        let test_data = vec![1, 2, 3, 4, 5];
+        let test_data = [1, 2, 3, 4, 5];
         let mut encrypted_data = Vec::new();
         let metadata = encryption_clone
             .file()
@@ -349,10 +554,7 @@ mod tests {
         let (pool, encryption, metrics) = setup_test_services().await;
 
         let service = MessageService::new(clients, pool, encryption, metrics);
-        let message = Message::Error {
-            code: chat_common::ErrorCode::PermissionDenied,
-            message: "Test error".to_string(),
-        };
+        let message = Message::error(chat_common::ErrorCode::PermissionDenied, "Test error");
 
         let result = service.handle_message(message).await;
         assert!(result.is_ok());
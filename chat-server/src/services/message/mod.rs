@@ -1,3 +1,5 @@
 pub mod broadcast;
+pub mod commands;
 pub mod handler;
+pub mod pipeline;
 pub mod processor;
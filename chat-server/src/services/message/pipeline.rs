@@ -0,0 +1,2204 @@
+//! Middleware pipeline for incoming chat messages.
+//!
+//! Every message other than a login passes through an ordered chain of
+//! middlewares. Each one inspects or rewrites the message in the shared
+//! [`MessageContext`] and either hands it on or stops processing, typically
+//! after replying to the sender. Cross-cutting behavior is added by
+//! registering a new middleware instead of editing the processor.
+//!
+//! The standard chain is:
+//!
+//! 1. `auth` - rejects unauthenticated clients and records the sender
+//! 2. `workspace_switch` - handles workspace switch requests
+//! 3. `terms` - rejects chat messages until the sender accepts the `TERMS_FILE`
+//! 4. `feature_flags` - rejects message types behind a disabled flag
+//! 5. `announcements` - rejects posts by regular members of announcement-only workspaces
+//! 6. `rate_limit` - enforces `MESSAGE_RATE_LIMIT` per user
+//! 7. `moderation` - rejects text containing `BLOCKED_WORDS`
+//! 8. `antispam` - mutes users sending duplicate bursts, mass mentions or link floods
+//! 9. `status` - sets the sender's status and announces it
+//! 10. `commands` - runs slash commands like `/me`
+//! 11. `polls` - creates polls and records votes, broadcasting the tally
+//! 12. `quotes` - fills in quotes of replies from the stored message
+//! 13. `reports` - files reports of messages with the workspace's moderators
+//! 14. `edits` - edits and deletes messages on request of their sender
+//! 15. `transfer_cap` - rejects attachment uploads and downloads over the sender's `ATTACHMENT_DAILY_TRANSFER_MIB`
+//! 16. `downloads` - sends stored attachments back on request
+//! 17. `actions` - signs `.me` actions with the sender's username
+//! 18. `expiry` - stamps ephemeral messages with their expiry
+//! 19. `attachments` - sniffs, re-classifies or rejects attachments
+//! 20. `image_metadata` - strips EXIF and GPS metadata from images
+//! 21. `quota` - rejects attachments over the sender's `ATTACHMENT_QUOTA_MIB`
+//! 22. `persistence` - stores the message and any attachment content
+//! 23. `metrics` - counts the message
+//! 24. `broadcast` - acknowledges the message and delivers it with its sender's username
+//! 25. `activity` - tells members in other workspaces about the stored message
+//! 26. `notifications` - notifies mentioned users
+//!
+//! The server also registers `registration` before `auth`, which creates
+//! accounts for connections that are not logged in, `password_change` before
+//! `feature_flags`, which changes the sender's password on request, and
+//! `webhooks` last, which calls the webhooks of mentioned bots.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::SharedConfig;
+use crate::models::message::Message as StoredMessage;
+use crate::models::poll::{NewPoll, NewPollVote, Poll};
+use crate::models::user::NewUserRequest;
+use crate::repositories::message::MessageRepository;
+use crate::repositories::onboarding::OnboardingRepository;
+use crate::repositories::poll::PollRepository;
+use crate::repositories::user::{UserRepository, UserWriteError};
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::bot::WebhookService;
+use crate::services::feature_flags;
+use crate::services::notification::NotificationService;
+use crate::services::onboarding::{terms_sha256, ACCEPT_COMMAND};
+use crate::services::password::{PasswordChangeError, PasswordService, PasswordViolation};
+use crate::services::presence::{self, PresenceService};
+use crate::services::registration::{RegistrationError, RegistrationService};
+use crate::services::report::{ReportError, ReportService};
+use crate::services::room_keys;
+use crate::services::spam::SpamDetector;
+use crate::services::storage::{attachment_key, ObjectNotFound};
+use crate::utils::log_sampling::LogSampler;
+use anyhow::Result;
+use chat_common::encryption::file::sha256_hex;
+use chat_common::encryption::message::EncryptedMessage;
+use chat_common::file_ops::strip_image_metadata;
+use chat_common::{
+    ErrorCode, Message, MAX_POLL_OPTIONS, MAX_REPORT_REASON_CHARS, MAX_STATUS_TEXT_CHARS,
+};
+use chrono::{NaiveDate, Utc};
+use rocket::async_trait;
+use tokio::io::BufReader;
+use tracing::{debug, error, info, warn};
+
+use super::broadcast::MessageBroadcaster;
+use super::commands::{CommandRegistry, SlashCommands};
+use super::processor::{stamp_expiry, MessageProcessor};
+
+/// Length of the window `MESSAGE_RATE_LIMIT` is counted in
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Longest excerpt of a quoted text message, in characters
+const QUOTE_EXCERPT_CHARS: usize = 120;
+
+/// Longest poll question, in characters
+const MAX_POLL_QUESTION_CHARS: usize = 300;
+
+/// Longest poll option, in characters
+const MAX_POLL_OPTION_CHARS: usize = 100;
+
+/// What happens after a middleware ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Hand the message to the next middleware
+    Continue,
+    /// Stop processing the message
+    Stop,
+}
+
+/// The message being processed and what earlier middlewares found out about it.
+#[derive(Debug)]
+pub struct MessageContext {
+    /// The connection the message arrived on
+    pub client_id: usize,
+    /// The message, possibly rewritten by earlier middlewares
+    pub message: Message,
+    /// The sender's user ID, set by the `auth` middleware
+    pub user_id: Option<i32>,
+    /// The sender's current workspace, set by the `auth` middleware
+    pub workspace_id: Option<i32>,
+    /// SHA-256 of an attachment's content, set by the `attachments` middleware
+    pub sha256: Option<String>,
+    /// Decrypted content of an attachment, set by the `attachments` middleware
+    pub attachment: Option<Vec<u8>>,
+    /// The stored message, set by the `persistence` middleware
+    pub stored: Option<StoredMessage>,
+    /// Attachment bytes within the daily transfer cap, set by the
+    /// `transfer_cap` middleware and counted once the transfer went through
+    pub transfer: Option<PendingTransfer>,
+}
+
+impl MessageContext {
+    pub fn new(client_id: usize, message: Message) -> Self {
+        Self {
+            client_id,
+            message,
+            user_id: None,
+            workspace_id: None,
+            sha256: None,
+            attachment: None,
+            stored: None,
+            transfer: None,
+        }
+    }
+
+    /// Returns the authenticated sender as `(user_id, workspace_id)`.
+    ///
+    /// Fails if the middleware runs before `auth`.
+    pub fn sender(&self) -> Result<(i32, i32)> {
+        self.user_id
+            .zip(self.workspace_id)
+            .ok_or_else(|| anyhow::anyhow!("Sender is not known before the auth middleware"))
+    }
+}
+
+/// A step of the message pipeline.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Unique name used to position other middlewares relative to this one
+    fn name(&self) -> &'static str;
+
+    /// Processes the message, returning whether the pipeline continues.
+    ///
+    /// # Arguments
+    /// * `processor` - Access to clients, storage and replies
+    /// * `ctx` - The message being processed
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow>;
+}
+
+/// An ordered chain of middlewares.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the standard pipeline described in the module documentation.
+    pub fn standard(config: SharedConfig) -> Self {
+        Self::new()
+            .register(AuthCheck)
+            .register(WorkspaceSwitch)
+            .register(Terms::new(config.clone()))
+            .register(FeatureGate)
+            .register(Announcements)
+            .register(RateLimit::new(config.clone()))
+            .register(Moderation::new(config.clone()))
+            .register(Antispam::new(config.clone()))
+            .register(StatusChange)
+            .register(SlashCommands::new(CommandRegistry::standard()))
+            .register(Polls)
+            .register(Quotes)
+            .register(Reports)
+            .register(Edits)
+            .register(TransferCap::new(config.clone()))
+            .register(Downloads)
+            .register(Actions)
+            .register(Expiry)
+            .register(Attachments)
+            .register(ImageMetadata)
+            .register(Quota::new(config))
+            .register(Persistence)
+            .register(MessageMetrics)
+            .register(Broadcast)
+            .register(Activity)
+            .register(Notifications)
+    }
+
+    /// Appends a middleware to the end of the chain.
+    pub fn register(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Inserts a middleware before the one called `before`, or appends it if
+    /// there is no such middleware.
+    pub fn register_before(mut self, before: &str, middleware: impl Middleware + 'static) -> Self {
+        let index = self
+            .middlewares
+            .iter()
+            .position(|existing| existing.name() == before)
+            .unwrap_or(self.middlewares.len());
+        self.middlewares.insert(index, Arc::new(middleware));
+        self
+    }
+
+    /// Removes the middleware called `name`.
+    pub fn remove(mut self, name: &str) -> Self {
+        self.middlewares
+            .retain(|middleware| middleware.name() != name);
+        self
+    }
+
+    /// Names of the middlewares in the order they run.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.middlewares
+            .iter()
+            .map(|middleware| middleware.name())
+            .collect()
+    }
+
+    /// Runs the message through the chain until a middleware stops it.
+    pub async fn run(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<()> {
+        for middleware in &self.middlewares {
+            if middleware.handle(processor, ctx).await? == Flow::Stop {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rejects unauthenticated clients and records who sent the message.
+pub struct AuthCheck;
+
+#[async_trait]
+impl Middleware for AuthCheck {
+    fn name(&self) -> &'static str {
+        "auth"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        match processor.get_auth_status(ctx.client_id).await? {
+            Some((user_id, workspace_id)) => {
+                ctx.user_id = Some(user_id);
+                ctx.workspace_id = Some(workspace_id);
+                Ok(Flow::Continue)
+            }
+            None => {
+                processor.handle_unauthenticated(ctx.client_id).await?;
+                Ok(Flow::Stop)
+            }
+        }
+    }
+}
+
+/// Moves the client into another workspace on request.
+pub struct WorkspaceSwitch;
+
+#[async_trait]
+impl Middleware for WorkspaceSwitch {
+    fn name(&self) -> &'static str {
+        "workspace_switch"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Message::SwitchWorkspace { slug } = &ctx.message else {
+            return Ok(Flow::Continue);
+        };
+
+        let (user_id, _) = ctx.sender()?;
+        processor
+            .handle_switch_workspace(ctx.client_id, user_id, slug)
+            .await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Rejects chat messages from users who have not accepted the server's
+/// terms, and records their acceptance when they send `/accept`.
+///
+/// The terms are read from the configuration on every message, so they can
+/// be reloaded. Accepted terms are remembered by their SHA-256, which makes
+/// users accept changed terms again.
+pub struct Terms {
+    config: SharedConfig,
+    /// SHA-256 of the terms each user last accepted
+    accepted: Mutex<HashMap<i32, String>>,
+}
+
+impl Terms {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            accepted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether the user accepted the terms with the given SHA-256.
+    async fn has_accepted(
+        &self,
+        processor: &MessageProcessor,
+        user_id: i32,
+        terms_sha256: &str,
+    ) -> Result<bool> {
+        if self
+            .accepted
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(String::as_str)
+            == Some(terms_sha256)
+        {
+            return Ok(true);
+        }
+
+        let conn = &mut *processor.pool().get().await?;
+        let Some(onboarding) = OnboardingRepository::find(conn, user_id).await? else {
+            return Ok(false);
+        };
+        if let Some(accepted) = &onboarding.terms_sha256 {
+            self.accepted
+                .lock()
+                .unwrap()
+                .insert(user_id, accepted.clone());
+        }
+        Ok(onboarding.accepted(terms_sha256))
+    }
+}
+
+#[async_trait]
+impl Middleware for Terms {
+    fn name(&self) -> &'static str {
+        "terms"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Some(terms) = self.config.current().onboarding.terms.clone() else {
+            return Ok(Flow::Continue);
+        };
+        if !is_chat_message(&ctx.message) {
+            return Ok(Flow::Continue);
+        }
+
+        let (user_id, _) = ctx.sender()?;
+        let sha256 = terms_sha256(&terms);
+        let accepts =
+            plain_text(processor, &ctx.message)?.is_some_and(|text| text.trim() == ACCEPT_COMMAND);
+        if !accepts {
+            if self.has_accepted(processor, user_id, &sha256).await? {
+                return Ok(Flow::Continue);
+            }
+            let reply = processor.error_reply(
+                ErrorCode::PermissionDenied,
+                processor.text("terms-not-accepted", &[("terms", terms.into())]),
+                &[("reason", "terms_not_accepted")],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        {
+            let conn = &mut *processor.pool().get().await?;
+            OnboardingRepository::accept_terms(conn, user_id, &sha256).await?;
+        }
+        self.accepted
+            .lock()
+            .unwrap()
+            .insert(user_id, sha256.clone());
+        info!(
+            target: "audit",
+            event = "terms_accepted",
+            user_id,
+            terms_sha256 = sha256,
+            "User accepted the terms"
+        );
+        let reply = Message::System(processor.text("terms-accepted", &[]));
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Rejects message types whose feature flag is disabled for the sender.
+pub struct FeatureGate;
+
+#[async_trait]
+impl Middleware for FeatureGate {
+    fn name(&self) -> &'static str {
+        "feature_flags"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Some(flag) = required_feature(&ctx.message) else {
+            return Ok(Flow::Continue);
+        };
+
+        let (user_id, _) = ctx.sender()?;
+        if processor.feature_flags().is_enabled(flag, Some(user_id)) {
+            return Ok(Flow::Continue);
+        }
+
+        let reply = processor.error_reply(
+            ErrorCode::PermissionDenied,
+            processor.text("feature-unavailable", &[]),
+            &[("reason", "feature_disabled"), ("feature", flag)],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Returns the feature flag a message type is gated behind, if any.
+fn required_feature(message: &Message) -> Option<&'static str> {
+    match message {
+        Message::Ephemeral { .. } => Some(feature_flags::EPHEMERAL_MESSAGES),
+        Message::PriorityText { .. } => Some(feature_flags::MESSAGE_PRIORITIES),
+        Message::File { .. } | Message::Image { .. } | Message::Download { .. } => {
+            Some(feature_flags::ATTACHMENTS)
+        }
+        Message::Poll { .. } | Message::Vote { .. } => Some(feature_flags::POLLS),
+        _ => None,
+    }
+}
+
+/// Rejects chat messages from regular members of announcement-only
+/// workspaces, where only admins and moderators may post. Votes in polls are
+/// still accepted.
+pub struct Announcements;
+
+#[async_trait]
+impl Middleware for Announcements {
+    fn name(&self) -> &'static str {
+        "announcements"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        if !is_chat_message(&ctx.message) {
+            return Ok(Flow::Continue);
+        }
+
+        let (user_id, workspace_id) = ctx.sender()?;
+        let workspace = {
+            let conn = &mut *processor.pool().get().await?;
+            let workspace = WorkspaceRepository::find_by_id(conn, workspace_id).await?;
+            if WorkspaceRepository::can_post(conn, &workspace, user_id).await? {
+                return Ok(Flow::Continue);
+            }
+            workspace
+        };
+
+        info!(
+            "Rejected post of client {} to read-only workspace {}",
+            ctx.client_id, workspace.slug
+        );
+        let reply = processor.error_reply(
+            ErrorCode::PermissionDenied,
+            processor.text(
+                "workspace-read-only",
+                &[("workspace", workspace.name.into())],
+            ),
+            &[("reason", "read_only_room"), ("workspace", &workspace.slug)],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Limits how many chat messages each user may send per minute.
+///
+/// Messages are counted in fixed one-minute windows per user. The limit is
+/// read from the configuration on every message, so it can be reloaded.
+pub struct RateLimit {
+    config: SharedConfig,
+    windows: Mutex<HashMap<i32, (Instant, u32)>>,
+    rejections: LogSampler,
+}
+
+impl RateLimit {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            windows: Mutex::new(HashMap::new()),
+            rejections: LogSampler::new(),
+        }
+    }
+
+    /// Counts a message, returning how long the user has to wait if the limit
+    /// was exceeded.
+    fn check(&self, user_id: i32, limit: u32, now: Instant) -> Option<Duration> {
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < RATE_LIMIT_WINDOW);
+
+        let (started, count) = windows.entry(user_id).or_insert((now, 0));
+        if *count >= limit {
+            return Some(RATE_LIMIT_WINDOW - now.duration_since(*started));
+        }
+        *count += 1;
+        None
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    fn name(&self) -> &'static str {
+        "rate_limit"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let config = self.config.current();
+        let limit = config.message_rate_limit;
+        if limit == 0 || !is_chat_message(&ctx.message) {
+            return Ok(Flow::Continue);
+        }
+
+        let (user_id, _) = ctx.sender()?;
+        let Some(retry_after) = self.check(user_id, limit, Instant::now()) else {
+            return Ok(Flow::Continue);
+        };
+
+        if let Some(occurrences) = self.rejections.sample(config.log_sample_rate) {
+            info!(occurrences, "Rate limited user {}", user_id);
+        }
+        let retry_after = retry_after.as_secs().max(1);
+        let retry_after_secs = retry_after.to_string();
+        let reply = processor.error_reply(
+            ErrorCode::PermissionDenied,
+            processor.text("rate-limited", &[("seconds", retry_after.into())]),
+            &[
+                ("reason", "rate_limited"),
+                ("retry_after_secs", &retry_after_secs),
+            ],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Returns `true` for messages users send to each other.
+fn is_chat_message(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::Text(_)
+            | Message::PriorityText { .. }
+            | Message::Ephemeral { .. }
+            | Message::Reply { .. }
+            | Message::Action { .. }
+            | Message::Edit { .. }
+            | Message::Poll { .. }
+            | Message::File { .. }
+            | Message::Image { .. }
+    )
+}
+
+/// Rejects text messages containing a blocked word.
+///
+/// The word list is read from the configuration on every message, so it can
+/// be reloaded.
+pub struct Moderation {
+    config: SharedConfig,
+    blocked: LogSampler,
+}
+
+impl Moderation {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            blocked: LogSampler::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for Moderation {
+    fn name(&self) -> &'static str {
+        "moderation"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let config = self.config.current();
+        if config.blocked_words.is_empty() {
+            return Ok(Flow::Continue);
+        }
+
+        let text = match &ctx.message {
+            Message::SetStatus {
+                text: Some(text), ..
+            } => text.clone(),
+            message => match plain_text(processor, message)? {
+                Some(text) => text,
+                None => return Ok(Flow::Continue),
+            },
+        };
+
+        if !contains_blocked_word(&text, &config.blocked_words) {
+            return Ok(Flow::Continue);
+        }
+
+        if let Some(occurrences) = self.blocked.sample(config.log_sample_rate) {
+            info!(occurrences, "Blocked message from client {}", ctx.client_id);
+        }
+        let reply = processor.error_reply(
+            ErrorCode::InvalidInput,
+            processor.text("blocked-words", &[]),
+            &[("reason", "blocked_content")],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Returns the decrypted text of a text message or the text of a poll.
+fn plain_text(processor: &MessageProcessor, message: &Message) -> Result<Option<String>> {
+    let text = match message {
+        Message::Text(content)
+        | Message::PriorityText { content, .. }
+        | Message::Ephemeral { content, .. }
+        | Message::Reply { content, .. }
+        | Message::Action { content, .. }
+        | Message::Edit {
+            new_content: content,
+            ..
+        } => {
+            let encrypted: EncryptedMessage = serde_json::from_str(content)?;
+            processor
+                .encryption()
+                .message_for(encrypted.key_id.as_deref())?
+                .decrypt(&encrypted)?
+        }
+        Message::Poll { question, options } => format!("{} {}", question, options.join(" ")),
+        _ => return Ok(None),
+    };
+    Ok(Some(text))
+}
+
+/// Mutes users whose messages look like spam, see [`crate::services::spam`].
+///
+/// Muted users cannot send chat messages until the mute ends. Every mute is
+/// recorded in the audit log, and the admins and moderators of the workspace
+/// are notified.
+pub struct Antispam {
+    detector: SpamDetector,
+}
+
+impl Antispam {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            detector: SpamDetector::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for Antispam {
+    fn name(&self) -> &'static str {
+        "antispam"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        if !is_chat_message(&ctx.message) {
+            return Ok(Flow::Continue);
+        }
+
+        let (user_id, workspace_id) = ctx.sender()?;
+        if let Some(remaining) = self.detector.muted_for(user_id, Instant::now()) {
+            let seconds = remaining.as_secs().max(1);
+            let retry_after_secs = seconds.to_string();
+            let reply = processor.error_reply(
+                ErrorCode::PermissionDenied,
+                processor.text("muted", &[("seconds", seconds.into())]),
+                &[("reason", "muted"), ("retry_after_secs", &retry_after_secs)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        let Some(text) = plain_text(processor, &ctx.message)? else {
+            return Ok(Flow::Continue);
+        };
+        let Some(offense) = self.detector.check(user_id, &text, Instant::now()) else {
+            return Ok(Flow::Continue);
+        };
+
+        let seconds = offense.mute.as_secs();
+        warn!(
+            target: "audit",
+            event = "spam_muted",
+            user_id,
+            workspace_id,
+            kind = offense.kind.as_str(),
+            offense = offense.count,
+            mute_secs = seconds,
+            "Muted user for spam"
+        );
+
+        let retry_after_secs = seconds.to_string();
+        let reply = processor.error_reply(
+            ErrorCode::PermissionDenied,
+            processor.text(
+                "spam-muted",
+                &[
+                    ("kind", offense.kind.as_str().into()),
+                    ("seconds", seconds.into()),
+                ],
+            ),
+            &[
+                ("reason", "spam"),
+                ("spam_kind", offense.kind.as_str()),
+                ("retry_after_secs", &retry_after_secs),
+            ],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+
+        let username = {
+            let conn = &mut *processor.pool().get().await?;
+            UserRepository::find_by_id(conn, user_id).await?.username
+        };
+        let notifications =
+            NotificationService::new(processor.pool().clone(), processor.clients().clone());
+        if let Err(e) = notifications
+            .notify_moderators(
+                workspace_id,
+                user_id,
+                None,
+                "spam-moderator-notice",
+                &[
+                    ("user", username),
+                    ("kind", offense.kind.as_str().to_string()),
+                    ("seconds", seconds.to_string()),
+                ],
+            )
+            .await
+        {
+            error!("Failed to notify moderators about spam: {}", e);
+        }
+        Ok(Flow::Stop)
+    }
+}
+
+/// Returns `true` if any word of `text` is in the lowercase `blocked_words`.
+pub(crate) fn contains_blocked_word(text: &str, blocked_words: &[String]) -> bool {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .any(|word| blocked_words.contains(&word.to_lowercase()))
+}
+
+/// Sets the sender's status on request.
+pub struct StatusChange;
+
+#[async_trait]
+impl Middleware for StatusChange {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Message::SetStatus { availability, text } = &ctx.message else {
+            return Ok(Flow::Continue);
+        };
+
+        let Ok(text) = presence::normalize_text(text.as_deref()) else {
+            let max = MAX_STATUS_TEXT_CHARS.to_string();
+            let reply = processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("status-too-long", &[("max", max.as_str().into())]),
+                &[("reason", "status_too_long"), ("max", &max)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        };
+
+        // The sender learns about the change like everyone else
+        let (user_id, _) = ctx.sender()?;
+        PresenceService::new(processor.pool().clone(), processor.clients().clone())
+            .set_status(user_id, *availability, text.as_deref())
+            .await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Creates polls and records votes.
+///
+/// Both are answered with a `PollTally` sent to the whole workspace, the
+/// sender included, and are not processed any further. Every user votes once
+/// per poll; later votes are rejected rather than replacing the first.
+pub struct Polls;
+
+impl Polls {
+    async fn create(
+        &self,
+        processor: &MessageProcessor,
+        ctx: &MessageContext,
+        question: &str,
+        options: &[String],
+    ) -> Result<Flow> {
+        let (user_id, workspace_id) = ctx.sender()?;
+        let question = question.trim();
+        let options: Vec<String> = options
+            .iter()
+            .map(|option| option.trim().to_string())
+            .collect();
+        if let Err(reason) = validate_poll(question, &options) {
+            let max = MAX_POLL_OPTIONS.to_string();
+            let reply = processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("poll-invalid", &[("max", max.as_str().into())]),
+                &[("reason", reason)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        let poll = {
+            let conn = &mut *processor.pool().get().await?;
+            PollRepository::create(
+                conn,
+                NewPoll {
+                    workspace_id,
+                    creator_id: user_id,
+                    question: question.to_string(),
+                    options,
+                },
+            )
+            .await?
+        };
+        info!("User {} created poll {}", user_id, poll.id);
+        let votes = vec![0; poll.options.len()];
+        self.broadcast_tally(processor, workspace_id, poll, votes)
+            .await?;
+        Ok(Flow::Stop)
+    }
+
+    async fn vote(
+        &self,
+        processor: &MessageProcessor,
+        ctx: &MessageContext,
+        poll_id: i32,
+        option: u32,
+    ) -> Result<Flow> {
+        let (user_id, workspace_id) = ctx.sender()?;
+        let id = poll_id.to_string();
+        let conn = &mut *processor.pool().get().await?;
+
+        let poll = match PollRepository::find_by_id(conn, poll_id).await? {
+            Some(poll) if poll.workspace_id == workspace_id => poll,
+            _ => {
+                let reply = processor.error_reply(
+                    ErrorCode::InvalidInput,
+                    processor.text("poll-not-found", &[("id", id.as_str().into())]),
+                    &[("reason", "poll_not_found"), ("id", &id)],
+                );
+                processor.reply(ctx.client_id, &reply).await?;
+                return Ok(Flow::Stop);
+            }
+        };
+
+        if option as usize >= poll.options.len() {
+            let count = poll.options.len().to_string();
+            let reply = processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text(
+                    "poll-invalid-option",
+                    &[("id", id.as_str().into()), ("count", count.as_str().into())],
+                ),
+                &[("reason", "poll_invalid_option"), ("id", &id)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        let vote = NewPollVote {
+            poll_id,
+            user_id,
+            option_index: option as i32,
+        };
+        if PollRepository::vote(conn, vote).await? == 0 {
+            let reply = processor.error_reply(
+                ErrorCode::Conflict,
+                processor.text("poll-already-voted", &[("id", id.as_str().into())]),
+                &[("reason", "poll_already_voted"), ("id", &id)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        let votes = PollRepository::tally(conn, &poll).await?;
+        self.broadcast_tally(processor, workspace_id, poll, votes)
+            .await?;
+        Ok(Flow::Stop)
+    }
+
+    async fn broadcast_tally(
+        &self,
+        processor: &MessageProcessor,
+        workspace_id: i32,
+        poll: Poll,
+        votes: Vec<u32>,
+    ) -> Result<()> {
+        let tally = Message::PollTally {
+            poll_id: poll.id,
+            question: poll.question,
+            options: poll.options,
+            votes,
+        };
+        MessageBroadcaster::new(processor.clients().clone())
+            .broadcast_message(&tally, None, Some(workspace_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl Middleware for Polls {
+    fn name(&self) -> &'static str {
+        "polls"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        match &ctx.message {
+            Message::Poll { question, options } => {
+                self.create(processor, ctx, question, options).await
+            }
+            Message::Vote { poll_id, option } => self.vote(processor, ctx, *poll_id, *option).await,
+            _ => Ok(Flow::Continue),
+        }
+    }
+}
+
+/// Checks a trimmed poll, returning the reason it is rejected.
+pub(crate) fn validate_poll(question: &str, options: &[String]) -> Result<(), &'static str> {
+    if question.is_empty() || question.chars().count() > MAX_POLL_QUESTION_CHARS {
+        return Err("poll_invalid_question");
+    }
+    if options.len() < 2 || options.len() > MAX_POLL_OPTIONS {
+        return Err("poll_option_count");
+    }
+    if options
+        .iter()
+        .any(|option| option.is_empty() || option.chars().count() > MAX_POLL_OPTION_CHARS)
+    {
+        return Err("poll_invalid_option");
+    }
+    Ok(())
+}
+
+/// Fills in the author and excerpt of a reply's quote from the stored message.
+///
+/// Whatever the sender put into the quote besides its ID is replaced, so a
+/// quote always shows what was actually said. Quotes of messages that do not
+/// exist or belong to another workspace are rejected.
+pub struct Quotes;
+
+#[async_trait]
+impl Middleware for Quotes {
+    fn name(&self) -> &'static str {
+        "quotes"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (_, workspace_id) = ctx.sender()?;
+        let Message::Reply { content, quote } = &mut ctx.message else {
+            return Ok(Flow::Continue);
+        };
+
+        let quoted = {
+            let conn = &mut *processor.pool().get().await?;
+            match MessageRepository::find_by_id(conn, quote.id).await {
+                Ok(message) if message.workspace_id == workspace_id => {
+                    let author = UserRepository::find_by_id(conn, message.sender_id)
+                        .await?
+                        .username;
+                    let text = message.content.or(message.file_name).unwrap_or_default();
+                    Some((author, quote_excerpt(&text)))
+                }
+                Ok(_) | Err(diesel::result::Error::NotFound) => None,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let Some((author, excerpt)) = quoted else {
+            let id = quote.id.to_string();
+            let reply = processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("quote-not-found", &[("id", id.as_str().into())]),
+                &[("reason", "quote_not_found"), ("id", &id)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        };
+
+        // Encrypted like the reply, so exactly its readers can read the excerpt
+        let encrypted: EncryptedMessage = serde_json::from_str(content)?;
+        let excerpt = processor
+            .encryption()
+            .message_for(encrypted.key_id.as_deref())?
+            .encrypt(&excerpt)?;
+        quote.author = author;
+        quote.excerpt = serde_json::to_string(&excerpt)?;
+        Ok(Flow::Continue)
+    }
+}
+
+/// Files reports of messages, which notify the moderators of the message's
+/// workspace.
+///
+/// Reports are acknowledged to the reporter only and are not processed any
+/// further.
+pub struct Reports;
+
+#[async_trait]
+impl Middleware for Reports {
+    fn name(&self) -> &'static str {
+        "reports"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Message::Report { message_id, reason } = &ctx.message else {
+            return Ok(Flow::Continue);
+        };
+        let (user_id, _) = ctx.sender()?;
+
+        let reports = ReportService::new(processor.pool().clone(), processor.clients().clone());
+        let id = message_id.to_string();
+        let reply = match reports.file(user_id, *message_id, reason).await? {
+            Ok(report) => {
+                info!("User {} reported message {}", user_id, message_id);
+                let report_id = report.id.to_string();
+                Message::System(processor.text(
+                    "report-filed",
+                    &[
+                        ("id", id.as_str().into()),
+                        ("report", report_id.as_str().into()),
+                    ],
+                ))
+            }
+            Err(ReportError::MessageNotFound) => processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("report-message-not-found", &[("id", id.as_str().into())]),
+                &[("reason", "report_message_not_found"), ("id", &id)],
+            ),
+            Err(ReportError::AlreadyReported) => processor.error_reply(
+                ErrorCode::Conflict,
+                processor.text("report-already-filed", &[("id", id.as_str().into())]),
+                &[("reason", "report_already_filed"), ("id", &id)],
+            ),
+            Err(_) => {
+                let max = MAX_REPORT_REASON_CHARS.to_string();
+                processor.error_reply(
+                    ErrorCode::InvalidInput,
+                    processor.text("report-reason-invalid", &[("max", max.as_str().into())]),
+                    &[("reason", "report_reason_invalid")],
+                )
+            }
+        };
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Edits and deletes stored messages on request of their sender.
+///
+/// Only the sender of a message may change it, and only text messages and
+/// actions can be edited. The change is delivered to the message's workspace,
+/// the sender included, and is not processed any further.
+pub struct Edits;
+
+impl Edits {
+    /// Loads the message the sender wants to change, replying with an error
+    /// if it is not theirs to change.
+    async fn find_own(
+        &self,
+        processor: &MessageProcessor,
+        ctx: &MessageContext,
+        message_id: i32,
+    ) -> Result<Option<StoredMessage>> {
+        let (user_id, workspace_id) = ctx.sender()?;
+        let id = message_id.to_string();
+        let stored = {
+            let conn = &mut *processor.pool().get().await?;
+            match MessageRepository::find_by_id(conn, message_id).await {
+                Ok(message) if message.workspace_id == workspace_id => Some(message),
+                Ok(_) | Err(diesel::result::Error::NotFound) => None,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let reply = match stored {
+            Some(message) if message.sender_id == user_id => return Ok(Some(message)),
+            Some(_) => processor.error_reply(
+                ErrorCode::PermissionDenied,
+                processor.text("edit-not-sender", &[("id", id.as_str().into())]),
+                &[("reason", "not_sender"), ("id", &id)],
+            ),
+            None => processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("edit-message-not-found", &[("id", id.as_str().into())]),
+                &[("reason", "message_not_found"), ("id", &id)],
+            ),
+        };
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(None)
+    }
+
+    async fn edit(
+        &self,
+        processor: &MessageProcessor,
+        ctx: &MessageContext,
+        message_id: i32,
+        new_content: &str,
+    ) -> Result<Flow> {
+        let Some(stored) = self.find_own(processor, ctx, message_id).await? else {
+            return Ok(Flow::Stop);
+        };
+        if stored.content.is_none() {
+            let id = message_id.to_string();
+            let reply = processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("edit-not-text", &[("id", id.as_str().into())]),
+                &[("reason", "edit_not_text"), ("id", &id)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        let encrypted: EncryptedMessage = serde_json::from_str(new_content)?;
+        let encryption = processor
+            .encryption()
+            .message_for(encrypted.key_id.as_deref())?;
+        let text = encryption.decrypt(&encrypted)?;
+
+        let (user_id, workspace_id) = ctx.sender()?;
+        {
+            let conn = &mut *processor.pool().get().await?;
+            MessageRepository::update_content(conn, message_id, text.clone(), user_id).await?;
+        }
+        info!(
+            target: "audit",
+            event = "message_edited",
+            message_id,
+            user_id,
+            "Message edited"
+        );
+
+        let edit = Message::Edit {
+            message_id,
+            new_content: serde_json::to_string(&encryption.encrypt(&text)?)?,
+        };
+        MessageBroadcaster::new(processor.clients().clone())
+            .broadcast_message(&edit, None, Some(workspace_id))
+            .await?;
+        Ok(Flow::Stop)
+    }
+
+    async fn delete(
+        &self,
+        processor: &MessageProcessor,
+        ctx: &MessageContext,
+        message_id: i32,
+    ) -> Result<Flow> {
+        if self.find_own(processor, ctx, message_id).await?.is_none() {
+            return Ok(Flow::Stop);
+        }
+
+        let (user_id, workspace_id) = ctx.sender()?;
+        {
+            let conn = &mut *processor.pool().get().await?;
+            MessageRepository::delete(conn, message_id).await?;
+        }
+        info!(
+            target: "audit",
+            event = "message_deleted",
+            message_id,
+            user_id,
+            "Message deleted"
+        );
+
+        MessageBroadcaster::new(processor.clients().clone())
+            .broadcast_message(&Message::Delete { message_id }, None, Some(workspace_id))
+            .await?;
+        Ok(Flow::Stop)
+    }
+}
+
+#[async_trait]
+impl Middleware for Edits {
+    fn name(&self) -> &'static str {
+        "edits"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        match &ctx.message {
+            Message::Edit {
+                message_id,
+                new_content,
+            } => self.edit(processor, ctx, *message_id, new_content).await,
+            Message::Delete { message_id } => self.delete(processor, ctx, *message_id).await,
+            _ => Ok(Flow::Continue),
+        }
+    }
+}
+
+/// Attachment bytes each user transferred, with the day they were counted on.
+type Transferred = Arc<Mutex<HashMap<i32, (NaiveDate, u64)>>>;
+
+/// Limits the attachment bytes each user may send and download per day to
+/// `ATTACHMENT_DAILY_TRANSFER_MIB`.
+///
+/// Transfers are counted per UTC day in memory, so a restart resets them. The
+/// cap is read from the configuration on every message, so it can be
+/// reloaded. A transfer only counts once `persistence` stored the upload or
+/// `downloads` delivered the attachment, so rejected messages use none of it.
+pub struct TransferCap {
+    config: SharedConfig,
+    /// Day and attachment bytes transferred by each user on it
+    transferred: Transferred,
+}
+
+impl TransferCap {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            transferred: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Checks a transfer of `size` bytes against the cap, returning the bytes
+    /// already transferred today instead if it would exceed it.
+    fn check(
+        &self,
+        user_id: i32,
+        size: u64,
+        cap: u64,
+        today: NaiveDate,
+    ) -> std::result::Result<PendingTransfer, u64> {
+        let mut transferred = self.transferred.lock().unwrap();
+        transferred.retain(|_, (day, _)| *day == today);
+
+        let used = transferred.get(&user_id).map_or(0, |(_, used)| *used);
+        if exceeds_quota(used, size, cap) {
+            return Err(used);
+        }
+        Ok(PendingTransfer {
+            transferred: self.transferred.clone(),
+            user_id,
+            size,
+            day: today,
+        })
+    }
+}
+
+/// A transfer within the daily cap that has not been counted yet.
+#[derive(Debug)]
+pub struct PendingTransfer {
+    transferred: Transferred,
+    user_id: i32,
+    size: u64,
+    day: NaiveDate,
+}
+
+impl PendingTransfer {
+    /// Counts the transfer towards the user's bytes of the day it was checked.
+    pub fn record(self) {
+        let mut transferred = self.transferred.lock().unwrap();
+        let (day, used) = transferred.entry(self.user_id).or_insert((self.day, 0));
+        if *day != self.day {
+            *day = self.day;
+            *used = 0;
+        }
+        *used += self.size;
+    }
+}
+
+#[async_trait]
+impl Middleware for TransferCap {
+    fn name(&self) -> &'static str {
+        "transfer_cap"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Some(cap) = self.config.current().attachment_daily_transfer else {
+            return Ok(Flow::Continue);
+        };
+        let size = match &ctx.message {
+            Message::File { data, .. } | Message::Image { data, .. } => data.len() as u64,
+            Message::Download { message_id } => {
+                let conn = &mut *processor.pool().get().await?;
+                match MessageRepository::find_by_id(conn, *message_id).await {
+                    Ok(message) => message.attachment_size.unwrap_or(0) as u64,
+                    // Downloads answers unknown messages itself
+                    Err(diesel::result::Error::NotFound) => 0,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            _ => return Ok(Flow::Continue),
+        };
+
+        let (user_id, _) = ctx.sender()?;
+        let used = match self.check(user_id, size, cap, Utc::now().date_naive()) {
+            Ok(transfer) => {
+                ctx.transfer = Some(transfer);
+                return Ok(Flow::Continue);
+            }
+            Err(used) => used,
+        };
+
+        info!(
+            "Rejected attachment transfer of user {}: {} of {} bytes transferred today",
+            user_id, used, cap
+        );
+        let (used_bytes, cap_bytes) = (used.to_string(), cap.to_string());
+        let reply = processor.error_reply(
+            ErrorCode::QuotaExceeded,
+            processor.text(
+                "attachment-transfer-cap-exceeded",
+                &[
+                    ("used", format_mib(used).into()),
+                    ("cap", format_mib(cap).into()),
+                ],
+            ),
+            &[
+                ("reason", "transfer_cap_exceeded"),
+                ("used_bytes", &used_bytes),
+                ("cap_bytes", &cap_bytes),
+            ],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Sends the attachment of a stored message back to a member of its
+/// workspace, encrypted with the workspace's key.
+///
+/// Only messages of the sender's current workspace can be downloaded, as the
+/// connection holds no other key. Downloads are not processed any further.
+pub struct Downloads;
+
+#[async_trait]
+impl Middleware for Downloads {
+    fn name(&self) -> &'static str {
+        "downloads"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Message::Download { message_id } = ctx.message else {
+            return Ok(Flow::Continue);
+        };
+        let (user_id, workspace_id) = ctx.sender()?;
+
+        let (stored, key_id) = {
+            let conn = &mut *processor.pool().get().await?;
+            let stored = match MessageRepository::find_by_id(conn, message_id).await {
+                Ok(message) if message.workspace_id == workspace_id => Some(message),
+                Ok(_) | Err(diesel::result::Error::NotFound) => None,
+                Err(e) => return Err(e.into()),
+            };
+            let key_id = room_keys::load(conn, processor.encryption(), workspace_id).await?;
+            (stored, key_id)
+        };
+
+        let content = match (processor.storage(), &stored) {
+            (Some(storage), Some(stored)) => match (&stored.file_name, &stored.sha256) {
+                (Some(name), Some(sha256)) => {
+                    // Attachments stored before they were keyed by hash are under the message ID
+                    let content = match storage.get(&attachment_key(sha256)).await {
+                        Err(e) if e.is::<ObjectNotFound>() => {
+                            storage.get(&format!("attachments/{}", message_id)).await
+                        }
+                        content => content,
+                    };
+                    match content {
+                        Ok(content) => Some((name.clone(), content)),
+                        Err(e) if e.is::<ObjectNotFound>() => None,
+                        Err(e) => return Err(e),
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some((name, content)) = content else {
+            let id = message_id.to_string();
+            let reply = processor.error_reply(
+                ErrorCode::FileNotFound,
+                processor.text("download-not-found", &[("id", id.as_str().into())]),
+                &[("reason", "download_not_found"), ("id", &id)],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        };
+
+        let mut data = Vec::new();
+        let metadata = processor
+            .encryption()
+            .file_for(Some(&key_id))?
+            .encrypt_stream(BufReader::new(&content[..]), &mut data)
+            .await?;
+        info!(
+            target: "audit",
+            event = "attachment_downloaded",
+            message_id,
+            user_id,
+            "Attachment downloaded"
+        );
+        let reply = Message::Attachment {
+            message_id,
+            name,
+            metadata: serde_json::to_value(metadata)?,
+            data,
+        };
+        processor.reply(ctx.client_id, &reply).await?;
+        if let Some(transfer) = ctx.transfer.take() {
+            transfer.record();
+        }
+        Ok(Flow::Stop)
+    }
+}
+
+/// Shortens quoted text to `QUOTE_EXCERPT_CHARS`, marking the cut with `…`.
+pub(crate) fn quote_excerpt(text: &str) -> String {
+    let mut chars = text.chars();
+    let excerpt: String = chars.by_ref().take(QUOTE_EXCERPT_CHARS).collect();
+    if chars.next().is_some() {
+        format!("{}…", excerpt.trim_end())
+    } else {
+        excerpt
+    }
+}
+
+/// Signs actions with the sender's username, replacing whatever the sender
+/// put there, so an action cannot be attributed to someone else.
+pub struct Actions;
+
+#[async_trait]
+impl Middleware for Actions {
+    fn name(&self) -> &'static str {
+        "actions"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (user_id, _) = ctx.sender()?;
+        let Message::Action { username, .. } = &mut ctx.message else {
+            return Ok(Flow::Continue);
+        };
+
+        let conn = &mut *processor.pool().get().await?;
+        *username = Some(UserRepository::find_by_id(conn, user_id).await?.username);
+        Ok(Flow::Continue)
+    }
+}
+
+/// Stamps ephemeral messages with their expiry.
+pub struct Expiry;
+
+#[async_trait]
+impl Middleware for Expiry {
+    fn name(&self) -> &'static str {
+        "expiry"
+    }
+
+    async fn handle(
+        &self,
+        _processor: &MessageProcessor,
+        ctx: &mut MessageContext,
+    ) -> Result<Flow> {
+        if matches!(ctx.message, Message::Ephemeral { .. }) {
+            ctx.message = stamp_expiry(&ctx.message);
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+/// Sniffs attachments, re-classifying or rejecting them.
+pub struct Attachments;
+
+#[async_trait]
+impl Middleware for Attachments {
+    fn name(&self) -> &'static str {
+        "attachments"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        match processor.check_attachment(&ctx.message).await? {
+            Ok((message, content)) => {
+                ctx.message = message;
+                ctx.sha256 = content.as_deref().map(sha256_hex);
+                ctx.attachment = content;
+                Ok(Flow::Continue)
+            }
+            Err(rejection) => {
+                processor.reply(ctx.client_id, &rejection).await?;
+                Ok(Flow::Stop)
+            }
+        }
+    }
+}
+
+/// Strips EXIF, GPS and other metadata from images before they are stored or
+/// delivered, so senders do not give away where a photo was taken.
+///
+/// The image is re-encoded and encrypted again with the key of the original.
+/// Images that cannot be decoded are rejected rather than passed on with their
+/// metadata.
+pub struct ImageMetadata;
+
+#[async_trait]
+impl Middleware for ImageMetadata {
+    fn name(&self) -> &'static str {
+        "image_metadata"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (Message::Image { name, metadata, .. }, Some(content)) =
+            (&ctx.message, &ctx.attachment)
+        else {
+            return Ok(Flow::Continue);
+        };
+
+        let stripped = match strip_image_metadata(content.clone()).await {
+            Ok(stripped) => stripped,
+            Err(e) => {
+                info!("Rejected image '{}': {}", name, e);
+                let reply = processor.error_reply(
+                    ErrorCode::InvalidInput,
+                    processor.text("image-undecodable", &[("name", name.into())]),
+                    &[("reason", "image_undecodable"), ("name", name)],
+                );
+                processor.reply(ctx.client_id, &reply).await?;
+                return Ok(Flow::Stop);
+            }
+        };
+        if &stripped == content {
+            return Ok(Flow::Continue);
+        }
+
+        debug!(
+            "Stripped metadata from image '{}' ({} -> {} bytes)",
+            name,
+            content.len(),
+            stripped.len()
+        );
+        let (metadata, data) = processor.encrypt_attachment(metadata, &stripped).await?;
+        ctx.message = Message::Image {
+            name: name.clone(),
+            metadata,
+            data,
+        };
+        ctx.sha256 = Some(sha256_hex(&stripped));
+        ctx.attachment = Some(stripped);
+        Ok(Flow::Continue)
+    }
+}
+
+/// Rejects attachments that would take the sender's stored attachments over
+/// `ATTACHMENT_QUOTA_MIB`.
+///
+/// The quota is read from the configuration on every message, so it can be
+/// reloaded. Lowering it does not remove anything already stored; users over
+/// the new quota just cannot send attachments until they are below it.
+pub struct Quota {
+    config: SharedConfig,
+}
+
+impl Quota {
+    pub fn new(config: SharedConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl Middleware for Quota {
+    fn name(&self) -> &'static str {
+        "quota"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (Some(quota), Some(content)) =
+            (self.config.current().attachment_quota, &ctx.attachment)
+        else {
+            return Ok(Flow::Continue);
+        };
+
+        let (user_id, _) = ctx.sender()?;
+        let used = {
+            let conn = &mut *processor.pool().get().await?;
+            MessageRepository::attachment_bytes(conn, user_id).await? as u64
+        };
+        if !exceeds_quota(used, content.len() as u64, quota) {
+            return Ok(Flow::Continue);
+        }
+
+        info!(
+            "Rejected attachment of user {}: {} of {} bytes used",
+            user_id, used, quota
+        );
+        let (used_bytes, quota_bytes) = (used.to_string(), quota.to_string());
+        let reply = processor.error_reply(
+            ErrorCode::QuotaExceeded,
+            processor.text(
+                "attachment-quota-exceeded",
+                &[
+                    ("used", format_mib(used).into()),
+                    ("quota", format_mib(quota).into()),
+                ],
+            ),
+            &[
+                ("reason", "quota_exceeded"),
+                ("used_bytes", &used_bytes),
+                ("quota_bytes", &quota_bytes),
+            ],
+        );
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Returns `true` if storing `size` more bytes on top of `used` exceeds `quota`.
+pub(crate) fn exceeds_quota(used: u64, size: u64, quota: u64) -> bool {
+    used.saturating_add(size) > quota
+}
+
+/// Formats a byte count in MiB with one decimal, e.g. `12.5`.
+pub(crate) fn format_mib(bytes: u64) -> String {
+    format!("{:.1}", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Stores attachment content in the object storage, under
+/// `attachments/<SHA-256>`, and then the message in the database.
+///
+/// Content that is already stored, because someone sent the same file before,
+/// is not uploaded again; the message refers to the existing copy by its hash.
+/// If the upload fails, the message is not stored and the sender gets an
+/// error instead, so no message points at content that does not exist.
+pub struct Persistence;
+
+#[async_trait]
+impl Middleware for Persistence {
+    fn name(&self) -> &'static str {
+        "persistence"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (user_id, workspace_id) = ctx.sender()?;
+
+        if let (Some(storage), Some(content), Some(sha256)) =
+            (processor.storage(), &ctx.attachment, &ctx.sha256)
+        {
+            let key = attachment_key(sha256);
+            match storage.exists(&key).await {
+                Ok(true) => {
+                    debug!("Attachment {} is already stored", sha256);
+                    processor
+                        .metrics()
+                        .lock()
+                        .await
+                        .attachments_deduplicated
+                        .inc();
+                }
+                // If the check fails, storing the content again does no harm
+                Ok(false) | Err(_) => {
+                    if let Err(e) = storage.put(&key, content).await {
+                        error!(
+                            "Failed to store attachment of client {}: {}",
+                            ctx.client_id, e
+                        );
+                        let name = match &ctx.message {
+                            Message::File { name, .. } | Message::Image { name, .. } => {
+                                name.as_str()
+                            }
+                            _ => "",
+                        };
+                        let reply = processor.error_reply(
+                            ErrorCode::ServerError,
+                            processor.text("attachment-not-stored", &[("name", name.into())]),
+                            &[("reason", "storage_failed")],
+                        );
+                        processor.reply(ctx.client_id, &reply).await?;
+                        return Ok(Flow::Stop);
+                    }
+                }
+            }
+        }
+
+        ctx.stored = processor
+            .save_message_to_db(
+                &ctx.message,
+                user_id,
+                workspace_id,
+                ctx.sha256.clone(),
+                ctx.attachment.as_ref().map(|content| content.len() as i64),
+            )
+            .await?;
+        if let Some(transfer) = ctx.transfer.take() {
+            transfer.record();
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+/// Counts sent messages.
+pub struct MessageMetrics;
+
+#[async_trait]
+impl Middleware for MessageMetrics {
+    fn name(&self) -> &'static str {
+        "metrics"
+    }
+
+    async fn handle(
+        &self,
+        processor: &MessageProcessor,
+        _ctx: &mut MessageContext,
+    ) -> Result<Flow> {
+        processor.metrics().lock().await.messages_sent.inc();
+        Ok(Flow::Continue)
+    }
+}
+
+/// Acknowledges the message to its sender and delivers it to the workspace
+/// in a `ChatMessage` envelope naming the sender.
+pub struct Broadcast;
+
+#[async_trait]
+impl Middleware for Broadcast {
+    fn name(&self) -> &'static str {
+        "broadcast"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let (user_id, workspace_id) = ctx.sender()?;
+        let stored = ctx
+            .stored
+            .as_ref()
+            .map(|stored| (stored.seq as u64, stored.created_at.and_utc()));
+        let seq = stored.map(|(seq, _)| seq);
+        let sender = {
+            let conn = &mut *processor.pool().get().await?;
+            UserRepository::find_by_id(conn, user_id).await?.username
+        };
+
+        // First send acknowledgment to the sender
+        processor
+            .send_acknowledgment(ctx.client_id, &ctx.message, seq)
+            .await?;
+
+        // Then broadcast to all other authenticated users of the workspace,
+        // numbered if the message was stored
+        let message = match stored {
+            Some((seq, sent_at)) => Message::Sequenced {
+                seq,
+                sent_at: Some(sent_at),
+                message: Box::new(ctx.message.clone()),
+            },
+            None => ctx.message.clone(),
+        };
+        let message = Message::ChatMessage {
+            sender,
+            sent_at: stored.map_or_else(Utc::now, |(_, sent_at)| sent_at),
+            body: Box::new(message),
+        };
+        MessageBroadcaster::new(processor.clients().clone())
+            .broadcast_message(&message, Some(ctx.client_id), Some(workspace_id))
+            .await?;
+        Ok(Flow::Continue)
+    }
+}
+
+/// Tells members connected to other workspaces that a message was stored,
+/// so their clients can count unread messages per workspace.
+pub struct Activity;
+
+#[async_trait]
+impl Middleware for Activity {
+    fn name(&self) -> &'static str {
+        "activity"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        if ctx.stored.is_none() {
+            return Ok(Flow::Continue);
+        }
+        let (_, workspace_id) = ctx.sender()?;
+
+        // Skip the queries while everyone is in this workspace
+        let elsewhere = processor.clients().lock().await.values().any(|connection| {
+            connection.is_authenticated() && connection.workspace_id != Some(workspace_id)
+        });
+        if !elsewhere {
+            return Ok(Flow::Continue);
+        }
+
+        let (workspace, members) = {
+            let conn = &mut *processor.pool().get().await?;
+            (
+                WorkspaceRepository::find_by_id(conn, workspace_id).await?,
+                WorkspaceRepository::find_member_ids(conn, workspace_id).await?,
+            )
+        };
+        MessageBroadcaster::new(processor.clients().clone())
+            .send_activity(workspace_id, &workspace.slug, &members)
+            .await?;
+        Ok(Flow::Continue)
+    }
+}
+
+/// Notifies users mentioned in stored text messages.
+pub struct Notifications;
+
+#[async_trait]
+impl Middleware for Notifications {
+    fn name(&self) -> &'static str {
+        "notifications"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Some(StoredMessage {
+            id,
+            content: Some(text),
+            ..
+        }) = &ctx.stored
+        else {
+            return Ok(Flow::Continue);
+        };
+
+        let (user_id, workspace_id) = ctx.sender()?;
+        let notifications =
+            NotificationService::new(processor.pool().clone(), processor.clients().clone());
+        if let Err(e) = notifications
+            .notify_mentions(user_id, *id, workspace_id, text)
+            .await
+        {
+            error!("Failed to deliver mention notifications: {}", e);
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+/// Changes the sender's password on request.
+pub struct PasswordChange {
+    passwords: Arc<PasswordService>,
+}
+
+impl PasswordChange {
+    pub fn new(passwords: Arc<PasswordService>) -> Self {
+        Self { passwords }
+    }
+}
+
+#[async_trait]
+impl Middleware for PasswordChange {
+    fn name(&self) -> &'static str {
+        "password_change"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Message::ChangePassword {
+            current_password,
+            new_password,
+        } = &ctx.message
+        else {
+            return Ok(Flow::Continue);
+        };
+
+        let (user_id, _) = ctx.sender()?;
+        let result = {
+            let conn = &mut *processor.pool().get().await?;
+            self.passwords
+                .change(conn, user_id, current_password, new_password)
+                .await
+        };
+
+        let reply = match result {
+            Ok(()) => {
+                info!("User {} changed their password", user_id);
+                Message::System(processor.text("password-changed", &[]))
+            }
+            Err(PasswordChangeError::WrongPassword) => processor.error_reply(
+                ErrorCode::PermissionDenied,
+                processor.text("password-wrong", &[]),
+                &[("reason", "wrong_password")],
+            ),
+            Err(PasswordChangeError::Policy(violations)) => {
+                password_policy_error(processor, "password-rejected", &violations)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Builds the reply to a password that breaks the password policy, listing
+/// every broken rule.
+fn password_policy_error(
+    processor: &MessageProcessor,
+    key: &str,
+    violations: &[PasswordViolation],
+) -> Message {
+    let messages = violations
+        .iter()
+        .map(|violation| violation.message(processor.locale()))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let rules = violations
+        .iter()
+        .map(|violation| violation.rule())
+        .collect::<Vec<_>>()
+        .join(",");
+    processor.error_reply(
+        ErrorCode::InvalidInput,
+        processor.text(key, &[("violations", messages.into())]),
+        &[("reason", "password_policy"), ("violations", &rules)],
+    )
+}
+
+/// Creates accounts for connections that are not logged in and logs them in
+/// as the new user.
+pub struct Registration {
+    registrations: RegistrationService,
+}
+
+impl Registration {
+    pub fn new(registrations: RegistrationService) -> Self {
+        Self { registrations }
+    }
+}
+
+#[async_trait]
+impl Middleware for Registration {
+    fn name(&self) -> &'static str {
+        "registration"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Message::Register {
+            username,
+            email,
+            password,
+            invite_code,
+        } = &ctx.message
+        else {
+            return Ok(Flow::Continue);
+        };
+
+        if processor.get_auth_status(ctx.client_id).await?.is_some() {
+            let reply = processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("register-logged-in", &[]),
+                &[("reason", "already_authenticated")],
+            );
+            processor.reply(ctx.client_id, &reply).await?;
+            return Ok(Flow::Stop);
+        }
+
+        let request = NewUserRequest {
+            username: username.clone(),
+            email: email.clone(),
+            password: password.clone(),
+            invite_code: invite_code.clone(),
+        };
+        let result = {
+            let conn = &mut *processor.pool().get().await?;
+            self.registrations.register(conn, request).await
+        };
+
+        let reply = match result {
+            Ok(user) => {
+                processor.handle_registered(ctx.client_id, &user).await?;
+                return Ok(Flow::Stop);
+            }
+            Err(RegistrationError::InviteRequired) => processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("register-invite-required", &[]),
+                &[("reason", "invite_required")],
+            ),
+            Err(RegistrationError::InvalidInvite) => processor.error_reply(
+                ErrorCode::InvalidInput,
+                processor.text("register-invite-invalid", &[]),
+                &[("reason", "invalid_invite")],
+            ),
+            Err(RegistrationError::Policy(violations)) => {
+                password_policy_error(processor, "register-password-rejected", &violations)
+            }
+            Err(RegistrationError::Write(UserWriteError::InvalidUsername(e))) => processor
+                .error_reply(
+                    ErrorCode::InvalidInput,
+                    processor.text(
+                        "register-username-invalid",
+                        &[("error", e.to_string().into())],
+                    ),
+                    &[("reason", "invalid_username"), ("rule", e.rule())],
+                ),
+            Err(RegistrationError::Write(UserWriteError::UsernameTaken)) => processor.error_reply(
+                ErrorCode::Conflict,
+                processor.text("register-username-taken", &[]),
+                &[("reason", "username_taken")],
+            ),
+            Err(RegistrationError::Write(UserWriteError::EmailTaken)) => processor.error_reply(
+                ErrorCode::Conflict,
+                processor.text("register-email-taken", &[]),
+                &[("reason", "email_taken")],
+            ),
+            Err(RegistrationError::Write(UserWriteError::Database(e))) => return Err(e.into()),
+        };
+        processor.reply(ctx.client_id, &reply).await?;
+        Ok(Flow::Stop)
+    }
+}
+
+/// Calls the webhooks of bots mentioned in stored text messages.
+pub struct Webhooks {
+    webhooks: WebhookService,
+}
+
+impl Webhooks {
+    pub fn new(webhooks: WebhookService) -> Self {
+        Self { webhooks }
+    }
+}
+
+#[async_trait]
+impl Middleware for Webhooks {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, processor: &MessageProcessor, ctx: &mut MessageContext) -> Result<Flow> {
+        let Some(stored) = &ctx.stored else {
+            return Ok(Flow::Continue);
+        };
+
+        let conn = &mut *processor.pool().get().await?;
+        if let Err(e) = self.webhooks.deliver_mentions(conn, stored).await {
+            error!("Failed to queue webhook calls: {}", e);
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named(&'static str);
+
+    #[async_trait]
+    impl Middleware for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn handle(
+            &self,
+            _processor: &MessageProcessor,
+            _ctx: &mut MessageContext,
+        ) -> Result<Flow> {
+            Ok(Flow::Continue)
+        }
+    }
+
+    #[test]
+    fn test_registration_order() {
+        let pipeline = Pipeline::standard(SharedConfig::default())
+            .register_before("persistence", Named("audit"))
+            .register(Named("last"))
+            .remove("moderation");
+
+        let names = pipeline.names();
+        assert_eq!(names.first(), Some(&"auth"));
+        assert_eq!(names.last(), Some(&"last"));
+        assert!(!names.contains(&"moderation"));
+
+        let position = |name| names.iter().position(|n| *n == name).unwrap();
+        assert_eq!(position("audit") + 1, position("persistence"));
+        assert!(position("rate_limit") < position("broadcast"));
+        assert_eq!(position("workspace_switch") + 1, position("terms"));
+        assert!(position("terms") < position("commands"));
+        assert!(position("announcements") < position("rate_limit"));
+        assert!(position("rate_limit") < position("antispam"));
+        assert!(position("antispam") < position("status"));
+        assert_eq!(position("quotes") + 1, position("reports"));
+        assert_eq!(position("reports") + 1, position("edits"));
+        assert_eq!(position("edits") + 1, position("transfer_cap"));
+        assert_eq!(position("transfer_cap") + 1, position("downloads"));
+        assert!(position("actions") < position("persistence"));
+        assert_eq!(position("broadcast") + 1, position("activity"));
+        assert_eq!(position("attachments") + 1, position("image_metadata"));
+        assert_eq!(position("image_metadata") + 1, position("quota"));
+    }
+
+    #[test]
+    fn test_exceeds_quota() {
+        let mib = 1024 * 1024;
+        assert!(!exceeds_quota(0, mib, mib));
+        assert!(!exceeds_quota(mib / 2, mib / 2, mib));
+        assert!(exceeds_quota(mib / 2, mib / 2 + 1, mib));
+        assert!(exceeds_quota(u64::MAX, 1, mib));
+        assert_eq!(format_mib(mib * 25 / 2), "12.5");
+        assert_eq!(format_mib(0), "0.0");
+    }
+
+    #[test]
+    fn test_transfer_cap_resets_daily() {
+        let cap = TransferCap::new(SharedConfig::default());
+        let today = NaiveDate::from_ymd_opt(2025, 4, 1).unwrap();
+
+        cap.check(1, 600, 1000, today).unwrap().record();
+        assert_eq!(cap.check(1, 600, 1000, today).unwrap_err(), 600);
+        cap.check(2, 1000, 1000, today).unwrap().record();
+        cap.check(1, 400, 1000, today).unwrap().record();
+        assert_eq!(cap.check(1, 1, 1000, today).unwrap_err(), 1000);
+        assert!(cap.check(1, 1, 1000, today.succ_opt().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_transfer_cap_counts_recorded_transfers_only() {
+        let cap = TransferCap::new(SharedConfig::default());
+        let today = NaiveDate::from_ymd_opt(2025, 4, 1).unwrap();
+
+        // A transfer rejected later in the pipeline is never recorded
+        drop(cap.check(1, 600, 1000, today).unwrap());
+        let transfer = cap.check(1, 600, 1000, today).unwrap();
+        transfer.record();
+        assert_eq!(cap.check(1, 600, 1000, today).unwrap_err(), 600);
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let limiter = RateLimit::new(SharedConfig::default());
+        let start = Instant::now();
+
+        assert_eq!(limiter.check(1, 2, start), None);
+        assert_eq!(limiter.check(1, 2, start), None);
+        assert_eq!(
+            limiter.check(1, 2, start + Duration::from_secs(20)),
+            Some(Duration::from_secs(40))
+        );
+        // Other users have their own window
+        assert_eq!(limiter.check(2, 2, start), None);
+        // The window resets after a minute
+        assert_eq!(limiter.check(1, 2, start + RATE_LIMIT_WINDOW), None);
+    }
+
+    #[test]
+    fn test_quote_excerpt() {
+        assert_eq!(quote_excerpt("Short and sweet"), "Short and sweet");
+
+        // Cut at a character boundary, without the space before the cut
+        let long = format!("{} tail", "ř".repeat(QUOTE_EXCERPT_CHARS - 1));
+        let excerpt = quote_excerpt(&long);
+        assert!(excerpt.ends_with("ř…"));
+        assert_eq!(excerpt.chars().count(), QUOTE_EXCERPT_CHARS);
+    }
+
+    #[test]
+    fn test_validate_poll() {
+        let options = |options: &[&str]| options.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            validate_poll("Lunch?", &options(&["Pizza", "Sushi"])),
+            Ok(())
+        );
+        assert_eq!(
+            validate_poll("", &options(&["Pizza", "Sushi"])),
+            Err("poll_invalid_question")
+        );
+        assert_eq!(
+            validate_poll("Lunch?", &options(&["Pizza"])),
+            Err("poll_option_count")
+        );
+        assert_eq!(
+            validate_poll("Lunch?", &options(&["Pizza"; MAX_POLL_OPTIONS + 1])),
+            Err("poll_option_count")
+        );
+        assert_eq!(
+            validate_poll("Lunch?", &options(&["Pizza", ""])),
+            Err("poll_invalid_option")
+        );
+    }
+
+    #[test]
+    fn test_contains_blocked_word() {
+        let blocked = vec!["spam".to_string()];
+        assert!(contains_blocked_word("Buy SPAM now!", &blocked));
+        assert!(!contains_blocked_word("spammer", &blocked));
+        assert!(!contains_blocked_word("hello", &blocked));
+    }
+}
@@ -1,35 +1,75 @@
 //! Message processing service for the chat server.
 //!
-//! This module handles the processing of messages, including authentication,
-//! message persistence, and message broadcasting to appropriate clients.
+//! This module handles logins and runs every other message through the
+//! middleware [`Pipeline`], which takes care of persistence and broadcasting.
 
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::models::message::{MessageType, NewMessage};
+use crate::config::{AttachmentPolicy, DuplicateLoginPolicy, OnboardingConfig, SharedConfig};
+use crate::i18n;
+use crate::models::do_not_disturb::DndSettings;
+use crate::models::message::{Message as StoredMessage, MessageType, NewMessage};
+use crate::models::user::User;
+use crate::models::workspace::{Workspace, DEFAULT_WORKSPACE_SLUG};
+use crate::repositories::client_certificate::ClientCertificateRepository;
+use crate::repositories::do_not_disturb::DoNotDisturbRepository;
+use crate::repositories::invitation::InvitationRepository;
+use crate::repositories::message::MessageRepository;
+use crate::repositories::preference::PreferenceRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::attachment::{self, AttachmentKind};
 use crate::services::auth::AuthService;
+use crate::services::auth_throttle::{AuthFailure, AuthThrottle};
+use crate::services::feature_flags::FeatureFlags;
+use crate::services::onboarding::OnboardingService;
+use crate::services::room_keys;
+use crate::services::storage::Storage;
 use crate::types::{AuthState, Clients};
 use crate::utils::db_connection::DbPool;
-use crate::utils::metrics::Metrics;
-use anyhow::Result;
-use chat_common::async_message_stream::AsyncMessageStream;
+use crate::utils::metrics::{ErrorClass, Metrics};
+use anyhow::{bail, Result};
+use chat_common::encryption::file::{EncryptedFileMetadata, IntegrityError};
 use chat_common::encryption::EncryptionService;
-use chat_common::{ErrorCode, Message};
-use diesel_async::RunQueryDsl;
+use chat_common::error::ChatError;
+use chat_common::{
+    ErrorCode, HistoricalKind, HistoricalMessage, Message, HISTORY_CAPABILITY,
+    MAX_EPHEMERAL_TTL_SECS,
+};
+use chrono::Utc;
+use fluent_bundle::FluentValue;
+use tokio::io::BufReader;
 use tokio::net::tcp::OwnedReadHalf;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn, Span};
 
-use super::broadcast::MessageBroadcaster;
+use super::pipeline::{MessageContext, Pipeline};
 
 /// Service responsible for processing incoming messages and managing message flow.
 ///
-/// The `MessageProcessor` handles message authentication, persistence, and broadcasting.
-/// It ensures messages are properly saved to the database and delivered to appropriate clients.
-pub(super) struct MessageProcessor {
+/// The `MessageProcessor` handles logins and runs all other messages through its
+/// middleware pipeline. Middlewares use it to reach clients, storage and the
+/// encryption service, and to reply to the sender.
+pub struct MessageProcessor {
     clients: Clients,
     pool: Arc<DbPool>,
     encryption: Arc<EncryptionService>,
     metrics: Arc<Mutex<Metrics>>,
+    /// `client_msg_id` of the request being processed, echoed in error replies
+    in_reply_to: Option<String>,
+    attachment_policy: AttachmentPolicy,
+    duplicate_login_policy: DuplicateLoginPolicy,
+    /// Welcome message, default workspaces and terms for joining users
+    onboarding: OnboardingConfig,
+    feature_flags: FeatureFlags,
+    storage: Option<Arc<dyn Storage>>,
+    pipeline: Arc<Pipeline>,
+    /// Failed-login limits shared by all connections
+    auth_throttle: Arc<AuthThrottle>,
+    /// Latest messages of the workspace replayed after logging in
+    history_limit: u32,
+    /// Locale of the sender's connection, used for replies
+    locale: String,
 }
 
 impl MessageProcessor {
@@ -51,6 +91,113 @@ impl MessageProcessor {
             pool,
             encryption,
             metrics,
+            in_reply_to: None,
+            attachment_policy: AttachmentPolicy::default(),
+            duplicate_login_policy: DuplicateLoginPolicy::Allow,
+            onboarding: OnboardingConfig::default(),
+            feature_flags: FeatureFlags::default(),
+            storage: None,
+            pipeline: Arc::new(Pipeline::standard(SharedConfig::default())),
+            auth_throttle: Arc::new(AuthThrottle::new(SharedConfig::default())),
+            history_limit: 0,
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+        }
+    }
+
+    /// Sets the middleware pipeline messages are processed with.
+    pub fn with_pipeline(mut self, pipeline: Arc<Pipeline>) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Sets the failed-login limits shared with other connections.
+    pub fn with_auth_throttle(mut self, auth_throttle: Arc<AuthThrottle>) -> Self {
+        self.auth_throttle = auth_throttle;
+        self
+    }
+
+    /// Sets the feature flags deciding which message types are available.
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlags) -> Self {
+        self.feature_flags = feature_flags;
+        self
+    }
+
+    /// Sets the storage attachment content is kept in.
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Restricts the attachment types accepted in file and image messages.
+    pub fn with_attachment_policy(mut self, attachment_policy: AttachmentPolicy) -> Self {
+        self.attachment_policy = attachment_policy;
+        self
+    }
+
+    /// Sets whether a login signs out the user's other sessions.
+    pub fn with_duplicate_login_policy(mut self, policy: DuplicateLoginPolicy) -> Self {
+        self.duplicate_login_policy = policy;
+        self
+    }
+
+    /// Sets what happens when users join the server.
+    pub fn with_onboarding(mut self, onboarding: OnboardingConfig) -> Self {
+        self.onboarding = onboarding;
+        self
+    }
+
+    /// Sets how many of the latest messages of the workspace clients that
+    /// support it are sent after logging in; 0 sends none.
+    pub fn with_history_limit(mut self, history_limit: u32) -> Self {
+        self.history_limit = history_limit;
+        self
+    }
+
+    /// Sets the locale replies to the sender are written in.
+    pub fn with_locale(mut self, locale: String) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Sets the `client_msg_id` that error replies refer back to.
+    pub fn with_reply_to(mut self, client_msg_id: Option<String>) -> Self {
+        self.in_reply_to = client_msg_id;
+        self
+    }
+
+    /// Formats the message `key` in the sender's locale.
+    ///
+    /// # Arguments
+    /// * `key` - The message key in the `.ftl` files
+    /// * `args` - Values of the message's `$variables`
+    pub fn text(&self, key: &str, args: &[(&str, FluentValue<'_>)]) -> String {
+        i18n::text(&self.locale, key, args)
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Builds an error reply for the request being processed.
+    ///
+    /// # Arguments
+    /// * `code` - The error code
+    /// * `message` - A human-readable description
+    /// * `details` - Machine-readable context as key/value pairs
+    pub fn error_reply(
+        &self,
+        code: ErrorCode,
+        message: String,
+        details: &[(&str, &str)],
+    ) -> Message {
+        Message::Error {
+            code,
+            message,
+            in_reply_to: self.in_reply_to.clone(),
+            details: details
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
         }
     }
 
@@ -66,13 +213,9 @@ impl MessageProcessor {
     ///
     /// # Message Processing Flow
     /// 1. Authentication messages are handled separately
-    /// 2. For other messages, client authentication is verified
-    /// 3. If authenticated:
-    ///    - Message is saved to database
-    ///    - Acknowledgment is sent to sender
-    ///    - Message is broadcast to other authenticated clients
-    /// 4. If not authenticated:
-    ///    - Error message is sent to client
+    /// 2. All other messages run through the middleware pipeline, which
+    ///    verifies authentication, applies feature flags, rate limits and
+    ///    moderation, checks attachments, and stores and broadcasts the message
     pub async fn process(
         &self,
         _stream: Option<&OwnedReadHalf>,
@@ -83,47 +226,205 @@ impl MessageProcessor {
             return self.handle_auth(client_id, username, password).await;
         }
 
-        let (is_authenticated, user_id) = self.get_auth_status(client_id).await?;
+        let mut ctx = MessageContext::new(client_id, message.clone());
+        self.pipeline.run(self, &mut ctx).await
+    }
 
-        if !is_authenticated {
-            return self.handle_unauthenticated(client_id).await;
+    pub fn clients(&self) -> &Clients {
+        &self.clients
+    }
+
+    pub fn pool(&self) -> &Arc<DbPool> {
+        &self.pool
+    }
+
+    pub fn encryption(&self) -> &EncryptionService {
+        &self.encryption
+    }
+
+    pub fn metrics(&self) -> &Arc<Mutex<Metrics>> {
+        &self.metrics
+    }
+
+    pub fn feature_flags(&self) -> &FeatureFlags {
+        &self.feature_flags
+    }
+
+    pub fn storage(&self) -> Option<&Arc<dyn Storage>> {
+        self.storage.as_ref()
+    }
+
+    /// Sends a reply to a single connection. Error replies are counted in
+    /// `chat_errors_total`.
+    pub async fn reply(&self, client_id: usize, message: &Message) -> Result<()> {
+        if let Message::Error { code, .. } = message {
+            self.metrics
+                .lock()
+                .await
+                .count_error(ErrorClass::of_code(code));
+        }
+        let clients = self.clients.lock().await;
+        if let Some(client) = clients.get(&client_id) {
+            client.send(message).await?;
         }
+        Ok(())
+    }
 
-        // Save message to database
-        self.save_message_to_db(message, user_id).await?;
+    /// Checks the content of a file or image attachment against its claimed type.
+    ///
+    /// The attachment is decrypted and sniffed; files that contain an image are
+    /// re-classified as images. Other messages are returned unchanged.
+    ///
+    /// # Arguments
+    /// * `message` - The message to check
+    ///
+    /// # Returns
+    /// * `Result<Result<(Message, Option<Vec<u8>>), Message>>` - The message to deliver
+    ///   with an attachment's decrypted content, or the error reply for the sender
+    ///   if the attachment is rejected
+    pub(super) async fn check_attachment(
+        &self,
+        message: &Message,
+    ) -> Result<Result<(Message, Option<Vec<u8>>), Message>> {
+        let (name, metadata, data, claimed_image) = match message {
+            Message::File {
+                name,
+                metadata,
+                data,
+            } => (name, metadata, data, false),
+            Message::Image {
+                name,
+                metadata,
+                data,
+            } => (name, metadata, data, true),
+            message => return Ok(Ok((message.clone(), None))),
+        };
 
-        // Increment message counter
-        self.metrics.lock().await.messages_sent.inc();
+        let decrypted = match self.decrypt_attachment(metadata, data).await {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                let reply = match e.downcast_ref::<IntegrityError>() {
+                    Some(integrity) => self.error_reply(
+                        ErrorCode::IntegrityError,
+                        self.text("attachment-corrupted", &[("name", name.into())]),
+                        &[
+                            ("reason", "integrity_check_failed"),
+                            ("name", name),
+                            ("expected_sha256", &integrity.expected),
+                            ("actual_sha256", &integrity.actual),
+                        ],
+                    ),
+                    None => self.error_reply(
+                        ErrorCode::InvalidInput,
+                        self.text(
+                            "attachment-undecryptable",
+                            &[("name", name.into()), ("error", e.to_string().into())],
+                        ),
+                        &[("reason", "attachment_undecryptable"), ("name", name)],
+                    ),
+                };
+                return Ok(Err(reply));
+            }
+        };
+        let inspection =
+            match attachment::inspect(name, claimed_image, &decrypted, &self.attachment_policy) {
+                Ok(inspection) => inspection,
+                Err(rejection) => {
+                    info!(
+                        "Rejected attachment '{}': {}",
+                        name,
+                        rejection.message(i18n::DEFAULT_LOCALE)
+                    );
+                    return Ok(Err(self.error_reply(
+                        ErrorCode::InvalidInput,
+                        rejection.message(&self.locale),
+                        &[
+                            ("reason", "attachment_rejected"),
+                            ("name", name),
+                            ("detected_type", &rejection.mime),
+                        ],
+                    )));
+                }
+            };
 
-        // First send acknowledgment to the sender
-        self.send_acknowledgment(client_id, message).await?;
+        let (name, metadata, data) = (inspection.name, metadata.clone(), data.clone());
+        let message = match inspection.kind {
+            AttachmentKind::Image => {
+                if !claimed_image {
+                    info!("Re-classified file '{}' as {}", name, inspection.mime);
+                }
+                Message::Image {
+                    name,
+                    metadata,
+                    data,
+                }
+            }
+            AttachmentKind::File => Message::File {
+                name,
+                metadata,
+                data,
+            },
+        };
+        Ok(Ok((message, Some(decrypted))))
+    }
 
-        // Then broadcast to all other authenticated users
-        let broadcaster = MessageBroadcaster::new(self.clients.clone());
-        broadcaster
-            .broadcast_message(message, Some(client_id))
+    /// Decrypts the content of a file or image attachment.
+    async fn decrypt_attachment(
+        &self,
+        metadata: &serde_json::Value,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let metadata: EncryptedFileMetadata = serde_json::from_value(metadata.clone())?;
+        let mut decrypted = Vec::new();
+        self.encryption
+            .file_for(metadata.key_id.as_deref())?
+            .decrypt_stream(BufReader::new(data), &mut decrypted, &metadata)
             .await?;
+        Ok(decrypted)
+    }
 
-        Ok(())
+    /// Encrypts replaced attachment content with the key of the original.
+    ///
+    /// # Arguments
+    /// * `metadata` - The encryption metadata of the original attachment
+    /// * `content` - The new content
+    ///
+    /// # Returns
+    /// * `Result<(serde_json::Value, Vec<u8>)>` - The metadata and the encrypted content
+    pub(super) async fn encrypt_attachment(
+        &self,
+        metadata: &serde_json::Value,
+        content: &[u8],
+    ) -> Result<(serde_json::Value, Vec<u8>)> {
+        let metadata: EncryptedFileMetadata = serde_json::from_value(metadata.clone())?;
+        let mut encrypted = Vec::new();
+        let metadata = self
+            .encryption
+            .file_for(metadata.key_id.as_deref())?
+            .encrypt_stream(BufReader::new(content), &mut encrypted)
+            .await?;
+        Ok((serde_json::to_value(metadata)?, encrypted))
     }
 
-    /// Retrieves the authentication status and user ID for a client.
+    /// Retrieves the user and workspace of an authenticated client.
     ///
     /// # Arguments
     /// * `client_id` - The ID of the client to check
     ///
     /// # Returns
-    /// * `Result<(bool, i32)>` - Tuple containing (is_authenticated, user_id)
-    async fn get_auth_status(&self, client_id: usize) -> Result<(bool, i32)> {
+    /// * `Result<Option<(i32, i32)>>` - Tuple containing (user_id, workspace_id),
+    ///   or None if the client is not authenticated
+    pub(super) async fn get_auth_status(&self, client_id: usize) -> Result<Option<(i32, i32)>> {
         let clients = self.clients.lock().await;
         let client = clients
             .get(&client_id)
             .ok_or_else(|| anyhow::anyhow!("Client not found"))?;
 
-        Ok((
-            client.is_authenticated(),
-            client.user_id.unwrap_or_default(),
-        ))
+        if !client.is_authenticated() {
+            return Ok(None);
+        }
+
+        Ok(client.user_id.zip(client.workspace_id))
     }
 
     /// Handles unauthenticated client messages by sending an error response.
@@ -133,14 +434,16 @@ impl MessageProcessor {
     ///
     /// # Returns
     /// * `Result<()>` - Ok if the error was sent successfully, Err otherwise
-    async fn handle_unauthenticated(&self, client_id: usize) -> Result<()> {
+    pub(super) async fn handle_unauthenticated(&self, client_id: usize) -> Result<()> {
+        self.metrics.lock().await.count_error(ErrorClass::Auth);
         let mut clients = self.clients.lock().await;
         if let Some(client) = clients.get_mut(&client_id) {
-            let error = Message::Error {
-                code: ErrorCode::PermissionDenied,
-                message: "Authentication required".to_string(),
-            };
-            client.writer.write_message(&error).await?;
+            let error = self.error_reply(
+                ErrorCode::PermissionDenied,
+                self.text("auth-required", &[]),
+                &[("reason", "not_authenticated")],
+            );
+            client.send(&error).await?;
         }
         Ok(())
     }
@@ -150,24 +453,61 @@ impl MessageProcessor {
     /// # Arguments
     /// * `message` - The message to save
     /// * `user_id` - The ID of the user sending the message
+    /// * `workspace_id` - The workspace the message was sent in
+    /// * `sha256` - The SHA-256 of an attachment's content
+    /// * `attachment_size` - The size of an attachment's content in bytes
     ///
     /// # Returns
-    /// * `Result<()>` - Ok if the message was saved successfully, Err otherwise
-    async fn save_message_to_db(&self, message: &Message, user_id: i32) -> Result<()> {
+    /// * `Result<Option<StoredMessage>>` - The stored message, or None for message
+    ///   types that are not persisted
+    pub(super) async fn save_message_to_db(
+        &self,
+        message: &Message,
+        user_id: i32,
+        workspace_id: i32,
+        sha256: Option<String>,
+        attachment_size: Option<i64>,
+    ) -> Result<Option<StoredMessage>> {
         let conn = &mut *self.pool.get().await?;
 
         let new_message = match message {
-            Message::Text(content) => {
+            Message::Text(content)
+            | Message::PriorityText { content, .. }
+            | Message::Reply { content, .. } => {
                 // Decrypt the text message before saving
                 let encrypted: chat_common::encryption::message::EncryptedMessage =
                     serde_json::from_str(content)?;
-                let decrypted = self.encryption.message().decrypt(&encrypted)?;
+                let decrypted = self
+                    .encryption
+                    .message_for(encrypted.key_id.as_deref())?
+                    .decrypt(&encrypted)?;
 
                 Some(NewMessage {
                     sender_id: user_id,
                     message_type: MessageType::Text,
                     content: Some(decrypted),
                     file_name: None,
+                    workspace_id,
+                    sha256: None,
+                    attachment_size: None,
+                })
+            }
+            Message::Action { content, .. } => {
+                let encrypted: chat_common::encryption::message::EncryptedMessage =
+                    serde_json::from_str(content)?;
+                let decrypted = self
+                    .encryption
+                    .message_for(encrypted.key_id.as_deref())?
+                    .decrypt(&encrypted)?;
+
+                Some(NewMessage {
+                    sender_id: user_id,
+                    message_type: MessageType::Action,
+                    content: Some(decrypted),
+                    file_name: None,
+                    workspace_id,
+                    sha256: None,
+                    attachment_size: None,
                 })
             }
             Message::File { name, .. } => Some(NewMessage {
@@ -175,24 +515,29 @@ impl MessageProcessor {
                 message_type: MessageType::File,
                 content: None,
                 file_name: Some(name.clone()),
+                workspace_id,
+                sha256: sha256.clone(),
+                attachment_size,
             }),
             Message::Image { name, .. } => Some(NewMessage {
                 sender_id: user_id,
                 message_type: MessageType::Image,
                 content: None,
                 file_name: Some(name.clone()),
+                workspace_id,
+                sha256: sha256.clone(),
+                attachment_size,
             }),
             _ => None,
         };
 
-        if let Some(msg) = new_message {
-            diesel::insert_into(crate::schema::messages::table)
-                .values(&msg)
-                .execute(conn)
-                .await?;
-        }
+        let Some(msg) = new_message else {
+            return Ok(None);
+        };
 
-        Ok(())
+        let stored = MessageRepository::create(conn, msg).await?;
+
+        Ok(Some(stored))
     }
 
     /// Sends an acknowledgment message to the sender.
@@ -200,27 +545,54 @@ impl MessageProcessor {
     /// # Arguments
     /// * `client_id` - The ID of the client to send the acknowledgment to
     /// * `message` - The original message that was processed
+    /// * `seq` - Sequence number of the stored message, sent along with the
+    ///   acknowledgment so the sender does not count its own message as missed
     ///
     /// # Returns
     /// * `Result<()>` - Ok if the acknowledgment was sent successfully, Err otherwise
-    async fn send_acknowledgment(&self, client_id: usize, message: &Message) -> Result<()> {
+    pub(super) async fn send_acknowledgment(
+        &self,
+        client_id: usize,
+        message: &Message,
+        seq: Option<u64>,
+    ) -> Result<()> {
         let ack_message = match message {
-            Message::Text(_) => Some(Message::System("Message sent successfully".to_string())),
-            Message::File { name, .. } => Some(Message::System(format!(
-                "File '{}' sent successfully",
-                name
-            ))),
-            Message::Image { name, .. } => Some(Message::System(format!(
-                "Image '{}' sent successfully",
-                name
+            Message::Text(_)
+            | Message::PriorityText { .. }
+            | Message::Reply { .. }
+            | Message::Action { .. } => Some(Message::System(self.text("message-sent", &[]))),
+            Message::Ephemeral {
+                expires_at: Some(expires_at),
+                ..
+            } => Some(Message::System(self.text(
+                "ephemeral-sent",
+                &[(
+                    "expires_at",
+                    expires_at.format("%H:%M:%S UTC").to_string().into(),
+                )],
             ))),
+            Message::File { name, .. } => Some(Message::System(
+                self.text("file-sent", &[("name", name.into())]),
+            )),
+            Message::Image { name, .. } => Some(Message::System(
+                self.text("image-sent", &[("name", name.into())]),
+            )),
             _ => None,
         };
 
+        let ack_message = match (ack_message, seq) {
+            (Some(ack), Some(seq)) => Some(Message::Sequenced {
+                seq,
+                sent_at: None,
+                message: Box::new(ack),
+            }),
+            (ack, _) => ack,
+        };
+
         if let Some(ack) = ack_message {
             let mut clients = self.clients.lock().await;
             if let Some(client) = clients.get_mut(&client_id) {
-                if let Err(e) = client.writer.write_message(&ack).await {
+                if let Err(e) = client.send(&ack).await {
                     error!("Failed to send acknowledgment: {}", e);
                 }
             }
@@ -231,52 +603,592 @@ impl MessageProcessor {
 
     /// Handles client authentication.
     ///
+    /// Logins for a username that failed too often are refused without
+    /// checking the password. Failed logins are audited, counted, and answered
+    /// after a delay that grows with every failure on the connection.
+    ///
     /// # Arguments
     /// * `client_id` - The ID of the client to authenticate
     /// * `username` - The username provided for authentication
     /// * `password` - The password provided for authentication
     ///
     /// # Returns
-    /// * `Result<()>` - Ok if authentication was processed successfully, Err otherwise
+    /// * `Result<()>` - Ok if authentication was processed successfully, Err otherwise,
+    ///   including when the connection exceeded its failed-login limit
     async fn handle_auth(&self, client_id: usize, username: &str, password: &str) -> Result<()> {
+        if let Some(retry_after) = self.auth_throttle.locked_for(username, Instant::now()) {
+            return self
+                .handle_auth_failure(client_id, username, AuthFailure::Locked { retry_after })
+                .await;
+        }
+
         let auth_service = AuthService::new(self.pool.clone());
 
-        match auth_service.authenticate(username, password).await? {
-            Some((user_id, token)) => {
-                let mut clients = self.clients.lock().await;
-                if let Some(client) = clients.get_mut(&client_id) {
-                    client.user_id = Some(user_id);
-                    client.auth_state = AuthState::Authenticated {
-                        user_id,
-                        token: token.clone(),
-                    };
+        let authenticated = match auth_service.authenticate(username, password).await? {
+            Some((user_id, token)) => match self.resolve_workspace(user_id).await? {
+                Some(workspace_id) => Ok((user_id, token, workspace_id)),
+                None => Err(AuthFailure::NoWorkspace),
+            },
+            None => Err(AuthFailure::InvalidCredentials),
+        };
 
-                    let response = Message::AuthResponse {
-                        success: true,
-                        token: Some(token),
-                        message: "Authentication successful".to_string(),
-                    };
+        let (user_id, token, workspace_id) = match authenticated {
+            Ok(authenticated) => authenticated,
+            Err(failure) => return self.handle_auth_failure(client_id, username, failure).await,
+        };
 
-                    info!("Client {} authenticated successfully", client_id);
+        self.auth_throttle.record_success(username);
+        Span::current().record("user", username);
+        self.complete_login(client_id, user_id, token, workspace_id)
+            .await
+    }
 
-                    client.writer.write_message(&response).await?;
+    /// Logs in a connection whose TLS client certificate is registered for an
+    /// account, without an `Auth` message.
+    ///
+    /// Connections presenting an unknown certificate are told so and stay
+    /// logged out, so they can still log in with a password.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client to authenticate
+    /// * `fingerprint` - Fingerprint of the presented certificate
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the certificate was processed, Err otherwise
+    pub async fn handle_certificate_auth(&self, client_id: usize, fingerprint: &str) -> Result<()> {
+        let user = {
+            let conn = &mut *self.pool.get().await?;
+            ClientCertificateRepository::find_user(conn, fingerprint).await?
+        };
+        let Some(user) = user else {
+            warn!(
+                target: "audit",
+                event = "certificate_unknown",
+                client_id,
+                fingerprint,
+                "Unknown client certificate"
+            );
+            return self
+                .reply(
+                    client_id,
+                    &Message::System(self.text("auth-certificate-unknown", &[])),
+                )
+                .await;
+        };
+
+        let Some(workspace_id) = self.resolve_workspace(user.id).await? else {
+            return self
+                .handle_auth_failure(client_id, &user.username, AuthFailure::NoWorkspace)
+                .await;
+        };
+
+        info!(
+            target: "audit",
+            event = "certificate_login",
+            client_id,
+            user_id = user.id,
+            fingerprint,
+            "Logged in with a client certificate"
+        );
+        Span::current().record("user", user.username.as_str());
+        let token = AuthService::new(self.pool.clone()).generate_token();
+        self.complete_login(client_id, user.id, token, workspace_id)
+            .await
+    }
+
+    /// Logs in a connection as the account it just registered.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client that registered
+    /// * `user` - The new user
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the login was answered, Err otherwise
+    pub(super) async fn handle_registered(&self, client_id: usize, user: &User) -> Result<()> {
+        let Some(workspace_id) = self.resolve_workspace(user.id).await? else {
+            return self
+                .handle_auth_failure(client_id, &user.username, AuthFailure::NoWorkspace)
+                .await;
+        };
+
+        info!(
+            target: "audit",
+            event = "registered",
+            client_id,
+            user_id = user.id,
+            username = user.username.as_str(),
+            "Registered an account"
+        );
+        Span::current().record("user", user.username.as_str());
+        let token = AuthService::new(self.pool.clone()).generate_token();
+        self.complete_login(client_id, user.id, token, workspace_id)
+            .await
+    }
+
+    /// Marks a connection as logged in and sends it the session token, the
+    /// room key and any read-only notice.
+    ///
+    /// Under the `replace` duplicate login policy, the user's other
+    /// connections are signed out.
+    async fn complete_login(
+        &self,
+        client_id: usize,
+        user_id: i32,
+        token: String,
+        workspace_id: i32,
+    ) -> Result<()> {
+        let locale = self.user_locale(user_id).await?;
+        let do_not_disturb = self.user_do_not_disturb(user_id).await?;
+        let (room_key, read_only) = {
+            let conn = &mut *self.pool.get().await?;
+            let workspace = WorkspaceRepository::find_by_id(conn, workspace_id).await?;
+            let room_key =
+                room_keys::for_session(conn, &self.encryption, &workspace, &token).await?;
+            let read_only = !WorkspaceRepository::can_post(conn, &workspace, user_id).await?;
+            (room_key, read_only.then_some(workspace.name))
+        };
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get_mut(&client_id) {
+            client.user_id = Some(user_id);
+            client.workspace_id = Some(workspace_id);
+            client.auth_state = AuthState::Authenticated {
+                user_id,
+                token: token.clone(),
+            };
+            client.failed_logins = 0;
+            client.do_not_disturb = do_not_disturb;
+
+            let response = Message::AuthResponse {
+                success: true,
+                token: Some(token),
+                message: i18n::text(&locale, "auth-success", &[]),
+            };
+            client.locale = locale;
+
+            info!("Client {} authenticated successfully", client_id);
+
+            client.send(&response).await?;
+            client.send(&room_key).await?;
+            if let Some(workspace) = read_only {
+                let notice = i18n::text(
+                    &client.locale,
+                    "workspace-read-only",
+                    &[("workspace", workspace.into())],
+                );
+                client.send(&Message::System(notice)).await?;
+            }
+        }
+
+        if self.duplicate_login_policy == DuplicateLoginPolicy::Replace {
+            // Sign out the user's older sessions, telling each why
+            for (other_id, other) in clients.iter_mut() {
+                if *other_id == client_id || other.user_id != Some(user_id) {
+                    continue;
+                }
+                other.user_id = None;
+                other.workspace_id = None;
+                other.auth_state = AuthState::NotAuthenticated;
+
+                let response = Message::AuthResponse {
+                    success: false,
+                    token: None,
+                    message: i18n::text(&other.locale, "auth-session-replaced", &[]),
+                };
+                if let Err(e) = other.send(&response).await {
+                    warn!("Failed to notify replaced client {}: {}", other_id, e);
                 }
+                info!(
+                    "Client {} signed out by a newer login on client {}",
+                    other_id, client_id
+                );
             }
-            None => {
-                let mut clients = self.clients.lock().await;
-                if let Some(client) = clients.get_mut(&client_id) {
-                    let response = Message::AuthResponse {
-                        success: false,
-                        token: None,
-                        message: "Invalid credentials".to_string(),
-                    };
+        }
+        drop(clients);
+
+        self.replay_history(client_id, workspace_id).await;
+        self.onboard(client_id, user_id).await;
+        Ok(())
+    }
+
+    /// Sends the latest messages of the workspace to a client that just logged
+    /// in, if it advertised support for `History`. Failures are logged, the
+    /// login stands regardless.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client the user logged in on
+    /// * `workspace_id` - The ID of the workspace the client chats in
+    async fn replay_history(&self, client_id: usize, workspace_id: i32) {
+        if self.history_limit == 0 {
+            return;
+        }
+        let supported = self
+            .clients
+            .lock()
+            .await
+            .get(&client_id)
+            .is_some_and(|client| client.supports(HISTORY_CAPABILITY));
+        if !supported {
+            return;
+        }
+
+        let history = match self.load_history(workspace_id).await {
+            Ok(history) if history.is_empty() => return,
+            Ok(history) => history,
+            Err(e) => {
+                error!(
+                    "Failed to load the history of workspace {}: {}",
+                    workspace_id, e
+                );
+                return;
+            }
+        };
+        let clients = self.clients.lock().await;
+        if let Some(client) = clients.get(&client_id) {
+            if let Err(e) = client.send(&Message::History(history)).await {
+                warn!("Failed to send the history to client {}: {}", client_id, e);
+            }
+        }
+    }
+
+    /// Loads the latest messages of a workspace, oldest first, with their text
+    /// encrypted with the workspace's key.
+    ///
+    /// # Arguments
+    /// * `workspace_id` - The ID of the workspace
+    async fn load_history(&self, workspace_id: i32) -> Result<Vec<HistoricalMessage>> {
+        let conn = &mut *self.pool.get().await?;
+        let latest = MessageRepository::find_latest_with_senders(
+            conn,
+            workspace_id,
+            self.history_limit.into(),
+        )
+        .await?;
+        if latest.is_empty() {
+            return Ok(Vec::new());
+        }
+        let key_id = room_keys::load(conn, &self.encryption, workspace_id).await?;
+        let encryption = self.encryption.message_for(Some(&key_id))?;
+
+        let mut history = Vec::with_capacity(latest.len());
+        for (message, sender) in latest {
+            let content = match &message.content {
+                Some(text) => Some(serde_json::to_string(&encryption.encrypt(text)?)?),
+                None => None,
+            };
+            history.push(HistoricalMessage {
+                id: message.id,
+                seq: message.seq as u64,
+                sent_at: message.created_at.and_utc(),
+                sender,
+                kind: match message.message_type {
+                    MessageType::Text => HistoricalKind::Text,
+                    MessageType::Action => HistoricalKind::Action,
+                    MessageType::File => HistoricalKind::File,
+                    MessageType::Image => HistoricalKind::Image,
+                },
+                content,
+                file_name: message.file_name,
+            });
+        }
+        Ok(history)
+    }
+
+    /// Runs the on-join hooks for a user who just logged in and reminds them
+    /// of terms they have not accepted yet. Failures are logged, the login
+    /// stands regardless.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client the user logged in on
+    /// * `user_id` - The ID of the user
+    async fn onboard(&self, client_id: usize, user_id: i32) {
+        let onboarding = OnboardingService::new(
+            self.pool.clone(),
+            self.clients.clone(),
+            self.onboarding.clone(),
+        );
+        if let Err(e) = onboarding.welcome(user_id).await {
+            error!("Failed to welcome user {}: {}", user_id, e);
+        }
+
+        let terms = match onboarding.pending_terms(user_id).await {
+            Ok(Some(terms)) => terms,
+            Ok(None) => return,
+            Err(e) => {
+                error!(
+                    "Failed to look up the terms user {} accepted: {}",
+                    user_id, e
+                );
+                return;
+            }
+        };
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get_mut(&client_id) {
+            let notice = i18n::text(&client.locale, "terms-required", &[("terms", terms.into())]);
+            if let Err(e) = client.send(&Message::System(notice)).await {
+                warn!("Failed to send the terms to client {}: {}", client_id, e);
+            }
+        }
+    }
+
+    /// Audits and answers a failed login, and closes connections that keep failing.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client that failed to log in
+    /// * `username` - The username provided for authentication
+    /// * `failure` - Why the login failed
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the failure was answered, Err if the connection
+    ///   has to be closed or the reply could not be sent
+    async fn handle_auth_failure(
+        &self,
+        client_id: usize,
+        username: &str,
+        failure: AuthFailure,
+    ) -> Result<()> {
+        let (failures, peer_addr) = {
+            let mut clients = self.clients.lock().await;
+            let Some(client) = clients.get_mut(&client_id) else {
+                return Ok(());
+            };
+            client.failed_logins += 1;
+            (client.failed_logins, client.peer_addr)
+        };
+
+        if failure == AuthFailure::InvalidCredentials {
+            self.auth_throttle.record_failure(username, Instant::now());
+        }
+
+        warn!(
+            target: "audit",
+            event = "login_failed",
+            client_id,
+            %peer_addr,
+            username,
+            reason = failure.as_str(),
+            failures,
+            "Failed login"
+        );
+        {
+            let metrics = self.metrics.lock().await;
+            metrics
+                .auth_failures
+                .with_label_values(&[failure.as_str()])
+                .inc();
+            metrics.count_error(ErrorClass::Auth);
+        }
+
+        tokio::time::sleep(self.auth_throttle.delay(failures)).await;
+
+        let message = match failure {
+            AuthFailure::InvalidCredentials => self.text("auth-invalid-credentials", &[]),
+            AuthFailure::NoWorkspace => self.text("auth-no-workspace", &[]),
+            AuthFailure::Locked { retry_after } => self.text(
+                "auth-locked",
+                &[("seconds", retry_after.as_secs().max(1).into())],
+            ),
+        };
+        let response = Message::AuthResponse {
+            success: false,
+            token: None,
+            message,
+        };
+        self.reply(client_id, &response).await?;
+
+        if self.auth_throttle.exceeds_connection_limit(failures) {
+            warn!(
+                target: "audit",
+                event = "login_disconnect",
+                client_id,
+                %peer_addr,
+                failures,
+                "Disconnecting client after repeated failed logins"
+            );
+            self.metrics.lock().await.auth_disconnects.inc();
+            bail!(ChatError::PermissionDenied(format!(
+                "Client {} exceeded the failed login limit",
+                client_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the locale a user has chosen, or the default locale.
+    async fn user_locale(&self, user_id: i32) -> Result<String> {
+        let conn = &mut *self.pool.get().await?;
+        let preferences = PreferenceRepository::find(conn, user_id).await?;
+        Ok(preferences
+            .map(|preferences| preferences.locale)
+            .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_string()))
+    }
+
+    /// Returns a user's do-not-disturb settings, off if never set.
+    async fn user_do_not_disturb(&self, user_id: i32) -> Result<DndSettings> {
+        let conn = &mut *self.pool.get().await?;
+        let settings = DoNotDisturbRepository::find(conn, user_id).await?;
+        Ok(settings.as_ref().map(DndSettings::from).unwrap_or_default())
+    }
+
+    /// Picks the workspace a freshly authenticated user starts in.
+    ///
+    /// On the first login after redeeming a workspace invite, users land in the
+    /// invited workspace. Otherwise they land in the default workspace if they
+    /// belong to it, or else in the first workspace they joined.
+    ///
+    /// # Arguments
+    /// * `user_id` - The ID of the authenticated user
+    ///
+    /// # Returns
+    /// * `Result<Option<i32>>` - The workspace ID, or None if the user has no workspace
+    async fn resolve_workspace(&self, user_id: i32) -> Result<Option<i32>> {
+        let conn = &mut *self.pool.get().await?;
+
+        if let Some(workspace_id) =
+            InvitationRepository::take_first_login_workspace(conn, user_id).await?
+        {
+            if WorkspaceRepository::is_member(conn, workspace_id, user_id).await? {
+                return Ok(Some(workspace_id));
+            }
+        }
+
+        let workspaces = WorkspaceRepository::find_for_user(conn, user_id).await?;
+
+        let index = workspaces
+            .iter()
+            .position(|workspace| workspace.slug == DEFAULT_WORKSPACE_SLUG)
+            .unwrap_or(0);
+
+        Ok(workspaces.get(index).map(|workspace| workspace.id))
+    }
+
+    /// Wraps the key of a workspace for the session of a client.
+    ///
+    /// # Returns
+    /// * `Result<Option<Message>>` - The `RoomKey` message, or None if the
+    ///   client is gone or not authenticated
+    async fn room_key(&self, client_id: usize, workspace: &Workspace) -> Result<Option<Message>> {
+        let token = {
+            let clients = self.clients.lock().await;
+            clients
+                .get(&client_id)
+                .and_then(|client| client.token().map(str::to_string))
+        };
+        let Some(token) = token else {
+            return Ok(None);
+        };
 
-                    info!("Client {} authentication failed", client_id);
+        let conn = &mut *self.pool.get().await?;
+        let room_key = room_keys::for_session(conn, &self.encryption, workspace, &token).await?;
+        Ok(Some(room_key))
+    }
 
-                    client.writer.write_message(&response).await?;
+    /// Moves an authenticated client into another workspace.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client switching workspaces
+    /// * `user_id` - The ID of the user behind the connection
+    /// * `slug` - The slug of the target workspace
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the switch was processed, Err otherwise
+    pub(super) async fn handle_switch_workspace(
+        &self,
+        client_id: usize,
+        user_id: i32,
+        slug: &str,
+    ) -> Result<()> {
+        let (workspace, read_only) = {
+            let conn = &mut *self.pool.get().await?;
+            match WorkspaceRepository::find_by_slug(conn, slug).await {
+                Ok(workspace)
+                    if WorkspaceRepository::is_member(conn, workspace.id, user_id).await? =>
+                {
+                    let read_only =
+                        !WorkspaceRepository::can_post(conn, &workspace, user_id).await?;
+                    (Some(workspace), read_only)
                 }
+                Ok(_) | Err(diesel::result::Error::NotFound) => (None, false),
+                Err(e) => return Err(e.into()),
             }
+        };
+        let room_key = match &workspace {
+            Some(workspace) => self.room_key(client_id, workspace).await?,
+            None => None,
+        };
+
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get_mut(&client_id) {
+            let response = match workspace {
+                Some(workspace) => {
+                    client.workspace_id = Some(workspace.id);
+                    if let Some(room_key) = &room_key {
+                        client.send(room_key).await?;
+                    }
+                    info!(
+                        "Client {} switched to workspace {}",
+                        client_id, workspace.slug
+                    );
+                    let key = if read_only {
+                        "workspace-switched-read-only"
+                    } else {
+                        "workspace-switched"
+                    };
+                    Message::System(self.text(key, &[("workspace", workspace.name.into())]))
+                }
+                None => self.error_reply(
+                    ErrorCode::PermissionDenied,
+                    self.text("workspace-not-member", &[("slug", slug.into())]),
+                    &[("reason", "not_a_member"), ("slug", slug)],
+                ),
+            };
+            client.send(&response).await?;
         }
+
         Ok(())
     }
 }
+
+/// Sets the expiry of an ephemeral message from its TTL, capped at
+/// `MAX_EPHEMERAL_TTL_SECS`. Any expiry chosen by the sender is ignored.
+pub(super) fn stamp_expiry(message: &Message) -> Message {
+    match message {
+        Message::Ephemeral {
+            content, ttl_secs, ..
+        } => {
+            let ttl_secs = (*ttl_secs).clamp(1, MAX_EPHEMERAL_TTL_SECS);
+            Message::Ephemeral {
+                content: content.clone(),
+                ttl_secs,
+                expires_at: Some(Utc::now() + chrono::Duration::seconds(ttl_secs as i64)),
+            }
+        }
+        message => message.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp_expiry_caps_ttl() {
+        let before = Utc::now();
+        let stamped = stamp_expiry(&Message::Ephemeral {
+            content: "secret".to_string(),
+            ttl_secs: u64::MAX,
+            expires_at: Some(before - chrono::Duration::days(1)),
+        });
+
+        let Message::Ephemeral {
+            ttl_secs,
+            expires_at: Some(expires_at),
+            ..
+        } = stamped
+        else {
+            panic!("Expected Ephemeral message");
+        };
+        assert_eq!(ttl_secs, MAX_EPHEMERAL_TTL_SECS);
+        assert!(expires_at >= before + chrono::Duration::seconds(MAX_EPHEMERAL_TTL_SECS as i64));
+    }
+}
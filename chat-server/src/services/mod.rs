@@ -1,4 +1,30 @@
+pub mod account;
+pub mod attachment;
 pub mod auth;
+pub mod auth_throttle;
+pub mod backup;
+pub mod bot;
+pub mod chat_handle;
 pub mod client_service;
+pub mod config_reload;
 pub mod connection_service;
+pub mod email;
+pub mod email_links;
+pub mod export;
+pub mod feature_flags;
+pub mod health;
 pub mod message;
+pub mod notification;
+pub mod onboarding;
+pub mod outbound_queue;
+pub mod password;
+pub mod password_reset_throttle;
+pub mod presence;
+pub mod registration;
+pub mod report;
+pub mod retry_queue;
+pub mod room_keys;
+pub mod spam;
+pub mod storage;
+pub mod tls;
+pub mod user_import;
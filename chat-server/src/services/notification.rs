@@ -0,0 +1,239 @@
+//! Notification inbox service for the chat server.
+//!
+//! Notifications are persisted so users find them after reconnecting, and are
+//! pushed immediately to every connected session of the recipient, unless the
+//! recipient is in do-not-disturb mode.
+
+use std::sync::Arc;
+
+use crate::i18n;
+use crate::models::notification::{NewNotification, Notification, NotificationKind};
+use crate::repositories::notification::NotificationRepository;
+use crate::repositories::preference::PreferenceRepository;
+use crate::repositories::user::UserRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::message::broadcast::MessageBroadcaster;
+use crate::types::Clients;
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chat_common::Message;
+use fluent_bundle::FluentValue;
+use tracing::debug;
+
+/// Maximum number of characters of a message quoted in a mention notification
+const MENTION_PREVIEW_LENGTH: usize = 80;
+
+/// Service responsible for storing notifications and pushing them to clients.
+pub struct NotificationService {
+    pool: Arc<DbPool>,
+    clients: Clients,
+}
+
+impl NotificationService {
+    /// Creates a new `NotificationService` instance.
+    ///
+    /// # Arguments
+    /// * `pool` - A shared database connection pool
+    /// * `clients` - Connected chat clients that receive live notifications
+    pub fn new(pool: Arc<DbPool>, clients: Clients) -> Self {
+        Self { pool, clients }
+    }
+
+    /// Stores a notification and pushes it to the recipient's connected sessions.
+    ///
+    /// Recipients in do-not-disturb mode are not pushed to; the notification
+    /// waits for them unread in the inbox.
+    ///
+    /// # Arguments
+    /// * `new_notification` - The notification to deliver
+    ///
+    /// # Returns
+    /// * `Result<Notification>` - The stored notification
+    pub async fn notify(&self, new_notification: NewNotification) -> Result<Notification> {
+        let notification = {
+            let conn = &mut *self.pool.get().await?;
+            NotificationRepository::create(conn, new_notification).await?
+        };
+
+        let push = Message::Notification {
+            id: notification.id,
+            kind: notification.kind.to_string(),
+            content: notification.content.clone(),
+        };
+
+        // In do-not-disturb mode the notification waits in the inbox
+        let pushed = MessageBroadcaster::new(self.clients.clone())
+            .push_to_user(notification.user_id, &push)
+            .await?;
+        if !pushed {
+            debug!(
+                "Held back notification {} of user {} in do-not-disturb mode",
+                notification.id, notification.user_id
+            );
+        }
+
+        Ok(notification)
+    }
+
+    /// Notifies workspace members mentioned with `@username` in a text message.
+    ///
+    /// The notification text is written in each recipient's locale.
+    ///
+    /// # Arguments
+    /// * `sender_id` - The ID of the user who wrote the message
+    /// * `message_id` - The ID of the stored message
+    /// * `workspace_id` - The workspace the message was sent in
+    /// * `text` - The plain text of the message
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of notified users
+    pub async fn notify_mentions(
+        &self,
+        sender_id: i32,
+        message_id: i32,
+        workspace_id: i32,
+        text: &str,
+    ) -> Result<usize> {
+        let mentions = extract_mentions(text);
+        if mentions.is_empty() {
+            return Ok(0);
+        }
+
+        let (sender, recipients, locales) = {
+            let conn = &mut *self.pool.get().await?;
+            let sender = UserRepository::find_by_id(conn, sender_id).await?;
+            let members = WorkspaceRepository::find_members(conn, workspace_id).await?;
+            let recipients: Vec<i32> = members
+                .into_iter()
+                .filter(|member| member.id != sender_id && mentions.contains(&member.username))
+                .map(|member| member.id)
+                .collect();
+            let locales = PreferenceRepository::find_locales(conn, &recipients).await?;
+            (sender, recipients, locales)
+        };
+
+        let preview: String = text.chars().take(MENTION_PREVIEW_LENGTH).collect();
+        for user_id in &recipients {
+            let locale = locales
+                .get(user_id)
+                .map(String::as_str)
+                .unwrap_or(i18n::DEFAULT_LOCALE);
+            self.notify(NewNotification {
+                user_id: *user_id,
+                kind: NotificationKind::Mention,
+                actor_id: Some(sender_id),
+                message_id: Some(message_id),
+                content: i18n::text(
+                    locale,
+                    "mention",
+                    &[
+                        ("sender", sender.username.as_str().into()),
+                        ("preview", preview.as_str().into()),
+                    ],
+                ),
+            })
+            .await?;
+        }
+
+        Ok(recipients.len())
+    }
+
+    /// Notifies the admins and moderators of a workspace about a user.
+    ///
+    /// The notification text is written in each recipient's locale.
+    ///
+    /// # Arguments
+    /// * `workspace_id` - The workspace whose moderators are notified
+    /// * `actor_id` - The user the notification is about, who is not notified
+    /// * `message_id` - The message the notification refers to, if any
+    /// * `key` - The message key of the notification text
+    /// * `args` - The values of the text's `$variables`
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of notified users
+    pub async fn notify_moderators(
+        &self,
+        workspace_id: i32,
+        actor_id: i32,
+        message_id: Option<i32>,
+        key: &str,
+        args: &[(&str, String)],
+    ) -> Result<usize> {
+        let (recipients, locales) = {
+            let conn = &mut *self.pool.get().await?;
+            let mut recipients = WorkspaceRepository::find_moderators(conn, workspace_id).await?;
+            recipients.retain(|user_id| *user_id != actor_id);
+            let locales = PreferenceRepository::find_locales(conn, &recipients).await?;
+            (recipients, locales)
+        };
+
+        for user_id in &recipients {
+            let locale = locales
+                .get(user_id)
+                .map(String::as_str)
+                .unwrap_or(i18n::DEFAULT_LOCALE);
+            // Fluent values are not Sync, so they must not live across an await
+            let content = {
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|(name, value)| (*name, FluentValue::from(value.as_str())))
+                    .collect();
+                i18n::text(locale, key, &args)
+            };
+            self.notify(NewNotification {
+                user_id: *user_id,
+                kind: NotificationKind::System,
+                actor_id: Some(actor_id),
+                message_id,
+                content,
+            })
+            .await?;
+        }
+
+        Ok(recipients.len())
+    }
+}
+
+/// Extracts the distinct usernames mentioned as `@username` in a text.
+///
+/// A username consists of letters, digits, `_`, `-` and `.`, so punctuation
+/// following it (`@bob,`, `@bob's`, `@bob.`) is not part of the mention.
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+
+    for word in text.split_whitespace() {
+        let Some(name) = word.strip_prefix('@') else {
+            continue;
+        };
+        let end = name
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+            .unwrap_or(name.len());
+        let name = name[..end].trim_end_matches(['.', '-']);
+        if !name.is_empty() && !mentions.iter().any(|mention| mention == name) {
+            mentions.push(name.to_string());
+        }
+    }
+
+    mentions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_mentions() {
+        assert_eq!(
+            extract_mentions("hey @alice, have you seen @bob_2's note? @alice"),
+            vec!["alice".to_string(), "bob_2".to_string()]
+        );
+        assert_eq!(
+            extract_mentions("mail me at bob@example.com @ noon"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            extract_mentions("thanks @carol."),
+            vec!["carol".to_string()]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! What happens when users join the server.
+//!
+//! A user joins the server by logging in for the first time. They are added
+//! to the `DEFAULT_WORKSPACES` and get the welcome message as a notification.
+//! If the server has terms, users have to accept them with `/accept` before
+//! they may send chat messages, which the `terms` middleware enforces. Users
+//! accept the terms again whenever they change.
+
+use std::sync::Arc;
+
+use crate::config::OnboardingConfig;
+use crate::models::notification::{NewNotification, NotificationKind};
+use crate::models::workspace::NewWorkspaceMember;
+use crate::repositories::onboarding::OnboardingRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::notification::NotificationService;
+use crate::types::Clients;
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chat_common::encryption::file::sha256_hex;
+use tracing::{info, warn};
+
+/// The command users accept the terms with
+pub const ACCEPT_COMMAND: &str = "/accept";
+
+/// Returns the SHA-256 acceptances of `terms` are recorded with.
+pub fn terms_sha256(terms: &str) -> String {
+    sha256_hex(terms.as_bytes())
+}
+
+/// Service running the on-join hooks and tracking accepted terms.
+pub struct OnboardingService {
+    pool: Arc<DbPool>,
+    clients: Clients,
+    config: OnboardingConfig,
+}
+
+impl OnboardingService {
+    /// Creates a new `OnboardingService` instance.
+    ///
+    /// # Arguments
+    /// * `pool` - A shared database connection pool
+    /// * `clients` - Connected chat clients that receive the welcome message
+    /// * `config` - The welcome message, default workspaces and terms
+    pub fn new(pool: Arc<DbPool>, clients: Clients, config: OnboardingConfig) -> Self {
+        Self {
+            pool,
+            clients,
+            config,
+        }
+    }
+
+    /// Welcomes a user logging in for the first time: adds them to the
+    /// default workspaces and sends them the welcome message. Users who were
+    /// welcomed before are left alone.
+    ///
+    /// # Returns
+    /// * `Result<bool>` - `true` if the user joined the server with this login
+    pub async fn welcome(&self, user_id: i32) -> Result<bool> {
+        let joined = {
+            let conn = &mut *self.pool.get().await?;
+            if !OnboardingRepository::mark_welcomed(conn, user_id).await? {
+                return Ok(false);
+            }
+
+            let mut joined = Vec::new();
+            for slug in &self.config.default_workspaces {
+                let workspace = match WorkspaceRepository::find_by_slug(conn, slug).await {
+                    Ok(workspace) => workspace,
+                    Err(diesel::result::Error::NotFound) => {
+                        warn!("Default workspace '{}' does not exist", slug);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                if !WorkspaceRepository::is_member(conn, workspace.id, user_id).await? {
+                    WorkspaceRepository::add_member(
+                        conn,
+                        NewWorkspaceMember {
+                            workspace_id: workspace.id,
+                            user_id,
+                        },
+                    )
+                    .await?;
+                    joined.push(workspace.slug);
+                }
+            }
+            joined
+        };
+
+        info!(
+            target: "audit",
+            event = "user_joined",
+            user_id,
+            workspaces = joined.join(","),
+            "User joined the server"
+        );
+        if let Some(welcome_message) = &self.config.welcome_message {
+            NotificationService::new(self.pool.clone(), self.clients.clone())
+                .notify(NewNotification {
+                    user_id,
+                    kind: NotificationKind::System,
+                    actor_id: None,
+                    message_id: None,
+                    content: welcome_message.clone(),
+                })
+                .await?;
+        }
+        Ok(true)
+    }
+
+    /// Returns the terms a user still has to accept, if any.
+    pub async fn pending_terms(&self, user_id: i32) -> Result<Option<&str>> {
+        let Some(terms) = &self.config.terms else {
+            return Ok(None);
+        };
+        let conn = &mut *self.pool.get().await?;
+        let onboarding = OnboardingRepository::find(conn, user_id).await?;
+        let accepted =
+            onboarding.is_some_and(|onboarding| onboarding.accepted(&terms_sha256(terms)));
+        Ok((!accepted).then_some(terms.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terms_sha256_changes_with_terms() {
+        let terms = "Be nice.";
+        assert_eq!(terms_sha256(terms), terms_sha256("Be nice."));
+        assert_ne!(terms_sha256(terms), terms_sha256("Be nice!"));
+        assert_eq!(terms_sha256(terms).len(), 64);
+    }
+}
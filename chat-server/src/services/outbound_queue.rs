@@ -0,0 +1,552 @@
+//! Per-client outbound message queues.
+//!
+//! Every connection owns a bounded queue that is drained by a dedicated writer
+//! task. Producers (acknowledgments, broadcasts) only enqueue, so a stalled TCP
+//! client can no longer hold up the rest of the server. When a queue is full the
+//! configured [`BackpressurePolicy`] decides whether to wait, drop the oldest
+//! message, or eventually disconnect the client. Broadcasts never wait: under
+//! `Block` they set up to `capacity` messages aside until the writer makes room
+//! for them, and a client that falls further behind is disconnected, so a
+//! queue never holds more than twice its capacity.
+//!
+//! Queues are ordered by message priority: urgent messages overtake queued
+//! normal and low priority ones, and messages of equal priority keep their order.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::BackpressurePolicy;
+use crate::services::connection_service::ConnectionHealth;
+use crate::utils::metrics::Metrics;
+use anyhow::{bail, Result};
+use chat_common::async_message_stream::AsyncMessageStream;
+use chat_common::{Message, Priority};
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Outcome of trying to enqueue a message without waiting.
+enum Enqueue {
+    Queued,
+    DroppedOldest,
+    DroppedNew {
+        total_dropped: usize,
+    },
+    Full,
+    /// The messages set aside reached the capacity
+    Overflowed,
+}
+
+struct Shared {
+    messages: std::sync::Mutex<VecDeque<Message>>,
+    /// Messages waiting for room in a full `Block` queue, in arrival order, at
+    /// most `capacity` of them. Always locked after `messages`.
+    deferred: std::sync::Mutex<VecDeque<Message>>,
+    capacity: usize,
+    policy: BackpressurePolicy,
+    dropped: AtomicUsize,
+    closed: AtomicBool,
+    message_available: Notify,
+    space_available: Notify,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+/// A bounded, policy-driven queue of messages waiting to be written to one client.
+///
+/// Cloning the queue yields another handle to the same underlying buffer.
+#[derive(Clone)]
+pub struct OutboundQueue {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for OutboundQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutboundQueue")
+            .field("capacity", &self.shared.capacity)
+            .field("policy", &self.shared.policy)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl OutboundQueue {
+    /// Creates a new empty queue.
+    ///
+    /// # Arguments
+    /// * `capacity` - Maximum number of buffered messages
+    /// * `policy` - Behavior when the queue is full
+    /// * `metrics` - Shared metrics for queue depth and drop counters
+    pub fn new(capacity: usize, policy: BackpressurePolicy, metrics: Arc<Mutex<Metrics>>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                messages: std::sync::Mutex::new(VecDeque::with_capacity(capacity.min(64))),
+                deferred: std::sync::Mutex::new(VecDeque::new()),
+                capacity: capacity.max(1),
+                policy,
+                dropped: AtomicUsize::new(0),
+                closed: AtomicBool::new(false),
+                message_available: Notify::new(),
+                space_available: Notify::new(),
+                metrics,
+            }),
+        }
+    }
+
+    /// Returns the number of messages currently waiting to be written.
+    pub fn len(&self) -> usize {
+        let messages = self.shared.messages.lock().unwrap();
+        messages.len() + self.shared.deferred.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no messages are waiting to be written.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of messages dropped because of backpressure.
+    pub fn dropped(&self) -> usize {
+        self.shared.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` once the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::SeqCst)
+    }
+
+    /// Enqueues a message for delivery, applying the backpressure policy.
+    ///
+    /// # Arguments
+    /// * `message` - The message to deliver
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the message was queued or dropped according to the policy,
+    ///   Err if the queue is closed or the client exceeded its drop allowance
+    pub async fn push(&self, message: Message) -> Result<()> {
+        let mut message = Some(message);
+
+        loop {
+            let space_available = self.shared.space_available.notified();
+
+            if self.is_closed() {
+                bail!("Outbound queue is closed");
+            }
+
+            if self.enqueue(&mut message, false).await? {
+                return Ok(());
+            }
+            space_available.await;
+        }
+    }
+
+    /// Enqueues a message for delivery without waiting for space.
+    ///
+    /// Broadcasts hand a message to every recipient this way, so a slow client
+    /// never holds up the sender. Where `push` would wait under the `Block`
+    /// policy, the message is set aside and moves into the queue, in order, as
+    /// the writer makes room. A client with `capacity` messages already set
+    /// aside is not keeping up at all; rather than buffering without limit or
+    /// silently losing messages the `Block` policy promises to deliver, the
+    /// queue is closed and the client disconnected.
+    ///
+    /// # Arguments
+    /// * `message` - The message to deliver
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the message was queued, set aside or dropped
+    ///   according to the policy; Err like `push`, or if too many messages were
+    ///   set aside
+    pub async fn push_or_defer(&self, message: Message) -> Result<()> {
+        if self.is_closed() {
+            bail!("Outbound queue is closed");
+        }
+
+        self.enqueue(&mut Some(message), true).await?;
+        Ok(())
+    }
+
+    /// Applies the backpressure policy to a message, taking it unless the
+    /// queue is full and the policy is to wait.
+    ///
+    /// # Arguments
+    /// * `message` - The message to enqueue
+    /// * `defer` - Whether to set the message aside rather than wait
+    ///
+    /// # Returns
+    /// * `Result<bool>` - Whether the message was taken
+    async fn enqueue(&self, message: &mut Option<Message>, defer: bool) -> Result<bool> {
+        match self.try_enqueue(message, defer) {
+            Enqueue::Queued => {
+                self.shared.metrics.lock().await.outbound_queue_depth.inc();
+            }
+            Enqueue::DroppedOldest => {
+                self.shared.metrics.lock().await.outbound_dropped.inc();
+            }
+            Enqueue::DroppedNew { total_dropped } => {
+                self.shared.metrics.lock().await.outbound_dropped.inc();
+                if let BackpressurePolicy::Disconnect { max_dropped } = self.shared.policy {
+                    if total_dropped >= max_dropped {
+                        self.close().await;
+                        bail!(
+                            "Client exceeded outbound drop limit ({} messages)",
+                            max_dropped
+                        );
+                    }
+                }
+            }
+            Enqueue::Full => return Ok(false),
+            Enqueue::Overflowed => {
+                self.shared.metrics.lock().await.outbound_dropped.inc();
+                self.close().await;
+                bail!(
+                    "Client fell more than {} messages behind",
+                    2 * self.shared.capacity
+                );
+            }
+        }
+        Ok(true)
+    }
+
+    fn try_enqueue(&self, message: &mut Option<Message>, defer: bool) -> Enqueue {
+        let mut messages = self.shared.messages.lock().unwrap();
+        let mut deferred = self.shared.deferred.lock().unwrap();
+
+        // Nothing overtakes the messages set aside
+        let outcome = if messages.len() < self.shared.capacity && deferred.is_empty() {
+            insert_by_priority(&mut messages, message.take());
+            Enqueue::Queued
+        } else {
+            match self.shared.policy {
+                BackpressurePolicy::Block if defer => {
+                    if deferred.len() >= self.shared.capacity {
+                        message.take();
+                        self.shared.dropped.fetch_add(1, Ordering::SeqCst);
+                        return Enqueue::Overflowed;
+                    }
+                    deferred.extend(message.take());
+                    Enqueue::Queued
+                }
+                BackpressurePolicy::Block => return Enqueue::Full,
+                BackpressurePolicy::DropOldest => {
+                    // Evict the oldest message of the lowest queued priority, or the
+                    // new message itself if it ranks below everything queued
+                    let priority = message.as_ref().map(Message::priority).unwrap_or_default();
+                    let lowest = messages
+                        .iter()
+                        .map(Message::priority)
+                        .min()
+                        .unwrap_or(Priority::Urgent);
+                    if priority < lowest {
+                        message.take();
+                    } else {
+                        if let Some(index) = messages.iter().position(|m| m.priority() == lowest) {
+                            messages.remove(index);
+                        }
+                        insert_by_priority(&mut messages, message.take());
+                    }
+                    self.shared.dropped.fetch_add(1, Ordering::SeqCst);
+                    Enqueue::DroppedOldest
+                }
+                BackpressurePolicy::Disconnect { .. } => {
+                    message.take();
+                    let total_dropped = self.shared.dropped.fetch_add(1, Ordering::SeqCst) + 1;
+                    return Enqueue::DroppedNew { total_dropped };
+                }
+            }
+        };
+
+        drop(deferred);
+        drop(messages);
+        self.shared.message_available.notify_one();
+        outcome
+    }
+
+    /// Waits for the next queued message.
+    ///
+    /// # Returns
+    /// * `Option<Message>` - The next message, or None once the queue is closed
+    pub async fn pop(&self) -> Option<Message> {
+        loop {
+            let message_available = self.shared.message_available.notified();
+
+            if self.is_closed() {
+                return None;
+            }
+
+            let next = {
+                let mut messages = self.shared.messages.lock().unwrap();
+                let next = messages.pop_front();
+                if next.is_some() {
+                    // Messages set aside take the room that was freed
+                    let mut deferred = self.shared.deferred.lock().unwrap();
+                    while messages.len() < self.shared.capacity {
+                        let Some(message) = deferred.pop_front() else {
+                            break;
+                        };
+                        insert_by_priority(&mut messages, Some(message));
+                    }
+                }
+                next
+            };
+            if let Some(message) = next {
+                self.shared.space_available.notify_one();
+                self.shared.metrics.lock().await.outbound_queue_depth.dec();
+                return Some(message);
+            }
+
+            message_available.await;
+        }
+    }
+
+    /// Closes the queue, discarding pending messages and waking all waiters.
+    pub async fn close(&self) {
+        if self.shared.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let discarded = {
+            let mut messages = self.shared.messages.lock().unwrap();
+            let mut deferred = self.shared.deferred.lock().unwrap();
+            let discarded = messages.len() + deferred.len();
+            messages.clear();
+            deferred.clear();
+            discarded
+        };
+
+        self.shared.message_available.notify_waiters();
+        self.shared.space_available.notify_waiters();
+
+        self.shared
+            .metrics
+            .lock()
+            .await
+            .outbound_queue_depth
+            .sub(discarded as f64);
+    }
+}
+
+/// Inserts a message behind all queued messages of the same or higher priority.
+fn insert_by_priority(messages: &mut VecDeque<Message>, message: Option<Message>) {
+    let Some(message) = message else { return };
+    let priority = message.priority();
+    let index = messages
+        .iter()
+        .position(|queued| queued.priority() < priority)
+        .unwrap_or(messages.len());
+    messages.insert(index, message);
+}
+
+/// Spawns the task that drains a client's queue into its stream, usually the
+/// write half of its TCP socket.
+///
+/// The task exits when the queue is closed or a write fails; a failed write
+/// closes the queue so producers notice the client is gone. The latency of
+/// every write is recorded in `health` for slow-consumer detection. The writer
+/// is dropped at the end, which shuts down a TCP write half.
+///
+/// # Arguments
+/// * `writer` - The stream the client's messages are written to
+/// * `queue` - The queue to drain
+/// * `health` - Write statistics of the connection
+pub fn spawn_writer<W>(
+    mut writer: W,
+    queue: OutboundQueue,
+    health: Arc<ConnectionHealth>,
+) -> JoinHandle<()>
+where
+    W: AsyncMessageStream + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Some(message) = queue.pop().await {
+            health.write_started();
+            let started = Instant::now();
+            let written = writer.write_message(&message).await;
+            health.write_finished(started.elapsed());
+
+            if let Err(e) = written {
+                error!("Failed to write to client: {}", e);
+                queue.close().await;
+                break;
+            }
+        }
+
+        if queue.dropped() > 0 {
+            warn!(
+                "Client queue closed after dropping {} messages",
+                queue.dropped()
+            );
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn text(content: &str) -> Message {
+        Message::System(content.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_push_and_pop_in_order() {
+        let queue = OutboundQueue::new(4, BackpressurePolicy::Block, Metrics::new());
+
+        queue.push(text("one")).await.unwrap();
+        queue.push(text("two")).await.unwrap();
+
+        assert_eq!(queue.pop().await, Some(text("one")));
+        assert_eq!(queue.pop().await, Some(text("two")));
+        assert!(queue.is_empty());
+    }
+
+    fn prioritized(priority: Priority, content: &str) -> Message {
+        Message::PriorityText {
+            priority,
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_urgent_messages_overtake_queued_messages() {
+        let queue = OutboundQueue::new(8, BackpressurePolicy::Block, Metrics::new());
+
+        queue.push(prioritized(Priority::Low, "low")).await.unwrap();
+        queue.push(text("one")).await.unwrap();
+        queue
+            .push(prioritized(Priority::Urgent, "urgent"))
+            .await
+            .unwrap();
+        queue.push(text("two")).await.unwrap();
+
+        assert_eq!(
+            queue.pop().await,
+            Some(prioritized(Priority::Urgent, "urgent"))
+        );
+        assert_eq!(queue.pop().await, Some(text("one")));
+        assert_eq!(queue.pop().await, Some(text("two")));
+        assert_eq!(queue.pop().await, Some(prioritized(Priority::Low, "low")));
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_evicts_lowest_priority_first() {
+        let queue = OutboundQueue::new(2, BackpressurePolicy::DropOldest, Metrics::new());
+
+        queue
+            .push(prioritized(Priority::Urgent, "urgent"))
+            .await
+            .unwrap();
+        queue.push(prioritized(Priority::Low, "low")).await.unwrap();
+        queue.push(text("normal")).await.unwrap();
+        queue
+            .push(prioritized(Priority::Low, "late"))
+            .await
+            .unwrap();
+
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(
+            queue.pop().await,
+            Some(prioritized(Priority::Urgent, "urgent"))
+        );
+        assert_eq!(queue.pop().await, Some(text("normal")));
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_keeps_latest_messages() {
+        let queue = OutboundQueue::new(2, BackpressurePolicy::DropOldest, Metrics::new());
+
+        for content in ["one", "two", "three"] {
+            queue.push(text(content)).await.unwrap();
+        }
+
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop().await, Some(text("two")));
+        assert_eq!(queue.pop().await, Some(text("three")));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_after_max_dropped() {
+        let policy = BackpressurePolicy::Disconnect { max_dropped: 2 };
+        let queue = OutboundQueue::new(1, policy, Metrics::new());
+
+        queue.push(text("one")).await.unwrap();
+        assert!(queue.push(text("two")).await.is_ok());
+        assert!(queue.push(text("three")).await.is_err());
+        assert!(queue.is_closed());
+        assert_eq!(queue.pop().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_space() {
+        let queue = OutboundQueue::new(1, BackpressurePolicy::Block, Metrics::new());
+        queue.push(text("one")).await.unwrap();
+
+        let producer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.push(text("two")).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(queue.pop().await, Some(text("one")));
+        producer.await.unwrap().unwrap();
+        assert_eq!(queue.pop().await, Some(text("two")));
+    }
+
+    #[tokio::test]
+    async fn test_push_or_defer_sets_messages_aside_when_full() {
+        let queue = OutboundQueue::new(1, BackpressurePolicy::Block, Metrics::new());
+
+        queue.push_or_defer(text("one")).await.unwrap();
+        queue.push_or_defer(text("two")).await.unwrap();
+        assert_eq!(queue.len(), 2);
+
+        // A blocking push waits behind the message set aside
+        let producer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.push(text("three")).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(queue.pop().await, Some(text("one")));
+        assert_eq!(queue.pop().await, Some(text("two")));
+        producer.await.unwrap().unwrap();
+        assert_eq!(queue.pop().await, Some(text("three")));
+
+        queue.close().await;
+        assert!(queue.push_or_defer(text("four")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_push_or_defer_disconnects_client_too_far_behind() {
+        let queue = OutboundQueue::new(2, BackpressurePolicy::Block, Metrics::new());
+
+        for content in ["one", "two", "three", "four"] {
+            queue.push_or_defer(text(content)).await.unwrap();
+        }
+        assert_eq!(queue.len(), 4);
+
+        assert!(queue.push_or_defer(text("five")).await.is_err());
+        assert!(queue.is_closed());
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_close_wakes_blocked_producer() {
+        let queue = OutboundQueue::new(1, BackpressurePolicy::Block, Metrics::new());
+        queue.push(text("one")).await.unwrap();
+
+        let producer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.push(text("two")).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        queue.close().await;
+
+        assert!(producer.await.unwrap().is_err());
+    }
+}
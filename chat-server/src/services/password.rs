@@ -0,0 +1,457 @@
+//! Password policy.
+//!
+//! New passwords are checked against the configured [`PasswordPolicy`] when an
+//! account is created over REST or gRPC and when users change their password
+//! over REST or the chat protocol. A password is rejected with every rule it
+//! breaks, so users can fix all of them at once.
+//!
+//! The strength score is a simplified estimate on zxcvbn's scale from 0 (too
+//! guessable) to 4 (very unguessable): common passwords and the user's own
+//! name count as a few guesses, repeated and sequential characters as two, and
+//! every other character as the size of the alphabet the password draws from.
+//!
+//! Passwords can also be checked against a list of breached passwords, either
+//! the file in `PASSWORD_DENYLIST_FILE` or any [`BreachedPasswords`]
+//! implementation passed to [`PasswordService::with_breach_check`].
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{CharacterClass, PasswordPolicy, SharedConfig, MAX_PASSWORD_SCORE};
+use crate::i18n;
+use crate::models::user::User;
+use crate::repositories::user::UserRepository;
+use anyhow::Result;
+use diesel_async::AsyncPgConnection;
+use rocket::async_trait;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::error;
+
+/// bcrypt ignores everything after the first 72 bytes of a password
+pub const MAX_PASSWORD_BYTES: usize = 72;
+/// Cost of the bcrypt hashes passwords are stored as
+pub const BCRYPT_COST: u32 = 10;
+
+/// Passwords and words so common that they only add a few guesses
+const COMMON_WORDS: &[&str] = &[
+    "password", "passw0rd", "p@ssw0rd", "qwerty", "qwertz", "asdf", "zxcv", "letmein", "welcome",
+    "admin", "login", "iloveyou", "monkey", "dragon", "master", "sunshine", "princess", "football",
+    "baseball", "shadow", "superman", "trustno1", "secret", "abc123", "123456", "654321", "111111",
+    "000000", "chat",
+];
+/// Guesses needed per common word or user input in a password, as a power of ten
+const COMMON_WORD_GUESSES_LOG10: f64 = 2.0;
+/// Upper bounds of the guesses needed for scores 0 to 3, as powers of ten,
+/// matching zxcvbn's thresholds
+const SCORE_THRESHOLDS_LOG10: [f64; 4] = [3.0, 6.0, 8.0, 10.0];
+
+/// A password policy rule a password breaks.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum PasswordViolation {
+    MinLength { min: usize },
+    MaxLength { max_bytes: usize },
+    CharacterClass { class: CharacterClass },
+    Strength { score: u8, min_score: u8 },
+    Breached,
+}
+
+impl PasswordViolation {
+    /// Machine-readable name of the rule.
+    pub fn rule(&self) -> &'static str {
+        match self {
+            PasswordViolation::MinLength { .. } => "min_length",
+            PasswordViolation::MaxLength { .. } => "max_length",
+            PasswordViolation::CharacterClass { .. } => "character_class",
+            PasswordViolation::Strength { .. } => "strength",
+            PasswordViolation::Breached => "breached",
+        }
+    }
+
+    /// Describes the broken rule in `locale`.
+    pub fn message(&self, locale: &str) -> String {
+        match self {
+            PasswordViolation::MinLength { min } => {
+                i18n::text(locale, "password-too-short", &[("min", (*min).into())])
+            }
+            PasswordViolation::MaxLength { max_bytes } => i18n::text(
+                locale,
+                "password-too-long",
+                &[("max_bytes", (*max_bytes).into())],
+            ),
+            PasswordViolation::CharacterClass { class } => {
+                let key = match class {
+                    CharacterClass::Lowercase => "password-missing-lowercase",
+                    CharacterClass::Uppercase => "password-missing-uppercase",
+                    CharacterClass::Digit => "password-missing-digit",
+                    CharacterClass::Symbol => "password-missing-symbol",
+                };
+                i18n::text(locale, key, &[])
+            }
+            PasswordViolation::Strength { score, min_score } => i18n::text(
+                locale,
+                "password-too-weak",
+                &[
+                    ("score", (*score).into()),
+                    ("max_score", MAX_PASSWORD_SCORE.into()),
+                    ("min_score", (*min_score).into()),
+                ],
+            ),
+            PasswordViolation::Breached => i18n::text(locale, "password-breached", &[]),
+        }
+    }
+}
+
+impl fmt::Display for PasswordViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message(i18n::DEFAULT_LOCALE))
+    }
+}
+
+/// Lists the rules of `policy` that `password` breaks, apart from the breach check.
+///
+/// # Arguments
+/// * `policy` - The rules to check
+/// * `password` - The new password
+/// * `user_inputs` - The user's name and email, which make a password weaker
+pub fn check(
+    policy: &PasswordPolicy,
+    password: &str,
+    user_inputs: &[&str],
+) -> Vec<PasswordViolation> {
+    let mut violations = Vec::new();
+
+    if password.chars().count() < policy.min_length {
+        violations.push(PasswordViolation::MinLength {
+            min: policy.min_length,
+        });
+    }
+    if password.len() > MAX_PASSWORD_BYTES {
+        violations.push(PasswordViolation::MaxLength {
+            max_bytes: MAX_PASSWORD_BYTES,
+        });
+    }
+    for class in &policy.required_classes {
+        if !password.chars().any(|c| class.matches(c)) {
+            violations.push(PasswordViolation::CharacterClass { class: *class });
+        }
+    }
+    if policy.min_score > 0 {
+        let score = strength(password, user_inputs);
+        if score < policy.min_score {
+            violations.push(PasswordViolation::Strength {
+                score,
+                min_score: policy.min_score,
+            });
+        }
+    }
+
+    violations
+}
+
+/// Estimates how hard a password is to guess, from 0 to 4.
+///
+/// # Arguments
+/// * `password` - The password
+/// * `user_inputs` - Words of the user, such as their name, that attackers try first
+pub fn strength(password: &str, user_inputs: &[&str]) -> u8 {
+    let guesses = guesses_log10(password, user_inputs);
+    SCORE_THRESHOLDS_LOG10
+        .iter()
+        .position(|threshold| guesses < *threshold)
+        .map(|score| score as u8)
+        .unwrap_or(MAX_PASSWORD_SCORE)
+}
+
+/// Estimates the number of guesses needed for a password, as a power of ten.
+fn guesses_log10(password: &str, user_inputs: &[&str]) -> f64 {
+    let mut words: Vec<String> = COMMON_WORDS
+        .iter()
+        .map(|word| word.to_string())
+        .chain(user_inputs.iter().map(|input| input.to_lowercase()))
+        .filter(|word| word.chars().count() >= 3)
+        .collect();
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+
+    // Known words are cut out and charged a few guesses each
+    let mut guesses = 0.0;
+    let mut rest = password.to_lowercase();
+    for word in &words {
+        while let Some(index) = rest.find(word.as_str()) {
+            rest.replace_range(index..index + word.len(), " ");
+            guesses += COMMON_WORD_GUESSES_LOG10;
+        }
+    }
+
+    let alphabet: usize = [
+        (CharacterClass::Lowercase, 26),
+        (CharacterClass::Uppercase, 26),
+        (CharacterClass::Digit, 10),
+        (CharacterClass::Symbol, 33),
+    ]
+    .iter()
+    .filter(|(class, _)| password.chars().any(|c| class.matches(c)))
+    .map(|(_, size)| size)
+    .sum();
+    let per_character = (alphabet.max(1) as f64).log10();
+
+    let mut previous: Option<char> = None;
+    for c in rest.chars() {
+        if c == ' ' && previous.is_none() {
+            continue;
+        }
+        let predictable = previous.is_some_and(|previous| {
+            let distance = (c as i64 - previous as i64).abs();
+            distance <= 1
+        });
+        guesses += if predictable {
+            2f64.log10()
+        } else {
+            per_character
+        };
+        previous = Some(c);
+    }
+    guesses
+}
+
+/// Tells whether a password is known from a data breach.
+#[async_trait]
+pub trait BreachedPasswords: Send + Sync {
+    async fn is_breached(&self, password: &str) -> Result<bool>;
+}
+
+/// A fixed list of forbidden passwords, compared case-insensitively.
+pub struct Denylist {
+    passwords: HashSet<String>,
+}
+
+impl Denylist {
+    pub fn new(passwords: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            passwords: passwords
+                .into_iter()
+                .map(|password| password.trim().to_lowercase())
+                .filter(|password| !password.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Reads a denylist with one password per line.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::new(content.lines().map(str::to_string)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.passwords.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passwords.is_empty()
+    }
+}
+
+#[async_trait]
+impl BreachedPasswords for Denylist {
+    async fn is_breached(&self, password: &str) -> Result<bool> {
+        Ok(self.passwords.contains(&password.to_lowercase()))
+    }
+}
+
+/// Why a password could not be changed.
+#[derive(Debug, Error)]
+pub enum PasswordChangeError {
+    #[error("The current password is wrong")]
+    WrongPassword,
+    #[error("The password does not meet the password policy")]
+    Policy(Vec<PasswordViolation>),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+    #[error(transparent)]
+    Hash(#[from] bcrypt::BcryptError),
+}
+
+/// Checks new passwords against the configured policy and the breach check.
+pub struct PasswordService {
+    config: SharedConfig,
+    breached: Option<Arc<dyn BreachedPasswords>>,
+}
+
+impl PasswordService {
+    /// Creates the service without a breach check.
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            breached: None,
+        }
+    }
+
+    /// Rejects passwords the given check reports as breached.
+    pub fn with_breach_check(mut self, breached: Arc<dyn BreachedPasswords>) -> Self {
+        self.breached = Some(breached);
+        self
+    }
+
+    /// Lists every rule a new password breaks.
+    ///
+    /// Passwords are accepted if the breach check fails, so an unavailable
+    /// breach database does not lock users out.
+    ///
+    /// # Arguments
+    /// * `password` - The new password
+    /// * `user_inputs` - The user's name and email
+    ///
+    /// # Returns
+    /// * `Vec<PasswordViolation>` - The broken rules, empty if the password is accepted
+    pub async fn validate(&self, password: &str, user_inputs: &[&str]) -> Vec<PasswordViolation> {
+        let policy = self.config.current().password_policy.clone();
+        let mut violations = check(&policy, password, user_inputs);
+
+        if let Some(breached) = &self.breached {
+            match breached.is_breached(password).await {
+                Ok(true) => violations.push(PasswordViolation::Breached),
+                Ok(false) => {}
+                Err(e) => error!("Failed to check the password against breaches: {}", e),
+            }
+        }
+        violations
+    }
+
+    /// Changes a user's password after verifying the current one.
+    ///
+    /// # Arguments
+    /// * `conn` - Connection the user is loaded and updated with
+    /// * `user_id` - The user changing their password
+    /// * `current_password` - The password the user has now
+    /// * `new_password` - The password to set
+    pub async fn change(
+        &self,
+        conn: &mut AsyncPgConnection,
+        user_id: i32,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), PasswordChangeError> {
+        let user = UserRepository::find_by_id(conn, user_id).await?;
+        if !bcrypt::verify(current_password, &user.password_hash).unwrap_or(false) {
+            return Err(PasswordChangeError::WrongPassword);
+        }
+        self.set(conn, &user, new_password).await
+    }
+
+    /// Sets a new password for a user who proved who they are another way,
+    /// such as with a password reset link.
+    ///
+    /// # Arguments
+    /// * `conn` - Connection the user is updated with
+    /// * `user` - The user whose password is reset
+    /// * `new_password` - The password to set
+    pub async fn reset(
+        &self,
+        conn: &mut AsyncPgConnection,
+        user: &User,
+        new_password: &str,
+    ) -> Result<(), PasswordChangeError> {
+        self.set(conn, user, new_password).await
+    }
+
+    /// Checks a new password against the policy and stores its hash.
+    async fn set(
+        &self,
+        conn: &mut AsyncPgConnection,
+        user: &User,
+        new_password: &str,
+    ) -> Result<(), PasswordChangeError> {
+        let violations = self
+            .validate(new_password, &[&user.username, &user.email])
+            .await;
+        if !violations.is_empty() {
+            return Err(PasswordChangeError::Policy(violations));
+        }
+
+        let password_hash = bcrypt::hash(new_password, BCRYPT_COST)?;
+        UserRepository::set_password_hash(conn, user.id, password_hash).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(violations: &[PasswordViolation]) -> Vec<&'static str> {
+        violations.iter().map(PasswordViolation::rule).collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            required_classes: vec![CharacterClass::Uppercase, CharacterClass::Digit],
+            min_score: 3,
+        };
+
+        assert_eq!(
+            rules(&check(&policy, "password", &[])),
+            vec![
+                "min_length",
+                "character_class",
+                "character_class",
+                "strength"
+            ]
+        );
+        assert!(check(&policy, "Plum-Orbit-Cactus-42", &[]).is_empty());
+        assert_eq!(
+            rules(&check(&policy, &"Aa1".repeat(30), &[])),
+            vec!["max_length"]
+        );
+        assert!(check(&PasswordPolicy::default(), "password123", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_strength() {
+        assert_eq!(strength("password", &[]), 0);
+        assert_eq!(strength("aaaaaaaaaaaa", &[]), 1);
+        assert!(strength("password123", &[]) <= 1);
+        assert!(strength("Plum-Orbit-Cactus-42", &[]) >= 3);
+        assert_eq!(strength("correct horse battery staple", &[]), 4);
+
+        // The user's own name is guessed early
+        let score = strength("carolinexyz", &[]);
+        assert!(strength("carolinexyz", &["caroline"]) < score);
+    }
+
+    #[tokio::test]
+    async fn test_breach_check() {
+        let denylist = Arc::new(Denylist::new(vec!["Hunter2".to_string(), "  ".to_string()]));
+        assert_eq!(denylist.len(), 1);
+
+        let service = PasswordService::new(SharedConfig::default()).with_breach_check(denylist);
+        assert_eq!(
+            service.validate("hunter2xyz", &[]).await,
+            Vec::<PasswordViolation>::new()
+        );
+        assert_eq!(
+            rules(&service.validate("HUNTER2", &[]).await),
+            vec!["min_length", "breached"]
+        );
+    }
+
+    #[test]
+    fn test_violation_json() {
+        let violation = PasswordViolation::CharacterClass {
+            class: CharacterClass::Symbol,
+        };
+        assert_eq!(
+            serde_json::to_value(&violation).unwrap(),
+            serde_json::json!({"rule": "character_class", "class": "symbol"})
+        );
+        assert_eq!(violation.to_string(), "The password must contain a symbol");
+        assert_eq!(
+            violation.message("cs"),
+            "Heslo musí obsahovat speciální znak"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Throttling of password reset requests.
+//!
+//! Every request for a reset link counts against the email address it names
+//! and the IP address it came from, whether or not the email address is
+//! registered. Once either made too many requests within the window, further
+//! requests are refused until the oldest ones age out of the window, so the
+//! endpoint can neither flood an inbox nor be used to probe many addresses.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::SharedConfig;
+
+/// What a password reset request is counted against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RequestKey {
+    /// The lowercased email address the link is requested for
+    Address(String),
+    /// The IP address the request came from
+    Ip(IpAddr),
+}
+
+/// Password reset request bookkeeping shared by all REST requests.
+///
+/// The limits are read from the configuration on every request, so they can
+/// be reloaded.
+pub struct PasswordResetThrottle {
+    config: SharedConfig,
+    /// Times of recent requests per email and IP address, oldest first
+    requests: Mutex<HashMap<RequestKey, VecDeque<Instant>>>,
+}
+
+impl PasswordResetThrottle {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request for a reset link unless the address or IP is over its limit.
+    ///
+    /// # Arguments
+    /// * `address` - The email address the link is requested for
+    /// * `ip` - The IP address of the client, if known
+    /// * `now` - The time of the request
+    ///
+    /// # Returns
+    /// * `Result<(), Duration>` - Ok if the request may go ahead, otherwise
+    ///   how long until it would be accepted
+    pub fn check(&self, address: &str, ip: Option<IpAddr>, now: Instant) -> Result<(), Duration> {
+        let throttle = self.config.current().password_reset_throttle.clone();
+        let mut limits = vec![(
+            RequestKey::Address(address.trim().to_lowercase()),
+            throttle.max_per_address,
+        )];
+        if let Some(ip) = ip {
+            limits.push((RequestKey::Ip(ip), throttle.max_per_ip));
+        }
+
+        let mut requests = self.requests.lock().unwrap();
+        requests.retain(|_, times| {
+            times.retain(|requested_at| now.duration_since(*requested_at) < throttle.window);
+            !times.is_empty()
+        });
+
+        // Refused until enough requests age out to drop below the limit
+        let retry_after = limits
+            .iter()
+            .filter(|(_, limit)| *limit > 0)
+            .filter_map(|(key, limit)| {
+                let times = requests.get(key)?;
+                let limit = *limit as usize;
+                (times.len() >= limit).then(|| {
+                    let unlocking = times[times.len() - limit];
+                    throttle.window - now.duration_since(unlocking)
+                })
+            })
+            .max();
+        if let Some(retry_after) = retry_after {
+            return Err(retry_after);
+        }
+
+        for (key, _) in limits {
+            requests.entry(key).or_default().push_back(now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PasswordResetThrottleConfig, ServerConfig};
+
+    fn throttle_with(
+        password_reset_throttle: PasswordResetThrottleConfig,
+    ) -> PasswordResetThrottle {
+        PasswordResetThrottle::new(SharedConfig::new(ServerConfig {
+            password_reset_throttle,
+            ..ServerConfig::default()
+        }))
+    }
+
+    #[test]
+    fn test_address_limited_until_requests_age_out() {
+        let throttle = throttle_with(PasswordResetThrottleConfig {
+            max_per_address: 2,
+            max_per_ip: 0,
+            window: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+
+        assert!(throttle.check("alice@example.com", None, start).is_ok());
+        assert!(throttle
+            .check(" Alice@Example.com", None, start + Duration::from_secs(10))
+            .is_ok());
+        assert_eq!(
+            throttle.check("ALICE@example.com", None, start + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        // Other addresses are not affected
+        assert!(throttle
+            .check("bob@example.com", None, start + Duration::from_secs(20))
+            .is_ok());
+        // Requests are accepted again once the first one leaves the window
+        assert!(throttle
+            .check("alice@example.com", None, start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_ip_limited_across_addresses() {
+        let throttle = throttle_with(PasswordResetThrottleConfig {
+            max_per_address: 0,
+            max_per_ip: 2,
+            window: Duration::from_secs(60),
+        });
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(throttle.check("a@example.com", Some(ip), now).is_ok());
+        assert!(throttle.check("b@example.com", Some(ip), now).is_ok());
+        assert!(throttle.check("c@example.com", Some(ip), now).is_err());
+        // Other clients are not affected
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        assert!(throttle.check("c@example.com", Some(other), now).is_ok());
+    }
+}
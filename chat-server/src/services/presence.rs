@@ -0,0 +1,108 @@
+//! User statuses.
+//!
+//! Users set their availability and optional custom text over a chat
+//! connection or the REST API. Statuses are persisted, so they survive
+//! reconnects, and every change is announced to the connected members of the
+//! user's workspaces.
+
+use std::sync::Arc;
+
+use crate::models::user_status::UserStatus;
+use crate::repositories::user::UserRepository;
+use crate::repositories::user_status::UserStatusRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::message::broadcast::MessageBroadcaster;
+use crate::types::Clients;
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chat_common::{Availability, Message, MAX_STATUS_TEXT_CHARS};
+use thiserror::Error;
+
+/// Custom status text longer than `MAX_STATUS_TEXT_CHARS`.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("Status text may be at most {MAX_STATUS_TEXT_CHARS} characters long")]
+pub struct StatusTextTooLong;
+
+/// Trims custom status text, treating blank text as no text.
+pub fn normalize_text(text: Option<&str>) -> Result<Option<String>, StatusTextTooLong> {
+    match text.map(str::trim).filter(|text| !text.is_empty()) {
+        Some(text) if text.chars().count() > MAX_STATUS_TEXT_CHARS => Err(StatusTextTooLong),
+        text => Ok(text.map(str::to_string)),
+    }
+}
+
+/// Service responsible for storing statuses and announcing changes.
+pub struct PresenceService {
+    pool: Arc<DbPool>,
+    clients: Clients,
+}
+
+impl PresenceService {
+    /// Creates a new `PresenceService` instance.
+    ///
+    /// # Arguments
+    /// * `pool` - A shared database connection pool
+    /// * `clients` - Connected chat clients that are told about status changes
+    pub fn new(pool: Arc<DbPool>, clients: Clients) -> Self {
+        Self { pool, clients }
+    }
+
+    /// Stores a user's status and announces it to the user's workspaces.
+    ///
+    /// # Arguments
+    /// * `user_id` - The user whose status changed
+    /// * `availability` - Whether the user is around
+    /// * `text` - Custom text, already checked with [`normalize_text`]
+    ///
+    /// # Returns
+    /// * `Result<UserStatus>` - The stored status
+    pub async fn set_status(
+        &self,
+        user_id: i32,
+        availability: Availability,
+        text: Option<&str>,
+    ) -> Result<UserStatus> {
+        let (status, username, workspaces) = {
+            let conn = &mut *self.pool.get().await?;
+            let status = UserStatusRepository::set(conn, user_id, availability, text).await?;
+            let username = UserRepository::find_by_id(conn, user_id).await?.username;
+            let workspaces = WorkspaceRepository::find_for_user(conn, user_id).await?;
+            (status, username, workspaces)
+        };
+
+        let changed = Message::StatusChanged {
+            username,
+            availability,
+            text: status.text.clone(),
+        };
+        let broadcaster = MessageBroadcaster::new(self.clients.clone());
+        for workspace in workspaces {
+            broadcaster
+                .broadcast_message(&changed, None, Some(workspace.id))
+                .await?;
+        }
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_text() {
+        assert_eq!(normalize_text(None), Ok(None));
+        assert_eq!(normalize_text(Some("   ")), Ok(None));
+        assert_eq!(
+            normalize_text(Some(" 🌴 on vacation ")),
+            Ok(Some("🌴 on vacation".to_string()))
+        );
+        // Emoji count as single characters
+        assert!(normalize_text(Some(&"🌴".repeat(MAX_STATUS_TEXT_CHARS))).is_ok());
+        assert_eq!(
+            normalize_text(Some(&"a".repeat(MAX_STATUS_TEXT_CHARS + 1))),
+            Err(StatusTextTooLong)
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Account registration over the chat protocol.
+//!
+//! Clients create accounts with a `Register` message instead of the REST API,
+//! so users of the command-line client do not need the web admin. The same
+//! rules apply as over REST: the password must meet the password policy, the
+//! username must be valid and free, and servers with `REQUIRE_INVITE` need an
+//! invite code. The password is stored as a bcrypt hash and the new user joins
+//! the default workspace, or the workspace of the invite. New users are
+//! emailed a link confirming their address.
+
+use std::sync::Arc;
+
+use crate::config::SharedConfig;
+use crate::models::user::{NewUserRequest, User};
+use crate::repositories::user::{UserRepository, UserWriteError};
+use crate::services::email::EmailService;
+use crate::services::email_links;
+use crate::services::password::{PasswordService, PasswordViolation};
+use diesel_async::AsyncPgConnection;
+use thiserror::Error;
+use tracing::error;
+
+/// Why an account could not be registered.
+#[derive(Debug, Error)]
+pub enum RegistrationError {
+    #[error("An invite code is required")]
+    InviteRequired,
+    #[error("The invite code is invalid or expired")]
+    InvalidInvite,
+    #[error("The password does not meet the password policy")]
+    Policy(Vec<PasswordViolation>),
+    #[error(transparent)]
+    Write(#[from] UserWriteError),
+}
+
+/// Creates accounts requested over a chat connection.
+pub struct RegistrationService {
+    config: SharedConfig,
+    passwords: Arc<PasswordService>,
+    email: Option<EmailService>,
+}
+
+impl RegistrationService {
+    /// Creates the service.
+    ///
+    /// # Arguments
+    /// * `config` - Configuration deciding whether invites are required
+    /// * `passwords` - Policy new passwords are checked against
+    pub fn new(config: SharedConfig, passwords: Arc<PasswordService>) -> Self {
+        Self {
+            config,
+            passwords,
+            email: None,
+        }
+    }
+
+    /// Emails new users a link confirming their address.
+    pub fn with_email(mut self, email: EmailService) -> Self {
+        self.email = Some(email);
+        self
+    }
+
+    /// Creates an account, redeeming the invite code if one is given.
+    ///
+    /// # Arguments
+    /// * `conn` - Connection the account is created with
+    /// * `request` - Username, email, password and optional invite code
+    ///
+    /// # Returns
+    /// * `Result<User, RegistrationError>` - The new user, or why it was rejected
+    pub async fn register(
+        &self,
+        conn: &mut AsyncPgConnection,
+        mut request: NewUserRequest,
+    ) -> Result<User, RegistrationError> {
+        let violations = self
+            .passwords
+            .validate(&request.password, &[&request.username, &request.email])
+            .await;
+        if !violations.is_empty() {
+            return Err(RegistrationError::Policy(violations));
+        }
+
+        let user = match request.invite_code.take() {
+            Some(code) => UserRepository::create_with_invite(conn, request, &code)
+                .await?
+                .ok_or(RegistrationError::InvalidInvite)?,
+            None if self.config.current().require_invite => {
+                return Err(RegistrationError::InviteRequired)
+            }
+            None => UserRepository::create(conn, request).await?,
+        };
+
+        // The account is usable even if the verification email cannot be queued
+        if let Some(email) = &self.email {
+            let public_url = self.config.current().public_url.clone();
+            if let Err(e) = email_links::send_verification(conn, email, &public_url, &user).await {
+                error!(
+                    "Failed to send a verification email to user {}: {}",
+                    user.id, e
+                );
+            }
+        }
+        Ok(user)
+    }
+}
@@ -0,0 +1,261 @@
+//! Reports of messages to the moderators of their workspace.
+//!
+//! Members report messages over a chat connection or the REST API. The admins
+//! and moderators of the message's workspace are notified and work through the
+//! open reports in the moderation queue. Resolving a report can delete the
+//! message, warn its sender or remove the sender from the workspace.
+
+use std::sync::Arc;
+
+use crate::i18n;
+use crate::models::notification::{NewNotification, NotificationKind};
+use crate::models::report::{NewReport, Report, ReportStatus};
+use crate::repositories::message::MessageRepository;
+use crate::repositories::preference::PreferenceRepository;
+use crate::repositories::report::ReportRepository;
+use crate::repositories::user::UserRepository;
+use crate::repositories::workspace::WorkspaceRepository;
+use crate::services::notification::NotificationService;
+use crate::types::Clients;
+use crate::utils::db_connection::DbPool;
+use anyhow::Result;
+use chat_common::MAX_REPORT_REASON_CHARS;
+use diesel::result::Error as DieselError;
+use thiserror::Error;
+use tracing::{error, info};
+
+/// Why a report cannot be filed or resolved.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum ReportError {
+    #[error("A report needs a reason")]
+    EmptyReason,
+    #[error("The reason may be at most {MAX_REPORT_REASON_CHARS} characters long")]
+    ReasonTooLong,
+    #[error("The message does not exist in any of your workspaces")]
+    MessageNotFound,
+    #[error("You have already reported this message")]
+    AlreadyReported,
+    #[error("The report has already been resolved")]
+    AlreadyResolved,
+    #[error("A report can only be resolved as dismissed, deleted, warned or banned")]
+    NotAResolution,
+}
+
+/// Trims the reason of a report, which must not be blank or too long.
+pub fn normalize_reason(reason: &str) -> Result<String, ReportError> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        Err(ReportError::EmptyReason)
+    } else if reason.chars().count() > MAX_REPORT_REASON_CHARS {
+        Err(ReportError::ReasonTooLong)
+    } else {
+        Ok(reason.to_string())
+    }
+}
+
+/// Service responsible for filing and resolving reports.
+pub struct ReportService {
+    pool: Arc<DbPool>,
+    clients: Clients,
+}
+
+impl ReportService {
+    /// Creates a new `ReportService` instance.
+    ///
+    /// # Arguments
+    /// * `pool` - A shared database connection pool
+    /// * `clients` - Connected chat clients that receive notifications
+    pub fn new(pool: Arc<DbPool>, clients: Clients) -> Self {
+        Self { pool, clients }
+    }
+
+    /// Reports a message and notifies the moderators of its workspace.
+    ///
+    /// # Arguments
+    /// * `reporter_id` - The user filing the report, who must be a member of
+    ///   the message's workspace
+    /// * `message_id` - The reported message
+    /// * `reason` - Why the message is reported
+    ///
+    /// # Returns
+    /// * `Result<Result<Report, ReportError>>` - The filed report, or why it
+    ///   was refused
+    pub async fn file(
+        &self,
+        reporter_id: i32,
+        message_id: i32,
+        reason: &str,
+    ) -> Result<Result<Report, ReportError>> {
+        let reason = match normalize_reason(reason) {
+            Ok(reason) => reason,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        let (report, reporter, sender) = {
+            let conn = &mut *self.pool.get().await?;
+            let message = match MessageRepository::find_by_id(conn, message_id).await {
+                Ok(message) => message,
+                Err(DieselError::NotFound) => return Ok(Err(ReportError::MessageNotFound)),
+                Err(e) => return Err(e.into()),
+            };
+            if !WorkspaceRepository::is_member(conn, message.workspace_id, reporter_id).await? {
+                return Ok(Err(ReportError::MessageNotFound));
+            }
+
+            let new_report = NewReport {
+                workspace_id: message.workspace_id,
+                message_id: Some(message.id),
+                reported_user_id: Some(message.sender_id),
+                reporter_id: Some(reporter_id),
+                reason,
+            };
+            let Some(report) = ReportRepository::create(conn, new_report).await? else {
+                return Ok(Err(ReportError::AlreadyReported));
+            };
+            let reporter = UserRepository::find_by_id(conn, reporter_id).await?;
+            let sender = UserRepository::find_by_id(conn, message.sender_id).await?;
+            (report, reporter, sender)
+        };
+
+        info!(
+            target: "audit",
+            event = "message_reported",
+            report_id = report.id,
+            message_id,
+            reporter_id,
+            reported_user_id = sender.id,
+            "Message reported"
+        );
+        let notifications = NotificationService::new(self.pool.clone(), self.clients.clone());
+        if let Err(e) = notifications
+            .notify_moderators(
+                report.workspace_id,
+                reporter_id,
+                Some(message_id),
+                "report-moderator-notice",
+                &[
+                    ("reporter", reporter.username),
+                    ("sender", sender.username),
+                    ("reason", report.reason.clone()),
+                ],
+            )
+            .await
+        {
+            error!(
+                "Failed to notify moderators about report {}: {}",
+                report.id, e
+            );
+        }
+
+        Ok(Ok(report))
+    }
+
+    /// Resolves an open report, carrying out the resolution.
+    ///
+    /// * `Deleted` deletes the reported message
+    /// * `Warned` sends its sender a warning notification
+    /// * `Banned` removes its sender from the workspace and notifies them
+    ///
+    /// # Arguments
+    /// * `report_id` - The report to resolve
+    /// * `moderator_id` - The moderator resolving it, whose permission has
+    ///   been checked
+    /// * `status` - The resolution
+    ///
+    /// # Returns
+    /// * `Result<Result<Report, ReportError>>` - The resolved report, or why it
+    ///   cannot be resolved
+    pub async fn resolve(
+        &self,
+        report_id: i32,
+        moderator_id: i32,
+        status: ReportStatus,
+    ) -> Result<Result<Report, ReportError>> {
+        if status == ReportStatus::Open {
+            return Ok(Err(ReportError::NotAResolution));
+        }
+
+        let (report, notice) = {
+            let conn = &mut *self.pool.get().await?;
+            let Some(report) =
+                ReportRepository::resolve(conn, report_id, status, moderator_id).await?
+            else {
+                return Ok(Err(ReportError::AlreadyResolved));
+            };
+
+            let key = match (status, report.message_id, report.reported_user_id) {
+                (ReportStatus::Deleted, Some(message_id), _) => {
+                    MessageRepository::delete(conn, message_id).await?;
+                    None
+                }
+                (ReportStatus::Warned, _, Some(user_id)) => Some((user_id, "report-warning")),
+                (ReportStatus::Banned, _, Some(user_id)) => {
+                    WorkspaceRepository::remove_member(conn, report.workspace_id, user_id).await?;
+                    Some((user_id, "report-banned"))
+                }
+                _ => None,
+            };
+            let notice = match key {
+                Some((user_id, key)) => {
+                    let workspace =
+                        WorkspaceRepository::find_by_id(conn, report.workspace_id).await?;
+                    let locale = PreferenceRepository::find_locales(conn, &[user_id])
+                        .await?
+                        .remove(&user_id)
+                        .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_string());
+                    let content = i18n::text(&locale, key, &[("workspace", workspace.name.into())]);
+                    Some((user_id, content))
+                }
+                None => None,
+            };
+            (report, notice)
+        };
+        info!(
+            target: "audit",
+            event = "report_resolved",
+            report_id,
+            moderator_id,
+            status = status.as_str(),
+            message_id = report.message_id,
+            reported_user_id = report.reported_user_id,
+            "Report resolved"
+        );
+
+        if let Some((user_id, content)) = notice {
+            let notifications = NotificationService::new(self.pool.clone(), self.clients.clone());
+            if let Err(e) = notifications
+                .notify(NewNotification {
+                    user_id,
+                    kind: NotificationKind::System,
+                    actor_id: None,
+                    message_id: report.message_id,
+                    content,
+                })
+                .await
+            {
+                error!(
+                    "Failed to notify user {} about report {}: {}",
+                    user_id, report_id, e
+                );
+            }
+        }
+
+        Ok(Ok(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_reason() {
+        assert_eq!(normalize_reason("  spam  "), Ok("spam".to_string()));
+        assert_eq!(normalize_reason(" \n "), Err(ReportError::EmptyReason));
+        assert!(normalize_reason(&"ř".repeat(MAX_REPORT_REASON_CHARS)).is_ok());
+        assert_eq!(
+            normalize_reason(&"a".repeat(MAX_REPORT_REASON_CHARS + 1)),
+            Err(ReportError::ReasonTooLong)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Deliveries that are retried until they succeed.
+//!
+//! Items are put on a queue and handed to a transport by a background worker.
+//! Failed deliveries are retried with exponential backoff before the worker
+//! gives up on them. Email and bot webhooks are delivered this way.
+
+use anyhow::Result;
+use rocket::async_trait;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+/// Number of delivery attempts before an item is dropped
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry; it doubles with every further attempt
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Items waiting for delivery before `push` waits for room
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Delivers the items of a [`RetryQueue`].
+#[async_trait]
+pub trait Transport<T>: Send + Sync {
+    async fn deliver(&self, item: &T) -> Result<()>;
+}
+
+/// Queues items for delivery by a background worker.
+pub struct RetryQueue<T> {
+    queue: mpsc::Sender<(T, u32)>,
+}
+
+impl<T> Clone for RetryQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T: Display + Send + Sync + 'static> RetryQueue<T> {
+    /// Creates the queue and spawns its delivery worker.
+    pub fn new(transport: Arc<dyn Transport<T>>) -> Self {
+        let (queue, pending) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(deliver_queued(transport, pending, queue.downgrade()));
+        Self { queue }
+    }
+
+    /// Queues an item for its first delivery attempt.
+    pub async fn push(&self, item: T) -> Result<()> {
+        self.queue
+            .send((item, 1))
+            .await
+            .map_err(|_| anyhow::anyhow!("Delivery queue is closed"))
+    }
+}
+
+/// Delivers queued items, scheduling retries for failed deliveries.
+async fn deliver_queued<T: Display + Send + Sync + 'static>(
+    transport: Arc<dyn Transport<T>>,
+    mut pending: mpsc::Receiver<(T, u32)>,
+    retries: mpsc::WeakSender<(T, u32)>,
+) {
+    while let Some((item, attempt)) = pending.recv().await {
+        let Err(e) = transport.deliver(&item).await else {
+            continue;
+        };
+
+        if attempt >= MAX_ATTEMPTS {
+            error!("Giving up on {} after {} attempts: {}", item, attempt, e);
+            continue;
+        }
+
+        let delay = INITIAL_RETRY_DELAY * 2u32.pow(attempt - 1);
+        warn!(
+            "Failed to deliver {} (attempt {}), retrying in {:?}: {}",
+            item, attempt, delay, e
+        );
+        let Some(retries) = retries.upgrade() else {
+            break;
+        };
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = retries.send((item, attempt + 1)).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Fails the first `failures` deliveries and records the rest.
+    struct FlakyTransport {
+        failures: Mutex<u32>,
+        delivered: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Transport<String> for FlakyTransport {
+        async fn deliver(&self, item: &String) -> Result<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                anyhow::bail!("Connection refused");
+            }
+            self.delivered.lock().unwrap().push(item.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_deliveries_are_retried() {
+        let transport = Arc::new(FlakyTransport {
+            failures: Mutex::new(2),
+            delivered: Mutex::new(Vec::new()),
+        });
+        let queue = RetryQueue::new(transport.clone());
+
+        queue.push("welcome email".to_string()).await.unwrap();
+
+        // Two retries wait 5 and 10 seconds
+        tokio::time::sleep(Duration::from_secs(14)).await;
+        assert!(transport.delivered.lock().unwrap().is_empty());
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(*transport.delivered.lock().unwrap(), ["welcome email"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gives_up_after_max_attempts() {
+        let transport = Arc::new(FlakyTransport {
+            failures: Mutex::new(MAX_ATTEMPTS),
+            delivered: Mutex::new(Vec::new()),
+        });
+        let queue = RetryQueue::new(transport.clone());
+
+        queue.push("webhook call".to_string()).await.unwrap();
+
+        tokio::time::sleep(Duration::from_secs(300)).await;
+        assert!(transport.delivered.lock().unwrap().is_empty());
+        assert_eq!(*transport.failures.lock().unwrap(), 0);
+    }
+}
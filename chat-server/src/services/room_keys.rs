@@ -0,0 +1,113 @@
+//! Per-workspace encryption keys.
+//!
+//! Every workspace gets its own key the first time it is needed. The key is
+//! stored wrapped with the server's `ENCRYPTION_KEY` and loaded into the
+//! server's keyring, so the server can read the content it moderates and
+//! persists. Members receive the key wrapped with their session token when
+//! they enter the workspace, so the shared key alone no longer decrypts their
+//! conversations.
+
+use anyhow::{anyhow, Result};
+use chat_common::encryption::keyring::{KeyWrapper, WrappedKey};
+use chat_common::encryption::message::MessageEncryption;
+use chat_common::encryption::EncryptionService;
+use chat_common::Message;
+use diesel_async::AsyncPgConnection;
+use rand::{distr::Alphanumeric, Rng};
+
+use crate::models::workspace::Workspace;
+use crate::models::workspace_key::NewWorkspaceKey;
+use crate::repositories::workspace_key::WorkspaceKeyRepository;
+
+/// Length of generated key IDs
+const KEY_ID_LENGTH: usize = 16;
+
+/// Returns the ID of a workspace's key, loading the key into the keyring.
+///
+/// # Arguments
+/// * `conn` - The database connection
+/// * `encryption` - The server's encryption service
+/// * `workspace_id` - The ID of the workspace
+pub async fn load(
+    conn: &mut AsyncPgConnection,
+    encryption: &EncryptionService,
+    workspace_id: i32,
+) -> Result<String> {
+    Ok(current_key(conn, encryption, workspace_id).await?.0)
+}
+
+/// Wraps a workspace's key for a single member's session, together with the
+/// sequence number of the workspace's latest message.
+///
+/// # Arguments
+/// * `conn` - The database connection
+/// * `encryption` - The server's encryption service
+/// * `workspace` - The workspace the member entered
+/// * `token` - The session token of the member's connection
+///
+/// # Returns
+/// * `Result<Message>` - The `RoomKey` message to send over the connection
+pub async fn for_session(
+    conn: &mut AsyncPgConnection,
+    encryption: &EncryptionService,
+    workspace: &Workspace,
+    token: &str,
+) -> Result<Message> {
+    let (key_id, key) = current_key(conn, encryption, workspace.id).await?;
+    let wrapped = KeyWrapper::new(token.as_bytes()).wrap(&key)?;
+
+    Ok(Message::RoomKey {
+        workspace: workspace.slug.clone(),
+        key_id,
+        wrapped_key: wrapped.wrapped_key,
+        nonce: wrapped.nonce,
+        last_seq: workspace.last_message_seq as u64,
+    })
+}
+
+/// Returns the ID and key of a workspace, creating the key on first use.
+async fn current_key(
+    conn: &mut AsyncPgConnection,
+    encryption: &EncryptionService,
+    workspace_id: i32,
+) -> Result<(String, [u8; 32])> {
+    let stored = match WorkspaceKeyRepository::find_by_workspace(conn, workspace_id).await? {
+        Some(stored) => stored,
+        None => {
+            let wrapped = encryption.wrap_key(&MessageEncryption::generate_key())?;
+            WorkspaceKeyRepository::create(
+                conn,
+                NewWorkspaceKey {
+                    workspace_id,
+                    key_id: generate_key_id(),
+                    wrapped_key: wrapped.wrapped_key,
+                    nonce: wrapped.nonce,
+                },
+            )
+            .await?;
+
+            // Another connection may have stored its key first
+            WorkspaceKeyRepository::find_by_workspace(conn, workspace_id)
+                .await?
+                .ok_or_else(|| anyhow!("Key of workspace {} was not stored", workspace_id))?
+        }
+    };
+
+    let key = encryption.unwrap_key(&WrappedKey {
+        wrapped_key: stored.wrapped_key,
+        nonce: stored.nonce,
+    })?;
+    if !encryption.has_key(&stored.key_id) {
+        encryption.add_key(&stored.key_id, &key)?;
+    }
+
+    Ok((stored.key_id, key))
+}
+
+fn generate_key_id() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(KEY_ID_LENGTH)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,277 @@
+//! Spam detection for chat messages.
+//!
+//! Three heuristics look at what each user sent recently: bursts of the same
+//! message, messages mentioning many users, and floods of links. A user caught
+//! by one of them is muted; every further offense doubles the mute, up to a
+//! day. Offenses are forgotten once a day passes without one.
+//!
+//! Mutes are kept in memory, so they end when the server restarts.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::SharedConfig;
+use crate::services::notification::extract_mentions;
+
+/// Longest mute handed out for repeated offenses
+const MAX_MUTE: Duration = Duration::from_secs(24 * 3600);
+
+/// How long an offense counts towards the length of the next mute
+const OFFENSE_MEMORY: Duration = Duration::from_secs(24 * 3600);
+
+/// The heuristic that caught a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamKind {
+    /// The same message sent too often
+    Duplicates,
+    /// Too many users mentioned at once
+    Mentions,
+    /// Too many links sent
+    Links,
+}
+
+impl SpamKind {
+    /// Returns the label used for logs and audit events.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpamKind::Duplicates => "duplicates",
+            SpamKind::Mentions => "mentions",
+            SpamKind::Links => "links",
+        }
+    }
+}
+
+/// A message found to be spam, and the mute its sender got for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offense {
+    pub kind: SpamKind,
+    /// How long the sender is muted
+    pub mute: Duration,
+    /// How many offenses in a row the sender has committed, including this one
+    pub count: u32,
+}
+
+/// What is remembered about the recent messages of a user.
+#[derive(Default)]
+struct History {
+    /// Time, normalized content hash and link count of recent messages, oldest first
+    messages: VecDeque<(Instant, u64, u32)>,
+    muted_until: Option<Instant>,
+    offenses: u32,
+    last_offense: Option<Instant>,
+}
+
+/// Spam bookkeeping shared by all connections.
+///
+/// The limits are read from the configuration on every message, so they can
+/// be reloaded.
+pub struct SpamDetector {
+    config: SharedConfig,
+    users: Mutex<HashMap<i32, History>>,
+}
+
+impl SpamDetector {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how long `user_id` stays muted, if they are.
+    pub fn muted_for(&self, user_id: i32, now: Instant) -> Option<Duration> {
+        let users = self.users.lock().unwrap();
+        let muted_until = users.get(&user_id)?.muted_until?;
+        muted_until
+            .checked_duration_since(now)
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Records a message of `user_id` with the plain text `text`, muting the
+    /// user if it is spam.
+    ///
+    /// # Returns
+    /// * `Option<Offense>` - The offense if the message is spam, None otherwise
+    pub fn check(&self, user_id: i32, text: &str, now: Instant) -> Option<Offense> {
+        let spam = self.config.current().spam.clone();
+        let mut users = self.users.lock().unwrap();
+        users.retain(|_, history| {
+            history
+                .messages
+                .retain(|(sent_at, _, _)| now.duration_since(*sent_at) < spam.window);
+            !history.messages.is_empty()
+                || history.muted_until.is_some_and(|until| until > now)
+                || history
+                    .last_offense
+                    .is_some_and(|at| now.duration_since(at) < OFFENSE_MEMORY)
+        });
+
+        let history = users.entry(user_id).or_default();
+        let hash = content_hash(text);
+        let links = count_links(text);
+        history.messages.push_back((now, hash, links));
+
+        let duplicates = history
+            .messages
+            .iter()
+            .filter(|(_, other, _)| *other == hash)
+            .count() as u32;
+        let recent_links: u32 = history.messages.iter().map(|(_, _, links)| links).sum();
+        let kind = if spam.duplicate_limit > 0 && duplicates > spam.duplicate_limit {
+            SpamKind::Duplicates
+        } else if spam.mention_limit > 0
+            && extract_mentions(text).len() > spam.mention_limit as usize
+        {
+            SpamKind::Mentions
+        } else if spam.link_limit > 0 && recent_links > spam.link_limit {
+            SpamKind::Links
+        } else {
+            return None;
+        };
+
+        if history
+            .last_offense
+            .is_some_and(|at| now.duration_since(at) >= OFFENSE_MEMORY)
+        {
+            history.offenses = 0;
+        }
+        history.offenses += 1;
+        history.last_offense = Some(now);
+        let mute = mute_duration(spam.mute_duration, history.offenses);
+        history.muted_until = Some(now + mute);
+        // The muted messages are dealt with; counting starts over after the mute
+        history.messages.clear();
+
+        Some(Offense {
+            kind,
+            mute,
+            count: history.offenses,
+        })
+    }
+}
+
+/// Returns the mute for the `offenses`-th offense in a row.
+fn mute_duration(base: Duration, offenses: u32) -> Duration {
+    let doublings = offenses.saturating_sub(1).min(16);
+    base.checked_mul(2u32.pow(doublings))
+        .map_or(MAX_MUTE, |mute| mute.min(MAX_MUTE))
+}
+
+/// Hashes a message so case and spacing changes do not hide duplicates.
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in text.split_whitespace() {
+        word.to_lowercase().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Counts the web links in a text.
+fn count_links(text: &str) -> u32 {
+    text.split_whitespace()
+        .filter(|word| {
+            let word = word.to_ascii_lowercase();
+            word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.")
+        })
+        .count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ServerConfig, SpamConfig};
+
+    fn detector_with(spam: SpamConfig) -> SpamDetector {
+        SpamDetector::new(SharedConfig::new(ServerConfig {
+            spam,
+            ..ServerConfig::default()
+        }))
+    }
+
+    #[test]
+    fn test_duplicate_burst_mutes_with_escalation() {
+        let detector = detector_with(SpamConfig {
+            duplicate_limit: 2,
+            mute_duration: Duration::from_secs(60),
+            ..SpamConfig::default()
+        });
+        let start = Instant::now();
+
+        assert_eq!(detector.check(1, "Buy now", start), None);
+        assert_eq!(detector.check(1, "buy   NOW", start), None);
+        // Other users and other messages do not count
+        assert_eq!(detector.check(2, "Buy now", start), None);
+        assert_eq!(detector.check(1, "Something else", start), None);
+
+        let offense = detector.check(1, "Buy now", start).unwrap();
+        assert_eq!(offense.kind, SpamKind::Duplicates);
+        assert_eq!(offense.mute, Duration::from_secs(60));
+        assert_eq!(
+            detector.muted_for(1, start + Duration::from_secs(10)),
+            Some(Duration::from_secs(50))
+        );
+        assert_eq!(detector.muted_for(2, start), None);
+
+        // The next offense doubles the mute
+        let later = start + Duration::from_secs(120);
+        assert_eq!(detector.muted_for(1, later), None);
+        for _ in 0..2 {
+            assert_eq!(detector.check(1, "Buy now", later), None);
+        }
+        let offense = detector.check(1, "Buy now", later).unwrap();
+        assert_eq!(offense.count, 2);
+        assert_eq!(offense.mute, Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_duplicates_age_out_of_window() {
+        let detector = detector_with(SpamConfig {
+            duplicate_limit: 1,
+            window: Duration::from_secs(30),
+            ..SpamConfig::default()
+        });
+        let start = Instant::now();
+
+        assert_eq!(detector.check(1, "hello", start), None);
+        assert_eq!(
+            detector.check(1, "hello", start + Duration::from_secs(30)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mentions_and_links() {
+        let detector = detector_with(SpamConfig {
+            mention_limit: 2,
+            link_limit: 2,
+            ..SpamConfig::default()
+        });
+        let now = Instant::now();
+
+        assert_eq!(detector.check(1, "@ann @bob hi", now), None);
+        assert_eq!(
+            detector.check(2, "@ann @bob @cid hi", now).map(|o| o.kind),
+            Some(SpamKind::Mentions)
+        );
+
+        assert_eq!(detector.check(3, "see https://example.com", now), None);
+        assert_eq!(detector.check(3, "and www.example.org", now), None);
+        assert_eq!(
+            detector
+                .check(3, "HTTP://example.net too", now)
+                .map(|o| o.kind),
+            Some(SpamKind::Links)
+        );
+    }
+
+    #[test]
+    fn test_mute_duration_is_capped() {
+        let base = Duration::from_secs(60);
+        assert_eq!(mute_duration(base, 1), base);
+        assert_eq!(mute_duration(base, 3), base * 4);
+        assert_eq!(mute_duration(base, 100), MAX_MUTE);
+    }
+}
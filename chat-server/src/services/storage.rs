@@ -0,0 +1,371 @@
+//! Object storage for attachments and avatars.
+//!
+//! Content is addressed by slash-separated keys such as `avatars/7` and kept
+//! either in a local directory or in an S3-compatible bucket, depending on the
+//! configured backend. Attachments are keyed by the SHA-256 of their content,
+//! so the same file sent twice is stored once. Backends that can hand out presigned URLs let clients
+//! download directly from the store; the local backend serves everything
+//! through the server.
+
+use crate::config::StorageConfig;
+use anyhow::{Context, Result};
+use rocket::async_trait;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use std::fmt;
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+
+/// A stream of stored content.
+pub type ByteStream = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Returned when a key does not exist in the store.
+#[derive(Debug)]
+pub struct ObjectNotFound(pub String);
+
+impl fmt::Display for ObjectNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Object '{}' does not exist", self.0)
+    }
+}
+
+impl std::error::Error for ObjectNotFound {}
+
+/// A place to keep binary content.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores content under a key, replacing any previous content.
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// Reads the whole content of a key.
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Returns whether content is stored under a key.
+    async fn exists(&self, key: &str) -> Result<bool>;
+
+    /// Returns the size of the content of a key in bytes.
+    async fn size(&self, key: &str) -> Result<u64>;
+
+    /// Deletes a key. Deleting a missing key succeeds.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Opens the content of a key for streaming.
+    async fn stream(&self, key: &str) -> Result<ByteStream>;
+
+    /// Opens part of the content of a key for streaming, `range` being end
+    /// exclusive and within the content.
+    async fn stream_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream>;
+
+    /// Returns a URL clients can download the content from directly, or None if
+    /// the backend cannot presign URLs.
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<Option<String>>;
+}
+
+/// Key of an attachment with the given SHA-256 of its content.
+pub fn attachment_key(sha256: &str) -> String {
+    format!("attachments/{}", sha256)
+}
+
+/// Creates the configured storage backend.
+pub fn from_config(config: &StorageConfig) -> Result<Arc<dyn Storage>> {
+    Ok(match config {
+        StorageConfig::Local { root } => Arc::new(LocalStorage::new(root.clone())),
+        StorageConfig::S3 { .. } => Arc::new(S3Storage::new(config)?),
+    })
+}
+
+/// Stores content as files below a root directory.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Maps a key to a file below the root, rejecting keys that would escape it.
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        let valid = !key.is_empty()
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if !valid {
+            anyhow::bail!("Invalid storage key '{}'", key);
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+/// Converts a missing file into [`ObjectNotFound`].
+fn not_found(key: &str, e: std::io::Error) -> anyhow::Error {
+    if e.kind() == ErrorKind::NotFound {
+        ObjectNotFound(key.to_string()).into()
+    } else {
+        e.into()
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Write next to the target and rename, so readers never see partial content
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        tokio::fs::read(self.path(key)?)
+            .await
+            .map_err(|e| not_found(key, e))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(tokio::fs::try_exists(self.path(key)?).await?)
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        let metadata = tokio::fs::metadata(self.path(key)?)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        Ok(metadata.len())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream> {
+        let file = tokio::fs::File::open(self.path(key)?)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        Ok(Box::pin(file))
+    }
+
+    async fn stream_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream> {
+        let mut file = tokio::fs::File::open(self.path(key)?)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+        Ok(Box::pin(file.take(range.end - range.start)))
+    }
+
+    async fn presigned_url(&self, _key: &str, _expires_in: Duration) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Bytes buffered between the bucket and the client when streaming a range
+const RANGE_PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Stores content in an S3-compatible bucket.
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    pub fn new(config: &StorageConfig) -> Result<Self> {
+        let StorageConfig::S3 {
+            bucket,
+            region,
+            endpoint,
+            access_key,
+            secret_key,
+            path_style,
+        } = config
+        else {
+            anyhow::bail!("Not an S3 storage configuration");
+        };
+
+        let region = match endpoint {
+            Some(endpoint) => Region::Custom {
+                region: region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => region.parse()?,
+        };
+        let credentials = match (access_key, secret_key) {
+            (Some(access_key), Some(secret_key)) => {
+                Credentials::new(Some(access_key), Some(secret_key), None, None, None)?
+            }
+            _ => Credentials::default().context("No S3 credentials found")?,
+        };
+
+        let bucket = Bucket::new(bucket, region, credentials)?;
+        Ok(Self {
+            bucket: if *path_style {
+                bucket.with_path_style()
+            } else {
+                bucket
+            },
+        })
+    }
+}
+
+/// Converts a 404 response into [`ObjectNotFound`].
+fn s3_error(key: &str, e: S3Error) -> anyhow::Error {
+    match e {
+        S3Error::HttpFailWithBody(404, _) => ObjectNotFound(key.to_string()).into(),
+        e => e.into(),
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.bucket.put_object(key, data).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let response = self
+            .bucket
+            .get_object(key)
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        Ok(response.to_vec())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.bucket.head_object(key).await {
+            Ok((_, 404)) | Err(S3Error::HttpFailWithBody(404, _)) => Ok(false),
+            Ok(_) => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        let (head, _) = self
+            .bucket
+            .head_object(key)
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        Ok(head.content_length.unwrap_or_default().max(0) as u64)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.bucket.delete_object(key).await {
+            Err(e) if !matches!(e, S3Error::HttpFailWithBody(404, _)) => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream> {
+        let response = self
+            .bucket
+            .get_object_stream(key)
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        Ok(Box::pin(response))
+    }
+
+    async fn stream_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream> {
+        // The bucket writes the range into a pipe that the caller reads from,
+        // so large ranges are never held in memory as a whole
+        let (reader, mut writer) = tokio::io::duplex(RANGE_PIPE_CAPACITY);
+        let bucket = self.bucket.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = bucket
+                .get_object_range_to_writer(&key, range.start, Some(range.end - 1), &mut writer)
+                .await
+            {
+                tracing::error!("Failed to stream range of '{}': {}", key, e);
+            }
+        });
+        Ok(Box::pin(reader))
+    }
+
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<Option<String>> {
+        let url = self
+            .bucket
+            .presign_get(key, expires_in.as_secs() as u32, None)
+            .await?;
+        Ok(Some(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn temp_storage(name: &str) -> LocalStorage {
+        LocalStorage::new(std::env::temp_dir().join(format!(
+            "chat-storage-{}-{}",
+            name,
+            std::process::id()
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_local_roundtrip() {
+        let storage = temp_storage("roundtrip");
+
+        storage.put("avatars/1", b"first").await.unwrap();
+        storage.put("avatars/1", b"second").await.unwrap();
+        assert_eq!(storage.get("avatars/1").await.unwrap(), b"second");
+        assert!(storage.exists("avatars/1").await.unwrap());
+
+        let mut streamed = Vec::new();
+        storage
+            .stream("avatars/1")
+            .await
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, b"second");
+
+        let mut part = Vec::new();
+        storage
+            .stream_range("avatars/1", 1..4)
+            .await
+            .unwrap()
+            .read_to_end(&mut part)
+            .await
+            .unwrap();
+        assert_eq!(part, b"eco");
+        assert_eq!(storage.size("avatars/1").await.unwrap(), 6);
+        assert_eq!(
+            storage
+                .presigned_url("avatars/1", Duration::from_secs(60))
+                .await
+                .unwrap(),
+            None
+        );
+
+        storage.delete("avatars/1").await.unwrap();
+        storage.delete("avatars/1").await.unwrap();
+        assert!(!storage.exists("avatars/1").await.unwrap());
+        let missing = storage.get("avatars/1").await.unwrap_err();
+        assert!(missing.downcast_ref::<ObjectNotFound>().is_some());
+
+        tokio::fs::remove_dir_all(&storage.root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_rejects_escaping_keys() {
+        let storage = temp_storage("keys");
+        for key in ["", "../secret", "/etc/passwd", "avatars/../../x"] {
+            assert!(storage.put(key, b"x").await.is_err(), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! TLS for chat connections.
+//!
+//! Next to plain TCP, the server can serve chat connections over TLS. If a CA
+//! for client certificates is configured, clients may present a certificate
+//! during the handshake; a certificate registered for an account logs the
+//! connection in without an `Auth` message. Clients without a certificate log
+//! in with their password as usual.
+
+use crate::config::TlsConfig;
+use crate::models::client_certificate;
+use crate::services::client_service::ClientService;
+use crate::utils::metrics::Metrics;
+use anyhow::{bail, Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info, warn};
+
+/// How long a client may take to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builds the acceptor performing the server side of TLS handshakes.
+///
+/// # Arguments
+/// * `config` - The certificate, key and client CA files
+///
+/// # Returns
+/// * `Result<TlsAcceptor>` - The acceptor, or an error if a file cannot be read
+///   or does not contain a valid certificate or key
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &config.client_ca_file {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in load_certificates(path)? {
+                roots.add(certificate)?;
+            }
+            // Clients without a certificate are let in and log in with a password
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config = builder.with_single_cert(
+        load_certificates(&config.cert_file)?,
+        load_private_key(&config.key_file)?,
+    )?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Accepts TLS chat connections for as long as the server runs.
+///
+/// Every handshake runs on its own task and is abandoned after
+/// [`HANDSHAKE_TIMEOUT`], so slow or stalled clients cannot hold up others.
+///
+/// # Arguments
+/// * `listener` - The bound TLS port
+/// * `acceptor` - Performs the handshakes
+/// * `clients` - Handles the connections once the handshake completed
+/// * `metrics` - Counts the active connections
+pub async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    clients: Arc<ClientService>,
+    metrics: Arc<Mutex<Metrics>>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("TLS connection failed: {}", e);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let clients = Arc::clone(&clients);
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    warn!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+                Err(_) => {
+                    warn!("TLS handshake with {} timed out", addr);
+                    return;
+                }
+            };
+
+            info!("New TLS connection from: {}", addr);
+            metrics.lock().await.active_connections.inc();
+            if let Err(e) = clients.handle_new_tls_client(stream).await {
+                error!("Failed to handle TLS client {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Returns the fingerprint of the certificate the client presented, if any.
+pub fn peer_fingerprint(stream: &TlsStream<TcpStream>) -> Option<String> {
+    let (_, connection) = stream.get_ref();
+    let certificate = connection.peer_certificates()?.first()?;
+    Some(client_certificate::fingerprint(certificate.as_ref()))
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate in {}", path.display()))?;
+    if certificates.is_empty() {
+        bail!("No certificate in {}", path.display());
+    }
+    Ok(certificates)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Invalid private key in {}", path.display()))?
+        .with_context(|| format!("No private key in {}", path.display()))
+}
@@ -0,0 +1,331 @@
+//! Bulk creation of users from CSV files.
+//!
+//! Server admins upload a file with `username`, `email` and `password`
+//! columns in any order; other columns, such as those of a user export, are
+//! ignored. Every row is checked like a registration over REST: the username
+//! must be valid and free, the email must look like an address that is not
+//! registered yet, and the password must meet the password policy. Rows are
+//! also checked against each other, so a file cannot create a user twice.
+//! A dry run only reports the problems of each row; an import creates the
+//! users of the valid rows and skips the others.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::i18n;
+use crate::models::user::{normalize_username, NewUserRequest};
+use crate::repositories::user::{UserRepository, UserWriteError};
+use crate::services::password::PasswordService;
+use crate::utils::csv::{self, CsvError};
+use anyhow::Result;
+use diesel_async::AsyncPgConnection;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::info;
+
+/// Most rows a single import may have
+pub const MAX_IMPORT_ROWS: usize = 1000;
+
+/// Why an import file cannot be read at all.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum ImportError {
+    #[error(transparent)]
+    Csv(#[from] CsvError),
+    #[error("The file is empty")]
+    Empty,
+    #[error("The header line has no '{0}' column")]
+    MissingColumn(&'static str),
+    #[error("At most {MAX_IMPORT_ROWS} users can be imported at once")]
+    TooManyRows,
+}
+
+/// Outcome of one row of an import file.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImportRow {
+    /// Line of the row in the file, counting from 1
+    pub line: usize,
+    pub username: String,
+    pub email: String,
+    /// Why the row is rejected, empty if it is valid
+    pub errors: Vec<String>,
+    /// The user created from the row
+    pub user_id: Option<i32>,
+}
+
+/// Outcome of an import, row by row.
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Rows without errors
+    pub valid: usize,
+    /// Rows with errors, which are not imported
+    pub invalid: usize,
+    /// Users created, none in a dry run
+    pub created: usize,
+    pub rows: Vec<ImportRow>,
+}
+
+/// A row together with its password, which is left out of the report.
+struct Candidate {
+    row: ImportRow,
+    password: String,
+}
+
+/// Returns whether a value looks like an email address.
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains('@')
+        && !value.contains(char::is_whitespace)
+}
+
+/// Reads the rows of an import file and checks them on their own and against
+/// each other.
+fn read_rows(text: &str) -> Result<Vec<Candidate>, ImportError> {
+    let mut records = csv::parse(text)?.into_iter();
+    let header = records.next().ok_or(ImportError::Empty)?;
+    let column = |name: &'static str| {
+        header
+            .fields
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name))
+            .ok_or(ImportError::MissingColumn(name))
+    };
+    let (username_at, email_at, password_at) =
+        (column("username")?, column("email")?, column("password")?);
+
+    let records: Vec<_> = records.collect();
+    if records.len() > MAX_IMPORT_ROWS {
+        return Err(ImportError::TooManyRows);
+    }
+
+    let mut usernames = HashSet::new();
+    let mut emails = HashSet::new();
+    let candidates = records
+        .into_iter()
+        .map(|record| {
+            let field = |at: usize| record.fields.get(at).cloned().unwrap_or_default();
+            let mut errors = Vec::new();
+
+            let username = match normalize_username(&field(username_at)) {
+                Ok(username) => {
+                    if !usernames.insert(username.to_lowercase()) {
+                        errors.push(format!(
+                            "The username '{}' is on an earlier line too",
+                            username
+                        ));
+                    }
+                    username
+                }
+                Err(e) => {
+                    errors.push(e.to_string());
+                    field(username_at).trim().to_string()
+                }
+            };
+
+            let email = field(email_at).trim().to_string();
+            if !is_email(&email) {
+                errors.push(format!("'{}' is not an email address", email));
+            } else if !emails.insert(email.to_lowercase()) {
+                errors.push(format!("The email '{}' is on an earlier line too", email));
+            }
+
+            // Passwords are taken as they are, spaces included
+            let password = field(password_at);
+            if password.is_empty() {
+                errors.push("The password is missing".to_string());
+            }
+
+            Candidate {
+                row: ImportRow {
+                    line: record.line,
+                    username,
+                    email,
+                    errors,
+                    user_id: None,
+                },
+                password,
+            }
+        })
+        .collect();
+    Ok(candidates)
+}
+
+/// Creates users from CSV files uploaded by server admins.
+pub struct UserImportService {
+    passwords: Arc<PasswordService>,
+}
+
+impl UserImportService {
+    pub fn new(passwords: Arc<PasswordService>) -> Self {
+        Self { passwords }
+    }
+
+    /// Checks the rows of an import file and, unless `dry_run` is set, creates
+    /// the users of the valid ones.
+    ///
+    /// # Arguments
+    /// * `conn` - Connection the users are checked and created with
+    /// * `text` - The CSV file
+    /// * `dry_run` - Only checks the rows
+    /// * `admin_id` - The server admin importing the users, for the audit log
+    ///
+    /// # Returns
+    /// * `Result<Result<ImportReport, ImportError>>` - The outcome of every row,
+    ///   or why the file cannot be read
+    pub async fn import(
+        &self,
+        conn: &mut AsyncPgConnection,
+        text: &str,
+        dry_run: bool,
+        admin_id: i32,
+    ) -> Result<Result<ImportReport, ImportError>> {
+        let mut candidates = match read_rows(text) {
+            Ok(candidates) => candidates,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        let existing = UserRepository::find_all(conn).await?;
+        let taken_usernames: HashSet<_> = existing
+            .iter()
+            .map(|user| user.username.to_lowercase())
+            .collect();
+        let taken_emails: HashSet<_> = existing
+            .iter()
+            .map(|user| user.email.to_lowercase())
+            .collect();
+
+        for Candidate { row, password } in &mut candidates {
+            if taken_usernames.contains(&row.username.to_lowercase()) {
+                row.errors.push(UserWriteError::UsernameTaken.to_string());
+            }
+            if taken_emails.contains(&row.email.to_lowercase()) {
+                row.errors.push(UserWriteError::EmailTaken.to_string());
+            }
+            if !password.is_empty() {
+                let violations = self
+                    .passwords
+                    .validate(password, &[&row.username, &row.email])
+                    .await;
+                row.errors.extend(
+                    violations
+                        .iter()
+                        .map(|violation| violation.message(i18n::DEFAULT_LOCALE)),
+                );
+            }
+        }
+
+        if !dry_run {
+            for Candidate { row, password } in &mut candidates {
+                if !row.errors.is_empty() {
+                    continue;
+                }
+                let request = NewUserRequest {
+                    username: row.username.clone(),
+                    email: row.email.clone(),
+                    password: std::mem::take(password),
+                    invite_code: None,
+                };
+                // A row can still clash with a user registered in the meantime
+                match UserRepository::create(conn, request).await {
+                    Ok(user) => row.user_id = Some(user.id),
+                    Err(e) => row.errors.push(e.to_string()),
+                }
+            }
+        }
+
+        let rows: Vec<ImportRow> = candidates.into_iter().map(|c| c.row).collect();
+        let valid = rows.iter().filter(|row| row.errors.is_empty()).count();
+        let created = rows.iter().filter(|row| row.user_id.is_some()).count();
+        if !dry_run {
+            info!(
+                target: "audit",
+                event = "users_imported",
+                admin_id,
+                created,
+                skipped = rows.len() - created,
+                "Users imported"
+            );
+        }
+
+        Ok(Ok(ImportReport {
+            dry_run,
+            valid,
+            invalid: rows.len() - valid,
+            created,
+            rows,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(text: &str) -> Vec<Vec<String>> {
+        read_rows(text)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.row.errors)
+            .collect()
+    }
+
+    #[test]
+    fn test_read_rows_by_column_name() {
+        let candidates =
+            read_rows("Password,id,Email,Username\n\"s3cret, really\",7,bob@example.com, Bob \n")
+                .unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].password, "s3cret, really");
+        assert_eq!(
+            candidates[0].row,
+            ImportRow {
+                line: 2,
+                username: "Bob".to_string(),
+                email: "bob@example.com".to_string(),
+                errors: Vec::new(),
+                user_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_rows_reports_errors_per_row() {
+        let errors = errors(
+            "username,email,password\n\
+             alice,alice@example.com,secret\n\
+             ALICE,other@example.com,secret\n\
+             a,alice@example.com,\n\
+             carol,not-an-address,secret\n",
+        );
+
+        assert!(errors[0].is_empty());
+        assert_eq!(
+            errors[1],
+            vec!["The username 'ALICE' is on an earlier line too"]
+        );
+        assert_eq!(errors[2].len(), 3);
+        assert_eq!(errors[3], vec!["'not-an-address' is not an email address"]);
+    }
+
+    #[test]
+    fn test_read_rows_rejects_unusable_files() {
+        assert!(matches!(read_rows(""), Err(ImportError::Empty)));
+        assert!(matches!(
+            read_rows("username,email\nalice,alice@example.com\n"),
+            Err(ImportError::MissingColumn("password"))
+        ));
+
+        let mut text = "username,email,password\n".to_string();
+        for i in 0..=MAX_IMPORT_ROWS {
+            text.push_str(&format!("user{0},user{0}@example.com,secret\n", i));
+        }
+        assert!(matches!(read_rows(&text), Err(ImportError::TooManyRows)));
+    }
+}
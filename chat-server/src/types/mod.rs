@@ -1,13 +1,79 @@
+use crate::models::do_not_disturb::DndSettings;
+use crate::services::outbound_queue::OutboundQueue;
+use anyhow::Result;
+use chat_common::async_message_stream::{ByteCounters, ByteMeter};
+use chat_common::Message;
+use chrono::NaiveDateTime;
+use prometheus::Counter;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::tcp::OwnedWriteHalf;
 use tokio::sync::Mutex;
 
 #[derive(Debug)]
 pub struct ChatRoomConnection {
     pub user_id: Option<i32>,
-    pub writer: OwnedWriteHalf,
+    /// Workspace the connection chats in, set once the user is authenticated
+    pub workspace_id: Option<i32>,
+    pub outbound: OutboundQueue,
     pub auth_state: AuthState,
+    /// Remote address of the client
+    pub peer_addr: SocketAddr,
+    /// When the client connected, in UTC
+    pub connected_at: NaiveDateTime,
+    /// Locale of the texts the server sends, the user's preference once authenticated
+    pub locale: String,
+    /// Failed logins on this connection
+    pub failed_logins: u32,
+    /// The user's do-not-disturb settings once authenticated
+    pub do_not_disturb: DndSettings,
+    /// Bytes transferred over the connection
+    pub transfer: Arc<ConnectionTransfer>,
+    /// Optional protocol features the client advertised in its `Hello`
+    pub capabilities: Vec<String>,
+}
+
+/// Bytes a connection read and wrote, also added to the server-wide totals in
+/// the metrics. Connections that are not served over a socket of their own,
+/// like gRPC calls, are not metered and stay at zero.
+#[derive(Debug, Default)]
+pub struct ConnectionTransfer {
+    counters: ByteCounters,
+    /// `chat_bytes_read_total` and `chat_bytes_written_total`
+    totals: Option<(Counter, Counter)>,
+}
+
+impl ConnectionTransfer {
+    pub fn new(bytes_read: Counter, bytes_written: Counter) -> Self {
+        Self {
+            counters: ByteCounters::default(),
+            totals: Some((bytes_read, bytes_written)),
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.counters.bytes_read()
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.counters.bytes_written()
+    }
+}
+
+impl ByteMeter for ConnectionTransfer {
+    fn read(&self, bytes: u64) {
+        self.counters.read(bytes);
+        if let Some((bytes_read, _)) = &self.totals {
+            bytes_read.inc_by(bytes as f64);
+        }
+    }
+
+    fn written(&self, bytes: u64) {
+        self.counters.written(bytes);
+        if let Some((_, bytes_written)) = &self.totals {
+            bytes_written.inc_by(bytes as f64);
+        }
+    }
 }
 
 /// Type alias for the shared clients collection
@@ -23,4 +89,50 @@ impl ChatRoomConnection {
     pub fn is_authenticated(&self) -> bool {
         matches!(self.auth_state, AuthState::Authenticated { .. })
     }
+
+    /// Returns the session token of an authenticated connection.
+    pub fn token(&self) -> Option<&str> {
+        match &self.auth_state {
+            AuthState::Authenticated { token, .. } => Some(token),
+            AuthState::NotAuthenticated => None,
+        }
+    }
+
+    /// Returns whether the client advertised an optional protocol feature.
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Creates an authenticated connection of `user_id` in `workspace_id`
+    /// whose queue holds four messages, for tests.
+    #[cfg(test)]
+    pub fn for_test(user_id: i32, workspace_id: i32) -> Self {
+        use crate::config::BackpressurePolicy;
+        use crate::utils::metrics::Metrics;
+
+        Self {
+            user_id: Some(user_id),
+            workspace_id: Some(workspace_id),
+            outbound: OutboundQueue::new(4, BackpressurePolicy::Block, Metrics::new()),
+            auth_state: AuthState::Authenticated {
+                user_id,
+                token: "token".to_string(),
+            },
+            peer_addr: "127.0.0.1:4000".parse().unwrap(),
+            connected_at: chrono::Utc::now().naive_utc(),
+            locale: "en".to_string(),
+            failed_logins: 0,
+            do_not_disturb: Default::default(),
+            transfer: Default::default(),
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Queues a message for delivery to this connection.
+    ///
+    /// # Returns
+    /// * `Result<()>` - Err if the connection's queue is closed or overflowed
+    pub async fn send(&self, message: &Message) -> Result<()> {
+        self.outbound.push(message.clone()).await
+    }
 }
@@ -0,0 +1,186 @@
+//! Binding the server's ports at startup.
+//!
+//! A restarted server often finds its port still held by the previous process
+//! for a few seconds, so binding is retried with backoff while the address is
+//! in use. On Linux the process holding the port is looked up in `/proc` and
+//! logged. Port 0 binds an ephemeral port; the ports actually bound can be
+//! written to a file for test harnesses.
+
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tracing::warn;
+
+/// Delay before the first retry; it doubles up to [`MAX_BACKOFF`]
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Binds a TCP listener, retrying for up to `retry_period` while the address
+/// is in use.
+///
+/// # Arguments
+/// * `addr` - The address to bind, e.g. `0.0.0.0:8080`
+/// * `retry_period` - How long to keep retrying; zero fails immediately
+pub async fn bind_with_retry(addr: &str, retry_period: Duration) -> io::Result<TcpListener> {
+    let deadline = Instant::now() + retry_period;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempts = 0;
+
+    loop {
+        let e = match TcpListener::bind(addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == ErrorKind::AddrInUse => e,
+            Err(e) => return Err(e),
+        };
+        attempts += 1;
+
+        if Instant::now() + backoff > deadline {
+            let holder = describe_holder(addr);
+            return Err(io::Error::new(
+                ErrorKind::AddrInUse,
+                format!(
+                    "{} is still in use{} after {} attempts: {}",
+                    addr, holder, attempts, e
+                ),
+            ));
+        }
+        if attempts == 1 {
+            warn!(
+                "{} is in use{}, retrying for up to {:?}",
+                addr,
+                describe_holder(addr),
+                retry_period
+            );
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Waits until `addr` can be bound, for servers that bind the port
+/// themselves. Port 0 is always free.
+pub async fn wait_until_free(addr: &str, retry_period: Duration) -> io::Result<()> {
+    bind_with_retry(addr, retry_period).await.map(drop)
+}
+
+/// Writes the bound ports as `KEY=value` lines, using the names of the
+/// variables that configure them.
+pub fn write_port_file(path: &Path, tcp_port: u16, http_port: u16) -> io::Result<()> {
+    std::fs::write(
+        path,
+        format!("TCP_PORT={}\nROCKET_PORT={}\n", tcp_port, http_port),
+    )
+}
+
+/// Describes the process holding the port of `addr`, e.g. ` by pid 42 (nginx)`,
+/// or returns an empty string if it cannot be determined.
+fn describe_holder(addr: &str) -> String {
+    addr.rsplit(':')
+        .next()
+        .and_then(|port| port.parse().ok())
+        .and_then(port_holder)
+        .map(|holder| format!(" by {}", holder))
+        .unwrap_or_default()
+}
+
+/// Finds the process listening on a TCP port.
+#[cfg(target_os = "linux")]
+fn port_holder(port: u16) -> Option<String> {
+    let inode = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(table).ok())
+        .find_map(|table| listening_inode(&table, port))?;
+    let socket = format!("socket:[{}]", inode);
+
+    for process in std::fs::read_dir("/proc").ok()?.flatten() {
+        // Skip aliases such as /proc/self
+        let pid = process.file_name().to_string_lossy().to_string();
+        if !pid.bytes().all(|byte| byte.is_ascii_digit()) {
+            continue;
+        }
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        let holds_socket = fds.flatten().any(|fd| {
+            std::fs::read_link(fd.path()).is_ok_and(|target| target.as_os_str() == socket.as_str())
+        });
+        if holds_socket {
+            let name = std::fs::read_to_string(process.path().join("comm")).unwrap_or_default();
+            return Some(format!("pid {} ({})", pid, name.trim()));
+        }
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn port_holder(_port: u16) -> Option<String> {
+    None
+}
+
+/// Finds the socket inode listening on `port` in a `/proc/net/tcp` table.
+#[cfg(target_os = "linux")]
+fn listening_inode(table: &str, port: u16) -> Option<String> {
+    const LISTEN: &str = "0A";
+
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let local_port = fields.get(1)?.rsplit(':').next()?;
+        if u16::from_str_radix(local_port, 16).ok()? != port || *fields.get(3)? != LISTEN {
+            return None;
+        }
+        fields.get(9).map(|inode| inode.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_retries_until_port_is_released() {
+        let holder = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = holder.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            drop(holder);
+        });
+
+        let listener = bind_with_retry(&addr, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(listener.local_addr().unwrap().to_string(), addr);
+    }
+
+    #[tokio::test]
+    async fn test_bind_gives_up_after_retry_period() {
+        let holder = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = holder.local_addr().unwrap().to_string();
+
+        let e = bind_with_retry(&addr, Duration::from_millis(300))
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::AddrInUse);
+        assert!(e.to_string().contains(&addr));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_port_holder_is_found() {
+        let holder = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = holder.local_addr().unwrap().port();
+
+        let description = port_holder(port).unwrap();
+        assert!(description.starts_with(&format!("pid {} ", std::process::id())));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_listening_inode() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+                     0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 4242 1\n\
+                     1: 0100007F:1F91 0100007F:9C40 01 00000000:00000000 00:00000000 00000000     0        0 4343 1\n";
+        assert_eq!(listening_inode(table, 8080), Some("4242".to_string()));
+        assert_eq!(listening_inode(table, 8081), None);
+    }
+}
@@ -19,6 +19,10 @@ impl Fairing for Cors {
             "GET, POST, PUT, DELETE, OPTIONS",
         );
         res.set_raw_header("Access-Control-Allow-Headers", "*");
+        res.set_raw_header(
+            "Access-Control-Expose-Headers",
+            "Content-Disposition, Content-Range, ETag, X-Export-Rows",
+        );
         res.set_raw_header("Access-Control-Allow-Credentials", "true");
     }
 }
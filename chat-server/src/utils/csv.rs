@@ -0,0 +1,143 @@
+//! Reading and writing CSV for exports and imports.
+//!
+//! Fields are separated by commas and quoted with `"` when they contain a
+//! separator, quote or line break, with quotes inside doubled, as in RFC 4180.
+
+use std::borrow::Cow;
+use thiserror::Error;
+
+/// Why a CSV file cannot be read.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum CsvError {
+    #[error("Line {0}: the quoted field is never closed")]
+    UnterminatedQuote(usize),
+    #[error("Line {0}: a quoted field is followed by more text")]
+    TextAfterQuote(usize),
+}
+
+/// A line of a CSV file split into its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Line the record starts on, counting from 1
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+pub fn escape(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Splits CSV text into records, skipping blank lines.
+///
+/// A leading byte order mark, as written by spreadsheet programs, is ignored,
+/// and lines may end with `\n` or `\r\n`. Quoted fields may span lines.
+pub fn parse(text: &str) -> Result<Vec<Record>, CsvError> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut record_line = 1;
+
+    let mut chars = text
+        .strip_prefix('\u{feff}')
+        .unwrap_or(text)
+        .chars()
+        .peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => return Err(CsvError::UnterminatedQuote(start)),
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',' | '\n' | '\r')) {
+                    return Err(CsvError::TextAfterQuote(line));
+                }
+            }
+            ',' => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                if fields != [""] {
+                    records.push(Record {
+                        line: record_line,
+                        fields: std::mem::take(&mut fields),
+                    });
+                }
+                fields.clear();
+                line += 1;
+                record_line = line;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push(Record {
+            line: record_line,
+            fields,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(records: &[Record]) -> Vec<Vec<&str>> {
+        records
+            .iter()
+            .map(|record| record.fields.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_round_trips_escaped_fields() {
+        let values = ["plain", "a, b", "say \"hi\"", "two\nlines", ""];
+        let line = values.map(|value| escape(value).into_owned()).join(",");
+        let records = parse(&format!("{}\r\nnext,row\n", line)).unwrap();
+
+        assert_eq!(fields(&records), vec![values.to_vec(), vec!["next", "row"]]);
+        assert_eq!(records[1].line, 3);
+    }
+
+    #[test]
+    fn test_parse_skips_blank_lines_and_bom() {
+        let records = parse("\u{feff}username,email\n\nalice,alice@example.com").unwrap();
+
+        assert_eq!(
+            fields(&records),
+            vec![
+                vec!["username", "email"],
+                vec!["alice", "alice@example.com"]
+            ]
+        );
+        assert_eq!(records[1].line, 3);
+    }
+
+    #[test]
+    fn test_parse_rejects_broken_quotes() {
+        assert_eq!(parse("a,\"open\nb"), Err(CsvError::UnterminatedQuote(1)));
+        assert_eq!(parse("a\n\"quoted\"tail"), Err(CsvError::TextAfterQuote(2)));
+    }
+}
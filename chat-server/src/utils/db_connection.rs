@@ -1,7 +1,12 @@
 use anyhow::Result;
-use diesel_async::pooled_connection::deadpool::Pool;
+use diesel_async::pooled_connection::deadpool::{Object, Pool};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
 use diesel_async::AsyncPgConnection;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+use rocket_db_pools::Connection;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
 // Define the DbConn type for Rocket database connection
 #[derive(rocket_db_pools::Database)]
@@ -16,13 +21,89 @@ pub type DbPool = Pool<AsyncPgConnection>;
 /// This is used for non-Rocket parts of the application
 pub async fn create_pool() -> Result<DbPool> {
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    build_pool(database_url)
+}
 
+fn build_pool(database_url: String) -> Result<DbPool> {
     let config = AsyncDieselConnectionManager::<AsyncPgConnection>::new(database_url);
     let pool = Pool::builder(config).max_size(5).build()?;
 
     Ok(pool)
 }
 
+/// Connection pool of the read replica, if `DATABASE_REPLICA_URL` is set.
+///
+/// Read-only queries that can tolerate replication lag, such as listings,
+/// go to the replica; everything else uses the primary.
+#[derive(Clone, Default)]
+pub struct ReplicaPool(Option<Arc<DbPool>>);
+
+impl ReplicaPool {
+    /// Creates the replica pool from `DATABASE_REPLICA_URL`, or an empty one
+    /// that routes all reads to the primary if it is not set.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("DATABASE_REPLICA_URL") {
+            Ok(url) => Ok(Self(Some(Arc::new(build_pool(url)?)))),
+            Err(_) => Ok(Self(None)),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn pool(&self) -> Option<&DbPool> {
+        self.0.as_deref()
+    }
+}
+
+/// A connection for read-only queries in Rocket routes.
+///
+/// Uses the read replica when one is configured and reachable, and a regular
+/// [`DbConn`] connection to the primary otherwise.
+pub enum ReadConn {
+    Replica(Object<AsyncPgConnection>),
+    Primary(Connection<DbConn>),
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadConn {
+    type Error = <Connection<DbConn> as FromRequest<'r>>::Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let replica = req.guard::<&State<ReplicaPool>>().await.succeeded();
+        if let Some(replica) = replica.and_then(|replica| replica.0.as_ref()) {
+            match replica.get().await {
+                Ok(conn) => return Outcome::Success(ReadConn::Replica(conn)),
+                Err(e) => rocket::warn!("Read replica unavailable, using the primary: {}", e),
+            }
+        }
+        req.guard::<Connection<DbConn>>()
+            .await
+            .map(ReadConn::Primary)
+    }
+}
+
+impl Deref for ReadConn {
+    type Target = AsyncPgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ReadConn::Replica(conn) => conn,
+            ReadConn::Primary(conn) => conn,
+        }
+    }
+}
+
+impl DerefMut for ReadConn {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            ReadConn::Replica(conn) => conn,
+            ReadConn::Primary(conn) => conn,
+        }
+    }
+}
+
 #[derive(rocket_db_pools::Database)]
 #[database("redis")]
 pub struct CacheConn(rocket_db_pools::deadpool_redis::Pool);
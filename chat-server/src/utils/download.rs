@@ -0,0 +1,303 @@
+//! Streamed downloads with HTTP range and conditional request support.
+//!
+//! Browsers fetch large files and videos in pieces with `Range` headers and
+//! revalidate cached copies with `If-None-Match`. [`DownloadRequest`] reads
+//! both headers and [`Download`] answers with the matching status, headers
+//! and a body streamed from the storage backend.
+
+use crate::services::storage::ByteStream;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+use std::convert::Infallible;
+use std::ops::Range;
+
+/// The conditional and range headers of a download request.
+#[derive(Debug, Default)]
+pub struct DownloadRequest {
+    range: Option<String>,
+    if_none_match: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DownloadRequest {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let header = |name| req.headers().get_one(name).map(str::to_string);
+        Outcome::Success(Self {
+            range: header("Range"),
+            if_none_match: header("If-None-Match"),
+        })
+    }
+}
+
+/// The part of a resource a request asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The whole resource, also used for range headers that are ignored
+    Full,
+    /// Bytes of the resource, end exclusive
+    Partial(Range<u64>),
+    /// A range that lies entirely beyond the end of the resource
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header against a resource of `size` bytes.
+///
+/// Only single ranges are supported; headers with several ranges, other units
+/// or invalid syntax are ignored, which RFC 9110 allows, and the whole
+/// resource is sent.
+pub fn parse_range(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        // The last `suffix` bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(suffix) => size.saturating_sub(suffix)..size,
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => start..size,
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(size),
+            _ => return ByteRange::Full,
+        },
+    };
+
+    if range.start >= size {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(range)
+    }
+}
+
+/// A stored file sent to the client.
+pub struct Download {
+    status: Status,
+    content_type: ContentType,
+    etag: String,
+    disposition: Option<String>,
+    content_range: Option<String>,
+    body: Option<ByteStream>,
+}
+
+impl Download {
+    /// Answers a request for the whole resource with `200 OK`.
+    pub fn full(content_type: ContentType, etag: &str, body: ByteStream) -> Self {
+        Self {
+            status: Status::Ok,
+            content_type,
+            etag: quote_etag(etag),
+            disposition: None,
+            content_range: None,
+            body: Some(body),
+        }
+    }
+
+    /// Answers a range request with `206 Partial Content`.
+    pub fn partial(
+        content_type: ContentType,
+        etag: &str,
+        range: &Range<u64>,
+        size: u64,
+        body: ByteStream,
+    ) -> Self {
+        Self {
+            status: Status::PartialContent,
+            content_range: Some(format!("bytes {}-{}/{}", range.start, range.end - 1, size)),
+            ..Self::full(content_type, etag, body)
+        }
+    }
+
+    /// Answers a range beyond the end of the resource with `416`.
+    pub fn unsatisfiable(etag: &str, size: u64) -> Self {
+        Self {
+            status: Status::RangeNotSatisfiable,
+            content_type: ContentType::Binary,
+            etag: quote_etag(etag),
+            disposition: None,
+            content_range: Some(format!("bytes */{}", size)),
+            body: None,
+        }
+    }
+
+    /// Tells the client its cached copy is current with `304`.
+    pub fn not_modified(etag: &str) -> Self {
+        Self {
+            status: Status::NotModified,
+            content_type: ContentType::Binary,
+            etag: quote_etag(etag),
+            disposition: None,
+            content_range: None,
+            body: None,
+        }
+    }
+
+    /// Shows the content in the browser where possible, saving it under
+    /// `file_name` otherwise.
+    pub fn inline(mut self, file_name: &str) -> Self {
+        self.disposition = Some(content_disposition("inline", file_name));
+        self
+    }
+
+    /// Makes the browser save the content under `file_name` rather than show
+    /// it, even when the link is opened directly.
+    pub fn attachment(mut self, file_name: &str) -> Self {
+        self.disposition = Some(content_disposition("attachment", file_name));
+        self
+    }
+}
+
+impl DownloadRequest {
+    /// Returns `true` if the client already has the content tagged `etag`.
+    pub fn is_cached(&self, etag: &str) -> bool {
+        let quoted = quote_etag(etag);
+        self.if_none_match.as_deref().is_some_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == quoted)
+        })
+    }
+
+    /// The part of a resource of `size` bytes the client asks for.
+    pub fn range(&self, size: u64) -> ByteRange {
+        parse_range(self.range.as_deref(), size)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Download {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response
+            .status(self.status)
+            .header(self.content_type)
+            .raw_header("ETag", self.etag)
+            .raw_header("Accept-Ranges", "bytes")
+            // Browsers must not guess a type that runs scripts from the content
+            .raw_header("X-Content-Type-Options", "nosniff");
+        if let Some(disposition) = self.disposition {
+            response.raw_header("Content-Disposition", disposition);
+        }
+        if let Some(content_range) = self.content_range {
+            response.raw_header("Content-Range", content_range);
+        }
+        if let Some(body) = self.body {
+            response.streamed_body(body);
+        }
+        response.ok()
+    }
+}
+
+fn quote_etag(etag: &str) -> String {
+    format!("\"{}\"", etag)
+}
+
+/// Builds a `Content-Disposition` of `kind`, `inline` or `attachment`, with an
+/// ASCII fallback name and the exact name percent-encoded as UTF-8 (RFC 6266).
+fn content_disposition(kind: &str, file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| {
+            if c == ' ' || (c.is_ascii_graphic() && c != '"' && c != '\\') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        kind, fallback, encoded
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 100), ByteRange::Full);
+        assert_eq!(
+            parse_range(Some("bytes=0-9"), 100),
+            ByteRange::Partial(0..10)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=90-"), 100),
+            ByteRange::Partial(90..100)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-10"), 100),
+            ByteRange::Partial(90..100)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-500"), 100),
+            ByteRange::Partial(0..100)
+        );
+        // The end is clamped to the size
+        assert_eq!(
+            parse_range(Some("bytes=50-999"), 100),
+            ByteRange::Partial(50..100)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=-0"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+        // Ignored headers
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=9-5"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("items=0-5"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=abc"), 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_is_cached() {
+        let request = |tags: &str| DownloadRequest {
+            range: None,
+            if_none_match: Some(tags.to_string()),
+        };
+        assert!(request("\"abc\"").is_cached("abc"));
+        assert!(request("\"x\", W/\"abc\"").is_cached("abc"));
+        assert!(request("*").is_cached("abc"));
+        assert!(!request("\"abd\"").is_cached("abc"));
+        assert!(!DownloadRequest::default().is_cached("abc"));
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("attachment", "report.pdf"),
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+        assert_eq!(
+            content_disposition("inline", "žluťoučký \"kůň\".png"),
+            "inline; filename=\"_lu_ou_k_ _k___.png\"; \
+             filename*=UTF-8''%C5%BElu%C5%A5ou%C4%8Dk%C3%BD%20%22k%C5%AF%C5%88%22.png"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Sampling of high-volume log events.
+//!
+//! Events that can occur for every message, such as rate-limit rejections
+//! during a flood, would drown out everything else in production logs. Their
+//! call sites keep a [`LogSampler`] and only log one in `LOG_SAMPLE_RATE`
+//! occurrences, together with how many occurrences the logged one stands for.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts the occurrences of one log event to decide which are logged.
+#[derive(Debug, Default)]
+pub struct LogSampler {
+    seen: AtomicU64,
+}
+
+impl LogSampler {
+    /// Creates a sampler that has not seen any occurrences.
+    pub const fn new() -> Self {
+        Self {
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Records an occurrence of the event.
+    ///
+    /// # Arguments
+    /// * `rate` - Log one in `rate` occurrences; 0 and 1 log all of them
+    ///
+    /// # Returns
+    /// * `Option<u64>` - The number of occurrences the logged event stands for,
+    ///   or None if this occurrence should not be logged
+    pub fn sample(&self, rate: u32) -> Option<u64> {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        let rate = u64::from(rate.max(1));
+        seen.is_multiple_of(rate).then_some(rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_one_in_rate() {
+        let sampler = LogSampler::new();
+        let logged: Vec<_> = (0..7).map(|_| sampler.sample(3)).collect();
+        assert_eq!(
+            logged,
+            vec![Some(3), None, None, Some(3), None, None, Some(3)]
+        );
+    }
+
+    #[test]
+    fn test_rate_of_one_logs_everything() {
+        let sampler = LogSampler::new();
+        assert!((0..5).all(|_| sampler.sample(1) == Some(1)));
+        assert_eq!(sampler.sample(0), Some(1));
+    }
+}
@@ -1,10 +1,111 @@
-use prometheus::{Counter, Gauge, Registry};
+use chat_common::error::{ChatError, ErrorCode};
+use diesel_async::pooled_connection::deadpool::PoolError;
+use prometheus::{
+    Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, Opts, Registry,
+};
+use std::backtrace::Backtrace;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tracing::error;
+
+/// What went wrong, as counted by `chat_errors_total`, so alerts can tell
+/// rejected logins from misbehaving clients from an unavailable database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Failed logins and requests the client is not allowed to make
+    Auth,
+    /// Requests that are malformed or refer to something that does not exist
+    Protocol,
+    /// The database could not be reached or failed a query
+    Database,
+    /// Reading from or writing to a connection failed
+    Network,
+    /// Attachments that did not match their integrity hash
+    Integrity,
+    /// Requests beyond a storage quota
+    Quota,
+    /// Anything else the server failed at
+    Internal,
+}
+
+impl ErrorClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::Auth => "auth",
+            ErrorClass::Protocol => "protocol",
+            ErrorClass::Database => "database",
+            ErrorClass::Network => "network",
+            ErrorClass::Integrity => "integrity",
+            ErrorClass::Quota => "quota",
+            ErrorClass::Internal => "internal",
+        }
+    }
+
+    /// Classifies the code of an error reply sent to a client.
+    pub fn of_code(code: &ErrorCode) -> Self {
+        match code {
+            ErrorCode::PermissionDenied => ErrorClass::Auth,
+            ErrorCode::FileNotFound
+            | ErrorCode::InvalidInput
+            | ErrorCode::ImageProcessingError
+            | ErrorCode::Conflict
+            | ErrorCode::UnsupportedProtocol => ErrorClass::Protocol,
+            ErrorCode::NetworkError => ErrorClass::Network,
+            ErrorCode::IntegrityError => ErrorClass::Integrity,
+            ErrorCode::QuotaExceeded => ErrorClass::Quota,
+            ErrorCode::ServerError | ErrorCode::UnknownError => ErrorClass::Internal,
+        }
+    }
+
+    /// Classifies a chat error.
+    pub fn of_chat_error(e: &ChatError) -> Self {
+        match e {
+            ChatError::IoError(_) => ErrorClass::Network,
+            ChatError::SerializationError(_)
+            | ChatError::InvalidPath(_)
+            | ChatError::InvalidCommand(_) => ErrorClass::Protocol,
+            e => Self::of_code(&e.to_error_code()),
+        }
+    }
+
+    /// Classifies an error by the first cause in its chain that tells what
+    /// failed.
+    pub fn of(e: &anyhow::Error) -> Self {
+        for cause in e.chain() {
+            if cause.is::<diesel::result::Error>() || cause.is::<PoolError>() {
+                return ErrorClass::Database;
+            }
+            if let Some(e) = cause.downcast_ref::<ChatError>() {
+                return Self::of_chat_error(e);
+            }
+            if cause.is::<std::io::Error>() {
+                return ErrorClass::Network;
+            }
+        }
+        ErrorClass::Internal
+    }
+}
 
 pub struct Metrics {
     pub messages_sent: Counter,
     pub active_connections: Gauge,
+    pub outbound_queue_depth: Gauge,
+    pub outbound_dropped: Counter,
+    pub unhealthy_disconnects: CounterVec,
+    pub pool_connections: GaugeVec,
+    pub pool_max_size: GaugeVec,
+    pub pool_waiting: GaugeVec,
+    pub pool_wait_seconds: HistogramVec,
+    pub pool_timeouts: CounterVec,
+    pub redis_command_seconds: Histogram,
+    pub redis_errors: Counter,
+    pub auth_failures: CounterVec,
+    pub auth_disconnects: Counter,
+    pub attachments_deduplicated: Counter,
+    pub errors: CounterVec,
+    pub panics: Counter,
+    pub bytes_read: Counter,
+    pub bytes_written: Counter,
     registry: Registry,
 }
 
@@ -24,28 +125,251 @@ impl Metrics {
         )
         .unwrap();
 
+        let outbound_queue_depth = Gauge::new(
+            "chat_outbound_queue_depth",
+            "Number of messages waiting in client outbound queues",
+        )
+        .unwrap();
+
+        let outbound_dropped = Counter::new(
+            "chat_outbound_messages_dropped_total",
+            "Total number of outbound messages dropped due to backpressure",
+        )
+        .unwrap();
+
+        let unhealthy_disconnects = CounterVec::new(
+            Opts::new(
+                "chat_unhealthy_disconnects_total",
+                "Total number of clients disconnected by the health monitor",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+
+        let pool_connections = GaugeVec::new(
+            Opts::new(
+                "chat_pool_connections",
+                "Connections in each pool by state (available or in_use)",
+            ),
+            &["pool", "state"],
+        )
+        .unwrap();
+
+        let pool_max_size = GaugeVec::new(
+            Opts::new(
+                "chat_pool_max_connections",
+                "Maximum number of connections in each pool",
+            ),
+            &["pool"],
+        )
+        .unwrap();
+
+        let pool_waiting = GaugeVec::new(
+            Opts::new(
+                "chat_pool_waiting",
+                "Number of tasks waiting for a connection from each pool",
+            ),
+            &["pool"],
+        )
+        .unwrap();
+
+        let pool_wait_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "chat_pool_wait_seconds",
+                "Time health probes waited for a connection from each pool",
+            )
+            .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 2.0]),
+            &["pool"],
+        )
+        .unwrap();
+
+        let pool_timeouts = CounterVec::new(
+            Opts::new(
+                "chat_pool_timeouts_total",
+                "Total number of health probes that got no connection in time",
+            ),
+            &["pool"],
+        )
+        .unwrap();
+
+        let redis_command_seconds = Histogram::with_opts(
+            HistogramOpts::new("chat_redis_command_seconds", "Latency of Redis commands")
+                .buckets(vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]),
+        )
+        .unwrap();
+
+        let redis_errors = Counter::new(
+            "chat_redis_errors_total",
+            "Total number of failed Redis commands",
+        )
+        .unwrap();
+
+        let auth_failures = CounterVec::new(
+            Opts::new(
+                "chat_auth_failures_total",
+                "Total number of failed chat logins",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+
+        let auth_disconnects = Counter::new(
+            "chat_auth_disconnects_total",
+            "Total number of clients disconnected after repeated failed logins",
+        )
+        .unwrap();
+
+        let attachments_deduplicated = Counter::new(
+            "chat_attachments_deduplicated_total",
+            "Total number of attachments not uploaded because the same content was already stored",
+        )
+        .unwrap();
+
+        let errors = CounterVec::new(
+            Opts::new(
+                "chat_errors_total",
+                "Total number of errors by class (auth, protocol, database, network, integrity, quota, internal)",
+            ),
+            &["class"],
+        )
+        .unwrap();
+
+        let panics =
+            Counter::new("chat_panics_total", "Total number of panics in the server").unwrap();
+
+        let bytes_read = Counter::new(
+            "chat_bytes_read_total",
+            "Total number of bytes read from chat connections",
+        )
+        .unwrap();
+
+        let bytes_written = Counter::new(
+            "chat_bytes_written_total",
+            "Total number of bytes written to chat connections",
+        )
+        .unwrap();
+
         registry.register(Box::new(messages_sent.clone())).unwrap();
         registry
             .register(Box::new(active_connections.clone()))
             .unwrap();
+        registry
+            .register(Box::new(outbound_queue_depth.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(outbound_dropped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(unhealthy_disconnects.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(pool_connections.clone()))
+            .unwrap();
+        registry.register(Box::new(pool_max_size.clone())).unwrap();
+        registry.register(Box::new(pool_waiting.clone())).unwrap();
+        registry
+            .register(Box::new(pool_wait_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(pool_timeouts.clone())).unwrap();
+        registry
+            .register(Box::new(redis_command_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(redis_errors.clone())).unwrap();
+        registry.register(Box::new(auth_failures.clone())).unwrap();
+        registry
+            .register(Box::new(auth_disconnects.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(attachments_deduplicated.clone()))
+            .unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(panics.clone())).unwrap();
+        registry.register(Box::new(bytes_read.clone())).unwrap();
+        registry.register(Box::new(bytes_written.clone())).unwrap();
 
         Arc::new(Mutex::new(Self {
             messages_sent,
             active_connections,
+            outbound_queue_depth,
+            outbound_dropped,
+            unhealthy_disconnects,
+            pool_connections,
+            pool_max_size,
+            pool_waiting,
+            pool_wait_seconds,
+            pool_timeouts,
+            redis_command_seconds,
+            redis_errors,
+            auth_failures,
+            auth_disconnects,
+            attachments_deduplicated,
+            errors,
+            panics,
+            bytes_read,
+            bytes_written,
             registry,
         }))
     }
 
+    /// Counts an error in `chat_errors_total`.
+    pub fn count_error(&self, class: ErrorClass) {
+        self.errors.with_label_values(&[class.as_str()]).inc();
+    }
+
     pub fn get_metrics(&self) -> String {
         self.registry
             .gather()
             .iter()
             .map(|mf| {
                 prometheus::TextEncoder::new()
-                    .encode_to_string(&[mf.clone()])
+                    .encode_to_string(std::slice::from_ref(mf))
                     .unwrap()
             })
             .collect::<Vec<String>>()
             .join("\n")
     }
 }
+
+/// Counts panics in `chat_panics_total` and logs them with a backtrace.
+///
+/// # Arguments
+/// * `panics` - The panic counter of the server's metrics
+pub fn install_panic_hook(panics: Counter) {
+    std::panic::set_hook(Box::new(move |info| {
+        panics.inc();
+        let backtrace = Backtrace::force_capture();
+        error!("Panic: {}\n{}", info, backtrace);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_classes() {
+        let database =
+            anyhow::Error::from(diesel::result::Error::NotFound).context("Lookup failed");
+        assert_eq!(ErrorClass::of(&database), ErrorClass::Database);
+
+        let denied = anyhow::Error::from(ChatError::PermissionDenied("no".to_string()));
+        assert_eq!(ErrorClass::of(&denied), ErrorClass::Auth);
+
+        let io: anyhow::Result<()> = Err(std::io::Error::other("reset")).context("Read failed");
+        assert_eq!(ErrorClass::of(&io.unwrap_err()), ErrorClass::Network);
+
+        assert_eq!(
+            ErrorClass::of(&anyhow::anyhow!("Something else")),
+            ErrorClass::Internal
+        );
+        assert_eq!(
+            ErrorClass::of_chat_error(&ChatError::InvalidCommand(".x".to_string())),
+            ErrorClass::Protocol
+        );
+        assert_eq!(
+            ErrorClass::of_code(&ErrorCode::QuotaExceeded),
+            ErrorClass::Quota
+        );
+    }
+}
@@ -1,3 +1,10 @@
+pub mod bind;
 pub mod cors;
+pub mod csv;
 pub mod db_connection;
+pub mod download;
+pub mod log_sampling;
 pub mod metrics;
+pub mod sessions;
+pub mod timeout;
+pub mod user_cache;
@@ -0,0 +1,101 @@
+//! Web sessions in Redis.
+//!
+//! A session is stored as `sessions/<token>` holding the user ID and expires
+//! after [`SESSION_TTL_SECS`]. The tokens of each user are also kept in the
+//! set `user_sessions/<user_id>`, so a user's sessions can be listed, ended or
+//! moved without scanning every session. Tokens whose session expired are
+//! dropped from the set whenever it is read, and the set itself expires with
+//! the user's newest session.
+
+use rocket_db_pools::deadpool_redis::redis::{self, AsyncCommands, RedisResult};
+use rocket_db_pools::deadpool_redis::Connection;
+
+use crate::routes::authorization::SESSION_TTL_SECS;
+
+/// An unexpired web session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub token: String,
+    /// Seconds until the session expires
+    pub ttl_secs: u64,
+}
+
+fn session_key(token: &str) -> String {
+    format!("sessions/{}", token)
+}
+
+fn user_sessions_key(user_id: i32) -> String {
+    format!("user_sessions/{}", user_id)
+}
+
+/// Starts a session for a user.
+pub async fn create(cache: &mut Connection, user_id: i32, token: &str) -> RedisResult<()> {
+    let index = user_sessions_key(user_id);
+    redis::pipe()
+        .atomic()
+        .set_ex(session_key(token), user_id, SESSION_TTL_SECS)
+        .sadd(&index, token)
+        .expire(&index, SESSION_TTL_SECS as i64)
+        .query_async(&mut *cache)
+        .await
+}
+
+/// Lists the unexpired sessions of a user.
+pub async fn list(cache: &mut Connection, user_id: i32) -> RedisResult<Vec<Session>> {
+    let index = user_sessions_key(user_id);
+    let tokens: Vec<String> = cache.smembers(&index).await?;
+
+    let mut sessions = Vec::new();
+    for token in tokens {
+        let key = session_key(&token);
+        // The session may have expired, or been moved to another user
+        let owner: Option<i32> = cache.get(&key).await?;
+        let ttl: i64 = cache.ttl(&key).await?;
+        if owner != Some(user_id) || ttl <= 0 {
+            cache.srem::<_, _, ()>(&index, &token).await?;
+            continue;
+        }
+        sessions.push(Session {
+            token,
+            ttl_secs: ttl as u64,
+        });
+    }
+    Ok(sessions)
+}
+
+/// Ends every session of a user.
+///
+/// # Returns
+/// * `RedisResult<usize>` - The number of ended sessions
+pub async fn end_all(cache: &mut Connection, user_id: i32) -> RedisResult<usize> {
+    let sessions = list(cache, user_id).await?;
+    for session in &sessions {
+        cache.del::<_, ()>(session_key(&session.token)).await?;
+    }
+    cache.del::<_, ()>(user_sessions_key(user_id)).await?;
+    Ok(sessions.len())
+}
+
+/// Moves the sessions of one user to another, keeping their expiry.
+///
+/// # Returns
+/// * `RedisResult<usize>` - The number of moved sessions
+pub async fn move_all(cache: &mut Connection, from: i32, to: i32) -> RedisResult<usize> {
+    let sessions = list(cache, from).await?;
+    let index = user_sessions_key(to);
+    for session in &sessions {
+        redis::pipe()
+            .atomic()
+            .set_ex(session_key(&session.token), to, session.ttl_secs)
+            .sadd(&index, &session.token)
+            .query_async::<_, ()>(&mut *cache)
+            .await?;
+    }
+    if !sessions.is_empty() {
+        cache
+            .expire::<_, ()>(&index, SESSION_TTL_SECS as i64)
+            .await?;
+    }
+    cache.del::<_, ()>(user_sessions_key(from)).await?;
+    Ok(sessions.len())
+}
@@ -0,0 +1,112 @@
+//! Time limit for REST request handlers.
+//!
+//! Rocket fairings only see requests before and responses after the handler,
+//! so they cannot stop a handler that hangs, e.g. on a stalled database query.
+//! [`RequestTimeout`] instead wraps the handlers of mounted routes and answers
+//! `408 Request Timeout` once the configured time has passed; the handler's
+//! future is dropped, releasing whatever it held.
+
+use crate::config::SharedConfig;
+use rocket::http::Status;
+use rocket::route::{Handler, Outcome};
+use rocket::{Data, Request, Route};
+use tracing::warn;
+
+/// Wraps route handlers so they are aborted after `HTTP_REQUEST_TIMEOUT_SECS`.
+#[derive(Clone)]
+pub struct RequestTimeout {
+    config: SharedConfig,
+}
+
+impl RequestTimeout {
+    /// # Arguments
+    /// * `config` - Read on every request, so a reloaded timeout applies at once
+    pub fn new(config: SharedConfig) -> Self {
+        Self { config }
+    }
+
+    /// Puts the time limit on the given routes, for passing to `mount`.
+    pub fn wrap(&self, routes: Vec<Route>) -> Vec<Route> {
+        routes
+            .into_iter()
+            .map(|mut route| {
+                route.handler = Box::new(TimedHandler {
+                    handler: route.handler,
+                    config: self.config.clone(),
+                });
+                route
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+struct TimedHandler {
+    handler: Box<dyn Handler>,
+    config: SharedConfig,
+}
+
+#[rocket::async_trait]
+impl Handler for TimedHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let Some(limit) = self.config.current().http_request_timeout else {
+            return self.handler.handle(req, data).await;
+        };
+        match tokio::time::timeout(limit, self.handler.handle(req, data)).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                warn!(
+                    "{} {} timed out after {} s",
+                    req.method(),
+                    req.uri(),
+                    limit.as_secs_f64()
+                );
+                Outcome::Error(Status::RequestTimeout)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::errors::rocket_server_errors;
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::Value;
+    use std::time::Duration;
+
+    #[rocket::get("/slow")]
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        "done"
+    }
+
+    #[rocket::get("/fast")]
+    fn fast() -> &'static str {
+        "done"
+    }
+
+    #[rocket::async_test]
+    async fn test_slow_handler_times_out() {
+        let config = SharedConfig::new(ServerConfig {
+            http_request_timeout: Some(Duration::from_millis(50)),
+            ..ServerConfig::default()
+        });
+        let rocket = rocket::build()
+            .mount(
+                "/",
+                RequestTimeout::new(config).wrap(rocket::routes![slow, fast]),
+            )
+            .register("/", rocket_server_errors::catchers());
+        let client = Client::untracked(rocket).await.unwrap();
+
+        let response = client.get("/slow").dispatch().await;
+        assert_eq!(response.status(), Status::RequestTimeout);
+        assert!(response.into_json::<Value>().await.is_some());
+
+        let response = client.get("/fast").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().await.as_deref(), Some("done"));
+    }
+}
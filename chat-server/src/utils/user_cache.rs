@@ -0,0 +1,83 @@
+//! Short-lived cache of users authenticated by REST requests.
+//!
+//! The frontend sends bursts of requests on every page, each of which loads
+//! its user in the `User` request guard. With `USER_CACHE_TTL_SECS` set, the
+//! guard keeps loaded users in a [`UserCache`] for that long instead of
+//! querying Postgres every time. Sessions are still looked up in Redis on
+//! every request, so logging out takes effect immediately.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::user::User;
+
+/// Users loaded by the `User` request guard, by ID.
+///
+/// Routes that change or delete a user invalidate its entry. Changes made
+/// elsewhere, such as over gRPC, are seen once the entry expires.
+#[derive(Debug, Default)]
+pub struct UserCache {
+    entries: Mutex<HashMap<i32, (Instant, User)>>,
+}
+
+impl UserCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached user if it was loaded less than `ttl` ago.
+    pub fn get(&self, user_id: i32, ttl: Duration, now: Instant) -> Option<User> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (loaded, _)| now.duration_since(*loaded) < ttl);
+        entries.get(&user_id).map(|(_, user)| user.clone())
+    }
+
+    /// Caches a user loaded at `now`.
+    pub fn insert(&self, user: &User, now: Instant) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(user.id, (now, user.clone()));
+    }
+
+    /// Removes a changed or deleted user from the cache.
+    pub fn invalidate(&self, user_id: i32) {
+        self.entries.lock().unwrap().remove(&user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32) -> User {
+        User {
+            id,
+            username: format!("user{}", id),
+            email: format!("user{}@example.com", id),
+            password_hash: String::new(),
+            created_at: Default::default(),
+            updated_at: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_entries_expire_and_invalidate() {
+        let cache = UserCache::new();
+        let ttl = Duration::from_secs(5);
+        let start = Instant::now();
+
+        assert!(cache.get(1, ttl, start).is_none());
+        cache.insert(&user(1), start);
+        cache.insert(&user(2), start);
+        let cached = cache.get(1, ttl, start + Duration::from_secs(4));
+        assert_eq!(cached.map(|user| user.id), Some(1));
+        assert!(cache.get(1, ttl, start + ttl).is_none());
+
+        cache.insert(&user(2), start + ttl);
+        cache.invalidate(2);
+        assert!(cache.get(2, ttl, start + ttl).is_none());
+    }
+}